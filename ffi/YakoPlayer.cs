@@ -3,6 +3,151 @@ using System.Runtime.InteropServices;
 
 namespace YakoPlayer
 {
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void YakoDspCallback(IntPtr samples, uint frames, uint channels, IntPtr userData);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void YakoLogCallback(int level, IntPtr message);
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    public delegate void YakoOpenProgressCallback(int phase, IntPtr userData);
+
+    public enum OpenMode
+    {
+        Replace = 0,
+        Enqueue = 1,
+        Crossfade = 2,
+    }
+
+    public enum OpenPhase
+    {
+        Probing = 0,
+        ReadingMetadata = 1,
+        CreatingDecoder = 2,
+        Prebuffering = 3,
+        Ready = 4,
+    }
+
+    public enum DspStage
+    {
+        Equalizer = 0,
+        ChannelDelay = 1,
+        Convolution = 2,
+        UserProcessor = 3,
+        Limiter = 4,
+    }
+
+    public enum ReplayGainMode
+    {
+        Off = 0,
+        Track = 1,
+        Album = 2,
+    }
+
+    public enum FinishedAction
+    {
+        Stop = 0,
+        LoopTrack = 1,
+        AdvanceQueue = 2,
+    }
+
+    public enum SleepTimer
+    {
+        Off = 0,
+        AfterDuration = 1,
+        AfterCurrentTrack = 2,
+    }
+
+    public enum LogLevel
+    {
+        Off = 0,
+        Error = 1,
+        Warn = 2,
+        Info = 3,
+        Debug = 4,
+        Trace = 5,
+    }
+
+    public enum CaptureFormat
+    {
+        Wav = 0,
+        Flac = 1,
+    }
+
+    public enum VolumeCurve
+    {
+        Exponential = 0,
+        LinearDb = 1,
+        LinearAmplitude = 2,
+    }
+
+    public enum EndBehavior
+    {
+        Auto = -1,
+        RewindAndWait = 0,
+        HoldPosition = 1,
+        Handover = 2,
+    }
+
+    public enum ErrorResilience
+    {
+        Skip = 0,
+        Conceal = 1,
+        Abort = 2,
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct PlayerStatus
+    {
+        public Int64 CurrentTime;
+        public Int64 Duration;
+        public Int64 Bitrate;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool IsPlaying;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool IsSeekable;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool IsSourceLost;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool IsBuffering;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct ProbeResult
+    {
+        [MarshalAs(UnmanagedType.U1)]
+        public bool Ok;
+        public Int64 Duration;
+        public Int64 Bitrate;
+        public uint SampleRate;
+        public ushort Channels;
+        public uint BitsPerSample;
+    }
+
+    [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Ansi)]
+    public struct FileProbeResult
+    {
+        [MarshalAs(UnmanagedType.U1)]
+        public bool Ok;
+        [MarshalAs(UnmanagedType.ByValTStr, SizeConst = 32)]
+        public string Container;
+        [MarshalAs(UnmanagedType.ByValTStr, SizeConst = 32)]
+        public string Codec;
+        public Int64 Duration;
+        public uint SampleRate;
+        public ushort Channels;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool HasCover;
+        [MarshalAs(UnmanagedType.U1)]
+        public bool IsSeekable;
+    }
+
+    public struct CuePoint
+    {
+        public string Name;
+        public Int64 Position;
+    }
+
     internal class YakoPlayerNative
     {
         [DllImport("yako_player")]
@@ -14,6 +159,18 @@ namespace YakoPlayer
         [DllImport("yako_player", CharSet = CharSet.Unicode)]
         internal static extern int yako_player_open(YakoPlayerHandle player, string path);
 
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_open_with_progress(YakoPlayerHandle player, string path, YakoOpenProgressCallback? callback, IntPtr userData);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_open_directory(YakoPlayerHandle player, string path, int recursive);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_audition(YakoPlayerHandle player, string path, double startFraction, Int64 durationMs);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_probe_batch(YakoPlayerHandle player, string[] paths, UIntPtr count, [Out] ProbeResult[] results);
+
         [DllImport("yako_player")]
         internal static extern int yako_player_play(YakoPlayerHandle player);
 
@@ -23,9 +180,33 @@ namespace YakoPlayer
         [DllImport("yako_player")]
         internal static extern int yako_player_stop(YakoPlayerHandle player);
 
+        [DllImport("yako_player")]
+        internal static extern int yako_player_reset(YakoPlayerHandle player, int keepVolume, int keepDevice);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_reopen_current(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_recover_output(YakoPlayerHandle player);
+
         [DllImport("yako_player")]
         internal static extern int yako_player_seek(YakoPlayerHandle player, Int64 position);
 
+        [DllImport("yako_player")]
+        internal static extern int yako_player_wait_for_end(YakoPlayerHandle player, Int64 timeoutMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_seek_debounce_ms(YakoPlayerHandle player, uint debounceMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_rebuffer_threshold_ms(YakoPlayerHandle player, uint thresholdMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_target_buffer_ms(YakoPlayerHandle player, uint targetMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_prebuffer_ms(YakoPlayerHandle player, int prebufferMs);
+
         [DllImport("yako_player")]
         internal static extern uint yako_player_get_bitrate(YakoPlayerHandle player);
 
@@ -35,9 +216,33 @@ namespace YakoPlayer
         [DllImport("yako_player")]
         internal static extern Int64 yako_player_get_current_time(YakoPlayerHandle player);
 
+        [DllImport("yako_player")]
+        internal static extern Int64 yako_player_get_current_time_us(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_try_get_bitrate(YakoPlayerHandle player, out uint bitrate);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_try_get_duration(YakoPlayerHandle player, out Int64 duration);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_try_get_current_time(YakoPlayerHandle player, out Int64 currentTime);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_try_get_replay_gain_peak(YakoPlayerHandle player, out float peak);
+
         [DllImport("yako_player")]
         internal static extern int yako_player_is_playing(YakoPlayerHandle player);
 
+        [DllImport("yako_player")]
+        internal static extern int yako_player_is_lossless(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_has_video(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_get_status(YakoPlayerHandle player, out PlayerStatus status);
+
         [DllImport("yako_player")]
         internal static extern float yako_player_get_volume(YakoPlayerHandle player);
 
@@ -47,12 +252,233 @@ namespace YakoPlayer
         [DllImport("yako_player")]
         internal static extern int yako_player_set_mute(YakoPlayerHandle player, int mute);
 
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_volume_curve(YakoPlayerHandle player, int curve, float minDb);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_seamless_loop(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_finished_action(YakoPlayerHandle player, int action);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_lazy_cover(YakoPlayerHandle player, int lazy);
+
+        [Obsolete("Use yako_player_copy_album_cover instead")]
         [DllImport("yako_player")]
         internal static extern IntPtr yako_player_get_album_cover(YakoPlayerHandle player);
 
+        [Obsolete("Use yako_player_copy_album_cover instead")]
         [DllImport("yako_player")]
         internal static extern uint yako_player_get_album_cover_size(YakoPlayerHandle player);
 
+        [DllImport("yako_player")]
+        internal static extern int yako_player_copy_album_cover(YakoPlayerHandle player, byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player")]
+        internal static extern ulong yako_player_get_cover_generation(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_copy_album_cover_versioned(YakoPlayerHandle player, byte[]? buffer, UIntPtr bufferLen, out ulong generation);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_copy_codec_extradata(YakoPlayerHandle player, byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_decode_chunk_size(YakoPlayerHandle player, UIntPtr size);
+
+        [DllImport("yako_player")]
+        internal static extern UIntPtr yako_player_get_decode_chunk_size(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern UIntPtr yako_player_get_dynamic_buffer_size(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern double yako_player_get_output_latency_ms(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern double yako_player_get_buffer_latency_ms(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_speed(YakoPlayerHandle player, float speed);
+
+        [DllImport("yako_player")]
+        internal static extern float yako_player_get_speed(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_eq_enabled(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_eq_band_gain(YakoPlayerHandle player, UIntPtr index, float gainDb);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_graphic_eq(YakoPlayerHandle player, float[] gainsDb, UIntPtr count);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_eq_auto_gain(YakoPlayerHandle player, int autoGain);
+
+        [DllImport("yako_player")]
+        internal static extern float yako_player_get_eq_compensation(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_channel_delays(YakoPlayerHandle player, uint[] delays, UIntPtr count);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_output_channel_map(YakoPlayerHandle player, uint[] channelMap, UIntPtr count);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_master_limiter(YakoPlayerHandle player, int enabled, float ceilingDb);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_processing_order(YakoPlayerHandle player, int[] stages, UIntPtr count);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_load_impulse_response(YakoPlayerHandle player, string path);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_convolution(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_replay_gain(YakoPlayerHandle player, int mode, float preampDb);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_replay_gain_fallback(YakoPlayerHandle player, float fallbackDb, int preventClipping);
+
+        [DllImport("yako_player")]
+        internal static extern float yako_player_get_replay_gain_peak(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_end_behavior(YakoPlayerHandle player, int behavior);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_error_resilience(YakoPlayerHandle player, int resilience);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_ab_loop(YakoPlayerHandle player, long aMs, long bMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_clear_ab_loop(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_sleep_timer(YakoPlayerHandle player, int mode, ulong durationMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_sleep_fade_duration(YakoPlayerHandle player, ulong durationMs);
+
+        [DllImport("yako_player")]
+        internal static extern long yako_player_get_played_time(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_scrobble_threshold(YakoPlayerHandle player, double ratio, long maxMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_bookmarks_enabled(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_set_bookmarks_path(YakoPlayerHandle player, string path);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_add_cue_point(YakoPlayerHandle player, string name);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_get_cue_point_count(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern Int64 yako_player_get_cue_point_position(YakoPlayerHandle player, UIntPtr index);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_copy_cue_point_name(YakoPlayerHandle player, UIntPtr index, byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_jump_to_cue_point(YakoPlayerHandle player, UIntPtr index);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_remove_cue_point(YakoPlayerHandle player, UIntPtr index);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_analyze_queue_loudness(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_cancel_loudness_analysis(YakoPlayerHandle player);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_set_loudness_cache_path(YakoPlayerHandle player, string path);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_save_playlist(YakoPlayerHandle player, string path);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_set_input_option(YakoPlayerHandle player, string key, string value);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_clear_input_options(YakoPlayerHandle player);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_add_output(YakoPlayerHandle player, string deviceName);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_remove_output(YakoPlayerHandle player, uint id);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_output_volume(YakoPlayerHandle player, uint id, float volume);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_output_mute(YakoPlayerHandle player, uint id, int mute);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_open_deck(YakoPlayerHandle player, uint deck, string path);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_play_deck(YakoPlayerHandle player, uint deck);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_pause_deck(YakoPlayerHandle player, uint deck);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_close_deck(YakoPlayerHandle player, uint deck);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_deck_volume(YakoPlayerHandle player, uint deck, float volume);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_player_start_capture(YakoPlayerHandle player, string path, int format);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_stop_capture(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_capture_split_on_track(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_capture_pre_volume(YakoPlayerHandle player, int enabled);
+
+        [DllImport("yako_player")]
+        internal static extern ulong yako_player_get_capture_dropped_frames(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern ulong yako_player_get_clip_count(YakoPlayerHandle player);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_open_mode(YakoPlayerHandle player, int mode, uint crossfadeMs);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_set_dsp_callback(YakoPlayerHandle player, YakoDspCallback? callback, IntPtr userData);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_get_ffmpeg_version(byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_player_get_debug_report(YakoPlayerHandle player, byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_get_supported_formats(byte[]? buffer, UIntPtr bufferLen);
+
+        [DllImport("yako_player", CharSet = CharSet.Unicode)]
+        internal static extern int yako_probe_file(string path, out FileProbeResult result);
+
+        [DllImport("yako_player")]
+        internal static extern int yako_set_log_callback(YakoLogCallback? callback, int minLevel);
+
         [DllImport("yako_player")]
         internal static extern void clear_last_error();
 
@@ -85,11 +511,25 @@ namespace YakoPlayer
         }
     }
 
+    public class NoAudioStreamException : Exception
+    {
+        public NoAudioStreamException(string message) : base(message)
+        {
+        }
+    }
+
     public class YakoPlayer : IDisposable
     {
         private YakoPlayerHandle player;
 
-        private void CheckError(int returnValue)
+        // 保持委托的引用，避免被垃圾回收后原生代码调用失效的函数指针
+        private YakoDspCallback? dspCallback;
+        private YakoOpenProgressCallback? openProgressCallback;
+        private static YakoLogCallback? logCallback;
+
+        private const int NoAudioStream = -2;
+
+        private static void CheckError(int returnValue)
         {
             if (returnValue != 0)
             {
@@ -105,6 +545,10 @@ namespace YakoPlayer
                         }
                     }
                     string message = System.Text.Encoding.UTF8.GetString(buffer);
+                    if (returnValue == NoAudioStream)
+                    {
+                        throw new NoAudioStreamException(message);
+                    }
                     throw new Exception(message);
                 }
             }
@@ -120,6 +564,30 @@ namespace YakoPlayer
             CheckError(YakoPlayerNative.yako_player_open(player, filePath));
         }
 
+        public void Open(string filePath, Action<OpenPhase> progress)
+        {
+            // Ready 在开始播放之后由解码线程报告，委托需要一直保留到下一次打开
+            openProgressCallback = (phase, _) => progress((OpenPhase)phase);
+            CheckError(YakoPlayerNative.yako_player_open_with_progress(player, filePath, openProgressCallback, IntPtr.Zero));
+        }
+
+        public void OpenDirectory(string path, bool recursive = true)
+        {
+            CheckError(YakoPlayerNative.yako_player_open_directory(player, path, recursive ? 1 : 0));
+        }
+
+        public void Audition(string filePath, double startFraction = 0.3, Int64 durationMs = 10000)
+        {
+            CheckError(YakoPlayerNative.yako_player_audition(player, filePath, startFraction, durationMs));
+        }
+
+        public ProbeResult[] ProbeBatch(string[] paths)
+        {
+            var results = new ProbeResult[paths.Length];
+            YakoPlayerNative.yako_player_probe_batch(player, paths, (UIntPtr)paths.Length, results);
+            return results;
+        }
+
         public void Play()
         {
             CheckError(YakoPlayerNative.yako_player_play(player));
@@ -135,11 +603,53 @@ namespace YakoPlayer
             CheckError(YakoPlayerNative.yako_player_stop(player));
         }
 
-        public void Seek(Int64 position)
+        public void Reset(bool keepVolume = true, bool keepDevice = true)
+        {
+            CheckError(YakoPlayerNative.yako_player_reset(player, keepVolume ? 1 : 0, keepDevice ? 1 : 0));
+        }
+
+        public void ReopenCurrent()
+        {
+            CheckError(YakoPlayerNative.yako_player_reopen_current(player));
+        }
+
+        public bool RecoverOutput()
+        {
+            int result = YakoPlayerNative.yako_player_recover_output(player);
+            CheckError(Math.Min(result, 0));
+            return result > 0;
+        }
+
+        public bool WaitForEnd(Int64 timeoutMs = -1)
+        {
+            return YakoPlayerNative.yako_player_wait_for_end(player, timeoutMs) == 1;
+        }
+
+        public void Seek(Int64 position)
         {
             CheckError(YakoPlayerNative.yako_player_seek(player, position)); 
         }
 
+        public void SetSeekDebounceMs(uint debounceMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_seek_debounce_ms(player, debounceMs));
+        }
+
+        public void SetRebufferThresholdMs(uint thresholdMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_rebuffer_threshold_ms(player, thresholdMs));
+        }
+
+        public void SetTargetBufferMs(uint targetMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_target_buffer_ms(player, targetMs));
+        }
+
+        public void SetPrebufferMs(int prebufferMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_prebuffer_ms(player, prebufferMs));
+        }
+
         public uint GetBitrate()
         {
             return YakoPlayerNative.yako_player_get_bitrate(player);
@@ -155,11 +665,53 @@ namespace YakoPlayer
             return YakoPlayerNative.yako_player_get_current_time(player);
         }
 
+        public Int64 GetCurrentTimeUs()
+        {
+            return YakoPlayerNative.yako_player_get_current_time_us(player);
+        }
+
+        public bool TryGetBitrate(out uint bitrate)
+        {
+            return YakoPlayerNative.yako_player_try_get_bitrate(player, out bitrate) == 0;
+        }
+
+        public bool TryGetDuration(out Int64 duration)
+        {
+            return YakoPlayerNative.yako_player_try_get_duration(player, out duration) == 0;
+        }
+
+        public bool TryGetCurrentTime(out Int64 currentTime)
+        {
+            return YakoPlayerNative.yako_player_try_get_current_time(player, out currentTime) == 0;
+        }
+
+        public bool TryGetReplayGainPeak(out float peak)
+        {
+            return YakoPlayerNative.yako_player_try_get_replay_gain_peak(player, out peak) == 0;
+        }
+
         public bool IsPlaying()
         {
             return YakoPlayerNative.yako_player_is_playing(player) == 1;
         }
 
+        public bool? IsLossless()
+        {
+            int lossless = YakoPlayerNative.yako_player_is_lossless(player);
+            return lossless < 0 ? null : lossless == 1;
+        }
+
+        public bool HasVideo()
+        {
+            return YakoPlayerNative.yako_player_has_video(player) == 1;
+        }
+
+        public PlayerStatus GetStatus()
+        {
+            CheckError(YakoPlayerNative.yako_player_get_status(player, out PlayerStatus status));
+            return status;
+        }
+
         public float GetVolume()
         {
             return YakoPlayerNative.yako_player_get_volume(player);
@@ -176,19 +728,440 @@ namespace YakoPlayer
             CheckError(YakoPlayerNative.yako_player_set_mute(player, mute_int));
         }
 
+        public void SetVolumeCurve(VolumeCurve curve, float minDb = -60f)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_volume_curve(player, (int)curve, minDb));
+        }
+
+        public void SetSeamlessLoop(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_seamless_loop(player, enabled ? 1 : 0));
+        }
+
+        public void SetFinishedAction(FinishedAction action)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_finished_action(player, (int)action));
+        }
+
+        public void SetLazyCover(bool lazy)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_lazy_cover(player, lazy ? 1 : 0));
+        }
+
         public byte[]? GetAlbumCover()
         {
-            uint size = YakoPlayerNative.yako_player_get_album_cover_size(player);
-            byte[] result = new byte[size];
-            IntPtr address = YakoPlayerNative.yako_player_get_album_cover(player);
-            if (address == IntPtr.Zero)
+            return GetBytes((buffer, bufferLen) => YakoPlayerNative.yako_player_copy_album_cover(player, buffer, bufferLen));
+        }
+
+        public ulong GetCoverGeneration()
+        {
+            return YakoPlayerNative.yako_player_get_cover_generation(player);
+        }
+
+        public byte[]? GetAlbumCover(out ulong generation)
+        {
+            // 最后一次调用的封面和版本来自同一个快照
+            ulong snapshot = 0;
+            byte[]? cover = GetBytes((buffer, bufferLen) => YakoPlayerNative.yako_player_copy_album_cover_versioned(player, buffer, bufferLen, out snapshot));
+            generation = snapshot;
+            return cover;
+        }
+
+        public byte[]? GetCodecExtradata()
+        {
+            return GetBytes((buffer, bufferLen) => YakoPlayerNative.yako_player_copy_codec_extradata(player, buffer, bufferLen));
+        }
+
+        private delegate int BytesGetter(byte[]? buffer, UIntPtr bufferLen);
+
+        private static byte[]? GetBytes(BytesGetter getter)
+        {
+            // 两次调用之间数据可能变化，缓冲区不够大时按新的大小重试
+            byte[]? buffer = null;
+            while (true)
             {
-                return null;
+                int size = getter(buffer, new UIntPtr((uint)(buffer?.Length ?? 0)));
+                if (size < 0)
+                {
+                    CheckError(size);
+                }
+                if (size == 0)
+                {
+                    return null;
+                }
+                if (buffer != null && size <= buffer.Length)
+                {
+                    return size == buffer.Length ? buffer : buffer[..size];
+                }
+                buffer = new byte[size];
             }
-            Marshal.Copy(address, result, 0, (int)size);
+        }
+
+        public void SetDecodeChunkSize(ulong size)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_decode_chunk_size(player, new UIntPtr(size)));
+        }
+
+        public ulong GetDecodeChunkSize()
+        {
+            return YakoPlayerNative.yako_player_get_decode_chunk_size(player).ToUInt64();
+        }
+
+        public ulong GetDynamicBufferSize()
+        {
+            return YakoPlayerNative.yako_player_get_dynamic_buffer_size(player).ToUInt64();
+        }
+
+        public double GetOutputLatencyMs()
+        {
+            return YakoPlayerNative.yako_player_get_output_latency_ms(player);
+        }
+
+        public double GetBufferLatencyMs()
+        {
+            return YakoPlayerNative.yako_player_get_buffer_latency_ms(player);
+        }
+
+        public void SetSpeed(float speed)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_speed(player, speed));
+        }
+
+        public float GetSpeed()
+        {
+            return YakoPlayerNative.yako_player_get_speed(player);
+        }
+
+        public void SetEqEnabled(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_eq_enabled(player, enabled ? 1 : 0));
+        }
+
+        public void SetEqBandGain(ulong index, float gainDb)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_eq_band_gain(player, new UIntPtr(index), gainDb));
+        }
+
+        public void SetGraphicEq(float[] gainsDb)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_graphic_eq(player, gainsDb, new UIntPtr((uint)gainsDb.Length)));
+        }
+
+        public void SetEqAutoGain(bool autoGain)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_eq_auto_gain(player, autoGain ? 1 : 0));
+        }
+
+        public float GetEqCompensation()
+        {
+            return YakoPlayerNative.yako_player_get_eq_compensation(player);
+        }
+
+        public void SetChannelDelays(uint[] delaysMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_channel_delays(player, delaysMs, new UIntPtr((uint)delaysMs.Length)));
+        }
+
+        public void SetOutputChannelMap(uint[] channelMap)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_output_channel_map(player, channelMap, new UIntPtr((uint)channelMap.Length)));
+        }
+
+        public void SetMasterLimiter(bool enabled, float ceilingDb = -1)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_master_limiter(player, enabled ? 1 : 0, ceilingDb));
+        }
+
+        public void SetProcessingOrder(DspStage[] stages)
+        {
+            var values = Array.ConvertAll(stages, stage => (int)stage);
+            CheckError(YakoPlayerNative.yako_player_set_processing_order(player, values, new UIntPtr((uint)values.Length)));
+        }
+
+        public void LoadImpulseResponse(string path)
+        {
+            CheckError(YakoPlayerNative.yako_player_load_impulse_response(player, path));
+        }
+
+        public void SetConvolution(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_convolution(player, enabled ? 1 : 0));
+        }
+
+        public void SetReplayGain(ReplayGainMode mode, float preampDb = 0)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_replay_gain(player, (int)mode, preampDb));
+        }
+
+        public void SetReplayGainFallback(float fallbackDb, bool preventClipping = true)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_replay_gain_fallback(player, fallbackDb, preventClipping ? 1 : 0));
+        }
+
+        public float GetReplayGainPeak()
+        {
+            return YakoPlayerNative.yako_player_get_replay_gain_peak(player);
+        }
+
+        public void SetEndBehavior(EndBehavior behavior)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_end_behavior(player, (int)behavior));
+        }
+
+        public void SetErrorResilience(ErrorResilience resilience)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_error_resilience(player, (int)resilience));
+        }
+
+        public void SetAbLoop(long aMs, long bMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_ab_loop(player, aMs, bMs));
+        }
+
+        public void ClearAbLoop()
+        {
+            CheckError(YakoPlayerNative.yako_player_clear_ab_loop(player));
+        }
+
+        public void SetSleepTimer(SleepTimer timer, ulong durationMs = 0)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_sleep_timer(player, (int)timer, durationMs));
+        }
+
+        public void SetSleepFadeDuration(ulong durationMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_sleep_fade_duration(player, durationMs));
+        }
+
+        public long GetPlayedTime()
+        {
+            return YakoPlayerNative.yako_player_get_played_time(player);
+        }
+
+        public void SetScrobbleThreshold(double ratio, long maxMs)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_scrobble_threshold(player, ratio, maxMs));
+        }
+
+        public void SetBookmarksEnabled(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_bookmarks_enabled(player, enabled ? 1 : 0));
+        }
+
+        public void SetBookmarksPath(string path)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_bookmarks_path(player, path));
+        }
+
+        public int AddCuePoint(string name)
+        {
+            int index = YakoPlayerNative.yako_player_add_cue_point(player, name);
+            if (index < 0)
+            {
+                CheckError(index);
+            }
+            return index;
+        }
+
+        public CuePoint[] GetCuePoints()
+        {
+            int count = YakoPlayerNative.yako_player_get_cue_point_count(player);
+            CuePoint[] points = new CuePoint[count];
+            for (int i = 0; i < count; i++)
+            {
+                UIntPtr index = new UIntPtr((uint)i);
+                Int64 position = YakoPlayerNative.yako_player_get_cue_point_position(player, index);
+                if (position < 0)
+                {
+                    CheckError(-1);
+                }
+                points[i].Position = position;
+                points[i].Name = GetString((buffer, bufferLen) => YakoPlayerNative.yako_player_copy_cue_point_name(player, index, buffer, bufferLen));
+            }
+            return points;
+        }
+
+        public void JumpToCuePoint(int index)
+        {
+            CheckError(YakoPlayerNative.yako_player_jump_to_cue_point(player, new UIntPtr((uint)index)));
+        }
+
+        public void RemoveCuePoint(int index)
+        {
+            CheckError(YakoPlayerNative.yako_player_remove_cue_point(player, new UIntPtr((uint)index)));
+        }
+
+        public void AnalyzeQueueLoudness()
+        {
+            CheckError(YakoPlayerNative.yako_player_analyze_queue_loudness(player));
+        }
+
+        public void CancelLoudnessAnalysis()
+        {
+            CheckError(YakoPlayerNative.yako_player_cancel_loudness_analysis(player));
+        }
+
+        public void SetLoudnessCachePath(string path)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_loudness_cache_path(player, path));
+        }
+
+        public void SavePlaylist(string path)
+        {
+            CheckError(YakoPlayerNative.yako_player_save_playlist(player, path));
+        }
+
+        public void SetInputOption(string key, string value)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_input_option(player, key, value));
+        }
+
+        public void ClearInputOptions()
+        {
+            CheckError(YakoPlayerNative.yako_player_clear_input_options(player));
+        }
+
+        public uint AddOutput(string deviceName)
+        {
+            int id = YakoPlayerNative.yako_player_add_output(player, deviceName);
+            if (id < 0)
+            {
+                CheckError(id);
+            }
+            return (uint)id;
+        }
+
+        public void RemoveOutput(uint id)
+        {
+            CheckError(YakoPlayerNative.yako_player_remove_output(player, id));
+        }
+
+        public void SetOutputVolume(uint id, float volume)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_output_volume(player, id, volume));
+        }
+
+        public void SetOutputMute(uint id, bool mute)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_output_mute(player, id, mute ? 1 : 0));
+        }
+
+        public void OpenDeck(uint deck, string filePath)
+        {
+            CheckError(YakoPlayerNative.yako_player_open_deck(player, deck, filePath));
+        }
+
+        public void PlayDeck(uint deck)
+        {
+            CheckError(YakoPlayerNative.yako_player_play_deck(player, deck));
+        }
+
+        public void PauseDeck(uint deck)
+        {
+            CheckError(YakoPlayerNative.yako_player_pause_deck(player, deck));
+        }
+
+        public void CloseDeck(uint deck)
+        {
+            CheckError(YakoPlayerNative.yako_player_close_deck(player, deck));
+        }
+
+        public void SetDeckVolume(uint deck, float volume)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_deck_volume(player, deck, volume));
+        }
+
+        public void StartCapture(string path, CaptureFormat format)
+        {
+            CheckError(YakoPlayerNative.yako_player_start_capture(player, path, (int)format));
+        }
+
+        public void StopCapture()
+        {
+            CheckError(YakoPlayerNative.yako_player_stop_capture(player));
+        }
+
+        public void SetCaptureSplitOnTrack(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_capture_split_on_track(player, enabled ? 1 : 0));
+        }
+
+        public void SetCapturePreVolume(bool enabled)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_capture_pre_volume(player, enabled ? 1 : 0));
+        }
+
+        public ulong GetCaptureDroppedFrames()
+        {
+            return YakoPlayerNative.yako_player_get_capture_dropped_frames(player);
+        }
+
+        public ulong GetClipCount()
+        {
+            return YakoPlayerNative.yako_player_get_clip_count(player);
+        }
+
+        public void SetOpenMode(OpenMode mode, uint crossfadeMs = 0)
+        {
+            CheckError(YakoPlayerNative.yako_player_set_open_mode(player, (int)mode, crossfadeMs));
+        }
+
+        public void SetDspCallback(YakoDspCallback? callback)
+        {
+            dspCallback = callback;
+            CheckError(YakoPlayerNative.yako_player_set_dsp_callback(player, callback, IntPtr.Zero));
+        }
+
+        private delegate int StringGetter(byte[]? buffer, UIntPtr bufferLen);
+
+        private static string GetString(StringGetter getter)
+        {
+            // 先查询需要的大小，返回值包括结尾的 0，内容在两次调用之间变长时重新分配
+            int size = getter(null, UIntPtr.Zero);
+            while (true)
+            {
+                byte[] buffer = new byte[size];
+                int required = getter(buffer, new UIntPtr((uint)buffer.Length));
+                if (required <= size)
+                {
+                    return System.Text.Encoding.UTF8.GetString(buffer, 0, required - 1);
+                }
+                size = required;
+            }
+        }
+
+        public string GetDebugReport()
+        {
+            return GetString((buffer, bufferLen) => YakoPlayerNative.yako_player_get_debug_report(player, buffer, bufferLen));
+        }
+
+        public static string GetFfmpegVersion()
+        {
+            return GetString(YakoPlayerNative.yako_get_ffmpeg_version);
+        }
+
+        public static string[] GetSupportedFormats()
+        {
+            return GetString(YakoPlayerNative.yako_get_supported_formats).Split(',', StringSplitOptions.RemoveEmptyEntries);
+        }
+
+        public static FileProbeResult ProbeFile(string path)
+        {
+            CheckError(YakoPlayerNative.yako_probe_file(path, out FileProbeResult result));
             return result;
         }
 
+        public static void SetLogCallback(Action<LogLevel, string>? callback, LogLevel minLevel = LogLevel.Info)
+        {
+            YakoLogCallback? nativeCallback = null;
+            if (callback != null)
+            {
+                nativeCallback = (level, message) => callback((LogLevel)level, Marshal.PtrToStringUTF8(message) ?? "");
+            }
+            logCallback = nativeCallback;
+            CheckError(YakoPlayerNative.yako_set_log_callback(nativeCallback, (int)minLevel));
+        }
+
         public void Dispose()
         {
             player.Dispose();
@@ -3,40 +3,350 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+#if defined(_MSC_VER)
+#define YAKO_DEPRECATED(message) __declspec(deprecated(message))
+#else
+#define YAKO_DEPRECATED(message) __attribute__((deprecated(message)))
+#endif
+
 typedef struct YakoPlayer YakoPlayer;
 
+typedef struct PlayerStatus {
+  int64_t current_time;
+  int64_t duration;
+  int64_t bitrate;
+  bool is_playing;
+  bool is_seekable;
+  bool is_source_lost;
+  bool is_buffering;
+} PlayerStatus;
+
+typedef struct ProbeResult {
+  bool ok;
+  int64_t duration;
+  int64_t bitrate;
+  uint32_t sample_rate;
+  uint16_t channels;
+  uint32_t bits_per_sample;
+} ProbeResult;
+
+typedef struct FileProbeResult {
+  bool ok;
+  char container[32];
+  char codec[32];
+  int64_t duration;
+  uint32_t sample_rate;
+  uint16_t channels;
+  bool has_cover;
+  bool is_seekable;
+} FileProbeResult;
+
+enum YakoOpenMode {
+  YAKO_OPEN_MODE_REPLACE = 0,
+  YAKO_OPEN_MODE_ENQUEUE = 1,
+  YAKO_OPEN_MODE_CROSSFADE = 2,
+};
+
+enum YakoSleepTimer {
+  YAKO_SLEEP_TIMER_OFF = 0,
+  YAKO_SLEEP_TIMER_AFTER_DURATION = 1,
+  YAKO_SLEEP_TIMER_AFTER_CURRENT_TRACK = 2,
+};
+
+enum YakoReplayGainMode {
+  YAKO_REPLAY_GAIN_OFF = 0,
+  YAKO_REPLAY_GAIN_TRACK = 1,
+  YAKO_REPLAY_GAIN_ALBUM = 2,
+};
+
+enum YakoVolumeCurve {
+  YAKO_VOLUME_CURVE_EXPONENTIAL = 0,
+  YAKO_VOLUME_CURVE_LINEAR_DB = 1,
+  YAKO_VOLUME_CURVE_LINEAR_AMPLITUDE = 2,
+};
+
+enum YakoEndBehavior {
+  YAKO_END_BEHAVIOR_AUTO = -1,
+  YAKO_END_BEHAVIOR_REWIND_AND_WAIT = 0,
+  YAKO_END_BEHAVIOR_HOLD_POSITION = 1,
+  YAKO_END_BEHAVIOR_HANDOVER = 2,
+};
+
+enum YakoErrorResilience {
+  YAKO_ERROR_RESILIENCE_SKIP = 0,
+  YAKO_ERROR_RESILIENCE_CONCEAL = 1,
+  YAKO_ERROR_RESILIENCE_ABORT = 2,
+};
+
+enum YakoLogLevel {
+  YAKO_LOG_LEVEL_OFF = 0,
+  YAKO_LOG_LEVEL_ERROR = 1,
+  YAKO_LOG_LEVEL_WARN = 2,
+  YAKO_LOG_LEVEL_INFO = 3,
+  YAKO_LOG_LEVEL_DEBUG = 4,
+  YAKO_LOG_LEVEL_TRACE = 5,
+};
+
+enum YakoCaptureFormat {
+  YAKO_CAPTURE_FORMAT_WAV = 0,
+  YAKO_CAPTURE_FORMAT_FLAC = 1,
+};
+
+enum YakoError {
+  YAKO_ERROR_FAILED = -1,
+  YAKO_ERROR_NO_AUDIO_STREAM = -2,
+};
+
+enum YakoOpenPhase {
+  YAKO_OPEN_PHASE_PROBING = 0,
+  YAKO_OPEN_PHASE_READING_METADATA = 1,
+  YAKO_OPEN_PHASE_CREATING_DECODER = 2,
+  YAKO_OPEN_PHASE_PREBUFFERING = 3,
+  YAKO_OPEN_PHASE_READY = 4,
+};
+
+typedef void (*YakoLogCallback)(int32_t level, const char *message);
+
+typedef void (*YakoDspCallback)(float *samples, uint32_t frames, uint32_t channels, void *user_data);
+
+typedef void (*YakoOpenProgressCallback)(int32_t phase, void *user_data);
+
 struct YakoPlayer *yako_player_new(void);
 
 void yako_player_free(struct YakoPlayer *player);
 
 int32_t yako_player_open(struct YakoPlayer *player, const char *path);
 
+int32_t yako_player_open_with_progress(struct YakoPlayer *player,
+                                       const char *path,
+                                       YakoOpenProgressCallback callback,
+                                       void *user_data);
+
+int32_t yako_player_open_directory(struct YakoPlayer *player, const char *path, int32_t recursive);
+
+int32_t yako_player_audition(struct YakoPlayer *player,
+                             const char *path,
+                             double start_fraction,
+                             int64_t duration_ms);
+
+int32_t yako_player_probe_batch(const struct YakoPlayer *player,
+                                const char *const *paths,
+                                uintptr_t count,
+                                struct ProbeResult *results);
+
 int32_t yako_player_play(struct YakoPlayer *player);
 
 int32_t yako_player_pause(const struct YakoPlayer *player);
 
 int32_t yako_player_stop(const struct YakoPlayer *player);
 
+int32_t yako_player_reset(struct YakoPlayer *player, int32_t keep_volume, int32_t keep_device);
+
+int32_t yako_player_reopen_current(struct YakoPlayer *player);
+
+int32_t yako_player_recover_output(struct YakoPlayer *player);
+
+int32_t yako_player_wait_for_end(const struct YakoPlayer *player, int64_t timeout_ms);
+
 int32_t yako_player_seek(const struct YakoPlayer *player, int64_t position);
 
+int32_t yako_player_set_seek_debounce_ms(struct YakoPlayer *player, uint32_t debounce_ms);
+
+int32_t yako_player_set_rebuffer_threshold_ms(struct YakoPlayer *player, uint32_t threshold_ms);
+int32_t yako_player_set_target_buffer_ms(struct YakoPlayer *player, uint32_t target_ms);
+int32_t yako_player_set_prebuffer_ms(struct YakoPlayer *player, int32_t prebuffer_ms);
+
 uint32_t yako_player_get_bitrate(const struct YakoPlayer *player);
 
 int64_t yako_player_get_duration(const struct YakoPlayer *player);
 
 int64_t yako_player_get_current_time(const struct YakoPlayer *player);
 
+int64_t yako_player_get_current_time_us(const struct YakoPlayer *player);
+
+int32_t yako_player_try_get_bitrate(const struct YakoPlayer *player, uint32_t *bitrate);
+
+int32_t yako_player_try_get_duration(const struct YakoPlayer *player, int64_t *duration);
+
+int32_t yako_player_try_get_current_time(const struct YakoPlayer *player, int64_t *current_time);
+
+int32_t yako_player_try_get_replay_gain_peak(const struct YakoPlayer *player, float *peak);
+
 int32_t yako_player_is_playing(const struct YakoPlayer *player);
 
+int32_t yako_player_is_lossless(const struct YakoPlayer *player);
+
+int32_t yako_player_has_video(const struct YakoPlayer *player);
+
+int32_t yako_player_get_status(const struct YakoPlayer *player, struct PlayerStatus *status);
+
 float yako_player_get_volume(const struct YakoPlayer *player);
 
 int32_t yako_player_set_volume(struct YakoPlayer *player, float volume);
 
 int32_t yako_player_set_mute(const struct YakoPlayer *player, int32_t mute);
 
+int32_t yako_player_set_volume_curve(struct YakoPlayer *player, int32_t curve, float min_db);
+
+int32_t yako_player_set_seamless_loop(struct YakoPlayer *player, int32_t enabled);
+
+int32_t yako_player_set_finished_action(struct YakoPlayer *player, int32_t action);
+
+int32_t yako_player_set_lazy_cover(struct YakoPlayer *player, int32_t lazy);
+
+YAKO_DEPRECATED("use yako_player_copy_album_cover instead")
 const uint8_t *yako_player_get_album_cover(const struct YakoPlayer *player);
 
+YAKO_DEPRECATED("use yako_player_copy_album_cover instead")
 uint32_t yako_player_get_album_cover_size(const struct YakoPlayer *player);
 
+int32_t yako_player_copy_album_cover(const struct YakoPlayer *player,
+                                     uint8_t *buffer,
+                                     uintptr_t buffer_len);
+
+uint64_t yako_player_get_cover_generation(const struct YakoPlayer *player);
+
+int32_t yako_player_copy_album_cover_versioned(const struct YakoPlayer *player,
+                                               uint8_t *buffer,
+                                               uintptr_t buffer_len,
+                                               uint64_t *generation);
+
+int32_t yako_player_copy_codec_extradata(const struct YakoPlayer *player,
+                                         uint8_t *buffer,
+                                         uintptr_t buffer_len);
+
+int32_t yako_player_set_decode_chunk_size(struct YakoPlayer *player, uintptr_t size);
+
+uintptr_t yako_player_get_decode_chunk_size(const struct YakoPlayer *player);
+
+uintptr_t yako_player_get_dynamic_buffer_size(const struct YakoPlayer *player);
+
+double yako_player_get_output_latency_ms(const struct YakoPlayer *player);
+
+double yako_player_get_buffer_latency_ms(const struct YakoPlayer *player);
+
+int32_t yako_player_set_speed(struct YakoPlayer *player, float speed);
+
+float yako_player_get_speed(const struct YakoPlayer *player);
+
+int32_t yako_player_set_eq_enabled(struct YakoPlayer *player, int32_t enabled);
+
+int32_t yako_player_set_eq_band_gain(struct YakoPlayer *player, uintptr_t index, float gain_db);
+
+int32_t yako_player_set_graphic_eq(struct YakoPlayer *player, const float *gains_db, uintptr_t count);
+
+int32_t yako_player_set_eq_auto_gain(struct YakoPlayer *player, int32_t auto_gain);
+
+float yako_player_get_eq_compensation(const struct YakoPlayer *player);
+
+int32_t yako_player_set_channel_delays(struct YakoPlayer *player, const uint32_t *delays, uintptr_t count);
+
+int32_t yako_player_set_output_channel_map(struct YakoPlayer *player, const uint32_t *channel_map, uintptr_t count);
+
+int32_t yako_player_set_master_limiter(struct YakoPlayer *player, int32_t enabled, float ceiling_db);
+
+int32_t yako_player_set_processing_order(struct YakoPlayer *player, const int32_t *stages, uintptr_t count);
+
+int32_t yako_player_load_impulse_response(struct YakoPlayer *player, const char *path);
+
+int32_t yako_player_set_convolution(struct YakoPlayer *player, int32_t enabled);
+
+int32_t yako_player_set_replay_gain(struct YakoPlayer *player, int32_t mode, float preamp_db);
+
+int32_t yako_player_set_replay_gain_fallback(struct YakoPlayer *player,
+                                             float fallback_db,
+                                             int32_t prevent_clipping);
+
+float yako_player_get_replay_gain_peak(const struct YakoPlayer *player);
+
+int32_t yako_player_set_end_behavior(struct YakoPlayer *player, int32_t behavior);
+
+int32_t yako_player_set_error_resilience(struct YakoPlayer *player, int32_t resilience);
+
+int32_t yako_player_set_ab_loop(struct YakoPlayer *player, int64_t a_ms, int64_t b_ms);
+
+int32_t yako_player_clear_ab_loop(struct YakoPlayer *player);
+
+int32_t yako_player_set_sleep_timer(struct YakoPlayer *player, int32_t mode, uint64_t duration_ms);
+
+int32_t yako_player_set_sleep_fade_duration(struct YakoPlayer *player, uint64_t duration_ms);
+
+int64_t yako_player_get_played_time(const struct YakoPlayer *player);
+
+int32_t yako_player_set_scrobble_threshold(struct YakoPlayer *player, double ratio, int64_t max_ms);
+
+int32_t yako_player_set_bookmarks_enabled(struct YakoPlayer *player, int32_t enabled);
+
+int32_t yako_player_set_bookmarks_path(struct YakoPlayer *player, const char *path);
+
+int32_t yako_player_add_cue_point(struct YakoPlayer *player, const char *name);
+
+int32_t yako_player_get_cue_point_count(const struct YakoPlayer *player);
+
+int64_t yako_player_get_cue_point_position(const struct YakoPlayer *player, uintptr_t index);
+
+int32_t yako_player_copy_cue_point_name(const struct YakoPlayer *player, uintptr_t index, char *buffer, uintptr_t buffer_len);
+
+int32_t yako_player_jump_to_cue_point(const struct YakoPlayer *player, uintptr_t index);
+
+int32_t yako_player_remove_cue_point(struct YakoPlayer *player, uintptr_t index);
+
+int32_t yako_player_analyze_queue_loudness(struct YakoPlayer *player);
+
+int32_t yako_player_cancel_loudness_analysis(struct YakoPlayer *player);
+
+int32_t yako_player_set_loudness_cache_path(struct YakoPlayer *player, const char *path);
+
+int32_t yako_player_save_playlist(const struct YakoPlayer *player, const char *path);
+
+int32_t yako_player_get_debug_report(const struct YakoPlayer *player, char *buffer, uintptr_t buffer_len);
+
+int32_t yako_player_set_input_option(struct YakoPlayer *player, const char *key, const char *value);
+
+int32_t yako_player_clear_input_options(struct YakoPlayer *player);
+
+int32_t yako_player_add_output(struct YakoPlayer *player, const char *device_name);
+
+int32_t yako_player_remove_output(struct YakoPlayer *player, uint32_t id);
+
+int32_t yako_player_set_output_volume(struct YakoPlayer *player, uint32_t id, float volume);
+
+int32_t yako_player_set_output_mute(struct YakoPlayer *player, uint32_t id, int32_t mute);
+
+int32_t yako_player_open_deck(struct YakoPlayer *player, uint32_t deck, const char *path);
+
+int32_t yako_player_play_deck(struct YakoPlayer *player, uint32_t deck);
+
+int32_t yako_player_pause_deck(struct YakoPlayer *player, uint32_t deck);
+
+int32_t yako_player_close_deck(struct YakoPlayer *player, uint32_t deck);
+
+int32_t yako_player_set_deck_volume(struct YakoPlayer *player, uint32_t deck, float volume);
+
+int32_t yako_player_start_capture(struct YakoPlayer *player, const char *path, int32_t format);
+
+int32_t yako_player_stop_capture(struct YakoPlayer *player);
+
+int32_t yako_player_set_capture_split_on_track(struct YakoPlayer *player, int32_t enabled);
+
+int32_t yako_player_set_capture_pre_volume(struct YakoPlayer *player, int32_t enabled);
+
+uint64_t yako_player_get_capture_dropped_frames(const struct YakoPlayer *player);
+
+uint64_t yako_player_get_clip_count(const struct YakoPlayer *player);
+
+int32_t yako_player_set_open_mode(struct YakoPlayer *player, int32_t mode, uint32_t crossfade_ms);
+
+int32_t yako_player_set_dsp_callback(struct YakoPlayer *player, YakoDspCallback callback, void *user_data);
+
+int32_t yako_get_ffmpeg_version(char *buffer, uintptr_t buffer_len);
+
+int32_t yako_get_supported_formats(char *buffer, uintptr_t buffer_len);
+
+int32_t yako_probe_file(const char *path, struct FileProbeResult *result);
+
+int32_t yako_set_log_callback(YakoLogCallback callback, int32_t min_level);
+
 void clear_last_error(void);
 
 int32_t last_error_length(void);
@@ -0,0 +1,257 @@
+//! DEFLATE 解压（RFC 1951），只用于把压缩包中的文件完整解压到内存
+
+use snafu::{Snafu, ensure};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("invalid deflate data: {}", message))]
+    InvalidData {
+        message: String,
+    },
+
+    #[snafu(display("the decompressed data exceeds {} bytes", limit))]
+    TooLarge {
+        limit: usize,
+    },
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// 码长码表中码长的读取顺序
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const MAX_CODE_LENGTH: usize = 15;
+
+/// 按最低位优先读取比特，数据读完后补 0，最后检查是否读过了头
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, position: 0, buffer: 0, count: 0 }
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            let byte = self.data.get(self.position).copied().unwrap_or(0);
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self, bits: u32) -> u32 {
+        if self.count < bits {
+            self.refill();
+        }
+        (self.buffer & ((1u64 << bits) - 1)) as u32
+    }
+
+    fn consume(&mut self, bits: u32) {
+        self.buffer >>= bits;
+        self.count -= bits;
+    }
+
+    fn bits(&mut self, bits: u32) -> u32 {
+        let value = self.peek(bits);
+        self.consume(bits);
+        value
+    }
+
+    fn align_to_byte(&mut self) {
+        self.consume(self.count % 8);
+    }
+
+    /// 是否读取了超过数据长度的比特
+    fn is_overrun(&self) -> bool {
+        self.position * 8 - self.count as usize > self.data.len() * 8
+    }
+}
+
+/// 范式哈夫曼码的查找表，下标是按读取顺序排列的 max_length 个比特
+struct Huffman {
+    /// 高位是符号，低 4 位是码长，码长为 0 表示无效的编码
+    table: Vec<u16>,
+    max_length: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, Error> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left = 1i32;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            ensure!(left >= 0, InvalidDataSnafu { message: "over-subscribed Huffman code" });
+        }
+
+        let max_length = (1..=MAX_CODE_LENGTH).rev().find(|length| counts[*length] > 0).unwrap_or(0) as u32;
+        let mut next_code = [0u32; MAX_CODE_LENGTH + 2];
+        let mut code = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code = (code + counts[length - 1] as u32) << 1;
+            next_code[length] = code;
+        }
+
+        let mut table = vec![0u16; 1 << max_length];
+        for (symbol, length) in lengths.iter().enumerate() {
+            let length = *length as u32;
+            if length == 0 {
+                continue;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            // 比特流中哈夫曼码从最高位开始存放，查找表按读取顺序索引，需要反转
+            let reversed = code.reverse_bits() >> (32 - length);
+            let entry = ((symbol as u16) << 4) | length as u16;
+            for index in (reversed as usize..table.len()).step_by(1 << length) {
+                table[index] = entry;
+            }
+        }
+        Ok(Huffman { table, max_length })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, Error> {
+        ensure!(self.max_length > 0, InvalidDataSnafu { message: "empty Huffman code" });
+        let entry = self.table[reader.peek(self.max_length) as usize];
+        let length = (entry & 0xf) as u32;
+        ensure!(length > 0, InvalidDataSnafu { message: "invalid Huffman code" });
+        reader.consume(length);
+        Ok((entry >> 4) as usize)
+    }
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let literal_count = reader.bits(5) as usize + 257;
+    let distance_count = reader.bits(5) as usize + 1;
+    let code_length_count = reader.bits(4) as usize + 4;
+    ensure!(literal_count <= 286 && distance_count <= 30, InvalidDataSnafu { message: "too many codes" });
+
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.bits(3) as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                ensure!(index > 0, InvalidDataSnafu { message: "repeated length without a previous length" });
+                (lengths[index - 1], 3 + reader.bits(2) as usize)
+            },
+            17 => (0, 3 + reader.bits(3) as usize),
+            _ => (0, 11 + reader.bits(7) as usize),
+        };
+        ensure!(index + repeat <= lengths.len(), InvalidDataSnafu { message: "too many code lengths" });
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    ensure!(lengths[256] > 0, InvalidDataSnafu { message: "missing end-of-block code" });
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+/// 解压 DEFLATE 数据，解压后超过 limit 字节时返回错误
+pub fn inflate(data: &[u8], size_hint: usize, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::with_capacity(size_hint.min(limit));
+    let mut reader = BitReader::new(data);
+    loop {
+        let last = reader.bits(1) == 1;
+        match reader.bits(2) {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.bits(16) as usize;
+                let inverted = reader.bits(16) as usize;
+                ensure!(length == !inverted & 0xffff, InvalidDataSnafu { message: "stored block length mismatch" });
+                ensure!(output.len() + length <= limit, TooLargeSnafu { limit });
+                for _ in 0..length {
+                    output.push(reader.bits(8) as u8);
+                }
+            },
+            block_type @ (1 | 2) => {
+                let (literal, distance) = if block_type == 1 {
+                    fixed_tables()?
+                } else {
+                    dynamic_tables(&mut reader)?
+                };
+                loop {
+                    let symbol = literal.decode(&mut reader)?;
+                    ensure!(!reader.is_overrun(), InvalidDataSnafu { message: "unexpected end of data" });
+                    if symbol < 256 {
+                        ensure!(output.len() < limit, TooLargeSnafu { limit });
+                        output.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let index = symbol - 257;
+                    ensure!(index < LENGTH_BASE.len(), InvalidDataSnafu { message: "invalid length symbol" });
+                    let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32) as usize;
+                    let index = distance.decode(&mut reader)?;
+                    ensure!(index < DISTANCE_BASE.len(), InvalidDataSnafu { message: "invalid distance symbol" });
+                    let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32) as usize;
+                    ensure!(distance <= output.len(), InvalidDataSnafu { message: "distance too far back" });
+                    ensure!(output.len() + length <= limit, TooLargeSnafu { limit });
+                    // 复制的范围可能和输出重叠，只能逐字节复制
+                    let start = output.len() - distance;
+                    for offset in 0..length {
+                        output.push(output[start + offset]);
+                    }
+                }
+            },
+            _ => return InvalidDataSnafu { message: "invalid block type" }.fail(),
+        }
+        ensure!(!reader.is_overrun(), InvalidDataSnafu { message: "unexpected end of data" });
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// 计算 CRC-32（ZIP 使用的多项式）
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (index, entry) in table.iter_mut().enumerate() {
+        let mut value = index as u32;
+        for _ in 0..8 {
+            value = if value & 1 != 0 { 0xedb8_8320 ^ (value >> 1) } else { value >> 1 };
+        }
+        *entry = value;
+    }
+    !data.iter().fold(!0u32, |crc, byte| table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
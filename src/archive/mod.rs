@@ -0,0 +1,83 @@
+//! 从压缩包中播放音频文件，压缩包中的文件用 `album.zip!/03 track.flac` 形式的路径表示
+
+use std::path::{Path, PathBuf};
+
+use snafu::Snafu;
+
+pub mod inflate;
+pub mod zip;
+
+pub use self::zip::{ZipArchive, ZipEntry};
+
+/// 压缩包路径和压缩包内路径之间的分隔符
+pub const ENTRY_SEPARATOR: &str = "!/";
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("failed to read {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("invalid ZIP archive: {}", message))]
+    InvalidArchive {
+        message: String,
+    },
+
+    #[snafu(display("{} is password protected, encrypted archives are not supported", name))]
+    Encrypted {
+        name: String,
+    },
+
+    #[snafu(display("{} uses unsupported compression method {}", name, method))]
+    UnsupportedMethod {
+        name: String,
+        method: u16,
+    },
+
+    #[snafu(display("{} was not found in the archive", name))]
+    EntryNotFound {
+        name: String,
+    },
+
+    #[snafu(display("{} is too large to decompress ({} bytes)", name, size))]
+    EntryTooLarge {
+        name: String,
+        size: u64,
+    },
+
+    #[snafu(display("failed to decompress {}: {}", name, source))]
+    Inflate {
+        name: String,
+        source: inflate::Error,
+    },
+}
+
+/// 判断是否是 ZIP 压缩包
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.eq_ignore_ascii_case("zip"))
+        .unwrap_or(false)
+}
+
+/// 把 `album.zip!/03 track.flac` 拆分成压缩包路径和压缩包内的路径，不是压缩包中的文件时返回 None
+pub fn split_entry_path(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    let lowercase = path.to_ascii_lowercase();
+    ["!/", "!\\"].iter()
+        .filter_map(|separator| lowercase.find(&format!(".zip{}", separator)))
+        .min()
+        .map(|index| {
+            let archive_end = index + ".zip".len();
+            let name = path[archive_end + ENTRY_SEPARATOR.len()..].replace('\\', "/");
+            (PathBuf::from(&path[..archive_end]), name)
+        })
+}
+
+/// 拼接压缩包中文件的路径
+pub fn entry_path(archive: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}{}", archive.display(), ENTRY_SEPARATOR, name))
+}
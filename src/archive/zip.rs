@@ -0,0 +1,282 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use snafu::{ResultExt, OptionExt, ensure};
+
+use crate::audio::io::SeekableRead;
+use crate::player::scan;
+
+use super::inflate;
+use super::{Error, IoSnafu, InvalidArchiveSnafu, EncryptedSnafu, UnsupportedMethodSnafu, EntryNotFoundSnafu, EntryTooLargeSnafu, InflateSnafu};
+
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+/// 目录结束记录的长度（不包括注释）
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+/// 本地文件头的长度（不包括文件名和扩展字段）
+const LOCAL_HEADER_SIZE: u64 = 30;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 0x0001;
+
+/// 压缩的文件需要完整解压到内存，超过这个大小时不播放
+pub const MAX_INFLATED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// 压缩包中的一个文件
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// 压缩包内的路径，使用 / 分隔
+    pub name: String,
+    /// 解压后的大小
+    pub size: u64,
+    compressed_size: u64,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+}
+
+/// 小端序读取
+fn u16_at(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+fn u32_at(buffer: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buffer[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buffer: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut File, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buffer)
+}
+
+/// ZIP 压缩包，只读取目录，打开文件时再读取数据
+pub struct ZipArchive {
+    path: PathBuf,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive {
+    pub fn open<P: AsRef<Path>>(path: &P) -> Result<ZipArchive, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::open(&path).context(IoSnafu { path: path.clone() })?;
+        let entries = ZipArchive::read_central_directory(&mut file).context(IoSnafu { path: path.clone() })??;
+        Ok(ZipArchive { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 压缩包中的所有文件，按压缩包中的顺序排列
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// 压缩包中的音频文件，按压缩包中的顺序排列
+    pub fn audio_entries(&self) -> Vec<&ZipEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_dir() && scan::is_audio_file(Path::new(&entry.name)))
+            .collect()
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// 打开压缩包中的文件，未压缩的文件直接从压缩包中读取，压缩的文件解压到内存
+    pub fn open_entry(&self, name: &str) -> Result<Box<dyn SeekableRead>, Error> {
+        let entry = self.entry(name).context(EntryNotFoundSnafu { name })?;
+        ensure!(!entry.is_encrypted(), EncryptedSnafu { name });
+
+        let io_context = IoSnafu { path: self.path.clone() };
+        let mut file = File::open(&self.path).context(io_context.clone())?;
+        let mut header = [0u8; LOCAL_HEADER_SIZE as usize];
+        read_at(&mut file, entry.header_offset, &mut header).context(io_context.clone())?;
+        ensure!(u32_at(&header, 0) == LOCAL_HEADER_SIGNATURE, InvalidArchiveSnafu {
+            message: format!("the local header of {} is missing", name),
+        });
+        let data_offset = entry.header_offset + LOCAL_HEADER_SIZE + u16_at(&header, 26) as u64 + u16_at(&header, 28) as u64;
+
+        match entry.method {
+            METHOD_STORED => {
+                let mut reader = EntryReader { file, start: data_offset, size: entry.size, position: 0 };
+                reader.seek(SeekFrom::Start(0)).context(io_context)?;
+                Ok(Box::new(reader))
+            },
+            METHOD_DEFLATED => {
+                ensure!(entry.size <= MAX_INFLATED_SIZE, EntryTooLargeSnafu { name, size: entry.size });
+                let mut compressed = vec![0; entry.compressed_size as usize];
+                read_at(&mut file, data_offset, &mut compressed).context(io_context)?;
+                let data = inflate::inflate(&compressed, entry.size as usize, MAX_INFLATED_SIZE as usize)
+                    .context(InflateSnafu { name })?;
+                ensure!(inflate::crc32(&data) == entry.crc32, InvalidArchiveSnafu {
+                    message: format!("the checksum of {} does not match", name),
+                });
+                Ok(Box::new(Cursor::new(data)))
+            },
+            method => UnsupportedMethodSnafu { name, method }.fail(),
+        }
+    }
+
+    /// 读取中央目录，外层是读取错误，内层是格式错误
+    fn read_central_directory(file: &mut File) -> io::Result<Result<Vec<ZipEntry>, Error>> {
+        let file_size = file.seek(SeekFrom::End(0))?;
+        if file_size < END_OF_CENTRAL_DIRECTORY_SIZE {
+            return Ok(InvalidArchiveSnafu { message: "the file is too small" }.fail());
+        }
+
+        // 目录结束记录在文件末尾，后面可能有最长 65535 字节的注释
+        let tail_size = file_size.min(END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as u64);
+        let tail_offset = file_size - tail_size;
+        let mut tail = vec![0; tail_size as usize];
+        read_at(file, tail_offset, &mut tail)?;
+        let end_position = match (0..=tail.len() - END_OF_CENTRAL_DIRECTORY_SIZE as usize)
+            .rev()
+            .find(|position| u32_at(&tail, *position) == END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+        {
+            Some(position) => position,
+            None => return Ok(InvalidArchiveSnafu { message: "not a ZIP archive" }.fail()),
+        };
+        let end = &tail[end_position..];
+        let mut entry_count = u16_at(end, 10) as u64;
+        let mut directory_size = u32_at(end, 12) as u64;
+        let mut directory_offset = u32_at(end, 16) as u64;
+
+        // ZIP64：目录结束记录中的值放不下时，从 ZIP64 目录结束记录中读取
+        let end_offset = tail_offset + end_position as u64;
+        if (entry_count == u16::MAX as u64 || directory_size == u32::MAX as u64 || directory_offset == u32::MAX as u64)
+            && end_offset >= 20
+        {
+            let mut locator = [0u8; 20];
+            read_at(file, end_offset - 20, &mut locator)?;
+            if u32_at(&locator, 0) == ZIP64_LOCATOR_SIGNATURE {
+                let mut zip64_end = [0u8; 56];
+                read_at(file, u64_at(&locator, 8), &mut zip64_end)?;
+                if u32_at(&zip64_end, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+                    return Ok(InvalidArchiveSnafu { message: "the ZIP64 end of central directory is missing" }.fail());
+                }
+                entry_count = u64_at(&zip64_end, 32);
+                directory_size = u64_at(&zip64_end, 40);
+                directory_offset = u64_at(&zip64_end, 48);
+            }
+        }
+        if directory_offset.saturating_add(directory_size) > file_size {
+            return Ok(InvalidArchiveSnafu { message: "the central directory is out of range" }.fail());
+        }
+
+        let mut directory = vec![0; directory_size as usize];
+        read_at(file, directory_offset, &mut directory)?;
+        let mut entries = Vec::new();
+        let mut position = 0;
+        while entries.len() < entry_count as usize && position + 46 <= directory.len() {
+            let header = &directory[position..];
+            if u32_at(header, 0) != CENTRAL_DIRECTORY_SIGNATURE {
+                return Ok(InvalidArchiveSnafu { message: "corrupted central directory" }.fail());
+            }
+            let name_length = u16_at(header, 28) as usize;
+            let extra_length = u16_at(header, 30) as usize;
+            let comment_length = u16_at(header, 32) as usize;
+            let record_length = 46 + name_length + extra_length + comment_length;
+            if position + record_length > directory.len() {
+                return Ok(InvalidArchiveSnafu { message: "corrupted central directory" }.fail());
+            }
+
+            let flags = u16_at(header, 8);
+            // 没有 UTF-8 标记（0x0800）的文件名一般是本地编码，无法可靠地识别，一律按 UTF-8 尽量解码
+            let name = String::from_utf8_lossy(&header[46..46 + name_length]).replace('\\', "/");
+
+            let mut entry = ZipEntry {
+                name,
+                size: u32_at(header, 24) as u64,
+                compressed_size: u32_at(header, 20) as u64,
+                method: u16_at(header, 10),
+                flags,
+                crc32: u32_at(header, 16),
+                header_offset: u32_at(header, 42) as u64,
+            };
+            ZipArchive::apply_zip64_extra(&mut entry, &header[46 + name_length..46 + name_length + extra_length]);
+            entries.push(entry);
+            position += record_length;
+        }
+        Ok(Ok(entries))
+    }
+
+    /// ZIP64 扩展字段只包含原来的字段中值为 0xFFFFFFFF 的那些，按固定顺序排列
+    fn apply_zip64_extra(entry: &mut ZipEntry, mut extra: &[u8]) {
+        while extra.len() >= 4 {
+            let id = u16_at(extra, 0);
+            let size = (u16_at(extra, 2) as usize).min(extra.len() - 4);
+            if id == ZIP64_EXTRA_FIELD_ID {
+                let mut data = &extra[4..4 + size];
+                for field in [&mut entry.size, &mut entry.compressed_size, &mut entry.header_offset] {
+                    if *field == u32::MAX as u64 && data.len() >= 8 {
+                        *field = u64_at(data, 0);
+                        data = &data[8..];
+                    }
+                }
+                return;
+            }
+            extra = &extra[4 + size..];
+        }
+    }
+}
+
+/// 读取压缩包中一段未压缩的数据
+struct EntryReader {
+    file: File,
+    start: u64,
+    size: u64,
+    position: u64,
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let length = (buf.len() as u64).min(remaining) as usize;
+        if length == 0 {
+            return Ok(0);
+        }
+        let read = self.file.read(&mut buf[..length])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for EntryReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            SeekFrom::End(offset) => self.size as i64 + offset,
+        };
+        if position < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"));
+        }
+        let position = position as u64;
+        self.file.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
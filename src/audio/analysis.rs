@@ -0,0 +1,255 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::fft;
+use super::sample::AudioSample;
+
+/// 频谱分析使用的采样点数
+pub const ANALYSIS_WINDOW: usize = 2048;
+
+/// 默认的更新频率（次/秒），足够界面流畅地显示
+pub const DEFAULT_UPDATE_RATE_HZ: f32 = 30.;
+/// 可以设置的最高更新频率（次/秒）
+pub const MAX_UPDATE_RATE_HZ: f32 = 1000.;
+
+/// 频谱显示的最低频率
+const SPECTRUM_MIN_FREQUENCY: f32 = 20.;
+/// 频谱显示的动态范围（分贝）
+const SPECTRUM_RANGE_DB: f32 = 70.;
+
+struct AnalysisState {
+    /// 最近的单声道混合样本（环形缓冲区）
+    samples: Vec<f32>,
+    /// 下一个写入位置
+    write_position: usize,
+    /// 上次读取以来左右声道的峰值
+    peaks: [f32; 2],
+}
+
+/// 按更新频率保留的上一次结果，间隔之内的读取直接返回
+struct AnalysisCache {
+    interval: Duration,
+    peaks: Option<(Instant, (f32, f32))>,
+    spectrum: Option<(Instant, Vec<f32>)>,
+}
+
+/// 输出音频分析，在音频回调中收集样本，在其他线程上计算峰值和频谱
+///
+/// 峰值和频谱最多按更新频率重新计算，更频繁的读取返回上一次的结果，界面刷新得再快也不会增加计算量
+pub struct AnalysisTap {
+    enabled: AtomicBool,
+    sample_rate: AtomicU32,
+    state: Mutex<AnalysisState>,
+    cache: Mutex<AnalysisCache>,
+}
+
+impl AnalysisTap {
+    pub fn new() -> AnalysisTap {
+        AnalysisTap {
+            enabled: AtomicBool::new(false),
+            sample_rate: AtomicU32::new(48000),
+            state: Mutex::new(AnalysisState {
+                samples: vec![0.; ANALYSIS_WINDOW],
+                write_position: 0,
+                peaks: [0.; 2],
+            }),
+            cache: Mutex::new(AnalysisCache {
+                interval: Duration::from_secs_f32(1. / DEFAULT_UPDATE_RATE_HZ),
+                peaks: None,
+                spectrum: None,
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// 开关分析，关闭时音频回调中不做任何处理
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            // 和读取时相同，先锁定缓存再锁定样本
+            let mut cache = self.cache.lock().unwrap();
+            cache.peaks = None;
+            cache.spectrum = None;
+            let mut state = self.state.lock().unwrap();
+            state.samples.iter_mut().for_each(|sample| *sample = 0.);
+            state.peaks = [0.; 2];
+        }
+    }
+
+    /// 设置峰值和频谱每秒最多重新计算的次数，调用者保证范围在 0 ~ MAX_UPDATE_RATE_HZ 之间（不包括 0）
+    pub fn set_update_rate_hz(&self, rate_hz: f32) {
+        self.cache.lock().unwrap().interval = Duration::from_secs_f32(1. / rate_hz);
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// 在音频回调中收集一块样本，分析数据正在被读取时跳过
+    pub fn push_block(&self, block: &[AudioSample]) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        for audio_sample in block {
+            let channels = audio_sample.as_slice();
+            if channels.is_empty() {
+                continue;
+            }
+            let left = channels[0];
+            let right = if channels.len() > 1 { channels[1] } else { left };
+            state.peaks[0] = state.peaks[0].max(left.abs());
+            state.peaks[1] = state.peaks[1].max(right.abs());
+
+            let mono = channels.iter().sum::<f32>() / channels.len() as f32;
+            let position = state.write_position;
+            state.samples[position] = mono;
+            state.write_position = (position + 1) % ANALYSIS_WINDOW;
+        }
+    }
+
+    /// 获取上次读取以来左右声道的峰值（振幅比例），读取后重置
+    ///
+    /// 距离上次读取不到更新间隔时返回上次的结果，不重置，峰值继续累积到下一次更新
+    pub fn take_peak_levels(&self) -> (f32, f32) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((time, peaks)) = cache.peaks {
+            if time.elapsed() < cache.interval {
+                return peaks;
+            }
+        }
+        let mut state = self.state.lock().unwrap();
+        let peaks = (state.peaks[0], state.peaks[1]);
+        state.peaks = [0.; 2];
+        cache.peaks = Some((Instant::now(), peaks));
+        peaks
+    }
+
+    /// 计算对数分布的频谱，每个频段的值范围为 0 ~ 1
+    ///
+    /// 距离上次计算不到更新间隔并且频段数相同时返回上次的结果
+    pub fn spectrum(&self, bands: usize) -> Vec<f32> {
+        if bands == 0 {
+            return Vec::new();
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((time, spectrum)) = cache.spectrum.as_ref() {
+            if time.elapsed() < cache.interval && spectrum.len() == bands {
+                return spectrum.clone();
+            }
+        }
+        let spectrum = self.compute_spectrum(bands);
+        cache.spectrum = Some((Instant::now(), spectrum.clone()));
+        spectrum
+    }
+
+    fn compute_spectrum(&self, bands: usize) -> Vec<f32> {
+        let mut re = vec![0.; ANALYSIS_WINDOW];
+        let mut im = vec![0.; ANALYSIS_WINDOW];
+        {
+            let state = self.state.lock().unwrap();
+            // 按时间顺序取出样本并加汉宁窗
+            for i in 0..ANALYSIS_WINDOW {
+                let sample = state.samples[(state.write_position + i) % ANALYSIS_WINDOW];
+                let window = 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / (ANALYSIS_WINDOW - 1) as f32).cos();
+                re[i] = sample * window;
+            }
+        }
+        fft::fft(&mut re, &mut im);
+
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed) as f32;
+        let bin_width = sample_rate / ANALYSIS_WINDOW as f32;
+        let max_frequency = (sample_rate / 2.).min(20000.);
+        let ratio = (max_frequency / SPECTRUM_MIN_FREQUENCY).powf(1. / bands as f32);
+        // 汉宁窗下满幅正弦波的幅度约为 N / 4
+        let reference = ANALYSIS_WINDOW as f32 / 4.;
+
+        (0..bands)
+            .map(|band| {
+                let low = SPECTRUM_MIN_FREQUENCY * ratio.powi(band as i32);
+                let high = low * ratio;
+                let low_bin = ((low / bin_width) as usize).max(1);
+                let high_bin = ((high / bin_width) as usize).max(low_bin + 1).min(ANALYSIS_WINDOW / 2);
+                let magnitude = (low_bin..high_bin)
+                    .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt())
+                    .fold(0., f32::max);
+                let db = 20. * (magnitude / reference).max(1e-9).log10();
+                ((db + SPECTRUM_RANGE_DB) / SPECTRUM_RANGE_DB).clamp(0., 1.)
+            })
+            .collect()
+    }
+}
+
+impl Default for AnalysisTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_tap(rate_hz: f32) -> AnalysisTap {
+        let tap = AnalysisTap::new();
+        tap.set_enabled(true);
+        tap.set_update_rate_hz(rate_hz);
+        tap
+    }
+
+    /// 在 duration 内不停地读取频谱，返回重新计算的次数
+    fn count_spectrum_updates(tap: &AnalysisTap, duration: Duration) -> usize {
+        let started = Instant::now();
+        let mut updates = 0;
+        let mut last_update = None;
+        while started.elapsed() < duration {
+            tap.spectrum(16);
+            let update = tap.cache.lock().unwrap().spectrum.as_ref().map(|(time, _)| *time);
+            if update != last_update {
+                updates += 1;
+                last_update = update;
+            }
+        }
+        updates
+    }
+
+    #[test]
+    fn tight_loop_recomputes_the_spectrum_at_the_update_rate() {
+        let tap = enabled_tap(20.);
+        let updates = count_spectrum_updates(&tap, Duration::from_millis(300));
+        // 每 50 毫秒最多一次，再加上第一次读取
+        assert!((2..=7).contains(&updates), "{} updates", updates);
+    }
+
+    #[test]
+    fn lower_rate_produces_fewer_updates() {
+        let slow = count_spectrum_updates(&enabled_tap(10.), Duration::from_millis(200));
+        let fast = count_spectrum_updates(&enabled_tap(200.), Duration::from_millis(200));
+        assert!(slow < fast, "{} updates at 10 Hz, {} at 200 Hz", slow, fast);
+    }
+
+    #[test]
+    fn other_band_count_is_recomputed() {
+        let tap = enabled_tap(1.);
+        assert_eq!(tap.spectrum(16).len(), 16);
+        assert_eq!(tap.spectrum(32).len(), 32);
+    }
+
+    #[test]
+    fn peaks_keep_accumulating_until_the_next_update() {
+        let tap = enabled_tap(1.);
+        tap.push_block(&[AudioSample::from_slice(&[0.5, 0.25])]);
+        assert_eq!(tap.take_peak_levels(), (0.5, 0.25));
+        // 间隔之内返回上一次的结果，新的峰值留到下一次更新
+        tap.push_block(&[AudioSample::from_slice(&[0.75, 0.])]);
+        assert_eq!(tap.take_peak_levels(), (0.5, 0.25));
+        assert_eq!(tap.state.lock().unwrap().peaks, [0.75, 0.]);
+    }
+}
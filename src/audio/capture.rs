@@ -0,0 +1,566 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use ffmpeg::{codec, encoder, format, frame};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::player::event::PlayerEvent;
+
+use super::sample::AudioSample;
+
+/// 录制缓冲区能容纳的时长（秒），写入线程来不及处理时超出的部分会被丢弃
+const CAPTURE_BUFFER_SECONDS: usize = 2;
+
+/// 写入线程每次从缓冲区取出的最大帧数
+const CAPTURE_READ_FRAMES: usize = 4096;
+
+/// 缓冲区为空时写入线程等待的间隔
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 编码器没有指定每帧大小时使用的帧数
+const DEFAULT_FLAC_FRAME_SIZE: usize = 4608;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("the audio device has not been initialized"))]
+    DeviceNotReady,
+
+    #[snafu(display("failed to create capture file {}: {}", path.display(), source))]
+    CreateFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to write capture file: {}", source))]
+    WriteFile {
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to encode capture: {}", message))]
+    Encode {
+        message: String,
+        #[snafu(source(from(ffmpeg::Error, Box::new)))]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("failed to encode capture: {}", message))]
+    EncoderUnavailable {
+        message: String,
+    },
+}
+
+/// 录制文件的格式，都以 16 位整数保存
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Wav,
+    Flac,
+}
+
+/// 写入文件的编码方式，录制和导出共用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileEncoding {
+    /// 16 位整数 WAV
+    Wav16,
+    /// 32 位浮点 WAV
+    Wav32Float,
+    /// 16 位 FLAC
+    Flac,
+}
+
+impl From<CaptureFormat> for FileEncoding {
+    fn from(format: CaptureFormat) -> Self {
+        match format {
+            CaptureFormat::Wav => FileEncoding::Wav16,
+            CaptureFormat::Flac => FileEncoding::Flac,
+        }
+    }
+}
+
+/// 音频回调中的录制入口，只把数据写入无锁的环形缓冲区，编码和写入文件在单独的线程中进行
+pub struct CaptureTap {
+    enabled: AtomicBool,
+    /// 录制音量调整之前的数据
+    pre_volume: AtomicBool,
+    sample_rate: Arc<AtomicU32>,
+    /// 缓冲区已满而丢弃的帧数
+    dropped_frames: Arc<AtomicU64>,
+    producer: Mutex<Option<Producer<AudioSample>>>,
+}
+
+impl CaptureTap {
+    pub fn new() -> CaptureTap {
+        CaptureTap {
+            enabled: AtomicBool::new(false),
+            pre_volume: AtomicBool::new(false),
+            sample_rate: Arc::new(AtomicU32::new(48000)),
+            dropped_frames: Arc::new(AtomicU64::new(0)),
+            producer: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn set_pre_volume(&self, pre_volume: bool) {
+        self.pre_volume.store(pre_volume, Ordering::Relaxed);
+    }
+
+    pub fn is_pre_volume(&self) -> bool {
+        self.pre_volume.load(Ordering::Relaxed)
+    }
+
+    /// 录制开始以来因为写入线程来不及处理而丢弃的帧数
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// 在音频回调中写入一块数据，pre_volume 表示这块数据是否在音量调整之前
+    ///
+    /// 开始或者停止录制的瞬间缓冲区被占用时丢弃这一块，不在实时线程上等待锁
+    pub fn push_block(&self, block: &[AudioSample], pre_volume: bool) {
+        if !self.is_enabled() || self.is_pre_volume() != pre_volume {
+            return;
+        }
+        let mut producer = match self.producer.try_lock() {
+            Ok(producer) => producer,
+            Err(_) => {
+                self.dropped_frames.fetch_add(block.len() as u64, Ordering::Relaxed);
+                return;
+            },
+        };
+        if let Some(producer) = producer.as_mut() {
+            let written = producer.push_slice(block);
+            if written < block.len() {
+                self.dropped_frames.fetch_add((block.len() - written) as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 创建新的缓冲区并开始接收数据，返回写入线程使用的消费者
+    fn attach(&self) -> Consumer<AudioSample> {
+        let capacity = self.sample_rate.load(Ordering::Relaxed) as usize * CAPTURE_BUFFER_SECONDS;
+        let (producer, consumer) = RingBuffer::<AudioSample>::new(capacity.max(CAPTURE_READ_FRAMES)).split();
+        *self.producer.lock().unwrap() = Some(producer);
+        self.dropped_frames.store(0, Ordering::Relaxed);
+        self.enabled.store(true, Ordering::Relaxed);
+        consumer
+    }
+
+    /// 停止接收数据
+    fn detach(&self) {
+        self.enabled.store(false, Ordering::Relaxed);
+        *self.producer.lock().unwrap() = None;
+    }
+}
+
+impl Default for CaptureTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 录制文件的写入器
+pub(crate) trait CaptureWriter: Send {
+    /// 写入交错的采样
+    fn write(&mut self, samples: &[AudioSample]) -> Result<(), Error>;
+    /// 已经写入的帧数
+    fn frames(&self) -> u64;
+    /// 写入文件尾并关闭文件
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1., 1.) * i16::MAX as f32) as i16
+}
+
+/// 直接写入 16 位整数或者 32 位浮点 PCM 的 WAV 文件，结束时补写文件头中的长度
+struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    channels: u16,
+    float: bool,
+    frames: u64,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+
+    fn create(path: &Path, sample_rate: u32, channels: u16, float: bool) -> Result<WavWriter, Error> {
+        let file = File::create(path).context(CreateFileSnafu { path: path.to_path_buf() })?;
+        let mut writer = WavWriter {
+            file: BufWriter::new(file),
+            sample_rate,
+            channels,
+            float,
+            frames: 0,
+        };
+        writer.write_header(0).context(WriteFileSnafu)?;
+        Ok(writer)
+    }
+
+    fn bytes_per_sample(&self) -> u16 {
+        if self.float { 4 } else { 2 }
+    }
+
+    fn write_header(&mut self, data_size: u32) -> std::io::Result<()> {
+        let block_align = self.channels * self.bytes_per_sample();
+        self.file.write_all(b"RIFF")?;
+        self.file.write_all(&(Self::HEADER_SIZE - 8).saturating_add(data_size).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        self.file.write_all(&16u32.to_le_bytes())?;
+        // PCM 为 1，IEEE 浮点为 3
+        let format_tag: u16 = if self.float { 3 } else { 1 };
+        self.file.write_all(&format_tag.to_le_bytes())?;
+        self.file.write_all(&self.channels.to_le_bytes())?;
+        self.file.write_all(&self.sample_rate.to_le_bytes())?;
+        self.file.write_all(&(self.sample_rate * block_align as u32).to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&(self.bytes_per_sample() * 8).to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&data_size.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl CaptureWriter for WavWriter {
+    fn write(&mut self, samples: &[AudioSample]) -> Result<(), Error> {
+        for audio_sample in samples {
+            for sample in audio_sample.as_slice() {
+                if self.float {
+                    self.file.write_all(&sample.to_le_bytes()).context(WriteFileSnafu)?;
+                } else {
+                    self.file.write_all(&to_i16(*sample).to_le_bytes()).context(WriteFileSnafu)?;
+                }
+            }
+        }
+        self.frames += samples.len() as u64;
+        Ok(())
+    }
+
+    fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        // 超过 4 GB 的部分无法在文件头中表示，大多数软件仍然可以按文件长度读取
+        let data_size = (self.frames * self.channels as u64 * self.bytes_per_sample() as u64).min((u32::MAX - Self::HEADER_SIZE) as u64) as u32;
+        self.file.seek(SeekFrom::Start(0)).context(WriteFileSnafu)?;
+        self.write_header(data_size).context(WriteFileSnafu)?;
+        self.file.flush().context(WriteFileSnafu)
+    }
+}
+
+/// 使用 FFmpeg 的 FLAC 编码器写入文件
+struct FlacWriter {
+    output: format::context::Output,
+    encoder: encoder::audio::Encoder,
+    sample_rate: u32,
+    channel_layout: ffmpeg::ChannelLayout,
+    channels: usize,
+    frame_size: usize,
+    /// 不足一帧的交错采样
+    pending: Vec<i16>,
+    frames: u64,
+}
+
+impl FlacWriter {
+    fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<FlacWriter, Error> {
+        let codec = encoder::find(codec::Id::FLAC)
+            .context(EncoderUnavailableSnafu {
+                message: "the FLAC encoder is not available".to_string(),
+            })?
+            .audio()
+            .context(EncodeSnafu {
+                message: "the FLAC encoder is not an audio encoder".to_string(),
+            })?;
+        let mut output = format::output_as(&path, "flac").context(EncodeSnafu {
+            message: format!("failed to create {}", path.display()),
+        })?;
+        let channel_layout = ffmpeg::ChannelLayout::default(channels.into());
+        let encoder = {
+            let mut stream = output.add_stream(codec).context(EncodeSnafu {
+                message: "failed to add the audio stream".to_string(),
+            })?;
+            let context = codec::context::Context::from_parameters(stream.parameters()).context(EncodeSnafu {
+                message: "failed to create the encoder context".to_string(),
+            })?;
+            let mut encoder = context.encoder().audio().context(EncodeSnafu {
+                message: "failed to create the encoder".to_string(),
+            })?;
+            encoder.set_rate(sample_rate as i32);
+            encoder.set_channel_layout(channel_layout);
+            encoder.set_channels(channels.into());
+            encoder.set_format(format::Sample::I16(format::sample::Type::Packed));
+            encoder.set_time_base((1, sample_rate as i32));
+            stream.set_time_base((1, sample_rate as i32));
+            let encoder = encoder.open_as(codec).context(EncodeSnafu {
+                message: "failed to open the FLAC encoder".to_string(),
+            })?;
+            stream.set_parameters(&encoder);
+            encoder
+        };
+        output.write_header().context(EncodeSnafu {
+            message: "failed to write the file header".to_string(),
+        })?;
+        let frame_size = match encoder.frame_size() {
+            0 => DEFAULT_FLAC_FRAME_SIZE,
+            frame_size => frame_size as usize,
+        };
+        Ok(FlacWriter {
+            output,
+            encoder,
+            sample_rate,
+            channel_layout,
+            channels: channels.into(),
+            frame_size,
+            pending: Vec::new(),
+            frames: 0,
+        })
+    }
+
+    /// 把 samples 个帧的交错采样送入编码器
+    fn encode(&mut self, samples: usize) -> Result<(), Error> {
+        let mut audio_frame = frame::Audio::new(
+            format::Sample::I16(format::sample::Type::Packed),
+            samples,
+            self.channel_layout);
+        audio_frame.set_channels(self.channels as u16);
+        audio_frame.set_rate(self.sample_rate);
+        audio_frame.set_pts(Some(self.frames as i64));
+        let length = samples * self.channels;
+        for (bytes, sample) in audio_frame.data_mut(0).chunks_exact_mut(2).zip(self.pending.drain(..length)) {
+            bytes.copy_from_slice(&sample.to_ne_bytes());
+        }
+        self.frames += samples as u64;
+        self.encoder.send_frame(&audio_frame).context(EncodeSnafu {
+            message: "failed to send a frame to the encoder".to_string(),
+        })?;
+        self.write_packets()
+    }
+
+    fn write_packets(&mut self) -> Result<(), Error> {
+        let time_base = self.output.stream(0).map(|stream| stream.time_base());
+        let mut packet = ffmpeg::Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            if let Some(time_base) = time_base {
+                packet.rescale_ts((1, self.sample_rate as i32), time_base);
+            }
+            packet.write_interleaved(&mut self.output).context(EncodeSnafu {
+                message: "failed to write a packet".to_string(),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl CaptureWriter for FlacWriter {
+    fn write(&mut self, samples: &[AudioSample]) -> Result<(), Error> {
+        for audio_sample in samples {
+            self.pending.extend(audio_sample.as_slice().iter().map(|sample| to_i16(*sample)));
+        }
+        // FLAC 编码器除了最后一帧以外只接受固定大小的帧
+        while self.pending.len() >= self.frame_size * self.channels {
+            self.encode(self.frame_size)?;
+        }
+        Ok(())
+    }
+
+    fn frames(&self) -> u64 {
+        self.frames + (self.pending.len() / self.channels.max(1)) as u64
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        let remaining = self.pending.len() / self.channels.max(1);
+        if remaining > 0 {
+            self.encode(remaining)?;
+        }
+        self.encoder.send_eof().context(EncodeSnafu {
+            message: "failed to flush the encoder".to_string(),
+        })?;
+        self.write_packets()?;
+        self.output.write_trailer().context(EncodeSnafu {
+            message: "failed to write the file trailer".to_string(),
+        })
+    }
+}
+
+pub(crate) fn create_writer(path: &Path, encoding: FileEncoding, sample_rate: u32, channels: u16) -> Result<Box<dyn CaptureWriter>, Error> {
+    Ok(match encoding {
+        FileEncoding::Wav16 => Box::new(WavWriter::create(path, sample_rate, channels, false)?),
+        FileEncoding::Wav32Float => Box::new(WavWriter::create(path, sample_rate, channels, true)?),
+        FileEncoding::Flac => Box::new(FlacWriter::create(path, sample_rate, channels)?),
+    })
+}
+
+/// 分割后第 index 个文件的路径，第一个文件使用原来的路径，之后为 `name-002.flac` 的形式
+fn split_path(path: &Path, index: usize) -> PathBuf {
+    if index <= 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{:03}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}-{:03}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// 写入线程的状态
+struct CaptureThread {
+    path: PathBuf,
+    format: CaptureFormat,
+    /// 当前文件的序号，从 1 开始
+    index: usize,
+    sample_rate: u32,
+    channels: u16,
+    writer: Box<dyn CaptureWriter>,
+}
+
+impl CaptureThread {
+    /// 结束当前文件，按当前的格式写入下一个文件
+    fn split(&mut self) -> Result<(), Error> {
+        self.index += 1;
+        let next = create_writer(&split_path(&self.path, self.index), self.format.into(), self.sample_rate, self.channels)?;
+        std::mem::replace(&mut self.writer, next).finish()
+    }
+
+    fn run(
+        mut self,
+        mut consumer: Consumer<AudioSample>,
+        events: Receiver<PlayerEvent>,
+        device_sample_rate: Arc<AtomicU32>,
+        split_on_track: Arc<AtomicBool>,
+        stop: Arc<AtomicBool>,
+    ) -> Result<(), Error> {
+        let mut buffer = vec![AudioSample::silence(self.channels as usize); CAPTURE_READ_FRAMES];
+        loop {
+            let stopping = stop.load(Ordering::Relaxed);
+            // 同一次切换可能连续发出多个事件，当前文件没有数据时不再分割
+            let track_changed = events.try_iter()
+                .filter(|event| matches!(event,
+                    PlayerEvent::NowPlaying { .. }
+                    | PlayerEvent::TrackChanged(_)
+                    | PlayerEvent::StreamTitleChanged(_)))
+                .count() > 0;
+            if track_changed && split_on_track.load(Ordering::Relaxed) && self.writer.frames() > 0 {
+                self.split()?;
+            }
+
+            let count = consumer.pop_slice(&mut buffer);
+            if count == 0 {
+                if stopping {
+                    break;
+                }
+                std::thread::sleep(CAPTURE_POLL_INTERVAL);
+                continue;
+            }
+            let samples = &buffer[..count];
+
+            // 重新打开设备后声道数或者采样率可能改变，使用新的格式写入下一个文件
+            let sample_rate = device_sample_rate.load(Ordering::Relaxed);
+            let channels = samples[0].channels() as u16;
+            if sample_rate != self.sample_rate || channels != self.channels {
+                self.sample_rate = sample_rate;
+                self.channels = channels;
+                self.split()?;
+            }
+            self.writer.write(samples)?;
+        }
+        self.writer.finish()
+    }
+}
+
+/// 正在进行的录制
+pub struct CaptureSession {
+    stop: Arc<AtomicBool>,
+    split_on_track: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CaptureSession {
+    /// 创建录制文件并开始接收 tap 的数据
+    ///
+    /// events 中出现切换曲目的事件并且开启了分割时，结束当前文件并写入下一个文件
+    pub fn start(
+        tap: &CaptureTap,
+        path: PathBuf,
+        format: CaptureFormat,
+        channels: u16,
+        split_on_track: bool,
+        events: Receiver<PlayerEvent>,
+    ) -> Result<CaptureSession, Error> {
+        let sample_rate = tap.sample_rate.load(Ordering::Relaxed);
+        let writer = create_writer(&path, format.into(), sample_rate, channels)?;
+        let capture_thread = CaptureThread { path, format, index: 1, sample_rate, channels, writer };
+        let consumer = tap.attach();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let split_on_track = Arc::new(AtomicBool::new(split_on_track));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let device_sample_rate = tap.sample_rate.clone();
+            let split_on_track = split_on_track.clone();
+            let stop = stop.clone();
+            let running = running.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = capture_thread.run(consumer, events, device_sample_rate, split_on_track, stop) {
+                    log::error!("the capture has stopped: {}", err);
+                }
+                running.store(false, Ordering::Relaxed);
+            })
+        };
+
+        Ok(CaptureSession {
+            stop,
+            split_on_track,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// 切换曲目时是否写入新的文件
+    pub fn set_split_on_track(&self, split_on_track: bool) {
+        self.split_on_track.store(split_on_track, Ordering::Relaxed);
+    }
+
+    /// 写入线程是否还在运行，写入出错时会提前结束
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// 停止接收数据，写入缓冲区中剩余的数据并关闭文件
+    pub fn stop(mut self, tap: &CaptureTap) {
+        tap.detach();
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for CaptureSession {
+    fn drop(&mut self) {
+        // 没有调用 stop 时也要写完文件尾
+        self.stop_thread();
+    }
+}
@@ -1,13 +1,37 @@
-use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, cell::Cell};
+use std::{sync::{atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}, Arc, Mutex}, cell::Cell, time::Duration};
 
-use cpal::{Device, Stream, SampleFormat, traits::{HostTrait, DeviceTrait, StreamTrait}, Sample};
+use cpal::{Device, HostId, Stream, SampleFormat, traits::{HostTrait, DeviceTrait, StreamTrait}, Sample};
 use ringbuf::{Producer, Consumer, RingBuffer};
 use snafu::{Snafu, OptionExt, ResultExt, ensure};
 
-use super::{volume, sample::AudioSample};
+use super::{volume, sample::AudioSample, dsp::{self, DspProcessor, DspStage, ProcessingOrder, DEFAULT_PROCESSING_ORDER, convolution::Convolver, delay::ChannelDelay, equalizer::Equalizer, limiter::Limiter}, analysis::AnalysisTap, capture::CaptureTap, fade::Fade};
+use super::source::FFmpegSourceStatus;
 
 pub static BUFFER_CAPACITY: usize = 64_000;
 
+/// 输出缓冲区的数量，交叉淡化时两路同时输出
+const OUTPUT_SLOT_COUNT: usize = 2;
+
+type OutputConsumers = [Arc<Mutex<Consumer<AudioSample>>>; OUTPUT_SLOT_COUNT];
+
+/// 附加输出设备的编号
+pub type OutputId = u32;
+
+/// 附加输出和主设备之间保持的缓冲时长
+const SECONDARY_TARGET_LATENCY: Duration = Duration::from_millis(100);
+
+/// 没有设置重新缓冲阈值时，缓冲区积累到这个时长就结束缓冲状态
+const BUFFERING_RECOVER_DURATION: Duration = Duration::from_millis(40);
+
+/// 重新缓冲阈值的上限（帧数），缓冲区还需要留出解码线程写入的空间
+pub const MAX_REBUFFER_FRAMES: usize = BUFFER_CAPACITY / 4;
+
+/// 输出回调获取缓冲区的锁时最多尝试的次数，其他线程只会短暂地持有锁
+const CONSUMER_LOCK_ATTEMPTS: usize = 64;
+
+/// 输出回调一次处理的最大帧数，设备请求更多时分成几块处理，用户的音频处理器每次最多收到这么多帧
+pub const MAX_BLOCK_FRAMES: usize = 8192;
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("failed to init audio device: {}", message))]
@@ -46,50 +70,414 @@ pub enum Error {
     },
 }
 
+/// 在输出回调中获取缓冲区的锁，不等待其他线程。
+/// 清空缓冲区和读取缓冲区长度只会持有很短的时间，多尝试几次仍然失败时返回 None，由调用者按静音处理
+fn try_lock_consumer(consumer: &Mutex<Consumer<AudioSample>>) -> Option<std::sync::MutexGuard<Consumer<AudioSample>>> {
+    for _ in 0..CONSUMER_LOCK_ATTEMPTS {
+        if let Ok(consumer) = consumer.try_lock() {
+            return Some(consumer);
+        }
+        std::hint::spin_loop();
+    }
+    None
+}
+
 fn audio_output_stream<T: Sample>(
     data: &mut[T],
     context: &Arc<AudioDeviceContext>,
-    consumer: &Arc<Mutex<ringbuf::Consumer<AudioSample>>>,
+    consumers: &OutputConsumers,
     channels: u16,
+    channel_map: Option<&[usize]>,
+    block: &mut Vec<AudioSample>,
+    info: &cpal::OutputCallbackInfo,
 ) {
-    let volume = context.volume_amplitude.lock().unwrap().get();
+    let device_channels = channels as usize;
+    let frame_count = data.len() / device_channels;
+    // 设置了声道映射时，混合和处理都使用映射的声道数，写入设备时再分配到设备的声道上
+    let channels = channel_map.map_or(device_channels, |channel_map| channel_map.len());
+
+    // 记录每次回调的帧数和后端报告的回调到实际播放之间的时间，用于估计输出延迟
+    context.callback_frames.store(frame_count as u32, Ordering::Relaxed);
+    let timestamp = info.timestamp();
+    let device_latency = timestamp.playback.duration_since(&timestamp.callback).unwrap_or_default();
+    context.device_latency_us.store(device_latency.as_micros() as u64, Ordering::Relaxed);
 
-    let zero_frame = |frame: &mut [T]| {
-        for sample in frame {
+    if !context.playing.load(Ordering::Relaxed) {
+        for sample in data.iter_mut() {
             *sample = T::from(&0.0);
         }
-    };
-    
-    let audio_sample_write_to_frame = |frame: &mut [T], audio_sample: &AudioSample| {
-        audio_sample
-            .apply_process(|sample| (sample * volume).clamp(-1., 1.))
-            .write_slice_convert(frame, |sample| T::from(&sample));
-    };
-    
-    for frame in data.chunks_exact_mut(channels as usize) {
-        if context.playing.load(Ordering::Relaxed) {
-            let buffed_sample = consumer.lock().unwrap().pop();
-            if context.mute.load(Ordering::Relaxed) {
-                zero_frame(frame);
+        return;
+    }
+
+    // 每次最多处理 block 预先分配的帧数，设备请求更多时分成几块，输出回调中不分配内存
+    let block_frames = block.capacity().max(1);
+    for data in data.chunks_mut(block_frames * device_channels) {
+        render_block(context, consumers, channels, data.len() / device_channels, block);
+        match channel_map {
+            Some(channel_map) => {
+                for (frame, audio_sample) in data.chunks_exact_mut(device_channels).zip(block.iter()) {
+                    route_channels(frame, audio_sample, channel_map);
+                }
+            },
+            None => {
+                for (frame, audio_sample) in data.chunks_exact_mut(device_channels).zip(block.iter()) {
+                    audio_sample.write_slice_convert(frame, |sample| T::from(&sample));
+                }
+            },
+        }
+    }
+}
+
+/// 依次尝试的音频主机：可用的指定主机、默认主机，然后是其他可用主机
+///
+/// 指定的主机在当前系统上不可用时跳过
+fn host_order(preferred_host: Option<HostId>, default_host_id: HostId, available_hosts: &[HostId]) -> Vec<HostId> {
+    let mut host_ids = Vec::with_capacity(available_hosts.len() + 1);
+    if let Some(host_id) = preferred_host {
+        if available_hosts.contains(&host_id) {
+            host_ids.push(host_id);
+        } else {
+            log::warn!("The audio host {} is not available on this system, falling back", host_id.name());
+        }
+    }
+    if !host_ids.contains(&default_host_id) {
+        host_ids.push(default_host_id);
+    }
+    for host_id in available_hosts {
+        if !host_ids.contains(host_id) {
+            host_ids.push(*host_id);
+        }
+    }
+    host_ids
+}
+
+/// 按声道映射把一帧数据写到设备的各个声道上
+///
+/// 没有映射的设备声道保持静音，多个声道映射到同一个设备声道时相加
+fn route_channels<T: Sample>(frame: &mut [T], audio_sample: &AudioSample, channel_map: &[usize]) {
+    let mut routed = [0f32; AudioSample::MAX_CHANNELS];
+    for (sample, device_channel) in audio_sample.as_slice().iter().zip(channel_map) {
+        routed[*device_channel] += sample;
+    }
+    for (output, sample) in frame.iter_mut().zip(routed) {
+        *output = T::from(&sample.clamp(-1., 1.));
+    }
+}
+
+/// 从各路缓冲区取出 frame_count 帧，混合并经过所有处理步骤和音量，结果写入 block
+///
+/// frame_count 不能超过 block 的容量，否则会在实时线程上分配内存
+fn render_block(
+    context: &AudioDeviceContext,
+    consumers: &OutputConsumers,
+    channels: usize,
+    frame_count: usize,
+    block: &mut Vec<AudioSample>,
+) {
+    let volume = context.volume_amplitude.lock().unwrap().get();
+
+    // 先从各路缓冲区取出整块数据并按增益混合，缓冲区数据不足的部分用静音填充
+    block.clear();
+    block.resize(frame_count, AudioSample::silence(channels));
+    {
+        // 增益包络正在被修改（开始或者结束交叉淡化）时，这一块沿用上一块结束时的增益，不在实时线程上等待锁
+        let mut fades = context.fades.try_lock().ok();
+        let mut mixed = false;
+        for (slot, consumer) in consumers.iter().enumerate() {
+            let mut fade = match fades.as_ref() {
+                Some(fades) => fades[slot],
+                None => Fade::new(f32::from_bits(context.slot_gains[slot].load(Ordering::Relaxed))),
+            };
+            // 已经完全淡出的一路不再读取，它的解码线程会因为缓冲区已满而等待
+            if fade.is_silent() {
+                context.slot_gains[slot].store(0f32.to_bits(), Ordering::Relaxed);
+                continue;
+            }
+            // 解码线程正在清空缓冲区（定位）时这一路本来就没有可以播放的数据
+            let mut consumer = match try_lock_consumer(consumer) {
+                Some(consumer) => consumer,
+                None => continue,
+            };
+            let mut played = 0;
+            let slot_volume = f32::from_bits(context.slot_volumes[slot].load(Ordering::Relaxed));
+            if !mixed && fade.is_finished() && fade.gain() == 1. && slot_volume == 1. {
+                let input_status = context.input_status.try_lock().ok();
+                let status = input_status.as_ref().and_then(|input_status| input_status[slot].as_deref());
+                let live = status.map(is_input_live);
+                let buffering = &context.buffering[slot];
+                // 开始播放或者定位之后，等解码线程预先缓冲完成再消费，文件结束时解码线程不再写入，不需要等待
+                if live == Some(true) && status.map_or(false, |status| status.prebuffering.load(Ordering::Relaxed)) {
+                    buffering.store(true, Ordering::Relaxed);
+                    mixed = true;
+                    continue;
+                }
+                // 播放源还在解码但是缓冲区的数据不够时进入缓冲状态，缓冲区重新积累到足够的数据后结束。
+                // 设置了重新缓冲阈值时，缓冲期间不消费数据，避免断断续续地播放
+                if buffering.load(Ordering::Relaxed) {
+                    let rebuffer_frames = context.rebuffer_frames.load(Ordering::Relaxed) as usize;
+                    let recover_frames = if rebuffer_frames > 0 {
+                        rebuffer_frames
+                    } else {
+                        (BUFFERING_RECOVER_DURATION.as_secs_f64() * context.sample_rate.load(Ordering::Relaxed) as f64) as usize
+                    };
+                    if live == Some(false) || consumer.len() >= recover_frames {
+                        buffering.store(false, Ordering::Relaxed);
+                    } else if rebuffer_frames > 0 {
+                        mixed = true;
+                        continue;
+                    }
+                }
+                // 没有交叉淡化时直接复制
+                for audio_sample in block.iter_mut() {
+                    if let Some(input) = consumer.pop() {
+                        *audio_sample = input;
+                        played += 1;
+                    }
+                }
+                if live == Some(true) && (played as usize) < frame_count {
+                    buffering.store(true, Ordering::Relaxed);
+                }
             } else {
-                match buffed_sample {
-                    Some(audio_sample) => {
-                        audio_sample_write_to_frame(frame, &audio_sample);
-                    },
-                    None => {
-                        zero_frame(frame);
+                for audio_sample in block.iter_mut() {
+                    let gain = fade.next_gain() * slot_volume;
+                    if let Some(input) = consumer.pop() {
+                        for (output, input) in audio_sample.as_mut_slice().iter_mut().zip(input.as_slice()) {
+                            *output += input * gain;
+                        }
+                        played += 1;
                     }
                 }
             }
+            if let Some(fades) = fades.as_mut() {
+                fades[slot] = fade;
+            }
+            context.slot_gains[slot].store(fade.gain().to_bits(), Ordering::Relaxed);
+            mixed = true;
+            context.played_frames[slot].fetch_add(played, Ordering::Relaxed);
+            // 缓冲区有了空间，唤醒等待写入的解码线程
+            if played > 0 {
+                if let Ok(input_status) = context.input_status.try_lock() {
+                    if let Some(status) = input_status[slot].as_ref() {
+                        status.buffer_space.notify();
+                    }
+                }
+            }
+        }
+    }
+    for stage in dsp::unpack_processing_order(context.processing_order.load(Ordering::Relaxed)) {
+        match stage {
+            DspStage::Equalizer => {
+                // 设置正在被替换时跳过这一块，不在实时线程上等待锁
+                if let Ok(mut equalizer) = context.equalizer.try_lock() {
+                    equalizer.process(block);
+                }
+            },
+            DspStage::ChannelDelay => {
+                if let Ok(mut channel_delay) = context.channel_delay.try_lock() {
+                    channel_delay.process(block);
+                }
+            },
+            DspStage::Convolution => {
+                // 脉冲响应正在被替换时这一块不做卷积，不在实时线程上等待锁
+                if let Ok(mut convolver) = context.convolver.try_lock() {
+                    convolver.process(block);
+                }
+            },
+            DspStage::UserProcessor => {
+                // 用户处理器正在被替换时跳过这一块，不在实时线程上等待锁
+                if let Ok(mut processor) = context.dsp_processor.try_lock() {
+                    if let Some(processor) = processor.as_mut() {
+                        processor(block.as_mut_slice());
+                    }
+                }
+            },
+            // 限幅器在音量之后处理
+            DspStage::Limiter => {},
+        }
+    }
+
+    context.analysis.push_block(block);
+    context.capture.push_block(block, true);
+
+    // 附加输出使用各自的音量，写入音量调整之前的数据。正在增减附加输出时跳过这一块
+    if let Ok(mut producers) = context.secondary_producers.try_lock() {
+        for (_, producer) in producers.iter_mut() {
+            // 附加设备的时钟偏慢时缓冲区会写满，多出的部分直接丢弃，不阻塞主设备
+            producer.push_slice(block);
+        }
+    }
+
+    // 在整块数据上应用音量，录制可以得到和设备输出相同的数据
+    let mute = context.mute.load(Ordering::Relaxed);
+    // 总输出的包络正在被修改时，这一块沿用上一块结束时的增益，不在实时线程上等待锁
+    let mut master_fade = context.master_fade.try_lock().ok();
+    let mut master_gain = f32::from_bits(context.master_gain.load(Ordering::Relaxed));
+    for audio_sample in block.iter_mut() {
+        if let Some(master_fade) = master_fade.as_mut() {
+            master_gain = master_fade.next_gain();
+        }
+        let volume = volume * master_gain;
+        if mute {
+            *audio_sample = AudioSample::silence(channels);
         } else {
-            zero_frame(frame)
+            for sample in audio_sample.as_mut_slice() {
+                *sample *= volume;
+            }
         }
     }
+
+    if let Some(master_fade) = master_fade {
+        context.master_gain.store(master_fade.gain().to_bits(), Ordering::Relaxed);
+    }
+
+    // 限幅器在音量之后，开启时输出不会超过上限，也就不会被截断。设置正在被修改时跳过这一块
+    if let Ok(mut limiter) = context.limiter.try_lock() {
+        limiter.process(block);
+    }
+
+    let mut clipped = 0;
+    for audio_sample in block.iter_mut() {
+        for sample in audio_sample.as_mut_slice() {
+            if sample.abs() > 1. {
+                clipped += 1;
+            }
+            *sample = sample.clamp(-1., 1.);
+        }
+    }
+    if clipped > 0 {
+        context.clipped_samples.fetch_add(clipped, Ordering::Relaxed);
+    }
+    context.capture.push_block(block, false);
+}
+
+/// 播放源是否还会继续写入数据
+fn is_input_live(status: &FFmpegSourceStatus) -> bool {
+    status.avaliable.load(Ordering::Relaxed)
+        && status.playing.load(Ordering::Relaxed)
+        && !status.is_end.load(Ordering::Relaxed)
+        && !status.source_lost.load(Ordering::Relaxed)
+        && !status.dropping_frames.load(Ordering::Relaxed)
+        && !status.draining.load(Ordering::Relaxed)
+}
+
+/// 把主设备的声道映射到附加设备的声道，单声道设备使用左右声道的平均值
+fn map_secondary_channel(audio_sample: &AudioSample, channel: usize, output_channels: usize) -> f32 {
+    let input = audio_sample.as_slice();
+    match input.len() {
+        0 => 0.,
+        1 => input[0],
+        _ if output_channels == 1 => (input[0] + input[1]) * 0.5,
+        _ => input.get(channel).copied().unwrap_or(0.),
+    }
 }
 
+/// 把主设备采样率的数据线性插值到附加设备的采样率，两者相同时直接取出数据
+struct SecondaryResampler {
+    /// 每输出一帧前进的输入帧数，等于主设备采样率除以附加设备采样率
+    step: f64,
+    /// 输出位置在 previous 和 next 之间的比例，不小于 1 时需要读取新的输入帧
+    position: f64,
+    previous: AudioSample,
+    next: AudioSample,
+}
+
+impl SecondaryResampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        SecondaryResampler {
+            step: input_rate as f64 / output_rate as f64,
+            position: 2.,
+            previous: AudioSample::default(),
+            next: AudioSample::default(),
+        }
+    }
+
+    /// 缓冲区为空时重新开始插值，避免和之前的数据连在一起
+    fn reset(&mut self) {
+        self.position = 2.;
+    }
+
+    fn next_sample(&mut self, consumer: &mut Consumer<AudioSample>) -> Option<AudioSample> {
+        if self.step == 1. {
+            return consumer.pop();
+        }
+        while self.position >= 1. {
+            let audio_sample = consumer.pop()?;
+            self.previous = std::mem::replace(&mut self.next, audio_sample);
+            self.position -= 1.;
+        }
+        let ratio = self.position as f32;
+        let mut audio_sample = self.next;
+        for (sample, previous) in audio_sample.as_mut_slice().iter_mut().zip(self.previous.as_slice()) {
+            *sample = previous + (*sample - previous) * ratio;
+        }
+        self.position += self.step;
+        Some(audio_sample)
+    }
+}
+
+fn secondary_output_stream<T: Sample>(
+    data: &mut[T],
+    context: &SecondaryContext,
+    consumer: &mut Consumer<AudioSample>,
+    resampler: &mut SecondaryResampler,
+    channels: u16,
+    primed: &mut bool,
+) {
+    let channels = channels as usize;
+    // 附加设备的时钟偏慢时缓冲区中的数据越积越多，丢弃多余的部分，保持和主设备之间的延迟稳定
+    if consumer.len() > context.target_frames * 2 {
+        consumer.discard(consumer.len() - context.target_frames);
+    }
+    // 附加设备的时钟偏快或者主设备暂停时缓冲区会变空，输出静音直到重新积累到目标时长，避免反复断续
+    if !*primed && consumer.len() >= context.target_frames {
+        *primed = true;
+    }
+
+    let volume = if context.mute.load(Ordering::Relaxed) {
+        0.
+    } else {
+        f32::from_bits(context.volume_amplitude.load(Ordering::Relaxed))
+    };
+    for frame in data.chunks_exact_mut(channels) {
+        let input = if *primed { resampler.next_sample(consumer) } else { None };
+        match input {
+            Some(audio_sample) => {
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let value = map_secondary_channel(&audio_sample, channel, channels) * volume;
+                    *sample = T::from(&value.clamp(-1., 1.));
+                }
+            },
+            None => {
+                *primed = false;
+                resampler.reset();
+                for sample in frame {
+                    *sample = T::from(&0.0);
+                }
+            },
+        }
+    }
+}
+
+/// 附加输出设备的上下文，在附加设备自己的输出回调中读取
+struct SecondaryContext {
+    /// 输出音量增益（振幅比例，f32 的位表示）
+    volume_amplitude: AtomicU32,
+    /// 是否静音
+    mute: AtomicBool,
+    /// 缓冲区中保持的帧数
+    target_frames: usize,
+}
+
+/// 和主设备播放相同音频的附加输出设备，例如监听耳机
+struct SecondaryOutput {
+    id: OutputId,
+    device_name: String,
+    /// 输出流被丢弃时停止播放
+    _stream: Stream,
+    context: Arc<SecondaryContext>,
+}
 
 /// 音频设备上下文
-#[derive(Debug)]
 struct AudioDeviceContext {
     /// 是否静音
     mute: AtomicBool,
@@ -97,17 +485,160 @@ struct AudioDeviceContext {
     volume_amplitude: Mutex<Cell<f32>>,
     /// 是否消费缓冲区的数据并播放
     playing: AtomicBool,
+    /// 均衡器
+    equalizer: Mutex<Equalizer>,
+    /// 按声道延迟
+    channel_delay: Mutex<ChannelDelay>,
+    /// 脉冲响应卷积
+    convolver: Mutex<Convolver>,
+    /// 总输出的限幅器
+    limiter: Mutex<Limiter>,
+    /// 用户提供的音频处理器
+    dsp_processor: Mutex<Option<DspProcessor>>,
+    /// 音量之前各个处理步骤的顺序，由 dsp::pack_processing_order 打包，输出回调中不加锁读取
+    processing_order: AtomicU32,
+    /// 输出音频分析
+    analysis: AnalysisTap,
+    /// 输出音频录制
+    capture: CaptureTap,
+    /// 每个附加输出的缓冲区生产者
+    secondary_producers: Mutex<Vec<(OutputId, Producer<AudioSample>)>>,
+    /// 每一路输出缓冲区的增益包络
+    fades: Mutex<[Fade; OUTPUT_SLOT_COUNT]>,
+    /// 每一路输出缓冲区的包络在上一次回调结束时的增益
+    slot_gains: [AtomicU32; OUTPUT_SLOT_COUNT],
+    /// 每一路输出缓冲区的音量（振幅比例），同时播放两个文件时分别调整
+    slot_volumes: [AtomicU32; OUTPUT_SLOT_COUNT],
+    /// 总输出的增益包络，用于定时停止等场合的淡入淡出
+    master_fade: Mutex<Fade>,
+    /// 总输出的包络在上一次回调结束时的增益
+    master_gain: AtomicU32,
+    /// 设备采样率
+    sample_rate: AtomicU32,
+    /// 每一路输出缓冲区实际播放的帧数，不包括缓冲区不足时填充的静音
+    played_frames: [AtomicU64; OUTPUT_SLOT_COUNT],
+    /// 最近一次输出回调的帧数
+    callback_frames: AtomicU32,
+    /// 后端报告的回调到实际播放之间的时间（微秒），不支持时为 0
+    device_latency_us: AtomicU64,
+    /// 应用音量之后超出最大幅度被截断的样本数
+    clipped_samples: AtomicU64,
+    /// 每一路输出缓冲区对应的播放源状态，用于判断缓冲区不足是否因为解码跟不上
+    input_status: Mutex<[Option<Arc<FFmpegSourceStatus>>; OUTPUT_SLOT_COUNT]>,
+    /// 每一路输出缓冲区是否因为解码跟不上而处于缓冲状态
+    buffering: [AtomicBool; OUTPUT_SLOT_COUNT],
+    /// 进入缓冲状态后暂停消费，直到缓冲区积累到的帧数，0 表示不暂停
+    rebuffer_frames: AtomicU64,
+}
+
+/// 可以在其他线程中控制音频输出的句柄，重新打开设备后仍然有效
+#[derive(Clone)]
+pub struct OutputControl {
+    context: Arc<AudioDeviceContext>,
+}
+
+impl OutputControl {
+    /// 在 duration 内把总输出增益线性变化到 target
+    pub fn fade_to(&self, target: f32, duration: Duration) {
+        let sample_rate = self.context.sample_rate.load(Ordering::Relaxed);
+        let frames = (duration.as_secs_f64() * sample_rate as f64) as usize;
+        self.context.master_fade.lock().unwrap().start(target, frames);
+    }
+
+    /// 总输出增益是否已经到达目标值
+    pub fn is_fade_finished(&self) -> bool {
+        self.context.master_fade.lock().unwrap().is_finished()
+    }
+
+    /// 当前总输出增益
+    pub fn gain(&self) -> f32 {
+        self.context.master_fade.lock().unwrap().gain()
+    }
+
+    /// 不关闭音频设备，暂停音频输出
+    pub fn pause(&self) {
+        self.context.playing.store(false, Ordering::Relaxed);
+    }
+}
+
+/// 读取一路输出缓冲区实际播放帧数的句柄，重新打开设备后仍然有效
+#[derive(Clone)]
+pub struct PlayedFrameCounter {
+    context: Arc<AudioDeviceContext>,
+    consumer: Arc<Mutex<Consumer<AudioSample>>>,
+    slot: usize,
+}
+
+impl PlayedFrameCounter {
+    /// 累计播放的帧数，只在有数据输出时增加，暂停和缓冲区不足时不变
+    pub fn get(&self) -> u64 {
+        self.context.played_frames[self.slot].load(Ordering::Relaxed)
+    }
+
+    /// 当前设备采样率
+    pub fn sample_rate(&self) -> u32 {
+        self.context.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// 这一路输出是否因为解码跟不上而处于缓冲状态
+    pub fn is_buffering(&self) -> bool {
+        self.context.buffering[self.slot].load(Ordering::Relaxed)
+    }
+
+    /// 这一路输出缓冲区中还没有播放的帧数
+    pub fn buffered_frames(&self) -> usize {
+        self.consumer.lock().unwrap().len()
+    }
+}
+
+/// 读取输出时被截断的样本数的句柄，重新打开设备后仍然有效
+#[derive(Clone)]
+pub struct ClipCounter {
+    context: Arc<AudioDeviceContext>,
+}
+
+impl ClipCounter {
+    /// 上一次清零以来被截断的样本数，每个声道分别计数
+    pub fn get(&self) -> u64 {
+        self.context.clipped_samples.load(Ordering::Relaxed)
+    }
+
+    /// 清零计数
+    pub fn reset(&self) {
+        self.context.clipped_samples.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 一路音频输出缓冲区
+struct OutputSlot {
+    /// 生产者
+    producer: Arc<Mutex<Producer<AudioSample>>>,
+    /// 消费者
+    consumer: Arc<Mutex<Consumer<AudioSample>>>,
+}
+
+impl OutputSlot {
+    fn new() -> OutputSlot {
+        let buffer = RingBuffer::<AudioSample>::new(BUFFER_CAPACITY);
+        let (producer, consumer) = buffer.split();
+        OutputSlot {
+            producer: Arc::new(Mutex::new(producer)),
+            consumer: Arc::new(Mutex::new(consumer)),
+        }
+    }
 }
 
 /// 设备输出采样格式
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct DeviceSampleFormat {
     /// 设备采样率
     pub sample_rate: u32,
     /// 设备音频格式
     pub sample_format: SampleFormat,
-    /// 设备音频通道数
+    /// 解码和处理使用的声道数，设置了声道映射时为映射的长度，否则和设备的声道数相同
     pub channel_count: u16,
+    /// 设备实际的声道数
+    pub device_channel_count: u16,
 }
 
 /// 音频设备
@@ -116,52 +647,163 @@ pub struct AudioDevice {
     available: Arc<AtomicBool>,
     /// 音频设备
     device: Option<Device>,
+    /// 实际使用的音频主机
+    host_id: Option<HostId>,
+    /// 实际使用的音频设备名称
+    device_name: Option<String>,
     /// 音频输出流
     output_stream: Option<Stream>,
-    /// 音频输出缓冲区：生产者
-    output_buffer_producer: Arc<Mutex<Producer<AudioSample>>>,
-    /// 音频输出缓冲区：消费者
-    output_buffer_consumer: Arc<Mutex<Consumer<AudioSample>>>,
+    /// 音频输出缓冲区
+    output_slots: [OutputSlot; OUTPUT_SLOT_COUNT],
+    /// 当前播放源使用的输出缓冲区
+    active_slot: usize,
+    /// 第二个 deck 使用的输出缓冲区，和当前一路同时播放
+    deck_slot: Option<usize>,
     /// 设备输出采样格式
     pub sample_format: Option<DeviceSampleFormat>,
+    /// 声道映射，第 i 个元素是第 i 个声道输出到的设备声道，打开设备时生效
+    channel_map: Option<Vec<usize>>,
+    /// 附加输出设备
+    secondary_outputs: Vec<SecondaryOutput>,
+    /// 下一个附加输出的编号
+    next_output_id: OutputId,
     /// 音频设备上下文
     context: Arc<AudioDeviceContext>
 }
 
 impl AudioDevice {
     pub fn new() -> AudioDevice {
-        // 创建音频缓冲区
-        let buffer = RingBuffer::<AudioSample>::new(BUFFER_CAPACITY);
-        let (producer, consumer) = buffer.split();
-
         AudioDevice {
             available: Arc::new(AtomicBool::new(false)),
-            output_buffer_producer: Arc::new(Mutex::new(producer)),
-            output_buffer_consumer: Arc::new(Mutex::new(consumer)),
+            // 创建音频缓冲区
+            output_slots: [OutputSlot::new(), OutputSlot::new()],
+            active_slot: 0,
+            deck_slot: None,
             device: None,
+            host_id: None,
+            device_name: None,
             output_stream: None,
             sample_format: None,
+            channel_map: None,
+            secondary_outputs: Vec::new(),
+            next_output_id: 1,
             context: Arc::new(AudioDeviceContext {
                 mute: AtomicBool::new(false),
                 volume_amplitude: Mutex::new(Cell::new(0.0)),
                 playing: AtomicBool::new(true),
+                equalizer: Mutex::new(Equalizer::new()),
+                channel_delay: Mutex::new(ChannelDelay::new()),
+                convolver: Mutex::new(Convolver::new()),
+                limiter: Mutex::new(Limiter::new()),
+                dsp_processor: Mutex::new(None),
+                processing_order: AtomicU32::new(dsp::pack_processing_order(DEFAULT_PROCESSING_ORDER)),
+                analysis: AnalysisTap::new(),
+                capture: CaptureTap::new(),
+                secondary_producers: Mutex::new(Vec::new()),
+                // 备用的一路在交叉淡化之前保持静音
+                fades: Mutex::new([Fade::new(1.), Fade::new(0.)]),
+                slot_gains: [AtomicU32::new(1f32.to_bits()), AtomicU32::new(0f32.to_bits())],
+                slot_volumes: [AtomicU32::new(1f32.to_bits()), AtomicU32::new(1f32.to_bits())],
+                master_fade: Mutex::new(Fade::new(1.)),
+                master_gain: AtomicU32::new(1f32.to_bits()),
+                sample_rate: AtomicU32::new(0),
+                played_frames: [AtomicU64::new(0), AtomicU64::new(0)],
+                callback_frames: AtomicU32::new(0),
+                device_latency_us: AtomicU64::new(0),
+                clipped_samples: AtomicU64::new(0),
+                input_status: Mutex::new([None, None]),
+                buffering: [AtomicBool::new(false), AtomicBool::new(false)],
+                rebuffer_frames: AtomicU64::new(0),
             }),
         }
     }
 
+    /// 测试用：不连接音频设备，输出回调由 render 代替
+    #[cfg(test)]
+    pub(crate) fn null(sample_rate: u32, channels: u16) -> AudioDevice {
+        let mut device = AudioDevice::new();
+        device.set_sample_format(DeviceSampleFormat {
+            sample_rate,
+            sample_format: SampleFormat::F32,
+            channel_count: channels,
+            device_channel_count: channels,
+        });
+        device.set_volume(0.);
+        device.available.store(true, Ordering::Release);
+        device
+    }
+
+    /// 测试用：和输出回调一样取出 frames 帧并经过所有处理步骤，返回写入设备之前的数据
+    #[cfg(test)]
+    pub(crate) fn render(&self, frames: usize) -> Vec<AudioSample> {
+        let consumers: OutputConsumers = [
+            self.output_slots[0].consumer.clone(),
+            self.output_slots[1].consumer.clone(),
+        ];
+        let channels = self.sample_format.map_or(2, |format| format.channel_count as usize);
+        let mut block = Vec::with_capacity(frames);
+        if self.context.playing.load(Ordering::Relaxed) {
+            render_block(&self.context, &consumers, channels, frames, &mut block);
+        } else {
+            block.resize(frames, AudioSample::silence(channels));
+        }
+        block
+    }
+
     /// 初始化默认音频设备
     pub fn init_default_device(&mut self) -> Result<(), Error> {
-        let device = cpal::default_host()
-            .default_output_device()
-            .context(InitDeviceSnafu {
-                message: "failed to get default output device".to_string(),
-            })?;
+        self.init_device(None, None)
+    }
+
+    /// 初始化音频设备
+    ///
+    /// 优先尝试指定的音频主机，失败后依次尝试默认主机和其他可用主机，直到成功打开一个输出设备。
+    /// 指定了设备名称时，在每个主机上优先使用同名设备，找不到则使用该主机的默认设备
+    pub fn init_device(&mut self, preferred_host: Option<HostId>, preferred_device: Option<&str>) -> Result<(), Error> {
+        let host_ids = host_order(preferred_host, cpal::default_host().id(), &cpal::available_hosts());
+
+        let mut last_error = None;
+        for host_id in host_ids {
+            match self.init_device_with_host(host_id, preferred_device) {
+                Ok(_) => return Ok(()),
+                Err(err) => {
+                    log::warn!("Failed to open output device on audio host {}: {}", host_id.name(), err);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(Error::InitDevice {
+            message: "no audio host is available".to_string(),
+        }))
+    }
+
+    /// 使用指定音频主机的输出设备
+    fn init_device_with_host(&mut self, host_id: HostId, preferred_device: Option<&str>) -> Result<(), Error> {
+        let host = cpal::host_from_id(host_id).ok().context(InitDeviceSnafu {
+            message: format!("the audio host {} is unavailable", host_id.name()),
+        })?;
+
+        let named_device = preferred_device.and_then(|name| {
+            host.output_devices().ok()?
+                .find(|device| device.name().map(|device_name| device_name == name).unwrap_or(false))
+        });
+
+        let device = match named_device {
+            Some(device) => device,
+            None => host
+                .default_output_device()
+                .context(InitDeviceSnafu {
+                    message: "failed to get default output device".to_string(),
+                })?,
+        };
 
+        // 解码线程最多输出 AudioSample::MAX_CHANNELS 个声道，跳过声道数更多的输出格式
         let supported_config_range = device.supported_output_configs()
             .context(DeviceConfigSnafu {
                 message: "failed to get supported output configs".to_string(),
             })?
-            .next()
+            .find(|config| config.channels() > 0 && config.channels() as usize <= AudioSample::MAX_CHANNELS)
             .context(InitDeviceSnafu {
                 message: "the audio device does not have a supported output format".to_string(),
             })?;
@@ -169,35 +811,75 @@ impl AudioDevice {
         // 获取最高采样率的输出格式
         let device_config = supported_config_range.with_max_sample_rate();
 
-        self.sample_format = Some(DeviceSampleFormat {
+        // 附加输出只能使用和主设备相同的采样率
+        if self.sample_format.map(|format| format.sample_rate) != Some(device_config.sample_rate().0) {
+            for output in self.secondary_outputs.drain(..) {
+                log::warn!("The sample rate of the main output has changed, removing the output device {}", output.device_name);
+            }
+            self.context.secondary_producers.lock().unwrap().clear();
+        }
+
+        // 声道映射按设备的声道数检查，新设备的声道不够时不使用映射
+        let channel_map = self.channel_map.clone().filter(|channel_map| {
+            let valid = channel_map.iter().all(|channel| *channel < device_config.channels() as usize);
+            if !valid {
+                log::warn!("The channel map {:?} does not fit the {} channels of the output device, ignoring it",
+                    channel_map, device_config.channels());
+            }
+            valid
+        });
+        self.set_sample_format(DeviceSampleFormat {
             sample_rate: device_config.sample_rate().0,
             sample_format: device_config.sample_format(),
-            channel_count: device_config.channels(),
+            channel_count: channel_map.as_ref().map_or(device_config.channels(), |channel_map| channel_map.len() as u16),
+            device_channel_count: device_config.channels(),
         });
+        // 按设备一次回调最多请求的帧数预先分配处理用的缓冲区，后端没有报告时使用上限
+        let block_frames = match device_config.buffer_size() {
+            cpal::SupportedBufferSize::Range { max, .. } => (*max as usize).clamp(1, MAX_BLOCK_FRAMES),
+            cpal::SupportedBufferSize::Unknown => MAX_BLOCK_FRAMES,
+        };
 
         // 创建音频设备输出流，从缓冲区读取数据
         let device_avaliabled = self.available.clone();
         let error_callback = move |err| {
-            eprintln!("An error occurred while playing the audio: {}", err);
+            log::error!("An error occurred while playing the audio: {}", err);
             // 标记设备已经失效
             device_avaliabled.store(false, Ordering::Release);
         };
 
-        let consumer_f32 = self.output_buffer_consumer.clone();
+        let consumers: OutputConsumers = [
+            self.output_slots[0].consumer.clone(),
+            self.output_slots[1].consumer.clone(),
+        ];
         let status = self.context.clone();
         let channels = device_config.channels();
         let device_output_stream = match &device_config.sample_format() {
-            SampleFormat::I16 => device.build_output_stream(&device_config.into(), move |data: &mut[i16], _| {
-                audio_output_stream(data, &status, &consumer_f32, channels);
-            }, error_callback),
-            SampleFormat::U16 => device.build_output_stream(&device_config.into(), move |data: &mut[u16], _| {
-                audio_output_stream(data, &status, &consumer_f32, channels);
-            }, error_callback),
-            SampleFormat::F32 => device.build_output_stream(&device_config.into(), move |data: &mut[f32], _| {
-                audio_output_stream(data, &status, &consumer_f32, channels);
-            }, error_callback),
+            SampleFormat::I16 => {
+                let mut block = Vec::with_capacity(block_frames);
+                let channel_map = channel_map.clone();
+                device.build_output_stream(&device_config.into(), move |data: &mut[i16], info| {
+                    audio_output_stream(data, &status, &consumers, channels, channel_map.as_deref(), &mut block, info);
+                }, error_callback)
+            },
+            SampleFormat::U16 => {
+                let mut block = Vec::with_capacity(block_frames);
+                let channel_map = channel_map.clone();
+                device.build_output_stream(&device_config.into(), move |data: &mut[u16], info| {
+                    audio_output_stream(data, &status, &consumers, channels, channel_map.as_deref(), &mut block, info);
+                }, error_callback)
+            },
+            SampleFormat::F32 => {
+                let mut block = Vec::with_capacity(block_frames);
+                let channel_map = channel_map.clone();
+                device.build_output_stream(&device_config.into(), move |data: &mut[f32], info| {
+                    audio_output_stream(data, &status, &consumers, channels, channel_map.as_deref(), &mut block, info);
+                }, error_callback)
+            },
         }.context(BuildStreamSnafu)?;
 
+        self.device_name = device.name().ok();
+        self.host_id = Some(host_id);
         self.device = Some(device);
         self.output_stream = Some(device_output_stream);
         // 标记设备可用
@@ -207,6 +889,27 @@ impl AudioDevice {
         Ok(())
     }
 
+    /// 记录设备的输出格式，各个处理步骤按新的采样率和声道数准备状态
+    fn set_sample_format(&mut self, sample_format: DeviceSampleFormat) {
+        let sample_rate = sample_format.sample_rate;
+        let channels = sample_format.channel_count as usize;
+        self.sample_format = Some(sample_format);
+        self.context.analysis.set_sample_rate(sample_rate);
+        self.context.capture.set_sample_rate(sample_rate);
+        self.context.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.context.equalizer.lock().unwrap().set_format(sample_rate, channels);
+        self.context.channel_delay.lock().unwrap().set_format(sample_rate, channels);
+        self.context.limiter.lock().unwrap().set_format(sample_rate, channels);
+    }
+
+    /// 设置声道映射，第 i 个元素是解码后第 i 个声道输出到的设备声道（从 0 开始），下一次打开设备时生效
+    ///
+    /// None 时解码线程按设备的标准声道布局混音。设置了映射时解码为映射长度的标准声道布局，
+    /// 没有映射到的设备声道输出静音
+    pub fn set_channel_map(&mut self, channel_map: Option<Vec<usize>>) {
+        self.channel_map = channel_map;
+    }
+
     /// 开始音频输出
     pub fn open(&self) -> Result<(), Error> {
         ensure!(self.is_available(), OpenDeviceSnafu {
@@ -232,6 +935,11 @@ impl AudioDevice {
         self.context.clone().playing.store(true, Ordering::Relaxed);
     }
 
+    /// 是否在消费缓冲区的数据并播放
+    pub fn is_playing(&self) -> bool {
+        self.context.playing.load(Ordering::Relaxed)
+    }
+
     /// 停止音频输出
     pub fn close(&self) -> Result<(), Error> {
         if self.is_available() {
@@ -245,19 +953,286 @@ impl AudioDevice {
         todo!()
     }
 
-    /// 获取音频输出缓冲区生产者
+    /// 获取当前音频输出缓冲区生产者
     pub fn get_output_buffer_producer(&self) -> &Arc<Mutex<Producer<AudioSample>>> {
-        &self.output_buffer_producer
+        &self.output_slots[self.active_slot].producer
     }
 
-    /// 获取音频输出缓冲区消费者
+    /// 获取当前音频输出缓冲区消费者
     pub fn get_output_buffer_consumer(&self) -> &Arc<Mutex<Consumer<AudioSample>>> {
-        &self.output_buffer_consumer
+        &self.output_slots[self.active_slot].consumer
     }
 
-    /// 清空音频输出缓冲区
+    /// 清空当前音频输出缓冲区
     pub fn clear_output_buffer(&self) {
-        self.output_buffer_consumer.lock().unwrap().discard(BUFFER_CAPACITY);
+        AudioDevice::clear_buffer(self.get_output_buffer_consumer());
+    }
+
+    /// 开始交叉淡化：切换到另一路输出缓冲区并淡入，原来的一路在 duration 内淡出
+    ///
+    /// 调用之后 get_output_buffer_producer 返回新的一路，原来一路的播放源需要继续输出直到淡出结束
+    pub fn begin_crossfade(&mut self, duration: Duration) {
+        let sample_rate = self.sample_format.map(|format| format.sample_rate).unwrap_or(0);
+        let frames = (duration.as_secs_f64() * sample_rate as f64) as usize;
+
+        let previous_slot = self.active_slot;
+        self.active_slot = (previous_slot + 1) % OUTPUT_SLOT_COUNT;
+        self.clear_output_buffer();
+
+        let mut fades = self.context.fades.lock().unwrap();
+        fades[previous_slot].start(0., frames);
+        fades[self.active_slot] = Fade::new(0.);
+        fades[self.active_slot].start(1., frames);
+    }
+
+    /// 立即结束交叉淡化，只输出当前一路和第二个 deck
+    pub fn finish_crossfade(&self) {
+        let mut fades = self.context.fades.lock().unwrap();
+        for (index, fade) in fades.iter_mut().enumerate() {
+            let playing = index == self.active_slot || self.deck_slot == Some(index);
+            *fade = Fade::new(if playing { 1. } else { 0. });
+        }
+    }
+
+    /// 当前播放源使用的输出缓冲区序号
+    pub fn active_slot(&self) -> usize {
+        self.active_slot
+    }
+
+    /// 打开第二个 deck：清空另一路输出缓冲区，和当前一路同时播放，返回这一路的序号
+    ///
+    /// 调用之前需要结束交叉淡化，第二个 deck 关闭之前不能开始新的交叉淡化
+    pub fn open_deck_slot(&mut self) -> usize {
+        let slot = (self.active_slot + 1) % OUTPUT_SLOT_COUNT;
+        AudioDevice::clear_buffer(&self.output_slots[slot].consumer);
+        self.deck_slot = Some(slot);
+        self.context.fades.lock().unwrap()[slot] = Fade::new(1.);
+        slot
+    }
+
+    /// 关闭第二个 deck，这一路恢复静音并清空，音量恢复原样
+    pub fn close_deck_slot(&mut self) {
+        if let Some(slot) = self.deck_slot.take() {
+            self.context.fades.lock().unwrap()[slot] = Fade::new(0.);
+            self.set_slot_input_status(slot, None);
+            AudioDevice::clear_buffer(&self.output_slots[slot].consumer);
+            self.context.slot_volumes[slot].store(1f32.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// 第二个 deck 使用的输出缓冲区序号，没有打开时为 None
+    pub fn deck_slot(&self) -> Option<usize> {
+        self.deck_slot
+    }
+
+    /// 获取指定一路输出缓冲区的生产者
+    pub fn get_slot_producer(&self, slot: usize) -> &Arc<Mutex<Producer<AudioSample>>> {
+        &self.output_slots[slot].producer
+    }
+
+    /// 获取指定一路输出缓冲区的消费者
+    pub fn get_slot_consumer(&self, slot: usize) -> &Arc<Mutex<Consumer<AudioSample>>> {
+        &self.output_slots[slot].consumer
+    }
+
+    /// 设置指定一路输出缓冲区的音量，在总音量之前应用
+    pub fn set_slot_volume(&self, slot: usize, db_gain: f32) {
+        let amplitude = if db_gain == 0. { 1. } else { volume::db_gain_to_amplitude(db_gain) };
+        self.context.slot_volumes[slot].store(amplitude.to_bits(), Ordering::Relaxed);
+    }
+
+    /// 添加一个和主设备播放相同音频的附加输出设备
+    ///
+    /// 设备不支持主设备的采样率时使用设备默认的采样率，在附加设备的输出回调中重采样。
+    /// 附加设备的时钟和主设备不同步时丢弃或者补充静音，不会影响主设备的播放
+    pub fn add_secondary_output(&mut self, device_name: &str) -> Result<OutputId, Error> {
+        let sample_rate = self.sample_format.map(|format| format.sample_rate).context(OpenDeviceSnafu {
+            message: "audio device has not been initialized".to_string(),
+        })?;
+        let host = self.host_id
+            .and_then(|host_id| cpal::host_from_id(host_id).ok())
+            .context(OpenDeviceSnafu {
+                message: "audio device has not been initialized".to_string(),
+            })?;
+        let device = host.output_devices().ok()
+            .and_then(|mut devices| devices.find(|device| device.name().map(|name| name == device_name).unwrap_or(false)))
+            .context(InitDeviceSnafu {
+                message: format!("the output device {} is not available", device_name),
+            })?;
+        let device_config = match device.supported_output_configs()
+            .context(DeviceConfigSnafu {
+                message: "failed to get supported output configs".to_string(),
+            })?
+            .find(|config| config.min_sample_rate().0 <= sample_rate && sample_rate <= config.max_sample_rate().0)
+        {
+            Some(config) => config.with_sample_rate(cpal::SampleRate(sample_rate)),
+            None => device.default_output_config().ok().context(InitDeviceSnafu {
+                message: format!("the output device {} has no usable output config", device_name),
+            })?,
+        };
+        let output_rate = device_config.sample_rate().0;
+        if output_rate != sample_rate {
+            log::debug!("resampling the output on {} from {} Hz to {} Hz", device_name, sample_rate, output_rate);
+        }
+
+        let target_frames = (SECONDARY_TARGET_LATENCY.as_secs_f64() * sample_rate as f64) as usize;
+        let (producer, consumer) = RingBuffer::<AudioSample>::new(target_frames * 4).split();
+        let context = Arc::new(SecondaryContext {
+            volume_amplitude: AtomicU32::new(1f32.to_bits()),
+            mute: AtomicBool::new(false),
+            target_frames,
+        });
+
+        let error_device_name = device_name.to_string();
+        let error_callback = move |err| {
+            log::error!("An error occurred while playing the audio on {}: {}", error_device_name, err);
+        };
+        let channels = device_config.channels();
+        let stream_config: cpal::StreamConfig = device_config.clone().into();
+        let stream = match device_config.sample_format() {
+            SampleFormat::I16 => {
+                let context = context.clone();
+                let mut consumer = consumer;
+                let mut resampler = SecondaryResampler::new(sample_rate, output_rate);
+                let mut primed = false;
+                device.build_output_stream(&stream_config, move |data: &mut[i16], _| {
+                    secondary_output_stream(data, &context, &mut consumer, &mut resampler, channels, &mut primed);
+                }, error_callback)
+            },
+            SampleFormat::U16 => {
+                let context = context.clone();
+                let mut consumer = consumer;
+                let mut resampler = SecondaryResampler::new(sample_rate, output_rate);
+                let mut primed = false;
+                device.build_output_stream(&stream_config, move |data: &mut[u16], _| {
+                    secondary_output_stream(data, &context, &mut consumer, &mut resampler, channels, &mut primed);
+                }, error_callback)
+            },
+            SampleFormat::F32 => {
+                let context = context.clone();
+                let mut consumer = consumer;
+                let mut resampler = SecondaryResampler::new(sample_rate, output_rate);
+                let mut primed = false;
+                device.build_output_stream(&stream_config, move |data: &mut[f32], _| {
+                    secondary_output_stream(data, &context, &mut consumer, &mut resampler, channels, &mut primed);
+                }, error_callback)
+            },
+        }.context(BuildStreamSnafu)?;
+        stream.play().context(PlayStreamSnafu)?;
+
+        let id = self.next_output_id;
+        self.next_output_id += 1;
+        self.context.secondary_producers.lock().unwrap().push((id, producer));
+        self.secondary_outputs.push(SecondaryOutput {
+            id,
+            device_name: device_name.to_string(),
+            _stream: stream,
+            context,
+        });
+        Ok(id)
+    }
+
+    /// 移除附加输出设备，编号不存在时返回 false
+    pub fn remove_secondary_output(&mut self, id: OutputId) -> bool {
+        self.context.secondary_producers.lock().unwrap().retain(|(output_id, _)| *output_id != id);
+        let count = self.secondary_outputs.len();
+        self.secondary_outputs.retain(|output| output.id != id);
+        self.secondary_outputs.len() != count
+    }
+
+    /// 改变附加输出的音量，编号不存在时返回 false
+    pub fn set_secondary_volume(&self, id: OutputId, db_gain: f32) -> bool {
+        let amplitude = if db_gain == 0. { 1. } else { volume::db_gain_to_amplitude(db_gain) };
+        match self.secondary_outputs.iter().find(|output| output.id == id) {
+            Some(output) => {
+                output.context.volume_amplitude.store(amplitude.to_bits(), Ordering::Relaxed);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// 开关附加输出的静音，编号不存在时返回 false
+    pub fn set_secondary_mute(&self, id: OutputId, mute: bool) -> bool {
+        match self.secondary_outputs.iter().find(|output| output.id == id) {
+            Some(output) => {
+                output.context.mute.store(mute, Ordering::Relaxed);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// 列出附加输出设备的编号和名称
+    pub fn secondary_outputs(&self) -> Vec<(OutputId, String)> {
+        self.secondary_outputs.iter().map(|output| (output.id, output.device_name.clone())).collect()
+    }
+
+    /// 获取输出回调中启用的处理步骤
+    pub fn get_active_processing(&self) -> Vec<&'static str> {
+        let mut processing = Vec::new();
+        if self.is_crossfading() {
+            processing.push("crossfade");
+        }
+        if self.context.equalizer.lock().unwrap().is_active() {
+            processing.push("equalizer");
+        }
+        if self.context.channel_delay.lock().unwrap().is_active() {
+            processing.push("channel-delay");
+        }
+        if self.context.convolver.lock().unwrap().is_active() {
+            processing.push("convolution");
+        }
+        if self.context.dsp_processor.lock().unwrap().is_some() {
+            processing.push("dsp");
+        }
+        if self.context.analysis.is_enabled() {
+            processing.push("analysis");
+        }
+        if self.context.capture.is_enabled() {
+            processing.push("capture");
+        }
+        if self.context.mute.load(Ordering::Relaxed) {
+            processing.push("mute");
+        } else if self.context.volume_amplitude.lock().unwrap().get() != 1. {
+            processing.push("volume");
+        }
+        if self.context.limiter.lock().unwrap().is_enabled() {
+            processing.push("limiter");
+        }
+        processing
+    }
+
+    /// 判断是否还有淡出中的一路
+    pub fn is_crossfading(&self) -> bool {
+        let fades = self.context.fades.lock().unwrap();
+        fades.iter().enumerate()
+            .any(|(index, fade)| index != self.active_slot && self.deck_slot != Some(index) && !fade.is_silent())
+    }
+
+    /// 列出音频主机上的所有输出设备名称，未指定主机时使用默认主机
+    pub fn output_device_names(host_id: Option<HostId>) -> Vec<String> {
+        let host = match host_id {
+            Some(host_id) => match cpal::host_from_id(host_id) {
+                Ok(host) => host,
+                Err(_) => return Vec::new(),
+            },
+            None => cpal::default_host(),
+        };
+        match host.output_devices() {
+            Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// 获取实际使用的音频主机
+    pub fn get_host_id(&self) -> Option<HostId> {
+        self.host_id
+    }
+
+    /// 获取实际使用的音频设备名称
+    pub fn get_device_name(&self) -> Option<&str> {
+        self.device_name.as_deref()
     }
 
     /// 判断设备是否可用
@@ -276,8 +1251,375 @@ impl AudioDevice {
         self.context.clone().mute.store(mute, Ordering::Relaxed);
     }
 
+    /// 设置用户提供的音频处理器，传入 None 时移除
+    pub fn set_dsp_processor(&self, processor: Option<DspProcessor>) {
+        *self.context.dsp_processor.lock().unwrap() = processor;
+    }
+
+    /// 设置音量之前各个处理步骤的顺序
+    pub fn set_processing_order(&self, order: ProcessingOrder) {
+        self.context.processing_order.store(dsp::pack_processing_order(order), Ordering::Relaxed);
+    }
+
+    /// 获取均衡器
+    pub fn get_equalizer(&self) -> &Mutex<Equalizer> {
+        &self.context.equalizer
+    }
+
+    /// 获取按声道延迟
+    pub fn get_channel_delay(&self) -> &Mutex<ChannelDelay> {
+        &self.context.channel_delay
+    }
+
+    /// 获取脉冲响应卷积
+    pub fn get_convolver(&self) -> &Mutex<Convolver> {
+        &self.context.convolver
+    }
+
+    /// 总输出的限幅器
+    pub fn get_limiter(&self) -> &Mutex<Limiter> {
+        &self.context.limiter
+    }
+
+    /// 最近一次输出回调请求的帧数，还没有回调时为 0
+    pub fn callback_frames(&self) -> usize {
+        self.context.callback_frames.load(Ordering::Relaxed) as usize
+    }
+
+    /// 当前一路输出缓冲区中等待播放的帧数
+    pub fn buffered_frames(&self) -> usize {
+        self.output_slots[self.active_slot].consumer.lock().unwrap().len()
+    }
+
+    /// 当前一路输出缓冲区中等待播放的数据的时长
+    pub fn buffer_latency(&self) -> Duration {
+        let sample_rate = self.context.sample_rate.load(Ordering::Relaxed);
+        if sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.buffered_frames() as f64 / sample_rate as f64)
+    }
+
+    /// 从写入输出缓冲区到实际播放的总延迟，包括输出缓冲区、设备缓冲区、卷积的分段和限幅器的预读
+    ///
+    /// 后端报告了播放时间时使用报告的值，否则按一次回调的帧数估计设备缓冲区的延迟
+    pub fn output_latency(&self) -> Duration {
+        let device_latency = match self.context.device_latency_us.load(Ordering::Relaxed) {
+            0 => {
+                let sample_rate = self.context.sample_rate.load(Ordering::Relaxed);
+                let frames = self.context.callback_frames.load(Ordering::Relaxed);
+                if sample_rate == 0 {
+                    Duration::ZERO
+                } else {
+                    Duration::from_secs_f64(frames as f64 / sample_rate as f64)
+                }
+            },
+            latency => Duration::from_micros(latency),
+        };
+        let sample_rate = self.context.sample_rate.load(Ordering::Relaxed);
+        let processing_frames = self.context.limiter.lock().unwrap().latency_frames()
+            + self.context.convolver.lock().unwrap().latency_frames();
+        let processing_latency = match sample_rate {
+            0 => Duration::ZERO,
+            _ => Duration::from_secs_f64(processing_frames as f64 / sample_rate as f64),
+        };
+        device_latency + self.buffer_latency() + processing_latency
+    }
+
+    /// 获取可以在其他线程中控制输出的句柄
+    pub fn output_control(&self) -> OutputControl {
+        OutputControl {
+            context: self.context.clone(),
+        }
+    }
+
+    /// 获取当前一路输出缓冲区的播放帧数计数
+    pub fn played_frame_counter(&self) -> PlayedFrameCounter {
+        PlayedFrameCounter {
+            context: self.context.clone(),
+            consumer: self.output_slots[self.active_slot].consumer.clone(),
+            slot: self.active_slot,
+        }
+    }
+
+    /// 设置当前一路输出缓冲区对应的播放源状态，打开新的播放源后调用
+    pub fn set_input_status(&self, status: Option<Arc<FFmpegSourceStatus>>) {
+        self.set_slot_input_status(self.active_slot, status);
+    }
+
+    /// 设置指定一路输出缓冲区对应的播放源状态
+    pub fn set_slot_input_status(&self, slot: usize, status: Option<Arc<FFmpegSourceStatus>>) {
+        self.context.buffering[slot].store(false, Ordering::Relaxed);
+        self.context.input_status.lock().unwrap()[slot] = status;
+    }
+
+    /// 设置进入缓冲状态后暂停消费、重新积累的帧数，0 表示缓冲期间照常消费，超过 MAX_REBUFFER_FRAMES 时截断
+    pub fn set_rebuffer_frames(&self, frames: usize) {
+        self.context.rebuffer_frames.store(frames.min(MAX_REBUFFER_FRAMES) as u64, Ordering::Relaxed);
+    }
+
+    /// 获取输出截断的样本计数
+    pub fn clip_counter(&self) -> ClipCounter {
+        ClipCounter {
+            context: self.context.clone(),
+        }
+    }
+
+    /// 获取输出音频分析
+    pub fn get_analysis(&self) -> &AnalysisTap {
+        &self.context.analysis
+    }
+
+    /// 获取输出音频录制
+    pub fn get_capture(&self) -> &CaptureTap {
+        &self.context.capture
+    }
+
     /// 清除指定的缓冲区
     pub fn clear_buffer(buffer_consumer: &Arc<Mutex<Consumer<AudioSample>>>) {
         buffer_consumer.lock().unwrap().discard(BUFFER_CAPACITY);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 向一路输出缓冲区写入立体声的锯齿波，返回写入的数据
+    fn feed(device: &AudioDevice, slot: usize, frames: usize, amplitude: f32) -> Vec<AudioSample> {
+        let samples: Vec<AudioSample> = (0..frames)
+            .map(|frame| {
+                let value = amplitude * (frame % 100) as f32 / 100.;
+                AudioSample::from_slice(&[value, -value])
+            })
+            .collect();
+        assert_eq!(device.get_slot_producer(slot).lock().unwrap().push_slice(&samples), frames);
+        samples
+    }
+
+    fn assert_scaled(output: &[AudioSample], input: &[AudioSample], gain: f32) {
+        assert_eq!(output.len(), input.len());
+        for (output, input) in output.iter().zip(input) {
+            for (output, input) in output.as_slice().iter().zip(input.as_slice()) {
+                assert!((output - input * gain).abs() < 1e-6, "expected {} but got {}", input * gain, output);
+            }
+        }
+    }
+
+    #[test]
+    fn pass_through_processor_leaves_the_output_unchanged() {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        device.set_dsp_processor(Some(Box::new(|_: &mut [AudioSample]| {})));
+        let input = feed(&device, 0, 512, 0.8);
+        assert_scaled(&device.render(512), &input, 1.);
+    }
+
+    #[test]
+    fn gain_processor_scales_the_output() {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        device.set_dsp_processor(Some(Box::new(|block: &mut [AudioSample]| {
+            for audio_sample in block.iter_mut() {
+                for sample in audio_sample.as_mut_slice() {
+                    *sample *= 0.5;
+                }
+            }
+        })));
+        let input = feed(&device, 0, 512, 0.8);
+        assert_scaled(&device.render(512), &input, 0.5);
+    }
+
+    #[test]
+    fn unity_gain_does_not_clip() {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        let input = feed(&device, 0, 512, 0.8);
+        assert_scaled(&device.render(512), &input, 1.);
+        assert_eq!(device.clip_counter().get(), 0);
+    }
+
+    #[test]
+    fn overdriven_output_is_clamped_and_counted() {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        device.set_dsp_processor(Some(Box::new(|block: &mut [AudioSample]| {
+            for audio_sample in block.iter_mut() {
+                for sample in audio_sample.as_mut_slice() {
+                    *sample *= 2.;
+                }
+            }
+        })));
+        feed(&device, 0, 512, 0.8);
+        let output = device.render(512);
+        assert!(output.iter().all(|audio_sample| audio_sample.as_slice().iter().all(|sample| sample.abs() <= 1.)));
+        // 锯齿波每 100 帧中有 37 帧放大之后超过 1，两个声道分别计数
+        assert_eq!(device.clip_counter().get(), 5 * 37 * 2);
+
+        device.clip_counter().reset();
+        assert_eq!(device.clip_counter().get(), 0);
+    }
+
+    #[test]
+    fn both_decks_are_summed_with_their_own_volumes() {
+        let mut device = AudioDevice::null(SAMPLE_RATE, 2);
+        let deck = device.open_deck_slot();
+        assert_eq!(deck, 1);
+        device.set_slot_volume(0, -6.);
+        device.set_slot_volume(deck, -12.);
+        let first = feed(&device, 0, 512, 0.8);
+        let constant = vec![AudioSample::from_slice(&[0.4, -0.2]); 512];
+        device.get_slot_producer(deck).lock().unwrap().push_slice(&constant);
+
+        let output = device.render(512);
+        let first_gain = volume::db_gain_to_amplitude(-6.);
+        let deck_gain = volume::db_gain_to_amplitude(-12.);
+        for (output, first) in output.iter().zip(&first) {
+            assert!((output.ch1() - (first.ch1() * first_gain + 0.4 * deck_gain)).abs() < 1e-6);
+            assert!((output.ch2() - (first.ch2() * first_gain - 0.2 * deck_gain)).abs() < 1e-6);
+        }
+
+        // 关闭第二个 deck 之后只剩第一路
+        device.close_deck_slot();
+        let first = feed(&device, 0, 512, 0.8);
+        assert_scaled(&device.render(512), &first, first_gain);
+    }
+
+    /// 1 kHz 正弦波经过 +12 dB 的均衡器和把幅度限制在 0.3 的用户处理器，返回稳定之后的峰值
+    fn peak_with_order(order: ProcessingOrder) -> f32 {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        device.get_equalizer().lock().unwrap().set_bands(&[1000.], 1., &[12.]);
+        device.get_equalizer().lock().unwrap().set_enabled(true);
+        device.set_dsp_processor(Some(Box::new(|block: &mut [AudioSample]| {
+            for audio_sample in block.iter_mut() {
+                for sample in audio_sample.as_mut_slice() {
+                    *sample = sample.clamp(-0.3, 0.3);
+                }
+            }
+        })));
+        device.set_processing_order(order);
+        let sine: Vec<AudioSample> = (0..4800)
+            .map(|frame| {
+                let value = 0.2 * (2. * std::f32::consts::PI * 1000. * frame as f32 / SAMPLE_RATE as f32).sin();
+                AudioSample::from_slice(&[value, value])
+            })
+            .collect();
+        device.get_slot_producer(0).lock().unwrap().push_slice(&sine);
+        let output = device.render(4800);
+        output[2400..].iter().map(|audio_sample| audio_sample.ch1().abs()).fold(0., f32::max)
+    }
+
+    #[test]
+    fn equalizer_before_or_after_a_gain_stage_changes_the_output() {
+        // 先限制幅度再提升：没有达到限制，均衡器把 0.2 提升到大约 0.8
+        let mut order = DEFAULT_PROCESSING_ORDER;
+        order.sort_by_key(|stage| *stage != DspStage::UserProcessor);
+        let boosted = peak_with_order(order);
+        assert!(boosted > 0.7, "peak is {}", boosted);
+        // 先提升再限制：输出被限制在 0.3
+        let limited = peak_with_order(DEFAULT_PROCESSING_ORDER);
+        assert!((limited - 0.3).abs() < 1e-6, "peak is {}", limited);
+    }
+
+    #[test]
+    fn processing_order_survives_packing() {
+        let order = [DspStage::UserProcessor, DspStage::Convolution, DspStage::Equalizer, DspStage::ChannelDelay];
+        assert_eq!(dsp::unpack_processing_order(dsp::pack_processing_order(order)), order);
+        assert_eq!(
+            dsp::unpack_processing_order(dsp::pack_processing_order(DEFAULT_PROCESSING_ORDER)),
+            DEFAULT_PROCESSING_ORDER,
+        );
+    }
+
+    #[test]
+    fn rendering_reuses_the_preallocated_block() {
+        let device = AudioDevice::null(SAMPLE_RATE, 2);
+        let consumers: OutputConsumers = [
+            device.get_slot_consumer(0).clone(),
+            device.get_slot_consumer(1).clone(),
+        ];
+        let mut block = Vec::with_capacity(256);
+        let allocation = block.as_ptr();
+        for _ in 0..4 {
+            feed(&device, 0, 256, 0.5);
+            render_block(&device.context, &consumers, 2, 256, &mut block);
+            assert_eq!(block.len(), 256);
+            assert_eq!(block.as_ptr(), allocation);
+        }
+    }
+
+    #[test]
+    fn crossfade_keeps_the_previous_gains_while_the_fades_are_locked() {
+        let mut device = AudioDevice::null(SAMPLE_RATE, 2);
+        let constant = vec![AudioSample::from_slice(&[0.5, 0.5]); 4800];
+        device.get_slot_producer(0).lock().unwrap().push_slice(&constant);
+        // 第一路在 480 帧内淡出，新的一路没有数据
+        device.begin_crossfade(Duration::from_millis(10));
+        let first = device.render(240);
+        assert!((first[0].as_slice()[0] - 0.5).abs() < 1e-6);
+        let gain = first[239].as_slice()[0] / 0.5;
+        assert!(0.4 < gain && gain < 0.6, "gain after half of the fade is {}", gain);
+
+        // 包络被其他线程持有时输出不等待，增益停在上一块结束时的值
+        let fades = device.context.fades.lock().unwrap();
+        let held = device.render(240);
+        drop(fades);
+        let last_gain = f32::from_bits(device.context.slot_gains[0].load(Ordering::Relaxed));
+        for audio_sample in held.iter() {
+            assert!((audio_sample.as_slice()[0] - 0.5 * last_gain).abs() < 1e-6);
+        }
+
+        // 释放之后继续淡出到静音
+        let rest = device.render(480);
+        assert_eq!(rest[479].as_slice()[0], 0.);
+        assert!(!device.is_crossfading());
+    }
+
+    #[test]
+    fn an_unavailable_preferred_host_falls_back_to_the_other_hosts() {
+        for &preferred in cpal::ALL_HOSTS {
+            // 编译进来的主机在运行时不一定可用，例如没有安装 JACK
+            let available: Vec<HostId> = cpal::ALL_HOSTS.iter().copied().filter(|host_id| *host_id != preferred).collect();
+            let default_host_id = available.first().copied().unwrap_or(preferred);
+            let host_ids = host_order(Some(preferred), default_host_id, &available);
+            assert_eq!(host_ids[0], default_host_id);
+            assert_eq!(host_ids.contains(&preferred), default_host_id == preferred);
+            assert!(available.iter().all(|host_id| host_ids.contains(host_id)));
+        }
+    }
+
+    #[test]
+    fn an_available_preferred_host_is_tried_first_and_only_once() {
+        let default_host_id = cpal::ALL_HOSTS[0];
+        for &preferred in cpal::ALL_HOSTS {
+            let host_ids = host_order(Some(preferred), default_host_id, cpal::ALL_HOSTS);
+            assert_eq!(host_ids[0], preferred);
+            assert_eq!(host_ids.len(), cpal::ALL_HOSTS.len());
+            assert!(cpal::ALL_HOSTS.iter().all(|host_id| host_ids.contains(host_id)));
+        }
+        assert_eq!(host_order(None, default_host_id, cpal::ALL_HOSTS)[0], default_host_id);
+    }
+
+    #[test]
+    fn stereo_routed_to_the_first_two_of_four_channels() {
+        let mut frame = [1f32; 4];
+        route_channels(&mut frame, &AudioSample::from_slice(&[0.25, -0.5]), &[0, 1]);
+        assert_eq!(frame, [0.25, -0.5, 0., 0.]);
+
+        // 也可以送到后两个声道，前两个声道静音
+        route_channels(&mut frame, &AudioSample::from_slice(&[0.25, -0.5]), &[2, 3]);
+        assert_eq!(frame, [0., 0., 0.25, -0.5]);
+    }
+
+    #[test]
+    fn six_channels_mixed_down_to_stereo() {
+        // 左、中、左环绕到设备左声道，右、低音、右环绕到设备右声道
+        let mut frame = [0f32; 2];
+        let audio_sample = AudioSample::from_slice(&[0.1, 0.2, 0.3, 0.4, 0.05, 0.15]);
+        route_channels(&mut frame, &audio_sample, &[0, 1, 0, 1, 0, 1]);
+        assert!((frame[0] - 0.45).abs() < 1e-6, "left is {}", frame[0]);
+        assert!((frame[1] - 0.75).abs() < 1e-6, "right is {}", frame[1]);
+
+        // 相加超过满刻度时限制在 [-1, 1]
+        let loud = AudioSample::from_slice(&[0.8, -0.8, 0.8, -0.8, 0.8, -0.8]);
+        route_channels(&mut frame, &loud, &[0, 1, 0, 1, 0, 1]);
+        assert_eq!(frame, [1., -1.]);
+    }
+}
@@ -0,0 +1,363 @@
+use std::sync::Arc;
+
+use super::super::fft::FftPlan;
+use super::super::sample::AudioSample;
+
+/// 每个分段的帧数，也是卷积增加的延迟
+pub const BLOCK_FRAMES: usize = 256;
+
+/// 分段补零之后的变换长度
+const FFT_SIZE: usize = BLOCK_FRAMES * 2;
+
+/// 实数信号的频谱是共轭对称的，只需要保存前一半的频点
+const BINS: usize = FFT_SIZE / 2 + 1;
+
+/// 脉冲响应的最大时长（秒），更长的部分会被截掉。输出回调中的计算量和脉冲响应的长度成正比
+pub const MAX_IMPULSE_RESPONSE_SECONDS: u32 = 3;
+
+/// 已经按设备采样率准备好的脉冲响应
+///
+/// 按 BLOCK_FRAMES 分段，每段补零后变换到频域，在加载时完成，不占用实时线程
+pub struct ImpulseResponse {
+    sample_rate: u32,
+    channels: usize,
+    partitions: usize,
+    /// 按 [(声道 * 分段数 + 分段) * BINS + 频点] 存放的频谱
+    spectrum_re: Vec<f32>,
+    spectrum_im: Vec<f32>,
+}
+
+impl ImpulseResponse {
+    /// samples 为交错的采样。按能量最大的声道归一化，单位冲激保持原样，混响不会比原声响很多
+    ///
+    /// 所有采样都是 0 时返回 None
+    pub fn new(samples: &[f32], channels: usize, sample_rate: u32) -> Option<ImpulseResponse> {
+        if channels == 0 {
+            return None;
+        }
+        let frames = samples.len() / channels;
+        let norm = (0..channels)
+            .map(|channel| {
+                samples.iter().skip(channel).step_by(channels).map(|sample| sample * sample).sum::<f32>().sqrt()
+            })
+            .fold(0f32, f32::max);
+        if frames == 0 || norm <= 0. {
+            return None;
+        }
+
+        let partitions = (frames + BLOCK_FRAMES - 1) / BLOCK_FRAMES;
+        let plan = FftPlan::new(FFT_SIZE);
+        let mut spectrum_re = vec![0.; channels * partitions * BINS];
+        let mut spectrum_im = vec![0.; channels * partitions * BINS];
+        let mut re = vec![0.; FFT_SIZE];
+        let mut im = vec![0.; FFT_SIZE];
+        for channel in 0..channels {
+            for partition in 0..partitions {
+                re.iter_mut().for_each(|value| *value = 0.);
+                im.iter_mut().for_each(|value| *value = 0.);
+                let start = partition * BLOCK_FRAMES;
+                for (index, frame) in (start..frames.min(start + BLOCK_FRAMES)).enumerate() {
+                    re[index] = samples[frame * channels + channel] / norm;
+                }
+                plan.forward(&mut re, &mut im);
+                let offset = (channel * partitions + partition) * BINS;
+                spectrum_re[offset..offset + BINS].copy_from_slice(&re[..BINS]);
+                spectrum_im[offset..offset + BINS].copy_from_slice(&im[..BINS]);
+            }
+        }
+
+        Some(ImpulseResponse { sample_rate, channels, partitions, spectrum_re, spectrum_im })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// 截断之后的长度（帧），按分段向上取整
+    pub fn length_frames(&self) -> usize {
+        self.partitions * BLOCK_FRAMES
+    }
+}
+
+/// 按输出声道数分配好的卷积状态
+///
+/// 分配的内存和脉冲响应的长度成正比，在实时线程之外创建，再交给 Convolver
+pub struct ConvolutionState {
+    impulse_response: Arc<ImpulseResponse>,
+    channels: usize,
+    plan: FftPlan,
+    /// 每个声道最近两个分段的输入，按 [声道 * FFT_SIZE + 位置] 存放
+    input: Vec<f32>,
+    /// 每个声道最近 partitions 个输入分段的频谱，环形存放
+    history_re: Vec<f32>,
+    history_im: Vec<f32>,
+    history_position: usize,
+    /// 上一个分段的输出，按 [声道 * BLOCK_FRAMES + 位置] 存放
+    output: Vec<f32>,
+    /// 当前分段已经写入的帧数
+    filled: usize,
+    scratch_re: Vec<f32>,
+    scratch_im: Vec<f32>,
+    sum_re: Vec<f32>,
+    sum_im: Vec<f32>,
+}
+
+impl ConvolutionState {
+    /// 单声道的脉冲响应用于所有声道，声道数和输出相同时逐个声道对应，否则按声道序号循环使用
+    pub fn new(impulse_response: Arc<ImpulseResponse>, channels: usize) -> ConvolutionState {
+        let partitions = impulse_response.partitions;
+        ConvolutionState {
+            impulse_response,
+            channels,
+            plan: FftPlan::new(FFT_SIZE),
+            input: vec![0.; channels * FFT_SIZE],
+            history_re: vec![0.; channels * partitions * BINS],
+            history_im: vec![0.; channels * partitions * BINS],
+            history_position: 0,
+            output: vec![0.; channels * BLOCK_FRAMES],
+            filled: 0,
+            scratch_re: vec![0.; FFT_SIZE],
+            scratch_im: vec![0.; FFT_SIZE],
+            sum_re: vec![0.; BINS],
+            sum_im: vec![0.; BINS],
+        }
+    }
+
+    /// 清除之前的输入，从静音开始
+    pub fn reset(&mut self) {
+        self.input.iter_mut().for_each(|sample| *sample = 0.);
+        self.history_re.iter_mut().for_each(|value| *value = 0.);
+        self.history_im.iter_mut().for_each(|value| *value = 0.);
+        self.output.iter_mut().for_each(|sample| *sample = 0.);
+        self.history_position = 0;
+        self.filled = 0;
+    }
+
+    fn process(&mut self, block: &mut [AudioSample]) {
+        for audio_sample in block.iter_mut() {
+            let samples = audio_sample.as_mut_slice();
+            if samples.len() != self.channels {
+                // 声道数只会在打开设备时改变，此时会重新创建状态
+                return;
+            }
+            for (channel, sample) in samples.iter_mut().enumerate() {
+                self.input[channel * FFT_SIZE + BLOCK_FRAMES + self.filled] = *sample;
+                *sample = self.output[channel * BLOCK_FRAMES + self.filled];
+            }
+            self.filled += 1;
+            if self.filled == BLOCK_FRAMES {
+                self.convolve_partition();
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// 重叠保留法：变换最近两个分段的输入，和每个脉冲响应分段对应的历史频谱相乘后累加，
+    /// 逆变换的后半部分就是这个分段的输出
+    fn convolve_partition(&mut self) {
+        let partitions = self.impulse_response.partitions;
+        let impulse_channels = self.impulse_response.channels;
+        for channel in 0..self.channels {
+            let input = &mut self.input[channel * FFT_SIZE..(channel + 1) * FFT_SIZE];
+            self.scratch_re.copy_from_slice(input);
+            self.scratch_im.iter_mut().for_each(|value| *value = 0.);
+            input.copy_within(BLOCK_FRAMES.., 0);
+            self.plan.forward(&mut self.scratch_re, &mut self.scratch_im);
+
+            let history = channel * partitions;
+            let offset = (history + self.history_position) * BINS;
+            self.history_re[offset..offset + BINS].copy_from_slice(&self.scratch_re[..BINS]);
+            self.history_im[offset..offset + BINS].copy_from_slice(&self.scratch_im[..BINS]);
+
+            let impulse_channel = if impulse_channels == self.channels { channel } else { channel % impulse_channels };
+            self.sum_re.iter_mut().for_each(|value| *value = 0.);
+            self.sum_im.iter_mut().for_each(|value| *value = 0.);
+            for partition in 0..partitions {
+                // 第 k 个脉冲响应分段和 k 个分段之前的输入相乘
+                let slot = (self.history_position + partitions - partition) % partitions;
+                let x = (history + slot) * BINS;
+                let h = (impulse_channel * partitions + partition) * BINS;
+                let x_re = &self.history_re[x..x + BINS];
+                let x_im = &self.history_im[x..x + BINS];
+                let h_re = &self.impulse_response.spectrum_re[h..h + BINS];
+                let h_im = &self.impulse_response.spectrum_im[h..h + BINS];
+                for bin in 0..BINS {
+                    self.sum_re[bin] += x_re[bin] * h_re[bin] - x_im[bin] * h_im[bin];
+                    self.sum_im[bin] += x_re[bin] * h_im[bin] + x_im[bin] * h_re[bin];
+                }
+            }
+
+            // 按共轭对称补全另一半频点
+            self.scratch_re[..BINS].copy_from_slice(&self.sum_re);
+            self.scratch_im[..BINS].copy_from_slice(&self.sum_im);
+            for bin in BINS..FFT_SIZE {
+                self.scratch_re[bin] = self.sum_re[FFT_SIZE - bin];
+                self.scratch_im[bin] = -self.sum_im[FFT_SIZE - bin];
+            }
+            self.plan.inverse(&mut self.scratch_re, &mut self.scratch_im);
+            self.output[channel * BLOCK_FRAMES..(channel + 1) * BLOCK_FRAMES]
+                .copy_from_slice(&self.scratch_re[BLOCK_FRAMES..]);
+        }
+        self.history_position = (self.history_position + 1) % partitions;
+    }
+}
+
+/// 用脉冲响应对总输出做卷积，例如模拟房间混响或者耳机校正
+///
+/// 使用均匀分段的频域卷积，每 BLOCK_FRAMES 帧做一次变换，输出固定延迟 BLOCK_FRAMES 帧。
+/// 输出回调中不分配内存，脉冲响应和状态都在其他线程上准备好之后整体替换
+pub struct Convolver {
+    enabled: bool,
+    state: Option<ConvolutionState>,
+}
+
+impl Convolver {
+    pub fn new() -> Convolver {
+        Convolver { enabled: false, state: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 开启并且已经加载了脉冲响应
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.state.is_some()
+    }
+
+    /// 开关卷积，开启时从静音的输入开始
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            if let Some(state) = self.state.as_mut() {
+                state.reset();
+            }
+        }
+        self.enabled = enabled;
+    }
+
+    /// 替换卷积状态，返回原来的状态，由调用者在锁外释放
+    pub fn set_state(&mut self, state: Option<ConvolutionState>) -> Option<ConvolutionState> {
+        std::mem::replace(&mut self.state, state)
+    }
+
+    /// 当前使用的脉冲响应
+    pub fn impulse_response(&self) -> Option<&Arc<ImpulseResponse>> {
+        self.state.as_ref().map(|state| &state.impulse_response)
+    }
+
+    /// 开启时增加的延迟
+    pub fn latency_frames(&self) -> usize {
+        if self.is_active() {
+            BLOCK_FRAMES
+        } else {
+            0
+        }
+    }
+
+    /// 处理一块音频数据
+    pub fn process(&mut self, block: &mut [AudioSample]) {
+        if !self.enabled {
+            return;
+        }
+        if let Some(state) = self.state.as_mut() {
+            state.process(block);
+        }
+    }
+}
+
+impl Default for Convolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 两个声道不同的确定性信号，长度为 blocks 个分段
+    fn signal(blocks: usize) -> Vec<AudioSample> {
+        (0..blocks * BLOCK_FRAMES)
+            .map(|frame| {
+                let mut audio_sample = AudioSample::silence(2);
+                audio_sample.as_mut_slice()[0] = (frame as f32 * 0.05).sin() * 0.5;
+                audio_sample.as_mut_slice()[1] = ((frame * 7 % 13) as f32 / 13. - 0.5) * 0.5;
+                audio_sample
+            })
+            .collect()
+    }
+
+    /// 单声道的脉冲响应，第 delay 帧为 1，其余为 0
+    fn delayed_impulse(delay: usize) -> Convolver {
+        let mut samples = vec![0.; delay + 1];
+        samples[delay] = 1.;
+        let impulse_response = ImpulseResponse::new(&samples, 1, SAMPLE_RATE).unwrap();
+        let mut convolver = Convolver::new();
+        convolver.set_state(Some(ConvolutionState::new(Arc::new(impulse_response), 2)));
+        convolver.set_enabled(true);
+        convolver
+    }
+
+    /// 输出是输入延迟 shift 帧，之前是静音
+    fn assert_shifted(input: &[AudioSample], output: &[AudioSample], shift: usize) {
+        for (frame, audio_sample) in output.iter().enumerate() {
+            for channel in 0..2 {
+                let expected = match frame.checked_sub(shift) {
+                    Some(source) => input[source].as_slice()[channel],
+                    None => 0.,
+                };
+                let actual = audio_sample.as_slice()[channel];
+                assert!((actual - expected).abs() < 1e-4, "frame {} channel {}: {} != {}", frame, channel, actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn unit_impulse_passes_the_input_through() {
+        let mut convolver = delayed_impulse(0);
+        assert_eq!(convolver.latency_frames(), BLOCK_FRAMES);
+        let input = signal(6);
+        let mut output = input.clone();
+        convolver.process(&mut output);
+        // 只有分段带来的固定延迟
+        assert_shifted(&input, &output, BLOCK_FRAMES);
+    }
+
+    #[test]
+    fn delayed_impulse_delays_the_input() {
+        // 落在第一个分段内和跨到后面的分段
+        for delay in [37, BLOCK_FRAMES + 91] {
+            let mut convolver = delayed_impulse(delay);
+            let input = signal(6);
+            let mut output = input.clone();
+            // 按输出回调的方式分成不对齐的小块处理
+            for chunk in output.chunks_mut(100) {
+                convolver.process(chunk);
+            }
+            assert_shifted(&input, &output, BLOCK_FRAMES + delay);
+        }
+    }
+
+    #[test]
+    fn disabled_convolver_leaves_the_input_unchanged() {
+        let mut convolver = delayed_impulse(10);
+        convolver.set_enabled(false);
+        assert_eq!(convolver.latency_frames(), 0);
+        let input = signal(2);
+        let mut output = input.clone();
+        convolver.process(&mut output);
+        assert_shifted(&input, &output, 0);
+    }
+
+    #[test]
+    fn silent_impulse_response_is_rejected() {
+        assert!(ImpulseResponse::new(&[0.; 64], 1, SAMPLE_RATE).is_none());
+        assert!(ImpulseResponse::new(&[], 2, SAMPLE_RATE).is_none());
+    }
+}
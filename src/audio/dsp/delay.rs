@@ -0,0 +1,251 @@
+use super::super::sample::AudioSample;
+
+/// 每个声道允许的最大延迟（毫秒），约等于 68 米的距离差
+pub const MAX_CHANNEL_DELAY_MS: u32 = 200;
+
+/// 修改延迟时新旧延迟之间交叉淡化的时长（毫秒），避免直接跳变产生爆音
+const RAMP_MS: u32 = 10;
+
+/// 单个声道的延迟状态
+#[derive(Debug, Clone, Copy, Default)]
+struct DelayTap {
+    /// 当前使用的延迟（采样数）
+    current: usize,
+    /// 要切换到的延迟（采样数）
+    target: usize,
+    /// 交叉淡化的进度，等于 ramp_length 时切换完成
+    ramp_position: usize,
+}
+
+/// 按声道延迟输出，用于对齐到听音位置距离不同的音箱
+///
+/// 每个声道使用一条环形延迟线，长度按采样率和最大延迟分配。延迟线在打开设备时分配，音频回调中不分配内存
+pub struct ChannelDelay {
+    sample_rate: u32,
+    /// 用户设置的每个声道的延迟（毫秒）
+    delays_ms: Vec<u32>,
+    taps: Vec<DelayTap>,
+    /// 每个声道的环形缓冲区，按 [位置 * 声道数 + 声道] 交错存放
+    buffer: Vec<f32>,
+    /// 每个声道的环形缓冲区长度（采样数）
+    capacity: usize,
+    write_position: usize,
+    channels: usize,
+    ramp_length: usize,
+    /// 上一块数据是否经过了延迟处理
+    active: bool,
+}
+
+impl ChannelDelay {
+    pub fn new() -> ChannelDelay {
+        let mut delay = ChannelDelay {
+            sample_rate: 48000,
+            delays_ms: Vec::new(),
+            taps: Vec::new(),
+            buffer: Vec::new(),
+            capacity: 0,
+            write_position: 0,
+            channels: 0,
+            ramp_length: 0,
+            active: false,
+        };
+        delay.update_length();
+        delay
+    }
+
+    /// 按设备的采样率和声道数分配延迟线，在打开设备时调用
+    pub fn set_format(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate > 0 && (sample_rate != self.sample_rate || channels != self.channels) {
+            self.sample_rate = sample_rate;
+            self.update_length();
+            // 采样率改变后原来的数据已经没有意义
+            self.resize(channels);
+        }
+    }
+
+    /// 每个声道的延迟（毫秒），没有设置的声道不延迟
+    pub fn delays(&self) -> &[u32] {
+        &self.delays_ms
+    }
+
+    /// 设置每个声道的延迟（毫秒），超过 MAX_CHANNEL_DELAY_MS 的值会被限制
+    pub fn set_delays(&mut self, delays_ms: &[u32]) {
+        self.delays_ms = delays_ms.iter().map(|delay| (*delay).min(MAX_CHANNEL_DELAY_MS)).collect();
+        for channel in 0..self.taps.len() {
+            self.retarget(channel);
+        }
+    }
+
+    /// 是否需要处理，所有声道都不延迟并且没有正在进行的切换时跳过
+    pub fn is_active(&self) -> bool {
+        self.delays_ms.iter().any(|delay| *delay > 0)
+            || self.taps.iter().any(|tap| tap.current != tap.target || tap.current > 0)
+    }
+
+    /// 清除延迟线中的数据
+    pub fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|sample| *sample = 0.);
+        self.write_position = 0;
+    }
+
+    /// 处理一块音频数据
+    pub fn process(&mut self, block: &mut [AudioSample]) {
+        if !self.is_active() {
+            self.active = false;
+            return;
+        }
+        let channels = match block.first() {
+            Some(audio_sample) => audio_sample.channels(),
+            None => return,
+        };
+        if channels != self.channels {
+            // 声道数只会在打开设备时改变，此时已经按新的声道数分配了延迟线
+            return;
+        }
+        if !self.active {
+            // 没有处理的期间延迟线中的数据已经过时
+            self.reset();
+        }
+        self.active = true;
+
+        for audio_sample in block.iter_mut() {
+            let frame_offset = self.write_position * channels;
+            for (channel, sample) in audio_sample.as_mut_slice().iter_mut().enumerate() {
+                self.buffer[frame_offset + channel] = *sample;
+                let tap = self.taps[channel];
+                let current = self.read(channel, tap.current);
+                *sample = if tap.current == tap.target {
+                    current
+                } else {
+                    let target = self.read(channel, tap.target);
+                    let progress = tap.ramp_position as f32 / self.ramp_length as f32;
+                    current * (1. - progress) + target * progress
+                };
+                self.advance_ramp(channel);
+            }
+            self.write_position = (self.write_position + 1) % self.capacity;
+        }
+    }
+
+    /// 读取 delay 个采样之前写入的数据
+    fn read(&self, channel: usize, delay: usize) -> f32 {
+        let position = (self.write_position + self.capacity - delay) % self.capacity;
+        self.buffer[position * self.channels + channel]
+    }
+
+    fn advance_ramp(&mut self, channel: usize) {
+        let ramp_length = self.ramp_length;
+        let tap = &mut self.taps[channel];
+        if tap.current != tap.target {
+            tap.ramp_position += 1;
+            if tap.ramp_position >= ramp_length {
+                tap.current = tap.target;
+                tap.ramp_position = 0;
+            }
+        }
+    }
+
+    /// 根据用户设置更新声道的目标延迟
+    fn retarget(&mut self, channel: usize) {
+        let target = self.delay_samples(channel);
+        let tap = &mut self.taps[channel];
+        if tap.target != target {
+            // 上一次切换还没有完成时直接从上一次的目标开始
+            if tap.current != tap.target {
+                tap.current = tap.target;
+            }
+            tap.target = target;
+            tap.ramp_position = 0;
+        }
+    }
+
+    fn delay_samples(&self, channel: usize) -> usize {
+        let delay_ms = self.delays_ms.get(channel).copied().unwrap_or(0);
+        (delay_ms as u64 * self.sample_rate as u64 / 1000) as usize
+    }
+
+    fn update_length(&mut self) {
+        self.capacity = (MAX_CHANNEL_DELAY_MS as u64 * self.sample_rate as u64 / 1000) as usize + 1;
+        self.ramp_length = ((RAMP_MS as u64 * self.sample_rate as u64 / 1000) as usize).max(1);
+    }
+
+    /// 按声道数重新分配延迟线，新的延迟立即生效
+    fn resize(&mut self, channels: usize) {
+        self.channels = channels;
+        self.buffer = vec![0.; self.capacity * channels];
+        self.write_position = 0;
+        self.taps = (0..channels)
+            .map(|channel| {
+                let delay = self.delay_samples(channel);
+                DelayTap { current: delay, target: delay, ramp_position: 0 }
+            })
+            .collect();
+    }
+}
+
+impl Default for ChannelDelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 第一帧所有声道都是 1 的冲激，之后是静音
+    fn impulse(channels: usize, frames: usize) -> Vec<AudioSample> {
+        let mut block = vec![AudioSample::silence(channels); frames];
+        block[0].as_mut_slice().iter_mut().for_each(|sample| *sample = 1.);
+        block
+    }
+
+    /// 声道中非零采样的位置
+    fn nonzero_frames(block: &[AudioSample], channel: usize) -> Vec<usize> {
+        block.iter().enumerate().filter(|(_, audio_sample)| audio_sample.as_slice()[channel] != 0.).map(|(frame, _)| frame).collect()
+    }
+
+    #[test]
+    fn delayed_channel_shifts_the_impulse() {
+        let mut delay = ChannelDelay::new();
+        delay.set_delays(&[0, 1, 2]);
+        delay.set_format(SAMPLE_RATE, 3);
+
+        let mut block = impulse(3, 256);
+        delay.process(&mut block);
+        assert_eq!(nonzero_frames(&block, 0), vec![0]);
+        assert_eq!(nonzero_frames(&block, 1), vec![48]);
+        assert_eq!(nonzero_frames(&block, 2), vec![96]);
+        assert_eq!(block[48].as_slice()[1], 1.);
+    }
+
+    #[test]
+    fn changed_delay_applies_after_the_ramp() {
+        let mut delay = ChannelDelay::new();
+        delay.set_format(SAMPLE_RATE, 2);
+        delay.set_delays(&[0, 1]);
+        // 先让切换的交叉淡化结束
+        let mut silence = vec![AudioSample::silence(2); (RAMP_MS * SAMPLE_RATE / 1000) as usize + 1];
+        delay.process(&mut silence);
+
+        let mut block = impulse(2, 256);
+        delay.process(&mut block);
+        assert_eq!(nonzero_frames(&block, 0), vec![0]);
+        assert_eq!(nonzero_frames(&block, 1), vec![48]);
+    }
+
+    #[test]
+    fn block_with_other_channel_count_passes_through() {
+        let mut delay = ChannelDelay::new();
+        delay.set_delays(&[5]);
+        delay.set_format(SAMPLE_RATE, 2);
+        let capacity = delay.buffer.capacity();
+
+        let mut block = impulse(1, 64);
+        delay.process(&mut block);
+        assert_eq!(nonzero_frames(&block, 0), vec![0]);
+        assert_eq!(delay.buffer.capacity(), capacity);
+    }
+}
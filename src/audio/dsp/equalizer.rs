@@ -0,0 +1,408 @@
+use std::f64::consts::PI;
+
+use super::super::sample::AudioSample;
+
+/// 默认的十段图示均衡器中心频率
+pub const DEFAULT_BAND_FREQUENCIES: [f32; 10] = [31., 62., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.];
+
+/// ISO 266 的倍频程中心频率，用于十段图示均衡器
+pub const OCTAVE_BAND_FREQUENCIES: [f32; 10] = [31.5, 63., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.];
+
+/// ISO 266 的 1/3 倍频程中心频率，用于三十一段图示均衡器
+pub const THIRD_OCTAVE_BAND_FREQUENCIES: [f32; 31] = [
+    20., 25., 31.5, 40., 50., 63., 80., 100., 125., 160., 200., 250., 315., 400., 500., 630., 800.,
+    1000., 1250., 1600., 2000., 2500., 3150., 4000., 5000., 6300., 8000., 10000., 12500., 16000., 20000.,
+];
+
+/// 每个频段允许的最大增益（分贝）
+pub const MAX_BAND_GAIN: f32 = 24.;
+
+/// 默认频段的品质因数，相邻的倍频程频段之间平滑过渡
+const BAND_Q: f64 = 1.41;
+
+/// 估计最大增益时检查的频率点数
+const RESPONSE_POINTS: usize = 512;
+/// 估计最大增益时检查的最低频率
+const RESPONSE_MIN_FREQUENCY: f64 = 10.;
+/// 估计的最大增益是在有限的频率点上取得的，自动增益多降低一点，保证峰值不超过 0 dBFS
+const AUTO_GAIN_MARGIN_DB: f32 = 0.1;
+
+/// 带宽为 octaves 个倍频程的峰值滤波器的品质因数，一个倍频程约为 1.41，1/3 倍频程约为 4.32
+fn bandwidth_q(octaves: f64) -> f64 {
+    2f64.powf(octaves / 2.) / (2f64.powf(octaves) - 1.)
+}
+
+/// 十段或者三十一段图示均衡器的中心频率和品质因数，其他段数返回 None
+pub fn graphic_layout(num_bands: usize) -> Option<(&'static [f32], f64)> {
+    match num_bands {
+        10 => Some((&OCTAVE_BAND_FREQUENCIES, bandwidth_q(1.))),
+        31 => Some((&THIRD_OCTAVE_BAND_FREQUENCIES, bandwidth_q(1. / 3.))),
+        _ => None,
+    }
+}
+
+/// 二阶滤波器系数（已经按 a0 归一化）
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Biquad {
+    const IDENTITY: Biquad = Biquad { b0: 1., b1: 0., b2: 0., a1: 0., a2: 0. };
+
+    /// 峰值滤波器，参考 Robert Bristow-Johnson 的 Audio EQ Cookbook
+    fn peaking(frequency: f64, gain_db: f64, q: f64, sample_rate: f64) -> Biquad {
+        // 超过奈奎斯特频率的频段没有意义
+        if gain_db == 0. || frequency <= 0. || frequency >= sample_rate / 2. {
+            return Biquad::IDENTITY;
+        }
+        let a = 10f64.powf(gain_db / 40.);
+        let w0 = 2. * PI * frequency / sample_rate;
+        let alpha = w0.sin() / (2. * q);
+        let cos_w0 = w0.cos();
+
+        let a0 = 1. + alpha / a;
+        Biquad {
+            b0: (1. + alpha * a) / a0,
+            b1: -2. * cos_w0 / a0,
+            b2: (1. - alpha * a) / a0,
+            a1: -2. * cos_w0 / a0,
+            a2: (1. - alpha / a) / a0,
+        }
+    }
+
+    /// 角频率 w 处的幅度响应
+    fn magnitude(&self, w: f64) -> f64 {
+        let (sin_w, cos_w) = w.sin_cos();
+        let (sin_2w, cos_2w) = (2. * w).sin_cos();
+        let numerator_re = self.b0 + self.b1 * cos_w + self.b2 * cos_2w;
+        let numerator_im = -self.b1 * sin_w - self.b2 * sin_2w;
+        let denominator_re = 1. + self.a1 * cos_w + self.a2 * cos_2w;
+        let denominator_im = -self.a1 * sin_w - self.a2 * sin_2w;
+        (numerator_re.hypot(numerator_im)) / (denominator_re.hypot(denominator_im))
+    }
+}
+
+/// 图示均衡器，由一组串联的峰值滤波器组成
+///
+/// 开启自动增益时，根据所有频段叠加后的最大增益降低输出，避免提升频段后削波
+#[derive(Clone)]
+pub struct Equalizer {
+    enabled: bool,
+    auto_gain: bool,
+    sample_rate: u32,
+    frequencies: Vec<f32>,
+    /// 所有频段共用的品质因数
+    q: f64,
+    gains: Vec<f32>,
+    filters: Vec<Biquad>,
+    /// 每个频段、每个声道的滤波器状态
+    states: Vec<[f32; 2]>,
+    channels: usize,
+    /// 自动增益的补偿（分贝，不大于 0）
+    compensation_db: f32,
+}
+
+impl Equalizer {
+    pub fn new() -> Equalizer {
+        let mut equalizer = Equalizer {
+            enabled: false,
+            auto_gain: false,
+            sample_rate: 48000,
+            frequencies: DEFAULT_BAND_FREQUENCIES.to_vec(),
+            q: BAND_Q,
+            gains: vec![0.; DEFAULT_BAND_FREQUENCIES.len()],
+            filters: Vec::new(),
+            states: Vec::new(),
+            channels: 0,
+            compensation_db: 0.,
+        };
+        equalizer.update_filters();
+        equalizer
+    }
+
+    /// 按设备的采样率计算滤波器，按声道数分配滤波器状态，在打开设备和准备新的设置时调用
+    pub fn set_format(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate > 0 && sample_rate != self.sample_rate {
+            self.sample_rate = sample_rate;
+            self.update_filters();
+        }
+        if channels != self.channels {
+            self.channels = channels;
+            self.states = vec![[0.; 2]; self.filters.len() * channels];
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.reset();
+    }
+
+    pub fn band_frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    pub fn band_gains(&self) -> &[f32] {
+        &self.gains
+    }
+
+    pub fn band_q(&self) -> f64 {
+        self.q
+    }
+
+    /// 替换所有频段，gains 按频段对应，缺少的频段为 0 dB，超出范围的值会被限制
+    pub fn set_bands(&mut self, frequencies: &[f32], q: f64, gains: &[f32]) {
+        self.frequencies = frequencies.to_vec();
+        self.q = q;
+        self.gains = (0..frequencies.len())
+            .map(|index| gains.get(index).copied().unwrap_or(0.).clamp(-MAX_BAND_GAIN, MAX_BAND_GAIN))
+            .collect();
+        // 频段数改变之后按已知的声道数重新分配滤波器状态，音频回调中不分配内存
+        self.states = vec![[0.; 2]; frequencies.len() * self.channels];
+        self.update_filters();
+    }
+
+    /// 设置频段增益（分贝），超出范围的值会被限制
+    pub fn set_band_gain(&mut self, index: usize, gain_db: f32) {
+        if let Some(gain) = self.gains.get_mut(index) {
+            *gain = gain_db.clamp(-MAX_BAND_GAIN, MAX_BAND_GAIN);
+            self.update_filters();
+        }
+    }
+
+    pub fn is_auto_gain(&self) -> bool {
+        self.auto_gain
+    }
+
+    pub fn set_auto_gain(&mut self, auto_gain: bool) {
+        self.auto_gain = auto_gain;
+    }
+
+    /// 当前应用的自动增益补偿（分贝），没有开启自动增益或者不需要补偿时为 0
+    pub fn compensation_db(&self) -> f32 {
+        if self.enabled && self.auto_gain {
+            self.compensation_db
+        } else {
+            0.
+        }
+    }
+
+    /// 是否需要处理，关闭或者所有频段都是 0 dB 时跳过
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.gains.iter().any(|gain| *gain != 0.)
+    }
+
+    /// 换成在锁外准备好的均衡器，返回原来的均衡器，由调用者在锁外释放
+    ///
+    /// 计算滤波器和自动增益补偿需要扫描整个频率响应，在音频回调使用的锁里面只交换数据。
+    /// 开关状态和频段数都没有变化时保留滤波器状态，调整增益时不会产生爆音
+    pub fn replace(&mut self, mut prepared: Equalizer) -> Equalizer {
+        if prepared.enabled == self.enabled && prepared.states.len() == self.states.len() {
+            std::mem::swap(&mut prepared.states, &mut self.states);
+        }
+        std::mem::replace(self, prepared)
+    }
+
+    /// 清除滤波器状态
+    pub fn reset(&mut self) {
+        self.states.iter_mut().for_each(|state| *state = [0.; 2]);
+    }
+
+    fn update_filters(&mut self) {
+        let sample_rate = self.sample_rate as f64;
+        self.filters = self.frequencies.iter().zip(self.gains.iter())
+            .map(|(frequency, gain)| Biquad::peaking(*frequency as f64, *gain as f64, self.q, sample_rate))
+            .collect();
+        let max_gain_db = self.max_gain_db();
+        self.compensation_db = if max_gain_db > 0. { -(max_gain_db + AUTO_GAIN_MARGIN_DB) } else { 0. };
+    }
+
+    /// 在对数分布的频率点和各频段中心频率上估计所有频段叠加后的最大增益（分贝）
+    fn max_gain_db(&self) -> f32 {
+        let nyquist = self.sample_rate as f64 / 2.;
+        let ratio = (nyquist / RESPONSE_MIN_FREQUENCY).ln();
+        let max_magnitude = (0..RESPONSE_POINTS)
+            .map(|index| RESPONSE_MIN_FREQUENCY * (ratio * index as f64 / (RESPONSE_POINTS - 1) as f64).exp())
+            .chain(self.frequencies.iter().map(|frequency| *frequency as f64))
+            .map(|frequency| {
+                let w = 2. * PI * frequency.min(nyquist * 0.999) / self.sample_rate as f64;
+                self.filters.iter().map(|filter| filter.magnitude(w)).product::<f64>()
+            })
+            .fold(0., f64::max);
+        (20. * max_magnitude.log10()) as f32
+    }
+
+    /// 处理一块音频数据
+    pub fn process(&mut self, block: &mut [AudioSample]) {
+        if !self.is_active() {
+            return;
+        }
+        let channels = match block.first() {
+            Some(audio_sample) => audio_sample.channels(),
+            None => return,
+        };
+        if channels != self.channels {
+            // 声道数只会在打开设备时改变，此时已经按新的声道数分配了滤波器状态
+            return;
+        }
+
+        let compensation = if self.auto_gain {
+            10f32.powf(self.compensation_db / 20.)
+        } else {
+            1.
+        };
+
+        for audio_sample in block.iter_mut() {
+            for (channel, sample) in audio_sample.as_mut_slice().iter_mut().enumerate() {
+                let mut value = *sample;
+                for (band, filter) in self.filters.iter().enumerate() {
+                    if self.gains[band] == 0. {
+                        continue;
+                    }
+                    // 转置直接 II 型
+                    let state = &mut self.states[band * channels + channel];
+                    let output = filter.b0 as f32 * value + state[0];
+                    state[0] = filter.b1 as f32 * value - filter.a1 as f32 * output + state[1];
+                    state[1] = filter.b2 as f32 * value - filter.a2 as f32 * output;
+                    value = output;
+                }
+                *sample = value * compensation;
+            }
+        }
+    }
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 满幅的立体声正弦波
+    fn sine(frequency: f32, frames: usize) -> Vec<AudioSample> {
+        (0..frames)
+            .map(|frame| {
+                let value = (2. * std::f32::consts::PI * frequency * frame as f32 / SAMPLE_RATE as f32).sin();
+                AudioSample::from_slice(&[value, value])
+            })
+            .collect()
+    }
+
+    fn peak(block: &[AudioSample]) -> f32 {
+        block.iter().flat_map(|audio_sample| audio_sample.as_slice().iter()).fold(0., |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn auto_gain_keeps_a_boosted_band_below_full_scale() {
+        let mut equalizer = Equalizer::new();
+        equalizer.set_format(SAMPLE_RATE, 2);
+        equalizer.set_enabled(true);
+        equalizer.set_auto_gain(true);
+        let index = equalizer.band_frequencies().iter().position(|frequency| *frequency == 1000.).unwrap();
+        equalizer.set_band_gain(index, 12.);
+        assert!(equalizer.compensation_db() <= -11.9);
+
+        for frequency in [1000., 800., 1250.] {
+            let mut block = sine(frequency, SAMPLE_RATE as usize / 2);
+            equalizer.reset();
+            equalizer.process(&mut block);
+            let peak = peak(&block);
+            assert!(peak <= 1., "{} Hz peaked at {}", frequency, peak);
+        }
+    }
+
+    #[test]
+    fn boosted_band_clips_without_auto_gain() {
+        let mut equalizer = Equalizer::new();
+        equalizer.set_format(SAMPLE_RATE, 2);
+        equalizer.set_enabled(true);
+        let index = equalizer.band_frequencies().iter().position(|frequency| *frequency == 1000.).unwrap();
+        equalizer.set_band_gain(index, 12.);
+        assert_eq!(equalizer.compensation_db(), 0.);
+
+        let mut block = sine(1000., SAMPLE_RATE as usize / 2);
+        equalizer.process(&mut block);
+        assert!(peak(&block) > 3.5);
+    }
+
+    #[test]
+    fn replace_keeps_the_filter_state_of_the_same_layout() {
+        let mut equalizer = Equalizer::new();
+        equalizer.set_format(SAMPLE_RATE, 2);
+        equalizer.set_enabled(true);
+        equalizer.set_band_gain(0, 6.);
+        let mut block = sine(100., 256);
+        equalizer.process(&mut block);
+
+        let mut prepared = equalizer.clone();
+        prepared.set_band_gain(0, 3.);
+        prepared.reset();
+        let states = equalizer.states.clone();
+        let previous = equalizer.replace(prepared);
+        assert_eq!(equalizer.band_gains()[0], 3.);
+        assert_eq!(previous.band_gains()[0], 6.);
+        assert_eq!(equalizer.states, states);
+    }
+
+    #[test]
+    fn ten_band_layout_uses_the_iso_octave_frequencies() {
+        let (frequencies, q) = graphic_layout(10).unwrap();
+        assert_eq!(frequencies, &[31.5, 63., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.]);
+        assert!((q - std::f64::consts::SQRT_2).abs() < 1e-9);
+        assert_eq!(graphic_layout(31).unwrap().0.len(), 31);
+        assert!(graphic_layout(12).is_none());
+    }
+
+    #[test]
+    fn set_bands_allocates_the_filter_state_up_front() {
+        let mut equalizer = Equalizer::new();
+        equalizer.set_format(SAMPLE_RATE, 2);
+        equalizer.set_enabled(true);
+        let (frequencies, q) = graphic_layout(10).unwrap();
+        equalizer.set_bands(frequencies, q, &[3.; 10]);
+        assert_eq!(equalizer.band_frequencies(), frequencies);
+        assert_eq!(equalizer.states.len(), 10 * 2);
+
+        // 处理时使用已经分配好的状态
+        let states = equalizer.states.as_ptr();
+        let mut block = sine(1000., 256);
+        equalizer.process(&mut block);
+        assert_eq!(equalizer.states.as_ptr(), states);
+    }
+
+    #[test]
+    fn missing_gains_are_zero_and_extra_gains_are_ignored() {
+        let mut equalizer = Equalizer::new();
+        let (frequencies, q) = graphic_layout(10).unwrap();
+        equalizer.set_bands(frequencies, q, &[6.; 4]);
+        assert_eq!(equalizer.band_gains(), &[6., 6., 6., 6., 0., 0., 0., 0., 0., 0.]);
+        equalizer.set_bands(frequencies, q, &[30.; 12]);
+        assert_eq!(equalizer.band_gains(), &[MAX_BAND_GAIN; 10]);
+    }
+
+    #[test]
+    fn block_with_other_channel_count_passes_through() {
+        let mut equalizer = Equalizer::new();
+        equalizer.set_format(SAMPLE_RATE, 2);
+        equalizer.set_enabled(true);
+        equalizer.set_band_gain(0, 12.);
+
+        let mut block: Vec<AudioSample> = (0..64).map(|_| AudioSample::from_slice(&[0.5])).collect();
+        equalizer.process(&mut block);
+        assert!(block.iter().all(|audio_sample| audio_sample.as_slice() == [0.5]));
+    }
+}
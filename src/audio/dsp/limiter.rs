@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+
+use super::super::sample::AudioSample;
+use super::super::volume;
+
+/// 预读时长（毫秒），输出会延迟这么长的时间
+pub const LOOKAHEAD_MS: u32 = 5;
+
+/// 增益恢复的时间常数（毫秒）
+const RELEASE_MS: f32 = 80.;
+
+/// 允许设置的最低上限（dBFS）
+pub const MIN_CEILING_DB: f32 = -24.;
+
+/// 默认的上限（dBFS）
+pub const DEFAULT_CEILING_DB: f32 = -1.;
+
+/// 总输出的预读限幅器，保证输出的样本不超过上限
+///
+/// 每一帧按所有声道的峰值计算需要的增益，取预读窗口内的最小值并保持，
+/// 再用同样长度的滑动平均平滑增益的下降，平滑之后的增益仍然不大于窗口内任何一帧需要的增益。
+/// 恢复时按时间常数缓慢回升，所有声道使用相同的增益，不改变声像。
+/// 延迟线在打开设备时分配，音频回调中不分配内存
+pub struct Limiter {
+    enabled: bool,
+    sample_rate: u32,
+    /// 上限（振幅比例）
+    ceiling: f32,
+    /// 预读窗口的帧数
+    lookahead: usize,
+    /// 延迟线，按 [位置 * 声道数 + 声道] 交错存放
+    delay: Vec<f32>,
+    channels: usize,
+    position: usize,
+    /// 窗口内需要的增益的单调队列（帧序号，增益），用于求滑动最小值
+    minimum: VecDeque<(u64, f32)>,
+    frame_index: u64,
+    /// 恢复之后的增益
+    release_gain: f32,
+    release_coefficient: f32,
+    /// 最近 lookahead 帧的保持增益，用于滑动平均
+    history: Vec<f32>,
+    history_sum: f64,
+}
+
+impl Limiter {
+    pub fn new() -> Limiter {
+        let mut limiter = Limiter {
+            enabled: false,
+            sample_rate: 48000,
+            ceiling: volume::db_gain_to_amplitude(DEFAULT_CEILING_DB),
+            lookahead: 0,
+            delay: Vec::new(),
+            channels: 0,
+            position: 0,
+            minimum: VecDeque::new(),
+            frame_index: 0,
+            release_gain: 1.,
+            release_coefficient: 0.,
+            history: Vec::new(),
+            history_sum: 0.,
+        };
+        limiter.update_length();
+        limiter
+    }
+
+    /// 按设备的采样率和声道数分配延迟线，在打开设备时调用
+    pub fn set_format(&mut self, sample_rate: u32, channels: usize) {
+        if sample_rate > 0 && (sample_rate != self.sample_rate || channels != self.channels) {
+            self.sample_rate = sample_rate;
+            self.update_length();
+            self.resize(channels);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 开关限幅器，开启时从静音的延迟线开始
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.reset();
+        }
+        self.enabled = enabled;
+    }
+
+    /// 上限（dBFS）
+    pub fn ceiling_db(&self) -> f32 {
+        volume::amplitude_to_db_gain(self.ceiling)
+    }
+
+    pub fn set_ceiling_db(&mut self, ceiling_db: f32) {
+        self.ceiling = volume::db_gain_to_amplitude(ceiling_db.clamp(MIN_CEILING_DB, 0.));
+    }
+
+    /// 开启时增加的延迟
+    pub fn latency_frames(&self) -> usize {
+        if self.enabled {
+            self.lookahead - 1
+        } else {
+            0
+        }
+    }
+
+    /// 清除延迟线和增益状态
+    pub fn reset(&mut self) {
+        self.delay.iter_mut().for_each(|sample| *sample = 0.);
+        self.position = 0;
+        self.minimum.clear();
+        self.frame_index = 0;
+        self.release_gain = 1.;
+        self.history.iter_mut().for_each(|gain| *gain = 1.);
+        self.history_sum = self.lookahead as f64;
+    }
+
+    /// 处理一块音频数据
+    pub fn process(&mut self, block: &mut [AudioSample]) {
+        if !self.enabled {
+            return;
+        }
+        let channels = match block.first() {
+            Some(audio_sample) => audio_sample.channels(),
+            None => return,
+        };
+        if channels != self.channels {
+            // 声道数只会在打开设备时改变，此时已经按新的声道数分配了延迟线
+            return;
+        }
+
+        for audio_sample in block.iter_mut() {
+            let samples = audio_sample.as_mut_slice();
+            let peak = samples.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+            let required = if peak > self.ceiling { self.ceiling / peak } else { 1. };
+
+            // 窗口内需要的最小增益
+            while self.minimum.back().map_or(false, |(_, gain)| *gain >= required) {
+                self.minimum.pop_back();
+            }
+            self.minimum.push_back((self.frame_index, required));
+            while self.minimum.front().map_or(false, |(index, _)| *index + self.lookahead as u64 <= self.frame_index) {
+                self.minimum.pop_front();
+            }
+            let held = self.minimum.front().map_or(1., |(_, gain)| *gain);
+
+            // 立即降低，缓慢恢复
+            self.release_gain = (self.release_gain + (1. - self.release_gain) * self.release_coefficient).min(held);
+
+            // 滑动平均
+            let slot = self.frame_index as usize % self.lookahead;
+            self.history_sum += self.release_gain as f64 - self.history[slot] as f64;
+            self.history[slot] = self.release_gain;
+            let gain = (self.history_sum / self.lookahead as f64) as f32;
+
+            // 写入当前帧，取出 lookahead - 1 帧之前的帧
+            let offset = self.position * channels;
+            let read_offset = (self.position + 1) % self.lookahead * channels;
+            for (channel, sample) in samples.iter_mut().enumerate() {
+                self.delay[offset + channel] = *sample;
+                let delayed = self.delay[read_offset + channel] * gain;
+                // 舍入误差不会让输出超过上限
+                *sample = delayed.clamp(-self.ceiling, self.ceiling);
+            }
+            self.position = (self.position + 1) % self.lookahead;
+            self.frame_index += 1;
+        }
+    }
+
+    fn update_length(&mut self) {
+        self.lookahead = ((LOOKAHEAD_MS as u64 * self.sample_rate as u64 / 1000) as usize).max(2);
+        let release_frames = RELEASE_MS * self.sample_rate as f32 / 1000.;
+        self.release_coefficient = 1. - (-1. / release_frames).exp();
+    }
+
+    /// 按声道数和预读长度重新分配延迟线
+    fn resize(&mut self, channels: usize) {
+        self.channels = channels;
+        self.delay = vec![0.; self.lookahead * channels];
+        self.history = vec![1.; self.lookahead];
+        self.minimum = VecDeque::with_capacity(self.lookahead + 1);
+        self.reset();
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(amplitude: f32, frames: usize) -> Vec<AudioSample> {
+        (0..frames)
+            .map(|frame| {
+                let value = amplitude * (2. * std::f32::consts::PI * 440. * frame as f32 / SAMPLE_RATE as f32).sin();
+                let mut audio_sample = AudioSample::silence(2);
+                audio_sample.as_mut_slice().iter_mut().for_each(|sample| *sample = value);
+                audio_sample
+            })
+            .collect()
+    }
+
+    fn peak(block: &[AudioSample]) -> f32 {
+        block.iter().flat_map(|audio_sample| audio_sample.as_slice().iter()).fold(0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn signal_over_the_ceiling_is_limited_to_the_ceiling() {
+        let mut limiter = Limiter::new();
+        limiter.set_format(SAMPLE_RATE, 2);
+        limiter.set_ceiling_db(-1.);
+        limiter.set_enabled(true);
+
+        let ceiling = volume::db_gain_to_amplitude(-1.);
+        let mut block = sine(volume::db_gain_to_amplitude(5.), SAMPLE_RATE as usize);
+        limiter.process(&mut block);
+        assert!(peak(&block) <= ceiling);
+        // 延迟之后的输出接近上限，不是简单地压得很低
+        assert!(peak(&block[block.len() / 2..]) > ceiling * 0.9);
+    }
+
+    #[test]
+    fn signal_below_the_ceiling_is_only_delayed() {
+        let mut limiter = Limiter::new();
+        limiter.set_format(SAMPLE_RATE, 2);
+        limiter.set_enabled(true);
+
+        let input = sine(0.5, 1024);
+        let mut block = input.clone();
+        limiter.process(&mut block);
+        let latency = limiter.latency_frames();
+        for (output, input) in block[latency..].iter().zip(input.iter()) {
+            assert!((output.as_slice()[0] - input.as_slice()[0]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn disabled_limiter_passes_the_block_through() {
+        let mut limiter = Limiter::new();
+        limiter.set_format(SAMPLE_RATE, 2);
+
+        let input = sine(2., 256);
+        let mut block = input.clone();
+        limiter.process(&mut block);
+        assert_eq!(peak(&block), peak(&input));
+        assert_eq!(limiter.latency_frames(), 0);
+    }
+}
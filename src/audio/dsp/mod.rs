@@ -0,0 +1,78 @@
+use super::sample::AudioSample;
+
+/// 用户提供的音频处理器
+///
+/// 在音频设备的输出回调中，对每个输出块调用一次，发生在音量调节和格式转换之前，每块最多 MAX_BLOCK_FRAMES 帧。
+/// 处理器运行在实时音频线程上，不能分配内存、加锁、进行 IO 或其他可能阻塞的操作，
+/// 否则会导致爆音或卡顿
+pub type DspProcessor = Box<dyn FnMut(&mut [AudioSample]) + Send>;
+
+/// 输出回调中的处理步骤
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DspStage {
+    /// 均衡器
+    Equalizer,
+    /// 按声道延迟
+    ChannelDelay,
+    /// 卷积
+    Convolution,
+    /// 用户提供的音频处理器
+    UserProcessor,
+    /// 总输出的限幅器，总是在音量之后最后处理，不能调整位置
+    Limiter,
+}
+
+impl DspStage {
+    fn from_u8(value: u8) -> DspStage {
+        match value {
+            1 => DspStage::ChannelDelay,
+            2 => DspStage::Convolution,
+            3 => DspStage::UserProcessor,
+            4 => DspStage::Limiter,
+            _ => DspStage::Equalizer,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            DspStage::Equalizer => 0,
+            DspStage::ChannelDelay => 1,
+            DspStage::Convolution => 2,
+            DspStage::UserProcessor => 3,
+            DspStage::Limiter => 4,
+        }
+    }
+}
+
+/// 音量之前可以调整顺序的步骤数量
+pub const REORDERABLE_STAGE_COUNT: usize = 4;
+
+/// 音量之前的处理顺序
+pub type ProcessingOrder = [DspStage; REORDERABLE_STAGE_COUNT];
+
+/// 默认的处理顺序：先在原始信号上均衡，再对齐音箱的延迟和卷积，用户处理器得到前面所有步骤的结果
+pub const DEFAULT_PROCESSING_ORDER: ProcessingOrder = [
+    DspStage::Equalizer,
+    DspStage::ChannelDelay,
+    DspStage::Convolution,
+    DspStage::UserProcessor,
+];
+
+/// 把处理顺序打包成一个整数，每个步骤占一个字节，用于在输出回调中不加锁地读取
+pub(crate) fn pack_processing_order(order: ProcessingOrder) -> u32 {
+    let mut bytes = [0u8; REORDERABLE_STAGE_COUNT];
+    for (byte, stage) in bytes.iter_mut().zip(order) {
+        *byte = stage.as_u8();
+    }
+    u32::from_le_bytes(bytes)
+}
+
+/// 还原 pack_processing_order 打包的处理顺序
+pub(crate) fn unpack_processing_order(packed: u32) -> ProcessingOrder {
+    u32::to_le_bytes(packed).map(DspStage::from_u8)
+}
+
+pub mod convolution;
+pub mod delay;
+pub mod equalizer;
+pub mod limiter;
@@ -0,0 +1,124 @@
+/// 增益包络：在指定的帧数内把增益线性变化到目标值
+#[derive(Debug, Clone, Copy)]
+pub struct Fade {
+    gain: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Fade {
+    pub fn new(gain: f32) -> Fade {
+        Fade {
+            gain,
+            target: gain,
+            step: 0.,
+        }
+    }
+
+    /// 在 frames 帧内变化到 target，frames 为 0 时立即变化
+    pub fn start(&mut self, target: f32, frames: usize) {
+        self.target = target;
+        if frames == 0 {
+            self.gain = target;
+            self.step = 0.;
+        } else {
+            self.step = (target - self.gain) / frames as f32;
+        }
+    }
+
+    /// 获取当前帧的增益并前进一帧
+    pub fn next_gain(&mut self) -> f32 {
+        let gain = self.gain;
+        if !self.is_finished() {
+            self.gain += self.step;
+            let reached = if self.step > 0. { self.gain >= self.target } else { self.gain <= self.target };
+            if reached || self.step == 0. {
+                self.gain = self.target;
+            }
+        }
+        gain
+    }
+
+    /// 当前增益
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// 是否已经到达目标值
+    pub fn is_finished(&self) -> bool {
+        self.gain == self.target
+    }
+
+    /// 是否已经完全静音并且不会再变化
+    pub fn is_silent(&self) -> bool {
+        self.is_finished() && self.gain == 0.
+    }
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self::new(1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_fade_holds_its_gain() {
+        let mut fade = Fade::new(0.5);
+        assert!(fade.is_finished());
+        assert!(!fade.is_silent());
+        for _ in 0..10 {
+            assert_eq!(fade.next_gain(), 0.5);
+        }
+    }
+
+    #[test]
+    fn fade_in_reaches_the_target_after_the_given_frames() {
+        let mut fade = Fade::new(0.);
+        fade.start(1., 4);
+        let gains: Vec<f32> = (0..6).map(|_| fade.next_gain()).collect();
+        assert_eq!(gains, vec![0., 0.25, 0.5, 0.75, 1., 1.]);
+        assert!(fade.is_finished());
+    }
+
+    #[test]
+    fn fade_out_stops_exactly_at_silence() {
+        let mut fade = Fade::new(1.);
+        fade.start(0., 3);
+        assert!(!fade.is_silent());
+        for _ in 0..3 {
+            fade.next_gain();
+        }
+        // 浮点误差不会让增益越过目标值
+        assert_eq!(fade.gain(), 0.);
+        assert!(fade.is_silent());
+        assert_eq!(fade.next_gain(), 0.);
+    }
+
+    #[test]
+    fn zero_frames_changes_the_gain_immediately() {
+        let mut fade = Fade::new(1.);
+        fade.start(0.2, 0);
+        assert!(fade.is_finished());
+        assert_eq!(fade.next_gain(), 0.2);
+    }
+
+    #[test]
+    fn restarting_continues_from_the_current_gain() {
+        let mut fade = Fade::new(0.);
+        fade.start(1., 10);
+        for _ in 0..5 {
+            fade.next_gain();
+        }
+        fade.start(0., 5);
+        assert!((fade.next_gain() - 0.5).abs() < 1e-6);
+        // 累积的浮点误差最多让淡出多一帧
+        for _ in 0..5 {
+            fade.next_gain();
+        }
+        assert!(fade.is_silent());
+    }
+}
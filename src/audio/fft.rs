@@ -0,0 +1,137 @@
+/// 原地基 2 快速傅里叶变换
+///
+/// re 和 im 分别为实部和虚部，长度必须相同且为 2 的幂
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n, "FFT length must be a power of two");
+
+    // 按位反转重排
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    // 蝶形运算
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f64::consts::PI / len as f64;
+        let half = len / 2;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (w_re, w_im) = (w_re as f32, w_im as f32);
+                let a = start + k;
+                let b = a + half;
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// 逆变换，结果已经除以长度
+pub fn ifft(re: &mut [f32], im: &mut [f32]) {
+    for value in im.iter_mut() {
+        *value = -*value;
+    }
+    fft(re, im);
+    let scale = 1.0 / re.len() as f32;
+    for (r, i) in re.iter_mut().zip(im.iter_mut()) {
+        *r *= scale;
+        *i = -*i * scale;
+    }
+}
+
+/// 预先计算了位反转顺序和旋转因子的基 2 快速傅里叶变换
+///
+/// 适合在实时线程上反复变换相同长度的数据，变换时不分配内存，也不计算三角函数
+pub struct FftPlan {
+    size: usize,
+    /// 每个下标按位反转之后的下标
+    reversed: Vec<usize>,
+    /// e^(-2πik/size) 的实部和虚部，k 从 0 到 size / 2
+    twiddle_re: Vec<f32>,
+    twiddle_im: Vec<f32>,
+}
+
+impl FftPlan {
+    pub fn new(size: usize) -> FftPlan {
+        assert!(size.is_power_of_two(), "FFT length must be a power of two");
+        let bits = size.trailing_zeros();
+        let reversed = (0..size)
+            .map(|index| if bits == 0 { 0 } else { index.reverse_bits() >> (usize::BITS - bits) })
+            .collect();
+        let (twiddle_re, twiddle_im) = (0..size / 2)
+            .map(|k| {
+                let (sin, cos) = (-2.0 * std::f64::consts::PI * k as f64 / size as f64).sin_cos();
+                (cos as f32, sin as f32)
+            })
+            .unzip();
+        FftPlan { size, reversed, twiddle_re, twiddle_im }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 原地正变换，re 和 im 的长度必须等于 size
+    pub fn forward(&self, re: &mut [f32], im: &mut [f32]) {
+        let n = self.size;
+        assert!(re.len() == n && im.len() == n, "FFT length does not match the plan");
+
+        for (i, &j) in self.reversed.iter().enumerate() {
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let half = len / 2;
+            let step = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..half {
+                    let w_re = self.twiddle_re[k * step];
+                    let w_im = self.twiddle_im[k * step];
+                    let a = start + k;
+                    let b = a + half;
+                    let t_re = re[b] * w_re - im[b] * w_im;
+                    let t_im = re[b] * w_im + im[b] * w_re;
+                    re[b] = re[a] - t_re;
+                    im[b] = im[a] - t_im;
+                    re[a] += t_re;
+                    im[a] += t_im;
+                }
+            }
+            len <<= 1;
+        }
+    }
+
+    /// 原地逆变换，结果已经除以长度
+    pub fn inverse(&self, re: &mut [f32], im: &mut [f32]) {
+        for value in im.iter_mut() {
+            *value = -*value;
+        }
+        self.forward(re, im);
+        let scale = 1.0 / self.size as f32;
+        for (r, i) in re.iter_mut().zip(im.iter_mut()) {
+            *r *= scale;
+            *i = -*i * scale;
+        }
+    }
+}
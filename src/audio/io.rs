@@ -0,0 +1,451 @@
+extern crate ffmpeg_next as ffmpeg;
+extern crate ffmpeg_sys_next as ffmpeg_c_api;
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ffmpeg::format;
+use ffmpeg::format::context::Input;
+use ffmpeg::Dictionary;
+use ffmpeg_c_api::{AVERROR, AVERROR_EOF, AVIOContext, AVIOInterruptCB};
+use libc::{c_char, c_int, c_void};
+
+/// 自定义读取器使用的缓冲区大小
+const IO_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 网络流断线后 FFmpeg 自动重连的最长等待时间（秒）
+const RECONNECT_DELAY_MAX: &str = "30";
+
+/// 网络流超过这个时间没有读到数据时认为正在缓冲
+const BUFFERING_THRESHOLD: Duration = Duration::from_millis(1000);
+
+/// 判断是否是网络地址，例如 http://example.com/stream
+pub fn is_network_url(uri: &str) -> bool {
+    ["http://", "https://", "icy://", "rtmp://", "rtsp://", "mms://", "mmsh://"]
+        .iter()
+        .any(|scheme| uri.len() >= scheme.len() && uri[..scheme.len()].eq_ignore_ascii_case(scheme))
+}
+
+/// 把用户设置的选项转换为 FFmpeg 的字典
+fn input_options(options: &HashMap<String, String>) -> Dictionary<'static> {
+    let mut dictionary = Dictionary::new();
+    for (key, value) in options {
+        dictionary.set(key, value);
+    }
+    dictionary
+}
+
+/// 网络流的读取状态，FFmpeg 在阻塞读取期间会反复调用中断回调检查它
+pub struct NetworkMonitor {
+    aborted: AtomicBool,
+    /// 开始等待数据的时间
+    read_start: Mutex<Instant>,
+    buffering: AtomicBool,
+    /// 开始缓冲时传入 true，恢复读取时传入 false
+    buffering_callback: Mutex<Option<Box<dyn Fn(bool) + Send>>>,
+}
+
+impl NetworkMonitor {
+    fn new() -> NetworkMonitor {
+        NetworkMonitor {
+            aborted: AtomicBool::new(false),
+            read_start: Mutex::new(Instant::now()),
+            buffering: AtomicBool::new(false),
+            buffering_callback: Mutex::new(None),
+        }
+    }
+
+    /// 中断正在进行和之后的读取，用于关闭阻塞中的网络流
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_buffering_callback(&self, callback: Option<Box<dyn Fn(bool) + Send>>) {
+        *self.buffering_callback.lock().unwrap() = callback;
+    }
+
+    /// 读到数据后调用，重新开始计时，正在缓冲时通知缓冲结束
+    pub fn mark_read(&self) {
+        *self.read_start.lock().unwrap() = Instant::now();
+        if self.buffering.swap(false, Ordering::Relaxed) {
+            self.notify(false);
+        }
+    }
+
+    pub fn is_buffering(&self) -> bool {
+        self.buffering.load(Ordering::Relaxed)
+    }
+
+    /// 中断回调，返回 true 时 FFmpeg 放弃当前的读取
+    fn check(&self) -> bool {
+        if self.aborted.load(Ordering::Relaxed) {
+            return true;
+        }
+        let waiting = self.read_start.lock().unwrap().elapsed();
+        if waiting >= BUFFERING_THRESHOLD && !self.buffering.swap(true, Ordering::Relaxed) {
+            self.notify(true);
+        }
+        false
+    }
+
+    fn notify(&self, buffering: bool) {
+        if let Some(callback) = self.buffering_callback.lock().unwrap().as_ref() {
+            callback(buffering);
+        }
+    }
+}
+
+unsafe extern "C" fn check_interrupt(opaque: *mut c_void) -> c_int {
+    let monitor = &*(opaque as *const NetworkMonitor);
+    monitor.check() as c_int
+}
+
+/// 超过截止时间后让 FFmpeg 放弃当前的操作
+unsafe extern "C" fn check_deadline(opaque: *mut c_void) -> c_int {
+    let deadline = &*(opaque as *const Instant);
+    (Instant::now() >= *deadline) as c_int
+}
+
+/// 可以定位的读取器，例如压缩包中的文件
+pub trait SeekableRead: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> SeekableRead for T {}
+
+enum MediaReader {
+    /// 不可定位的流
+    Stream(Box<dyn Read + Send>),
+    Seekable(Box<dyn SeekableRead>),
+}
+
+impl Read for MediaReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MediaReader::Stream(reader) => reader.read(buf),
+            MediaReader::Seekable(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// 传给 FFmpeg 读取回调的数据
+struct ReaderOpaque {
+    reader: MediaReader,
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let opaque = &mut *(opaque as *mut ReaderOpaque);
+    let buffer = std::slice::from_raw_parts_mut(buf, buf_size.max(0) as usize);
+    loop {
+        match opaque.reader.read(buffer) {
+            Ok(0) => return AVERROR_EOF,
+            Ok(size) => return size as c_int,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                log::error!("failed to read from the media stream: {}", err);
+                return AVERROR(libc::EIO);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let opaque = &mut *(opaque as *mut ReaderOpaque);
+    let reader = match &mut opaque.reader {
+        MediaReader::Seekable(reader) => reader,
+        MediaReader::Stream(_) => return AVERROR(libc::ENOSYS) as i64,
+    };
+    let whence = whence & !(ffmpeg_c_api::AVSEEK_FORCE as c_int);
+    let result = if whence == ffmpeg_c_api::AVSEEK_SIZE as c_int {
+        // 返回总大小，不改变当前位置
+        (|| -> io::Result<u64> {
+            let current = reader.stream_position()?;
+            let size = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(current))?;
+            Ok(size)
+        })()
+    } else {
+        let position = match whence {
+            libc::SEEK_SET if offset >= 0 => SeekFrom::Start(offset as u64),
+            libc::SEEK_CUR => SeekFrom::Current(offset),
+            libc::SEEK_END => SeekFrom::End(offset),
+            _ => return AVERROR(libc::EINVAL) as i64,
+        };
+        reader.seek(position)
+    };
+    match result {
+        Ok(position) => position as i64,
+        Err(err) => {
+            log::warn!("failed to seek in the media stream: {}", err);
+            AVERROR(libc::EIO) as i64
+        }
+    }
+}
+
+/// 自定义读取器的 AVIOContext，释放时一起释放缓冲区和读取器
+struct CustomIo {
+    avio: *mut AVIOContext,
+    opaque: *mut ReaderOpaque,
+    seekable: bool,
+}
+
+impl Drop for CustomIo {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.avio.is_null() {
+                // 缓冲区可能已经被 FFmpeg 重新分配，需要释放 AVIOContext 当前持有的缓冲区
+                ffmpeg_c_api::av_freep(&mut (*self.avio).buffer as *mut *mut u8 as *mut c_void);
+                ffmpeg_c_api::avio_context_free(&mut self.avio);
+            }
+            drop(Box::from_raw(self.opaque));
+        }
+    }
+}
+
+/// 已打开的媒体输入，可以来自文件或者自定义读取器
+pub struct MediaInput {
+    input: ManuallyDrop<Input>,
+    io: Option<CustomIo>,
+    /// 网络流的中断回调使用的状态，需要在输入关闭之后释放
+    network: Option<Arc<NetworkMonitor>>,
+    /// 限时打开时中断回调使用的截止时间，同样需要在输入关闭之后释放
+    deadline: Option<Box<Instant>>,
+}
+
+// 读取器本身是 Send 的，AVIOContext 只会在持有 MediaInput 的线程上使用
+unsafe impl Send for MediaInput {}
+
+impl MediaInput {
+    /// 打开文件，options 为传给 FFmpeg 的协议和解封装器选项
+    pub fn open_file<P: AsRef<Path>>(path: &P, options: &HashMap<String, String>) -> Result<MediaInput, ffmpeg::Error> {
+        let input = if options.is_empty() {
+            format::input(path)?
+        } else {
+            format::input_with_dictionary(path, input_options(options))?
+        };
+        Ok(MediaInput {
+            input: ManuallyDrop::new(input),
+            io: None,
+            network: None,
+            deadline: None,
+        })
+    }
+
+    /// 打开文件或者网络地址，超过 deadline 时中断打开和之后的所有读取
+    ///
+    /// 用于扫描目录等场景，一个损坏的文件或者没有响应的服务器不会让调用者一直等待
+    pub fn open_with_deadline<P: AsRef<Path>>(uri: &P, deadline: Instant) -> Result<MediaInput, ffmpeg::Error> {
+        let uri = uri.as_ref().to_str()
+            .and_then(|uri| CString::new(uri).ok())
+            .ok_or_else(|| ffmpeg::Error::from(AVERROR(libc::EINVAL)))?;
+        let deadline = Box::new(deadline);
+
+        unsafe {
+            let mut format_ctx = ffmpeg_c_api::avformat_alloc_context();
+            if format_ctx.is_null() {
+                return Err(ffmpeg::Error::from(AVERROR(libc::ENOMEM)));
+            }
+            (*format_ctx).interrupt_callback = AVIOInterruptCB {
+                callback: Some(check_deadline),
+                opaque: deadline.as_ref() as *const Instant as *mut c_void,
+            };
+
+            // 打开失败时 FFmpeg 会释放 format_ctx
+            let result = ffmpeg_c_api::avformat_open_input(&mut format_ctx, uri.as_ptr(), std::ptr::null_mut(), std::ptr::null_mut());
+            if result != 0 {
+                return Err(ffmpeg::Error::from(result));
+            }
+            match ffmpeg_c_api::avformat_find_stream_info(format_ctx, std::ptr::null_mut()) {
+                result if result >= 0 => Ok(MediaInput {
+                    input: ManuallyDrop::new(Input::wrap(format_ctx)),
+                    io: None,
+                    network: None,
+                    deadline: Some(deadline),
+                }),
+                err => {
+                    ffmpeg_c_api::avformat_close_input(&mut format_ctx);
+                    Err(ffmpeg::Error::from(err))
+                }
+            }
+        }
+    }
+
+    /// 打开网络流，启用 ICY 元数据和断线自动重连
+    ///
+    /// user_options 中的选项会覆盖默认的选项，例如 user_agent、timeout 和 headers
+    pub fn open_network(url: &str, user_options: &HashMap<String, String>) -> Result<MediaInput, ffmpeg::Error> {
+        let url = CString::new(url).map_err(|_| ffmpeg::Error::from(AVERROR(libc::EINVAL)))?;
+        let monitor = Arc::new(NetworkMonitor::new());
+
+        let mut options = Dictionary::new();
+        options.set("icy", "1");
+        options.set("reconnect", "1");
+        options.set("reconnect_streamed", "1");
+        options.set("reconnect_on_network_error", "1");
+        options.set("reconnect_delay_max", RECONNECT_DELAY_MAX);
+        for (key, value) in user_options {
+            options.set(key, value);
+        }
+
+        unsafe {
+            let mut format_ctx = ffmpeg_c_api::avformat_alloc_context();
+            if format_ctx.is_null() {
+                return Err(ffmpeg::Error::from(AVERROR(libc::ENOMEM)));
+            }
+            (*format_ctx).interrupt_callback = AVIOInterruptCB {
+                callback: Some(check_interrupt),
+                opaque: Arc::as_ptr(&monitor) as *mut c_void,
+            };
+
+            // 打开失败时 FFmpeg 会释放 format_ctx，没有用到的选项由 Dictionary 释放
+            let mut options = options.disown();
+            let result = ffmpeg_c_api::avformat_open_input(&mut format_ctx, url.as_ptr(), std::ptr::null_mut(), &mut options);
+            drop(Dictionary::own(options));
+            if result != 0 {
+                return Err(ffmpeg::Error::from(result));
+            }
+            match ffmpeg_c_api::avformat_find_stream_info(format_ctx, std::ptr::null_mut()) {
+                result if result >= 0 => Ok(MediaInput {
+                    input: ManuallyDrop::new(Input::wrap(format_ctx)),
+                    io: None,
+                    network: Some(monitor),
+                    deadline: None,
+                }),
+                err => {
+                    ffmpeg_c_api::avformat_close_input(&mut format_ctx);
+                    Err(ffmpeg::Error::from(err))
+                }
+            }
+        }
+    }
+
+    /// 从读取器打开，读取器被视为不可定位的流
+    pub fn open_reader(reader: Box<dyn Read + Send>) -> Result<MediaInput, ffmpeg::Error> {
+        MediaInput::open_custom(MediaReader::Stream(reader))
+    }
+
+    /// 从可以定位的读取器打开
+    pub fn open_seekable_reader(reader: Box<dyn SeekableRead>) -> Result<MediaInput, ffmpeg::Error> {
+        MediaInput::open_custom(MediaReader::Seekable(reader))
+    }
+
+    fn open_custom(reader: MediaReader) -> Result<MediaInput, ffmpeg::Error> {
+        let seekable = matches!(reader, MediaReader::Seekable(_));
+        unsafe {
+            let buffer = ffmpeg_c_api::av_malloc(IO_BUFFER_SIZE) as *mut u8;
+            if buffer.is_null() {
+                return Err(ffmpeg::Error::from(AVERROR(libc::ENOMEM)));
+            }
+
+            let opaque = Box::into_raw(Box::new(ReaderOpaque { reader }));
+            let avio = ffmpeg_c_api::avio_alloc_context(
+                buffer,
+                IO_BUFFER_SIZE as c_int,
+                0,
+                opaque as *mut c_void,
+                Some(read_packet),
+                None,
+                if seekable { Some(seek) } else { None },
+            );
+            if avio.is_null() {
+                ffmpeg_c_api::av_free(buffer as *mut c_void);
+                drop(Box::from_raw(opaque));
+                return Err(ffmpeg::Error::from(AVERROR(libc::ENOMEM)));
+            }
+            (*avio).seekable = if seekable { ffmpeg_c_api::AVIO_SEEKABLE_NORMAL as c_int } else { 0 };
+            // 之后出错时由 CustomIo 负责释放
+            let io = CustomIo { avio, opaque, seekable };
+
+            let mut format_ctx = ffmpeg_c_api::avformat_alloc_context();
+            if format_ctx.is_null() {
+                return Err(ffmpeg::Error::from(AVERROR(libc::ENOMEM)));
+            }
+            (*format_ctx).pb = avio;
+            (*format_ctx).flags |= ffmpeg_c_api::AVFMT_FLAG_CUSTOM_IO as c_int;
+
+            // 打开失败时 FFmpeg 会释放 format_ctx
+            match ffmpeg_c_api::avformat_open_input(&mut format_ctx, std::ptr::null(), std::ptr::null_mut(), std::ptr::null_mut()) {
+                0 => {},
+                err => return Err(ffmpeg::Error::from(err)),
+            }
+            match ffmpeg_c_api::avformat_find_stream_info(format_ctx, std::ptr::null_mut()) {
+                result if result >= 0 => Ok(MediaInput {
+                    input: ManuallyDrop::new(Input::wrap(format_ctx)),
+                    io: Some(io),
+                    network: None,
+                    deadline: None,
+                }),
+                err => {
+                    ffmpeg_c_api::avformat_close_input(&mut format_ctx);
+                    Err(ffmpeg::Error::from(err))
+                }
+            }
+        }
+    }
+
+    /// 网络流的读取状态，不是网络流时为 None
+    pub fn network_monitor(&self) -> Option<Arc<NetworkMonitor>> {
+        self.network.clone()
+    }
+
+    /// 网络流中最近一次收到的 ICY 元数据，例如 StreamTitle='Artist - Title';
+    pub fn icy_metadata_packet(&self) -> Option<String> {
+        unsafe {
+            let pb = (*self.input.as_ptr()).pb;
+            if pb.is_null() {
+                return None;
+            }
+            let mut value: *mut u8 = std::ptr::null_mut();
+            let result = ffmpeg_c_api::av_opt_get(
+                pb as *mut c_void,
+                b"icy_metadata_packet\0".as_ptr() as *const c_char,
+                ffmpeg_c_api::AV_OPT_SEARCH_CHILDREN as c_int,
+                &mut value);
+            if result < 0 || value.is_null() {
+                return None;
+            }
+            let packet = CStr::from_ptr(value as *const c_char).to_string_lossy().into_owned();
+            ffmpeg_c_api::av_free(value as *mut c_void);
+            Some(packet)
+        }
+    }
+
+    /// 是否可以定位
+    pub fn is_seekable(&self) -> bool {
+        if let Some(io) = self.io.as_ref() {
+            return io.seekable;
+        }
+        unsafe {
+            let pb = (*self.input.as_ptr()).pb;
+            !pb.is_null() && (*pb).seekable & ffmpeg_c_api::AVIO_SEEKABLE_NORMAL as c_int != 0
+        }
+    }
+}
+
+impl Deref for MediaInput {
+    type Target = Input;
+
+    fn deref(&self) -> &Input {
+        &self.input
+    }
+}
+
+impl DerefMut for MediaInput {
+    fn deref_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+}
+
+impl Drop for MediaInput {
+    fn drop(&mut self) {
+        // 先关闭输入，再释放它使用的 AVIOContext
+        unsafe {
+            ManuallyDrop::drop(&mut self.input);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::f64::consts::PI;
+
+use super::sample::AudioSample;
+
+/// ReplayGain 2.0 的参考响度（LUFS）
+pub const REPLAY_GAIN_REFERENCE_LUFS: f64 = -18.;
+
+/// 测量块长度 400 毫秒，每 100 毫秒计算一次，相邻的块重叠 75%
+const SUBBLOCKS_PER_BLOCK: usize = 4;
+/// 绝对门限（LUFS）
+const ABSOLUTE_GATE_LUFS: f64 = -70.;
+/// 相对门限（LU），低于第一次门限后平均响度这么多的块不参与计算
+const RELATIVE_GATE_LU: f64 = -10.;
+
+/// 二阶 IIR 滤波器（直接 II 型转置）
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// ITU-R BS.1770 的 K 计权滤波器：高频搁架滤波器和 RLB 高通滤波器，系数按采样率计算
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let k = (PI * 1681.974450955533 / rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1. + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2. * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        z: [0.; 2],
+    };
+
+    let k = (PI * 38.13547087602444 / rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1. + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1., -2., 1.],
+        a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        z: [0.; 2],
+    };
+
+    [shelf, high_pass]
+}
+
+/// 声道的权重，按 FFmpeg 的默认声道顺序，5.1 及以上时 LFE 不计入，环绕声道加权 1.41
+fn channel_weight(channels: usize, channel: usize) -> f64 {
+    match (channels >= 5, channel) {
+        (true, 3) => 0.,
+        (true, 4) | (true, 5) => 1.41,
+        _ => 1.,
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10. * energy.log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.)
+}
+
+/// 按 EBU R128 测量整段音频的综合响度和采样峰值，用于离线分析
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<[Biquad; 2]>,
+    /// 每个子块（100 毫秒）的帧数
+    subblock_frames: usize,
+    /// 当前子块已经累积的帧数和加权能量
+    subblock_position: usize,
+    subblock_energy: f64,
+    /// 最近几个子块的平均能量
+    recent_subblocks: Vec<f64>,
+    /// 每个测量块的平均能量
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> LoudnessMeter {
+        LoudnessMeter {
+            channels,
+            filters: vec![k_weighting(sample_rate); channels],
+            subblock_frames: (sample_rate as usize / 10).max(1),
+            subblock_position: 0,
+            subblock_energy: 0.,
+            recent_subblocks: Vec::with_capacity(SUBBLOCKS_PER_BLOCK),
+            blocks: Vec::new(),
+            peak: 0.,
+        }
+    }
+
+    /// 输入一段音频，声道数需要和创建时相同
+    pub fn push(&mut self, samples: &[AudioSample]) {
+        for sample in samples {
+            let mut energy = 0.;
+            for (channel, value) in sample.as_slice().iter().take(self.channels).enumerate() {
+                self.peak = self.peak.max(value.abs());
+                let weight = channel_weight(self.channels, channel);
+                if weight == 0. {
+                    continue;
+                }
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let filtered = high_pass.process(shelf.process(*value as f64));
+                energy += weight * filtered * filtered;
+            }
+            self.subblock_energy += energy;
+            self.subblock_position += 1;
+            if self.subblock_position == self.subblock_frames {
+                self.finish_subblock();
+            }
+        }
+    }
+
+    fn finish_subblock(&mut self) {
+        if self.recent_subblocks.len() == SUBBLOCKS_PER_BLOCK {
+            self.recent_subblocks.remove(0);
+        }
+        self.recent_subblocks.push(self.subblock_energy / self.subblock_frames as f64);
+        self.subblock_energy = 0.;
+        self.subblock_position = 0;
+        if self.recent_subblocks.len() == SUBBLOCKS_PER_BLOCK {
+            self.blocks.push(self.recent_subblocks.iter().sum::<f64>() / SUBBLOCKS_PER_BLOCK as f64);
+        }
+    }
+
+    /// 综合响度（LUFS），音频太短或者全部低于绝对门限时返回 None
+    pub fn integrated_lufs(&self) -> Option<f64> {
+        let absolute_gate = lufs_to_energy(ABSOLUTE_GATE_LUFS);
+        let gated_mean = |gate: f64| {
+            let (sum, count) = self.blocks.iter()
+                .filter(|energy| **energy > gate)
+                .fold((0., 0usize), |(sum, count), energy| (sum + energy, count + 1));
+            if count > 0 {
+                Some(sum / count as f64)
+            } else {
+                None
+            }
+        };
+        let relative_gate = lufs_to_energy(energy_to_lufs(gated_mean(absolute_gate)?) + RELATIVE_GATE_LU);
+        gated_mean(absolute_gate.max(relative_gate)).map(energy_to_lufs)
+    }
+
+    /// 采样峰值（振幅比例）
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// 达到 ReplayGain 2.0 参考响度需要的增益（分贝）
+    pub fn replay_gain(&self) -> Option<f32> {
+        self.integrated_lufs().map(|lufs| (REPLAY_GAIN_REFERENCE_LUFS - lufs) as f32)
+    }
+}
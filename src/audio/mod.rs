@@ -1,4 +1,15 @@
 pub mod device;
 pub mod source;
+pub mod io;
 pub mod volume;
-pub mod sample;
\ No newline at end of file
+pub mod sample;
+pub mod dsp;
+pub mod fft;
+pub mod analysis;
+pub mod fade;
+pub mod tempo;
+pub mod replaygain;
+pub mod loudness;
+pub mod capture;
+#[cfg(test)]
+pub(crate) mod test_media;
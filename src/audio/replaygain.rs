@@ -0,0 +1,122 @@
+use crate::info::media::ReplayGain;
+
+use super::volume;
+
+/// R128 标签的参考响度是 -23 LUFS，ReplayGain 的参考响度是 -18 LUFS
+const R128_TO_REPLAY_GAIN_DB: f32 = 5.;
+
+/// 应用 ReplayGain 的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    /// 不调整
+    Off,
+    /// 使用曲目增益
+    Track,
+    /// 使用专辑增益，没有专辑增益或者在播放队列的专辑边界上时使用曲目增益
+    Album,
+}
+
+impl Default for ReplayGainMode {
+    fn default() -> Self {
+        ReplayGainMode::Off
+    }
+}
+
+/// 解析 REPLAYGAIN_*_GAIN 标签，例如 "-6.50 dB"
+pub fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value.strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value);
+    value.trim().parse::<f32>().ok().filter(|gain| gain.is_finite())
+}
+
+/// 解析 REPLAYGAIN_*_PEAK 标签，峰值是振幅比例，可能大于 1
+pub fn parse_peak(value: &str) -> Option<f32> {
+    value.trim().parse::<f32>().ok().filter(|peak| peak.is_finite() && *peak > 0.)
+}
+
+/// 解析 R128_*_GAIN 标签（Opus），值是以 1/256 dB 为单位的整数，换算成 ReplayGain 的增益
+pub fn parse_r128_gain(value: &str) -> Option<f32> {
+    let value = value.trim().parse::<i16>().ok()?;
+    Some(value as f32 / 256. + R128_TO_REPLAY_GAIN_DB)
+}
+
+/// 应用 ReplayGain 的设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// 预增益（分贝），加在标签中的增益上
+    pub preamp_db: f32,
+    /// 文件中没有可用的增益标签时使用的增益（分贝），不加预增益
+    pub fallback_db: f32,
+    /// 是否根据峰值标签限制增益，使峰值乘以增益后不超过 1
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        ReplayGainSettings {
+            mode: ReplayGainMode::default(),
+            preamp_db: 0.,
+            fallback_db: 0.,
+            prevent_clipping: true,
+        }
+    }
+}
+
+/// 实际应用的增益来自哪里
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GainSource {
+    /// 没有开启 ReplayGain
+    Off,
+    /// 曲目增益
+    Track,
+    /// 专辑增益
+    Album,
+    /// 没有增益标签，使用设置中的默认增益
+    Fallback,
+}
+
+/// 实际应用的增益
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectiveGain {
+    pub source: GainSource,
+    /// 包括预增益和峰值限制的增益（分贝）
+    pub gain_db: f32,
+    /// 和 gain_db 相同的振幅比例
+    pub amplitude: f32,
+    /// 用于防止削波的峰值
+    pub peak: Option<f32>,
+}
+
+impl EffectiveGain {
+    fn new(source: GainSource, gain_db: f32, peak: Option<f32>) -> Self {
+        EffectiveGain { source, gain_db, amplitude: volume::db_gain_to_amplitude(gain_db), peak }
+    }
+}
+
+impl ReplayGainSettings {
+    /// 计算应用的增益
+    ///
+    /// 专辑模式下 use_album 为 false 时（例如专辑边界上的曲目）使用曲目增益，没有专辑增益时也使用曲目增益
+    pub fn effective_gain(&self, replay_gain: &ReplayGain, use_album: bool) -> EffectiveGain {
+        let (source, gain, peak) = match self.mode {
+            ReplayGainMode::Off => return EffectiveGain::new(GainSource::Off, 0., None),
+            ReplayGainMode::Album if use_album && replay_gain.album_gain.is_some() => {
+                (GainSource::Album, replay_gain.album_gain, replay_gain.album_peak.or(replay_gain.track_peak))
+            },
+            _ => (GainSource::Track, replay_gain.track_gain, replay_gain.track_peak),
+        };
+        let gain = match gain {
+            Some(gain) => gain + self.preamp_db,
+            None => return EffectiveGain::new(GainSource::Fallback, self.fallback_db, None),
+        };
+        let gain = match peak.filter(|_| self.prevent_clipping) {
+            Some(peak) => gain.min(-volume::amplitude_to_db_gain(peak)),
+            None => gain,
+        };
+        EffectiveGain::new(source, gain, peak)
+    }
+}
@@ -7,6 +7,9 @@ pub struct AudioSample {
 }
 
 impl AudioSample {
+    /// 支持的最大声道数
+    pub const MAX_CHANNELS: usize = 8;
+
     /// 从切片生成音频样本
     pub fn from_slice(slice: &[f32]) -> Self {
         let mut audio_sample = Self::default();
@@ -15,6 +18,13 @@ impl AudioSample {
         audio_sample
     }
 
+    /// 生成指定声道数的静音样本
+    pub fn silence(channels: usize) -> Self {
+        let mut audio_sample = Self::default();
+        audio_sample.channels = channels.min(audio_sample.data.len()) as u8;
+        audio_sample
+    }
+
     /// 将音频样本写入切片
     pub fn write_slice(&self, slice: &mut [f32]) {
         slice.copy_from_slice(&self.data[..slice.len()]);
@@ -45,6 +55,17 @@ impl AudioSample {
         self.channels as usize
     }
 
+    /// 以切片形式获取各声道的样本，长度等于声道数
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data[..self.channels()]
+    }
+
+    /// 以可变切片形式获取各声道的样本，长度等于声道数
+    pub fn as_mut_slice(&mut self) -> &mut [f32] {
+        let channels = self.channels();
+        &mut self.data[..channels]
+    }
+
     pub fn get_sample(&self, channel: usize) -> f32 {
         self.data[channel]
     }
@@ -80,4 +101,37 @@ impl AudioSample {
     pub fn ch8(&self) -> f32 {
         self.data[7]
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_matches_numbered_accessors() {
+        let mut audio_sample = AudioSample::from_slice(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        let accessors = [
+            audio_sample.ch1(),
+            audio_sample.ch2(),
+            audio_sample.ch3(),
+            audio_sample.ch4(),
+            audio_sample.ch5(),
+            audio_sample.ch6(),
+        ];
+        assert_eq!(audio_sample.as_slice().len(), 6);
+        for (sample, accessor) in audio_sample.as_slice().iter().zip(accessors) {
+            assert_eq!(*sample, accessor);
+        }
+
+        for sample in audio_sample.as_mut_slice() {
+            *sample *= 2.;
+        }
+        assert_eq!(audio_sample.ch1(), 0.2);
+        assert_eq!(audio_sample.ch6(), 1.2);
+    }
+
+    #[test]
+    fn silence_has_the_requested_channels() {
+        assert_eq!(AudioSample::silence(2).as_slice(), &[0., 0.]);
+        assert_eq!(AudioSample::silence(12).as_slice().len(), AudioSample::MAX_CHANNELS);
+    }
+}
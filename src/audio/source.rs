@@ -2,22 +2,33 @@ extern crate ffmpeg_next as ffmpeg;
 extern crate ffmpeg_sys_next as ffmpeg_c_api;
 
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::Read;
 use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Mutex, Arc};
-use std::sync::mpsc::{self, channel};
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Condvar, Mutex, Arc};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use ffmpeg::{codec, decoder, frame, format, media};
 use ffmpeg::software::resampling::context::Context as SwrContext;
 use ffmpeg::{rescale, Rescale};
 use ringbuf::{Producer, Consumer};
-use snafu::{Snafu, ResultExt, OptionExt};
+use snafu::{Snafu, ResultExt, OptionExt, ensure};
 
-use crate::info::media::MediaInfo;
-use crate::metadata;
+use crate::info::media::{next_cover_generation, AudioStreamInfo, MediaInfo, RawTags, StreamDisposition, TechnicalInfo};
+use crate::info::status::DebugStats;
+use crate::metadata::{MetadataExtractor, MetadataSource};
+use crate::metadata::ffmpeg::{FFmpegMetadataExtractor, attached_picture, tag_from_input_context};
+use crate::metadata::icy;
+use crate::player::event::{EventBus, PlayerEvent};
 
 use super::device::{DeviceSampleFormat, AudioDevice};
+use super::io::{self, MediaInput, SeekableRead};
 use super::sample::AudioSample;
+use super::tempo::TempoFilter;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -50,28 +61,165 @@ pub enum Error {
         source: Box<dyn std::error::Error + Send + Sync>
     },
 
-    #[snafu(display("{}", message))]
-    SendSeek {
+    #[snafu(display("failed to decode: {}", message))]
+    Decode {
         message: String,
-        #[snafu(source(from(std::sync::mpsc::SendError<i64>, Box::new)))]
+        #[snafu(source(from(ffmpeg::Error, Box::new)))]
         source: Box<dyn std::error::Error + Send + Sync>
     },
+
+    /// 文件可以打开，但是没有可以播放的音频流，例如没有声音的视频
+    #[snafu(display("the file does not contain an audio stream"))]
+    NoAudioStream,
+}
+
+/// 默认的定位防抖时间（毫秒）
+pub const DEFAULT_SEEK_DEBOUNCE_MS: u32 = 50;
+
+/// 播放到结尾后解码线程的行为
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndBehavior {
+    /// 回到开头，用户再次播放时从头开始
+    RewindAndWait,
+    /// 停留在结尾的位置，需要定位之后才能继续播放
+    HoldPosition,
+    /// 结束解码线程，释放输出缓冲区给接着播放的下一个播放源
+    Handover,
+}
+
+impl Default for EndBehavior {
+    fn default() -> Self {
+        EndBehavior::RewindAndWait
+    }
+}
+
+impl EndBehavior {
+    fn from_u8(value: u8) -> EndBehavior {
+        match value {
+            1 => EndBehavior::HoldPosition,
+            2 => EndBehavior::Handover,
+            _ => EndBehavior::RewindAndWait,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            EndBehavior::RewindAndWait => 0,
+            EndBehavior::HoldPosition => 1,
+            EndBehavior::Handover => 2,
+        }
+    }
+}
+
+/// 打开文件的阶段，用于在打开较慢的网络文件时向界面报告进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenPhase {
+    /// 打开文件或者连接网络，探测容器格式
+    Probing,
+    /// 读取标签和封面
+    ReadingMetadata,
+    /// 创建解码器和重采样器
+    CreatingDecoder,
+    /// 解码线程已经启动，开始播放后填充输出缓冲区
+    Prebuffering,
+    /// 输出缓冲区中已经有可以播放的数据
+    Ready,
+}
+
+impl OpenPhase {
+    /// FFI 使用的数值，和声明的顺序相同
+    pub fn as_i32(self) -> i32 {
+        match self {
+            OpenPhase::Probing => 0,
+            OpenPhase::ReadingMetadata => 1,
+            OpenPhase::CreatingDecoder => 2,
+            OpenPhase::Prebuffering => 3,
+            OpenPhase::Ready => 4,
+        }
+    }
+}
+
+/// 打开进度回调，Ready 之前的阶段在打开文件的线程中按顺序调用，Ready 在解码线程中调用
+pub type OpenProgress = Arc<dyn Fn(OpenPhase) + Send + Sync>;
+
+/// 遇到无法解码的数据包（文件损坏、网络流数据错误）时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorResilience {
+    /// 丢弃这个数据包，继续解码后面的数据
+    Skip,
+    /// 按数据包的时长写入静音，保持后面的音频在时间上不偏移
+    Conceal,
+    /// 停止播放并发出 DecodeError 事件
+    Abort,
+}
+
+impl Default for ErrorResilience {
+    fn default() -> Self {
+        ErrorResilience::Skip
+    }
+}
+
+impl ErrorResilience {
+    fn from_u8(value: u8) -> ErrorResilience {
+        match value {
+            1 => ErrorResilience::Conceal,
+            2 => ErrorResilience::Abort,
+            _ => ErrorResilience::Skip,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            ErrorResilience::Skip => 0,
+            ErrorResilience::Conceal => 1,
+            ErrorResilience::Abort => 2,
+        }
+    }
 }
 
 pub trait AudioSource {
     fn close(&mut self) -> Result<(), Error>;
+    fn detach(&mut self) -> Result<(), Error>;
     fn streaming(&self) -> Result<(), Error>;
+    fn set_prebuffer_frames(&self, frames: Option<usize>);
     fn pause(&self) -> Result<(), Error>;
     fn seek(&self, time: i64) -> Result<(), Error>;
     fn clear_buffer(&self);
     fn get_duration(&self) -> i64;
     fn get_bitrate(&self) -> i64;
+    fn get_current_bitrate(&self) -> i64;
     fn get_current_time(&self) -> i64;
+    fn get_current_time_us(&self, buffered_frames: usize, sample_rate: u32) -> i64;
     fn set_buffer_chunk_size(&mut self, size: usize);
+    fn get_buffer_chunk_size(&self) -> usize;
     fn is_end(&self) -> bool;
+    fn get_debug_stats(&self) -> DebugStats;
+    fn end_signal(&self) -> Arc<EndSignal>;
+    fn interrupt_wait(&self);
     fn is_streaming(&self) -> bool;
     fn set_dynamic_device_buffer_size(&self, size: usize);
-    fn get_media_info(&self) -> &MediaInfo;
+    fn get_dynamic_device_buffer_size(&self) -> usize;
+    fn get_media_info(&self) -> Arc<MediaInfo>;
+    fn is_seekable(&self) -> bool;
+    fn set_speed(&self, speed: f32);
+    fn get_speed(&self) -> f32;
+    fn get_active_processing(&self) -> Vec<&'static str>;
+    fn set_gain(&self, amplitude: f32);
+    fn get_gain(&self) -> f32;
+    fn is_source_lost(&self) -> bool;
+    fn is_buffering(&self) -> bool;
+    fn set_end_behavior(&self, behavior: EndBehavior);
+    fn get_end_behavior(&self) -> EndBehavior;
+    fn set_seamless_loop(&self, enabled: bool);
+    fn set_successor(&self, successor: Option<Arc<FFmpegSourceStatus>>);
+    fn is_seamless_loop(&self) -> bool;
+    fn set_output_format(&self, format: DeviceSampleFormat);
+    fn set_seek_debounce_ms(&self, debounce_ms: u32);
+    fn get_seek_debounce_ms(&self) -> u32;
+    fn set_error_resilience(&self, resilience: ErrorResilience);
+    fn get_error_resilience(&self) -> ErrorResilience;
+    fn set_ab_loop(&self, ab_loop: Option<(i64, i64)>);
+    fn get_ab_loop(&self) -> Option<(i64, i64)>;
 }
 
 pub struct FFmpegSourceStatus {
@@ -80,369 +228,2506 @@ pub struct FFmpegSourceStatus {
     pub playing: AtomicBool,
     pub current_time: Mutex<Cell<i64>>,
     pub is_end: AtomicBool,
+    pub seekable: AtomicBool,
+    /// 实时码率（bit/s）
+    pub current_bitrate: AtomicI64,
+    /// 播放速度（f32 的位表示）
+    pub speed: AtomicU32,
+    /// 解码后应用的增益（振幅比例，f32 的位表示），用于 ReplayGain
+    pub gain: AtomicU32,
+    /// 本地文件在播放中无法继续读取（例如拔出了 U 盘），解码线程已经停止，当前位置保持不变
+    pub source_lost: AtomicBool,
+    /// 播放到结尾后的行为（EndBehavior 的数值表示）
+    pub end_behavior: AtomicU8,
+    /// 播放到结尾时不停止，直接从头继续解码，首尾之间没有间隙
+    pub seamless_loop: AtomicBool,
+    /// 结束行为为 Handover 时，播放完毕后接着写入同一个输出缓冲区的下一个播放源
+    pub successor: Mutex<Option<Arc<FFmpegSourceStatus>>>,
+    /// 上一个播放源播放完毕后把输出交给了这个播放源
+    pub handed_over: AtomicBool,
+    /// 定位防抖时间（毫秒），上一次定位之后这段时间内的定位请求只执行最后一个
+    pub seek_debounce_ms: AtomicU32,
+    /// 遇到无法解码的数据包时的处理方式（ErrorResilience 的数值表示）
+    pub error_resilience: AtomicU8,
+    /// A-B 循环的起点和终点（毫秒），解码到终点后回到起点
+    pub ab_loop: Mutex<Option<(i64, i64)>>,
+    /// 开始播放或者定位之后正在预先缓冲，输出等到解码线程清除这个标记再开始消费
+    pub prebuffering: AtomicBool,
+    /// 解码线程已经写入了最后的数据，正在等待输出缓冲区播放完，输出不再为它缓冲
+    pub draining: AtomicBool,
+    /// 预先缓冲的帧数，u64::MAX 表示缓冲到动态缓冲区写满为止
+    pub prebuffer_frames: AtomicU64,
+    /// 写入输出缓冲区的总帧数，用于在数据包之间按采样数推算位置
+    pub written_frames: AtomicU64,
+    /// 最近一个数据包（或者定位目标）的时间（微秒）和当时已经写入的帧数
+    pub position_anchor: Mutex<(i64, u64)>,
+    /// 通知等待播放结束的线程，等待的线程持有自己的引用，不需要在等待期间访问播放源
+    pub end_signal: Arc<EndSignal>,
+    /// 解码线程还没有处理的定位目标
+    pub seek_target: SeekSlot,
+    /// 设备的格式改变后，解码线程还没有切换到的新格式
+    pub output_format: Mutex<Option<DeviceSampleFormat>>,
+    /// 输出缓冲区有了空间或者播放状态改变的通知
+    pub buffer_space: SpaceSignal,
+    /// 读取和解码的统计数据
+    pub counters: DecodeCounters,
+    /// 还没有报告 Ready 的打开进度回调
+    pub open_progress: Mutex<Option<OpenProgress>>,
 }
 
-pub struct FFmpegSource {
-    media_info: MediaInfo,
-    seek_channel_tx: Option<mpsc::Sender<i64>>,
-    decode_thread: Option<std::thread::JoinHandle<()>>,
-    decode_thread_suspend_rx: Option<mpsc::Receiver<u8>>,
-    pub status: Arc<FFmpegSourceStatus>,
-    buffer_producer: Arc<Mutex<Producer<AudioSample>>>,
-    buffer_consumer: Arc<Mutex<Consumer<AudioSample>>>,
-    buffer_chunk_size: Arc<Mutex<Cell<usize>>>,
-    dynamic_device_buffer_size: Arc<Mutex<Cell<usize>>>,
+/// 解码线程更新的统计数据，只使用原子变量，不影响解码
+pub struct DecodeCounters {
+    bytes_read: AtomicU64,
+    packets_read: AtomicU64,
+    frames_decoded: AtomicU64,
+    resampled_samples: AtomicU64,
+    seeks: AtomicU64,
+    buffer_discards: AtomicU64,
+    write_blocked_us: AtomicU64,
+    buffer_high_watermark: AtomicU64,
+    /// u64::MAX 表示还没有记录
+    buffer_low_watermark: AtomicU64,
+    buffer_capacity: AtomicU64,
 }
 
-impl FFmpegSource {
-    pub fn new(
-        buffer_producer: &Arc<Mutex<Producer<AudioSample>>>,
-        buffer_consumer: &Arc<Mutex<Consumer<AudioSample>>>,
-        dynamic_device_buffer_size: usize,
-    ) -> FFmpegSource {
-        FFmpegSource {
-            media_info: MediaInfo::default(),
-            seek_channel_tx: None,
-            decode_thread: None,
-            decode_thread_suspend_rx: None,
-            status: Arc::new(FFmpegSourceStatus { 
-                dropping_frames: AtomicBool::new(false),
-                avaliable: AtomicBool::new(false),
-                playing: AtomicBool::new(false),
-                current_time: Mutex::new(Cell::new(0)),
-                is_end: AtomicBool::new(false),
-            }),
-            buffer_producer: buffer_producer.clone(),
-            buffer_consumer: buffer_consumer.clone(),
-            buffer_chunk_size: Arc::new(Mutex::new(Cell::new(dynamic_device_buffer_size / 2))),
-            dynamic_device_buffer_size: Arc::new(Mutex::new(Cell::new(dynamic_device_buffer_size))),
+impl DecodeCounters {
+    fn new() -> DecodeCounters {
+        DecodeCounters {
+            bytes_read: AtomicU64::new(0),
+            packets_read: AtomicU64::new(0),
+            frames_decoded: AtomicU64::new(0),
+            resampled_samples: AtomicU64::new(0),
+            seeks: AtomicU64::new(0),
+            buffer_discards: AtomicU64::new(0),
+            write_blocked_us: AtomicU64::new(0),
+            buffer_high_watermark: AtomicU64::new(0),
+            buffer_low_watermark: AtomicU64::new(u64::MAX),
+            buffer_capacity: AtomicU64::new(0),
         }
     }
 
-    fn ffmpeg_frame_to_slice(frame: &frame::Audio) -> Vec<AudioSample> {
-        if !frame.is_packed() {
-            panic!("音频帧数据不是交错格式");
+    fn reset(&self) {
+        for counter in [&self.bytes_read, &self.packets_read, &self.frames_decoded, &self.resampled_samples,
+            &self.seeks, &self.buffer_discards, &self.write_blocked_us, &self.buffer_high_watermark] {
+            counter.store(0, Ordering::Relaxed);
         }
-        
-        let pcm = unsafe {
-            std::slice::from_raw_parts((*frame.as_ptr()).data[0] as *const f32, frame.samples() * frame.channels() as usize)
+        self.buffer_low_watermark.store(u64::MAX, Ordering::Relaxed);
+    }
+
+    /// 读取了一个数据包，字节数使用 FFmpeg 的 AVIOContext 的统计
+    fn count_packet(&self, input_ctx: &format::context::Input) {
+        self.packets_read.fetch_add(1, Ordering::Relaxed);
+        let bytes_read = unsafe {
+            let pb = (*input_ctx.as_ptr()).pb;
+            if pb.is_null() {
+                return;
+            }
+            (*pb).bytes_read
         };
+        self.bytes_read.store(bytes_read.max(0) as u64, Ordering::Relaxed);
+    }
 
-        pcm.chunks_exact(frame.channels() as usize)
-            .map(AudioSample::from_slice)
-            .collect()
+    fn count_write_wait(&self, waited: Duration) {
+        self.write_blocked_us.fetch_add(waited.as_micros() as u64, Ordering::Relaxed);
     }
 
-    fn clear_resampler_buffer(resampler: &mut SwrContext) {
+    /// 记录写入前后输出缓冲区中的数据量，预先缓冲时缓冲区本来就是空的，不计入最小值
+    fn count_buffer_level(&self, before: u64, after: u64, capacity: u64, prebuffering: bool) {
+        if !prebuffering {
+            self.buffer_low_watermark.fetch_min(before, Ordering::Relaxed);
+        }
+        self.buffer_high_watermark.fetch_max(after, Ordering::Relaxed);
+        self.buffer_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DebugStats {
+        let low = self.buffer_low_watermark.load(Ordering::Relaxed);
+        DebugStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            packets_read: self.packets_read.load(Ordering::Relaxed),
+            frames_decoded: self.frames_decoded.load(Ordering::Relaxed),
+            resampled_samples: self.resampled_samples.load(Ordering::Relaxed),
+            seeks: self.seeks.load(Ordering::Relaxed),
+            buffer_discards: self.buffer_discards.load(Ordering::Relaxed),
+            write_blocked_us: self.write_blocked_us.load(Ordering::Relaxed),
+            buffer_high_watermark: self.buffer_high_watermark.load(Ordering::Relaxed),
+            buffer_low_watermark: if low == u64::MAX { 0 } else { low },
+            buffer_capacity: self.buffer_capacity.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 输出缓冲区有了空间或者播放状态改变的通知，唤醒等待写入或者暂停中的解码线程，代替固定间隔的轮询
+///
+/// 输出回调只在解码线程等待时才唤醒，没有多余的系统调用。
+/// 播放、定位、修改结束行为和关闭时由控制线程唤醒，等待最多持续到超时，之后重新检查状态
+#[derive(Default)]
+pub struct SpaceSignal {
+    waiting: AtomicBool,
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+impl SpaceSignal {
+    /// 在输出回调中调用，不会阻塞
+    pub fn notify(&self) {
+        if self.waiting.load(Ordering::Relaxed) {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// 等待输出回调消费数据，最多等待 timeout
+    fn wait(&self, timeout: Duration) {
+        let guard = self.lock.lock().unwrap();
+        self.waiting.store(true, Ordering::Relaxed);
+        drop(self.condvar.wait_timeout(guard, timeout).unwrap());
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+
+    /// 等到 ready 返回 true、被唤醒或者超时，ready 在持有锁时检查，不会错过 wake 的通知
+    fn wait_until<F: Fn() -> bool>(&self, timeout: Duration, ready: F) {
+        let guard = self.lock.lock().unwrap();
+        if ready() {
+            return;
+        }
+        self.waiting.store(true, Ordering::Relaxed);
+        drop(self.condvar.wait_timeout(guard, timeout).unwrap());
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+
+    /// 在控制线程中调用，改变播放状态之后立即唤醒解码线程
+    pub fn wake(&self) {
+        drop(self.lock.lock().unwrap());
+        self.condvar.notify_all();
+    }
+}
+
+/// 播放结束的通知，等待的线程在播放到结尾（输出缓冲区已经播放完）、停止或者关闭时被唤醒
+#[derive(Default)]
+pub struct EndSignal {
+    /// 是否已经解码到结尾，以及被打断的次数
+    state: Mutex<(bool, u64)>,
+    condvar: Condvar,
+}
+
+impl EndSignal {
+    fn set_ended(&self, ended: bool) {
+        self.state.lock().unwrap().0 = ended;
+        if ended {
+            self.condvar.notify_all();
+        }
+    }
+
+    /// 停止或者关闭时唤醒所有等待的线程
+    pub fn interrupt(&self) {
+        self.state.lock().unwrap().1 += 1;
+        self.condvar.notify_all();
+    }
+
+    /// 等到播放到结尾时返回 true，已经在结尾时立即返回。超时或者被打断时返回 false
+    pub fn wait(&self, timeout: Option<Duration>) -> bool {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        let interruptions = state.1;
         loop {
-            let mut resampled = frame::Audio::empty();
-            if let Ok(delay) = resampler.flush(&mut resampled) {
-                if delay.is_none() {
-                    break;
-                }
-            } else {
-                break;
+            if state.0 {
+                return true;
             }
+            if state.1 != interruptions {
+                return false;
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.condvar.wait_timeout(state, deadline - now).unwrap().0
+                },
+                None => self.condvar.wait(state).unwrap(),
+            };
         }
-        unsafe {
-            // 关闭重采样器来释放 FIFO 里不足以写入一帧的数据
-            // https://ffmpeg.org/doxygen/4.1/group__lswr.html#gaa4bf1048740dfc08d68aba9f1b4db22e
-            let resampler_ptr = resampler.as_mut_ptr();
-            ffmpeg_c_api::swr_close(resampler_ptr);
-            ffmpeg_c_api::swr_init(resampler_ptr);
-        }
+    }
+}
+
+/// 解码线程还没有处理的定位目标，只保存最新的一个
+///
+/// 拖动进度条时会连续发出很多定位请求，新的目标直接覆盖还没有执行的目标，
+/// 解码线程处理时只定位到最后一个位置，不会逐个清空缓冲区和解码器
+#[derive(Default)]
+pub struct SeekSlot {
+    /// 定位目标（AV_TIME_BASE）
+    target: Mutex<Option<i64>>,
+    condvar: Condvar,
+}
+
+impl SeekSlot {
+    /// 设置新的定位目标，覆盖还没有处理的目标
+    pub fn post(&self, target: i64) {
+        *self.target.lock().unwrap() = Some(target);
+        self.condvar.notify_all();
     }
 
-    fn blocking_write_buffer(
-        status: &Arc<FFmpegSourceStatus>,
-        chunk_size: usize,
-        dynamic_device_buffer_size: usize,
-        slice: &[AudioSample],
-        producer: &mut ringbuf::Producer<AudioSample>
-    ) {
-        // 先分块，避免缓冲区容量比帧小，产生死锁
-        let chunks = slice.chunks(chunk_size);
-        for chunk in chunks {
-            if !status.avaliable.load(Ordering::Relaxed) {
-                return;
+    /// 是否有还没有处理的定位目标
+    pub fn is_pending(&self) -> bool {
+        self.target.lock().unwrap().is_some()
+    }
+
+    /// 取出定位目标，没有时返回 None
+    pub fn take(&self) -> Option<i64> {
+        self.target.lock().unwrap().take()
+    }
+
+    /// 等待新的定位目标，超时返回 None
+    pub fn wait(&self, timeout: Duration) -> Option<i64> {
+        let deadline = Instant::now() + timeout;
+        let mut target = self.target.lock().unwrap();
+        loop {
+            if let Some(target) = target.take() {
+                return Some(target);
             }
-            
-            // 根据采样率动态调整缓冲区大小
-            // producer.remaining() < chunk.len() + producer.capacity() - dynamic_device_buffer_size
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            target = self.condvar.wait_timeout(target, deadline - now).unwrap().0;
+        }
+    }
 
-            // 缓冲区满或者暂停则等待
-            while producer.remaining() < chunk.len() + producer.capacity() - dynamic_device_buffer_size
-                || !status.playing.load(Ordering::Relaxed) {
-                if !status.avaliable.load(Ordering::Relaxed) {
-                    return;
+    /// 定位防抖：在截止时间之前等待新的目标，新目标覆盖旧目标，返回最后设置的目标
+    ///
+    /// 没有截止时间时（单独的一次定位）不等待，`avaliable` 变为 false 时提前结束
+    pub fn settle(&self, mut target: i64, deadline: Option<Instant>, avaliable: &AtomicBool) -> i64 {
+        if let Some(deadline) = deadline {
+            loop {
+                let now = Instant::now();
+                if now >= deadline || !avaliable.load(Ordering::Relaxed) {
+                    break;
                 }
-                // 系统需要丢弃未写入缓冲区的帧数据
-                if status.dropping_frames.load(Ordering::Relaxed) {
-                    println!("丢弃不再需要的音频帧");
-                    return;
+                match self.wait(deadline - now) {
+                    Some(next_target) => target = next_target,
+                    None => break,
                 }
-                std::thread::sleep(std::time::Duration::from_millis(10));
-            }
-            // 向缓冲区写入数据，如果需要丢弃帧数据则直接跳出循环
-            if !status.dropping_frames.load(Ordering::Relaxed) {
-                producer.push_slice(chunk);
-            } else {
-                return;
             }
         }
+        // 取出请求之后又设置了新的目标时，直接定位到新的目标
+        self.take().unwrap_or(target)
     }
+}
 
-    fn decode_to_buffer (
-        status: &Arc<FFmpegSourceStatus>,
-        chunck_size: &Arc<Mutex<Cell<usize>>>,
-        dynamic_device_buffer_size: &Arc<Mutex<Cell<usize>>>,
-        decoder: &mut decoder::Audio,
-        producer: &mut ringbuf::Producer<AudioSample>,
-        resampler: &mut SwrContext,
-    ) -> Result<(), ffmpeg::Error> {
-        let chunk_size = chunck_size.lock().unwrap().get();
-        let dynamic_device_buffer_size = dynamic_device_buffer_size.lock().unwrap().get();
+impl FFmpegSourceStatus {
+    /// 标记是否已经解码到结尾，并通知等待播放结束的线程
+    fn set_end(&self, is_end: bool) {
+        self.is_end.store(is_end, Ordering::Relaxed);
+        self.end_signal.set_ended(is_end);
+    }
 
-        let mut decoded = frame::Audio::empty();
-        while decoder.receive_frame(&mut decoded).is_ok() {
-            if !status.avaliable.load(Ordering::Relaxed) {
-                return Ok(());
+    /// 输出缓冲区中有了可以播放的数据，打开之后第一次调用时报告 Ready
+    fn report_ready(&self) {
+        let progress = self.open_progress.lock().unwrap().take();
+        if let Some(progress) = progress {
+            progress(OpenPhase::Ready);
+        }
+    }
+
+    /// 记录接下来写入的数据从 position_us 开始
+    fn anchor_position(&self, position_us: i64) {
+        *self.position_anchor.lock().unwrap() = (position_us, self.written_frames.load(Ordering::Relaxed));
+    }
+}
+
+/// 统计最近一段时间内读取的数据量，计算实时码率
+#[derive(Default)]
+struct BitrateMeter {
+    bytes: usize,
+    seconds: f64,
+}
+
+impl BitrateMeter {
+    /// 统计的时间窗口（秒）
+    const WINDOW: f64 = 0.5;
+
+    /// 记录一个数据包，累计时长足够时返回码率（bit/s）
+    fn push(&mut self, size: usize, seconds: f64) -> Option<i64> {
+        self.bytes += size;
+        self.seconds += seconds;
+        if self.seconds < Self::WINDOW {
+            return None;
+        }
+        let bitrate = (self.bytes * 8) as f64 / self.seconds;
+        self.reset();
+        Some(bitrate as i64)
+    }
+
+    fn reset(&mut self) {
+        self.bytes = 0;
+        self.seconds = 0.;
+    }
+}
+
+/// 解码线程读取数据包时更新播放位置、实时码率、封面和网络电台的标题
+struct PlaybackObserver {
+    status: Arc<FFmpegSourceStatus>,
+    network: Option<Arc<io::NetworkMonitor>>,
+    media_info: Arc<Mutex<Arc<MediaInfo>>>,
+    events: EventBus,
+    /// 音频流第一个数据包的时间，播放位置相对于这个时间
+    start_ms: i64,
+    bitrate_meter: BitrateMeter,
+    pictures: HashMap<usize, Vec<u8>>,
+    last_icy_check: Instant,
+}
+
+impl PacketObserver for PlaybackObserver {
+    fn packet_read(&mut self, input_ctx: &MediaInput, packet: &ffmpeg::Packet, audio: bool) {
+        self.status.counters.count_packet(input_ctx);
+        let stream = match input_ctx.stream(packet.stream()) {
+            Some(stream) => stream,
+            None => return,
+        };
+        if audio {
+            // 更新当前时间
+            if let Some(pts) = packet.pts() {
+                let current_time = pts as f64 * f64::from(stream.time_base()) * 1000.0 - self.start_ms as f64;
+                self.status.current_time.lock().unwrap().set((current_time as i64).max(0));
+                self.status.anchor_position((current_time * 1000.) as i64);
             }
 
-            let mut resampled = frame::Audio::empty();
-            let mut delay = resampler.run(&decoded, &mut resampled)?;
-            loop {
-                if !status.avaliable.load(Ordering::Relaxed) {
-                    return Ok(());
-                }
-                // 将重采样后的将音频数据写入对应的缓冲区中
-                FFmpegSource::blocking_write_buffer(
-                    status,
-                    chunk_size,
-                    dynamic_device_buffer_size,
-                    FFmpegSource::ffmpeg_frame_to_slice(&resampled).as_slice(),
-                    producer);
-                // 输出的大小装不下的部分会在重采样器里缓存，需要循环读取到缓存为空
-                if delay == None {
-                    break;
+            // 更新实时码率
+            if packet.duration() > 0 {
+                let seconds = packet.duration() as f64 * f64::from(stream.time_base());
+                if let Some(bitrate) = self.bitrate_meter.push(packet.size(), seconds) {
+                    self.status.current_bitrate.store(bitrate, Ordering::Relaxed);
                 }
-                delay = resampler.flush(&mut resampled)?;
+            }
+        } else if stream.disposition().contains(format::stream::Disposition::ATTACHED_PIC) {
+            if let Some(picture) = packet.data() {
+                FFmpegSource::update_cover(stream.index(), picture, &mut self.pictures, &self.media_info, &self.events);
+            }
+        }
+
+        if let Some(network) = self.network.as_ref() {
+            network.mark_read();
+            if self.last_icy_check.elapsed() >= ICY_POLL_INTERVAL {
+                self.last_icy_check = Instant::now();
+                FFmpegSource::update_stream_title(input_ctx, &self.media_info, &self.events);
             }
         }
-        Ok(())
     }
 
-    pub fn open<P: AsRef<Path>>(&mut self, uri: &P, device_sample_format: &DeviceSampleFormat) -> Result<(), Error> {
-        // 打开文件，获取音频流
-        let mut input_ctx = format::input(&uri).context(OpenMediaFileWithFFmpegSnafu {
-            message: "the file could not be opened, either because the file does not exist, cannot be accessed, or the file format is not supported".to_string(),
-        })?;
+    fn read_failed(&mut self, err: &ffmpeg::Error) -> bool {
+        if !self.status.avaliable.load(Ordering::Relaxed) {
+            return false;
+        }
+        // 网络中断时 FFmpeg 会按重连选项尝试恢复，稍后再读取
+        if self.network.is_some() {
+            std::thread::sleep(NETWORK_RETRY_INTERVAL);
+            return true;
+        }
+        // 本地文件读取出错，一般是文件所在的设备被移除或者文件被删除，重试没有意义
+        log::error!("lost the media file: {}", err);
+        self.status.source_lost.store(true, Ordering::Relaxed);
+        false
+    }
+}
 
-        // 获取专辑封面
-        self.media_info.cover = metadata::ffmpeg::first_picture_from_input_context(&input_ctx);
+/// 检查网络电台 ICY 元数据的间隔
+const ICY_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-        let stream = input_ctx.streams().best(media::Type::Audio).context(OpenMediaFileSnafu {
-            message: "failed to get audio stream".to_string(),
-        })?;
+/// 网络流读取失败后重试的间隔
+const NETWORK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 等待输出缓冲区空间时最长的等待时间，暂停、定位和关闭在这个时间内生效
+const WRITE_WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 暂停或者播放结束时等待通知的最长时间，控制线程会立即唤醒，超时只是保险
+const IDLE_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 关闭时等待解码线程退出的最长时间，超时后不再等待，线程醒来后发现播放源已关闭会自行退出
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 解码线程，附带一个在线程结束时断开的通道，关闭时可以只等待有限的时间
+struct DecodeThread {
+    handle: JoinHandle<()>,
+    exited: Receiver<()>,
+}
+
+impl DecodeThread {
+    fn spawn<F: FnOnce() + Send + 'static>(body: F) -> DecodeThread {
+        let (exit_sender, exited) = mpsc::channel::<()>();
+        let handle = std::thread::spawn(move || {
+            // 线程结束（包括 panic）时丢弃发送端，等待的一方收到断开
+            let _exit_sender = exit_sender;
+            body();
+        });
+        DecodeThread { handle, exited }
+    }
+
+    /// 等待线程退出，超过 timeout 时放弃等待并返回错误，线程留在后台自行结束
+    fn join(self, timeout: Duration) -> Result<(), Error> {
+        match self.exited.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => CloseMediaFileSnafu {
+                message: format!("the decode thread did not exit within {} ms, detached it", timeout.as_millis()),
+            }.fail(),
+            _ => self.handle.join().map_err(|_| Error::CloseMediaFile {
+                message: "the decode thread panicked".to_string(),
+            }),
+        }
+    }
+}
+
+/// 播放源的虚拟时间窗口，只播放文件中 [start_ms, end_ms) 范围内的音频
+///
+/// 用于只播放文件中的一个片段（例如 CUE 中的一条曲目）。对外的播放位置、总长度和定位都相对于窗口起点，
+/// 解码到终点时和读到文件结尾一样结束播放。end_ms 为 None 时播放到文件结尾
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeWindow {
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+}
+
+impl TimeWindow {
+    pub fn new(start_ms: i64, end_ms: Option<i64>) -> TimeWindow {
+        TimeWindow { start_ms, end_ms }
+    }
+
+    /// 是否是整个文件
+    pub fn is_full(&self) -> bool {
+        self.start_ms <= 0 && self.end_ms.is_none()
+    }
+
+    /// 窗口的长度，file_duration 为文件的总长度，未知时为负数
+    pub fn duration(&self, file_duration: i64) -> i64 {
+        let end = match (self.end_ms, file_duration >= 0) {
+            (Some(end_ms), true) => end_ms.min(file_duration),
+            (Some(end_ms), false) => end_ms,
+            (None, true) => file_duration,
+            (None, false) => return file_duration,
+        };
+        (end - self.start_ms).max(0)
+    }
+
+    /// 相对于窗口起点的位置转换为文件中的位置，超出窗口时限制在窗口内
+    pub fn to_absolute(&self, relative_ms: i64) -> i64 {
+        let absolute = self.start_ms + relative_ms.max(0);
+        match self.end_ms {
+            Some(end_ms) => absolute.min(end_ms),
+            None => absolute,
+        }
+    }
+
+    /// 文件中的位置转换为相对于窗口起点的位置
+    pub fn to_relative(&self, absolute_ms: i64) -> i64 {
+        let absolute = match self.end_ms {
+            Some(end_ms) => absolute_ms.min(end_ms),
+            None => absolute_ms,
+        };
+        (absolute - self.start_ms).max(0)
+    }
+}
+
+/// 按解码帧的时间戳裁掉起点之前和终点之后的采样，用于时间窗口和 A-B 循环
+struct WindowTrim {
+    start_ms: i64,
+    end_ms: Option<i64>,
+    time_base: ffmpeg::Rational,
+    /// 音频流起始时间戳对应的毫秒数，范围相对于这个时间
+    stream_start_ms: i64,
+    /// 已经解码到终点
+    reached_end: bool,
+}
+
+impl WindowTrim {
+    fn new(start_ms: i64, end_ms: Option<i64>, time_base: ffmpeg::Rational, stream_start_ms: i64) -> WindowTrim {
+        WindowTrim { start_ms, end_ms, time_base, stream_start_ms, reached_end: false }
+    }
+
+    /// 一帧中需要保留的采样范围，整帧都在范围之外时返回 None
+    fn range(&mut self, frame: &frame::Audio) -> Option<(usize, usize)> {
+        let samples = frame.samples();
+        if self.reached_end {
+            return None;
+        }
+        let timestamp = match frame.timestamp() {
+            Some(timestamp) => timestamp,
+            None => return Some((0, samples)),
+        };
+        let frame_ms = timestamp as f64 * f64::from(self.time_base) * 1000. - self.stream_start_ms as f64;
+        let to_index = |ms: i64| ((ms as f64 - frame_ms) * frame.rate() as f64 / 1000.).round();
+        let start = to_index(self.start_ms).clamp(0., samples as f64) as usize;
+        let end = self.end_ms.map(to_index).unwrap_or(samples as f64);
+        if end < samples as f64 {
+            self.reached_end = true;
+        }
+        let end = end.clamp(0., samples as f64) as usize;
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+}
+
+/// 音频流第一个数据包的时间（毫秒）
+///
+/// 部分容器（例如 MPEG-TS）的时间戳不从 0 开始，播放位置和定位目标都以这个时间作为 0
+fn stream_start_ms(stream: &format::stream::Stream) -> i64 {
+    let start_time = stream.start_time();
+    if start_time == ffmpeg_c_api::AV_NOPTS_VALUE || start_time <= 0 {
+        return 0;
+    }
+    start_time.rescale(stream.time_base(), (1, 1000))
+}
+
+/// 解码线程输出给设备的格式
+struct OutputFormat {
+    sample_rate: u32,
+    channel_layout: ffmpeg::ChannelLayout,
+}
+
+/// 播放源中可能启用的处理步骤名称
+pub const PROCESSING_FORMAT_CONVERT: &str = "format-convert";
+pub const PROCESSING_RESAMPLE: &str = "resample";
+pub const PROCESSING_DOWNMIX: &str = "downmix";
+pub const PROCESSING_UPMIX: &str = "upmix";
+pub const PROCESSING_CHANNEL_REMAP: &str = "channel-remap";
+pub const PROCESSING_TEMPO: &str = "tempo";
+pub const PROCESSING_GAIN: &str = "gain";
+
+/// 打开文件，options 为传给 FFmpeg 的协议和解封装器选项
+pub fn open_media_file<P: AsRef<Path>>(uri: &P, options: &HashMap<String, String>) -> Result<MediaInput, Error> {
+    if let Some(url) = uri.as_ref().to_str().filter(|uri| io::is_network_url(uri)) {
+        return MediaInput::open_network(url, options).context(OpenMediaFileWithFFmpegSnafu {
+            message: "the stream could not be opened, either because the server cannot be reached or the format is not supported".to_string(),
+        });
+    }
+
+    // 空文件交给 FFmpeg 只会得到难以理解的错误
+    let is_empty = std::fs::metadata(uri).map(|metadata| metadata.is_file() && metadata.len() == 0).unwrap_or(false);
+    ensure!(!is_empty, OpenMediaFileSnafu {
+        message: "the file is empty".to_string(),
+    });
+
+    MediaInput::open_file(uri, options).context(OpenMediaFileWithFFmpegSnafu {
+        message: "the file could not be opened, either because the file does not exist, cannot be accessed, or the file format is not supported".to_string(),
+    })
+}
+
+/// 和 open_media_file 相同，超过 deadline 时中断打开，不接受输入选项
+pub fn open_media_with_deadline<P: AsRef<Path>>(uri: &P, deadline: Instant) -> Result<MediaInput, Error> {
+    let is_empty = std::fs::metadata(uri).map(|metadata| metadata.is_file() && metadata.len() == 0).unwrap_or(false);
+    ensure!(!is_empty, OpenMediaFileSnafu {
+        message: "the file is empty".to_string(),
+    });
+    ensure!(Instant::now() < deadline, OpenMediaFileSnafu {
+        message: "the time budget was used up before opening the file".to_string(),
+    });
+
+    MediaInput::open_with_deadline(uri, deadline).context(OpenMediaFileWithFFmpegSnafu {
+        message: "the file could not be opened in time, either because it cannot be accessed or the format is not supported".to_string(),
+    })
+}
+
+/// 从可以定位的读取器打开
+pub fn open_seekable_media(reader: Box<dyn SeekableRead>) -> Result<MediaInput, Error> {
+    MediaInput::open_seekable_reader(reader).context(OpenMediaFileWithFFmpegSnafu {
+        message: "the stream could not be opened, either because it cannot be read or the format is not supported".to_string(),
+    })
+}
+
+/// 只读取文件头获取媒体信息，会创建解码器检查格式是否支持，但不解码任何数据
+pub fn probe_input(input_ctx: &MediaInput, path: Option<&Path>, extractors: &[Arc<dyn MetadataExtractor>]) -> Result<MediaInfo, Error> {
+    let mut media_info = MediaInfo::default();
+    read_metadata(input_ctx, path, true, extractors, &mut media_info);
+    let stream = default_audio_stream(input_ctx).context(NoAudioStreamSnafu)?;
+    let decoder = open_decoder(&stream)?;
+    read_stream_info(input_ctx, &stream, &decoder, &mut media_info);
+    Ok(media_info)
+}
+
+/// 选择要播放的音频流，优先使用标记为默认的音频流，没有时使用 FFmpeg 选择的最佳音频流
+///
+/// FFmpeg 按声道数和码率等选择，可能选中解说或者口述影像音轨，和其他播放器的选择不同
+pub fn default_audio_stream(input_ctx: &format::context::Input) -> Option<format::stream::Stream> {
+    input_ctx.streams()
+        .filter(|stream| stream.parameters().medium() == media::Type::Audio)
+        .find(|stream| stream.disposition().contains(format::stream::Disposition::DEFAULT))
+        .or_else(|| input_ctx.streams().best(media::Type::Audio))
+}
+
+/// 列出文件中的所有音频流，selected_index 为正在播放的流
+fn audio_streams(input_ctx: &format::context::Input, selected_index: usize) -> Vec<AudioStreamInfo> {
+    use format::stream::Disposition;
+
+    input_ctx.streams()
+        .filter(|stream| stream.parameters().medium() == media::Type::Audio)
+        .map(|stream| {
+            let parameters = unsafe { &*stream.parameters().as_ptr() };
+            let disposition = stream.disposition();
+            let tag = |key: &str| stream.metadata().get(key)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty());
+            AudioStreamInfo {
+                index: stream.index(),
+                codec: Some(unsafe {
+                    CStr::from_ptr(ffmpeg_c_api::avcodec_get_name(parameters.codec_id))
+                }.to_string_lossy().to_uppercase()),
+                sample_rate: parameters.sample_rate.max(0) as u32,
+                channels: parameters.channels.max(0) as u16,
+                language: tag("language"),
+                title: tag("title"),
+                disposition: StreamDisposition {
+                    default: disposition.contains(Disposition::DEFAULT),
+                    dub: disposition.contains(Disposition::DUB),
+                    original: disposition.contains(Disposition::ORIGINAL),
+                    comment: disposition.contains(Disposition::COMMENT),
+                    karaoke: disposition.contains(Disposition::KARAOKE),
+                    hearing_impaired: disposition.contains(Disposition::HEARING_IMPAIRED),
+                    visual_impaired: disposition.contains(Disposition::VISUAL_IMPAIRED),
+                },
+                selected: stream.index() == selected_index,
+            }
+        })
+        .collect()
+}
+
+/// 依次使用 FFmpeg 和用户提供的读取器获取封面和标签，include_cover 为 false 时 FFmpeg 不读取封面
+fn read_metadata(
+    input_ctx: &MediaInput,
+    path: Option<&Path>,
+    include_cover: bool,
+    extractors: &[Arc<dyn MetadataExtractor>],
+    media_info: &mut MediaInfo,
+) {
+    let metadata_source = MetadataSource { path, input: input_ctx, include_cover };
+    FFmpegMetadataExtractor.extract(&metadata_source, media_info);
+    for extractor in extractors.iter() {
+        extractor.extract(&metadata_source, media_info);
+    }
+}
+
+/// 设备声道数对应的输出声道布局
+///
+/// 重采样器按输入和输出的声道布局生成混音矩阵，布局为空或者和声道数不一致时会静默地得到错误的声道映射，
+/// 所以只接受 FFmpeg 有标准布局并且 AudioSample 能容纳的声道数
+fn output_channel_layout(device_channels: u16) -> Result<ffmpeg::ChannelLayout, Error> {
+    let channel_layout = ffmpeg::ChannelLayout::default(device_channels.into());
+    ensure!(device_channels > 0
+        && device_channels as usize <= AudioSample::MAX_CHANNELS
+        && !channel_layout.is_empty()
+        && channel_layout.channels() == i32::from(device_channels), OpenMediaFileSnafu {
+        message: format!("the audio device has an unsupported channel count: {}", device_channels),
+    });
+    Ok(channel_layout)
+}
+
+/// 创建音频流的解码器
+fn open_decoder(stream: &format::stream::Stream) -> Result<decoder::Audio, Error> {
+    let context = codec::context::Context::from_parameters(stream.parameters()).context(OpenMediaFileWithFFmpegSnafu {
+        message: "failed to create codec context".to_string(),
+    })?;
+    let mut decoder = context.decoder().audio().context(OpenMediaFileWithFFmpegSnafu {
+        message: "failed to create audio decoder".to_string(),
+    })?;
+    // 将音频流相关信息拷贝到 AVCodecContext 中
+    decoder.set_parameters(stream.parameters()).context(OpenMediaFileWithFFmpegSnafu {
+        message: "failed to set codec parameters".to_string(),
+    })?;
+    // 有些格式（例如 WAV）没有 channel layout，有些文件的 channel layout 和声道数不一致
+    // 重采样器会检查 input stream 的配置和输入配置是否一致
+    if decoder.channel_layout().is_empty()
+        || decoder.channel_layout().channels() != i32::from(decoder.channels()) {
+        decoder.set_channel_layout(ffmpeg::ChannelLayout::default(decoder.channels().into()));
+    };
+    Ok(decoder)
+}
+
+/// 创建解码器时音频流的参数，链式 Ogg 等格式在切换到下一段时会改变
+struct StreamParameters {
+    codec_id: codec::Id,
+    sample_rate: i32,
+    channels: i32,
+    extradata: Vec<u8>,
+}
+
+impl StreamParameters {
+    fn from_stream(stream: &format::stream::Stream) -> StreamParameters {
+        let parameters = stream.parameters();
+        unsafe {
+            let raw = &*parameters.as_ptr();
+            StreamParameters {
+                codec_id: parameters.id(),
+                sample_rate: raw.sample_rate,
+                channels: raw.channels,
+                extradata: StreamParameters::extradata(raw).to_vec(),
+            }
+        }
+    }
+
+    unsafe fn extradata(raw: &ffmpeg_c_api::AVCodecParameters) -> &[u8] {
+        if raw.extradata.is_null() || raw.extradata_size <= 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(raw.extradata, raw.extradata_size as usize)
+        }
+    }
+
+    /// 音频流的参数是否和创建解码器时一致，每个数据包都会检查，不复制数据
+    fn matches(&self, stream: &format::stream::Stream) -> bool {
+        let parameters = stream.parameters();
+        unsafe {
+            let raw = &*parameters.as_ptr();
+            parameters.id() == self.codec_id
+                && raw.sample_rate == self.sample_rate
+                && raw.channels == self.channels
+                && StreamParameters::extradata(raw) == self.extradata.as_slice()
+        }
+    }
+}
+
+/// 让解封装器丢弃其他流的数据包，例如视频文件中的画面和字幕，只读取要播放的音频流
+///
+/// 附加图片流（封面）不丢弃，FFmpeg 在打开和定位之后会重新送出封面的数据包，链式流中途也可能出现新的封面
+fn discard_other_streams(input_ctx: &mut format::context::Input, stream_index: usize) {
+    unsafe {
+        let ctx = input_ctx.as_mut_ptr();
+        for index in 0..(*ctx).nb_streams as usize {
+            let stream = *(*ctx).streams.add(index);
+            let attached_pic = (*stream).disposition & ffmpeg_c_api::AV_DISPOSITION_ATTACHED_PIC as i32 != 0;
+            if index != stream_index && !attached_pic {
+                (*stream).discard = ffmpeg_c_api::AVDiscard::AVDISCARD_ALL;
+            }
+        }
+    }
+}
+
+/// 按 FFmpeg 的编码属性判断是否是无损编码，同时标记为有损和无损的编码（例如包含无损扩展的 DTS）返回 None
+///
+/// WavPack 也有这两个标记，有损的混合模式很少见，按无损处理
+fn codec_is_lossless(id: codec::Id) -> Option<bool> {
+    if id == codec::Id::WAVPACK {
+        return Some(true);
+    }
+    let descriptor = unsafe { ffmpeg_c_api::avcodec_descriptor_get(id.into()) };
+    if descriptor.is_null() {
+        return None;
+    }
+    let props = unsafe { (*descriptor).props } as u32;
+    let lossless = props & ffmpeg_c_api::AV_CODEC_PROP_LOSSLESS != 0;
+    let lossy = props & ffmpeg_c_api::AV_CODEC_PROP_LOSSY != 0;
+    match (lossless, lossy) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// 获取音频流格式、码率和总长度
+fn read_stream_info(input_ctx: &MediaInput, stream: &format::stream::Stream, decoder: &decoder::Audio, media_info: &mut MediaInfo) {
+    media_info.codec = Some(unsafe {
+        CStr::from_ptr(ffmpeg_c_api::avcodec_get_name(decoder.id().into()))
+    }.to_string_lossy().to_uppercase());
+    media_info.is_lossless = codec_is_lossless(decoder.id());
+    media_info.sample_rate = decoder.rate();
+    media_info.channels = decoder.channels();
+    let bits_per_raw_sample = unsafe { (*stream.parameters().as_ptr()).bits_per_raw_sample };
+    media_info.bits_per_sample = if bits_per_raw_sample > 0 {
+        bits_per_raw_sample as u32
+    } else {
+        // 浮点格式一般来自有损编码，没有意义的位深
+        match decoder.format() {
+            format::Sample::U8(_) => 8,
+            format::Sample::I16(_) => 16,
+            format::Sample::I32(_) => 32,
+            format::Sample::I64(_) => 64,
+            _ => 0,
+        }
+    };
+    media_info.bitrate = input_ctx.bit_rate();
+    media_info.audio_streams = audio_streams(input_ctx, stream.index());
+    media_info.has_video = input_ctx.streams().any(|stream| {
+        stream.parameters().medium() == media::Type::Video
+            && !stream.disposition().contains(format::stream::Disposition::ATTACHED_PIC)
+    });
+    let extradata = unsafe { StreamParameters::extradata(&*stream.parameters().as_ptr()) };
+    media_info.codec_extradata = (!extradata.is_empty()).then(|| Arc::from(extradata));
+    media_info.raw_tags = RawTags {
+        container: input_ctx.metadata().iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        stream: stream.metadata().iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+    };
+
+    // 计算总长度（毫秒），未知时为 -1
+    media_info.duration = if input_ctx.duration() > 0 {
+        (input_ctx.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE) * 1000.0) as i64
+    } else {
+        -1
+    };
+}
+
+pub struct FFmpegSource {
+    /// 媒体信息的快照，解码线程收到新的 ICY 标题时替换
+    media_info: Arc<Mutex<Arc<MediaInfo>>>,
+    /// 打开时确定的格式转换步骤
+    processing: Vec<&'static str>,
+    /// 用户提供的元数据读取器，在 FFmpeg 之后调用
+    metadata_extractors: Vec<Arc<dyn MetadataExtractor>>,
+    /// 解码线程发出标题变化和缓冲事件
+    events: EventBus,
+    /// 网络流的读取状态，关闭时用来中断阻塞的读取
+    network: Option<Arc<io::NetworkMonitor>>,
+    /// 只播放文件中的这一段
+    window: TimeWindow,
+    /// 打开进度回调
+    open_progress: Option<OpenProgress>,
+    /// 打开时不读取内嵌的封面，由解码线程在打开之后读取
+    lazy_cover: bool,
+    decode_thread: Option<DecodeThread>,
+    pub status: Arc<FFmpegSourceStatus>,
+    buffer_producer: Arc<Mutex<Producer<AudioSample>>>,
+    buffer_consumer: Arc<Mutex<Consumer<AudioSample>>>,
+    buffer_chunk_size: Arc<Mutex<Cell<usize>>>,
+    dynamic_device_buffer_size: Arc<Mutex<Cell<usize>>>,
+}
+
+impl FFmpegSource {
+    pub fn new(
+        buffer_producer: &Arc<Mutex<Producer<AudioSample>>>,
+        buffer_consumer: &Arc<Mutex<Consumer<AudioSample>>>,
+        dynamic_device_buffer_size: usize,
+    ) -> FFmpegSource {
+        FFmpegSource {
+            media_info: Arc::new(Mutex::new(Arc::new(MediaInfo::default()))),
+            processing: Vec::new(),
+            metadata_extractors: Vec::new(),
+            events: EventBus::new(),
+            network: None,
+            window: TimeWindow::default(),
+            open_progress: None,
+            lazy_cover: false,
+            decode_thread: None,
+            status: Arc::new(FFmpegSourceStatus { 
+                dropping_frames: AtomicBool::new(false),
+                avaliable: AtomicBool::new(false),
+                playing: AtomicBool::new(false),
+                current_time: Mutex::new(Cell::new(0)),
+                is_end: AtomicBool::new(false),
+                seekable: AtomicBool::new(false),
+                current_bitrate: AtomicI64::new(0),
+                prebuffering: AtomicBool::new(false),
+                draining: AtomicBool::new(false),
+                prebuffer_frames: AtomicU64::new(u64::MAX),
+                written_frames: AtomicU64::new(0),
+                position_anchor: Mutex::new((0, 0)),
+                end_signal: Arc::new(EndSignal::default()),
+                seek_target: SeekSlot::default(),
+                output_format: Mutex::new(None),
+                buffer_space: SpaceSignal::default(),
+                counters: DecodeCounters::new(),
+                open_progress: Mutex::new(None),
+                speed: AtomicU32::new(1f32.to_bits()),
+                gain: AtomicU32::new(1f32.to_bits()),
+                source_lost: AtomicBool::new(false),
+                end_behavior: AtomicU8::new(EndBehavior::default().as_u8()),
+                seamless_loop: AtomicBool::new(false),
+                successor: Mutex::new(None),
+                handed_over: AtomicBool::new(false),
+                seek_debounce_ms: AtomicU32::new(DEFAULT_SEEK_DEBOUNCE_MS),
+                error_resilience: AtomicU8::new(ErrorResilience::default().as_u8()),
+                ab_loop: Mutex::new(None),
+            }),
+            buffer_producer: buffer_producer.clone(),
+            buffer_consumer: buffer_consumer.clone(),
+            buffer_chunk_size: Arc::new(Mutex::new(Cell::new(dynamic_device_buffer_size / 2))),
+            dynamic_device_buffer_size: Arc::new(Mutex::new(Cell::new(dynamic_device_buffer_size))),
+        }
+    }
+
+    /// 在调用者的线程上同步解码文件，不需要音频设备，也不启动解码线程
+    ///
+    /// 用于可视化、分析等需要自己处理音频数据的场景
+    pub fn frames<P: AsRef<Path>>(uri: &P) -> Result<Frames, Error> {
+        FFmpegSource::frames_in(uri, &HashMap::new(), TimeWindow::default())
+    }
+
+    /// 和 frames 相同，只解码文件中 window 范围内的采样，起点和终点精确到采样
+    pub fn frames_in<P: AsRef<Path>>(uri: &P, options: &HashMap<String, String>, window: TimeWindow) -> Result<Frames, Error> {
+        Frames::open(uri, options, window)
+    }
+
+    /// 设置只播放文件中的哪一段，需要在打开文件之前调用
+    pub fn set_window(&mut self, window: TimeWindow) {
+        self.window = window;
+    }
+
+    /// 设置打开进度回调，需要在打开文件之前调用
+    pub fn set_open_progress(&mut self, progress: Option<OpenProgress>) {
+        self.open_progress = progress;
+    }
+
+    /// 设置是否在解码线程中读取内嵌的封面，需要在打开文件之前调用
+    ///
+    /// 开启后打开文件时不复制封面图片，读取完成后更新 MediaInfo.cover 并发出 CoverReady 事件
+    pub fn set_lazy_cover(&mut self, lazy_cover: bool) {
+        self.lazy_cover = lazy_cover;
+    }
+
+    fn report_progress(&self, phase: OpenPhase) {
+        if let Some(progress) = self.open_progress.as_ref() {
+            progress(phase);
+        }
+    }
+
+    /// 设置用户提供的元数据读取器，需要在打开文件之前调用
+    pub fn set_metadata_extractors(&mut self, extractors: Vec<Arc<dyn MetadataExtractor>>) {
+        self.metadata_extractors = extractors;
+    }
+
+    /// 设置解码线程发出事件使用的事件总线，需要在打开文件之前调用
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = events;
+    }
+
+    /// 上一个播放源播放完毕后是否已经把输出交给了这个播放源
+    pub fn is_handed_over(&self) -> bool {
+        self.status.handed_over.load(Ordering::Relaxed)
+    }
+
+    fn ffmpeg_frame_to_slice(frame: &frame::Audio) -> Vec<AudioSample> {
+        let channels = frame.channels() as usize;
+        if frame.format() == format::Sample::F32(format::sample::Type::Packed) {
+            let pcm = unsafe {
+                std::slice::from_raw_parts((*frame.as_ptr()).data[0] as *const f32, frame.samples() * channels)
+            };
+            return pcm.chunks_exact(channels)
+                .map(AudioSample::from_slice)
+                .collect();
+        }
+
+        // 不经过重采样器时，在这里把其他采样格式直接转换为 32 位浮点
+        let read: fn(*const u8, usize) -> f32 = match frame.format() {
+            format::Sample::U8(_) => |data, index| (unsafe { *data.add(index) } as f32 - 128.) / 128.,
+            format::Sample::I16(_) => |data, index| unsafe { *(data as *const i16).add(index) } as f32 / 32768.,
+            format::Sample::I32(_) => |data, index| unsafe { *(data as *const i32).add(index) } as f32 / 2147483648.,
+            format::Sample::F32(_) => |data, index| unsafe { *(data as *const f32).add(index) },
+            format::Sample::F64(_) => |data, index| unsafe { *(data as *const f64).add(index) } as f32,
+            _ => return Vec::new(),
+        };
+        let planar = frame.is_planar();
+        let plane = |channel: usize| unsafe { *(*frame.as_ptr()).extended_data.add(channel) as *const u8 };
+        (0..frame.samples())
+            .map(|index| {
+                let mut audio_sample = AudioSample::silence(channels);
+                for (channel, sample) in audio_sample.as_mut_slice().iter_mut().enumerate() {
+                    *sample = if planar {
+                        read(plane(channel), index)
+                    } else {
+                        read(plane(0), index * channels + channel)
+                    };
+                }
+                audio_sample
+            })
+            .collect()
+    }
+
+    /// 是否需要重采样器，采样率和声道布局一致并且可以直接转换采样格式时跳过重采样器
+    ///
+    /// 变速滤镜只接受 32 位浮点交错格式，开启变速时其他格式仍然需要经过重采样器转换
+    fn needs_resampler(
+        input_format: format::Sample,
+        input_layout: ffmpeg::ChannelLayout,
+        input_rate: u32,
+        output_format: &OutputFormat,
+        tempo_active: bool,
+    ) -> bool {
+        let direct_format = match input_format {
+            format::Sample::F32(format::sample::Type::Packed) => true,
+            format::Sample::U8(_) | format::Sample::I16(_) | format::Sample::I32(_)
+                | format::Sample::F32(_) | format::Sample::F64(_) => !tempo_active,
+            _ => false,
+        };
+        !(direct_format && input_layout == output_format.channel_layout && input_rate == output_format.sample_rate)
+    }
+
+    fn clear_resampler_buffer(resampler: &mut SwrContext) {
+        loop {
+            let mut resampled = frame::Audio::empty();
+            if let Ok(delay) = resampler.flush(&mut resampled) {
+                if delay.is_none() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+        unsafe {
+            // 关闭重采样器来释放 FIFO 里不足以写入一帧的数据
+            // https://ffmpeg.org/doxygen/4.1/group__lswr.html#gaa4bf1048740dfc08d68aba9f1b4db22e
+            let resampler_ptr = resampler.as_mut_ptr();
+            ffmpeg_c_api::swr_close(resampler_ptr);
+            ffmpeg_c_api::swr_init(resampler_ptr);
+        }
+    }
+
+    fn blocking_write_buffer(
+        status: &Arc<FFmpegSourceStatus>,
+        chunk_size: usize,
+        dynamic_device_buffer_size: usize,
+        slice: &[AudioSample],
+        producer: &Mutex<Producer<AudioSample>>
+    ) {
+        // 先分块，避免缓冲区容量比帧小，产生死锁
+        let chunks = slice.chunks(chunk_size);
+        for chunk in chunks {
+            if !status.avaliable.load(Ordering::Relaxed) {
+                return;
+            }
+            
+            // 根据采样率动态调整缓冲区大小
+            // producer.remaining() < chunk.len() + producer.capacity() - dynamic_device_buffer_size
+
+            // 缓冲区满或者暂停则等待，等待期间不持有锁
+            let has_room = || {
+                let producer = producer.lock().unwrap();
+                producer.remaining() >= chunk.len() + producer.capacity() - dynamic_device_buffer_size
+            };
+            let wait_start = Instant::now();
+            while !has_room() || !status.playing.load(Ordering::Relaxed) {
+                if !status.avaliable.load(Ordering::Relaxed) {
+                    return;
+                }
+                // 系统需要丢弃未写入缓冲区的帧数据，日志在丢弃结束时记录一次
+                if status.dropping_frames.load(Ordering::Relaxed) {
+                    return;
+                }
+                // 播放中缓冲区已经写满，预先缓冲到此为止
+                if status.playing.load(Ordering::Relaxed) {
+                    status.prebuffering.store(false, Ordering::Relaxed);
+                }
+                // 输出回调消费数据后立即唤醒，缓冲区目标很小时也能及时补充
+                status.buffer_space.wait(WRITE_WAIT_INTERVAL);
+            }
+            status.counters.count_write_wait(wait_start.elapsed());
+            // 向缓冲区写入数据，如果需要丢弃帧数据则直接跳出循环
+            if !status.dropping_frames.load(Ordering::Relaxed) {
+                // 只在写入一块数据时持有锁
+                let mut producer = producer.lock().unwrap();
+                let buffered_before = (producer.capacity() - producer.remaining()) as u64;
+                let written = producer.push_slice(chunk);
+                status.written_frames.fetch_add(written as u64, Ordering::Relaxed);
+                let buffered = (producer.capacity() - producer.remaining()) as u64;
+                status.counters.count_buffer_level(
+                    buffered_before,
+                    buffered,
+                    producer.capacity() as u64,
+                    status.prebuffering.load(Ordering::Relaxed));
+                if buffered >= status.prebuffer_frames.load(Ordering::Relaxed) {
+                    status.prebuffering.store(false, Ordering::Relaxed);
+                }
+                if !status.prebuffering.load(Ordering::Relaxed) {
+                    status.report_ready();
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// 按照当前播放速度更新变速滤镜，原速时不使用滤镜
+    fn update_tempo_filter(
+        status: &Arc<FFmpegSourceStatus>,
+        tempo: &mut Option<TempoFilter>,
+        output_format: &OutputFormat,
+    ) -> Result<(), ffmpeg::Error> {
+        let speed = f32::from_bits(status.speed.load(Ordering::Relaxed));
+        if speed == 1. {
+            *tempo = None;
+        } else if tempo.as_ref().map(|tempo| tempo.speed() != speed).unwrap_or(true) {
+            *tempo = Some(TempoFilter::new(speed, output_format.sample_rate, output_format.channel_layout)?);
+        }
+        Ok(())
+    }
+
+    /// 把设备格式的一帧转换为采样，并应用 ReplayGain 增益
+    fn frame_to_samples(status: &Arc<FFmpegSourceStatus>, frame: &frame::Audio) -> Vec<AudioSample> {
+        let gain = f32::from_bits(status.gain.load(Ordering::Relaxed));
+        let mut samples = FFmpegSource::ffmpeg_frame_to_slice(frame);
+        if gain != 1. {
+            for audio_sample in samples.iter_mut() {
+                for sample in audio_sample.as_mut_slice() {
+                    *sample *= gain;
+                }
+            }
+        }
+        samples
+    }
+
+    /// 把设备格式的一帧（经过变速滤镜后）写入缓冲区
+    fn write_frame(
+        status: &Arc<FFmpegSourceStatus>,
+        chunk_size: usize,
+        dynamic_device_buffer_size: usize,
+        frame: &frame::Audio,
+        producer: &Mutex<Producer<AudioSample>>,
+        tempo: &mut Option<TempoFilter>,
+    ) -> Result<(), ffmpeg::Error> {
+        let to_samples = |frame: &frame::Audio| FFmpegSource::frame_to_samples(status, frame);
+        match tempo.as_mut() {
+            Some(tempo) => {
+                tempo.process(frame, |filtered| {
+                    FFmpegSource::blocking_write_buffer(
+                        status,
+                        chunk_size,
+                        dynamic_device_buffer_size,
+                        to_samples(filtered).as_slice(),
+                        producer);
+                })?;
+            },
+            None => {
+                FFmpegSource::blocking_write_buffer(
+                    status,
+                    chunk_size,
+                    dynamic_device_buffer_size,
+                    to_samples(frame).as_slice(),
+                    producer);
+            },
+        }
+        Ok(())
+    }
+
+    /// 把解码器输出的一帧转换为设备格式（经过变速滤镜）写入缓冲区
+    fn frame_to_buffer(
+        status: &Arc<FFmpegSourceStatus>,
+        chunck_size: &Arc<Mutex<Cell<usize>>>,
+        dynamic_device_buffer_size: &Arc<Mutex<Cell<usize>>>,
+        decoded: &frame::Audio,
+        producer: &Mutex<Producer<AudioSample>>,
+        resampler: &mut Option<SwrContext>,
+        output_format: &OutputFormat,
+        tempo: &mut Option<TempoFilter>,
+    ) -> Result<(), ffmpeg::Error> {
+        let chunk_size = chunck_size.lock().unwrap().get();
+        let dynamic_device_buffer_size = dynamic_device_buffer_size.lock().unwrap().get();
+        FFmpegSource::update_tempo_filter(status, tempo, output_format)?;
+
+        FFmpegSource::update_resampler(resampler, decoded, output_format, tempo.is_some())?;
+        let resampler = match resampler.as_mut() {
+            Some(resampler) => resampler,
+            None => {
+                // 采样率和声道布局已经和设备一致，转换采样格式后直接写入
+                return FFmpegSource::write_frame(status, chunk_size, dynamic_device_buffer_size, decoded, producer, tempo);
+            },
+        };
+
+        let mut resampled = frame::Audio::empty();
+        let mut delay = resampler.run(decoded, &mut resampled)?;
+        loop {
+            if !status.avaliable.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            status.counters.resampled_samples.fetch_add(resampled.samples() as u64, Ordering::Relaxed);
+            // 将重采样（和变速）后的将音频数据写入对应的缓冲区中
+            FFmpegSource::write_frame(status, chunk_size, dynamic_device_buffer_size, &resampled, producer, tempo)?;
+            // 输出的大小装不下的部分会在重采样器里缓存，需要循环读取到缓存为空
+            if delay == None {
+                break;
+            }
+            delay = resampler.flush(&mut resampled)?;
+        }
+        Ok(())
+    }
+
+    /// 复制一帧中 [start, end) 范围内的采样
+    fn trim_frame(frame: &frame::Audio, start: usize, end: usize) -> frame::Audio {
+        let channels = frame.channels() as usize;
+        let channel_layout = if frame.channel_layout().is_empty() {
+            ffmpeg::ChannelLayout::default(channels as i32)
+        } else {
+            frame.channel_layout()
+        };
+        let mut trimmed = frame::Audio::new(frame.format(), end - start, channel_layout);
+        trimmed.set_channels(frame.channels());
+        trimmed.set_rate(frame.rate());
+        let (planes, stride) = if frame.is_planar() {
+            (channels, frame.format().bytes())
+        } else {
+            (1, frame.format().bytes() * channels)
+        };
+        for plane in 0..planes {
+            unsafe {
+                let source = (*(*frame.as_ptr()).extended_data.add(plane)).add(start * stride);
+                let target = *(*trimmed.as_mut_ptr()).extended_data.add(plane);
+                std::ptr::copy_nonoverlapping(source, target, (end - start) * stride);
+            }
+        }
+        trimmed
+    }
+
+    /// 按时长写入静音，用于替代无法解码的数据包
+    fn write_silence(
+        status: &Arc<FFmpegSourceStatus>,
+        chunck_size: &Arc<Mutex<Cell<usize>>>,
+        dynamic_device_buffer_size: &Arc<Mutex<Cell<usize>>>,
+        seconds: f64,
+        producer: &Mutex<Producer<AudioSample>>,
+        output_format: &OutputFormat,
+    ) {
+        // 变速播放时输出的时长按速度缩放
+        let speed = f32::from_bits(status.speed.load(Ordering::Relaxed)).max(f32::EPSILON) as f64;
+        let frames = (seconds * output_format.sample_rate as f64 / speed) as usize;
+        if frames == 0 {
+            return;
+        }
+        let silence = vec![AudioSample::silence(output_format.channel_layout.channels() as usize); frames];
+        FFmpegSource::blocking_write_buffer(
+            status,
+            chunck_size.lock().unwrap().get(),
+            dynamic_device_buffer_size.lock().unwrap().get(),
+            silence.as_slice(),
+            producer);
+    }
+
+    /// 解码出的帧和重采样器的输入格式不一致时（例如链式 Ogg 切换到下一段）重新创建重采样器，
+    /// 开关变速后重新判断是否可以跳过重采样器
+    fn update_resampler(
+        resampler: &mut Option<SwrContext>,
+        frame: &frame::Audio,
+        output_format: &OutputFormat,
+        tempo_active: bool,
+    ) -> Result<(), ffmpeg::Error> {
+        let channel_layout = if frame.channel_layout().is_empty()
+            || frame.channel_layout().channels() != i32::from(frame.channels()) {
+            ffmpeg::ChannelLayout::default(frame.channels().into())
+        } else {
+            frame.channel_layout()
+        };
+        if !FFmpegSource::needs_resampler(frame.format(), channel_layout, frame.rate(), output_format, tempo_active) {
+            *resampler = None;
+            return Ok(());
+        }
+        let input = (frame.format(), channel_layout, frame.rate());
+        if let Some(current) = resampler.as_ref() {
+            if (current.input().format, current.input().channel_layout, current.input().rate) == input {
+                return Ok(());
+            }
+        }
+        *resampler = Some(SwrContext::get(
+            input.0,
+            input.1,
+            input.2,
+            format::Sample::F32(format::sample::Type::Packed),
+            output_format.channel_layout,
+            output_format.sample_rate,
+        )?);
+        Ok(())
+    }
+
+    /// 链式流切换到下一段后，更新媒体信息中的标签和格式，并通知界面
+    fn update_chained_media_info(
+        input_ctx: &MediaInput,
+        stream: &format::stream::Stream,
+        decoder: &decoder::Audio,
+        media_info: &Mutex<Arc<MediaInfo>>,
+        events: &EventBus,
+    ) {
+        let mut current = media_info.lock().unwrap();
+        let mut updated = MediaInfo::clone(&current);
+        updated.title = tag_from_input_context(input_ctx, "title");
+        updated.artist = tag_from_input_context(input_ctx, "artist");
+        updated.album = tag_from_input_context(input_ctx, "album");
+        read_stream_info(input_ctx, stream, decoder, &mut updated);
+        let title_changed = updated.title != current.title;
+        let updated = Arc::new(updated);
+        *current = updated.clone();
+        drop(current);
+
+        events.emit(PlayerEvent::TrackChanged(TechnicalInfo::from(updated.as_ref())));
+        if let Some(title) = updated.title.clone().filter(|_| title_changed) {
+            events.emit(PlayerEvent::StreamTitleChanged(title));
+        }
+    }
+
+    /// 播放到结尾或者链式流切换到下一段时取出重采样器和变速滤镜中缓存的数据，避免丢失最后一小段音频，
+    /// 解码器中剩余的帧已经由 Frames 取出
+    fn flush_to_buffer(
+        status: &Arc<FFmpegSourceStatus>,
+        chunck_size: &Arc<Mutex<Cell<usize>>>,
+        dynamic_device_buffer_size: &Arc<Mutex<Cell<usize>>>,
+        producer: &Mutex<Producer<AudioSample>>,
+        resampler: &mut Option<SwrContext>,
+        tempo: &mut Option<TempoFilter>,
+    ) -> Result<(), ffmpeg::Error> {
+        let chunk_size = chunck_size.lock().unwrap().get();
+        let dynamic_device_buffer_size = dynamic_device_buffer_size.lock().unwrap().get();
+        if let Some(resampler) = resampler.as_mut() {
+            // 没有输入时重采样器输出内部缓存的全部数据，直到输出为空
+            loop {
+                if !status.avaliable.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                let mut resampled = frame::Audio::empty();
+                resampler.flush(&mut resampled)?;
+                if resampled.samples() == 0 {
+                    break;
+                }
+                status.counters.resampled_samples.fetch_add(resampled.samples() as u64, Ordering::Relaxed);
+                FFmpegSource::write_frame(status, chunk_size, dynamic_device_buffer_size, &resampled, producer, tempo)?;
+            }
+        }
+        // 结束后的变速滤镜不能再输入，重新播放时会重新创建
+        if let Some(mut filter) = tempo.take() {
+            filter.flush(|filtered| {
+                FFmpegSource::blocking_write_buffer(
+                    status,
+                    chunk_size,
+                    dynamic_device_buffer_size,
+                    FFmpegSource::frame_to_samples(status, filtered).as_slice(),
+                    producer);
+            })?;
+        }
+        Ok(())
+    }
+
+    /// 文件中所有附加图片流的图片，按流序号记录
+    fn attached_pictures(input_ctx: &MediaInput) -> HashMap<usize, Vec<u8>> {
+        input_ctx.streams()
+            .filter(|stream| stream.disposition().contains(format::stream::Disposition::ATTACHED_PIC))
+            .map(|stream| (stream.index(), attached_picture(&stream)))
+            .collect()
+    }
+
+    /// 打开时跳过的封面读取完成，使用序号最小的附加图片流，用户的读取器已经提供封面时不替换
+    fn publish_lazy_cover(pictures: &HashMap<usize, Vec<u8>>, media_info: &Mutex<Arc<MediaInfo>>, events: &EventBus) {
+        let picture = match pictures.iter().filter(|(_, picture)| !picture.is_empty()).min_by_key(|(index, _)| **index) {
+            Some((_, picture)) => picture,
+            None => return,
+        };
+        let cover: Arc<[u8]> = Arc::from(picture.as_slice());
+        let mut media_info = media_info.lock().unwrap();
+        if media_info.cover.is_some() {
+            return;
+        }
+        let mut updated = MediaInfo::clone(&media_info);
+        updated.replace_cover(Some(cover.clone()));
+        let generation = updated.cover_generation;
+        *media_info = Arc::new(updated);
+        drop(media_info);
+        events.emit(PlayerEvent::CoverReady(cover.clone()));
+        events.emit(PlayerEvent::ArtworkChanged { generation, cover: Some(cover) });
+    }
+
+    /// 附加图片流中出现了新的图片（链式流的下一段或者数据流中途推送的封面）时替换封面并发出事件
+    ///
+    /// pictures 记录每个附加图片流已知的图片，打开时已有的图片不会触发事件，
+    /// 避免定位后重新读到的图片覆盖用户的读取器提供的封面
+    fn update_cover(
+        stream_index: usize,
+        picture: &[u8],
+        pictures: &mut HashMap<usize, Vec<u8>>,
+        media_info: &Mutex<Arc<MediaInfo>>,
+        events: &EventBus,
+    ) {
+        if picture.is_empty() || pictures.get(&stream_index).map(Vec::as_slice) == Some(picture) {
+            return;
+        }
+        pictures.insert(stream_index, picture.to_vec());
+        let cover: Arc<[u8]> = Arc::from(picture);
+        let mut media_info = media_info.lock().unwrap();
+        let mut updated = MediaInfo::clone(&media_info);
+        updated.replace_cover(Some(cover.clone()));
+        let generation = updated.cover_generation;
+        *media_info = Arc::new(updated);
+        drop(media_info);
+        events.emit(PlayerEvent::CoverChanged(cover.clone()));
+        events.emit(PlayerEvent::ArtworkChanged { generation, cover: Some(cover) });
+    }
+
+    /// 检查网络电台 ICY 元数据中的曲目标题，变化时更新媒体信息并发出事件
+    fn update_stream_title(input_ctx: &MediaInput, media_info: &Mutex<Arc<MediaInfo>>, events: &EventBus) {
+        let title = match input_ctx.icy_metadata_packet().and_then(|packet| icy::parse_stream_title(&packet)) {
+            Some(title) => title,
+            None => return,
+        };
+        let mut media_info = media_info.lock().unwrap();
+        if media_info.title.as_deref() == Some(title.as_str()) {
+            return;
+        }
+        let mut updated = MediaInfo::clone(&media_info);
+        updated.title = Some(title.clone());
+        *media_info = Arc::new(updated);
+        drop(media_info);
+        events.emit(PlayerEvent::StreamTitleChanged(title));
+    }
+
+    pub fn open<P: AsRef<Path>>(&mut self, uri: &P, options: &HashMap<String, String>, device_sample_format: &DeviceSampleFormat) -> Result<(), Error> {
+        self.report_progress(OpenPhase::Probing);
+        let input_ctx = open_media_file(uri, options)?;
+        self.open_input(input_ctx, Some(uri.as_ref()), device_sample_format)
+    }
+
+    /// 从读取器打开，读取器被视为不可定位的流，总长度未知
+    pub fn open_reader(&mut self, reader: Box<dyn Read + Send>, device_sample_format: &DeviceSampleFormat) -> Result<(), Error> {
+        self.report_progress(OpenPhase::Probing);
+        let input_ctx = MediaInput::open_reader(reader).context(OpenMediaFileWithFFmpegSnafu {
+            message: "the stream could not be opened, either because it cannot be read or the format is not supported".to_string(),
+        })?;
+        self.open_input(input_ctx, None, device_sample_format)
+    }
+
+    /// 从可以定位的读取器打开，例如压缩包中的文件
+    pub fn open_seekable_reader(&mut self, reader: Box<dyn SeekableRead>, device_sample_format: &DeviceSampleFormat) -> Result<(), Error> {
+        self.report_progress(OpenPhase::Probing);
+        let input_ctx = open_seekable_media(reader)?;
+        self.open_input(input_ctx, None, device_sample_format)
+    }
+
+    fn open_input(&mut self, mut input_ctx: MediaInput, path: Option<&Path>, device_sample_format: &DeviceSampleFormat) -> Result<(), Error> {
+        // 还没有开始播放时，播放位置应当为 0
+        self.status.current_time.lock().unwrap().set(0);
+        self.status.anchor_position(0);
+        self.status.set_end(false);
+        self.status.counters.reset();
+        self.status.seekable.store(input_ctx.is_seekable(), Ordering::Relaxed);
+
+        self.report_progress(OpenPhase::ReadingMetadata);
+        let mut media_info = MediaInfo::default();
+        read_metadata(&input_ctx, path, !self.lazy_cover, &self.metadata_extractors, &mut media_info);
+
+        self.report_progress(OpenPhase::CreatingDecoder);
+        let stream = default_audio_stream(&input_ctx).context(NoAudioStreamSnafu)?;
+
+        let stream_index = stream.index();
+        let decoder = open_decoder(&stream)?;
+
+        let device_channels = device_sample_format.channel_count;
+        let device_sample_rate = device_sample_format.sample_rate;
+
+        read_stream_info(&input_ctx, &stream, &decoder, &mut media_info);
+        self.status.current_bitrate.store(media_info.bitrate, Ordering::Relaxed);
+        discard_other_streams(&mut input_ctx, stream_index);
+
+        // 网络电台是没有总长度的直播流，不能定位，标题来自 ICY 元数据
+        let network = input_ctx.network_monitor();
+        if network.is_some() && media_info.duration < 0 {
+            self.status.seekable.store(false, Ordering::Relaxed);
+        }
+        if network.is_some() {
+            if let Some(title) = input_ctx.icy_metadata_packet().and_then(|packet| icy::parse_stream_title(&packet)) {
+                media_info.title = Some(title);
+            }
+        }
+        // 新打开的文件总是使用新的封面版本，即使和上一个文件的封面相同
+        media_info.cover_generation = next_cover_generation();
+        *self.media_info.lock().unwrap() = Arc::new(media_info);
+        self.network = network.clone();
+        if let Some(network) = network.as_ref() {
+            let events = self.events.clone();
+            network.set_buffering_callback(Some(Box::new(move |buffering| {
+                events.emit(PlayerEvent::Buffering(buffering));
+            })));
+        }
+
+        // 检查解码器配置，避免异常文件在解码线程中崩溃
+        ensure!(decoder.channels() > 0, OpenMediaFileSnafu {
+            message: "the audio stream has no channels".to_string(),
+        });
+        ensure!(decoder.rate() > 0, OpenMediaFileSnafu {
+            message: "the audio stream has an invalid sample rate".to_string(),
+        });
+        ensure!(decoder.format() != format::Sample::None, OpenMediaFileSnafu {
+            message: "the audio stream has an unknown sample format".to_string(),
+        });
+        ensure!(device_channels > 0 && device_sample_rate > 0, OpenMediaFileSnafu {
+            message: "the audio device format is invalid".to_string(),
+        });
+
+        ensure!(!decoder.channel_layout().is_empty(), OpenMediaFileSnafu {
+            message: format!("unsupported channel count: {}", decoder.channels()),
+        });
+
+        // 输出格式 (一律使用32位浮点)
+        let mut output_format = OutputFormat {
+            sample_rate: device_sample_rate,
+            channel_layout: output_channel_layout(device_channels)?,
+        };
+
+        // 检查需要进行的转换，格式、采样率和声道布局都一致时不使用重采样器
+        self.processing.clear();
+        if decoder.format() != format::Sample::F32(format::sample::Type::Packed) {
+            self.processing.push(PROCESSING_FORMAT_CONVERT);
+        }
+        if decoder.rate() != device_sample_rate {
+            self.processing.push(PROCESSING_RESAMPLE);
+        }
+        match decoder.channels().cmp(&device_channels) {
+            std::cmp::Ordering::Greater => self.processing.push(PROCESSING_DOWNMIX),
+            std::cmp::Ordering::Less => self.processing.push(PROCESSING_UPMIX),
+            std::cmp::Ordering::Equal if decoder.channel_layout() != output_format.channel_layout => {
+                self.processing.push(PROCESSING_CHANNEL_REMAP);
+            },
+            std::cmp::Ordering::Equal => {},
+        }
+
+        // 创建重采样器，转换音频数据为音频设备支持的格式，只需要转换采样格式时直接转换
+        let tempo_active = f32::from_bits(self.status.speed.load(Ordering::Relaxed)) != 1.;
+        let needs_resampler = FFmpegSource::needs_resampler(
+            decoder.format(),
+            decoder.channel_layout(),
+            decoder.rate(),
+            &output_format,
+            tempo_active);
+        let mut resampler = if !needs_resampler {
+            None
+        } else {
+            Some(SwrContext::get(
+                // 输入格式
+                decoder.format(),
+                decoder.channel_layout(),
+                decoder.rate(),
+                // 输出格式
+                format::Sample::F32(format::sample::Type::Packed),
+                output_format.channel_layout,
+                output_format.sample_rate,
+            ).context(OpenMediaFileWithFFmpegSnafu {
+                message: "failed to create resampler".to_string(),
+            })?)
+        };
+
+        // 可定位的输入先找到第一个音频数据包，确认有可以播放的数据，再回到开头（或者时间窗口的起点）
+        // 不可定位的输入通过解码并丢弃起点之前的数据到达起点
+        let window = self.window;
+        let start_ms = stream_start_ms(&input_ctx.stream(stream_index).unwrap());
+        // 定位目标加上音频流的起始时间，定位到 0 时才能回到真正的开头
+        let start_offset = start_ms.rescale((1, 1000), rescale::TIME_BASE);
+        let window_start = window.start_ms.rescale((1, 1000), rescale::TIME_BASE) + start_offset;
+        if input_ctx.is_seekable() {
+            let has_audio_packet = input_ctx.packets().any(|(stream, _)| stream.index() == stream_index);
+            ensure!(has_audio_packet, OpenMediaFileSnafu {
+                message: "the file does not contain any audio data".to_string(),
+            });
+            input_ctx.seek(window_start, ..window_start).context(OpenMediaFileWithFFmpegSnafu {
+                message: "failed to rewind the file".to_string(),
+            })?;
+        }
+
+        // 上一次打开时没有处理的定位目标不属于这个文件
+        self.status.seek_target.take();
+
+        let producer = self.buffer_producer.clone();
+        let consumer = self.buffer_consumer.clone();
+
+        let status = self.status.clone();
+        let buffer_chunk_size = self.buffer_chunk_size.clone();
+        let dynamic_device_buffer_size = self.dynamic_device_buffer_size.clone();
+        let media_info = self.media_info.clone();
+        let events = self.events.clone();
+        let lazy_cover = self.lazy_cover;
+        let pictures = if lazy_cover { HashMap::new() } else { FFmpegSource::attached_pictures(&input_ctx) };
+        let mut observer = PlaybackObserver {
+            status: status.clone(),
+            network: network.clone(),
+            media_info: media_info.clone(),
+            events: events.clone(),
+            start_ms,
+            bitrate_meter: BitrateMeter::default(),
+            pictures,
+            last_icy_check: Instant::now(),
+        };
+        let mut frames = Frames::new(input_ctx, decoder, stream_index, window);
+        // 先报告 Prebuffering，解码线程写入数据后才会报告 Ready
+        self.report_progress(OpenPhase::Prebuffering);
+        *self.status.open_progress.lock().unwrap() = self.open_progress.clone();
+        self.decode_thread = Some(
+            DecodeThread::spawn(move || {
+                let mut tempo: Option<TempoFilter> = None;
+                // 播放结束后收到的定位请求，重新进入解码循环时处理
+                let mut pending_seek: Option<i64> = None;
+                // 上一次执行定位的时间，用于定位防抖
+                let mut last_seek: Option<Instant> = None;
+                if lazy_cover {
+                    observer.pictures = FFmpegSource::attached_pictures(frames.input());
+                    FFmpegSource::publish_lazy_cover(&observer.pictures, &media_info, &events);
+                }
+                loop {
+                    if !status.avaliable.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // 可以实时定位的解码逻辑
+                    let mut seek: Option<i64> = pending_seek.take();
+                    // 错误处理方式为 Abort 时遇到的解码错误
+                    let mut decode_error: Option<Error> = None;
+                    loop {
+                        if !status.avaliable.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if let Some(seek_time) = seek {
+                            // 定位防抖：距离上一次定位不到防抖时间时等到时间结束，期间的新目标覆盖旧目标，
+                            // 单独的一次定位立即执行
+                            let debounce = Duration::from_millis(status.seek_debounce_ms.load(Ordering::Relaxed) as u64);
+                            let deadline = last_seek.map(|last_seek| last_seek + debounce);
+                            let seek_time = status.seek_target.settle(seek_time, deadline, &status.avaliable);
+                            last_seek = Some(Instant::now());
+
+                            // 更改输入的位置，然后清除定位信息
+                            if let Err(err) = frames.seek(seek_time.rescale(rescale::TIME_BASE, (1, 1000))) {
+                                log::warn!("failed to seek: {}", err);
+                            } else {
+                                observer.bitrate_meter.reset();
+                                if let Some(resampler) = resampler.as_mut() {
+                                    FFmpegSource::clear_resampler_buffer(resampler);
+                                }
+                                // 丢弃变速滤镜里缓存的数据
+                                tempo = None;
+                                // TODO: 解耦合
+                                AudioDevice::clear_buffer(&consumer);
+                                status.anchor_position(seek_time.rescale(rescale::TIME_BASE, (1, 1_000_000)));
+                                // 定位之后的音频也先预先缓冲再播放
+                                if status.prebuffer_frames.load(Ordering::Relaxed) > 0 {
+                                    status.prebuffering.store(true, Ordering::Relaxed);
+                                }
+                                status.counters.seeks.fetch_add(1, Ordering::Relaxed);
+                            }
+
+                            seek = None;
+                        }
+                        if let Some(network) = network.as_ref() {
+                            network.mark_read();
+                        }
+                        loop {
+                            if !status.avaliable.load(Ordering::Relaxed) {
+                                break;
+                            }
+                            // 已经没有音频帧了，关闭丢弃帧模式。每次定位或者清空缓冲区只记录一次，不按数据块重复输出
+                            if status.dropping_frames.swap(false, Ordering::Relaxed) {
+                                status.counters.buffer_discards.fetch_add(1, Ordering::Relaxed);
+                                log::debug!("dropped audio frames that are no longer needed");
+                            }
+
+                            if let Some(device_format) = status.output_format.lock().unwrap().take() {
+                                let old_sample_rate = output_format.sample_rate;
+                                match output_channel_layout(device_format.channel_count) {
+                                    Ok(channel_layout) => {
+                                        output_format = OutputFormat { sample_rate: device_format.sample_rate, channel_layout };
+                                        // 下一帧按新的格式重新创建重采样器和变速滤镜
+                                        resampler = None;
+                                        tempo = None;
+                                        // 缓冲区中是按原来的格式输出的数据，不能交给新的设备播放。
+                                        // 可以定位时从实际听到的位置按新的格式重新解码，否则直接丢弃
+                                        if status.seekable.load(Ordering::Relaxed) {
+                                            let buffered_frames = consumer.lock().unwrap().len() as f64;
+                                            let speed = f32::from_bits(status.speed.load(Ordering::Relaxed)) as f64;
+                                            let buffered_ms = (buffered_frames * 1000. / old_sample_rate as f64 * speed) as i64;
+                                            let heard_ms = (status.current_time.lock().unwrap().get() - buffered_ms).max(window.start_ms);
+                                            seek = Some(heard_ms.rescale((1, 1000), rescale::TIME_BASE));
+                                            break;
+                                        }
+                                        AudioDevice::clear_buffer(&consumer);
+                                    },
+                                    Err(err) => log::warn!("failed to switch to the new output format: {}", err),
+                                }
+                            }
+
+                            if let Some(seek_time) = status.seek_target.take() {
+                                // 如果接收到定位请求，则跳出循环
+                                seek = Some(seek_time);
+                                break;
+                            };
+
+                            // 阻塞暂停和停止状态（避免清除帧数据的过程中继续解码数据）
+                            while !status.playing.load(Ordering::Relaxed) {
+                                if !status.avaliable.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                status.buffer_space.wait_until(IDLE_WAIT_INTERVAL, || {
+                                    status.playing.load(Ordering::Relaxed) || !status.avaliable.load(Ordering::Relaxed)
+                                });
+                            }
+
+                            // A-B 循环的范围可能随时改变，每一帧重新读取，
+                            // A-B 循环的位置相对于时间窗口，并且一定在窗口之内
+                            let ab_loop = status.ab_loop.lock().unwrap()
+                                .map(|(a, b)| (window.to_absolute(a), window.to_absolute(b)));
+                            match ab_loop {
+                                Some((a, b)) => frames.set_range(a, Some(b)),
+                                None => frames.set_range(window.start_ms, window.end_ms),
+                            }
+
+                            let written = match frames.next_frame_with(&mut observer) {
+                                Some(Ok(decoded)) => {
+                                    status.counters.frames_decoded.fetch_add(1, Ordering::Relaxed);
+                                    if frames.take_chained() {
+                                        // 链式流的新一段可能使用不同的格式，先写入上一段在重采样器和变速滤镜中剩余的数据
+                                        FFmpegSource::flush_to_buffer(
+                                            &status,
+                                            &buffer_chunk_size,
+                                            &dynamic_device_buffer_size,
+                                            &producer,
+                                            &mut resampler,
+                                            &mut tempo)
+                                            .unwrap_or_else(|err| log::warn!("failed to drain the resampler: {}", err));
+                                        let input_ctx = frames.input();
+                                        let stream = input_ctx.stream(stream_index).unwrap();
+                                        FFmpegSource::update_chained_media_info(input_ctx, &stream, frames.decoder(), &media_info, &events);
+                                        for (index, picture) in FFmpegSource::attached_pictures(input_ctx) {
+                                            FFmpegSource::update_cover(index, &picture, &mut observer.pictures, &media_info, &events);
+                                        }
+                                    }
+                                    FFmpegSource::frame_to_buffer(
+                                        &status,
+                                        &buffer_chunk_size,
+                                        &dynamic_device_buffer_size,
+                                        &decoded,
+                                        &producer,
+                                        &mut resampler,
+                                        &output_format,
+                                        &mut tempo)
+                                        .context(DecodeSnafu {
+                                            message: "the decoded audio could not be converted".to_string(),
+                                        })
+                                },
+                                Some(Err(err)) => Err(err),
+                                None => {
+                                    if let Some((a, _)) = ab_loop.filter(|_| frames.reached_range_end()) {
+                                        // 解码到 B 点后回到 A 点之前的关键帧，A 点之前的采样在解码时裁掉，
+                                        // 输出缓冲区不清空，B 点之前的音频会完整播放
+                                        match frames.seek(a) {
+                                            Ok(()) => {
+                                                observer.bitrate_meter.reset();
+                                                continue;
+                                            },
+                                            Err(err) => log::warn!("failed to loop back to the A point: {}", err),
+                                        }
+                                    }
+                                    // 读到文件结尾，或者到达时间窗口的终点，和读到文件结尾一样结束
+                                    break;
+                                },
+                            };
+                            if let Err(err) = written {
+                                if !status.avaliable.load(Ordering::Relaxed) || status.source_lost.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                match ErrorResilience::from_u8(status.error_resilience.load(Ordering::Relaxed)) {
+                                    ErrorResilience::Skip => log::warn!("skipped an undecodable packet: {}", err),
+                                    ErrorResilience::Conceal => {
+                                        log::warn!("concealed an undecodable packet: {}", err);
+                                        FFmpegSource::write_silence(
+                                            &status,
+                                            &buffer_chunk_size,
+                                            &dynamic_device_buffer_size,
+                                            frames.last_packet_seconds(),
+                                            &producer,
+                                            &output_format);
+                                    },
+                                    ErrorResilience::Abort => {
+                                        decode_error = Some(err);
+                                        break;
+                                    },
+                                }
+                            }
+                        }
+                        if seek == None {
+                            // 如果没有定位信息，表示正常播放结束，解码器中剩余的帧已经写入，再写入重采样器中剩余的数据
+                            if status.avaliable.load(Ordering::Relaxed)
+                                && !status.source_lost.load(Ordering::Relaxed)
+                                && decode_error.is_none() {
+                                if status.seamless_loop.load(Ordering::Relaxed) && status.seekable.load(Ordering::Relaxed) {
+                                    // 无缝循环：保留重采样器和变速滤镜的状态，输出缓冲区也不清空，
+                                    // 立即从头继续解码，开头的数据紧接着结尾写入
+                                    match frames.seek(window.start_ms) {
+                                        Ok(()) => {
+                                            observer.bitrate_meter.reset();
+                                            // 每一遍播放完都报告结束，解码线程领先输出的时长和输出缓冲区的长度相同
+                                            events.emit(PlayerEvent::EndOfStream);
+                                            continue;
+                                        },
+                                        Err(err) => log::warn!("failed to loop back to the beginning: {}", err),
+                                    }
+                                }
+                                FFmpegSource::flush_to_buffer(
+                                    &status,
+                                    &buffer_chunk_size,
+                                    &dynamic_device_buffer_size,
+                                    &producer,
+                                    &mut resampler,
+                                    &mut tempo)
+                                    .unwrap_or_else(|err| log::warn!("failed to drain the resampler: {}", err));
+                            }
+                            break;
+                        }
+                    }
+
+                    if let Some(err) = decode_error {
+                        // 解码出错时停止播放，保留当前位置，等待关闭或者定位到其他位置
+                        log::error!("stopped at an undecodable packet: {}", err);
+                        status.playing.store(false, Ordering::Relaxed);
+                        let position_ms = window.to_relative(status.current_time.lock().unwrap().get());
+                        // 报告 FFmpeg 的错误信息，比外层的说明更具体
+                        let message = std::error::Error::source(&err).map_or_else(|| err.to_string(), ToString::to_string);
+                        events.emit(PlayerEvent::DecodeError { position_ms, message });
+                        loop {
+                            if !status.avaliable.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            if let Some(seek_time) = status.seek_target.take() {
+                                pending_seek = Some(seek_time);
+                                break;
+                            }
+                            status.buffer_space.wait_until(IDLE_WAIT_INTERVAL, || {
+                                !status.avaliable.load(Ordering::Relaxed) || status.seek_target.is_pending()
+                            });
+                        }
+                        continue;
+                    }
+
+                    if status.source_lost.load(Ordering::Relaxed) {
+                        // 文件丢失时停止播放，保留当前位置，等待关闭或者重新打开
+                        status.playing.store(false, Ordering::Relaxed);
+                        let position_ms = window.to_relative(status.current_time.lock().unwrap().get());
+                        events.emit(PlayerEvent::SourceLost { position_ms });
+                        while status.avaliable.load(Ordering::Relaxed) {
+                            status.buffer_space.wait_until(IDLE_WAIT_INTERVAL, || !status.avaliable.load(Ordering::Relaxed));
+                        }
+                        return;
+                    }
+
+                    // 播放完毕
+                    let end_behavior = EndBehavior::from_u8(status.end_behavior.load(Ordering::Relaxed));
+                    status.prebuffering.store(false, Ordering::Relaxed);
+                    // 很短的文件在预先缓冲完成之前就已经解码完毕
+                    status.report_ready();
+                    if end_behavior != EndBehavior::Handover {
+                        // 等输出回调播放完缓冲区中剩余的音频，再停止、回到开头和报告结束，否则最后一段会被截掉。
+                        // 交给下一首时不等待，下一首紧接着写入同一个输出缓冲区
+                        status.draining.store(true, Ordering::Relaxed);
+                        let drained = || consumer.lock().unwrap().is_empty();
+                        while status.avaliable.load(Ordering::Relaxed) && !status.seek_target.is_pending() && !drained() {
+                            status.buffer_space.wait_until(IDLE_WAIT_INTERVAL, || {
+                                !status.avaliable.load(Ordering::Relaxed) || status.seek_target.is_pending() || drained()
+                            });
+                        }
+                        status.draining.store(false, Ordering::Relaxed);
+                        if !status.avaliable.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        // 播放完之前定位到了其他位置，从新的位置继续解码
+                        if let Some(seek_time) = status.seek_target.take() {
+                            pending_seek = Some(seek_time);
+                            continue;
+                        }
+                    }
+                    status.playing.store(false, Ordering::Relaxed);
+                    if end_behavior == EndBehavior::RewindAndWait {
+                        status.current_time.lock().unwrap().set(window.start_ms);
+                        status.anchor_position(window.start_ms * 1000);
+                    }
+                    status.set_end(true);
+                    if end_behavior == EndBehavior::Handover {
+                        // 预先打开的下一首立即开始写入，首尾之间没有间隙
+                        if let Some(successor) = status.successor.lock().unwrap().take() {
+                            successor.handed_over.store(true, Ordering::Relaxed);
+                            successor.playing.store(true, Ordering::Relaxed);
+                            successor.buffer_space.wake();
+                        }
+                    }
+                    // 程序可以决定停止播放、下一首或者单曲循环，播放器自己执行的结束操作已经在上面完成
+                    events.emit(PlayerEvent::EndOfStream);
+
+                    if end_behavior == EndBehavior::Handover {
+                        // 不再写入输出缓冲区，关闭时不需要等待
+                        return;
+                    }
+
+                    loop {
+                        // 文件已关闭
+                        if !status.avaliable.load(Ordering::Relaxed) {
+                            return;
+                        }
+
+                        // 定位之后从新的位置继续解码，解码器和重采样器会在处理定位时清空
+                        if let Some(seek_time) = status.seek_target.take() {
+                            status.set_end(false);
+                            pending_seek = Some(seek_time);
+                            break;
+                        }
+
+                        // 用户启动播放，不可定位的流无法从头重新播放，停留在结尾时需要先定位
+                        if status.playing.load(Ordering::Relaxed)
+                            && status.seekable.load(Ordering::Relaxed)
+                            && end_behavior == EndBehavior::RewindAndWait {
+                            match frames.seek(window.start_ms) {
+                                Ok(()) => {
+                                    status.set_end(false);
+                                    // 结束时已经清空了重采样器，需要恢复到可以继续输入的状态，解码器在定位时清空
+                                    if let Some(resampler) = resampler.as_mut() {
+                                        FFmpegSource::clear_resampler_buffer(resampler);
+                                    }
+                                },
+                                Err(err) => {
+                                    // 无法回到开头时仍然停留在结尾，重新报告播放结束
+                                    log::warn!("failed to rewind to the start: {}", err);
+                                    status.set_end(true);
+                                },
+                            }
+                            break;
+                        }
+
+                        // 等待关闭、定位或者重新播放，期间不占用 CPU
+                        status.buffer_space.wait_until(IDLE_WAIT_INTERVAL, || {
+                            !status.avaliable.load(Ordering::Relaxed)
+                                || status.seek_target.is_pending()
+                                || (status.playing.load(Ordering::Relaxed)
+                                    && status.seekable.load(Ordering::Relaxed)
+                                    && end_behavior == EndBehavior::RewindAndWait)
+                        });
+                    }                    
+                }
+            })
+        );
+
+        self.status.avaliable.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+/// 读取数据包时通知调用者，播放时用来更新位置、码率、封面和网络状态
+trait PacketObserver {
+    /// 读取到一个数据包，audio 表示它属于正在解码的音频流
+    fn packet_read(&mut self, _input_ctx: &MediaInput, _packet: &ffmpeg::Packet, _audio: bool) {}
+
+    /// 读取数据包出错，返回 true 时稍后重新读取（例如等待网络流重连），否则结束并返回错误
+    fn read_failed(&mut self, _err: &ffmpeg::Error) -> bool {
+        false
+    }
+}
+
+/// 同步解码不需要观察数据包
+impl PacketObserver for () {}
+
+/// 同步解码的音频帧迭代器，由 FFmpegSource::frames 创建，播放时的解码线程也使用它解码
+///
+/// 每次返回解码器输出的一帧，采样率和声道数和文件相同，采样格式转换为 32 位浮点。
+/// 损坏的数据包返回错误之后可以继续迭代，读取文件出错时结束
+pub struct Frames {
+    input_ctx: MediaInput,
+    decoder: decoder::Audio,
+    stream_index: usize,
+    time_base: ffmpeg::Rational,
+    /// 音频流第一个数据包的时间，位置都相对于这个时间
+    start_ms: i64,
+    /// 范围的终点，定位之后仍然有效
+    end_ms: Option<i64>,
+    /// 只返回范围内的采样
+    window_trim: Option<WindowTrim>,
+    /// 当前解码器的编码参数，链式流切换到下一段时会改变
+    stream_parameters: StreamParameters,
+    /// 链式流下一段的第一个数据包，取完上一段解码器中剩余的帧之后送入新的解码器
+    chained_packet: Option<ffmpeg::Packet>,
+    /// 已经切换到链式流的下一段，调用者还没有处理
+    chained: bool,
+    /// 上一个音频数据包的时长（秒）
+    last_packet_seconds: f64,
+    /// 已经读到文件结尾，正在取出解码器中剩余的帧
+    draining: bool,
+    finished: bool,
+}
+
+impl Frames {
+    /// 打开文件并创建解码器，定位到 window 的起点
+    fn open<P: AsRef<Path>>(uri: &P, options: &HashMap<String, String>, window: TimeWindow) -> Result<Frames, Error> {
+        let mut input_ctx = open_media_file(uri, options)?;
+        let stream = default_audio_stream(&input_ctx).context(NoAudioStreamSnafu)?;
+        let stream_index = stream.index();
+        let decoder = open_decoder(&stream)?;
+        ensure!(decoder.channels() > 0 && decoder.channels() as usize <= AudioSample::MAX_CHANNELS, OpenMediaFileSnafu {
+            message: format!("unsupported channel count: {}", decoder.channels()),
+        });
+        discard_other_streams(&mut input_ctx, stream_index);
+        let mut frames = Frames::new(input_ctx, decoder, stream_index, window);
+        // 定位到起点之前的关键帧，不可定位的输入解码并丢弃起点之前的数据
+        if window.start_ms > 0 && frames.input_ctx.is_seekable() {
+            frames.seek(window.start_ms)?;
+        }
+        Ok(frames)
+    }
+
+    /// 使用已经打开的输入和解码器，从输入当前的位置开始解码，只返回 window 范围内的采样
+    fn new(input_ctx: MediaInput, decoder: decoder::Audio, stream_index: usize, window: TimeWindow) -> Frames {
+        let stream = input_ctx.stream(stream_index).unwrap();
+        let time_base = stream.time_base();
+        let start_ms = stream_start_ms(&stream);
+        let stream_parameters = StreamParameters::from_stream(&stream);
+        let window_trim = if window.is_full() {
+            None
+        } else {
+            Some(WindowTrim::new(window.start_ms, window.end_ms, time_base, start_ms))
+        };
+        Frames {
+            input_ctx,
+            decoder,
+            stream_index,
+            time_base,
+            start_ms,
+            end_ms: window.end_ms,
+            window_trim,
+            stream_parameters,
+            chained_packet: None,
+            chained: false,
+            last_packet_seconds: 0.,
+            draining: false,
+            finished: false,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.rate()
+    }
+
+    pub fn channels(&self) -> usize {
+        self.decoder.channels() as usize
+    }
+
+    /// 读取文件的媒体信息
+    pub fn media_info(&self, path: Option<&Path>, extractors: &[Arc<dyn MetadataExtractor>]) -> MediaInfo {
+        let mut media_info = MediaInfo::default();
+        read_metadata(&self.input_ctx, path, true, extractors, &mut media_info);
+        if let Some(stream) = self.input_ctx.stream(self.stream_index) {
+            read_stream_info(&self.input_ctx, &stream, &self.decoder, &mut media_info);
+        }
+        media_info
+    }
+
+    fn input(&self) -> &MediaInput {
+        &self.input_ctx
+    }
+
+    fn decoder(&self) -> &decoder::Audio {
+        &self.decoder
+    }
+
+    /// 上一个音频数据包的时长（秒），用于按时长代替无法解码的数据包
+    fn last_packet_seconds(&self) -> f64 {
+        self.last_packet_seconds
+    }
+
+    /// 上一次返回的帧是否来自链式流的新一段，读取后清除
+    fn take_chained(&mut self) -> bool {
+        std::mem::take(&mut self.chained)
+    }
+
+    /// 因为解码到范围的终点而结束，而不是读到了文件结尾
+    fn reached_range_end(&self) -> bool {
+        self.window_trim.as_ref().map_or(false, |window_trim| window_trim.reached_end)
+    }
+
+    /// 更改返回采样的范围，用于随时可能改变的 A-B 循环，已经解码到终点的状态保留到下一次定位
+    fn set_range(&mut self, start_ms: i64, end_ms: Option<i64>) {
+        self.end_ms = end_ms;
+        if start_ms <= 0 && end_ms.is_none() {
+            self.window_trim = None;
+        } else if let Some(window_trim) = self.window_trim.as_mut() {
+            window_trim.start_ms = start_ms;
+            window_trim.end_ms = end_ms;
+        } else {
+            self.window_trim = Some(WindowTrim::new(start_ms, end_ms, self.time_base, self.start_ms));
+        }
+    }
+
+    /// 定位到 position_ms，之后返回的第一个采样就在这个位置
+    fn seek(&mut self, position_ms: i64) -> Result<(), Error> {
+        ensure!(self.input_ctx.is_seekable(), SeekSnafu {
+            message: "the media stream is not seekable".to_string(),
+        });
+        let position = (position_ms + self.start_ms).rescale((1, 1000), rescale::TIME_BASE);
+        self.input_ctx.seek(position, ..position).map_err(|err| Error::Seek {
+            message: err.to_string(),
+        })?;
+        self.decoder.flush();
+        self.window_trim = Some(WindowTrim::new(position_ms, self.end_ms, self.time_base, self.start_ms));
+        self.chained_packet = None;
+        self.draining = false;
+        self.finished = false;
+        Ok(())
+    }
+
+    /// 解码下一帧，只包含范围内的采样
+    fn next_frame(&mut self) -> Option<Result<frame::Audio, Error>> {
+        self.next_frame_with(&mut ())
+    }
+
+    /// 解码下一帧，读取的每个数据包（包括其他流的数据包）都交给 observer
+    fn next_frame_with(&mut self, observer: &mut dyn PacketObserver) -> Option<Result<frame::Audio, Error>> {
+        let mut decoded = frame::Audio::empty();
+        while !self.finished {
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(_) => match self.window_trim.as_mut().map(|window_trim| window_trim.range(&decoded)) {
+                    Some(Some((start, end))) if start > 0 || end < decoded.samples() => {
+                        return Some(Ok(FFmpegSource::trim_frame(&decoded, start, end)));
+                    },
+                    Some(None) if self.reached_range_end() => {
+                        // 已经解码到终点，后面的数据都不需要
+                        self.decoder.flush();
+                        self.finished = true;
+                        return None;
+                    },
+                    Some(None) => continue,
+                    _ => return Some(Ok(decoded)),
+                },
+                Err(ffmpeg::Error::Other { errno: ffmpeg::error::EAGAIN }) if !self.draining => {},
+                Err(ffmpeg::Error::Other { errno: ffmpeg::error::EAGAIN }) | Err(ffmpeg::Error::Eof)
+                    if self.chained_packet.is_some() => {
+                    // 上一段的解码器已经取完，换成下一段的解码器
+                    if let Err(err) = self.switch_decoder() {
+                        return Some(Err(err));
+                    }
+                    continue;
+                },
+                Err(ffmpeg::Error::Other { errno: ffmpeg::error::EAGAIN }) | Err(ffmpeg::Error::Eof) => {
+                    self.finished = true;
+                    return None;
+                },
+                Err(err) => return Some(Err(err).context(DecodeSnafu {
+                    message: "the decoder failed".to_string(),
+                })),
+            }
+
+            // 解码器需要更多的数据包。不使用 packets()，它在读取出错时会无限重试，关闭时无法退出
+            let mut packet = ffmpeg::Packet::empty();
+            match packet.read(&mut self.input_ctx) {
+                Ok(_) => {
+                    let audio = packet.stream() == self.stream_index;
+                    observer.packet_read(&self.input_ctx, &packet, audio);
+                    if !audio {
+                        continue;
+                    }
+                    let stream = self.input_ctx.stream(self.stream_index).unwrap();
+                    self.last_packet_seconds = packet.duration().max(0) as f64 * f64::from(stream.time_base());
+                    // 链式 Ogg 切换到下一段时，编码参数和标签都可能改变，先取出上一段解码器中剩余的帧，再重新创建解码器
+                    if !self.stream_parameters.matches(&stream) {
+                        self.chained_packet = Some(packet);
+                        self.draining = true;
+                        if let Err(err) = self.decoder.send_eof() {
+                            log::warn!("failed to drain the decoder: {}", err);
+                        }
+                        continue;
+                    }
+                    if let Err(err) = self.decoder.send_packet(&packet) {
+                        return Some(Err(err).context(DecodeSnafu {
+                            message: "the packet could not be decoded".to_string(),
+                        }));
+                    }
+                },
+                Err(ffmpeg::Error::Eof) => {
+                    self.draining = true;
+                    if let Err(err) = self.decoder.send_eof() {
+                        self.finished = true;
+                        return Some(Err(err).context(DecodeSnafu {
+                            message: "failed to drain the decoder".to_string(),
+                        }));
+                    }
+                },
+                Err(err) => {
+                    if observer.read_failed(&err) {
+                        continue;
+                    }
+                    self.finished = true;
+                    return Some(Err(err).context(DecodeSnafu {
+                        message: "failed to read the media file".to_string(),
+                    }));
+                },
+            }
+        }
+        None
+    }
 
-        let stream_index = stream.index();
+    /// 链式流的上一段已经解码完，按新一段的参数重新创建解码器，再送入新一段的第一个数据包
+    fn switch_decoder(&mut self) -> Result<(), Error> {
+        let packet = match self.chained_packet.take() {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+        let stream = self.input_ctx.stream(self.stream_index).unwrap();
+        self.stream_parameters = StreamParameters::from_stream(&stream);
+        self.draining = false;
+        match open_decoder(&stream) {
+            Ok(decoder) => {
+                self.decoder = decoder;
+                self.chained = true;
+            },
+            Err(err) => {
+                log::error!("failed to reopen the decoder for the next chained stream: {}", err);
+                // 继续使用原来的解码器，避免每个数据包都重试
+                self.decoder.flush();
+            },
+        }
+        self.decoder.send_packet(&packet).context(DecodeSnafu {
+            message: "the packet could not be decoded".to_string(),
+        })
+    }
+}
 
-        // 创建解码器
-        let context = codec::context::Context::from_parameters(stream.parameters()).context(OpenMediaFileWithFFmpegSnafu {
-            message: "failed to create codec context".to_string(),
-        })?;
-        let mut decoder = context.decoder().audio().context(OpenMediaFileWithFFmpegSnafu {
-            message: "failed to create audio decoder".to_string(),
-        })?;
-        // 将音频流相关信息拷贝到 AVCodecContext 中
-        decoder.set_parameters(stream.parameters()).context(OpenMediaFileWithFFmpegSnafu {
-            message: "failed to set codec parameters".to_string(),
-        })?;
+impl Iterator for Frames {
+    type Item = Result<Vec<AudioSample>, Error>;
 
-        let device_channels = device_sample_format.channel_count;
-        let device_sample_rate = device_sample_format.sample_rate;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().map(|decoded| decoded.map(|decoded| FFmpegSource::ffmpeg_frame_to_slice(&decoded)))
+    }
+}
 
-        // 计算总长度（毫秒）
-        let duration = input_ctx.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE) * 1000.0;
-        self.media_info.duration = duration as i64;
+/// 由调用者拉取解码数据的音频流，由 Player::open_decoder 创建
+///
+/// 在调用者的线程上同步解码并转换为指定的采样率和声道数，不使用音频设备、解码线程和输出缓冲区，
+/// 适合游戏引擎或者自己混音的程序。和播放时使用相同的解码器和重采样器
+pub struct DecodedStream {
+    frames: Frames,
+    output_format: OutputFormat,
+    resampler: Option<SwrContext>,
+    /// 已经转换但还没有被读取的交错采样
+    pending: Vec<f32>,
+    pending_offset: usize,
+    media_info: Arc<MediaInfo>,
+    /// 上一次定位的位置（毫秒）
+    base_ms: i64,
+    /// 定位之后已经读取的帧数
+    read_frames: u64,
+    /// 已经取出了重采样器中缓存的数据
+    flushed: bool,
+}
 
-        // 有些格式（例如 WAV）没有 channel layout
-        // 重采样器会检查 input stream 的配置和输入配置是否一致
-        if decoder.channel_layout().is_empty() {
-            decoder.set_channel_layout(ffmpeg::ChannelLayout::default(decoder.channels().into()));
+impl DecodedStream {
+    /// 打开文件，只解码 window 范围内的采样，位置仍然相对于文件开头
+    pub fn open<P: AsRef<Path>>(
+        uri: &P,
+        options: &HashMap<String, String>,
+        extractors: &[Arc<dyn MetadataExtractor>],
+        window: TimeWindow,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<DecodedStream, Error> {
+        ensure!(sample_rate > 0, OpenMediaFileSnafu {
+            message: "the sample rate must be greater than 0".to_string(),
+        });
+        let output_format = OutputFormat {
+            sample_rate,
+            channel_layout: output_channel_layout(channels)?,
         };
+        let frames = Frames::open(uri, options, window)?;
+        let media_info = Arc::new(frames.media_info(Some(uri.as_ref()), extractors));
+        Ok(DecodedStream {
+            frames,
+            output_format,
+            resampler: None,
+            pending: Vec::new(),
+            pending_offset: 0,
+            media_info,
+            base_ms: window.start_ms,
+            read_frames: 0,
+            flushed: false,
+        })
+    }
 
-        // 创建重采样器，转换音频数据为音频设备支持的格式
-        let mut resampler =  SwrContext::get(
-            // 输入格式
-            decoder.format(),
-            decoder.channel_layout(),
-            decoder.rate(),
-            // 输出格式 (一律使用32位浮点)
-            format::Sample::F32(format::sample::Type::Packed),
-            ffmpeg::ChannelLayout::default(device_channels.into()),
-            device_sample_rate
-        ).context(OpenMediaFileWithFFmpegSnafu {
-            message: "failed to create resampler".to_string(),
-        })?;
+    pub fn sample_rate(&self) -> u32 {
+        self.output_format.sample_rate
+    }
 
-        // 用来接收解码线程退出消息的通道
-        let (decode_thread_suspend_tx, decode_thread_suspend_rx) = channel::<u8>();
-        self.decode_thread_suspend_rx = Some(decode_thread_suspend_rx);
+    pub fn channels(&self) -> usize {
+        self.output_format.channel_layout.channels() as usize
+    }
 
-        let (seek_tx, seek_rx) = channel::<i64>();
-        self.seek_channel_tx = Some(seek_tx);
+    pub fn media_info(&self) -> Arc<MediaInfo> {
+        self.media_info.clone()
+    }
 
-        let producer = self.buffer_producer.clone();
-        let consumer = self.buffer_consumer.clone();
+    /// 下一个读取的采样的位置（毫秒）
+    pub fn position(&self) -> i64 {
+        self.base_ms + (self.read_frames * 1000 / self.output_format.sample_rate as u64) as i64
+    }
 
-        let status = self.status.clone();
-        let buffer_chunk_size = self.buffer_chunk_size.clone();
-        let dynamic_device_buffer_size = self.dynamic_device_buffer_size.clone();
-        self.decode_thread = Some(
-            std::thread::spawn(move || {
-                loop {
-                    if !status.avaliable.load(Ordering::Relaxed) {
-                        break;
-                    }
-                    // 可以实时定位的解码逻辑
-                    let mut seek: Option<i64> = None;
-                    loop {
-                        if !status.avaliable.load(Ordering::Relaxed) {
-                            break;
-                        }
-                        if let Some(seek_time) = seek {
-                            // 更改 input_ctx 的位置，然后清除定位信息
-                            if let Err(err) = input_ctx.seek(seek_time, ..seek_time) {
-                                eprintln!("failed to seek: {}", err);
-                            } else {
-                                decoder.flush();
-                                FFmpegSource::clear_resampler_buffer(&mut resampler);
-                                // TODO: 解耦合
-                                AudioDevice::clear_buffer(&consumer);
-                            }
+    /// 读取交错的 32 位浮点采样，返回写入的采样数（总是声道数的整数倍），到达结尾时返回 0
+    ///
+    /// 没有到达结尾时总是填满 buffer 中能容纳的整帧
+    pub fn read_samples(&mut self, buffer: &mut [f32]) -> Result<usize, Error> {
+        let channels = self.channels();
+        let capacity = buffer.len() / channels * channels;
+        let mut written = 0;
+        while written < capacity {
+            if self.pending_offset >= self.pending.len() && !self.fill_pending()? {
+                break;
+            }
+            let count = (capacity - written).min(self.pending.len() - self.pending_offset);
+            buffer[written..written + count].copy_from_slice(&self.pending[self.pending_offset..self.pending_offset + count]);
+            self.pending_offset += count;
+            written += count;
+        }
+        self.read_frames += (written / channels) as u64;
+        Ok(written)
+    }
 
-                            seek = None;
-                        }
-                        for (stream, packet) in input_ctx.packets() {
-                            if !status.avaliable.load(Ordering::Relaxed) {
-                                break;
-                            }
-                            // 已经没有音频帧了，关闭丢弃帧模式
-                            status.dropping_frames.store(false, Ordering::Relaxed);
+    /// 定位到 position_ms，之后读取的第一个采样就在这个位置
+    pub fn seek(&mut self, position_ms: i64) -> Result<(), Error> {
+        let position_ms = position_ms.max(0);
+        self.frames.seek(position_ms)?;
+        if let Some(resampler) = self.resampler.as_mut() {
+            FFmpegSource::clear_resampler_buffer(resampler);
+        }
+        self.pending.clear();
+        self.pending_offset = 0;
+        self.base_ms = position_ms;
+        self.read_frames = 0;
+        self.flushed = false;
+        Ok(())
+    }
 
-                            if let Ok(seek_time) = seek_rx.try_recv() {
-                                // 如果接收到定位请求，则跳出循环
-                                seek = Some(seek_time);
+    /// 解码并转换下一段数据，没有更多数据时返回 false
+    fn fill_pending(&mut self) -> Result<bool, Error> {
+        self.pending.clear();
+        self.pending_offset = 0;
+        while self.pending.is_empty() {
+            match self.frames.next_frame() {
+                Some(decoded) => self.convert(&decoded?)?,
+                None if !self.flushed => {
+                    // 没有输入时重采样器输出内部缓存的全部数据
+                    self.flushed = true;
+                    if let Some(resampler) = self.resampler.as_mut() {
+                        loop {
+                            let mut resampled = frame::Audio::empty();
+                            resampler.flush(&mut resampled).context(DecodeSnafu {
+                                message: "failed to flush the resampler".to_string(),
+                            })?;
+                            if resampled.samples() == 0 {
                                 break;
-                            };
-
-                            // 阻塞暂停和停止状态（避免清除帧数据的过程中继续解码数据）
-                            while !status.playing.load(Ordering::Relaxed) {
-                                if !status.avaliable.load(Ordering::Relaxed) {
-                                    break;
-                                }
-                                std::thread::sleep(std::time::Duration::from_millis(10));
-                            }
-
-                            if stream.index() == stream_index {
-                                // 更新当前时间
-                                packet.pts().map(|pts| {
-                                    let current_time = pts as f64 * f64::from(stream.time_base()) * 1000.0;
-                                    status.current_time.lock().unwrap().set(current_time as i64);
-                                });
-
-                                decoder.send_packet(&packet).unwrap();
-                                FFmpegSource::decode_to_buffer(
-                                    &status.clone(),
-                                    &buffer_chunk_size,
-                                    &dynamic_device_buffer_size,
-                                    &mut decoder,
-                                    &mut producer.lock().unwrap(),
-                                    &mut resampler)
-                                    .unwrap();
                             }
-                        }
-                        if seek == None {
-                            // 如果没有定位信息，表示正常播放结束
-                            break;
+                            DecodedStream::push_frame(&mut self.pending, &resampled);
                         }
                     }
-                    
-                    // 播放完毕
-                    status.playing.store(false, Ordering::Relaxed);
-                    let current_time = status.current_time.lock().unwrap();
-                    current_time.set(0);
-                    std::mem::drop(current_time);
-
-                    // TODO: 发送播放完毕的消息，程序可以决定停止播放、下一首或者单曲循环
-
-                    status.is_end.store(true, Ordering::Relaxed);
-
-                    loop {
-                        // 文件已关闭
-                        if !status.avaliable.load(Ordering::Relaxed) {
-                            decode_thread_suspend_tx.send(0).unwrap();
-                            return;
-                        }
+                },
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
 
-                        // 用户启动播放
-                        if status.playing.load(Ordering::Relaxed) {
-                            status.is_end.store(false, Ordering::Relaxed);
-                            input_ctx.seek(0, ..0).unwrap();
-                            break;
-                        }
-                    }                    
-                }
-            })
-        );
+    /// 把解码器输出的一帧转换为输出格式，写入待读取的采样
+    fn convert(&mut self, decoded: &frame::Audio) -> Result<(), Error> {
+        FFmpegSource::update_resampler(&mut self.resampler, decoded, &self.output_format, false).context(DecodeSnafu {
+            message: "failed to create the resampler".to_string(),
+        })?;
+        let resampler = match self.resampler.as_mut() {
+            Some(resampler) => resampler,
+            None => {
+                DecodedStream::push_frame(&mut self.pending, decoded);
+                return Ok(());
+            },
+        };
+        let mut resampled = frame::Audio::empty();
+        let mut delay = resampler.run(decoded, &mut resampled).context(DecodeSnafu {
+            message: "failed to resample".to_string(),
+        })?;
+        let mut frames = vec![resampled];
+        while delay.is_some() {
+            let mut resampled = frame::Audio::empty();
+            delay = resampler.flush(&mut resampled).context(DecodeSnafu {
+                message: "failed to resample".to_string(),
+            })?;
+            frames.push(resampled);
+        }
+        for resampled in frames.iter() {
+            DecodedStream::push_frame(&mut self.pending, resampled);
+        }
+        Ok(())
+    }
 
-        self.status.avaliable.store(true, Ordering::Relaxed);
+    fn push_frame(pending: &mut Vec<f32>, frame: &frame::Audio) {
+        for audio_sample in FFmpegSource::ffmpeg_frame_to_slice(frame) {
+            pending.extend_from_slice(audio_sample.as_slice());
+        }
+    }
+}
 
-        Ok(())
+impl Drop for FFmpegSource {
+    /// 没有成功关闭就被丢弃时通知解码线程退出，不等待它结束
+    fn drop(&mut self) {
+        if self.decode_thread.take().is_some() {
+            self.status.avaliable.store(false, Ordering::Relaxed);
+            self.status.end_signal.interrupt();
+            self.status.buffer_space.wake();
+            if let Some(network) = self.network.take() {
+                network.abort();
+            }
+        }
     }
 }
 
 impl AudioSource for FFmpegSource {
+    /// 结束解码线程并等待它退出，然后清空输出缓冲区
+    ///
+    /// 输入上下文、解码器、重采样器等 FFmpeg 资源都由解码线程持有，返回时已经全部释放，
+    /// 之后可以立即重新打开同一个文件
     fn close(&mut self) -> Result<(), Error> {
-        // 结束解码线程
+        self.detach()?;
+        AudioDevice::clear_buffer(&self.buffer_consumer);
+        Ok(())
+    }
+
+    /// 结束解码线程并等待它退出，不清空输出缓冲区
+    ///
+    /// 用于已经把输出交给下一首，或者预先打开之后还没有开始写入的播放源，
+    /// 缓冲区中的数据属于共用同一个输出缓冲区的另一个播放源
+    fn detach(&mut self) -> Result<(), Error> {
+        // 结束解码线程，阻塞在网络读取中时中断读取
         self.status.clone().avaliable.store(false, Ordering::Relaxed);
-        self.decode_thread_suspend_rx.as_ref()
-        .context(CloseMediaFileSnafu {
+        self.status.end_signal.interrupt();
+        self.status.buffer_space.wake();
+        if let Some(network) = self.network.take() {
+            network.abort();
+        }
+        let decode_thread = self.decode_thread.take().context(CloseMediaFileSnafu {
             message: "no file opened".to_string(),
-        })?.recv().ok();
-
-        // TODO：清理资源
-        self.decode_thread = None;
-        self.seek_channel_tx = None;
-        self.decode_thread_suspend_rx = None;
-
-        AudioDevice::clear_buffer(&self.buffer_consumer);
+        })?;
+        // 卡在无法中断的读取中的线程不能让关闭无限期地阻塞
+        decode_thread.join(CLOSE_TIMEOUT)?;
 
         Ok(())
     }
 
+    /// 开始播放，从暂停或者停止开始时先预先缓冲，缓冲区已经有足够的数据时立即开始输出
     fn streaming(&self) -> Result<(), Error> {
+        if !self.status.playing.load(Ordering::Relaxed) && self.status.prebuffer_frames.load(Ordering::Relaxed) > 0 {
+            self.status.prebuffering.store(true, Ordering::Relaxed);
+        }
         self.status.clone().playing.store(true, Ordering::Relaxed);
+        self.status.buffer_space.wake();
         Ok(())
     }
 
+    /// 设置预先缓冲的帧数，None 表示缓冲到动态缓冲区写满为止，0 表示不预先缓冲
+    fn set_prebuffer_frames(&self, frames: Option<usize>) {
+        let frames = frames.map_or(u64::MAX, |frames| frames as u64);
+        self.status.prebuffer_frames.store(frames, Ordering::Relaxed);
+    }
+
     fn pause(&self) -> Result<(), Error> {
         self.status.clone().playing.store(false, Ordering::Relaxed);
         Ok(())
     }
 
     fn seek(&self, time: i64) -> Result<(), Error> {
+        ensure!(self.is_seekable(), SeekSnafu {
+            message: "the media stream is not seekable".to_string(),
+        });
+        // 位置相对于时间窗口，限制在窗口之内
+        let duration = self.get_duration();
+        let time = if duration >= 0 { time.clamp(0, duration) } else { time.max(0) };
+        let time = self.window.to_absolute(time);
         // 相当于 time * ( 1 / 1000 ) / AV_TIME_BASE
         let time_base = time.rescale((1, 1000), rescale::TIME_BASE);
-        self.seek_channel_tx.as_ref().context(SeekSnafu {
+        ensure!(self.decode_thread.is_some(), SeekSnafu {
             message: "no file opened".to_string(),
-        })?
-        .send(time_base).context(SendSeekSnafu {
-            message: "decoding thread may have terminated".to_string(),
-        })?;
+        });
+        // 解码线程还没有处理上一个目标时直接覆盖，只定位到最后一个位置
+        self.status.seek_target.post(time_base);
         let status = self.status.clone();
         status.dropping_frames.store(true, Ordering::Relaxed);
+        status.buffer_space.wake();
         Ok(())
     }
 
@@ -450,26 +2735,65 @@ impl AudioSource for FFmpegSource {
         self.status.clone().dropping_frames.store(true, Ordering::Relaxed);
     }
 
+    /// 总长度，只播放文件中的一段时为这一段的长度
     fn get_duration(&self) -> i64 {
-        self.media_info.duration
+        self.window.duration(self.media_info.lock().unwrap().duration)
     }
 
     fn get_bitrate(&self) -> i64 {
-        self.media_info.bitrate
+        self.media_info.lock().unwrap().bitrate
+    }
+
+    fn get_current_bitrate(&self) -> i64 {
+        self.status.current_bitrate.load(Ordering::Relaxed)
     }
 
     fn get_current_time(&self) -> i64 {
-        self.status.current_time.lock().unwrap().get()
+        self.window.to_relative(self.status.current_time.lock().unwrap().get())
+    }
+
+    /// 实际播放到的位置（微秒），buffered_frames 为输出缓冲区中还没有播放的帧数，sample_rate 为输出的采样率
+    ///
+    /// 从最近一个数据包的时间开始，按写入输出缓冲区并且已经被取走的帧数推算，精确到输出的一帧。
+    /// 误差主要来自重采样器和变速滤镜的延迟（几毫秒），不包括设备缓冲区的延迟
+    fn get_current_time_us(&self, buffered_frames: usize, sample_rate: u32) -> i64 {
+        if sample_rate == 0 {
+            return self.get_current_time() * 1000;
+        }
+        let (anchor_us, anchor_frames) = *self.status.position_anchor.lock().unwrap();
+        let written_frames = self.status.written_frames.load(Ordering::Relaxed);
+        let played_frames = written_frames.saturating_sub(buffered_frames as u64) as i64 - anchor_frames as i64;
+        let speed = f32::from_bits(self.status.speed.load(Ordering::Relaxed)) as f64;
+        let position_us = anchor_us + (played_frames as f64 * 1_000_000. / sample_rate as f64 * speed) as i64;
+        (position_us - self.window.start_ms * 1000).max(0)
     }
 
     fn set_buffer_chunk_size(&mut self, size: usize) {
         self.buffer_chunk_size.clone().lock().unwrap().set(size);
     }
 
+    fn get_buffer_chunk_size(&self) -> usize {
+        self.buffer_chunk_size.lock().unwrap().get()
+    }
+
     fn is_end(&self) -> bool {
         self.status.is_end.load(Ordering::Relaxed)
     }
 
+    fn get_debug_stats(&self) -> DebugStats {
+        self.status.counters.snapshot()
+    }
+
+    /// 播放结束的通知，在解码线程等到输出缓冲区播放完之后发出
+    fn end_signal(&self) -> Arc<EndSignal> {
+        self.status.end_signal.clone()
+    }
+
+    /// 唤醒等待 end_signal 的线程
+    fn interrupt_wait(&self) {
+        self.status.end_signal.interrupt();
+    }
+
     fn is_streaming(&self) -> bool {
         self.status.clone().playing.load(Ordering::Relaxed)
     }
@@ -479,7 +2803,415 @@ impl AudioSource for FFmpegSource {
         self.buffer_chunk_size.clone().lock().unwrap().set(size / 2);
     }
 
-    fn get_media_info(&self) -> &MediaInfo {
-        &self.media_info
+    fn get_dynamic_device_buffer_size(&self) -> usize {
+        self.dynamic_device_buffer_size.lock().unwrap().get()
+    }
+
+    fn get_media_info(&self) -> Arc<MediaInfo> {
+        self.media_info.lock().unwrap().clone()
+    }
+
+    fn is_seekable(&self) -> bool {
+        self.status.seekable.load(Ordering::Relaxed)
+    }
+
+    /// 设置播放速度，解码线程在下一个数据包生效
+    fn set_speed(&self, speed: f32) {
+        self.status.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get_speed(&self) -> f32 {
+        f32::from_bits(self.status.speed.load(Ordering::Relaxed))
+    }
+
+    /// 获取解码线程中启用的处理步骤
+    fn get_active_processing(&self) -> Vec<&'static str> {
+        let mut processing = self.processing.clone();
+        if self.get_speed() != 1. {
+            processing.push(PROCESSING_TEMPO);
+        }
+        if self.get_gain() != 1. {
+            processing.push(PROCESSING_GAIN);
+        }
+        processing
+    }
+
+    fn set_gain(&self, amplitude: f32) {
+        self.status.gain.store(amplitude.to_bits(), Ordering::Relaxed);
+    }
+
+    fn get_gain(&self) -> f32 {
+        f32::from_bits(self.status.gain.load(Ordering::Relaxed))
+    }
+
+    fn is_source_lost(&self) -> bool {
+        self.status.source_lost.load(Ordering::Relaxed)
+    }
+
+    /// 网络流是否正在等待数据
+    fn is_buffering(&self) -> bool {
+        self.network.as_ref().map_or(false, |network| network.is_buffering())
+    }
+
+    fn set_end_behavior(&self, behavior: EndBehavior) {
+        self.status.end_behavior.store(behavior.as_u8(), Ordering::Relaxed);
+        self.status.buffer_space.wake();
+    }
+
+    fn get_end_behavior(&self) -> EndBehavior {
+        EndBehavior::from_u8(self.status.end_behavior.load(Ordering::Relaxed))
+    }
+
+    fn set_seamless_loop(&self, enabled: bool) {
+        self.status.seamless_loop.store(enabled, Ordering::Relaxed);
+    }
+
+    fn is_seamless_loop(&self) -> bool {
+        self.status.seamless_loop.load(Ordering::Relaxed)
+    }
+
+    /// 设置结束行为为 Handover 时接着播放的播放源，它需要已经打开并且处于暂停状态，
+    /// 播放完毕后由解码线程让它开始播放，紧接着写入同一个输出缓冲区
+    fn set_successor(&self, successor: Option<Arc<FFmpegSourceStatus>>) {
+        *self.status.successor.lock().unwrap() = successor;
+    }
+
+    /// 输出设备的采样率或者声道数改变后调用，解码线程在下一个数据包之前换成新的格式输出，
+    /// 丢弃缓冲区中按原来的格式输出的数据，并且从实际听到的位置重新解码
+    fn set_output_format(&self, format: DeviceSampleFormat) {
+        *self.status.output_format.lock().unwrap() = Some(format);
+    }
+
+    fn set_seek_debounce_ms(&self, debounce_ms: u32) {
+        self.status.seek_debounce_ms.store(debounce_ms, Ordering::Relaxed);
+    }
+
+    fn get_seek_debounce_ms(&self) -> u32 {
+        self.status.seek_debounce_ms.load(Ordering::Relaxed)
+    }
+
+    fn set_error_resilience(&self, resilience: ErrorResilience) {
+        self.status.error_resilience.store(resilience.as_u8(), Ordering::Relaxed);
+    }
+
+    fn get_error_resilience(&self) -> ErrorResilience {
+        ErrorResilience::from_u8(self.status.error_resilience.load(Ordering::Relaxed))
+    }
+
+    fn set_ab_loop(&self, ab_loop: Option<(i64, i64)>) {
+        *self.status.ab_loop.lock().unwrap() = ab_loop;
+    }
+
+    fn get_ab_loop(&self) -> Option<(i64, i64)> {
+        *self.status.ab_loop.lock().unwrap()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::audio::test_media;
+
+    #[test]
+    fn wake_ends_the_idle_wait() {
+        let signal = Arc::new(SpaceSignal::default());
+        let playing = Arc::new(AtomicBool::new(false));
+        let controller = {
+            let signal = signal.clone();
+            let playing = playing.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                playing.store(true, Ordering::Relaxed);
+                signal.wake();
+            })
+        };
+        let started = Instant::now();
+        while !playing.load(Ordering::Relaxed) {
+            signal.wait_until(Duration::from_secs(5), || playing.load(Ordering::Relaxed));
+        }
+        controller.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn idle_wait_returns_when_already_ready() {
+        let signal = SpaceSignal::default();
+        let started = Instant::now();
+        signal.wait_until(Duration::from_secs(5), || true);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn single_seek_is_not_delayed() {
+        let slot = SeekSlot::default();
+        let avaliable = AtomicBool::new(true);
+        let started = Instant::now();
+        assert_eq!(slot.settle(10, None, &avaliable), 10);
+        assert!(started.elapsed() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn seeks_within_the_debounce_keep_the_last_target() {
+        let slot = Arc::new(SeekSlot::default());
+        let avaliable = AtomicBool::new(true);
+        let poster = {
+            let slot = slot.clone();
+            thread::spawn(move || {
+                for target in 1..=5 {
+                    thread::sleep(Duration::from_millis(5));
+                    slot.post(target * 1000);
+                }
+            })
+        };
+        let started = Instant::now();
+        let target = slot.settle(0, Some(started + Duration::from_millis(200)), &avaliable);
+        poster.join().unwrap();
+        assert_eq!(target, 5000);
+        // 等到防抖时间结束才返回
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn pending_target_is_replaced_by_the_latest() {
+        let slot = SeekSlot::default();
+        for target in 0..100 {
+            slot.post(target);
+        }
+        assert_eq!(slot.take(), Some(99));
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn rapid_seeks_end_at_the_latest_target() {
+        let slot = Arc::new(SeekSlot::default());
+        let avaliable = Arc::new(AtomicBool::new(true));
+        // 和解码线程一样取出目标，防抖之后执行定位
+        let decoder = {
+            let slot = slot.clone();
+            let avaliable = avaliable.clone();
+            thread::spawn(move || {
+                let mut executed = Vec::new();
+                while executed.last() != Some(&99) {
+                    if let Some(target) = slot.wait(Duration::from_secs(5)) {
+                        let deadline = Instant::now() + Duration::from_millis(20);
+                        executed.push(slot.settle(target, Some(deadline), &avaliable));
+                    } else {
+                        break;
+                    }
+                }
+                executed
+            })
+        };
+        for target in 0..100 {
+            slot.post(target);
+        }
+        let executed = decoder.join().unwrap();
+        assert_eq!(executed.last(), Some(&99));
+        assert!(executed.len() < 10, "{} seeks executed", executed.len());
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn debounce_ends_when_the_source_is_closed() {
+        let slot = SeekSlot::default();
+        let avaliable = AtomicBool::new(false);
+        let started = Instant::now();
+        assert_eq!(slot.settle(7, Some(started + Duration::from_secs(5)), &avaliable), 7);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn joining_a_finished_decode_thread_succeeds() {
+        let decode_thread = DecodeThread::spawn(|| thread::sleep(Duration::from_millis(10)));
+        assert!(decode_thread.join(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn joining_a_stuck_decode_thread_gives_up_after_the_timeout() {
+        let release = Arc::new(AtomicBool::new(false));
+        let decode_thread = {
+            let release = release.clone();
+            DecodeThread::spawn(move || {
+                // 模拟卡在无法中断的读取中
+                while !release.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+            })
+        };
+        let started = Instant::now();
+        let result = decode_thread.join(Duration::from_millis(50));
+        assert!(matches!(result, Err(Error::CloseMediaFile { .. })));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        release.store(true, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn joining_a_panicked_decode_thread_reports_the_panic() {
+        let decode_thread = DecodeThread::spawn(|| panic!("decode thread panic"));
+        assert!(matches!(decode_thread.join(Duration::from_secs(5)), Err(Error::CloseMediaFile { .. })));
+    }
+
+    const RAMP_SAMPLE_RATE: u32 = 8000;
+
+    /// 写入 1 秒的单声道斜坡，第 n 个样本的值是 n / 采样率，从读到的值可以知道它在文件中的位置
+    fn write_ramp(name: &str) -> std::path::PathBuf {
+        let path = test_media::temp_dir(name).join("ramp.wav");
+        let samples: Vec<f32> = (0..RAMP_SAMPLE_RATE).map(|n| n as f32 / RAMP_SAMPLE_RATE as f32).collect();
+        test_media::write_wav(&path, RAMP_SAMPLE_RATE, 1, &samples);
+        path
+    }
+
+    fn open_ramp(path: &Path, window: TimeWindow, sample_rate: u32, channels: u16) -> Result<DecodedStream, Error> {
+        DecodedStream::open(&path, &HashMap::new(), &[], window, sample_rate, channels)
+    }
+
+    /// 按 chunk 个采样一次读取到结尾
+    fn read_to_end(stream: &mut DecodedStream, chunk: usize) -> Vec<f32> {
+        let mut samples = Vec::new();
+        let mut buffer = vec![0.; chunk];
+        loop {
+            let written = stream.read_samples(&mut buffer).unwrap();
+            if written == 0 {
+                return samples;
+            }
+            samples.extend_from_slice(&buffer[..written]);
+        }
+    }
+
+    #[test]
+    fn frames_iterate_over_every_sample_of_the_file() {
+        let path = write_ramp("frames-energy");
+        let frames = FFmpegSource::frames(&path).unwrap();
+        assert_eq!((frames.sample_rate(), frames.channels()), (RAMP_SAMPLE_RATE, 1));
+        let mut count = 0;
+        let mut energy = 0f64;
+        for block in frames {
+            for audio_sample in block.unwrap() {
+                assert_eq!(audio_sample.as_slice().len(), 1);
+                energy += (audio_sample.ch1() as f64).powi(2);
+                count += 1;
+            }
+        }
+        assert_eq!(count, RAMP_SAMPLE_RATE);
+        let expected: f64 = (0..RAMP_SAMPLE_RATE).map(|n| (n as f32 / RAMP_SAMPLE_RATE as f32) as f64).map(|sample| sample * sample).sum();
+        assert!((energy - expected).abs() < 1e-6, "energy {} instead of {}", energy, expected);
+    }
+
+    #[test]
+    fn frames_in_a_window_only_return_the_window() {
+        let path = write_ramp("frames-window");
+        let frames = FFmpegSource::frames_in(&path, &HashMap::new(), TimeWindow::new(250, Some(500))).unwrap();
+        let samples: Vec<f32> = frames.flat_map(|block| block.unwrap()).map(|audio_sample| audio_sample.ch1()).collect();
+        assert_eq!(samples.len(), RAMP_SAMPLE_RATE as usize / 4);
+        assert_eq!(samples[0], 0.25);
+    }
+
+    #[test]
+    fn decoded_stream_reads_every_sample_then_returns_zero() {
+        let path = write_ramp("decoded-all");
+        let mut stream = open_ramp(&path, TimeWindow::default(), RAMP_SAMPLE_RATE, 1).unwrap();
+        let samples = read_to_end(&mut stream, 1000);
+        assert_eq!(samples.len(), RAMP_SAMPLE_RATE as usize);
+        for (n, sample) in samples.iter().enumerate() {
+            assert_eq!(*sample, n as f32 / RAMP_SAMPLE_RATE as f32);
+        }
+        // 到达结尾之后一直返回 0，不返回错误
+        let mut buffer = [0.; 16];
+        assert_eq!(stream.read_samples(&mut buffer).unwrap(), 0);
+        assert_eq!(stream.read_samples(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn decoded_stream_position_follows_the_samples_read() {
+        let path = write_ramp("decoded-position");
+        let mut stream = open_ramp(&path, TimeWindow::default(), RAMP_SAMPLE_RATE, 1).unwrap();
+        assert_eq!(stream.position(), 0);
+        let mut buffer = vec![0.; RAMP_SAMPLE_RATE as usize / 2];
+        assert_eq!(stream.read_samples(&mut buffer).unwrap(), buffer.len());
+        assert_eq!(stream.position(), 500);
+        read_to_end(&mut stream, 1000);
+        assert_eq!(stream.position(), 1000);
+    }
+
+    #[test]
+    fn decoded_stream_seek_starts_at_the_target() {
+        let path = write_ramp("decoded-seek");
+        let mut stream = open_ramp(&path, TimeWindow::default(), RAMP_SAMPLE_RATE, 1).unwrap();
+        read_to_end(&mut stream, 1000);
+
+        // 读到结尾之后仍然可以定位
+        stream.seek(250).unwrap();
+        assert_eq!(stream.position(), 250);
+        let mut buffer = [0.; 8];
+        assert_eq!(stream.read_samples(&mut buffer).unwrap(), 8);
+        for (n, sample) in buffer.iter().enumerate() {
+            assert_eq!(*sample, (2000 + n) as f32 / RAMP_SAMPLE_RATE as f32);
+        }
+        assert_eq!(stream.position(), 251);
+        assert_eq!(read_to_end(&mut stream, 1000).len(), RAMP_SAMPLE_RATE as usize - 2008);
+
+        // 负数的位置从开头读取
+        stream.seek(-100).unwrap();
+        assert_eq!(stream.position(), 0);
+        assert_eq!(read_to_end(&mut stream, 1000).len(), RAMP_SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn decoded_stream_only_reads_the_window() {
+        let path = write_ramp("decoded-window");
+        let mut stream = open_ramp(&path, TimeWindow::new(250, Some(500)), RAMP_SAMPLE_RATE, 1).unwrap();
+        // 位置相对于文件开头
+        assert_eq!(stream.position(), 250);
+        let samples = read_to_end(&mut stream, 1000);
+        assert_eq!(samples.len(), 2000);
+        assert_eq!(samples[0], 2000. / RAMP_SAMPLE_RATE as f32);
+        assert_eq!(samples[1999], 3999. / RAMP_SAMPLE_RATE as f32);
+        assert_eq!(stream.position(), 500);
+    }
+
+    #[test]
+    fn decoded_stream_converts_to_the_requested_format() {
+        let path = write_ramp("decoded-convert");
+        let mut stream = open_ramp(&path, TimeWindow::default(), RAMP_SAMPLE_RATE * 2, 2).unwrap();
+        assert_eq!(stream.sample_rate(), RAMP_SAMPLE_RATE * 2);
+        assert_eq!(stream.channels(), 2);
+        // 奇数长度的缓冲区只写入整帧
+        let mut buffer = [0.; 7];
+        assert_eq!(stream.read_samples(&mut buffer).unwrap(), 6);
+        let samples = read_to_end(&mut stream, 999);
+        // 重采样的首尾可能差几帧，总长度仍然是 1 秒
+        let frames = (samples.len() + 6) / 2;
+        let expected = RAMP_SAMPLE_RATE as usize * 2;
+        assert!(frames.abs_diff(expected) <= expected / 100, "{} frames", frames);
+        // 单声道复制到两个声道
+        for frame in samples.chunks(2) {
+            assert!((frame[0] - frame[1]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn decoded_stream_reports_open_errors() {
+        let path = write_ramp("decoded-errors");
+        assert!(matches!(open_ramp(&path, TimeWindow::default(), 0, 1), Err(Error::OpenMediaFile { .. })));
+        assert!(open_ramp(&path.with_file_name("missing.wav"), TimeWindow::default(), RAMP_SAMPLE_RATE, 1).is_err());
+    }
+
+    #[test]
+    fn lossless_codecs_are_told_apart_from_lossy_ones() {
+        for id in [codec::Id::FLAC, codec::Id::ALAC, codec::Id::WAVPACK, codec::Id::APE, codec::Id::PCM_S16LE, codec::Id::PCM_F32LE] {
+            assert_eq!(codec_is_lossless(id), Some(true), "{:?}", id);
+        }
+        for id in [codec::Id::MP3, codec::Id::AAC, codec::Id::OPUS, codec::Id::VORBIS] {
+            assert_eq!(codec_is_lossless(id), Some(false), "{:?}", id);
+        }
+        assert_eq!(codec_is_lossless(codec::Id::None), None);
+    }
+
+    #[test]
+    fn decoded_stream_reports_a_video_without_audio() {
+        let path = test_media::temp_dir("decoded-video").join("video.y4m");
+        test_media::write_y4m(&path, 25);
+        assert!(matches!(open_ramp(&path, TimeWindow::default(), RAMP_SAMPLE_RATE, 1), Err(Error::NoAudioStream)));
     }
-}
\ No newline at end of file
+}
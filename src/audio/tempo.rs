@@ -0,0 +1,65 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::{filter, format, frame, ChannelLayout};
+
+/// 变速不变调滤镜（FFmpeg atempo），输入输出都是 32 位浮点交错格式
+pub struct TempoFilter {
+    graph: filter::Graph,
+    speed: f32,
+}
+
+impl TempoFilter {
+    pub fn new(speed: f32, sample_rate: u32, channel_layout: ChannelLayout) -> Result<TempoFilter, ffmpeg::Error> {
+        let mut graph = filter::Graph::new();
+
+        let args = format!(
+            "time_base=1/{}:sample_rate={}:sample_fmt=flt:channel_layout=0x{:x}",
+            sample_rate,
+            sample_rate,
+            channel_layout.bits()
+        );
+        graph.add(&filter::find("abuffer").ok_or(ffmpeg::Error::FilterNotFound)?, "in", &args)?;
+        graph.add(&filter::find("abuffersink").ok_or(ffmpeg::Error::FilterNotFound)?, "out", "")?;
+
+        {
+            let mut out = graph.get("out").unwrap();
+            out.set_sample_format(format::Sample::F32(format::sample::Type::Packed));
+            out.set_channel_layout(channel_layout);
+            out.set_sample_rate(sample_rate);
+        }
+
+        graph.output("in", 0)?.input("out", 0)?.parse(&format!("atempo={}", speed))?;
+        graph.validate()?;
+
+        Ok(TempoFilter { graph, speed })
+    }
+
+    /// 当前滤镜的播放速度
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// 送入一帧，对每一个已经处理好的输出帧调用 output
+    pub fn process(&mut self, input: &frame::Audio, mut output: impl FnMut(&frame::Audio)) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().add(input)?;
+
+        let mut filtered = frame::Audio::empty();
+        // 没有更多输出时返回 EAGAIN
+        while self.graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            output(&filtered);
+        }
+        Ok(())
+    }
+
+    /// 输入结束，对滤镜中剩余的每一个输出帧调用 output，之后不能再送入数据
+    pub fn flush(&mut self, mut output: impl FnMut(&frame::Audio)) -> Result<(), ffmpeg::Error> {
+        self.graph.get("in").unwrap().source().flush()?;
+
+        let mut filtered = frame::Audio::empty();
+        // 所有数据输出后返回 EOF
+        while self.graph.get("out").unwrap().sink().frame(&mut filtered).is_ok() {
+            output(&filtered);
+        }
+        Ok(())
+    }
+}
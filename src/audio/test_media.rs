@@ -0,0 +1,230 @@
+//! 测试用的临时目录和音频文件
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 每个测试使用单独的临时目录
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("yako-player-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// 写入 32 位浮点的 WAV 文件，samples 为交错排列的样本，FFmpeg 解码后得到完全相同的数值
+pub fn write_wav(path: &Path, sample_rate: u32, channels: u16, samples: &[f32]) {
+    let data_size = (samples.len() * 4) as u32;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // WAVE_FORMAT_IEEE_FLOAT
+    wav.extend_from_slice(&3u16.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * channels as u32 * 4).to_le_bytes());
+    wav.extend_from_slice(&(channels * 4).to_le_bytes());
+    wav.extend_from_slice(&32u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    fs::write(path, wav).unwrap();
+}
+
+/// 写入每个样本都等于 value 的立体声文件，用于在输出中分辨是哪个文件的数据
+pub fn write_constant_wav(path: &Path, sample_rate: u32, frames: usize, value: f32) {
+    write_wav(path, sample_rate, 2, &vec![value; frames * 2]);
+}
+
+/// 写入 16 位的 FLAC 文件，samples 为交错排列的样本，每帧 4096 个样本且不压缩（VERBATIM 子帧）
+///
+/// picture 为 (MIME 类型, 图片数据)，写入一个封面类型的 PICTURE 元数据块。
+/// STREAMINFO 块位于文件的第 8 到 42 字节，FFmpeg 把它作为编码的附加数据
+pub fn write_flac(path: &Path, sample_rate: u32, channels: u16, samples: &[i16], picture: Option<(&str, &[u8])>) {
+    const BLOCK_SIZE: usize = 4096;
+    let channels = channels as usize;
+    let total_frames = (samples.len() / channels) as u64;
+
+    let mut flac = Vec::new();
+    flac.extend_from_slice(b"fLaC");
+
+    let mut stream_info = Vec::with_capacity(34);
+    stream_info.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+    stream_info.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+    // 最小和最大帧大小未知
+    stream_info.extend_from_slice(&[0; 6]);
+    let packed = (sample_rate as u64) << 44 | ((channels - 1) as u64) << 41 | 15 << 36 | total_frames;
+    stream_info.extend_from_slice(&packed.to_be_bytes());
+    // MD5 全为 0 表示不校验
+    stream_info.extend_from_slice(&[0; 16]);
+    write_metadata_block(&mut flac, 0, picture.is_none(), &stream_info);
+
+    if let Some((mime_type, data)) = picture {
+        let mut block = Vec::new();
+        // 封面（正面）
+        block.extend_from_slice(&3u32.to_be_bytes());
+        block.extend_from_slice(&(mime_type.len() as u32).to_be_bytes());
+        block.extend_from_slice(mime_type.as_bytes());
+        // 没有描述，宽、高、色深和索引颜色数未知
+        block.extend_from_slice(&[0; 20]);
+        block.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        block.extend_from_slice(data);
+        write_metadata_block(&mut flac, 6, true, &block);
+    }
+
+    for (frame_number, block) in samples.chunks(BLOCK_SIZE * channels).enumerate() {
+        let frame_start = flac.len();
+        let block_frames = block.len() / channels;
+        // 固定块大小，块大小在帧头末尾用 16 位表示，采样率和 STREAMINFO 相同
+        flac.extend_from_slice(&[0xFF, 0xF8, 0x70, ((channels - 1) << 4) as u8 | 0x08]);
+        // 帧号使用 UTF-8 的编码方式
+        match frame_number {
+            0..=0x7F => flac.push(frame_number as u8),
+            0x80..=0x7FF => flac.extend_from_slice(&[0xC0 | (frame_number >> 6) as u8, 0x80 | (frame_number & 0x3F) as u8]),
+            _ => panic!("too many frames"),
+        }
+        flac.extend_from_slice(&((block_frames - 1) as u16).to_be_bytes());
+        let header_crc = crc8(&flac[frame_start..]);
+        flac.push(header_crc);
+        for channel in 0..channels {
+            flac.push(0x02);
+            for frame in block.chunks_exact(channels) {
+                flac.extend_from_slice(&frame[channel].to_be_bytes());
+            }
+        }
+        let frame_crc = crc16(&flac[frame_start..]);
+        flac.extend_from_slice(&frame_crc.to_be_bytes());
+    }
+    fs::write(path, flac).unwrap();
+}
+
+fn write_metadata_block(flac: &mut Vec<u8>, block_type: u8, last: bool, data: &[u8]) {
+    flac.push(if last { 0x80 | block_type } else { block_type });
+    flac.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+    flac.extend_from_slice(data);
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { crc << 1 ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |mut crc, byte| {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { crc << 1 ^ 0x8005 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// 写入只有视频流的 YUV4MPEG2 文件，4x4 像素、25 帧每秒，共 frames 帧灰色画面
+pub fn write_y4m(path: &Path, frames: usize) {
+    let mut y4m = b"YUV4MPEG2 W4 H4 F25:1 Ip A1:1 C420jpeg\n".to_vec();
+    for _ in 0..frames {
+        y4m.extend_from_slice(b"FRAME\n");
+        // 4x4 的亮度平面和两个 2x2 的色度平面
+        y4m.extend_from_slice(&[0x80; 16 + 4 + 4]);
+    }
+    fs::write(path, y4m).unwrap();
+}
+
+/// 写入 frames 帧静音的 MP3 文件（MPEG-1 Layer III，44100 Hz，128 kbps，立体声），每帧 1152 个样本
+pub fn write_silent_mp3(path: &Path, frames: usize) {
+    // 不使用填充位时每帧 144 * 128000 / 44100 = 417 字节，帧头之后全为 0 的边信息和主数据解码为静音
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    fs::write(path, frame.repeat(frames)).unwrap();
+}
+
+/// 写入 25 帧每秒、4x4 像素的 AVI 视频文件，共 frames 帧，没有索引
+///
+/// audio 不为 None 时包含每个样本都等于这个值的 32 位浮点立体声音频流，每帧视频之后是同样时长的音频
+pub fn write_avi(path: &Path, sample_rate: u32, frames: usize, audio: Option<f32>) {
+    const FPS: u32 = 25;
+    let audio_frames = (sample_rate / FPS) as usize;
+    let streams = if audio.is_some() { 2 } else { 1 };
+
+    let mut avih = Vec::with_capacity(56);
+    for value in [1_000_000 / FPS, 0, 0, 0, frames as u32, 0, streams, 0, 4, 4, 0, 0, 0, 0] {
+        avih.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut hdrl = b"hdrl".to_vec();
+    write_chunk(&mut hdrl, b"avih", &avih);
+
+    // RGB24 的 BITMAPINFOHEADER
+    let mut bitmap_info = Vec::with_capacity(40);
+    bitmap_info.extend_from_slice(&40u32.to_le_bytes());
+    bitmap_info.extend_from_slice(&4i32.to_le_bytes());
+    bitmap_info.extend_from_slice(&4i32.to_le_bytes());
+    bitmap_info.extend_from_slice(&1u16.to_le_bytes());
+    bitmap_info.extend_from_slice(&24u16.to_le_bytes());
+    for value in [0u32, 48, 0, 0, 0, 0] {
+        bitmap_info.extend_from_slice(&value.to_le_bytes());
+    }
+    write_stream_list(&mut hdrl, b"vids", 1, FPS, frames as u32, 0, &bitmap_info);
+
+    if audio.is_some() {
+        // WAVE_FORMAT_IEEE_FLOAT 的 WAVEFORMATEX
+        let mut wave_format = Vec::with_capacity(18);
+        wave_format.extend_from_slice(&3u16.to_le_bytes());
+        wave_format.extend_from_slice(&2u16.to_le_bytes());
+        wave_format.extend_from_slice(&sample_rate.to_le_bytes());
+        wave_format.extend_from_slice(&(sample_rate * 8).to_le_bytes());
+        wave_format.extend_from_slice(&8u16.to_le_bytes());
+        wave_format.extend_from_slice(&32u16.to_le_bytes());
+        wave_format.extend_from_slice(&0u16.to_le_bytes());
+        write_stream_list(&mut hdrl, b"auds", 8, sample_rate * 8, (frames * audio_frames) as u32, 8, &wave_format);
+    }
+
+    let mut movi = b"movi".to_vec();
+    for _ in 0..frames {
+        write_chunk(&mut movi, b"00db", &[0x80; 48]);
+        if let Some(value) = audio {
+            let samples: Vec<u8> = (0..audio_frames * 2).flat_map(|_| value.to_le_bytes()).collect();
+            write_chunk(&mut movi, b"01wb", &samples);
+        }
+    }
+
+    let mut avi = b"AVI ".to_vec();
+    write_chunk(&mut avi, b"LIST", &hdrl);
+    write_chunk(&mut avi, b"LIST", &movi);
+    let mut riff = Vec::with_capacity(avi.len() + 8);
+    write_chunk(&mut riff, b"RIFF", &avi);
+    fs::write(path, riff).unwrap();
+}
+
+/// AVI 的流头和格式，scale 和 rate 为时间单位，sample_size 为 0 表示每个数据块是一帧
+fn write_stream_list(hdrl: &mut Vec<u8>, stream_type: &[u8; 4], scale: u32, rate: u32, length: u32, sample_size: u32, format: &[u8]) {
+    let mut strh = stream_type.to_vec();
+    // 编码器、标记、优先级和语言、起始帧
+    strh.extend_from_slice(&[0; 16]);
+    for value in [scale, rate, 0, length, 0, u32::MAX, sample_size] {
+        strh.extend_from_slice(&value.to_le_bytes());
+    }
+    // 画面区域
+    strh.extend_from_slice(&[0; 8]);
+    let mut strl = b"strl".to_vec();
+    write_chunk(&mut strl, b"strh", &strh);
+    write_chunk(&mut strl, b"strf", format);
+    write_chunk(hdrl, b"LIST", &strl);
+}
+
+/// RIFF 数据块，长度为奇数时补一个字节
+fn write_chunk(output: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(id);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        output.push(0);
+    }
+}
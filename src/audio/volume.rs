@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 /// 改变音量
 pub fn change_volume_db(sample: f32, db_gain: f32) -> f32 {
     (sample * db_gain_to_amplitude(db_gain)).clamp(-1., 1.)
@@ -8,6 +10,11 @@ pub fn db_gain_to_amplitude(db_gain: f32) -> f32 {
     10f32.powf(db_gain * 0.05)
 }
 
+/// 振幅比例转分贝
+pub fn amplitude_to_db_gain(amplitude: f32) -> f32 {
+    20. * amplitude.log10()
+}
+
 /// 转换音量等级到分贝
 /// 
 /// 设 a = 最低分贝，b = 系数
@@ -28,4 +35,81 @@ pub fn volume_level_to_db(volume: f32) -> f32 {
         let c = lowest_db * (-coefficient).exp();
         ((lowest_db + c) * (-coefficient * volume as f64).exp() - c).clamp(f64::MIN, 0.) as f32
     }
-}
\ No newline at end of file
+}
+
+/// 音量等级（0 到 1）到分贝的映射
+#[derive(Clone)]
+pub enum VolumeCurve {
+    /// 默认的指数曲线，见 volume_level_to_db
+    Exponential,
+    /// 分贝值在 min_db 到 0 之间线性变化
+    LinearDb {
+        min_db: f32,
+    },
+    /// 振幅比例等于音量等级，音量为 0 时静音
+    LinearAmplitude,
+    /// 分贝值表，表项均匀分布在 0 到 1 之间，表项之间线性插值
+    Table(Arc<Vec<f32>>),
+    /// 用户提供的映射函数
+    Custom(Arc<dyn Fn(f32) -> f32 + Send + Sync>),
+}
+
+impl VolumeCurve {
+    /// 转换音量等级到分贝，音量等级超出 0 到 1 时截断
+    pub fn to_db(&self, volume: f32) -> f32 {
+        let volume = volume.clamp(0., 1.);
+        match self {
+            VolumeCurve::Exponential => volume_level_to_db(volume),
+            VolumeCurve::LinearDb { min_db } => min_db * (1. - volume),
+            VolumeCurve::LinearAmplitude => 20. * volume.log10(),
+            VolumeCurve::Table(table) => {
+                let position = volume * (table.len() - 1) as f32;
+                let index = (position as usize).min(table.len() - 2);
+                let fraction = position - index as f32;
+                table[index] + (table[index + 1] - table[index]) * fraction
+            },
+            VolumeCurve::Custom(curve) => curve(volume),
+        }
+    }
+}
+
+impl Default for VolumeCurve {
+    fn default() -> Self {
+        VolumeCurve::Exponential
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_db_midpoint_is_half_the_range() {
+        let curve = VolumeCurve::LinearDb { min_db: -60. };
+        assert_eq!(curve.to_db(0.5), -30.);
+        assert_eq!(curve.to_db(0.), -60.);
+        assert_eq!(curve.to_db(1.), 0.);
+    }
+
+    #[test]
+    fn linear_amplitude_midpoint_is_half_the_amplitude() {
+        let db = VolumeCurve::LinearAmplitude.to_db(0.5);
+        assert!((db_gain_to_amplitude(db) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn table_interpolates_between_entries() {
+        let curve = VolumeCurve::Table(Arc::new(vec![-80., -20., 0.]));
+        assert_eq!(curve.to_db(0.5), -20.);
+        assert_eq!(curve.to_db(0.25), -50.);
+        assert_eq!(curve.to_db(1.), 0.);
+    }
+
+    #[test]
+    fn volume_outside_the_range_is_clamped() {
+        let curve = VolumeCurve::LinearDb { min_db: -60. };
+        assert_eq!(curve.to_db(2.), 0.);
+        assert_eq!(curve.to_db(-1.), -60.);
+        assert_eq!(VolumeCurve::Exponential.to_db(1.5), 0.);
+    }
+}
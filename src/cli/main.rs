@@ -0,0 +1,401 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use player_core::audio::loudness::LoudnessMeter;
+use player_core::audio::source::FFmpegSource;
+use player_core::info::probe;
+use player_core::player::{YakoPlayer, Player};
+use player_core::player::export::ExportFormat;
+
+/// 命令行用法
+const USAGE: &str = "\
+Usage: yako-cli [--json] <command> [arguments]
+
+Commands:
+  probe FILE                  Show the format and tags of FILE
+  render FILE OUT [--format wav16|wav32f|flac]
+                              Decode FILE into OUT without an audio device
+  loudness FILE               Measure the integrated loudness and sample peak of FILE
+  cover FILE OUT              Save the embedded cover art of FILE to OUT
+  play FILE                   Play FILE on the default audio device until it ends
+
+Options:
+  --json                      Print the result as JSON";
+
+/// 执行失败的退出码
+const EXIT_FAILED: i32 = 1;
+/// 参数错误的退出码
+const EXIT_USAGE: i32 = 2;
+
+/// 执行失败的原因
+#[derive(Debug)]
+enum CliError {
+    /// 参数不正确，同时显示用法
+    Usage(String),
+    Failed(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) | CliError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl CliError {
+    /// 进程的退出码
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Failed(_) => EXIT_FAILED,
+        }
+    }
+}
+
+/// 库中的各种错误都转换为执行失败
+fn failed<E: fmt::Display>(err: E) -> CliError {
+    CliError::Failed(err.to_string())
+}
+
+/// 把警告和错误输出到标准错误，标准输出只有命令的结果
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// 解析后的命令行参数
+struct CommandLine {
+    command: String,
+    /// 命令之后的位置参数
+    arguments: Vec<String>,
+    /// render 的 --format
+    format: ExportFormat,
+}
+
+impl CommandLine {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<CommandLine, CliError> {
+        let mut format = ExportFormat::Wav16;
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // 出错时也要按 JSON 输出，在解析之前就已经检查
+                "--json" => {},
+                "--format" => format = match args.next().as_deref() {
+                    Some("wav16") => ExportFormat::Wav16,
+                    Some("wav32f") => ExportFormat::Wav32f,
+                    Some("flac") => ExportFormat::Flac,
+                    Some(other) => return Err(CliError::Usage(format!("unknown output format: {}", other))),
+                    None => return Err(CliError::Usage("--format requires a value".to_string())),
+                },
+                "-h" | "--help" => return Err(CliError::Usage(String::new())),
+                _ => positional.push(arg),
+            }
+        }
+        if positional.is_empty() {
+            return Err(CliError::Usage("no command given".to_string()));
+        }
+        let command = positional.remove(0);
+        Ok(CommandLine { command, arguments: positional, format })
+    }
+
+    /// 检查位置参数的数量并返回为路径
+    fn paths(&self, names: &[&str]) -> Result<Vec<PathBuf>, CliError> {
+        if self.arguments.len() != names.len() {
+            return Err(CliError::Usage(format!("{} expects {}", self.command, names.join(" "))));
+        }
+        Ok(self.arguments.iter().map(PathBuf::from).collect())
+    }
+}
+
+/// 按 --json 输出结果，否则每个字段一行
+fn print_result(json: bool, result: &Value) {
+    if json {
+        println!("{}", result);
+        return;
+    }
+    if let Value::Object(fields) = result {
+        for (key, value) in fields {
+            match value {
+                Value::Null => println!("{}: -", key),
+                Value::String(text) => println!("{}: {}", key, text),
+                other => println!("{}: {}", key, other),
+            }
+        }
+    }
+}
+
+/// 格式和标签，使用播放器打开文件时相同的探测和元数据读取
+fn probe_file(path: &Path) -> Result<Value, CliError> {
+    let file_probe = probe::probe(&path).map_err(failed)?;
+    let player = YakoPlayer::new();
+    let media_info = player.probe_batch(&[path.to_path_buf()]).pop()
+        .ok_or_else(|| CliError::Failed("the probe returned no result".to_string()))?
+        .map_err(failed)?;
+    Ok(json!({
+        "path": path.display().to_string(),
+        "container": file_probe.container,
+        "codec": media_info.codec,
+        "duration_ms": media_info.duration,
+        "bitrate": media_info.bitrate,
+        "sample_rate": media_info.sample_rate,
+        "channels": media_info.channels,
+        "bits_per_sample": media_info.bits_per_sample,
+        "lossless": media_info.is_lossless,
+        "has_video": media_info.has_video,
+        "seekable": file_probe.is_seekable,
+        "title": media_info.title,
+        "artist": media_info.artist,
+        "album": media_info.album,
+        "track_gain_db": media_info.replay_gain.track_gain,
+        "album_gain_db": media_info.replay_gain.album_gain,
+        "has_cover": media_info.cover.is_some(),
+    }))
+}
+
+/// 不经过音频设备导出整个文件
+fn render_file(path: &Path, output: &Path, format: ExportFormat) -> Result<Value, CliError> {
+    let duration = probe::probe(&path).map_err(failed)?.duration_ms;
+    if duration <= 0 {
+        return Err(CliError::Failed(format!("the duration of {} is unknown", path.display())));
+    }
+    let player = YakoPlayer::new();
+    player.export_range(&path, 0, duration, output.to_path_buf(), format, None)
+        .map_err(failed)?
+        .wait()
+        .map_err(failed)?;
+    Ok(json!({
+        "path": path.display().to_string(),
+        "output": output.display().to_string(),
+        "duration_ms": duration,
+    }))
+}
+
+/// 按 EBU R128 测量整个文件
+fn measure_loudness(path: &Path) -> Result<Value, CliError> {
+    let frames = FFmpegSource::frames(&path).map_err(failed)?;
+    let mut meter = LoudnessMeter::new(frames.sample_rate(), frames.channels());
+    for samples in frames {
+        match samples {
+            Ok(samples) => meter.push(&samples),
+            Err(err) => log::warn!("skipped an undecodable packet: {}", err),
+        }
+    }
+    let peak = meter.peak();
+    Ok(json!({
+        "path": path.display().to_string(),
+        "integrated_lufs": meter.integrated_lufs(),
+        "replay_gain_db": meter.replay_gain(),
+        "sample_peak": peak,
+        "sample_peak_dbfs": if peak > 0. { Some(20. * peak.log10()) } else { None },
+    }))
+}
+
+/// 保存内嵌的封面，数据原样写入，不转换图片格式
+fn save_cover(path: &Path, output: &Path) -> Result<Value, CliError> {
+    let player = YakoPlayer::new();
+    let media_info = player.probe_batch(&[path.to_path_buf()]).pop()
+        .ok_or_else(|| CliError::Failed("the probe returned no result".to_string()))?
+        .map_err(failed)?;
+    let cover = media_info.cover
+        .ok_or_else(|| CliError::Failed(format!("{} has no embedded cover art", path.display())))?;
+    std::fs::write(output, &cover).map_err(failed)?;
+    Ok(json!({
+        "path": path.display().to_string(),
+        "output": output.display().to_string(),
+        "size": cover.len(),
+    }))
+}
+
+/// 在默认设备上播放到结尾
+fn play_file(path: &Path) -> Result<Value, CliError> {
+    let mut player = YakoPlayer::new();
+    player.open(&path).map_err(failed)?;
+    player.play().map_err(failed)?;
+    let ended = player.wait_for_end(None);
+    let stats = player.get_playback_stats();
+    player.close().map_err(failed)?;
+    if !ended {
+        return Err(CliError::Failed("playback was interrupted".to_string()));
+    }
+    Ok(json!({
+        "path": path.display().to_string(),
+        "played_ms": stats.played_ms,
+    }))
+}
+
+fn run(command_line: &CommandLine) -> Result<Value, CliError> {
+    match command_line.command.as_str() {
+        "probe" => probe_file(&command_line.paths(&["FILE"])?[0]),
+        "render" => {
+            let paths = command_line.paths(&["FILE", "OUT"])?;
+            render_file(&paths[0], &paths[1], command_line.format)
+        },
+        "loudness" => measure_loudness(&command_line.paths(&["FILE"])?[0]),
+        "cover" => {
+            let paths = command_line.paths(&["FILE", "OUT"])?;
+            save_cover(&paths[0], &paths[1])
+        },
+        "play" => play_file(&command_line.paths(&["FILE"])?[0]),
+        other => Err(CliError::Usage(format!("unknown command: {}", other))),
+    }
+}
+
+pub fn main() {
+    if log::set_boxed_logger(Box::new(StderrLogger)).is_ok() {
+        log::set_max_level(LevelFilter::Warn);
+        player_core::logging::forward_ffmpeg_log();
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    let result = CommandLine::parse(args).and_then(|command_line| run(&command_line));
+    match result {
+        Ok(result) => print_result(json, &result),
+        Err(err) => {
+            if json {
+                println!("{}", json!({ "error": err.to_string() }));
+            } else if !err.to_string().is_empty() {
+                eprintln!("error: {}", err);
+            }
+            if let CliError::Usage(_) = err {
+                eprintln!("{}", USAGE);
+            }
+            std::process::exit(err.exit_code());
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn parse(args: &[&str]) -> Result<CommandLine, CliError> {
+        CommandLine::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yako-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 写入 1 秒、每个样本都等于 value 的 32 位浮点立体声 WAV 文件
+    fn write_wav(path: &Path, value: f32) {
+        let data_size = SAMPLE_RATE * 2 * 4;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&3u16.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 8).to_le_bytes());
+        wav.extend_from_slice(&8u16.to_le_bytes());
+        wav.extend_from_slice(&32u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for _ in 0..SAMPLE_RATE * 2 {
+            wav.extend_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(path, wav).unwrap();
+    }
+
+    fn run_args(args: &[&str]) -> Result<Value, CliError> {
+        run(&parse(args)?)
+    }
+
+    #[test]
+    fn options_are_accepted_anywhere() {
+        let command_line = parse(&["--json", "render", "in.flac", "--format", "flac", "out.flac"]).unwrap();
+        assert_eq!(command_line.command, "render");
+        assert_eq!(command_line.arguments, ["in.flac", "out.flac"]);
+        assert_eq!(command_line.format, ExportFormat::Flac);
+        assert_eq!(parse(&["render", "a", "b"]).unwrap().format, ExportFormat::Wav16);
+    }
+
+    #[test]
+    fn bad_arguments_are_usage_errors() {
+        let cases: [&[&str]; 8] = [&[], &["--json"], &["render", "a", "b", "--format", "mp3"], &["render", "a", "b", "--format"],
+            &["--help"], &["transcode", "a"], &["probe"], &["cover", "a"]];
+        for args in cases {
+            let err = run_args(args).unwrap_err();
+            assert!(matches!(err, CliError::Usage(_)), "{:?} gave {:?}", args, err);
+            assert_eq!(err.exit_code(), EXIT_USAGE);
+        }
+    }
+
+    #[test]
+    fn probe_reports_the_format() {
+        let dir = temp_dir("probe");
+        let path = dir.join("tone.wav");
+        write_wav(&path, 0.25);
+        let result = run_args(&["probe", path.to_str().unwrap()]).unwrap();
+        assert_eq!(result["sample_rate"], SAMPLE_RATE);
+        assert_eq!(result["channels"], 2);
+        assert_eq!(result["duration_ms"], 1000);
+        assert_eq!(result["lossless"], true);
+        assert_eq!(result["has_video"], false);
+        assert_eq!(result["has_cover"], false);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn render_writes_a_file_of_the_same_length() {
+        let dir = temp_dir("render");
+        let path = dir.join("tone.wav");
+        let output = dir.join("rendered.wav");
+        write_wav(&path, 0.25);
+        let result = run_args(&["render", path.to_str().unwrap(), output.to_str().unwrap(), "--format", "wav32f"]).unwrap();
+        assert_eq!(result["duration_ms"], 1000);
+        let rendered = run_args(&["probe", output.to_str().unwrap()]).unwrap();
+        assert_eq!(rendered["duration_ms"], 1000);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loudness_reports_the_sample_peak() {
+        let dir = temp_dir("loudness");
+        let path = dir.join("tone.wav");
+        write_wav(&path, 0.25);
+        let result = run_args(&["loudness", path.to_str().unwrap()]).unwrap();
+        assert!((result["sample_peak"].as_f64().unwrap() - 0.25).abs() < 1e-6);
+        assert!((result["sample_peak_dbfs"].as_f64().unwrap() + 12.04).abs() < 0.01);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_are_reported_with_their_own_exit_code() {
+        let dir = temp_dir("failures");
+        let path = dir.join("tone.wav");
+        write_wav(&path, 0.25);
+        let missing = dir.join("missing.wav");
+        let cover = dir.join("cover.jpg");
+        let cases: [&[&str]; 2] = [&["probe", missing.to_str().unwrap()], &["cover", path.to_str().unwrap(), cover.to_str().unwrap()]];
+        for args in cases {
+            let err = run_args(args).unwrap_err();
+            assert!(matches!(err, CliError::Failed(_)), "{:?} gave {:?}", args, err);
+            assert_eq!(err.exit_code(), EXIT_FAILED);
+        }
+        assert!(!cover.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
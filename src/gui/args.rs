@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use player_core::player::scan;
+
+/// 表示从标准输入读取的命令行参数
+pub const STDIN_ARGUMENT: &str = "-";
+
+/// 解析后的命令行参数
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    /// 展开目录后的文件列表
+    pub paths: Vec<PathBuf>,
+    /// 是否从标准输入读取
+    pub stdin: bool,
+    /// --device NAME：输出设备名称
+    pub device: Option<String>,
+    /// --volume N：初始音量（0 ~ 100）
+    pub volume: Option<f32>,
+    /// 无效的参数和路径，启动后显示在错误提示中
+    pub errors: Vec<String>,
+}
+
+impl CommandLine {
+    /// 解析命令行参数（不包括程序名）
+    pub fn parse(args: impl IntoIterator<Item = String>) -> CommandLine {
+        let mut command_line = CommandLine::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--device" => match args.next() {
+                    Some(name) => command_line.device = Some(name),
+                    None => command_line.errors.push("--device requires a device name".to_string()),
+                },
+                "--volume" => match args.next().map(|value| value.parse::<f32>()) {
+                    Some(Ok(volume)) if (0. ..=100.).contains(&volume) => command_line.volume = Some(volume / 100.),
+                    Some(_) => command_line.errors.push("--volume must be a number between 0 and 100".to_string()),
+                    None => command_line.errors.push("--volume requires a value".to_string()),
+                },
+                STDIN_ARGUMENT => command_line.stdin = true,
+                _ => command_line.add_path(Path::new(&arg)),
+            }
+        }
+        command_line
+    }
+
+    /// 添加文件或者递归展开目录，无效的路径记录到错误列表中
+    fn add_path(&mut self, path: &Path) {
+        if path.is_dir() {
+            let summary = scan::scan_directory(path, true);
+            for warning in summary.warnings {
+                log::warn!("{}", warning);
+            }
+            if summary.files.is_empty() {
+                self.errors.push(format!("No audio files found in {}", path.display()));
+            }
+            self.paths.extend(summary.files);
+        } else if path.is_file() {
+            self.paths.push(path.to_path_buf());
+        } else {
+            self.errors.push(format!("File not found: {}", path.display()));
+        }
+    }
+}
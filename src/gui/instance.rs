@@ -0,0 +1,157 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use iced_native::futures::channel::mpsc;
+use iced_native::futures::stream::{self, BoxStream, StreamExt};
+use iced_native::subscription::{EventStream, Recipe};
+use iced_native::{Hasher, Subscription};
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream, NameTypeSupport};
+
+/// 本地通信端点名称
+const ENDPOINT_NAME: &str = "yako-player";
+
+/// 启动时的实例身份
+pub enum Instance {
+    /// 第一个启动的实例，负责接收其他实例转发的文件
+    Primary(IncomingFiles),
+    /// 已经有实例在运行，文件已转发给它
+    Secondary,
+}
+
+/// 尝试成为唯一的播放器实例
+///
+/// 如果已经有实例在运行，把文件路径转发给它；否则创建通信端点并在后台线程中等待其他实例发来的文件
+pub fn acquire(paths: &[String]) -> Instance {
+    match connect() {
+        Ok(stream) => match forward_paths(stream, paths) {
+            Ok(_) => return Instance::Secondary,
+            Err(err) => log::warn!("Failed to forward files to the running instance: {}", err),
+        },
+        Err(_) => {
+            // 连接失败说明没有正在运行的实例，端点文件可能是崩溃的实例遗留的
+        },
+    }
+
+    let listener = match bind() {
+        Ok(listener) => listener,
+        Err(_) => {
+            // 端点已经存在，可能是另一个实例刚刚启动，再连接一次，仍然无法连接时才是崩溃的实例遗留的端点
+            if let Ok(stream) = connect() {
+                // 端点属于正在运行的实例，不能删除
+                if let Err(err) = forward_paths(stream, paths) {
+                    log::warn!("Failed to forward files to the running instance: {}", err);
+                    return Instance::Primary(IncomingFiles::default());
+                }
+                return Instance::Secondary;
+            }
+            remove_stale_endpoint();
+            match bind() {
+                Ok(listener) => listener,
+                Err(err) => {
+                    log::warn!("Failed to create the single-instance endpoint: {}", err);
+                    return Instance::Primary(IncomingFiles::default());
+                }
+            }
+        }
+    };
+
+    let (sender, receiver) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        for connection in listener.incoming() {
+            let connection = match connection {
+                Ok(connection) => connection,
+                Err(err) => {
+                    log::warn!("Failed to accept a connection from another instance: {}", err);
+                    continue;
+                }
+            };
+            let paths: Vec<PathBuf> = BufReader::new(connection)
+                .lines()
+                .filter_map(|line| line.ok())
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect();
+            if !paths.is_empty() && sender.unbounded_send(paths).is_err() {
+                // 界面已经退出
+                return;
+            }
+        }
+    });
+
+    Instance::Primary(IncomingFiles {
+        receiver: Arc::new(Mutex::new(Some(receiver))),
+    })
+}
+
+fn forward_paths(mut stream: LocalSocketStream, paths: &[String]) -> io::Result<()> {
+    for path in paths {
+        // 两个实例的工作目录可能不同，转发绝对路径
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        writeln!(stream, "{}", path.display())?;
+    }
+    stream.flush()
+}
+
+/// 非 Windows 平台使用的套接字文件路径
+fn endpoint_path() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_default();
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("{}-{}.sock", ENDPOINT_NAME, user))
+}
+
+fn uses_namespaced_endpoint() -> bool {
+    NameTypeSupport::query() == NameTypeSupport::OnlyNamespaced
+}
+
+fn connect() -> io::Result<LocalSocketStream> {
+    if uses_namespaced_endpoint() {
+        LocalSocketStream::connect(format!("@{}", ENDPOINT_NAME))
+    } else {
+        LocalSocketStream::connect(endpoint_path())
+    }
+}
+
+fn bind() -> io::Result<LocalSocketListener> {
+    if uses_namespaced_endpoint() {
+        LocalSocketListener::bind(format!("@{}", ENDPOINT_NAME))
+    } else {
+        LocalSocketListener::bind(endpoint_path())
+    }
+}
+
+fn remove_stale_endpoint() {
+    if !uses_namespaced_endpoint() {
+        let _ = std::fs::remove_file(endpoint_path());
+    }
+}
+
+/// 其他实例转发来的文件
+#[derive(Debug, Clone, Default)]
+pub struct IncomingFiles {
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<Vec<PathBuf>>>>>,
+}
+
+impl IncomingFiles {
+    pub fn subscription(&self) -> Subscription<Vec<PathBuf>> {
+        Subscription::from_recipe(self.clone())
+    }
+}
+
+impl Recipe<Hasher, (iced_native::Event, iced_native::event::Status)> for IncomingFiles {
+    type Output = Vec<PathBuf>;
+
+    fn hash(&self, state: &mut Hasher) {
+        use std::hash::Hash;
+
+        std::any::TypeId::of::<Self>().hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: EventStream) -> BoxStream<'static, Self::Output> {
+        match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver.boxed(),
+            None => stream::empty().boxed(),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// 把日志输出到标准错误
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// 安装输出到标准错误的 logger，同时转发 FFmpeg 的警告和错误
+pub fn init() {
+    let level = if cfg!(debug_assertions) { LevelFilter::Debug } else { LevelFilter::Info };
+    if log::set_boxed_logger(Box::new(StderrLogger)).is_ok() {
+        log::set_max_level(level);
+        player_core::logging::forward_ffmpeg_log();
+    }
+}
+
@@ -0,0 +1,174 @@
+//! 系统媒体控制集成
+//!
+//! 开启 `media-controls` 特性后，Windows 上注册到系统媒体传输控件（SMTC），Linux 上提供 MPRIS2 D-Bus 对象，
+//! 媒体键和系统媒体面板的操作会作为事件发送给界面。未开启特性或不支持的平台上所有操作都不做任何事
+
+#[cfg(all(feature = "media-controls", target_os = "linux"))]
+mod mpris;
+#[cfg(all(feature = "media-controls", windows))]
+mod smtc;
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use iced_native::futures::channel::mpsc;
+use iced_native::futures::stream::{self, BoxStream, StreamExt};
+use iced_native::subscription::{EventStream, Recipe};
+use iced_native::{Hasher, Subscription};
+
+/// 系统发来的控制命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaControlEvent {
+    Play,
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    /// 跳转到指定位置（毫秒）
+    SetPosition(i64),
+    /// 相对当前位置跳转（毫秒）
+    Seek(i64),
+}
+
+/// 播放状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl Default for PlaybackStatus {
+    fn default() -> Self {
+        Self::Stopped
+    }
+}
+
+/// 显示在系统媒体面板上的曲目信息
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// 总长度（毫秒），未知时为 0
+    pub duration: i64,
+    /// 封面图片数据
+    pub cover: Option<Vec<u8>>,
+}
+
+/// 平台相关的实现
+trait Backend {
+    fn set_metadata(&mut self, metadata: &TrackMetadata);
+    fn set_playback(&mut self, status: PlaybackStatus, position: i64);
+    fn seeked(&mut self, position: i64);
+}
+
+/// 系统媒体控制
+#[derive(Default)]
+pub struct MediaControls {
+    backend: Option<Box<dyn Backend>>,
+    events: MediaControlEvents,
+}
+
+impl MediaControls {
+    /// 注册到系统媒体控制，失败或者不支持时返回一个不做任何事的实例
+    pub fn new() -> MediaControls {
+        let (sender, receiver) = mpsc::unbounded();
+        let backend = create_backend(sender);
+        MediaControls {
+            backend,
+            events: MediaControlEvents {
+                receiver: Arc::new(Mutex::new(Some(receiver))),
+            },
+        }
+    }
+
+    /// 曲目改变时更新显示的信息
+    pub fn set_metadata(&mut self, metadata: &TrackMetadata) {
+        if let Some(backend) = self.backend.as_mut() {
+            backend.set_metadata(metadata);
+        }
+    }
+
+    /// 更新播放状态和当前位置（毫秒）
+    pub fn set_playback(&mut self, status: PlaybackStatus, position: i64) {
+        if let Some(backend) = self.backend.as_mut() {
+            backend.set_playback(status, position);
+        }
+    }
+
+    /// 通知系统播放位置发生了跳转（毫秒）
+    pub fn seeked(&mut self, position: i64) {
+        if let Some(backend) = self.backend.as_mut() {
+            backend.seeked(position);
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<MediaControlEvent> {
+        Subscription::from_recipe(self.events.clone())
+    }
+}
+
+#[cfg(all(feature = "media-controls", target_os = "linux"))]
+fn create_backend(sender: mpsc::UnboundedSender<MediaControlEvent>) -> Option<Box<dyn Backend>> {
+    match mpris::Mpris::new(sender) {
+        Ok(mpris) => Some(Box::new(mpris)),
+        Err(err) => {
+            log::warn!("Failed to register the MPRIS service: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(all(feature = "media-controls", windows))]
+fn create_backend(sender: mpsc::UnboundedSender<MediaControlEvent>) -> Option<Box<dyn Backend>> {
+    match smtc::Smtc::new(sender) {
+        Ok(smtc) => Some(Box::new(smtc)),
+        Err(err) => {
+            log::warn!("Failed to register the system media transport controls: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(not(all(feature = "media-controls", any(target_os = "linux", windows))))]
+fn create_backend(_sender: mpsc::UnboundedSender<MediaControlEvent>) -> Option<Box<dyn Backend>> {
+    None
+}
+
+/// 把封面写入临时文件，系统媒体面板只能通过路径读取封面
+#[allow(dead_code)]
+fn write_cover_file(cover: &[u8]) -> Option<PathBuf> {
+    let path = std::env::temp_dir().join(format!("yako-player-cover-{}", std::process::id()));
+    match std::fs::write(&path, cover) {
+        Ok(_) => Some(path),
+        Err(err) => {
+            log::warn!("Failed to write the cover image: {}", err);
+            None
+        }
+    }
+}
+
+/// 系统发来的控制命令
+#[derive(Debug, Clone, Default)]
+struct MediaControlEvents {
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<MediaControlEvent>>>>,
+}
+
+impl Recipe<Hasher, (iced_native::Event, iced_native::event::Status)> for MediaControlEvents {
+    type Output = MediaControlEvent;
+
+    fn hash(&self, state: &mut Hasher) {
+        use std::hash::Hash;
+
+        std::any::TypeId::of::<Self>().hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: EventStream) -> BoxStream<'static, Self::Output> {
+        match self.receiver.lock().unwrap().take() {
+            Some(receiver) => receiver.boxed(),
+            None => stream::empty().boxed(),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::Connection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::{Message, Path};
+use dbus_crossroads::{Crossroads, IfaceBuilder};
+use iced_native::futures::channel::mpsc::UnboundedSender;
+
+use super::{write_cover_file, Backend, MediaControlEvent, PlaybackStatus, TrackMetadata};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.yako_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// 服务线程处理消息的间隔
+const PROCESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 界面和服务线程共享的状态
+#[derive(Default)]
+struct State {
+    metadata: TrackMetadata,
+    cover_url: Option<String>,
+    /// 每次换曲目时递增，用来生成曲目 ID
+    track_number: u64,
+    status: PlaybackStatus,
+    /// 当前位置（毫秒）
+    position: i64,
+    /// 需要发送 PropertiesChanged 信号
+    properties_changed: bool,
+    /// 需要发送 Seeked 信号的位置（毫秒）
+    seeked: Option<i64>,
+    /// 界面已经关闭，服务线程应该退出
+    closed: bool,
+}
+
+impl State {
+    fn track_id(&self) -> Path<'static> {
+        Path::new(format!("/org/yako_player/track/{}", self.track_number)).unwrap()
+    }
+
+    fn status_name(&self) -> &'static str {
+        match self.status {
+            PlaybackStatus::Playing => "Playing",
+            PlaybackStatus::Paused => "Paused",
+            PlaybackStatus::Stopped => "Stopped",
+        }
+    }
+
+    fn metadata_map(&self) -> PropMap {
+        let mut map = PropMap::new();
+        map.insert("mpris:trackid".to_string(), Variant(Box::new(self.track_id()) as Box<dyn RefArg>));
+        if self.metadata.duration > 0 {
+            map.insert("mpris:length".to_string(), Variant(Box::new(self.metadata.duration * 1000) as Box<dyn RefArg>));
+        }
+        if let Some(title) = self.metadata.title.as_ref() {
+            map.insert("xesam:title".to_string(), Variant(Box::new(title.clone()) as Box<dyn RefArg>));
+        }
+        if let Some(artist) = self.metadata.artist.as_ref() {
+            map.insert("xesam:artist".to_string(), Variant(Box::new(vec![artist.clone()]) as Box<dyn RefArg>));
+        }
+        if let Some(album) = self.metadata.album.as_ref() {
+            map.insert("xesam:album".to_string(), Variant(Box::new(album.clone()) as Box<dyn RefArg>));
+        }
+        if let Some(cover_url) = self.cover_url.as_ref() {
+            map.insert("mpris:artUrl".to_string(), Variant(Box::new(cover_url.clone()) as Box<dyn RefArg>));
+        }
+        map
+    }
+}
+
+/// 注册在 D-Bus 上的对象数据
+struct ObjectData {
+    state: Arc<Mutex<State>>,
+    sender: UnboundedSender<MediaControlEvent>,
+}
+
+impl ObjectData {
+    fn send(&self, event: MediaControlEvent) {
+        let _ = self.sender.unbounded_send(event);
+    }
+}
+
+/// Linux MPRIS2 服务
+pub struct Mpris {
+    state: Arc<Mutex<State>>,
+}
+
+impl Mpris {
+    pub fn new(sender: UnboundedSender<MediaControlEvent>) -> Result<Mpris, dbus::Error> {
+        let state = Arc::new(Mutex::new(State::default()));
+
+        // 连接在服务线程上创建，注册结果通过通道返回
+        let (result_sender, result_receiver) = std_mpsc::channel();
+        let thread_state = state.clone();
+        std::thread::spawn(move || {
+            let connection = match Connection::new_session()
+                .and_then(|connection| connection.request_name(BUS_NAME, false, true, false).map(|_| connection)) {
+                Ok(connection) => {
+                    let _ = result_sender.send(Ok(()));
+                    connection
+                },
+                Err(err) => {
+                    let _ = result_sender.send(Err(err));
+                    return;
+                }
+            };
+            serve(connection, thread_state, sender);
+        });
+
+        match result_receiver.recv() {
+            Ok(Ok(_)) => Ok(Mpris { state }),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(dbus::Error::new_failed("the MPRIS service thread exited unexpectedly")),
+        }
+    }
+}
+
+impl Backend for Mpris {
+    fn set_metadata(&mut self, metadata: &TrackMetadata) {
+        let cover_url = metadata.cover.as_ref()
+            .and_then(|cover| write_cover_file(cover))
+            .map(|path| format!("file://{}", path.display()));
+
+        let mut state = self.state.lock().unwrap();
+        state.metadata = metadata.clone();
+        state.cover_url = cover_url;
+        state.track_number += 1;
+        state.position = 0;
+        state.properties_changed = true;
+    }
+
+    fn set_playback(&mut self, status: PlaybackStatus, position: i64) {
+        let mut state = self.state.lock().unwrap();
+        // 客户端会自己根据播放状态推算位置，只有状态改变时才需要通知
+        if state.status != status {
+            state.status = status;
+            state.properties_changed = true;
+        }
+        state.position = position;
+    }
+
+    fn seeked(&mut self, position: i64) {
+        let mut state = self.state.lock().unwrap();
+        state.position = position;
+        state.seeked = Some(position);
+    }
+}
+
+impl Drop for Mpris {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().closed = true;
+    }
+}
+
+fn serve(connection: Connection, state: Arc<Mutex<State>>, sender: UnboundedSender<MediaControlEvent>) {
+    let mut crossroads = Crossroads::new();
+
+    let root = crossroads.register(ROOT_INTERFACE, |builder: &mut IfaceBuilder<ObjectData>| {
+        builder.method("Raise", (), (), |_, _, _: ()| Ok(()));
+        builder.method("Quit", (), (), |_, _, _: ()| Ok(()));
+        builder.property("CanQuit").get(|_, _| Ok(false));
+        builder.property("CanRaise").get(|_, _| Ok(false));
+        builder.property("HasTrackList").get(|_, _| Ok(false));
+        builder.property("Identity").get(|_, _| Ok("YakoPlayer".to_string()));
+        builder.property("SupportedUriSchemes").get(|_, _| Ok(vec!["file".to_string()]));
+        builder.property("SupportedMimeTypes").get(|_, _| Ok(Vec::<String>::new()));
+    });
+
+    let player = crossroads.register(PLAYER_INTERFACE, |builder: &mut IfaceBuilder<ObjectData>| {
+        let commands = [
+            ("Play", MediaControlEvent::Play),
+            ("Pause", MediaControlEvent::Pause),
+            ("PlayPause", MediaControlEvent::Toggle),
+            ("Stop", MediaControlEvent::Stop),
+            ("Next", MediaControlEvent::Next),
+            ("Previous", MediaControlEvent::Previous),
+        ];
+        for (name, event) in commands {
+            builder.method(name, (), (), move |_, data: &mut ObjectData, _: ()| {
+                data.send(event);
+                Ok(())
+            });
+        }
+        // MPRIS 的时间单位是微秒
+        builder.method("Seek", ("Offset",), (), |_, data: &mut ObjectData, (offset,): (i64,)| {
+            data.send(MediaControlEvent::Seek(offset / 1000));
+            Ok(())
+        });
+        builder.method("SetPosition", ("TrackId", "Position"), (), |_, data: &mut ObjectData, (track_id, position): (Path<'static>, i64)| {
+            // 曲目已经切换时忽略过期的请求
+            if track_id == data.state.lock().unwrap().track_id() && position >= 0 {
+                data.send(MediaControlEvent::SetPosition(position / 1000));
+            }
+            Ok(())
+        });
+        builder.signal::<(i64,), _>("Seeked", ("Position",));
+
+        builder.property("PlaybackStatus").get(|_, data: &mut ObjectData| {
+            Ok(data.state.lock().unwrap().status_name().to_string())
+        });
+        builder.property("Metadata").get(|_, data: &mut ObjectData| {
+            Ok(data.state.lock().unwrap().metadata_map())
+        });
+        builder.property("Position").get(|_, data: &mut ObjectData| {
+            Ok(data.state.lock().unwrap().position * 1000)
+        });
+        builder.property("Rate").get(|_, _| Ok(1.0f64));
+        builder.property("MinimumRate").get(|_, _| Ok(1.0f64));
+        builder.property("MaximumRate").get(|_, _| Ok(1.0f64));
+        builder.property("Volume").get(|_, _| Ok(1.0f64));
+        builder.property("CanGoNext").get(|_, _| Ok(true));
+        builder.property("CanGoPrevious").get(|_, _| Ok(true));
+        builder.property("CanPlay").get(|_, _| Ok(true));
+        builder.property("CanPause").get(|_, _| Ok(true));
+        builder.property("CanSeek").get(|_, _| Ok(true));
+        builder.property("CanControl").get(|_, _| Ok(true));
+    });
+
+    crossroads.insert(OBJECT_PATH, &[root, player], ObjectData {
+        state: state.clone(),
+        sender,
+    });
+
+    connection.start_receive(MatchRule::new_method_call(), Box::new(move |message, connection| {
+        let _ = crossroads.handle_message(message, connection);
+        true
+    }));
+
+    let path = Path::new(OBJECT_PATH).unwrap();
+    loop {
+        if let Err(err) = connection.process(PROCESS_INTERVAL) {
+            log::warn!("The MPRIS service stopped: {}", err);
+            return;
+        }
+
+        let (changed, seeked) = {
+            let mut state = state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+            let changed = if state.properties_changed {
+                state.properties_changed = false;
+                let mut properties = PropMap::new();
+                properties.insert("PlaybackStatus".to_string(), Variant(Box::new(state.status_name().to_string()) as Box<dyn RefArg>));
+                properties.insert("Metadata".to_string(), Variant(Box::new(state.metadata_map()) as Box<dyn RefArg>));
+                Some(properties)
+            } else {
+                None
+            };
+            (changed, state.seeked.take())
+        };
+
+        if let Some(changed_properties) = changed {
+            let signal = PropertiesPropertiesChanged {
+                interface_name: PLAYER_INTERFACE.to_string(),
+                changed_properties,
+                invalidated_properties: Vec::new(),
+            };
+            let _ = connection.send(signal.to_emit_message(&path));
+        }
+        if let Some(position) = seeked {
+            if let Ok(message) = Message::new_signal(OBJECT_PATH, PLAYER_INTERFACE, "Seeked") {
+                let _ = connection.send(message.append1(position * 1000));
+            }
+        }
+    }
+}
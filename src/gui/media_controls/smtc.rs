@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use iced_native::futures::channel::mpsc::UnboundedSender;
+use windows::Foundation::{TypedEventHandler, Uri};
+use windows::Media::Playback::MediaPlayer;
+use windows::Media::{
+    MediaPlaybackStatus, MediaPlaybackType, PlaybackPositionChangeRequestedEventArgs, SystemMediaTransportControls,
+    SystemMediaTransportControlsButton, SystemMediaTransportControlsButtonPressedEventArgs,
+    SystemMediaTransportControlsTimelineProperties,
+};
+use windows::Storage::Streams::RandomAccessStreamReference;
+
+use super::{write_cover_file, Backend, MediaControlEvent, PlaybackStatus, TrackMetadata};
+
+/// 播放中更新时间线的最小间隔，系统会自己推算播放中的位置
+const TIMELINE_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Windows 系统媒体传输控件
+pub struct Smtc {
+    /// 通过 MediaPlayer 获取控件，不需要窗口句柄，需要保持它存活
+    _player: MediaPlayer,
+    controls: SystemMediaTransportControls,
+    status: PlaybackStatus,
+    duration: i64,
+    last_timeline_update: Option<Instant>,
+}
+
+impl Smtc {
+    pub fn new(sender: UnboundedSender<MediaControlEvent>) -> windows::core::Result<Smtc> {
+        let player = MediaPlayer::new()?;
+        // 不使用 MediaPlayer 自带的命令处理，由播放器自己响应按钮
+        player.CommandManager()?.SetIsEnabled(false)?;
+        let controls = player.SystemMediaTransportControls()?;
+        controls.SetIsEnabled(true)?;
+        controls.SetIsPlayEnabled(true)?;
+        controls.SetIsPauseEnabled(true)?;
+        controls.SetIsStopEnabled(true)?;
+        controls.SetIsNextEnabled(true)?;
+        controls.SetIsPreviousEnabled(true)?;
+        controls.SetPlaybackStatus(MediaPlaybackStatus::Closed)?;
+
+        let button_sender = sender.clone();
+        controls.ButtonPressed(TypedEventHandler::new(
+            move |_: &Option<SystemMediaTransportControls>, args: &Option<SystemMediaTransportControlsButtonPressedEventArgs>| {
+                if let Some(args) = args {
+                    let button = args.Button()?;
+                    let event = if button == SystemMediaTransportControlsButton::Play {
+                        Some(MediaControlEvent::Play)
+                    } else if button == SystemMediaTransportControlsButton::Pause {
+                        Some(MediaControlEvent::Pause)
+                    } else if button == SystemMediaTransportControlsButton::Stop {
+                        Some(MediaControlEvent::Stop)
+                    } else if button == SystemMediaTransportControlsButton::Next {
+                        Some(MediaControlEvent::Next)
+                    } else if button == SystemMediaTransportControlsButton::Previous {
+                        Some(MediaControlEvent::Previous)
+                    } else {
+                        None
+                    };
+                    if let Some(event) = event {
+                        let _ = button_sender.unbounded_send(event);
+                    }
+                }
+                Ok(())
+            },
+        ))?;
+
+        controls.PlaybackPositionChangeRequested(TypedEventHandler::new(
+            move |_: &Option<SystemMediaTransportControls>, args: &Option<PlaybackPositionChangeRequestedEventArgs>| {
+                if let Some(args) = args {
+                    // TimeSpan 的单位是 100 纳秒
+                    let position = args.RequestedPlaybackPosition()?.Duration / 10_000;
+                    let _ = sender.unbounded_send(MediaControlEvent::SetPosition(position));
+                }
+                Ok(())
+            },
+        ))?;
+
+        Ok(Smtc {
+            _player: player,
+            controls,
+            status: PlaybackStatus::Stopped,
+            duration: 0,
+            last_timeline_update: None,
+        })
+    }
+
+    fn update_metadata(&mut self, metadata: &TrackMetadata) -> windows::core::Result<()> {
+        let updater = self.controls.DisplayUpdater()?;
+        updater.ClearAll()?;
+        updater.SetType(MediaPlaybackType::Music)?;
+
+        let properties = updater.MusicProperties()?;
+        if let Some(title) = metadata.title.as_deref() {
+            properties.SetTitle(title)?;
+        }
+        if let Some(artist) = metadata.artist.as_deref() {
+            properties.SetArtist(artist)?;
+        }
+        if let Some(album) = metadata.album.as_deref() {
+            properties.SetAlbumTitle(album)?;
+        }
+
+        if let Some(path) = metadata.cover.as_ref().and_then(|cover| write_cover_file(cover)) {
+            let uri = Uri::CreateUri(format!("file:///{}", path.display()))?;
+            updater.SetThumbnail(RandomAccessStreamReference::CreateFromUri(uri)?)?;
+        }
+
+        updater.Update()
+    }
+
+    fn update_timeline(&mut self, position: i64) -> windows::core::Result<()> {
+        let timeline = SystemMediaTransportControlsTimelineProperties::new()?;
+        let duration = Duration::from_millis(self.duration.max(0) as u64);
+        timeline.SetStartTime(Duration::ZERO)?;
+        timeline.SetEndTime(duration)?;
+        timeline.SetMinSeekTime(Duration::ZERO)?;
+        timeline.SetMaxSeekTime(duration)?;
+        timeline.SetPosition(Duration::from_millis(position.max(0) as u64))?;
+        self.controls.UpdateTimelineProperties(timeline)?;
+        self.last_timeline_update = Some(Instant::now());
+        Ok(())
+    }
+
+    fn update_status(&mut self, status: PlaybackStatus) -> windows::core::Result<()> {
+        self.status = status;
+        self.controls.SetPlaybackStatus(match status {
+            PlaybackStatus::Playing => MediaPlaybackStatus::Playing,
+            PlaybackStatus::Paused => MediaPlaybackStatus::Paused,
+            PlaybackStatus::Stopped => MediaPlaybackStatus::Stopped,
+        })
+    }
+}
+
+impl Backend for Smtc {
+    fn set_metadata(&mut self, metadata: &TrackMetadata) {
+        self.duration = metadata.duration;
+        if let Err(err) = self.update_metadata(metadata).and_then(|_| self.update_timeline(0)) {
+            log::warn!("Failed to update the system media transport controls: {}", err);
+        }
+    }
+
+    fn set_playback(&mut self, status: PlaybackStatus, position: i64) {
+        let status_changed = self.status != status;
+        let timeline_expired = self.last_timeline_update
+            .map(|time| time.elapsed() >= TIMELINE_UPDATE_INTERVAL)
+            .unwrap_or(true);
+        if !status_changed && !timeline_expired {
+            return;
+        }
+
+        let result = if status_changed { self.update_status(status) } else { Ok(()) }
+            .and_then(|_| self.update_timeline(position));
+        if let Err(err) = result {
+            log::warn!("Failed to update the system media transport controls: {}", err);
+        }
+    }
+
+    fn seeked(&mut self, position: i64) {
+        if let Err(err) = self.update_timeline(position) {
+            log::warn!("Failed to update the system media transport controls: {}", err);
+        }
+    }
+}
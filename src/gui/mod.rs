@@ -0,0 +1,6 @@
+pub mod args;
+pub mod logger;
+pub mod settings;
+pub mod instance;
+pub mod visualizer;
+pub mod media_controls;
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use player_core::player::queue::RepeatMode;
+use serde::{Deserialize, Serialize};
+
+/// 配置目录下的子目录名
+const APP_DIR_NAME: &str = "YakoPlayer";
+/// 配置文件名
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// 播放器界面设置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerSettings {
+    /// 音量等级（0 ~ 1）
+    pub volume: f32,
+    /// 是否静音
+    pub mute: bool,
+    /// 输出设备名称，为空时使用默认设备
+    pub output_device: Option<String>,
+    /// 循环模式
+    pub repeat_mode: RepeatMode,
+    /// 是否随机播放
+    pub shuffle: bool,
+    /// 上次打开文件的目录
+    pub last_directory: Option<PathBuf>,
+    /// 显示剩余时间而不是已播放时间
+    pub show_remaining: bool,
+    /// 窗口位置
+    pub window_position: Option<(i32, i32)>,
+    /// 播放速度
+    pub speed: f32,
+    /// 打开新曲目时把播放速度恢复为原速
+    pub reset_speed_per_track: bool,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.,
+            mute: false,
+            output_device: None,
+            repeat_mode: RepeatMode::Off,
+            shuffle: false,
+            last_directory: None,
+            show_remaining: false,
+            window_position: None,
+            speed: 1.,
+            reset_speed_per_track: false,
+        }
+    }
+}
+
+impl PlayerSettings {
+    /// 配置文件路径
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME).join(SETTINGS_FILE_NAME))
+    }
+
+    /// 读取设置，文件不存在或者损坏时使用默认设置
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Self::default(),
+        };
+
+        match serde_json::from_str::<Self>(&content) {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                log::warn!("Failed to parse settings file {}: {}", path.display(), err);
+                Self::default()
+            }
+        }
+    }
+
+    /// 保存设置，先写入临时文件再重命名，避免写入过程中断导致文件损坏
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "the config directory could not be determined")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let temp_path = path.with_extension("json.tmp");
+        {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &path)
+    }
+
+    /// 修正超出范围的值
+    fn sanitized(mut self) -> Self {
+        if !self.volume.is_finite() {
+            self.volume = Self::default().volume;
+        }
+        self.volume = self.volume.clamp(0., 1.);
+        if !self.speed.is_finite() {
+            self.speed = Self::default().speed;
+        }
+        self.speed = self.speed.clamp(0.5, 2.);
+        if let Some(dir) = self.last_directory.as_ref() {
+            if !dir.is_dir() {
+                self.last_directory = None;
+            }
+        }
+        self
+    }
+}
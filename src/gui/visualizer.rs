@@ -0,0 +1,170 @@
+use iced::canvas::{self, event, Canvas, Cursor, Frame, Geometry, Program};
+use iced::{mouse, Color, Element, Length, Point, Rectangle, Size};
+
+/// 频谱的频段数
+pub const SPECTRUM_BANDS: usize = 32;
+
+/// 每次刷新时显示值的最大下降量，让柱子平滑回落
+const FALL_PER_TICK: f32 = 0.06;
+/// 电平表显示的动态范围（分贝）
+const METER_RANGE_DB: f32 = 60.;
+/// 每根频谱柱子的最小宽度（包括间隔）
+const MIN_BAR_WIDTH: f32 = 4.;
+
+const BAR_COLOR: Color = Color::from_rgb(0.25, 0.55, 0.9);
+const PEAK_COLOR: Color = Color::from_rgb(0.9, 0.35, 0.25);
+const BACKGROUND_COLOR: Color = Color::from_rgb(0.94, 0.94, 0.94);
+
+/// 可视化显示模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualizerMode {
+    /// 频谱柱状图
+    Bars,
+    /// 左右声道电平表
+    Meter,
+    /// 关闭
+    Off,
+}
+
+impl Default for VisualizerMode {
+    fn default() -> Self {
+        Self::Bars
+    }
+}
+
+/// 频谱和电平表显示
+#[derive(Default)]
+pub struct Visualizer {
+    mode: VisualizerMode,
+    spectrum: Vec<f32>,
+    levels: [f32; 2],
+}
+
+impl Visualizer {
+    pub fn mode(&self) -> VisualizerMode {
+        self.mode
+    }
+
+    /// 切换到下一个显示模式：柱状图 → 电平表 → 关闭
+    pub fn cycle_mode(&mut self) -> VisualizerMode {
+        self.mode = match self.mode {
+            VisualizerMode::Bars => VisualizerMode::Meter,
+            VisualizerMode::Meter => VisualizerMode::Off,
+            VisualizerMode::Off => VisualizerMode::Bars,
+        };
+        self.clear();
+        self.mode
+    }
+
+    /// 更新显示数据，新值比当前值小时缓慢回落
+    pub fn update(&mut self, spectrum: Vec<f32>, peak_levels: (f32, f32)) {
+        if self.spectrum.len() != spectrum.len() {
+            self.spectrum = vec![0.; spectrum.len()];
+        }
+        for (current, value) in self.spectrum.iter_mut().zip(spectrum) {
+            *current = value.max(*current - FALL_PER_TICK);
+        }
+
+        let (left, right) = peak_levels;
+        for (current, peak) in self.levels.iter_mut().zip([left, right]) {
+            *current = Self::amplitude_to_meter(peak).max(*current - FALL_PER_TICK);
+        }
+    }
+
+    /// 清空显示数据
+    pub fn clear(&mut self) {
+        self.spectrum.iter_mut().for_each(|value| *value = 0.);
+        self.levels = [0.; 2];
+    }
+
+    pub fn view<'a, Message: Clone + 'a>(&'a mut self, on_click: Message) -> Element<'a, Message> {
+        Canvas::new(VisualizerCanvas {
+            visualizer: self,
+            on_click,
+        })
+        .width(Length::Fill)
+        .height(Length::Units(60))
+        .into()
+    }
+
+    /// 把振幅比例转换为电平表的显示比例
+    fn amplitude_to_meter(amplitude: f32) -> f32 {
+        if amplitude <= 0. {
+            return 0.;
+        }
+        let db = 20. * amplitude.log10();
+        ((db + METER_RANGE_DB) / METER_RANGE_DB).clamp(0., 1.)
+    }
+
+    fn draw_bars(&self, frame: &mut Frame) {
+        if self.spectrum.is_empty() {
+            return;
+        }
+        // 窗口太窄时合并相邻频段
+        let bar_count = ((frame.width() / MIN_BAR_WIDTH) as usize).clamp(1, self.spectrum.len());
+        let group = self.spectrum.len() as f32 / bar_count as f32;
+        let slot_width = frame.width() / bar_count as f32;
+        let bar_width = (slot_width - 1.).max(1.);
+
+        for i in 0..bar_count {
+            let start = (i as f32 * group) as usize;
+            let end = (((i + 1) as f32 * group) as usize).max(start + 1).min(self.spectrum.len());
+            let value = self.spectrum[start..end].iter().cloned().fold(0., f32::max);
+            let height = value * frame.height();
+            frame.fill_rectangle(
+                Point::new(i as f32 * slot_width, frame.height() - height),
+                Size::new(bar_width, height),
+                BAR_COLOR,
+            );
+        }
+    }
+
+    fn draw_meter(&self, frame: &mut Frame) {
+        let gap = 4.;
+        let bar_height = ((frame.height() - gap) / 2.).max(1.);
+        for (i, level) in self.levels.iter().enumerate() {
+            let y = i as f32 * (bar_height + gap);
+            let width = level * frame.width();
+            // 接近满幅的部分用警示色
+            let color = if *level > 0.95 { PEAK_COLOR } else { BAR_COLOR };
+            frame.fill_rectangle(Point::new(0., y), Size::new(width, bar_height), color);
+        }
+    }
+}
+
+struct VisualizerCanvas<'a, Message> {
+    visualizer: &'a Visualizer,
+    on_click: Message,
+}
+
+impl<'a, Message: Clone> Program<Message> for VisualizerCanvas<'a, Message> {
+    fn update(&mut self, event: canvas::Event, bounds: Rectangle, cursor: Cursor) -> (event::Status, Option<Message>) {
+        match event {
+            canvas::Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if cursor.is_over(&bounds) => {
+                (event::Status::Captured, Some(self.on_click.clone()))
+            },
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(&self, bounds: Rectangle, _cursor: Cursor) -> Vec<Geometry> {
+        let mut frame = Frame::new(bounds.size());
+        frame.fill_rectangle(Point::ORIGIN, bounds.size(), BACKGROUND_COLOR);
+
+        match self.visualizer.mode {
+            VisualizerMode::Bars => self.visualizer.draw_bars(&mut frame),
+            VisualizerMode::Meter => self.visualizer.draw_meter(&mut frame),
+            VisualizerMode::Off => {},
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(&self, bounds: Rectangle, cursor: Cursor) -> mouse::Interaction {
+        if cursor.is_over(&bounds) {
+            mouse::Interaction::Pointer
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
@@ -0,0 +1,62 @@
+extern crate ffmpeg_sys_next as ffmpeg_c_api;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+
+/// 构建信息，用于排查问题时确认链接的 FFmpeg 版本和编译选项
+#[derive(Debug, Clone)]
+pub struct BuildInfo {
+    /// 播放器的版本
+    pub version: &'static str,
+    /// FFmpeg 的版本，例如 5.0.1
+    pub ffmpeg_version: String,
+    /// libavformat 的版本
+    pub avformat_version: String,
+    /// libavcodec 的版本
+    pub avcodec_version: String,
+    /// FFmpeg 的编译选项，可以用来确认是否启用了 libopus 等外部库
+    pub configuration: String,
+    /// 支持的封装格式（解复用器）名称
+    pub demuxers: Vec<String>,
+}
+
+/// 把 FFmpeg 返回的静态字符串转换为 String
+fn c_str_to_string(value: *const c_char) -> String {
+    if value.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(value) }.to_string_lossy().into_owned()
+}
+
+/// 按 AV_VERSION_INT 的格式拆分版本号
+fn format_version(version: u32) -> String {
+    format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff)
+}
+
+/// 在运行时读取链接的 FFmpeg 的版本、编译选项和支持的封装格式
+pub fn build_info() -> BuildInfo {
+    let mut demuxers = Vec::new();
+    let mut opaque: *mut c_void = std::ptr::null_mut();
+    loop {
+        let demuxer = unsafe { ffmpeg_c_api::av_demuxer_iterate(&mut opaque) };
+        if demuxer.is_null() {
+            break;
+        }
+        // 一个解复用器可能对应多个用逗号分隔的名称，例如 mov,mp4,m4a
+        let names = c_str_to_string(unsafe { (*demuxer).name });
+        demuxers.extend(names.split(',').filter(|name| !name.is_empty()).map(str::to_string));
+    }
+    demuxers.sort();
+    demuxers.dedup();
+
+    unsafe {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            ffmpeg_version: c_str_to_string(ffmpeg_c_api::av_version_info()),
+            avformat_version: format_version(ffmpeg_c_api::avformat_version()),
+            avcodec_version: format_version(ffmpeg_c_api::avcodec_version()),
+            configuration: c_str_to_string(ffmpeg_c_api::avformat_configuration()),
+            demuxers,
+        }
+    }
+}
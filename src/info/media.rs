@@ -1,6 +1,194 @@
-#[derive(Debug, Default)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// 文件中的 ReplayGain 标签，增益的单位是分贝，峰值是振幅比例
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// 音频流的处置标记，对应 FFmpeg 的 AV_DISPOSITION_*
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StreamDisposition {
+    /// 默认播放的流
+    pub default: bool,
+    /// 配音
+    pub dub: bool,
+    /// 原声
+    pub original: bool,
+    /// 解说
+    pub comment: bool,
+    /// 卡拉 OK（伴奏）
+    pub karaoke: bool,
+    /// 为听障人士准备的音轨
+    pub hearing_impaired: bool,
+    /// 为视障人士准备的音轨（口述影像）
+    pub visual_impaired: bool,
+}
+
+/// 文件中的一个音频流
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AudioStreamInfo {
+    /// 在文件中的流序号
+    pub index: usize,
+    /// 编码格式名称，例如 FLAC
+    pub codec: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 语言标签，例如 jpn
+    pub language: Option<String>,
+    /// 流的标题
+    pub title: Option<String>,
+    pub disposition: StreamDisposition,
+    /// 是否是正在播放的流
+    pub selected: bool,
+}
+
+/// FFmpeg 读取到的原始标签，保持文件中的顺序和键名的大小写，不做任何解析
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RawTags {
+    /// 容器级别的标签，例如 ID3v2、MP4 的 ilst、Matroska 的 Tags
+    pub container: Vec<(String, String)>,
+    /// 正在播放的音频流上的标签，例如 Ogg 和 FLAC 的 Vorbis Comment
+    pub stream: Vec<(String, String)>,
+}
+
+/// 媒体信息，网络电台的标题等信息可能在播放中更新，每次更新都会生成新的快照
+#[derive(Debug, Default, Clone)]
 pub struct MediaInfo {
+    /// 总长度（毫秒），未知时为 -1
     pub duration: i64,
+    /// 平均码率（bit/s）
     pub bitrate: i64,
-    pub cover: Option<Vec<u8>>,
-}
\ No newline at end of file
+    /// 封面图片数据，在快照之间共享
+    pub cover: Option<Arc<[u8]>>,
+    /// 封面的版本，打开文件或者播放中替换封面时分配新的值，只探测而没有播放的文件为 0
+    pub cover_generation: u64,
+    /// 标题
+    pub title: Option<String>,
+    /// 艺术家
+    pub artist: Option<String>,
+    /// 专辑
+    pub album: Option<String>,
+    /// 编码格式名称，例如 FLAC
+    pub codec: Option<String>,
+    /// 原始采样率
+    pub sample_rate: u32,
+    /// 原始声道数
+    pub channels: u16,
+    /// 原始采样位深，有损格式等未知的情况为 0
+    pub bits_per_sample: u32,
+    /// 是否是无损编码（FLAC、ALAC、PCM 等），无法判断时为 None
+    pub is_lossless: Option<bool>,
+    /// 是否包含视频画面（作为封面的附加图片除外），播放时只解码其中的音频
+    pub has_video: bool,
+    /// ReplayGain 标签
+    pub replay_gain: ReplayGain,
+    /// 文件中的所有音频流，按流序号排列
+    pub audio_streams: Vec<AudioStreamInfo>,
+    /// 正在播放的音频流的编码器附加数据（例如 FLAC 的 STREAMINFO），在快照之间共享
+    pub codec_extradata: Option<Arc<[u8]>>,
+    /// 容器和音频流上的所有标签
+    pub raw_tags: RawTags,
+}
+
+impl MediaInfo {
+    /// 替换封面并分配新的版本
+    pub fn replace_cover(&mut self, cover: Option<Arc<[u8]>>) {
+        self.cover = cover;
+        self.cover_generation = next_cover_generation();
+    }
+}
+
+/// 最近一次分配的封面版本
+static COVER_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// 分配一个新的封面版本，在整个进程中单调递增，不会是 0
+pub fn next_cover_generation() -> u64 {
+    COVER_GENERATION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// 当前播放的音频格式，界面可以用来显示正在播放的编码信息
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TechnicalInfo {
+    /// 编码格式名称，例如 FLAC
+    pub codec: Option<String>,
+    /// 原始采样率
+    pub sample_rate: u32,
+    /// 原始声道数
+    pub channels: u16,
+    /// 原始采样位深，未知时为 0
+    pub bits_per_sample: u32,
+    /// 平均码率（bit/s）
+    pub bitrate: i64,
+}
+
+impl From<&MediaInfo> for TechnicalInfo {
+    fn from(media_info: &MediaInfo) -> Self {
+        TechnicalInfo {
+            codec: media_info.codec.clone(),
+            sample_rate: media_info.sample_rate,
+            channels: media_info.channels,
+            bits_per_sample: media_info.bits_per_sample,
+            bitrate: media_info.bitrate,
+        }
+    }
+}
+
+/// 批量读取媒体信息时单个文件的结果，只包含数值字段，用于 FFI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProbeResult {
+    /// 是否读取成功，失败时其他字段为 0
+    pub ok: bool,
+    /// 总长度（毫秒），未知时为 -1
+    pub duration: i64,
+    /// 平均码率（bit/s）
+    pub bitrate: i64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u32,
+}
+
+impl From<&MediaInfo> for ProbeResult {
+    fn from(media_info: &MediaInfo) -> Self {
+        ProbeResult {
+            ok: true,
+            duration: media_info.duration,
+            bitrate: media_info.bitrate,
+            sample_rate: media_info.sample_rate,
+            channels: media_info.channels,
+            bits_per_sample: media_info.bits_per_sample,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cover_generations_increase_and_are_never_zero() {
+        let first = next_cover_generation();
+        let second = next_cover_generation();
+        assert!(first > 0);
+        assert!(second > first);
+    }
+
+    #[test]
+    fn replacing_the_cover_assigns_a_new_generation() {
+        let mut media_info = MediaInfo::default();
+        let cover: Arc<[u8]> = Arc::from(&[1u8, 2, 3][..]);
+        media_info.replace_cover(Some(cover.clone()));
+        let generation = media_info.cover_generation;
+        assert_eq!(media_info.cover, Some(cover));
+
+        // 移除封面也是一次替换
+        media_info.replace_cover(None);
+        assert!(media_info.cover_generation > generation);
+        assert!(media_info.cover.is_none());
+    }
+}
@@ -1 +1,6 @@
-pub mod media;
\ No newline at end of file
+pub mod build;
+pub mod media;
+pub mod probe;
+pub mod status;
+
+pub use self::build::{build_info, BuildInfo};
@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use libc::c_char;
+
+use crate::audio::source::{self, Error};
+
+/// 默认的时间限制，超过时放弃这个文件
+pub const DEFAULT_PROBE_BUDGET: Duration = Duration::from_secs(3);
+
+/// 快速检查文件的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProbe {
+    /// 封装格式名称，例如 flac、mov,mp4,m4a,3gp,3g2,mj2
+    pub container: String,
+    /// 音频编码名称，例如 FLAC
+    pub codec: Option<String>,
+    /// 总长度（毫秒），未知时为 -1
+    pub duration_ms: i64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// 是否有封面
+    pub has_cover: bool,
+    /// 是否可以定位
+    pub is_seekable: bool,
+}
+
+/// 检查文件能否播放并读取基本信息，使用默认的时间限制
+pub fn probe<P: AsRef<Path>>(path: &P) -> Result<FileProbe, Error> {
+    probe_with_budget(path, DEFAULT_PROBE_BUDGET)
+}
+
+/// 检查文件能否播放并读取基本信息，超过 budget 时返回错误
+///
+/// 只打开文件头并创建解码器，不会初始化音频设备，可以在多个线程中同时调用
+pub fn probe_with_budget<P: AsRef<Path>>(path: &P, budget: Duration) -> Result<FileProbe, Error> {
+    let input_ctx = source::open_media_with_deadline(path, Instant::now() + budget)?;
+    let media_info = source::probe_input(&input_ctx, Some(path.as_ref()), &[])?;
+    Ok(FileProbe {
+        container: input_ctx.format().name().to_string(),
+        codec: media_info.codec,
+        duration_ms: media_info.duration,
+        sample_rate: media_info.sample_rate,
+        channels: media_info.channels,
+        has_cover: media_info.cover.is_some(),
+        is_seekable: input_ctx.is_seekable(),
+    })
+}
+
+/// 名称字段的长度，包括结尾的 0，过长的名称会被截断
+const NAME_SIZE: usize = 32;
+
+/// FFI 使用的检查结果
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FileProbeResult {
+    /// 是否可以播放，为 false 时其他字段为 0
+    pub ok: bool,
+    /// 封装格式名称，以 0 结尾
+    pub container: [c_char; NAME_SIZE],
+    /// 音频编码名称，以 0 结尾，未知时为空
+    pub codec: [c_char; NAME_SIZE],
+    /// 总长度（毫秒），未知时为 -1
+    pub duration: i64,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub has_cover: bool,
+    pub is_seekable: bool,
+}
+
+impl Default for FileProbeResult {
+    fn default() -> Self {
+        FileProbeResult {
+            ok: false,
+            container: [0; NAME_SIZE],
+            codec: [0; NAME_SIZE],
+            duration: 0,
+            sample_rate: 0,
+            channels: 0,
+            has_cover: false,
+            is_seekable: false,
+        }
+    }
+}
+
+impl From<&FileProbe> for FileProbeResult {
+    fn from(probe: &FileProbe) -> Self {
+        FileProbeResult {
+            ok: true,
+            container: name_to_array(&probe.container),
+            codec: name_to_array(probe.codec.as_deref().unwrap_or("")),
+            duration: probe.duration_ms,
+            sample_rate: probe.sample_rate,
+            channels: probe.channels,
+            has_cover: probe.has_cover,
+            is_seekable: probe.is_seekable,
+        }
+    }
+}
+
+fn name_to_array(name: &str) -> [c_char; NAME_SIZE] {
+    let mut array = [0; NAME_SIZE];
+    for (target, byte) in array.iter_mut().zip(name.bytes().take(NAME_SIZE - 1)) {
+        *target = byte as c_char;
+    }
+    array
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+
+/// 读取和解码的统计数据，用于诊断播放卡顿，打开文件时清零
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DebugStats {
+    /// 从输入读取的字节数
+    pub bytes_read: u64,
+    /// 解封装得到的数据包数
+    pub packets_read: u64,
+    /// 解码器输出的音频帧数
+    pub frames_decoded: u64,
+    /// 重采样器输出的采样数（每个声道）
+    pub resampled_samples: u64,
+    /// 解码线程实际执行的定位次数，防抖期间合并的定位只算一次
+    pub seeks: u64,
+    /// 丢弃已经解码的数据的次数，每次定位或者清空缓冲区只算一次，不按丢弃的数据块计算
+    pub buffer_discards: u64,
+    /// 输出缓冲区已满或者暂停时等待写入的总时长（微秒）
+    pub write_blocked_us: u64,
+    /// 写入之后输出缓冲区中数据的最大值（帧）
+    pub buffer_high_watermark: u64,
+    /// 预先缓冲结束之后，写入之前输出缓冲区中数据的最小值（帧），还没有写入时为 0
+    pub buffer_low_watermark: u64,
+    /// 输出缓冲区的容量（帧）
+    pub buffer_capacity: u64,
+}
+
+impl fmt::Display for DebugStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "bytes read: {}", self.bytes_read)?;
+        writeln!(f, "packets read: {}", self.packets_read)?;
+        writeln!(f, "frames decoded: {}", self.frames_decoded)?;
+        writeln!(f, "resampled samples: {}", self.resampled_samples)?;
+        writeln!(f, "seeks: {}", self.seeks)?;
+        writeln!(f, "buffer discards: {}", self.buffer_discards)?;
+        writeln!(f, "write blocked: {:.1} ms", self.write_blocked_us as f64 / 1000.)?;
+        write!(f, "output buffer: low {} / high {} / capacity {} frames",
+            self.buffer_low_watermark, self.buffer_high_watermark, self.buffer_capacity)
+    }
+}
+
+/// 播放状态快照，一次获取界面刷新需要的所有状态
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayerStatus {
+    /// 当前位置（毫秒）
+    pub current_time: i64,
+    /// 总长度（毫秒），未知时为 -1
+    pub duration: i64,
+    /// 实时码率（bit/s），可变码率的文件会随播放变化
+    pub bitrate: i64,
+    pub is_playing: bool,
+    pub is_seekable: bool,
+    /// 文件在播放中丢失，可以调用 reopen_current 重新打开
+    pub is_source_lost: bool,
+    /// 网络流正在缓冲，或者解码跟不上播放导致输出缓冲区耗尽
+    pub is_buffering: bool,
+}
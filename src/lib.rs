@@ -7,13 +7,29 @@ pub mod metadata;
 
 pub mod info;
 pub mod player;
+pub mod archive;
+pub mod logging;
 
 #[cfg(not(windows))]
 use std::ffi::CStr;
+use std::sync::Arc;
 
 use ffi_helpers::null_pointer_check;
-use libc::c_char;
-use player::{YakoPlayer, Player};
+use libc::{c_char, c_void};
+use audio::sample::AudioSample;
+use audio::device::MAX_BLOCK_FRAMES;
+use audio::volume::VolumeCurve;
+use info::status::PlayerStatus;
+use info::media::ProbeResult;
+use info::probe::FileProbeResult;
+use player::{YakoPlayer, Player, FinishedAction, OpenMode, OpenOptions, ResetOptions};
+use player::sleep::SleepTimer;
+use player::stats::ScrobbleThreshold;
+use player::cue::CuePoint;
+use audio::dsp::DspStage;
+use audio::replaygain::ReplayGainMode;
+use audio::source::{EndBehavior, ErrorResilience, OpenPhase};
+use audio::capture::CaptureFormat;
 
 #[cfg(windows)]
 use widestring::U16CStr;
@@ -33,6 +49,7 @@ pub extern fn yako_player_free(player: *mut YakoPlayer) {
     }
 }
 
+/// 打开文件，成功时返回 0，文件没有音频流时返回 YAKO_ERROR_NO_AUDIO_STREAM，其他错误返回 -1
 #[no_mangle]
 pub extern fn yako_player_open(player: *mut YakoPlayer, path: *const c_char) -> i32 {
     null_pointer_check!(player);
@@ -53,6 +70,124 @@ pub extern fn yako_player_open(player: *mut YakoPlayer, path: *const c_char) ->
     };
 
     match player.open(&path) {
+        Ok(_) => 0,
+        Err(err) => open_error_code(err),
+    }
+}
+
+/// 文件没有音频流时打开函数返回的错误码，其他错误返回 -1
+pub const YAKO_ERROR_NO_AUDIO_STREAM: i32 = -2;
+
+/// 记录打开文件的错误并返回对应的错误码
+fn open_error_code(err: player::Error) -> i32 {
+    let code = if err.is_no_audio_stream() { YAKO_ERROR_NO_AUDIO_STREAM } else { -1 };
+    ffi_helpers::update_last_error(err);
+    code
+}
+
+/// 打开进度回调，phase 的数值见 OpenPhase::as_i32
+pub type YakoOpenProgressCallback = extern fn(phase: i32, user_data: *mut c_void);
+
+/// 回调的用户数据由调用方保证可以跨线程使用，Ready 阶段在解码线程中报告
+struct OpenProgressUserData(*mut c_void);
+
+unsafe impl Send for OpenProgressUserData {}
+unsafe impl Sync for OpenProgressUserData {}
+
+impl OpenProgressUserData {
+    fn as_ptr(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// 打开文件并报告打开进度，callback 为空时和 yako_player_open 相同
+///
+/// 0 到 3 的阶段在这个函数返回之前报告，4（可以播放）在开始播放之后由解码线程报告
+#[no_mangle]
+pub extern fn yako_player_open_with_progress(
+    player: *mut YakoPlayer,
+    path: *const c_char,
+    callback: Option<YakoOpenProgressCallback>,
+    user_data: *mut c_void,
+) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    let mut options = OpenOptions::new();
+    if let Some(callback) = callback {
+        let user_data = OpenProgressUserData(user_data);
+        options = options.progress(Arc::new(move |phase: OpenPhase| callback(phase.as_i32(), user_data.as_ptr())));
+    }
+    match player.open_with_options(&path, &options) {
+        Ok(_) => 0,
+        Err(err) => open_error_code(err),
+    }
+}
+
+/// 扫描目录下的音频文件（recursive 不为 0 时包括子目录），替换播放队列并播放第一个
+#[no_mangle]
+pub extern fn yako_player_open_directory(player: *mut YakoPlayer, path: *const c_char, recursive: i32) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.open_directory(&path, recursive != 0) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 试听：从文件总长度的 start_fraction（0 到 1 之间）处开始播放 duration_ms 毫秒后停止
+#[no_mangle]
+pub extern fn yako_player_audition(player: *mut YakoPlayer, path: *const c_char, start_fraction: f64, duration_ms: i64) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.audition(&path, start_fraction, duration_ms) {
         Ok(_) => 0,
         Err(err) => {
             ffi_helpers::update_last_error(err);
@@ -61,6 +196,58 @@ pub extern fn yako_player_open(player: *mut YakoPlayer, path: *const c_char) ->
     }
 }
 
+/// 并行读取 count 个文件的媒体信息，依次写入 results，返回读取成功的数量
+///
+/// 只读取文件头，不会打开音频设备，也不会影响正在播放的曲目
+#[no_mangle]
+pub extern fn yako_player_probe_batch(player: *const YakoPlayer, paths: *const *const c_char, count: usize, results: *mut ProbeResult) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(paths);
+    null_pointer_check!(results);
+
+    let player = unsafe {
+        &*player
+    };
+    let paths = unsafe {
+        std::slice::from_raw_parts(paths, count)
+    };
+    let results = unsafe {
+        std::slice::from_raw_parts_mut(results, count)
+    };
+
+    let mut path_bufs = Vec::with_capacity(count);
+    for path in paths {
+        null_pointer_check!(*path);
+
+        #[cfg(not(windows))]
+        let path = unsafe {
+            CStr::from_ptr(*path).to_str().unwrap()
+        };
+
+        #[cfg(windows)]
+        let path = unsafe {
+            U16CStr::from_ptr_str(*path as *const u16).to_string().unwrap()
+        };
+
+        path_bufs.push(std::path::PathBuf::from(path));
+    }
+
+    let mut succeeded = 0;
+    for (result, probed) in results.iter_mut().zip(player.probe_batch(&path_bufs)) {
+        *result = match probed {
+            Ok(media_info) => {
+                succeeded += 1;
+                ProbeResult::from(&media_info)
+            },
+            Err(err) => {
+                ffi_helpers::update_last_error(err);
+                ProbeResult::default()
+            },
+        };
+    }
+    succeeded
+}
+
 #[no_mangle]
 pub extern fn yako_player_play(player: *mut YakoPlayer) -> i32 {
     null_pointer_check!(player);
@@ -106,6 +293,75 @@ pub extern fn yako_player_stop(player: *const YakoPlayer) -> i32 {
     }
 }
 
+/// 把播放器恢复到刚创建时的状态，keep_volume 和 keep_device 为 0 时同时恢复音量和设备选择
+#[no_mangle]
+pub extern fn yako_player_reset(player: *mut YakoPlayer, keep_volume: i32, keep_device: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let options = ResetOptions::new()
+        .keep_volume(keep_volume != 0)
+        .keep_device_selection(keep_device != 0);
+    match player.reset(options) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern fn yako_player_reopen_current(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.reopen_current() {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 输出设备失效时在其他设备上重新打开输出并回到实际听到的位置，返回 1 表示已经恢复，0 表示设备正常
+#[no_mangle]
+pub extern fn yako_player_recover_output(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.recover_output() {
+        Ok(recovered) => recovered as i32,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 阻塞到当前曲目播放完毕，timeout_ms 为负数时一直等待。播放完毕返回 1，超时、停止或者关闭时返回 0
+#[no_mangle]
+pub extern fn yako_player_wait_for_end(player: *const YakoPlayer, timeout_ms: i64) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    let timeout = if timeout_ms >= 0 {
+        Some(std::time::Duration::from_millis(timeout_ms as u64))
+    } else {
+        None
+    };
+    // 只在取出结束通知时访问播放器，等待期间其他线程可以关闭或者替换曲目，关闭时等待被打断
+    match player.end_signal() {
+        Some(end_signal) => end_signal.wait(timeout) as i32,
+        None => 0,
+    }
+}
+
 #[no_mangle]
 pub extern fn yako_player_seek(player: *const YakoPlayer, position: i64) -> i32 {
     null_pointer_check!(player);
@@ -148,6 +404,104 @@ pub extern fn yako_player_get_current_time(player: *const YakoPlayer) -> i64 {
     player.get_current_time()
 }
 
+/// 获取实际播放到的位置（微秒），扣除了输出缓冲区中还没有播放的数据，精确到输出的一帧
+#[no_mangle]
+pub extern fn yako_player_get_current_time_us(player: *const YakoPlayer) -> i64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_current_time_us()
+}
+
+/// 获取平均码率并写入 bitrate，没有打开文件或者码率未知时返回 -1，不修改 bitrate
+#[no_mangle]
+pub extern fn yako_player_try_get_bitrate(player: *const YakoPlayer, bitrate: *mut u32) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(bitrate);
+    let player = unsafe {
+        &*player
+    };
+    match player.try_get_bitrate() {
+        Ok(value) => {
+            unsafe {
+                *bitrate = value;
+            }
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取总长度（毫秒）并写入 duration，没有打开文件或者总长度未知时返回 -1，不修改 duration
+#[no_mangle]
+pub extern fn yako_player_try_get_duration(player: *const YakoPlayer, duration: *mut i64) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(duration);
+    let player = unsafe {
+        &*player
+    };
+    match player.try_get_duration() {
+        Ok(value) => {
+            unsafe {
+                *duration = value;
+            }
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取当前位置（毫秒）并写入 current_time，没有打开文件时返回 -1，不修改 current_time
+#[no_mangle]
+pub extern fn yako_player_try_get_current_time(player: *const YakoPlayer, current_time: *mut i64) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(current_time);
+    let player = unsafe {
+        &*player
+    };
+    match player.try_get_current_time() {
+        Ok(value) => {
+            unsafe {
+                *current_time = value;
+            }
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取 ReplayGain 峰值并写入 peak，没有打开文件或者文件中没有峰值标签时返回 -1，不修改 peak
+#[no_mangle]
+pub extern fn yako_player_try_get_replay_gain_peak(player: *const YakoPlayer, peak: *mut f32) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(peak);
+    let player = unsafe {
+        &*player
+    };
+    match player.try_get_replay_gain_peak() {
+        Ok(value) => {
+            unsafe {
+                *peak = value;
+            }
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
 #[no_mangle]
 pub extern fn yako_player_is_playing(player: *const YakoPlayer) -> i32 {
     null_pointer_check!(player);
@@ -161,6 +515,47 @@ pub extern fn yako_player_is_playing(player: *const YakoPlayer) -> i32 {
     }
 }
 
+/// 当前的编码是否是无损的，返回 1 表示无损，0 表示有损，无法判断或者没有打开文件时返回 -1
+#[no_mangle]
+pub extern fn yako_player_is_lossless(player: *const YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.is_lossless() {
+        Some(true) => 1,
+        Some(false) => 0,
+        None => -1,
+    }
+}
+
+/// 当前文件是否包含视频画面，包含时返回 1，不包含或者没有打开文件时返回 0
+#[no_mangle]
+pub extern fn yako_player_has_video(player: *const YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.get_media_info() {
+        Some(media_info) if media_info.has_video => 1,
+        _ => 0,
+    }
+}
+
+/// 一次获取当前位置、总长度、实时码率等状态，写入 status
+#[no_mangle]
+pub extern fn yako_player_get_status(player: *const YakoPlayer, status: *mut PlayerStatus) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(status);
+    let player = unsafe {
+        &*player
+    };
+    unsafe {
+        *status = player.get_status();
+    }
+    0
+}
+
 #[no_mangle]
 pub extern fn yako_player_get_volume(player: *const YakoPlayer) -> f32 {
     let player = unsafe {
@@ -200,37 +595,171 @@ pub extern fn yako_player_set_mute(player: *const YakoPlayer, mute: i32) -> i32
     }
 }
 
+/// 选择内置的音量曲线：0 为默认的指数曲线，1 为从 min_db 到 0 dB 的线性分贝，2 为线性振幅
+///
+/// min_db 只用于线性分贝曲线，必须小于 0
 #[no_mangle]
-pub extern fn yako_player_get_album_cover(player: *const YakoPlayer) -> *const u8 {
+pub extern fn yako_player_set_volume_curve(player: *mut YakoPlayer, curve: i32, min_db: f32) -> i32 {
     null_pointer_check!(player);
     let player = unsafe {
-        &*player
+        &mut *player
     };
-    match player.get_media_info() {
-        Some(media_info) => {
-            match media_info.cover.as_ref() {
-                Some(cover) => {
-                    let mut cover_bytes = cover.to_vec();
-                    let cover_ptr = cover_bytes.as_mut_ptr();
-                    // std::mem::forget(cover_bytes);
-                    cover_ptr
-                },
-                None => std::ptr::null(),
-            }
+    let curve = match curve {
+        0 => VolumeCurve::Exponential,
+        1 => VolumeCurve::LinearDb { min_db },
+        2 => VolumeCurve::LinearAmplitude,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown volume curve {}", curve),
+            });
+            return -1;
         },
-        None => std::ptr::null(),
+    };
+    match player.set_volume_curve(curve) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
     }
 }
 
+/// 设置定位防抖时间（毫秒），0 为不防抖
 #[no_mangle]
-pub extern fn yako_player_get_album_cover_size(player: *const YakoPlayer) -> u32 {
+pub extern fn yako_player_set_seek_debounce_ms(player: *mut YakoPlayer, debounce_ms: u32) -> i32 {
     null_pointer_check!(player);
     let player = unsafe {
-        &*player
+        &mut *player
     };
-    match player.get_media_info() {
-        Some(media_info) => {
-            match media_info.cover.as_ref() {
+    player.set_seek_debounce_ms(debounce_ms);
+    0
+}
+
+/// 设置解码跟不上播放时暂停输出、重新积累的时长（毫秒），0 为不暂停
+#[no_mangle]
+pub extern fn yako_player_set_rebuffer_threshold_ms(player: *mut YakoPlayer, threshold_ms: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_rebuffer_threshold_ms(threshold_ms) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置动态缓冲区的时长（毫秒，5 到 1000），默认 80，低延迟的场合可以减小
+#[no_mangle]
+pub extern fn yako_player_set_target_buffer_ms(player: *mut YakoPlayer, target_ms: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_target_buffer_ms(target_ms) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置开始播放和定位之后预先缓冲的时长（毫秒），0 为不预先缓冲，负数为缓冲到动态缓冲区写满（默认）
+#[no_mangle]
+pub extern fn yako_player_set_prebuffer_ms(player: *mut YakoPlayer, prebuffer_ms: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let prebuffer_ms = if prebuffer_ms < 0 { None } else { Some(prebuffer_ms as u32) };
+    match player.set_prebuffer_ms(prebuffer_ms) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置是否无缝循环当前文件（enabled 不为 0 时开启），首尾之间没有间隙
+#[no_mangle]
+pub extern fn yako_player_set_seamless_loop(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_seamless_loop(enabled != 0);
+    0
+}
+
+/// 设置曲目播放到结尾时播放器自己执行的操作：0 为停止，1 为无缝循环当前曲目，2 为按播放队列交给下一首
+///
+/// 不需要注册回调，结束事件仍然照常发出
+#[no_mangle]
+pub extern fn yako_player_set_finished_action(player: *mut YakoPlayer, action: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let action = match action {
+        0 => FinishedAction::Stop,
+        1 => FinishedAction::LoopTrack,
+        2 => FinishedAction::AdvanceQueue,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown finished action {}", action),
+            });
+            return -1;
+        }
+    };
+    player.set_finished_action(action);
+    0
+}
+
+/// 设置打开文件时是否延迟读取内嵌的封面（lazy 不为 0 时开启），读取完成之前复制封面得到的长度为 0
+#[no_mangle]
+pub extern fn yako_player_set_lazy_cover(player: *mut YakoPlayer, lazy: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_lazy_cover(lazy != 0);
+    0
+}
+
+/// 返回的指针只在媒体信息更新（例如打开新文件）之前有效，和 yako_player_get_album_cover_size 之间可能不一致
+#[no_mangle]
+#[deprecated(note = "use yako_player_copy_album_cover instead")]
+pub extern fn yako_player_get_album_cover(player: *const YakoPlayer) -> *const u8 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.get_media_info() {
+        Some(media_info) => {
+            match media_info.cover.as_ref() {
+                // 封面数据由播放源持有的媒体信息共享，这里不复制
+                Some(cover) => cover.as_ptr(),
+                None => std::ptr::null(),
+            }
+        },
+        None => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+#[deprecated(note = "use yako_player_copy_album_cover instead")]
+pub extern fn yako_player_get_album_cover_size(player: *const YakoPlayer) -> u32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.get_media_info() {
+        Some(media_info) => {
+            match media_info.cover.as_ref() {
                 Some(cover) => cover.len() as u32,
                 None => 0,
             }
@@ -239,3 +768,1399 @@ pub extern fn yako_player_get_album_cover_size(player: *const YakoPlayer) -> u32
     }
 }
 
+/// 把封面数据复制到 buffer 中，返回封面的实际大小，没有封面时返回 0
+///
+/// buffer_len 小于封面大小时不复制，只返回需要的大小，可以先传入空指针和 0 查询大小再分配缓冲区
+#[no_mangle]
+pub extern fn yako_player_copy_album_cover(player: *const YakoPlayer, buffer: *mut u8, buffer_len: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.get_media_info().and_then(|media_info| media_info.cover.clone()) {
+        Some(cover) => copy_bytes_to_buffer(&cover, buffer, buffer_len),
+        None => 0,
+    }
+}
+
+/// 当前封面的版本，打开或者关闭曲目、播放中替换封面时改变，没有打开文件时返回 0
+///
+/// 可以定时比较版本，改变之后再复制封面数据
+#[no_mangle]
+pub extern fn yako_player_get_cover_generation(player: *const YakoPlayer) -> u64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_media_info().map_or(0, |media_info| media_info.cover_generation)
+}
+
+/// 和 yako_player_copy_album_cover 相同，同时把这份封面的版本写入 generation
+///
+/// 封面数据和版本来自同一个媒体信息快照，调用期间切换了曲目也不会不一致
+#[no_mangle]
+pub extern fn yako_player_copy_album_cover_versioned(
+    player: *const YakoPlayer,
+    buffer: *mut u8,
+    buffer_len: usize,
+    generation: *mut u64,
+) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(generation);
+    let player = unsafe {
+        &*player
+    };
+    let media_info = player.get_media_info();
+    unsafe {
+        *generation = media_info.as_ref().map_or(0, |media_info| media_info.cover_generation);
+    }
+    match media_info.and_then(|media_info| media_info.cover.clone()) {
+        Some(cover) => copy_bytes_to_buffer(&cover, buffer, buffer_len),
+        None => 0,
+    }
+}
+
+/// 把正在播放的音频流的编码器附加数据复制到 buffer 中，返回实际大小，没有附加数据时返回 0
+///
+/// buffer_len 小于数据大小时不复制，只返回需要的大小
+#[no_mangle]
+pub extern fn yako_player_copy_codec_extradata(player: *const YakoPlayer, buffer: *mut u8, buffer_len: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match player.get_media_info().and_then(|media_info| media_info.codec_extradata.clone()) {
+        Some(extradata) => copy_bytes_to_buffer(&extradata, buffer, buffer_len),
+        None => 0,
+    }
+}
+
+/// 把 data 复制到 buffer 中，返回 data 的大小。buffer_len 不够时不复制，太大无法用 i32 表示时返回 -1
+///
+/// buffer 只有在 buffer_len 为 0（查询大小）时可以是空指针，否则返回 -1，不会被当成没有数据
+fn copy_bytes_to_buffer(data: &[u8], buffer: *mut u8, buffer_len: usize) -> i32 {
+    if buffer.is_null() && buffer_len > 0 {
+        ffi_helpers::update_last_error(player::Error::InvalidArgument {
+            message: format!("the buffer is null but its length is {}", buffer_len),
+        });
+        return -1;
+    }
+    let size = match i32::try_from(data.len()) {
+        Ok(size) => size,
+        Err(_) => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("the data is too large ({} bytes)", data.len()),
+            });
+            return -1;
+        },
+    };
+    if !data.is_empty() && buffer_len >= data.len() {
+        let buffer = unsafe {
+            std::slice::from_raw_parts_mut(buffer, data.len())
+        };
+        buffer.copy_from_slice(data);
+    }
+    size
+}
+
+/// 设置解码线程每次写入输出缓冲区的采样数，不能超过动态缓冲区大小
+#[no_mangle]
+pub extern fn yako_player_set_decode_chunk_size(player: *mut YakoPlayer, size: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_decode_chunk_size(size) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern fn yako_player_get_decode_chunk_size(player: *const YakoPlayer) -> usize {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_decode_chunk_size()
+}
+
+#[no_mangle]
+pub extern fn yako_player_get_dynamic_buffer_size(player: *const YakoPlayer) -> usize {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_dynamic_buffer_size()
+}
+
+/// 获取解码后的数据到实际播放的延迟（毫秒），包括输出缓冲区和设备缓冲区
+#[no_mangle]
+pub extern fn yako_player_get_output_latency_ms(player: *const YakoPlayer) -> f64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_output_latency_ms()
+}
+
+/// 获取输出缓冲区中等待播放的数据的时长（毫秒）
+#[no_mangle]
+pub extern fn yako_player_get_buffer_latency_ms(player: *const YakoPlayer) -> f64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_buffer_latency_ms()
+}
+
+/// 设置播放速度（0.5 ~ 4），变速不变调
+#[no_mangle]
+pub extern fn yako_player_set_speed(player: *mut YakoPlayer, speed: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_speed(speed) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern fn yako_player_get_speed(player: *const YakoPlayer) -> f32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_speed()
+}
+
+#[no_mangle]
+pub extern fn yako_player_set_eq_enabled(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_eq_enabled(enabled != 0);
+    0
+}
+
+/// 设置均衡器频段增益（分贝），默认的频段为 31 Hz ~ 16 kHz 的十个倍频程
+#[no_mangle]
+pub extern fn yako_player_set_eq_band_gain(player: *mut YakoPlayer, index: usize, gain_db: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_eq_band_gain(index, gain_db) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 换成 count 段的图示均衡器（10 或者 31 段），gains_db 为按频率从低到高的 count 个频段增益（分贝）
+#[no_mangle]
+pub extern fn yako_player_set_graphic_eq(player: *mut YakoPlayer, gains_db: *const f32, count: usize) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(gains_db);
+    let player = unsafe {
+        &mut *player
+    };
+    let gains_db = unsafe {
+        std::slice::from_raw_parts(gains_db, count)
+    };
+    match player.set_graphic_eq(count, gains_db) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 开关均衡器自动增益，避免提升频段后削波
+#[no_mangle]
+pub extern fn yako_player_set_eq_auto_gain(player: *mut YakoPlayer, auto_gain: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_eq_auto_gain(auto_gain != 0);
+    0
+}
+
+/// 获取自动增益实际应用的补偿（分贝）
+#[no_mangle]
+pub extern fn yako_player_get_eq_compensation(player: *const YakoPlayer) -> f32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_eq_compensation()
+}
+
+/// 设置每个声道的延迟（毫秒），delays 的长度为 count，count 为 0 时取消所有延迟
+#[no_mangle]
+pub extern fn yako_player_set_channel_delays(player: *mut YakoPlayer, delays: *const u32, count: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let delays = if count == 0 {
+        &[]
+    } else {
+        null_pointer_check!(delays);
+        unsafe {
+            std::slice::from_raw_parts(delays, count)
+        }
+    };
+    match player.set_channel_delays(delays) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置声道映射，第 i 个元素是解码后第 i 个声道输出到的设备声道（从 0 开始），count 为 0 时恢复默认
+#[no_mangle]
+pub extern fn yako_player_set_output_channel_map(player: *mut YakoPlayer, channel_map: *const u32, count: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let channel_map = if count == 0 {
+        &[]
+    } else {
+        null_pointer_check!(channel_map);
+        unsafe {
+            std::slice::from_raw_parts(channel_map, count)
+        }
+    };
+    match player.set_output_channel_map(channel_map.iter().map(|channel| *channel as usize).collect()) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 开关总输出的限幅器，ceiling_db 为输出的上限（dBFS，-24 到 0）
+#[no_mangle]
+pub extern fn yako_player_set_master_limiter(player: *mut YakoPlayer, enabled: i32, ceiling_db: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_master_limiter(enabled != 0, ceiling_db) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置输出回调中各个处理步骤的顺序，stages 的长度为 count
+///
+/// 0 为均衡器，1 为按声道延迟，2 为卷积，3 为用户处理器，4 为限幅器。前四个步骤各出现一次，
+/// 限幅器可以省略，给出时必须在最后
+#[no_mangle]
+pub extern fn yako_player_set_processing_order(player: *mut YakoPlayer, stages: *const i32, count: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let stages = if count == 0 {
+        &[]
+    } else {
+        null_pointer_check!(stages);
+        unsafe {
+            std::slice::from_raw_parts(stages, count)
+        }
+    };
+    let mut order = Vec::with_capacity(stages.len());
+    for stage in stages {
+        order.push(match stage {
+            0 => DspStage::Equalizer,
+            1 => DspStage::ChannelDelay,
+            2 => DspStage::Convolution,
+            3 => DspStage::UserProcessor,
+            4 => DspStage::Limiter,
+            _ => {
+                ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                    message: format!("unknown processing stage {}", stage),
+                });
+                return -1;
+            }
+        });
+    }
+    match player.set_processing_order(&order) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 加载卷积使用的脉冲响应文件，path 为空指针时移除脉冲响应
+///
+/// 加载不会开启卷积，需要调用 yako_player_set_convolution
+#[no_mangle]
+pub extern fn yako_player_load_impulse_response(player: *mut YakoPlayer, path: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+
+    if path.is_null() {
+        player.clear_impulse_response();
+        return 0;
+    }
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.load_impulse_response(&path) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 开关卷积，开启时输出会增加 256 帧的延迟
+#[no_mangle]
+pub extern fn yako_player_set_convolution(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_convolution(enabled != 0);
+    0
+}
+
+/// 设置应用 ReplayGain 的方式：0 为关闭，1 为曲目增益，2 为专辑增益
+///
+/// 专辑模式下从播放队列播放时，只有相邻曲目属于同一张专辑才使用专辑增益。
+/// preamp_db 为预增益（分贝），开启防止削波时应用后的增益仍然受峰值标签限制
+#[no_mangle]
+pub extern fn yako_player_set_replay_gain(player: *mut YakoPlayer, mode: i32, preamp_db: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let mode = match mode {
+        0 => ReplayGainMode::Off,
+        1 => ReplayGainMode::Track,
+        2 => ReplayGainMode::Album,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown ReplayGain mode {}", mode),
+            });
+            return -1;
+        }
+    };
+    match player.set_replay_gain_preamp(preamp_db) {
+        Ok(_) => {
+            player.set_replay_gain_mode(mode);
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置没有增益标签时使用的增益（分贝），以及是否根据峰值标签防止削波（prevent_clipping 不为 0 时开启）
+#[no_mangle]
+pub extern fn yako_player_set_replay_gain_fallback(player: *mut YakoPlayer, fallback_db: f32, prevent_clipping: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_replay_gain_fallback(fallback_db) {
+        Ok(_) => {
+            player.set_replay_gain_prevent_clipping(prevent_clipping != 0);
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取当前曲目的峰值标签（振幅比例），没有峰值标签时返回 0
+#[no_mangle]
+pub extern fn yako_player_get_replay_gain_peak(player: *const YakoPlayer) -> f32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_replay_gain_peak().unwrap_or(0.)
+}
+
+/// 设置播放结束后的行为：-1 为根据循环模式和播放队列自动选择，0 为回到开头，1 为停留在结尾，2 为交给下一首
+#[no_mangle]
+pub extern fn yako_player_set_end_behavior(player: *mut YakoPlayer, behavior: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let behavior = match behavior {
+        -1 => None,
+        0 => Some(EndBehavior::RewindAndWait),
+        1 => Some(EndBehavior::HoldPosition),
+        2 => Some(EndBehavior::Handover),
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown end behavior {}", behavior),
+            });
+            return -1;
+        }
+    };
+    player.set_end_behavior(behavior);
+    0
+}
+
+/// 设置遇到无法解码的数据包时的处理方式：0 为跳过，1 为写入静音，2 为停止播放
+#[no_mangle]
+pub extern fn yako_player_set_error_resilience(player: *mut YakoPlayer, resilience: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let resilience = match resilience {
+        0 => ErrorResilience::Skip,
+        1 => ErrorResilience::Conceal,
+        2 => ErrorResilience::Abort,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown error resilience {}", resilience),
+            });
+            return -1;
+        }
+    };
+    player.set_error_resilience(resilience);
+    0
+}
+
+/// 在当前文件的 A、B 两点（毫秒）之间循环播放
+#[no_mangle]
+pub extern fn yako_player_set_ab_loop(player: *mut YakoPlayer, a_ms: i64, b_ms: i64) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_ab_loop(a_ms, b_ms) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern fn yako_player_clear_ab_loop(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.clear_ab_loop();
+    0
+}
+
+/// 设置定时停止：0 为关闭，1 为经过 duration_ms 毫秒后停止，2 为当前曲目结束时停止
+#[no_mangle]
+pub extern fn yako_player_set_sleep_timer(player: *mut YakoPlayer, mode: i32, duration_ms: u64) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let timer = match mode {
+        0 => SleepTimer::Off,
+        1 => SleepTimer::AfterDuration(duration_ms),
+        2 => SleepTimer::AfterCurrentTrack,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown sleep timer mode {}", mode),
+            });
+            return -1;
+        },
+    };
+    player.set_sleep_timer(timer);
+    0
+}
+
+/// 设置定时停止前淡出的时长（毫秒）
+#[no_mangle]
+pub extern fn yako_player_set_sleep_fade_duration(player: *mut YakoPlayer, duration_ms: u64) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_sleep_fade_duration(std::time::Duration::from_millis(duration_ms));
+    0
+}
+
+/// 获取当前曲目实际播放的时长（毫秒），不包括暂停的时间
+#[no_mangle]
+pub extern fn yako_player_get_played_time(player: *const YakoPlayer) -> i64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_playback_stats().played_ms
+}
+
+/// 设置提交播放记录的条件：实际播放的时长达到总长度的 ratio 或者 max_ms 毫秒
+#[no_mangle]
+pub extern fn yako_player_set_scrobble_threshold(player: *mut YakoPlayer, ratio: f64, max_ms: i64) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_scrobble_threshold(ScrobbleThreshold { ratio, max_ms }) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 开关播放位置书签，开启后打开文件时从上次记录的位置继续播放
+#[no_mangle]
+pub extern fn yako_player_set_bookmarks_enabled(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_bookmarks_enabled(enabled != 0);
+    0
+}
+
+/// 设置书签文件的路径
+#[no_mangle]
+pub extern fn yako_player_set_bookmarks_path(player: *mut YakoPlayer, path: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.set_bookmarks_path(std::path::PathBuf::from(path)) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 按位置排序的第 index 个标记点
+fn cue_point_at(player: &YakoPlayer, index: usize) -> Result<CuePoint, player::Error> {
+    player.list_cue_points().into_iter().nth(index).ok_or_else(|| player::Error::InvalidArgument {
+        message: format!("cue point index {} is out of range", index),
+    })
+}
+
+/// 在当前的播放位置添加标记点，成功时返回新的标记点按位置排序的序号，失败时返回 -1
+#[no_mangle]
+pub extern fn yako_player_add_cue_point(player: *mut YakoPlayer, name: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(name);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let name = unsafe {
+        CStr::from_ptr(name).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let name = unsafe {
+        U16CStr::from_ptr_str(name as *const u16).to_string().unwrap()
+    };
+
+    match player.add_cue_point(&name) {
+        Ok(id) => player.list_cue_points().iter().position(|point| point.id == id).unwrap_or(0) as i32,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 当前文件的标记点数量
+#[no_mangle]
+pub extern fn yako_player_get_cue_point_count(player: *const YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.list_cue_points().len() as i32
+}
+
+/// 第 index 个标记点的位置（毫秒），序号超出范围时返回 -1
+#[no_mangle]
+pub extern fn yako_player_get_cue_point_position(player: *const YakoPlayer, index: usize) -> i64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match cue_point_at(player, index) {
+        Ok(point) => point.position_ms,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取第 index 个标记点的名称，返回值的含义同 copy_string_to_buffer，序号超出范围时返回 -1
+#[no_mangle]
+pub extern fn yako_player_copy_cue_point_name(player: *const YakoPlayer, index: usize, buffer: *mut c_char, buffer_len: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match cue_point_at(player, index) {
+        Ok(point) => copy_string_to_buffer(&point.name, buffer, buffer_len),
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 定位到第 index 个标记点
+#[no_mangle]
+pub extern fn yako_player_jump_to_cue_point(player: *const YakoPlayer, index: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    match cue_point_at(player, index).and_then(|point| player.jump_to_cue(point.id)) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 移除第 index 个标记点，之后的标记点序号减一
+#[no_mangle]
+pub extern fn yako_player_remove_cue_point(player: *mut YakoPlayer, index: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match cue_point_at(player, index) {
+        Ok(point) => {
+            player.remove_cue_point(point.id);
+            0
+        },
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 在后台测量播放队列中没有增益标签的文件的响度，开启 ReplayGain 时代替曲目增益使用
+#[no_mangle]
+pub extern fn yako_player_analyze_queue_loudness(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.analyze_queue_loudness() {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 取消正在进行的响度分析
+#[no_mangle]
+pub extern fn yako_player_cancel_loudness_analysis(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.cancel_loudness_analysis();
+    0
+}
+
+/// 设置响度缓存文件的路径
+#[no_mangle]
+pub extern fn yako_player_set_loudness_cache_path(player: *mut YakoPlayer, path: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.set_loudness_cache_path(std::path::PathBuf::from(path)) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 把播放队列保存为 M3U 播放列表
+#[no_mangle]
+pub extern fn yako_player_save_playlist(player: *const YakoPlayer, path: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &*player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.save_playlist(&path) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置打开文件时传给 FFmpeg 的选项，value 为空指针时移除该选项
+#[no_mangle]
+pub extern fn yako_player_set_input_option(player: *mut YakoPlayer, key: *const c_char, value: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(key);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let (key, value) = unsafe {
+        (CStr::from_ptr(key).to_str().unwrap().to_string(),
+         (!value.is_null()).then(|| CStr::from_ptr(value).to_str().unwrap().to_string()))
+    };
+
+    #[cfg(windows)]
+    let (key, value) = unsafe {
+        (U16CStr::from_ptr_str(key as *const u16).to_string().unwrap(),
+         (!value.is_null()).then(|| U16CStr::from_ptr_str(value as *const u16).to_string().unwrap()))
+    };
+
+    let mut options = player.get_input_options().clone();
+    match value {
+        Some(value) => options.insert(key, value),
+        None => options.remove(&key),
+    };
+    player.set_input_options(options);
+    0
+}
+
+#[no_mangle]
+pub extern fn yako_player_clear_input_options(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_input_options(Default::default());
+    0
+}
+
+/// 添加一个附加输出设备，和主设备同时播放，成功时返回附加输出的编号，失败时返回 -1
+#[no_mangle]
+pub extern fn yako_player_add_output(player: *mut YakoPlayer, device_name: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(device_name);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let device_name = unsafe {
+        CStr::from_ptr(device_name).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let device_name = unsafe {
+        U16CStr::from_ptr_str(device_name as *const u16).to_string().unwrap()
+    };
+
+    match player.add_output(&device_name) {
+        Ok(id) => id as i32,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 移除附加输出设备
+#[no_mangle]
+pub extern fn yako_player_remove_output(player: *mut YakoPlayer, id: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.remove_output(id) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置附加输出的音量，取值范围和 yako_player_set_volume 相同
+#[no_mangle]
+pub extern fn yako_player_set_output_volume(player: *mut YakoPlayer, id: u32, volume: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_output_volume(id, volume) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 开关附加输出的静音
+#[no_mangle]
+pub extern fn yako_player_set_output_mute(player: *mut YakoPlayer, id: u32, mute: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_output_mute(id, mute != 0) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 在指定的 deck 上打开文件：0 为当前曲目，1 为同时播放的第二个 deck。返回值和 yako_player_open 相同
+#[no_mangle]
+pub extern fn yako_player_open_deck(player: *mut YakoPlayer, deck: u32, path: *const c_char) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    match player.open_deck(deck, &path) {
+        Ok(_) => 0,
+        Err(err) => open_error_code(err),
+    }
+}
+
+/// 开始播放指定的 deck
+#[no_mangle]
+pub extern fn yako_player_play_deck(player: *mut YakoPlayer, deck: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.play_deck(deck) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 暂停指定的 deck，另一个 deck 继续播放
+#[no_mangle]
+pub extern fn yako_player_pause_deck(player: *mut YakoPlayer, deck: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.pause_deck(deck) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 关闭指定的 deck
+#[no_mangle]
+pub extern fn yako_player_close_deck(player: *mut YakoPlayer, deck: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.close_deck(deck) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 设置 deck 的音量
+#[no_mangle]
+pub extern fn yako_player_set_deck_volume(player: *mut YakoPlayer, deck: u32, volume: f32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match player.set_deck_volume(deck, volume) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 把设备输出的音频录制到文件：format 为 0 时写入 WAV，1 时写入 FLAC
+#[no_mangle]
+pub extern fn yako_player_start_capture(player: *mut YakoPlayer, path: *const c_char, format: i32) -> i32 {
+    null_pointer_check!(player);
+    null_pointer_check!(path);
+
+    let player = unsafe {
+        &mut *player
+    };
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    let format = match format {
+        0 => CaptureFormat::Wav,
+        1 => CaptureFormat::Flac,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown capture format {}", format),
+            });
+            return -1;
+        }
+    };
+
+    match player.start_capture(std::path::PathBuf::from(path), format) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 结束录制并关闭文件
+#[no_mangle]
+pub extern fn yako_player_stop_capture(player: *mut YakoPlayer) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.stop_capture();
+    0
+}
+
+/// 设置录制时切换曲目是否写入新的文件
+#[no_mangle]
+pub extern fn yako_player_set_capture_split_on_track(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_capture_split_on_track(enabled != 0);
+    0
+}
+
+/// 设置是否录制音量调整之前的数据
+#[no_mangle]
+pub extern fn yako_player_set_capture_pre_volume(player: *mut YakoPlayer, enabled: i32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    player.set_capture_pre_volume(enabled != 0);
+    0
+}
+
+/// 获取当前录制中丢弃的帧数
+#[no_mangle]
+pub extern fn yako_player_get_capture_dropped_frames(player: *const YakoPlayer) -> u64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_capture_dropped_frames()
+}
+
+/// 获取当前曲目输出时被截断的样本数
+#[no_mangle]
+pub extern fn yako_player_get_clip_count(player: *const YakoPlayer) -> u64 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    player.get_clip_count()
+}
+
+/// 设置正在播放时打开新文件的方式：0 为替换，1 为加入播放队列，2 为交叉淡化
+///
+/// crossfade_ms 为交叉淡化的时长（毫秒），其他方式下忽略
+#[no_mangle]
+pub extern fn yako_player_set_open_mode(player: *mut YakoPlayer, mode: i32, crossfade_ms: u32) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    let mode = match mode {
+        0 => OpenMode::Replace,
+        1 => OpenMode::Enqueue,
+        2 => OpenMode::Crossfade(std::time::Duration::from_millis(crossfade_ms as u64)),
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown open mode {}", mode),
+            });
+            return -1;
+        },
+    };
+    player.set_open_mode(mode);
+    0
+}
+
+/// 音频处理回调：samples 为交错排列的 32 位浮点样本，共 frames * channels 个
+pub type YakoDspCallback = extern fn(samples: *mut f32, frames: u32, channels: u32, user_data: *mut c_void);
+
+/// 回调的用户数据由调用方保证可以跨线程使用
+struct DspUserData(*mut c_void);
+
+unsafe impl Send for DspUserData {}
+
+impl DspUserData {
+    fn as_ptr(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+/// 设置音频处理回调，callback 为空时移除
+///
+/// 回调运行在实时音频线程上，不能分配内存、加锁或进行其他可能阻塞的操作
+#[no_mangle]
+pub extern fn yako_player_set_dsp_callback(player: *mut YakoPlayer, callback: Option<YakoDspCallback>, user_data: *mut c_void) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &mut *player
+    };
+    match callback {
+        Some(callback) => {
+            let user_data = DspUserData(user_data);
+            // 按一块的最大帧数和声道数预先分配，实时线程上不需要扩大缓冲区
+            let mut buffer: Vec<f32> = Vec::with_capacity(MAX_BLOCK_FRAMES * AudioSample::MAX_CHANNELS);
+            player.set_dsp_processor(Some(Box::new(move |block: &mut [AudioSample]| {
+                let channels = match block.first() {
+                    Some(audio_sample) => audio_sample.channels(),
+                    None => return,
+                };
+                buffer.clear();
+                for audio_sample in block.iter() {
+                    buffer.extend_from_slice(audio_sample.as_slice());
+                }
+                callback(buffer.as_mut_ptr(), block.len() as u32, channels as u32, user_data.as_ptr());
+                for (audio_sample, samples) in block.iter_mut().zip(buffer.chunks_exact(channels)) {
+                    audio_sample.as_mut_slice().copy_from_slice(samples);
+                }
+            })));
+        },
+        None => player.set_dsp_processor(None),
+    }
+    0
+}
+
+/// 把字符串以 UTF-8 写入 buffer 并添加结尾的 0，返回需要的缓冲区大小（包括结尾的 0）
+///
+/// buffer_len 小于需要的大小时不写入，可以先传入空指针和 0 查询大小再分配缓冲区
+fn copy_string_to_buffer(value: &str, buffer: *mut c_char, buffer_len: usize) -> i32 {
+    let required = value.len() + 1;
+    if buffer_len >= required && !buffer.is_null() {
+        let buffer = unsafe {
+            std::slice::from_raw_parts_mut(buffer as *mut u8, required)
+        };
+        buffer[..value.len()].copy_from_slice(value.as_bytes());
+        buffer[value.len()] = 0;
+    }
+    required as i32
+}
+
+/// 获取当前文件的读取和解码统计，每项一行，返回值的含义同 copy_string_to_buffer
+#[no_mangle]
+pub extern fn yako_player_get_debug_report(player: *const YakoPlayer, buffer: *mut c_char, buffer_len: usize) -> i32 {
+    null_pointer_check!(player);
+    let player = unsafe {
+        &*player
+    };
+    copy_string_to_buffer(&player.get_debug_stats().to_string(), buffer, buffer_len)
+}
+
+/// 获取播放器和链接的 FFmpeg 的版本以及 FFmpeg 的编译选项，返回值的含义同 copy_string_to_buffer
+#[no_mangle]
+pub extern fn yako_get_ffmpeg_version(buffer: *mut c_char, buffer_len: usize) -> i32 {
+    let build_info = info::build_info();
+    let version = format!(
+        "YakoPlayer {}, FFmpeg {}, libavformat {}, libavcodec {}, configuration: {}",
+        build_info.version,
+        build_info.ffmpeg_version,
+        build_info.avformat_version,
+        build_info.avcodec_version,
+        build_info.configuration);
+    copy_string_to_buffer(&version, buffer, buffer_len)
+}
+
+/// 设置接收日志（包括 FFmpeg 的日志）的回调，callback 为空时不再输出日志
+///
+/// min_level 为 0（关闭）到 5（跟踪），回调在单独的线程上调用，不会在音频线程上调用
+#[no_mangle]
+pub extern fn yako_set_log_callback(callback: Option<logging::LogCallback>, min_level: i32) -> i32 {
+    let min_level = match min_level {
+        0 => log::LevelFilter::Off,
+        1 => log::LevelFilter::Error,
+        2 => log::LevelFilter::Warn,
+        3 => log::LevelFilter::Info,
+        4 => log::LevelFilter::Debug,
+        5 => log::LevelFilter::Trace,
+        _ => {
+            ffi_helpers::update_last_error(player::Error::InvalidArgument {
+                message: format!("unknown log level {}", min_level),
+            });
+            return -1;
+        },
+    };
+    match logging::set_log_callback(callback, min_level) {
+        Ok(_) => 0,
+        Err(err) => {
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+/// 获取支持的封装格式名称，用逗号分隔，返回值的含义同 copy_string_to_buffer
+#[no_mangle]
+pub extern fn yako_get_supported_formats(buffer: *mut c_char, buffer_len: usize) -> i32 {
+    copy_string_to_buffer(&info::build_info().demuxers.join(","), buffer, buffer_len)
+}
+
+/// 检查文件能否播放并把基本信息写入 result，不需要创建播放器，可以在多个线程中同时调用
+///
+/// 单个文件最多花费几秒，超时或者无法播放时返回 -1，result 的 ok 为 false
+#[no_mangle]
+pub extern fn yako_probe_file(path: *const c_char, result: *mut FileProbeResult) -> i32 {
+    null_pointer_check!(path);
+    null_pointer_check!(result);
+
+    #[cfg(not(windows))]
+    let path = unsafe {
+        CStr::from_ptr(path).to_str().unwrap()
+    };
+
+    #[cfg(windows)]
+    let path = unsafe {
+        U16CStr::from_ptr_str(path as *const u16).to_string().unwrap()
+    };
+
+    let result = unsafe {
+        &mut *result
+    };
+    match info::probe::probe(&path) {
+        Ok(probe) => {
+            *result = FileProbeResult::from(&probe);
+            0
+        },
+        Err(err) => {
+            *result = FileProbeResult::default();
+            ffi_helpers::update_last_error(err);
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_bytes_reports_the_size_before_copying() {
+        let data = [1u8, 2, 3, 4];
+        let size = copy_bytes_to_buffer(&data, std::ptr::null_mut(), 0);
+        assert_eq!(size, 4);
+
+        let mut buffer = vec![0u8; size as usize];
+        assert_eq!(copy_bytes_to_buffer(&data, buffer.as_mut_ptr(), buffer.len()), 4);
+        assert_eq!(buffer, data);
+    }
+
+    #[test]
+    fn copy_bytes_leaves_a_small_buffer_untouched() {
+        let mut buffer = [0u8; 2];
+        assert_eq!(copy_bytes_to_buffer(&[1, 2, 3], buffer.as_mut_ptr(), buffer.len()), 3);
+        assert_eq!(buffer, [0, 0]);
+    }
+
+    #[test]
+    fn copy_bytes_accepts_a_larger_buffer() {
+        let mut buffer = [0u8; 8];
+        assert_eq!(copy_bytes_to_buffer(&[5, 6], buffer.as_mut_ptr(), buffer.len()), 2);
+        assert_eq!(buffer[..2], [5, 6]);
+        assert_eq!(buffer[2..], [0; 6]);
+    }
+
+    #[test]
+    fn out_parameter_getters_tell_unknown_from_zero() {
+        let mut player = YakoPlayer::new();
+        // 没有打开文件时返回错误，不修改输出参数，简单的获取方法无法区分
+        let mut bitrate = 7u32;
+        let mut duration = 7i64;
+        let mut current_time = 7i64;
+        let mut peak = 7f32;
+        assert_eq!(yako_player_try_get_bitrate(&player, &mut bitrate), -1);
+        assert_eq!(yako_player_try_get_duration(&player, &mut duration), -1);
+        assert_eq!(yako_player_try_get_current_time(&player, &mut current_time), -1);
+        assert_eq!(yako_player_try_get_replay_gain_peak(&player, &mut peak), -1);
+        assert_eq!((bitrate, duration, current_time, peak), (7, 7, 7, 7.));
+        assert_eq!(player.get_bitrate(), 0);
+        assert_eq!(player.get_current_time(), 0);
+
+        let dir = audio::test_media::temp_dir("ffi-try-get");
+        let path = dir.join("tone.wav");
+        audio::test_media::write_constant_wav(&path, 44100, 44100, 0.25);
+        player.open(&path).unwrap();
+        // 打开文件之后位置为 0 是真实的值
+        assert_eq!(yako_player_try_get_current_time(&player, &mut current_time), 0);
+        assert_eq!(current_time, 0);
+        assert_eq!(yako_player_try_get_duration(&player, &mut duration), 0);
+        assert_eq!(duration, 1000);
+        // 没有 ReplayGain 标签
+        assert_eq!(yako_player_try_get_replay_gain_peak(&player, &mut peak), -1);
+        assert_eq!(peak, 7.);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cover_and_generation_are_copied_from_the_same_track() {
+        let dir = audio::test_media::temp_dir("ffi-cover");
+        let path = dir.join("cover.flac");
+        audio::test_media::write_flac(&path, 44100, 2, &vec![0x2000; 44100], Some(("image/png", b"cover")));
+        let mut player = YakoPlayer::new();
+        assert_eq!(yako_player_get_cover_generation(&player), 0);
+        // 延迟打开设备，只读取媒体信息
+        player.open(&path).unwrap();
+
+        let generation = yako_player_get_cover_generation(&player);
+        assert_ne!(generation, 0);
+        let mut buffer = [0u8; 16];
+        let mut copied_generation = 0;
+        let size = yako_player_copy_album_cover_versioned(&player, buffer.as_mut_ptr(), buffer.len(), &mut copied_generation);
+        assert_eq!(&buffer[..size as usize], b"cover");
+        assert_eq!(copied_generation, generation);
+
+        player.close().unwrap();
+        assert_eq!(yako_player_copy_album_cover_versioned(&player, buffer.as_mut_ptr(), buffer.len(), &mut copied_generation), 0);
+        assert_eq!(copied_generation, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_bytes_rejects_a_null_buffer_with_a_length() {
+        assert_eq!(copy_bytes_to_buffer(&[1, 2, 3], std::ptr::null_mut(), 16), -1);
+        assert!(ffi_helpers::take_last_error().is_some());
+    }
+}
@@ -0,0 +1,139 @@
+extern crate ffmpeg_sys_next as ffmpeg_c_api;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, Once};
+
+use libc::{c_char, c_int, c_void};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use snafu::{ensure, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("another logger has already been installed"))]
+    LoggerInstalled,
+}
+
+/// 日志回调：level 为 1（错误）到 5（跟踪），message 为 UTF-8 字符串，只在回调期间有效
+pub type LogCallback = extern fn(level: i32, message: *const c_char);
+
+/// 当前的日志回调，0 表示没有
+static CALLBACK: AtomicUsize = AtomicUsize::new(0);
+static INSTALL: Once = Once::new();
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// 把日志转发给宿主程序提供的回调
+///
+/// 日志可能来自实时音频线程，所以先放入队列，再由单独的线程调用回调
+struct CallbackLogger {
+    sender: Mutex<Sender<(Level, String)>>,
+}
+
+impl Log for CallbackLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let message = format!("{}: {}", record.target(), record.args());
+            self.sender.lock().unwrap().send((record.level(), message)).ok();
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// 设置接收日志的回调，callback 为空时不再输出日志
+///
+/// 第一次调用时安装转发日志的 logger，宿主程序已经安装了其他 logger 时返回错误
+pub fn set_log_callback(callback: Option<LogCallback>, min_level: LevelFilter) -> Result<(), Error> {
+    INSTALL.call_once(|| {
+        let (sender, receiver) = mpsc::channel::<(Level, String)>();
+        let logger = CallbackLogger { sender: Mutex::new(sender) };
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            INSTALLED.store(true, Ordering::Relaxed);
+            std::thread::spawn(move || {
+                for (level, message) in receiver {
+                    let callback = CALLBACK.load(Ordering::Acquire);
+                    if callback == 0 {
+                        continue;
+                    }
+                    // 回调由 LogCallback 转换而来
+                    let callback: LogCallback = unsafe { std::mem::transmute(callback) };
+                    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+                    callback(level as i32, message.as_ptr());
+                }
+            });
+        }
+    });
+    ensure!(INSTALLED.load(Ordering::Relaxed), LoggerInstalledSnafu);
+
+    CALLBACK.store(callback.map_or(0, |callback| callback as usize), Ordering::Release);
+    log::set_max_level(if callback.is_some() { min_level } else { LevelFilter::Off });
+    forward_ffmpeg_log();
+    Ok(())
+}
+
+#[cfg(all(target_arch = "x86_64", not(windows)))]
+type VaList = *mut ffmpeg_c_api::__va_list_tag;
+#[cfg(not(all(target_arch = "x86_64", not(windows))))]
+type VaList = ffmpeg_c_api::va_list;
+
+/// 一行日志的最大长度，超过的部分会被截断
+const FFMPEG_LINE_SIZE: usize = 1024;
+
+thread_local! {
+    /// FFmpeg 可能分几次输出同一行，先拼接完整再记录
+    static FFMPEG_LINE: RefCell<String> = RefCell::new(String::new());
+    static FFMPEG_PRINT_PREFIX: Cell<c_int> = Cell::new(1);
+}
+
+/// 把 FFmpeg 的日志转发到 log，target 为 "ffmpeg"
+///
+/// 只转发 av_log_get_level() 允许的消息
+pub fn forward_ffmpeg_log() {
+    unsafe {
+        ffmpeg_c_api::av_log_set_callback(Some(ffmpeg_log_callback));
+    }
+}
+
+fn ffmpeg_log_level(level: c_int) -> Level {
+    if level <= ffmpeg_c_api::AV_LOG_ERROR as c_int {
+        Level::Error
+    } else if level <= ffmpeg_c_api::AV_LOG_WARNING as c_int {
+        Level::Warn
+    } else if level <= ffmpeg_c_api::AV_LOG_INFO as c_int {
+        Level::Info
+    } else if level <= ffmpeg_c_api::AV_LOG_DEBUG as c_int {
+        Level::Debug
+    } else {
+        Level::Trace
+    }
+}
+
+unsafe extern "C" fn ffmpeg_log_callback(ptr: *mut c_void, level: c_int, fmt: *const c_char, vl: VaList) {
+    if level > ffmpeg_c_api::av_log_get_level() {
+        return;
+    }
+    let log_level = ffmpeg_log_level(level);
+    if !log::log_enabled!(target: "ffmpeg", log_level) {
+        return;
+    }
+    let mut buffer = [0 as c_char; FFMPEG_LINE_SIZE];
+    let mut print_prefix = FFMPEG_PRINT_PREFIX.with(|print_prefix| print_prefix.get());
+    ffmpeg_c_api::av_log_format_line2(
+        ptr, level, fmt, vl, buffer.as_mut_ptr(), buffer.len() as c_int, &mut print_prefix);
+    FFMPEG_PRINT_PREFIX.with(|cell| cell.set(print_prefix));
+    let text = CStr::from_ptr(buffer.as_ptr()).to_string_lossy();
+    FFMPEG_LINE.with(|line| {
+        let mut line = line.borrow_mut();
+        line.push_str(&text);
+        if line.ends_with('\n') {
+            log::log!(target: "ffmpeg", log_level, "{}", line.trim_end());
+            line.clear();
+        }
+    });
+}
@@ -1,28 +1,134 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod gui;
+
+use std::fmt;
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-use iced::{button, Alignment, Button, Column, Element, Settings, Text, Row, slider, Slider, time, Application, Command, Subscription, executor};
+use iced::{button, Alignment, Button, Column, Element, Settings, Text, Row, slider, Slider, pick_list, PickList, time, Application, Command, Subscription, executor};
+use iced_native::{keyboard, window, Event};
 use rfd::FileDialog;
-use player_core::{player::{YakoPlayer, Player}, audio::volume};
+use player_core::{player::{YakoPlayer, Player, queue::RepeatMode, scan, playlist, sleep::SleepTimer}, audio::volume};
+
+use gui::args::CommandLine;
+use gui::instance::{self, IncomingFiles, Instance};
+use gui::settings::PlayerSettings;
+use gui::visualizer::{self, Visualizer, VisualizerMode};
+use gui::media_controls::{MediaControlEvent, MediaControls, PlaybackStatus, TrackMetadata};
+
+/// 设备列表中表示默认设备的选项
+const DEFAULT_DEVICE_LABEL: &str = "Default device";
+/// 播放中刷新状态栏的间隔
+const STATUS_LINE_INTERVAL: Duration = Duration::from_millis(500);
+/// 调试信息的刷新间隔
+const DEBUG_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// 状态栏中表示未知值的占位符
+const UNKNOWN_VALUE: &str = "—";
+/// 错误提示的文字颜色
+const ERROR_COLOR: iced::Color = iced::Color::from_rgb(0.8, 0.2, 0.2);
+/// 可选的播放速度
+const SPEED_OPTIONS: [SpeedOption; 6] = [
+    SpeedOption(50), SpeedOption(75), SpeedOption(100), SpeedOption(125), SpeedOption(150), SpeedOption(200),
+];
+
+/// 播放速度选项（百分比）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SpeedOption(u32);
+
+impl SpeedOption {
+    fn from_speed(speed: f32) -> SpeedOption {
+        SpeedOption((speed * 100.).round() as u32)
+    }
+
+    fn speed(&self) -> f32 {
+        self.0 as f32 / 100.
+    }
+}
+
+/// 定时停止选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SleepOption {
+    Off,
+    Minutes(u64),
+    EndOfTrack,
+}
+
+impl SleepOption {
+    const ALL: [SleepOption; 5] = [
+        SleepOption::Off, SleepOption::Minutes(15), SleepOption::Minutes(30), SleepOption::Minutes(60), SleepOption::EndOfTrack,
+    ];
+
+    fn timer(&self) -> SleepTimer {
+        match self {
+            SleepOption::Off => SleepTimer::Off,
+            SleepOption::Minutes(minutes) => SleepTimer::AfterDuration(minutes * 60 * 1000),
+            SleepOption::EndOfTrack => SleepTimer::AfterCurrentTrack,
+        }
+    }
+}
+
+impl Default for SleepOption {
+    fn default() -> Self {
+        SleepOption::Off
+    }
+}
+
+impl fmt::Display for SleepOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SleepOption::Off => write!(f, "Sleep: Off"),
+            SleepOption::Minutes(minutes) => write!(f, "Sleep: {} min", minutes),
+            SleepOption::EndOfTrack => write!(f, "Sleep: End of track"),
+        }
+    }
+}
+
+impl fmt::Display for SpeedOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}×", self.speed())
+    }
+}
 
 pub fn main() -> iced::Result {
-    let open_file_path = std::env::args().nth(1);
+    gui::logger::init();
+    let command_line = CommandLine::parse(std::env::args().skip(1));
+
+    // 已经有实例在运行时，把文件交给它播放；标准输入无法转发，总是在当前进程中播放
+    let incoming_files = if command_line.stdin {
+        IncomingFiles::default()
+    } else {
+        let paths: Vec<String> = command_line.paths.iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        match instance::acquire(&paths) {
+            Instance::Primary(incoming_files) => incoming_files,
+            Instance::Secondary => return Ok(()),
+        }
+    };
+
+    let settings = PlayerSettings::load();
+
+    let position = match settings.window_position {
+        Some((x, y)) => iced::window::Position::Specific(x, y),
+        None => iced::window::Position::Default,
+    };
 
     PlayerController::run(Settings {
         window: iced::window::Settings {
-            size: (600, 130),
+            size: (720, 270),
+            position,
             resizable: false,
             ..iced::window::Settings::default()
         },
-        flags: open_file_path,
+        flags: (command_line, settings, incoming_files),
+        exit_on_close_request: false,
         ..Settings::default()
     })
 }
 
 #[derive(Default)]
 struct PlayerController {
-    last_seek_time: i64,
     value: f32,
     state: State,
     duration: i64,
@@ -31,20 +137,64 @@ struct PlayerController {
     play_button: button::State,
     pause_button: button::State,
     stop_button: button::State,
+    previous_button: button::State,
+    next_button: button::State,
+    speed_pick_list: pick_list::State<SpeedOption>,
+    speed_button: button::State,
     progress_bar_slider: slider::State,
     volume_slider: slider::State,
     volume: f32,
+    mute_button: button::State,
+    repeat_button: button::State,
+    shuffle_button: button::State,
+    time_mode_button: button::State,
+    device_pick_list: pick_list::State<String>,
+    sleep_pick_list: pick_list::State<SleepOption>,
+    sleep_option: SleepOption,
+    output_devices: Vec<String>,
+    visualizer: Visualizer,
+    media_controls: MediaControls,
+    status_line: String,
+    /// 显示在窗口顶部的错误提示
+    error_banner: Option<String>,
+    dismiss_error_button: button::State,
+    last_status_update: Option<Instant>,
+    /// 读取和解码的统计，按 Ctrl+D 显示或者隐藏，隐藏时为 None
+    debug_report: Option<String>,
+    last_debug_update: Option<Instant>,
+    settings: PlayerSettings,
+    incoming_files: IncomingFiles,
+    should_exit: bool,
     player: YakoPlayer,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Message {
     OpenPressed,
     PlayPressed,
     PausePressed,
     StopPressed,
+    PreviousPressed,
+    NextPressed,
     ProgressBarChanged(f32),
     VolumeChanged(f32),
+    VolumeReleased,
+    MuteToggled,
+    RepeatModeChanged,
+    ShuffleToggled,
+    TimeModeToggled,
+    OutputDeviceSelected(String),
+    SpeedSelected(SpeedOption),
+    SleepSelected(SleepOption),
+    WindowMoved(i32, i32),
+    CloseRequested,
+    FilesReceived(Vec<PathBuf>),
+    DebugToggled,
+    CueSet(usize),
+    CueJumped(usize),
+    VisualizerClicked,
+    ErrorDismissed,
+    MediaControl(MediaControlEvent),
     Tick(Instant),
 }
 
@@ -60,37 +210,267 @@ impl Default for State {
 }
 
 impl PlayerController {
-    pub fn play_from_file(&mut self, path: String) {
-        match self.player.open(&path) {
+    pub fn play_files(&mut self, paths: Vec<PathBuf>) {
+        let paths = self.expand_playlists(paths);
+        self.player.get_queue_mut().set_entries(paths);
+        self.play_adjacent(true);
+    }
+
+    /// 把播放列表替换为其中的文件，其他文件保持不变
+    fn expand_playlists(&mut self, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut expanded = Vec::with_capacity(paths.len());
+        for path in paths {
+            if !playlist::is_playlist(&path) {
+                expanded.push(path);
+                continue;
+            }
+            match playlist::load_m3u(&path) {
+                Ok(entries) => expanded.extend(entries.into_iter().map(|entry| entry.path)),
+                Err(err) => self.show_error(format!("Failed to read the playlist {}: {}", path.display(), err)),
+            }
+        }
+        expanded
+    }
+
+    /// 播放上一首或下一首
+    fn play_adjacent(&mut self, next: bool) {
+        let result = if next { self.player.play_next() } else { self.player.play_previous() };
+        match result {
+            Ok(_) => {
+                self.duration = self.player.get_duration();
+                self.current_time = self.player.get_current_time();
+                if self.player.is_playing() {
+                    self.state = State::Playing;
+                }
+                self.track_changed();
+            },
+            Err(err) => self.show_error(err.to_string()),
+        }
+    }
+
+    /// 在错误提示中显示消息
+    fn show_error(&mut self, message: String) {
+        log::error!("{}", message);
+        self.error_banner = Some(message);
+    }
+
+    /// 播放从标准输入读取的音频
+    fn play_stdin(&mut self) {
+        let result = self.player.open_reader(Box::new(std::io::stdin()))
+            .and_then(|_| self.player.play());
+        match result {
             Ok(_) => {
                 self.duration = self.player.get_duration();
                 self.current_time = 0;
+                self.state = State::Playing;
+                self.track_changed();
             },
-            Err(err) => {
-                println!("{}", err);
-            }
+            Err(err) => self.show_error(err.to_string()),
         }
+    }
+
+    fn play(&mut self) {
         if let Err(err) = self.player.play() {
-            println!("{}", err);
+            log::error!("{}", err);
         } else {
             self.state = State::Playing;
+            self.media_controls.set_playback(PlaybackStatus::Playing, self.player.get_current_time());
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Err(err) = self.player.pause() {
+            log::error!("{}", err);
+        } else {
+            self.state = State::Stop;
+            self.media_controls.set_playback(PlaybackStatus::Paused, self.player.get_current_time());
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Err(err) = self.player.stop() {
+            log::error!("{}", err);
+        } else {
+            self.state = State::Stop;
+            self.media_controls.set_playback(PlaybackStatus::Stopped, 0);
+        }
+        self.value = 0.0;
+        self.visualizer.clear();
+    }
+
+    fn seek(&mut self, time: i64) {
+        if let Err(err) = self.player.seek(time) {
+            log::error!("{}", err);
+        } else {
+            self.media_controls.seeked(time);
         }
     }
+
+    fn track_changed(&mut self) {
+        if self.settings.reset_speed_per_track && self.settings.speed != 1. {
+            self.set_speed(1.);
+        }
+        self.update_media_metadata();
+        self.update_status_line(self.player.get_current_bitrate());
+        self.last_status_update = Some(Instant::now());
+    }
+
+    /// 更新状态栏，例如 "FLAC · 44.1 kHz · 16-bit · 987 kbps"
+    fn update_status_line(&mut self, bitrate: i64) {
+        let media_info = self.player.get_media_info();
+        let codec = media_info
+            .as_ref()
+            .and_then(|media_info| media_info.codec.clone())
+            .unwrap_or_else(|| UNKNOWN_VALUE.to_string());
+        let sample_rate = match media_info.as_ref().map(|media_info| media_info.sample_rate).unwrap_or(0) {
+            0 => UNKNOWN_VALUE.to_string(),
+            rate if rate % 1000 == 0 => format!("{} kHz", rate / 1000),
+            rate => format!("{:.1} kHz", rate as f64 / 1000.),
+        };
+        let bits = match media_info.as_ref().map(|media_info| media_info.bits_per_sample).unwrap_or(0) {
+            0 => UNKNOWN_VALUE.to_string(),
+            bits => format!("{}-bit", bits),
+        };
+        let bitrate = if bitrate > 0 {
+            format!("{} kbps", (bitrate as f64 / 1000.).round())
+        } else {
+            UNKNOWN_VALUE.to_string()
+        };
+        self.status_line = format!("{} · {} · {} · {}", codec, sample_rate, bits, bitrate);
+    }
+
+    /// 曲目改变后更新系统媒体控制显示的信息
+    fn update_media_metadata(&mut self) {
+        let file_name = self.player.get_queue().current()
+            .and_then(|path| path.file_stem())
+            .map(|name| name.to_string_lossy().into_owned());
+        let metadata = match self.player.get_media_info() {
+            Some(media_info) => TrackMetadata {
+                title: media_info.title.clone().or(file_name),
+                artist: media_info.artist.clone(),
+                album: media_info.album.clone(),
+                duration: media_info.duration,
+                cover: media_info.cover.as_deref().map(<[u8]>::to_vec),
+            },
+            None => TrackMetadata {
+                title: file_name,
+                ..TrackMetadata::default()
+            },
+        };
+        self.media_controls.set_metadata(&metadata);
+        let status = if self.player.is_playing() { PlaybackStatus::Playing } else { PlaybackStatus::Paused };
+        self.media_controls.set_playback(status, self.player.get_current_time());
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        if let Err(err) = self.player.set_speed(speed) {
+            log::error!("{}", err);
+        } else {
+            self.settings.speed = speed;
+            self.save_settings();
+        }
+    }
+
+    fn save_settings(&self) {
+        if let Err(err) = self.settings.save() {
+            log::error!("Failed to save settings: {}", err);
+        }
+    }
+
+    fn repeat_mode_label(repeat_mode: RepeatMode) -> &'static str {
+        match repeat_mode {
+            RepeatMode::Off => "Repeat: Off",
+            RepeatMode::All => "Repeat: All",
+            RepeatMode::One => "Repeat: One",
+        }
+    }
+
+    fn format_time(time: i64) -> String {
+        // 总长度未知
+        if time < 0 {
+            return "--:--".to_string();
+        }
+        format!("{:0>2}:{:0>2}", time / 60000, (time / 1000) % 60)
+    }
+}
+
+/// 数字键对应的标记点名称
+fn cue_slot(key_code: keyboard::KeyCode) -> Option<usize> {
+    use keyboard::KeyCode::*;
+
+    [Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9].iter()
+        .position(|key| *key == key_code)
+        .map(|index| index + 1)
+}
+
+fn handle_native_event(event: Event, _status: iced_native::event::Status) -> Option<Message> {
+    match event {
+        Event::Window(window::Event::Moved { x, y }) => Some(Message::WindowMoved(x, y)),
+        Event::Window(window::Event::CloseRequested) => Some(Message::CloseRequested),
+        Event::Window(window::Event::FileDropped(path)) => Some(Message::FilesReceived(vec![path])),
+        Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) if modifiers.control() => {
+            match key_code {
+                keyboard::KeyCode::Left => Some(Message::PreviousPressed),
+                keyboard::KeyCode::Right => Some(Message::NextPressed),
+                keyboard::KeyCode::D => Some(Message::DebugToggled),
+                _ => None,
+            }
+        },
+        // 1～9 定位到对应的标记点，按住 Shift 时在当前位置设置这个标记点
+        Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) => {
+            cue_slot(key_code).map(|slot| if modifiers.shift() { Message::CueSet(slot) } else { Message::CueJumped(slot) })
+        },
+        _ => None,
+    }
 }
 
 impl Application for PlayerController {
     type Message = Message;
     type Executor = executor::Default;
-    type Flags = Option<String>;
+    type Flags = (CommandLine, PlayerSettings, IncomingFiles);
 
     fn new(flags: Self::Flags) -> (PlayerController, iced::Command<Message>) {
+        let (command_line, mut settings, incoming_files) = flags;
+        // 命令行指定的设备和音量优先于保存的设置
+        if let Some(device) = command_line.device {
+            settings.output_device = Some(device);
+        }
         let mut controller = Self {
-            volume: 1.,
+            volume: command_line.volume.unwrap_or(settings.volume),
+            settings,
+            incoming_files,
             ..Default::default()
         };
+
+        if let Err(err) = controller.player.set_volume(controller.volume) {
+            log::error!("{}", err);
+        }
+        if let Err(err) = controller.player.set_mute(controller.settings.mute) {
+            log::error!("{}", err);
+        }
+        if let Err(err) = controller.player.set_output_device(controller.settings.output_device.clone()) {
+            log::error!("{}", err);
+        }
+        controller.player.get_queue_mut().set_repeat_mode(controller.settings.repeat_mode);
+        controller.player.get_queue_mut().set_shuffle(controller.settings.shuffle);
+        if let Err(err) = controller.player.set_speed(controller.settings.speed) {
+            log::error!("{}", err);
+        }
+        controller.player.set_analysis_enabled(controller.visualizer.mode() != VisualizerMode::Off);
+        controller.media_controls = MediaControls::new();
+
+        controller.output_devices = std::iter::once(DEFAULT_DEVICE_LABEL.to_string())
+            .chain(controller.player.get_output_device_names())
+            .collect();
         
-        if let Some(path) = flags {
-            controller.play_from_file(path);
+        // 无效的路径已经被跳过，只在错误提示中报告
+        if !command_line.errors.is_empty() {
+            controller.show_error(command_line.errors.join("\n"));
+        }
+        if command_line.stdin {
+            controller.play_stdin();
+        } else if !command_line.paths.is_empty() {
+            controller.play_files(command_line.paths);
         }
 
         (controller, Command::none())
@@ -103,66 +483,90 @@ impl Application for PlayerController {
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::OpenPressed => {
+                let directory = self.settings.last_directory.clone()
+                    .unwrap_or_else(|| "/".into());
+                let extensions: Vec<&str> = scan::AUDIO_EXTENSIONS.iter()
+                    .chain(playlist::PLAYLIST_EXTENSIONS.iter())
+                    .copied()
+                    .collect();
                 let files = FileDialog::new()
-                    .add_filter("Music", &["wav", "mp3", "flac", "ogg", "opus", "aac", "m4a", "mp4", "wma", "ape", "tak", "alac"])
-                    .set_directory("/")
-                    .pick_file();
-                if let Some(file) = files {
-                    match self.player.open(&file) {
-                        Ok(_) => {
-                            self.duration = self.player.get_duration();
-                            self.current_time = 0;
-                        },
-                        Err(err) => {
-                            println!("{}", err);
-                        }
-                    }
-                    if let Err(err) = self.player.play() {
-                        println!("{}", err);
-                    } else {
-                        self.state = State::Playing;
+                    .add_filter("Music", &extensions)
+                    .add_filter("Playlists", &playlist::PLAYLIST_EXTENSIONS)
+                    .set_directory(&directory)
+                    .pick_files();
+                if let Some(files) = files {
+                    if let Some(parent) = files.first().and_then(|file| file.parent()) {
+                        self.settings.last_directory = Some(parent.to_path_buf());
+                        self.save_settings();
                     }
+                    self.play_files(files);
                 }
                 self.value = 0.0;
             },
             Message::PlayPressed => {
-                if let Err(err) = self.player.play() {
-                    println!("{}", err);
-                } else {
-                    self.state = State::Playing;
-                }
+                self.play();
             },
             Message::PausePressed => {
-                if let Err(err) = self.player.pause() {
-                    println!("{}", err);
-                } else {
-                    self.state = State::Stop;
-                }
+                self.pause();
             },
             Message::StopPressed => {
-                if let Err(err) = self.player.stop() {
-                    println!("{}", err);
-                } else {
-                    self.state = State::Stop;
+                self.stop();
+            },
+            Message::PreviousPressed => {
+                self.play_adjacent(false);
+            },
+            Message::NextPressed => {
+                if self.player.get_queue().has_next() {
+                    self.play_adjacent(true);
                 }
-                self.value = 0.0;
             },
+            // 总长度未知或者为 0 时无法换算定位时间
+            Message::ProgressBarChanged(_) if !self.player.is_seekable() || self.duration <= 0 => {},
             Message::ProgressBarChanged(value) => {
                 self.value = value;
                 let seek_time = (value * (self.duration as f32)) as i64;
-                if seek_time != self.last_seek_time {
-                    // 防抖
-                    self.last_seek_time = seek_time;
-
-                    if let Err(err) = self.player.seek(seek_time) {
-                        println!("{}", err);
-                    }
-                }
+                self.seek(seek_time);
             },
-            Message::Tick(_) => match &mut self.state {
+            Message::Tick(now) => match self.state {
                 State::Playing => {
-                    self.current_time = self.player.get_current_time();
-                    self.value = (self.current_time as f32) / (self.duration as f32);
+                    // 定时停止已经触发，播放器自己暂停了
+                    if self.sleep_option != SleepOption::Off && self.player.get_sleep_timer() == SleepTimer::Off {
+                        self.sleep_option = SleepOption::Off;
+                        if !self.player.is_playing() {
+                            self.state = State::Stop;
+                            self.media_controls.set_playback(PlaybackStatus::Paused, self.player.get_current_time());
+                            return Command::none();
+                        }
+                    }
+                    let status = self.player.get_status();
+                    self.current_time = status.current_time;
+                    self.duration = status.duration;
+                    self.value = if self.duration > 0 {
+                        (self.current_time as f32) / (self.duration as f32)
+                    } else {
+                        0.0
+                    };
+                    self.media_controls.set_playback(PlaybackStatus::Playing, self.current_time);
+                    if self.visualizer.mode() != VisualizerMode::Off {
+                        self.visualizer.update(
+                            self.player.get_spectrum(visualizer::SPECTRUM_BANDS),
+                            self.player.get_peak_levels(),
+                        );
+                    }
+                    let status_expired = self.last_status_update
+                        .map(|time| now.duration_since(time) >= STATUS_LINE_INTERVAL)
+                        .unwrap_or(true);
+                    if status_expired {
+                        self.update_status_line(status.bitrate);
+                        self.last_status_update = Some(now);
+                    }
+                    let debug_expired = self.last_debug_update
+                        .map(|time| now.duration_since(time) >= DEBUG_REPORT_INTERVAL)
+                        .unwrap_or(true);
+                    if self.debug_report.is_some() && debug_expired {
+                        self.debug_report = Some(self.player.get_debug_stats().to_string());
+                        self.last_debug_update = Some(now);
+                    }
                 }
                 _ => {}
             },
@@ -170,12 +574,171 @@ impl Application for PlayerController {
                 self.volume = value;
                 self.player.set_volume(value).unwrap();
             },
+            Message::VolumeReleased => {
+                self.settings.volume = self.volume;
+                self.save_settings();
+            },
+            Message::MuteToggled => {
+                let mute = !self.settings.mute;
+                if let Err(err) = self.player.set_mute(mute) {
+                    log::error!("{}", err);
+                } else {
+                    self.settings.mute = mute;
+                    self.save_settings();
+                }
+            },
+            Message::RepeatModeChanged => {
+                let repeat_mode = match self.settings.repeat_mode {
+                    RepeatMode::Off => RepeatMode::All,
+                    RepeatMode::All => RepeatMode::One,
+                    RepeatMode::One => RepeatMode::Off,
+                };
+                self.player.get_queue_mut().set_repeat_mode(repeat_mode);
+                self.settings.repeat_mode = repeat_mode;
+                self.save_settings();
+            },
+            Message::ShuffleToggled => {
+                let shuffle = !self.settings.shuffle;
+                self.player.get_queue_mut().set_shuffle(shuffle);
+                self.settings.shuffle = shuffle;
+                self.save_settings();
+            },
+            Message::TimeModeToggled => {
+                self.settings.show_remaining = !self.settings.show_remaining;
+                self.save_settings();
+            },
+            Message::OutputDeviceSelected(name) => {
+                let device = if name == DEFAULT_DEVICE_LABEL { None } else { Some(name) };
+                if let Err(err) = self.player.set_output_device(device.clone()) {
+                    log::error!("{}", err);
+                } else {
+                    self.settings.output_device = device;
+                    self.save_settings();
+                }
+            },
+            Message::SpeedSelected(option) => {
+                self.set_speed(option.speed());
+            },
+            Message::SleepSelected(option) => {
+                self.player.set_sleep_timer(option.timer());
+                self.sleep_option = option;
+            },
+            Message::WindowMoved(x, y) => {
+                self.settings.window_position = Some((x, y));
+            },
+            Message::FilesReceived(paths) => {
+                // 其他实例转发来的文件和拖放的文件加入队列，当前没有播放时立即播放第一个
+                let paths = self.expand_playlists(paths);
+                let first_index = self.player.get_queue().len();
+                for path in paths {
+                    self.player.get_queue_mut().push(path);
+                }
+                if !self.player.is_playing() {
+                    match self.player.play_queue_entry(first_index) {
+                        Ok(_) => {
+                            self.duration = self.player.get_duration();
+                            self.current_time = 0;
+                            self.value = 0.0;
+                            self.state = State::Playing;
+                            self.track_changed();
+                        },
+                        Err(err) => log::error!("{}", err),
+                    }
+                }
+            },
+            Message::ErrorDismissed => {
+                self.error_banner = None;
+            },
+            Message::DebugToggled => {
+                self.debug_report = match self.debug_report {
+                    Some(_) => None,
+                    None => Some(self.player.get_debug_stats().to_string()),
+                };
+                self.last_debug_update = Some(Instant::now());
+            },
+            Message::CueSet(slot) => {
+                // 同一个数字键只保留一个标记点，重新设置时替换原来的位置
+                let name = slot.to_string();
+                for point in self.player.list_cue_points().into_iter().filter(|point| point.name == name) {
+                    self.player.remove_cue_point(point.id);
+                }
+                if let Err(err) = self.player.add_cue_point(&name) {
+                    log::warn!("Failed to set cue point {}: {}", name, err);
+                }
+            },
+            Message::CueJumped(slot) => {
+                let name = slot.to_string();
+                if let Some(point) = self.player.list_cue_points().into_iter().find(|point| point.name == name) {
+                    self.seek(point.position_ms);
+                }
+            },
+            Message::VisualizerClicked => {
+                let mode = self.visualizer.cycle_mode();
+                // 关闭显示时同时关闭音频分析，不占用音频线程
+                self.player.set_analysis_enabled(mode != VisualizerMode::Off);
+            },
+            Message::MediaControl(event) => match event {
+                MediaControlEvent::Play => self.play(),
+                MediaControlEvent::Pause => self.pause(),
+                MediaControlEvent::Toggle => {
+                    if self.player.is_playing() {
+                        self.pause();
+                    } else {
+                        self.play();
+                    }
+                },
+                MediaControlEvent::Stop => self.stop(),
+                MediaControlEvent::Next => {
+                    if self.player.get_queue().has_next() {
+                        self.play_adjacent(true);
+                    }
+                },
+                MediaControlEvent::Previous => self.play_adjacent(false),
+                MediaControlEvent::SetPosition(position) => {
+                    self.seek(position.clamp(0, self.duration.max(0)));
+                },
+                MediaControlEvent::Seek(offset) => {
+                    let position = self.player.get_current_time() + offset;
+                    self.seek(position.clamp(0, self.duration.max(0)));
+                },
+            },
+            Message::CloseRequested => {
+                self.settings.volume = self.volume;
+                self.save_settings();
+                self.should_exit = true;
+            },
         }
 
         Command::none()
     }
 
     fn view(&mut self) -> Element<Message> {
+        let mut previous_button = Button::new(&mut self.previous_button, Text::new("Prev"));
+        if self.player.get_queue().has_previous() {
+            previous_button = previous_button.on_press(Message::PreviousPressed);
+        }
+        let mut next_button = Button::new(&mut self.next_button, Text::new("Next"));
+        if self.player.get_queue().has_next() {
+            next_button = next_button.on_press(Message::NextPressed);
+        }
+
+        // 没有打开文件时用不可点击的按钮代替下拉列表
+        let speed_option = SpeedOption::from_speed(self.settings.speed);
+        let speed_control: Element<Message> = if self.player.get_media_info().is_some() {
+            PickList::new(
+                    &mut self.speed_pick_list,
+                    &SPEED_OPTIONS[..],
+                    Some(speed_option),
+                    Message::SpeedSelected,
+                )
+                .width(iced::Length::Units(80))
+                .into()
+        } else {
+            Button::new(&mut self.speed_button, Text::new(speed_option.to_string()))
+                .width(iced::Length::Units(80))
+                .into()
+        };
+
         let row: Element<Message> = Row::new()
             .spacing(4)
             .align_items(Alignment::Center)
@@ -183,6 +746,7 @@ impl Application for PlayerController {
                 Button::new(&mut self.open_button, Text::new("Open"))
                     .on_press(Message::OpenPressed),
             )
+            .push(previous_button)
             .push(
                 Button::new(&mut self.play_button, Text::new("Play"))
                     .on_press(Message::PlayPressed),
@@ -195,6 +759,8 @@ impl Application for PlayerController {
                 Button::new(&mut self.stop_button, Text::new("Stop"))
                     .on_press(Message::StopPressed),
             )
+            .push(next_button)
+            .push(speed_control)
             .push(Text::new(" Volume:").size(20))
             .push(Slider::new(
                     &mut self.volume_slider,
@@ -202,14 +768,69 @@ impl Application for PlayerController {
                     self.volume,
                     Message::VolumeChanged,
                 )
-                .step(0.01).width(iced::Length::Units(160)),)
+                .on_release(Message::VolumeReleased)
+                .step(0.01).width(iced::Length::Units(120)),)
             .push(Text::new(format!(" {:.2} dB", volume::volume_level_to_db(self.volume))).size(20))
             .into();
 
-        Column::new()
+        let selected_device = Some(self.settings.output_device.clone()
+            .unwrap_or_else(|| DEFAULT_DEVICE_LABEL.to_string()));
+
+        let settings_row: Element<Message> = Row::new()
+            .spacing(4)
+            .align_items(Alignment::Center)
+            .push(
+                Button::new(&mut self.mute_button, Text::new(if self.settings.mute { "Unmute" } else { "Mute" }))
+                    .on_press(Message::MuteToggled),
+            )
+            .push(
+                Button::new(&mut self.repeat_button, Text::new(Self::repeat_mode_label(self.settings.repeat_mode)))
+                    .on_press(Message::RepeatModeChanged),
+            )
+            .push(
+                Button::new(&mut self.shuffle_button, Text::new(if self.settings.shuffle { "Shuffle: On" } else { "Shuffle: Off" }))
+                    .on_press(Message::ShuffleToggled),
+            )
+            .push(
+                Button::new(&mut self.time_mode_button, Text::new(if self.settings.show_remaining { "Elapsed" } else { "Remaining" }))
+                    .on_press(Message::TimeModeToggled),
+            )
+            .push(PickList::new(
+                    &mut self.sleep_pick_list,
+                    &SleepOption::ALL[..],
+                    Some(self.sleep_option),
+                    Message::SleepSelected,
+                )
+                .width(iced::Length::Units(110)),)
+            .push(Text::new(" Output:").size(20))
+            .push(PickList::new(
+                    &mut self.device_pick_list,
+                    &self.output_devices,
+                    selected_device,
+                    Message::OutputDeviceSelected,
+                )
+                .width(iced::Length::Units(140)),)
+            .into();
+
+        let mut content = Column::new()
             .padding(20)
             .spacing(6)
-            .align_items(Alignment::Start)
+            .align_items(Alignment::Start);
+
+        if let Some(message) = self.error_banner.as_ref() {
+            content = content.push(
+                Row::new()
+                    .spacing(8)
+                    .align_items(Alignment::Center)
+                    .push(Text::new(message.as_str()).size(16).color(ERROR_COLOR))
+                    .push(
+                        Button::new(&mut self.dismiss_error_button, Text::new("Dismiss").size(16))
+                            .on_press(Message::ErrorDismissed),
+                    ),
+            );
+        }
+
+        let content = content
             .push(
                 Slider::new(
                     &mut self.progress_bar_slider,
@@ -220,22 +841,44 @@ impl Application for PlayerController {
                 .step(0.01),
             )
             .push(Text::new({
-                let mut s = String::from("Current time: ");
-                s.push_str(format!("{:0>2}:{:0>2}", &self.current_time / 60000, (&self.current_time / 1000) % 60).as_str());
+                let mut s = if self.settings.show_remaining && self.duration >= 0 {
+                    // 剩余时间按播放速度换算为实际等待的时间
+                    let remaining = ((self.duration - self.current_time).max(0) as f32 / self.settings.speed) as i64;
+                    format!("Remaining time: -{}", Self::format_time(remaining))
+                } else {
+                    format!("Current time: {}", Self::format_time(self.current_time))
+                };
                 s.push_str(", Total time: ");
-                s.push_str(format!("{:0>2}:{:0>2}", &self.duration / 60000, (&self.duration / 1000) % 60).as_str());
+                s.push_str(Self::format_time(self.duration).as_str());
                 s
             }).size(20))
             .push(row)
-            .into()
+            .push(Text::new(if self.status_line.is_empty() { UNKNOWN_VALUE } else { self.status_line.as_str() }).size(16))
+            .push(settings_row)
+            .push(self.visualizer.view(Message::VisualizerClicked));
+
+        match self.debug_report.as_ref() {
+            Some(report) => content.push(Text::new(report.as_str()).size(12)).into(),
+            None => content.into(),
+        }
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        match self.state {
+        let tick = match self.state {
             State::Stop => Subscription::none(),
             State::Playing { .. } => {
                 time::every(Duration::from_millis(100)).map(Message::Tick)
             }
-        }
+        };
+        Subscription::batch(vec![
+            tick,
+            iced_native::subscription::events_with(handle_native_event),
+            self.incoming_files.subscription().map(Message::FilesReceived),
+            self.media_controls.subscription().map(Message::MediaControl),
+        ])
+    }
+
+    fn should_exit(&self) -> bool {
+        self.should_exit
     }
 }
\ No newline at end of file
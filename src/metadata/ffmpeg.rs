@@ -1,19 +1,80 @@
 extern crate ffmpeg_next as ffmpeg;
 extern crate ffmpeg_sys_next as ffmpeg_c_api;
 
+use std::sync::Arc;
+
 use ffmpeg::format;
 use ffmpeg_c_api::AVPacket;
 
+use crate::audio::replaygain;
+use crate::audio::source::default_audio_stream;
+use crate::info::media::{MediaInfo, ReplayGain};
+
+use super::{MetadataExtractor, MetadataSource};
+
+/// 使用 FFmpeg 解析的标签读取封面、标题等信息
+pub struct FFmpegMetadataExtractor;
+
+impl MetadataExtractor for FFmpegMetadataExtractor {
+    fn extract(&self, source: &MetadataSource, media_info: &mut MediaInfo) {
+        // 获取专辑封面
+        if source.include_cover {
+            media_info.cover = first_picture_from_input_context(source.input).map(Arc::from);
+        }
+        // 获取标签
+        media_info.title = tag_from_input_context(source.input, "title");
+        media_info.artist = tag_from_input_context(source.input, "artist");
+        media_info.album = tag_from_input_context(source.input, "album");
+        media_info.replay_gain = replay_gain_from_input_context(source.input);
+    }
+}
+
 pub fn first_picture_from_input_context(input_ctx: &format::context::input::Input) -> Option<Vec<u8>> {
     input_ctx.streams()
         .into_iter()
         .filter(|stream| stream.disposition().contains(format::stream::Disposition::ATTACHED_PIC))
         .next()
-        .map(|stream| {
-            let picture_data = unsafe {
-                let picture_packet = AVPacket::from((*stream.as_ptr()).attached_pic);
-                std::slice::from_raw_parts(picture_packet.data, picture_packet.size as usize)
-            };
-            picture_data.to_vec()
-        })
-}
\ No newline at end of file
+        .map(|stream| attached_picture(&stream))
+}
+
+/// 附加图片流的图片数据
+pub fn attached_picture(stream: &format::stream::Stream) -> Vec<u8> {
+    let picture_data = unsafe {
+        let picture_packet = AVPacket::from((*stream.as_ptr()).attached_pic);
+        if picture_packet.data.is_null() || picture_packet.size <= 0 {
+            return Vec::new();
+        }
+        std::slice::from_raw_parts(picture_packet.data, picture_packet.size as usize)
+    };
+    picture_data.to_vec()
+}
+
+/// 读取标签，先查找容器的元数据，找不到时再查找音频流的元数据（例如 Ogg 的 Vorbis Comment）
+pub fn tag_from_input_context(input_ctx: &format::context::input::Input, key: &str) -> Option<String> {
+    let value = input_ctx.metadata().get(key).map(|value| value.to_string()).or_else(|| {
+        default_audio_stream(input_ctx)
+            .and_then(|stream| stream.metadata().get(key).map(|value| value.to_string()))
+    })?;
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// 读取 ReplayGain 标签，没有 REPLAYGAIN_* 增益时使用 Opus 的 R128_* 增益
+pub fn replay_gain_from_input_context(input_ctx: &format::context::input::Input) -> ReplayGain {
+    let gain = |replay_gain_key: &str, r128_key: &str| {
+        tag_from_input_context(input_ctx, replay_gain_key)
+            .and_then(|value| replaygain::parse_gain(&value))
+            .or_else(|| tag_from_input_context(input_ctx, r128_key).and_then(|value| replaygain::parse_r128_gain(&value)))
+    };
+    let peak = |key: &str| tag_from_input_context(input_ctx, key).and_then(|value| replaygain::parse_peak(&value));
+    ReplayGain {
+        track_gain: gain("REPLAYGAIN_TRACK_GAIN", "R128_TRACK_GAIN"),
+        track_peak: peak("REPLAYGAIN_TRACK_PEAK"),
+        album_gain: gain("REPLAYGAIN_ALBUM_GAIN", "R128_ALBUM_GAIN"),
+        album_peak: peak("REPLAYGAIN_ALBUM_PEAK"),
+    }
+}
@@ -0,0 +1,17 @@
+//! Shoutcast/Icecast 网络电台的 ICY 元数据
+
+/// 从 ICY 元数据中读取当前播放的曲目标题，例如 `StreamTitle='Artist - Title';StreamUrl='';`
+///
+/// 标题中可能包含单引号，以 `';` 作为结束标记
+pub fn parse_stream_title(packet: &str) -> Option<String> {
+    const KEY: &str = "StreamTitle='";
+    let start = packet.find(KEY)? + KEY.len();
+    let rest = &packet[start..];
+    let end = rest.find("';").or_else(|| rest.rfind('\'')).unwrap_or(rest.len());
+    let title = rest[..end].trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    }
+}
@@ -1 +1,25 @@
-pub mod ffmpeg;
\ No newline at end of file
+use std::path::Path;
+
+use ffmpeg_next::format::context::input::Input;
+
+use crate::info::media::MediaInfo;
+
+pub mod ffmpeg;
+pub mod icy;
+
+/// 打开文件时提供给元数据读取器的信息
+pub struct MetadataSource<'a> {
+    /// 文件路径，从读取器打开时为 None
+    pub path: Option<&'a Path>,
+    /// FFmpeg 打开的输入
+    pub input: &'a Input,
+    /// 是否需要读取封面，为 false 时封面会在打开之后由解码线程读取
+    pub include_cover: bool,
+}
+
+/// 元数据读取器，打开文件时按顺序调用，后面的读取器可以补充或者覆盖前面读取到的信息
+///
+/// 在打开文件的线程中调用，读取器不应该修改音频格式相关的字段
+pub trait MetadataExtractor: Send + Sync {
+    fn extract(&self, source: &MetadataSource, media_info: &mut MediaInfo);
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::source::FFmpegSourceStatus;
+
+use super::cue::CuePoint;
+
+/// 配置目录下的子目录名
+pub const APP_DIR_NAME: &str = "YakoPlayer";
+/// 书签文件名
+const BOOKMARKS_FILE_NAME: &str = "bookmarks.json";
+/// 播放中记录位置的间隔
+const RECORD_INTERVAL: Duration = Duration::from_secs(15);
+/// 检查是否需要退出的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 播放超过这个时间（毫秒）才会恢复位置
+pub const MIN_RESUME_POSITION: i64 = 60_000;
+/// 播放进度超过这个比例时视为已经听完，从头开始
+pub const MAX_RESUME_RATIO: f64 = 0.95;
+
+/// 默认的书签文件路径
+pub fn default_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR_NAME)
+        .join(BOOKMARKS_FILE_NAME)
+}
+
+/// 书签的键：文件路径和大小，文件被替换后不会恢复到错误的位置
+pub fn bookmark_key(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    let size = fs::metadata(&path).ok()?.len();
+    Some(format!("{}#{}", path.display(), size))
+}
+
+/// 判断记录的位置是否值得恢复
+pub fn should_resume(position: i64, duration: i64) -> bool {
+    position > MIN_RESUME_POSITION && (duration <= 0 || (position as f64) < duration as f64 * MAX_RESUME_RATIO)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BookmarkFile {
+    /// 键为 bookmark_key，值为播放位置（毫秒）
+    positions: HashMap<String, i64>,
+    /// 键为 bookmark_key，值为文件中的标记点
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    cue_points: HashMap<String, Vec<CuePoint>>,
+}
+
+/// 正在播放的文件
+struct CurrentTrack {
+    key: String,
+    status: Arc<FFmpegSourceStatus>,
+}
+
+struct RecorderState {
+    path: PathBuf,
+    file: BookmarkFile,
+    current: Option<CurrentTrack>,
+}
+
+impl RecorderState {
+    fn load(path: PathBuf) -> io::Result<RecorderState> {
+        let file = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BookmarkFile::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(RecorderState { path, file, current: None })
+    }
+
+    /// 先写入临时文件再重命名，避免写入过程中断导致文件损坏
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(&self.file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let temp_path = self.path.with_extension("json.tmp");
+        {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// 记录当前文件的播放位置
+    fn record(&mut self) {
+        let (key, position) = match self.current.as_ref() {
+            Some(current) => (current.key.clone(), current.status.current_time.lock().unwrap().get()),
+            None => return,
+        };
+        if self.file.positions.get(&key) == Some(&position) {
+            return;
+        }
+        self.file.positions.insert(key, position);
+        if let Err(err) = self.save() {
+            log::warn!("Failed to save bookmarks to {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// 按文件记录播放位置，下次打开时从上次的位置继续播放
+pub struct BookmarkRecorder {
+    enabled: bool,
+    /// 是否已经读取了书签文件
+    loaded: bool,
+    state: Arc<Mutex<RecorderState>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BookmarkRecorder {
+    pub fn new() -> BookmarkRecorder {
+        // 开启书签时才读取文件
+        let state = RecorderState { path: default_path(), file: BookmarkFile::default(), current: None };
+        BookmarkRecorder {
+            enabled: false,
+            loaded: false,
+            state: Arc::new(Mutex::new(state)),
+            running: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 开关书签，开启时在后台线程中定时记录播放位置
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        self.enabled = enabled;
+        if enabled {
+            if !self.loaded {
+                let mut state = self.state.lock().unwrap();
+                match RecorderState::load(state.path.clone()) {
+                    Ok(loaded) => state.file = loaded.file,
+                    Err(err) => log::warn!("Failed to load bookmarks from {}: {}", state.path.display(), err),
+                }
+                self.loaded = true;
+            }
+            self.running.store(true, Ordering::Relaxed);
+            let state = self.state.clone();
+            let running = self.running.clone();
+            self.thread = Some(std::thread::spawn(move || {
+                let mut elapsed = Duration::ZERO;
+                while running.load(Ordering::Relaxed) {
+                    std::thread::sleep(POLL_INTERVAL);
+                    elapsed += POLL_INTERVAL;
+                    if elapsed < RECORD_INTERVAL {
+                        continue;
+                    }
+                    elapsed = Duration::ZERO;
+                    let mut state = state.lock().unwrap();
+                    let playing = state.current.as_ref()
+                        .map(|current| current.status.playing.load(Ordering::Relaxed))
+                        .unwrap_or(false);
+                    if playing {
+                        state.record();
+                    }
+                }
+            }));
+        } else {
+            self.stop_thread();
+        }
+    }
+
+    pub fn get_path(&self) -> PathBuf {
+        self.state.lock().unwrap().path.clone()
+    }
+
+    /// 改为使用指定的书签文件，读取失败时保持原来的文件
+    pub fn set_path(&mut self, path: PathBuf) -> io::Result<()> {
+        let mut new_state = RecorderState::load(path)?;
+        let mut state = self.state.lock().unwrap();
+        new_state.current = state.current.take();
+        *state = new_state;
+        self.loaded = true;
+        Ok(())
+    }
+
+    /// 查找文件记录的播放位置
+    pub fn get_position(&self, path: &Path) -> Option<i64> {
+        if !self.enabled {
+            return None;
+        }
+        let key = bookmark_key(path)?;
+        self.state.lock().unwrap().file.positions.get(&key).copied()
+    }
+
+    /// 设置当前播放的文件，先记录上一个文件的位置
+    pub fn set_current(&mut self, current: Option<(&Path, Arc<FFmpegSourceStatus>)>) {
+        let mut state = self.state.lock().unwrap();
+        if self.enabled {
+            state.record();
+        }
+        state.current = current.and_then(|(path, status)| {
+            bookmark_key(path).map(|key| CurrentTrack { key, status })
+        });
+    }
+
+    /// 查找文件保存的标记点
+    pub fn get_cue_points(&self, path: &Path) -> Vec<CuePoint> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        bookmark_key(path)
+            .and_then(|key| self.state.lock().unwrap().file.cue_points.get(&key).cloned())
+            .unwrap_or_default()
+    }
+
+    /// 保存文件的标记点并立即写入书签文件，没有标记点时删除记录
+    pub fn set_cue_points(&self, path: &Path, points: &[CuePoint]) {
+        if !self.enabled {
+            return;
+        }
+        let key = match bookmark_key(path) {
+            Some(key) => key,
+            None => return,
+        };
+        let mut state = self.state.lock().unwrap();
+        if points.is_empty() {
+            if state.file.cue_points.remove(&key).is_none() {
+                return;
+            }
+        } else {
+            state.file.cue_points.insert(key, points.to_vec());
+        }
+        if let Err(err) = state.save() {
+            log::warn!("Failed to save bookmarks to {}: {}", state.path.display(), err);
+        }
+    }
+
+    /// 立即记录当前文件的播放位置
+    pub fn record(&self) {
+        if self.enabled {
+            self.state.lock().unwrap().record();
+        }
+    }
+
+    fn stop_thread(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Default for BookmarkRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for BookmarkRecorder {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+/// 标记点的编号，在同一个文件中不会重复使用
+pub type CueId = u64;
+
+/// 文件中的一个命名标记点
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CuePoint {
+    pub id: CueId,
+    pub name: String,
+    /// 相对于文件（或者片段）开头的位置（毫秒）
+    pub position_ms: i64,
+}
+
+/// 当前文件的标记点，按位置排序，位置相同时按添加的顺序
+#[derive(Debug, Default)]
+pub struct CueList {
+    points: Vec<CuePoint>,
+    next_id: CueId,
+}
+
+impl CueList {
+    pub fn new() -> CueList {
+        CueList { points: Vec::new(), next_id: 0 }
+    }
+
+    /// 使用保存的标记点，新的编号从保存的最大编号之后开始
+    pub fn from_points(mut points: Vec<CuePoint>) -> CueList {
+        points.sort_by_key(|point| (point.position_ms, point.id));
+        let next_id = points.iter().map(|point| point.id + 1).max().unwrap_or(0);
+        CueList { points, next_id }
+    }
+
+    pub fn points(&self) -> &[CuePoint] {
+        &self.points
+    }
+
+    pub fn get(&self, id: CueId) -> Option<&CuePoint> {
+        self.points.iter().find(|point| point.id == id)
+    }
+
+    /// 添加标记点，返回新的编号
+    pub fn add(&mut self, name: &str, position_ms: i64) -> CueId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let position_ms = position_ms.max(0);
+        let index = self.points.partition_point(|point| point.position_ms <= position_ms);
+        self.points.insert(index, CuePoint { id, name: name.to_string(), position_ms });
+        id
+    }
+
+    /// 移除标记点，编号不存在时返回 false
+    pub fn remove(&mut self, id: CueId) -> bool {
+        let count = self.points.len();
+        self.points.retain(|point| point.id != id);
+        self.points.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.next_id = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &CueList) -> Vec<&str> {
+        list.points().iter().map(|point| point.name.as_str()).collect()
+    }
+
+    #[test]
+    fn points_are_kept_in_position_order() {
+        let mut list = CueList::new();
+        list.add("b", 2000);
+        list.add("a", 1000);
+        list.add("b2", 2000);
+        list.add("start", -5);
+        assert_eq!(names(&list), ["start", "a", "b", "b2"]);
+        assert_eq!(list.points()[0].position_ms, 0);
+    }
+
+    #[test]
+    fn removed_ids_are_not_reused() {
+        let mut list = CueList::new();
+        let first = list.add("first", 0);
+        assert!(list.remove(first));
+        assert!(!list.remove(first));
+        assert!(list.get(first).is_none());
+        assert_ne!(list.add("second", 0), first);
+    }
+
+    #[test]
+    fn saved_points_are_sorted_and_new_ids_follow_them() {
+        let saved = vec![
+            CuePoint { id: 7, name: "late".to_string(), position_ms: 3000 },
+            CuePoint { id: 2, name: "early".to_string(), position_ms: 1000 },
+        ];
+        let mut list = CueList::from_points(saved);
+        assert_eq!(names(&list), ["early", "late"]);
+        assert_eq!(list.add("new", 0), 8);
+
+        list.clear();
+        assert!(list.points().is_empty());
+        assert_eq!(list.add("again", 0), 0);
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::replaygain::EffectiveGain;
+use crate::info::media::TechnicalInfo;
+
+use super::loudness::LoudnessResult;
+
+/// 播放器在后台发出的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    /// 新的曲目开始播放，从读取器打开时没有路径
+    NowPlaying {
+        path: Option<PathBuf>,
+        /// 总长度（毫秒），未知时为 -1
+        duration_ms: i64,
+    },
+    /// 实际播放的时长达到了提交播放记录的条件，每个曲目只发出一次
+    ScrobbleReady {
+        played_ms: i64,
+        duration_ms: i64,
+    },
+    /// 曲目播放结束或者被替换，played_ms 为实际播放的总时长
+    TrackFinished {
+        played_ms: i64,
+        duration_ms: i64,
+    },
+    /// 网络电台的 ICY 元数据或者链式 Ogg 的标签中的曲目标题发生变化，MediaInfo.title 同时更新
+    StreamTitleChanged(String),
+    /// 数据流中途出现了新的封面图片（例如链式 Ogg 的下一段或者推送的附加图片），MediaInfo.cover 同时更新
+    CoverChanged(Arc<[u8]>),
+    /// 开启了延迟读取封面时，打开之后在解码线程中读取到了内嵌的封面，MediaInfo.cover 同时更新
+    CoverReady(Arc<[u8]>),
+    /// 当前的封面被替换：打开或者关闭了曲目，或者播放中读到了新的封面（同时发出 CoverChanged 或者 CoverReady）。
+    /// generation 和 MediaInfo.cover_generation 相同，关闭曲目时为 0，cover 为 None 表示没有封面
+    ArtworkChanged {
+        generation: u64,
+        cover: Option<Arc<[u8]>>,
+    },
+    /// 打开了新的播放源，或者链式 Ogg 等格式在同一个文件或者流中切换到了下一段，携带新的音频格式
+    TrackChanged(TechnicalInfo),
+    /// 当前曲目已经播放完毕，输出缓冲区中的音频都已经被设备取出。
+    /// 结束行为为 EndBehavior::Handover 时在最后的数据写入输出缓冲区后立即发出，下一首紧接着写入。
+    /// 无缝循环时每一遍解码到结尾都会发出，不等待输出缓冲区播放完
+    EndOfStream,
+    /// 本地文件在播放中无法继续读取（例如拔出了 U 盘），播放已经停止，position_ms 为停止时的位置
+    SourceLost {
+        position_ms: i64,
+    },
+    /// 遇到无法解码的数据包并且处理方式为 Abort，播放已经停止，定位之后可以继续播放
+    DecodeError {
+        position_ms: i64,
+        message: String,
+    },
+    /// 当前曲目输出时有样本超出最大幅度被截断，samples 为距离上一次事件新增的样本数。
+    /// 需要先用 set_clipping_events 开启，最多每 250 毫秒发出一次
+    Clipping {
+        samples: u64,
+    },
+    /// 网络流或者解码跟不上播放导致输出缓冲区耗尽时开始缓冲（true），恢复播放时为 false，断线重连期间保持缓冲状态
+    Buffering(bool),
+    /// 实际应用的 ReplayGain 增益发生变化，例如专辑模式下播放到了专辑边界，或者修改了 ReplayGain 设置
+    ReplayGainChanged(EffectiveGain),
+    /// 后台目录扫描完成，files 为找到的音频文件数量，warnings 为跳过的条目数量
+    DirectoryScanned {
+        path: PathBuf,
+        files: usize,
+        warnings: usize,
+    },
+    /// 播放队列的响度分析完成了一个文件，completed 为已经处理的文件数，total 为队列中的文件数
+    LoudnessAnalyzed {
+        path: PathBuf,
+        completed: usize,
+        total: usize,
+        result: LoudnessResult,
+    },
+}
+
+/// 把事件分发给所有订阅者，可以在多个线程之间共享
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<PlayerEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    /// 订阅事件，接收端被丢弃后自动取消订阅
+    pub fn subscribe(&self) -> Receiver<PlayerEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// 发出事件
+    pub fn emit(&self, event: PlayerEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use snafu::{ensure, ResultExt};
+
+use crate::audio::capture::{self, FileEncoding};
+use crate::audio::sample::AudioSample;
+use crate::audio::source::{DecodedStream, FFmpegSource, TimeWindow};
+
+use super::{Error, CaptureSnafu, ExportCancelledSnafu, SourceSnafu};
+
+/// 导出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// 16 位整数 WAV
+    Wav16,
+    /// 32 位浮点 WAV，不损失解码后的精度
+    Wav32f,
+    /// 16 位 FLAC
+    Flac,
+}
+
+impl From<ExportFormat> for FileEncoding {
+    fn from(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Wav16 => FileEncoding::Wav16,
+            ExportFormat::Wav32f => FileEncoding::Wav32Float,
+            ExportFormat::Flac => FileEncoding::Flac,
+        }
+    }
+}
+
+/// 导出文件的采样率和声道数，写入文件头的值总是和实际的数据一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportSampleFormat {
+    /// 和文件相同，不重采样
+    Native,
+    /// 和当前的输出设备相同，结果和录制设备输出时的格式一致
+    Device,
+    /// 转换为指定的采样率和声道数
+    Custom {
+        sample_rate: u32,
+        channels: u16,
+    },
+}
+
+impl Default for ExportSampleFormat {
+    fn default() -> Self {
+        ExportSampleFormat::Native
+    }
+}
+
+/// 转换格式时每次读取的帧数
+const EXPORT_CHUNK_FRAMES: usize = 4096;
+
+/// 导出进度回调，参数为已经完成的比例（0 到 1）
+pub type ExportProgress = Box<dyn Fn(f32) + Send>;
+
+/// 在后台线程中进行的导出
+pub struct ExportJob {
+    cancelled: Arc<AtomicBool>,
+    thread: JoinHandle<Result<(), Error>>,
+}
+
+impl ExportJob {
+    /// 开始把 input 中 window 范围内的音频写入 output
+    ///
+    /// conversion 为 None 时保持原来的采样率和声道数，否则转换为指定的采样率和声道数
+    pub fn start(
+        input: PathBuf,
+        input_options: HashMap<String, String>,
+        window: TimeWindow,
+        output: PathBuf,
+        format: ExportFormat,
+        conversion: Option<(u32, u16)>,
+        progress: Option<ExportProgress>,
+    ) -> ExportJob {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let cancelled = cancelled.clone();
+            std::thread::spawn(move || {
+                let result = match conversion {
+                    Some((sample_rate, channels)) => {
+                        DecodedStream::open(&input, &input_options, &[], window, sample_rate, channels)
+                            .context(SourceSnafu)
+                            .and_then(|stream| export_converted(stream, window, &output, format, progress.as_ref(), &cancelled))
+                    },
+                    None => export(&input, &input_options, window, &output, format, progress.as_ref(), &cancelled),
+                };
+                if result.is_err() {
+                    // 不保留不完整的文件
+                    std::fs::remove_file(&output).ok();
+                }
+                result
+            })
+        };
+        ExportJob { cancelled, thread }
+    }
+
+    /// 取消导出，已经写入的文件会被删除
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// 等待导出完成
+    pub fn wait(self) -> Result<(), Error> {
+        self.thread.join().unwrap_or_else(|_| Err(Error::Unavailable {
+            message: "the export thread panicked".to_string(),
+        }))
+    }
+}
+
+fn export(
+    input: &Path,
+    input_options: &HashMap<String, String>,
+    window: TimeWindow,
+    output: &Path,
+    format: ExportFormat,
+    progress: Option<&ExportProgress>,
+    cancelled: &AtomicBool,
+) -> Result<(), Error> {
+    let frames = FFmpegSource::frames_in(&input, input_options, window).context(SourceSnafu)?;
+    let sample_rate = frames.sample_rate();
+    let mut writer = capture::create_writer(output, format.into(), sample_rate, frames.channels() as u16)
+        .context(CaptureSnafu)?;
+    let total_frames = total_frames(window, sample_rate);
+    for samples in frames {
+        ensure!(!cancelled.load(Ordering::Relaxed), ExportCancelledSnafu);
+        match samples {
+            Ok(samples) => writer.write(&samples).context(CaptureSnafu)?,
+            // 和播放时一样跳过损坏的数据包
+            Err(err) => log::warn!("skipped an undecodable packet while exporting: {}", err),
+        }
+        if let (Some(progress), Some(total_frames)) = (progress, total_frames) {
+            progress((writer.frames() as f64 / total_frames as f64).min(1.) as f32);
+        }
+    }
+    writer.finish().context(CaptureSnafu)?;
+    if let Some(progress) = progress {
+        progress(1.);
+    }
+    Ok(())
+}
+
+/// 和 export 相同，stream 已经转换为导出的采样率和声道数，和 Player::open_decoder 使用相同的重采样器
+fn export_converted(
+    mut stream: DecodedStream,
+    window: TimeWindow,
+    output: &Path,
+    format: ExportFormat,
+    progress: Option<&ExportProgress>,
+    cancelled: &AtomicBool,
+) -> Result<(), Error> {
+    let sample_rate = stream.sample_rate();
+    let channels = stream.channels();
+    let mut writer = capture::create_writer(output, format.into(), sample_rate, channels as u16).context(CaptureSnafu)?;
+    let total_frames = total_frames(window, sample_rate);
+    let mut buffer = vec![0f32; EXPORT_CHUNK_FRAMES * channels];
+    let mut samples = Vec::with_capacity(EXPORT_CHUNK_FRAMES);
+    loop {
+        ensure!(!cancelled.load(Ordering::Relaxed), ExportCancelledSnafu);
+        let count = match stream.read_samples(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            // 和播放时一样跳过损坏的数据包
+            Err(err) => {
+                log::warn!("skipped an undecodable packet while exporting: {}", err);
+                continue;
+            },
+        };
+        samples.clear();
+        samples.extend(buffer[..count].chunks_exact(channels).map(AudioSample::from_slice));
+        writer.write(&samples).context(CaptureSnafu)?;
+        if let (Some(progress), Some(total_frames)) = (progress, total_frames) {
+            progress((writer.frames() as f64 / total_frames as f64).min(1.) as f32);
+        }
+    }
+    writer.finish().context(CaptureSnafu)?;
+    if let Some(progress) = progress {
+        progress(1.);
+    }
+    Ok(())
+}
+
+/// window 范围内的帧数，用于计算进度
+///
+/// 终点超过文件长度时进度会提前结束，完成时总是报告 1
+fn total_frames(window: TimeWindow, sample_rate: u32) -> Option<u64> {
+    window.end_ms
+        .map(|end_ms| ((end_ms - window.start_ms) as f64 * sample_rate as f64 / 1000.) as u64)
+        .filter(|total_frames| *total_frames > 0)
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::loudness::LoudnessMeter;
+use crate::audio::source::{FFmpegSource, TimeWindow};
+use crate::info::media::ReplayGain;
+use crate::metadata::MetadataExtractor;
+
+use super::bookmarks::APP_DIR_NAME;
+use super::event::{EventBus, PlayerEvent};
+
+/// 响度缓存文件名
+const LOUDNESS_CACHE_FILE_NAME: &str = "loudness.json";
+
+/// 默认的响度缓存文件路径
+pub fn default_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(APP_DIR_NAME)
+        .join(LOUDNESS_CACHE_FILE_NAME)
+}
+
+/// 缓存的键：文件路径和修改时间，文件被修改后重新测量
+pub fn cache_key(path: &Path) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+    let modified = fs::metadata(&path).ok()?.modified().ok()?;
+    let modified = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}#{}.{:09}", path.display(), modified.as_secs(), modified.subsec_nanos()))
+}
+
+/// 测量得到的响度，和 ReplayGain 曲目标签的含义相同
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MeasuredLoudness {
+    /// 达到 ReplayGain 参考响度需要的增益（分贝）
+    pub gain_db: f32,
+    /// 采样峰值
+    pub peak: f32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    /// 键为 cache_key
+    entries: HashMap<String, MeasuredLoudness>,
+}
+
+/// 保存在文件中的响度测量结果，第一次使用时才读取文件
+pub struct LoudnessCache {
+    path: PathBuf,
+    file: CacheFile,
+    loaded: bool,
+}
+
+impl LoudnessCache {
+    pub fn new() -> LoudnessCache {
+        LoudnessCache { path: default_path(), file: CacheFile::default(), loaded: false }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 改为使用指定的缓存文件，读取失败时保持原来的文件
+    pub fn set_path(&mut self, path: PathBuf) -> io::Result<()> {
+        self.file = LoudnessCache::load(&path)?;
+        self.path = path;
+        self.loaded = true;
+        Ok(())
+    }
+
+    fn load(path: &Path) -> io::Result<CacheFile> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(CacheFile::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn ensure_loaded(&mut self) {
+        if self.loaded {
+            return;
+        }
+        match LoudnessCache::load(&self.path) {
+            Ok(file) => self.file = file,
+            Err(err) => log::warn!("Failed to load the loudness cache from {}: {}", self.path.display(), err),
+        }
+        self.loaded = true;
+    }
+
+    /// 先写入临时文件再重命名，避免写入过程中断导致文件损坏
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string(&self.file)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let temp_path = self.path.with_extension("json.tmp");
+        {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+
+    /// 查找文件的测量结果，文件修改过时返回 None
+    pub fn get(&mut self, path: &Path) -> Option<MeasuredLoudness> {
+        self.ensure_loaded();
+        let key = cache_key(path)?;
+        self.file.entries.get(&key).copied()
+    }
+
+    fn insert(&mut self, key: String, loudness: MeasuredLoudness) {
+        self.ensure_loaded();
+        self.file.entries.insert(key, loudness);
+    }
+}
+
+impl Default for LoudnessCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 没有曲目增益标签时用测量结果代替，已有标签的文件保持不变
+pub fn fill_replay_gain(replay_gain: &ReplayGain, measured: Option<MeasuredLoudness>) -> ReplayGain {
+    match measured {
+        Some(measured) if replay_gain.track_gain.is_none() => ReplayGain {
+            track_gain: Some(measured.gain_db),
+            track_peak: Some(measured.peak),
+            ..*replay_gain
+        },
+        _ => *replay_gain,
+    }
+}
+
+/// 分析一个文件的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoudnessResult {
+    /// 测量完成，或者之前已经测量过
+    Measured(MeasuredLoudness),
+    /// 文件中已经有增益标签，不需要测量
+    Tagged,
+    /// 无法打开或者解码文件，或者音频太短无法测量
+    Failed,
+}
+
+/// 要分析的文件
+pub struct LoudnessTarget {
+    /// 队列中的路径
+    pub path: PathBuf,
+    /// 改写后实际打开的路径，用作缓存的键
+    pub uri: PathBuf,
+}
+
+/// 在后台线程中依次测量播放队列中文件的响度
+pub struct LoudnessAnalysis {
+    cancelled: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LoudnessAnalysis {
+    /// 开始分析，每个文件完成时发出 LoudnessAnalyzed 事件，结果写入 cache
+    pub fn start(
+        targets: Vec<LoudnessTarget>,
+        input_options: HashMap<String, String>,
+        extractors: Vec<Arc<dyn MetadataExtractor>>,
+        cache: Arc<Mutex<LoudnessCache>>,
+        events: EventBus,
+    ) -> LoudnessAnalysis {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let cancelled = cancelled.clone();
+            std::thread::spawn(move || {
+                let total = targets.len();
+                for (index, target) in targets.iter().enumerate() {
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let result = LoudnessAnalysis::analyze(target, &input_options, &extractors, &cache, &cancelled);
+                    if cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    events.emit(PlayerEvent::LoudnessAnalyzed {
+                        path: target.path.clone(),
+                        completed: index + 1,
+                        total,
+                        result,
+                    });
+                }
+                let cache = cache.lock().unwrap();
+                if let Err(err) = cache.save() {
+                    log::warn!("Failed to save the loudness cache to {}: {}", cache.path.display(), err);
+                }
+            })
+        };
+        LoudnessAnalysis { cancelled, thread: Some(thread) }
+    }
+
+    fn analyze(
+        target: &LoudnessTarget,
+        input_options: &HashMap<String, String>,
+        extractors: &[Arc<dyn MetadataExtractor>],
+        cache: &Mutex<LoudnessCache>,
+        cancelled: &AtomicBool,
+    ) -> LoudnessResult {
+        let key = match cache_key(&target.uri) {
+            Some(key) => key,
+            None => return LoudnessResult::Failed,
+        };
+        if let Some(measured) = cache.lock().unwrap().get(&target.uri) {
+            return LoudnessResult::Measured(measured);
+        }
+        let frames = match FFmpegSource::frames_in(&target.uri, input_options, TimeWindow::default()) {
+            Ok(frames) => frames,
+            Err(err) => {
+                log::warn!("failed to open {} for loudness analysis: {}", target.path.display(), err);
+                return LoudnessResult::Failed;
+            },
+        };
+        if frames.media_info(Some(target.uri.as_path()), extractors).replay_gain.track_gain.is_some() {
+            return LoudnessResult::Tagged;
+        }
+        let mut meter = LoudnessMeter::new(frames.sample_rate(), frames.channels());
+        for samples in frames {
+            if cancelled.load(Ordering::Relaxed) {
+                return LoudnessResult::Failed;
+            }
+            match samples {
+                Ok(samples) => meter.push(&samples),
+                // 和播放时一样跳过损坏的数据包
+                Err(err) => log::warn!("skipped an undecodable packet while analyzing loudness: {}", err),
+            }
+        }
+        let gain_db = match meter.replay_gain() {
+            Some(gain_db) => gain_db,
+            None => return LoudnessResult::Failed,
+        };
+        let measured = MeasuredLoudness { gain_db, peak: meter.peak() };
+        cache.lock().unwrap().insert(key, measured);
+        LoudnessResult::Measured(measured)
+    }
+
+    /// 停止分析，已经完成的结果仍然会保存
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// 等待分析结束
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for LoudnessAnalysis {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -0,0 +1,4407 @@
+pub mod queue;
+pub mod sleep;
+pub mod bookmarks;
+pub mod event;
+pub mod stats;
+pub mod scan;
+pub mod probe;
+pub mod export;
+pub mod loudness;
+pub mod position;
+pub mod playlist;
+pub mod cue;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, SystemTime};
+
+use cpal::HostId;
+use snafu::{Snafu, ResultExt, OptionExt, ensure};
+
+use crate::audio::device::{AudioDevice, DeviceSampleFormat, OutputId, MAX_REBUFFER_FRAMES};
+use crate::audio::source::{AudioSource, DecodedStream, EndBehavior, EndSignal, ErrorResilience, OpenProgress, TimeWindow};
+use crate::audio::device;
+use crate::audio::source;
+use crate::audio::source::FFmpegSource;
+use crate::audio::sample::AudioSample;
+use crate::audio::volume::VolumeCurve;
+use crate::audio::dsp::{self, DspProcessor, DspStage, ProcessingOrder};
+use crate::audio::dsp::convolution::{self, ConvolutionState, ImpulseResponse};
+use crate::audio::dsp::delay;
+use crate::audio::dsp::equalizer::{self, Equalizer};
+use crate::audio::dsp::limiter;
+use crate::audio::replaygain::{EffectiveGain, GainSource, ReplayGainMode, ReplayGainSettings};
+use crate::audio::capture::{self, CaptureFormat, CaptureSession};
+use crate::audio::analysis;
+use crate::archive::{self, ZipArchive};
+use crate::info::media::{self, MediaInfo, RawTags, TechnicalInfo};
+use crate::metadata::MetadataExtractor;
+use crate::info::status::{DebugStats, PlayerStatus};
+
+use self::queue::{PlayQueue, RepeatMode};
+use self::sleep::{SleepTimer, SleepTimerMonitor};
+use self::bookmarks::BookmarkRecorder;
+use self::event::{EventBus, PlayerEvent};
+use self::stats::{PlaybackStats, PlaybackTracker, ScrobbleThreshold};
+use self::scan::{DirectoryScan, ScanSummary};
+use self::export::{ExportFormat, ExportJob, ExportProgress, ExportSampleFormat};
+use self::loudness::{LoudnessAnalysis, LoudnessCache, LoudnessTarget, MeasuredLoudness};
+use self::position::PositionSmoother;
+use self::cue::{CueId, CueList, CuePoint};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Device { 
+        #[snafu(source(from(device::Error, Box::new)))]
+        source: Box::<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[snafu(display("{}", source))]
+    Source {
+        #[snafu(source(from(source::Error, Box::new)))]
+        source: Box::<dyn std::error::Error + Send + Sync>
+    },
+
+    #[snafu(display("{}", message))]
+    Queue {
+        message: String,
+    },
+
+    #[snafu(display("invalid argument: {}", message))]
+    InvalidArgument {
+        message: String,
+    },
+
+    #[snafu(display("failed to access bookmarks: {}", source))]
+    Bookmarks {
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to access the loudness cache: {}", source))]
+    LoudnessCache {
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to access the playlist: {}", source))]
+    Playlist {
+        source: std::io::Error,
+    },
+
+    #[snafu(display("{}", source))]
+    Archive {
+        source: archive::Error,
+    },
+
+    #[snafu(display("{}", message))]
+    Unavailable {
+        message: String,
+    },
+
+    #[snafu(display("{}", source))]
+    Capture {
+        source: capture::Error,
+    },
+
+    #[snafu(display("the export was cancelled"))]
+    ExportCancelled,
+}
+
+impl Error {
+    /// 文件可以打开，但是没有可以播放的音频流（例如没有声音的视频），和文件不存在、格式不支持等错误区分
+    pub fn is_no_audio_stream(&self) -> bool {
+        match self {
+            Error::Source { source } => matches!(source.downcast_ref::<source::Error>(), Some(source::Error::NoAudioStream)),
+            _ => false,
+        }
+    }
+}
+
+/// 正在播放时打开新文件的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// 关闭当前曲目，立即播放新文件
+    Replace,
+    /// 把新文件加入播放队列末尾，不打断当前曲目
+    Enqueue,
+    /// 当前曲目在指定时间内淡出，同时新文件淡入
+    Crossfade(Duration),
+}
+
+impl Default for OpenMode {
+    fn default() -> Self {
+        OpenMode::Replace
+    }
+}
+
+/// 曲目播放到结尾时播放器自己执行的操作，不需要宿主程序处理事件，EndOfStream 等事件仍然照常发出
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishedAction {
+    /// 播放完输出缓冲区中剩余的音频后停止，再次播放时从头开始
+    Stop,
+    /// 无缝地从头重新播放当前曲目
+    LoopTrack,
+    /// 按播放队列和循环模式把输出交给下一首
+    AdvanceQueue,
+}
+
+impl Default for FinishedAction {
+    fn default() -> Self {
+        FinishedAction::AdvanceQueue
+    }
+}
+
+/// deck 的编号，同时打开两个文件时分别控制
+pub type DeckId = u32;
+/// 当前曲目所在的 deck，和 open、play 等方法操作的是同一个播放源
+pub const MAIN_DECK: DeckId = 0;
+/// 和当前曲目同时播放的第二个 deck，用于对比两个文件或者手动交叉淡化
+pub const SECOND_DECK: DeckId = 1;
+
+/// 打开文件时的选项
+#[derive(Clone)]
+pub struct OpenOptions {
+    resume: bool,
+    clip: Option<(i64, i64)>,
+    progress: Option<OpenProgress>,
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions { resume: true, clip: None, progress: None }
+    }
+
+    /// 开启书签时，是否从上次记录的位置继续播放
+    pub fn resume(mut self, resume: bool) -> OpenOptions {
+        self.resume = resume;
+        self
+    }
+
+    /// 只播放文件中 start_ms 到 end_ms 之间的片段，例如试听铃声
+    ///
+    /// 总长度为片段的长度，播放位置和定位都相对于片段起点，播放到 end_ms 时和文件结尾一样结束。
+    /// 片段播放不会从书签记录的位置继续
+    pub fn clip(mut self, start_ms: i64, end_ms: i64) -> OpenOptions {
+        self.clip = Some((start_ms, end_ms));
+        self
+    }
+
+    /// 报告打开进度，依次收到 Probing、ReadingMetadata、CreatingDecoder、Prebuffering，
+    /// 开始播放并且输出缓冲区中有了数据之后收到 Ready。打开失败时停在失败的阶段
+    ///
+    /// 打开压缩包或者播放列表时只报告实际打开的那个文件
+    pub fn progress(mut self, progress: OpenProgress) -> OpenOptions {
+        self.progress = Some(progress);
+        self
+    }
+}
+
+impl std::fmt::Debug for OpenOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenOptions")
+            .field("resume", &self.resume)
+            .field("clip", &self.clip)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 打开播放源的方法，在创建播放源之后调用
+type SourceOpener = Box<dyn FnOnce(&mut FFmpegSource, &DeviceSampleFormat) -> Result<(), source::Error>>;
+
+/// 按播放队列预先打开的下一首，处于暂停状态，当前曲目播放完毕后接着播放
+struct PreparedSource {
+    path: PathBuf,
+    source: FFmpegSource,
+    replay_gain: EffectiveGain,
+}
+
+/// 延迟打开的文件：只读取了媒体信息，第一次播放时再打开设备和播放源
+struct PendingOpen {
+    path: PathBuf,
+    options: OpenOptions,
+    media_info: Arc<MediaInfo>,
+    /// 第一次播放时开始的位置（毫秒）
+    start_ms: i64,
+}
+
+/// 重置播放器时保留的设置
+///
+/// 默认保留音量、静音、音量曲线和设备选择，音频设备本身总是保持打开
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetOptions {
+    keep_volume: bool,
+    keep_device_selection: bool,
+}
+
+impl ResetOptions {
+    pub fn new() -> ResetOptions {
+        ResetOptions { keep_volume: true, keep_device_selection: true }
+    }
+
+    /// 是否保留音量、静音和音量曲线
+    pub fn keep_volume(mut self, keep_volume: bool) -> ResetOptions {
+        self.keep_volume = keep_volume;
+        self
+    }
+
+    /// 是否保留优先使用的音频主机和输出设备，不保留时从下一次重新打开设备开始使用默认设备
+    pub fn keep_device_selection(mut self, keep_device_selection: bool) -> ResetOptions {
+        self.keep_device_selection = keep_device_selection;
+        self
+    }
+}
+
+impl Default for ResetOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 用户提供的路径改写函数
+///
+/// 在打开文件之前调用，返回交给 FFmpeg 打开的路径或者 URL，例如把自定义的协议映射到实际的地址，
+/// 或者给网络地址加上 FFmpeg 的协议选项。书签、播放队列和事件仍然使用改写之前的路径
+pub type PathRewriter = Box<dyn Fn(&str) -> String + Send>;
+
+/// 打开文件时记录的修改时间和大小，用于检查文件是否在播放中被修改或者替换
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    /// 压缩包中的文件检查压缩包本身，网络地址等无法读取的路径返回 None
+    fn read(path: &Path) -> Option<FileStamp> {
+        let path = archive::split_entry_path(path)
+            .map(|(archive_path, _)| archive_path)
+            .unwrap_or_else(|| path.to_path_buf());
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(FileStamp { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+pub trait Player {
+    fn init_device_defalut(&mut self) -> Result<(), Error>;
+    fn open<P: AsRef<Path>>(&mut self, filepath: &P) -> Result<(), Error>;
+    fn open_with_options<P: AsRef<Path>>(&mut self, filepath: &P, options: &OpenOptions) -> Result<(), Error>;
+    fn open_reader(&mut self, reader: Box<dyn Read + Send>) -> Result<(), Error>;
+    fn audition<P: AsRef<Path>>(&mut self, filepath: &P, start_fraction: f64, duration_ms: i64) -> Result<(), Error>;
+    fn set_open_mode(&mut self, mode: OpenMode);
+    fn get_open_mode(&self) -> OpenMode;
+    fn close(&mut self) -> Result<(), Error>;
+    fn reset(&mut self, options: ResetOptions) -> Result<(), Error>;
+    fn play(&mut self) -> Result<(), Error>;
+    fn stop(&self) -> Result<(), Error>;
+    fn pause(&self) -> Result<(), Error>;
+    fn seek(&self, time: i64) -> Result<(), Error>;
+    fn wait_for_end(&self, timeout: Option<Duration>) -> bool;
+    fn end_signal(&self) -> Option<Arc<EndSignal>>;
+
+    fn get_bitrate(&self) -> u32;
+    fn get_duration(&self) -> i64;
+    fn get_current_time(&self) -> i64;
+    fn get_current_time_us(&self) -> i64;
+    fn try_get_bitrate(&self) -> Result<u32, Error>;
+    fn try_get_duration(&self) -> Result<i64, Error>;
+    fn try_get_current_time(&self) -> Result<i64, Error>;
+    fn try_get_replay_gain_peak(&self) -> Result<f32, Error>;
+    fn is_playing(&self) -> bool;
+    fn get_volume(&self) -> f32;
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), Error>;
+    fn set_volume_curve(&mut self, curve: VolumeCurve) -> Result<(), Error>;
+    fn get_volume_curve(&self) -> VolumeCurve;
+    fn set_mute(&self, mute: bool) -> Result<(), Error>;
+
+    fn get_media_info(&self) -> Option<Arc<MediaInfo>>;
+    fn get_codec_extradata(&self) -> Option<Vec<u8>>;
+    fn is_lossless(&self) -> Option<bool>;
+    fn get_all_metadata(&self) -> Option<RawTags>;
+    fn add_metadata_extractor(&mut self, extractor: Arc<dyn MetadataExtractor>);
+    fn clear_metadata_extractors(&mut self);
+    fn is_seekable(&self) -> bool;
+    fn get_current_bitrate(&self) -> i64;
+    fn get_status(&self) -> PlayerStatus;
+    fn is_source_lost(&self) -> bool;
+    fn is_source_changed(&self) -> bool;
+    fn reopen_current(&mut self) -> Result<(), Error>;
+    fn recover_output(&mut self) -> Result<bool, Error>;
+    fn set_end_behavior(&mut self, behavior: Option<EndBehavior>);
+    fn get_end_behavior(&self) -> EndBehavior;
+    fn set_lazy_device(&mut self, lazy: bool);
+    fn is_lazy_device(&self) -> bool;
+    fn set_lazy_cover(&mut self, lazy: bool);
+    fn is_lazy_cover(&self) -> bool;
+    fn set_seamless_loop(&mut self, enabled: bool);
+    fn is_seamless_loop(&self) -> bool;
+    fn set_finished_action(&mut self, action: FinishedAction);
+    fn get_finished_action(&self) -> FinishedAction;
+    fn set_seek_debounce_ms(&mut self, debounce_ms: u32);
+    fn get_seek_debounce_ms(&self) -> u32;
+    fn set_rebuffer_threshold_ms(&mut self, threshold_ms: u32) -> Result<(), Error>;
+    fn get_rebuffer_threshold_ms(&self) -> u32;
+    fn set_target_buffer_ms(&mut self, target_ms: u32) -> Result<(), Error>;
+    fn get_target_buffer_ms(&self) -> u32;
+    fn set_prebuffer_ms(&mut self, prebuffer_ms: Option<u32>) -> Result<(), Error>;
+    fn get_prebuffer_ms(&self) -> Option<u32>;
+    fn is_buffering(&self) -> bool;
+    fn set_error_resilience(&mut self, resilience: ErrorResilience);
+    fn get_error_resilience(&self) -> ErrorResilience;
+    fn set_ab_loop(&mut self, a_ms: i64, b_ms: i64) -> Result<(), Error>;
+    fn clear_ab_loop(&mut self);
+    fn get_ab_loop(&self) -> Option<(i64, i64)>;
+
+    fn set_preferred_host(&mut self, host: HostId) -> Result<(), Error>;
+    fn get_available_hosts(&self) -> Vec<HostId>;
+    fn get_output_host(&self) -> Option<HostId>;
+    fn get_output_device_name(&self) -> Option<String>;
+    fn set_output_device(&mut self, name: Option<String>) -> Result<(), Error>;
+    fn get_output_device_names(&self) -> Vec<String>;
+    fn set_output_channel_map(&mut self, channel_map: Vec<usize>) -> Result<(), Error>;
+    fn get_output_channel_map(&self) -> Vec<usize>;
+    fn add_output(&mut self, device_name: &str) -> Result<OutputId, Error>;
+    fn remove_output(&mut self, id: OutputId) -> Result<(), Error>;
+    fn set_output_volume(&mut self, id: OutputId, volume: f32) -> Result<(), Error>;
+    fn set_output_mute(&mut self, id: OutputId, mute: bool) -> Result<(), Error>;
+    fn get_outputs(&self) -> Vec<(OutputId, String)>;
+    fn open_deck<P: AsRef<Path>>(&mut self, deck: DeckId, path: &P) -> Result<(), Error>;
+    fn play_deck(&mut self, deck: DeckId) -> Result<(), Error>;
+    fn pause_deck(&self, deck: DeckId) -> Result<(), Error>;
+    fn close_deck(&mut self, deck: DeckId) -> Result<(), Error>;
+    fn set_deck_volume(&mut self, deck: DeckId, volume: f32) -> Result<(), Error>;
+
+    fn get_queue(&self) -> &PlayQueue;
+    fn get_queue_mut(&mut self) -> &mut PlayQueue;
+    fn play_queue_entry(&mut self, index: usize) -> Result<(), Error>;
+    fn save_playlist<P: AsRef<Path>>(&self, path: &P) -> Result<(), Error>;
+    fn play_next(&mut self) -> Result<bool, Error>;
+    fn play_previous(&mut self) -> Result<bool, Error>;
+    fn scan_directory<P: AsRef<Path>>(&self, path: &P, recursive: bool) -> DirectoryScan;
+    fn open_directory<P: AsRef<Path>>(&mut self, path: &P, recursive: bool) -> Result<ScanSummary, Error>;
+    fn probe_batch(&self, paths: &[PathBuf]) -> Vec<Result<MediaInfo, Error>>;
+    fn export_range<P: AsRef<Path>>(
+        &self,
+        input: &P,
+        start_ms: i64,
+        end_ms: i64,
+        output: PathBuf,
+        format: ExportFormat,
+        progress: Option<ExportProgress>,
+    ) -> Result<ExportJob, Error>;
+    fn set_export_sample_format(&mut self, sample_format: ExportSampleFormat) -> Result<(), Error>;
+    fn get_export_sample_format(&self) -> ExportSampleFormat;
+    fn open_decoder<P: AsRef<Path>>(&self, path: &P, sample_rate: u32, channels: u16) -> Result<DecodedStream, Error>;
+
+    fn set_dsp_processor(&mut self, processor: Option<DspProcessor>);
+    fn set_path_rewriter(&mut self, rewriter: Option<PathRewriter>);
+    fn set_input_options(&mut self, options: HashMap<String, String>);
+    fn get_input_options(&self) -> &HashMap<String, String>;
+
+    fn set_analysis_enabled(&mut self, enabled: bool);
+    fn is_analysis_enabled(&self) -> bool;
+    fn set_analysis_update_rate_hz(&mut self, rate_hz: f32) -> Result<(), Error>;
+    fn get_analysis_update_rate_hz(&self) -> f32;
+    fn get_peak_levels(&self) -> (f32, f32);
+    fn get_spectrum(&self, bands: usize) -> Vec<f32>;
+
+    fn start_capture(&mut self, path: PathBuf, format: CaptureFormat) -> Result<(), Error>;
+    fn stop_capture(&mut self);
+    fn is_capturing(&self) -> bool;
+    fn set_capture_split_on_track(&mut self, split_on_track: bool);
+    fn set_capture_pre_volume(&mut self, pre_volume: bool);
+    fn get_capture_dropped_frames(&self) -> u64;
+
+    fn set_decode_chunk_size(&mut self, size: usize) -> Result<(), Error>;
+    fn get_decode_chunk_size(&self) -> usize;
+    fn get_dynamic_buffer_size(&self) -> usize;
+    fn get_output_latency_ms(&self) -> f64;
+    fn get_buffer_latency_ms(&self) -> f64;
+
+    fn set_speed(&mut self, speed: f32) -> Result<(), Error>;
+    fn get_speed(&self) -> f32;
+
+    fn get_active_processing(&self) -> Vec<&'static str>;
+
+    fn set_eq_enabled(&mut self, enabled: bool);
+    fn is_eq_enabled(&self) -> bool;
+    fn set_eq_band_gain(&mut self, index: usize, gain_db: f32) -> Result<(), Error>;
+    fn get_eq_band_gains(&self) -> Vec<f32>;
+    fn get_eq_band_frequencies(&self) -> Vec<f32>;
+    fn set_graphic_eq(&mut self, num_bands: usize, gains_db: &[f32]) -> Result<(), Error>;
+    fn set_eq_auto_gain(&mut self, auto_gain: bool);
+    fn is_eq_auto_gain(&self) -> bool;
+    fn get_eq_compensation(&self) -> f32;
+
+    fn set_channel_delays(&mut self, delays_ms: &[u32]) -> Result<(), Error>;
+    fn get_channel_delays(&self) -> Vec<u32>;
+
+    fn set_master_limiter(&mut self, enabled: bool, ceiling_db: f32) -> Result<(), Error>;
+    fn get_master_limiter(&self) -> (bool, f32);
+
+    fn set_processing_order(&mut self, stages: &[DspStage]) -> Result<(), Error>;
+    fn get_processing_order(&self) -> Vec<DspStage>;
+
+    fn load_impulse_response<P: AsRef<Path>>(&mut self, path: &P) -> Result<(), Error>;
+    fn clear_impulse_response(&mut self);
+    fn get_impulse_response_path(&self) -> Option<PathBuf>;
+    fn set_convolution(&mut self, enabled: bool);
+    fn is_convolution_enabled(&self) -> bool;
+
+    fn set_replay_gain_mode(&mut self, mode: ReplayGainMode);
+    fn get_replay_gain_mode(&self) -> ReplayGainMode;
+    fn set_replay_gain_preamp(&mut self, preamp_db: f32) -> Result<(), Error>;
+    fn get_replay_gain_preamp(&self) -> f32;
+    fn set_replay_gain_fallback(&mut self, fallback_db: f32) -> Result<(), Error>;
+    fn get_replay_gain_fallback(&self) -> f32;
+    fn set_replay_gain_prevent_clipping(&mut self, enabled: bool);
+    fn is_replay_gain_prevent_clipping(&self) -> bool;
+    fn get_replay_gain_peak(&self) -> Option<f32>;
+    fn get_applied_replay_gain(&self) -> Option<EffectiveGain>;
+    fn analyze_queue_loudness(&mut self) -> Result<(), Error>;
+    fn cancel_loudness_analysis(&mut self);
+    fn set_loudness_cache_path(&mut self, path: PathBuf) -> Result<(), Error>;
+    fn get_loudness_cache_path(&self) -> PathBuf;
+
+    fn set_sleep_timer(&mut self, timer: SleepTimer);
+    fn get_sleep_timer(&self) -> SleepTimer;
+    fn get_sleep_timer_remaining(&self) -> Option<Duration>;
+    fn set_sleep_fade_duration(&mut self, duration: Duration);
+
+    fn get_resume_info(&self) -> Option<(PathBuf, i64)>;
+    fn set_bookmarks_enabled(&mut self, enabled: bool);
+    fn is_bookmarks_enabled(&self) -> bool;
+    fn set_bookmarks_path(&mut self, path: PathBuf) -> Result<(), Error>;
+    fn get_bookmarks_path(&self) -> PathBuf;
+    fn add_cue_point(&mut self, name: &str) -> Result<CueId, Error>;
+    fn list_cue_points(&self) -> Vec<CuePoint>;
+    fn jump_to_cue(&self, id: CueId) -> Result<(), Error>;
+    fn remove_cue_point(&mut self, id: CueId) -> bool;
+
+    fn subscribe_events(&self) -> Receiver<PlayerEvent>;
+    fn get_playback_stats(&self) -> PlaybackStats;
+    fn get_debug_stats(&self) -> DebugStats;
+    fn set_scrobble_threshold(&mut self, threshold: ScrobbleThreshold) -> Result<(), Error>;
+    fn get_scrobble_threshold(&self) -> ScrobbleThreshold;
+    fn get_clip_count(&self) -> u64;
+    fn set_clipping_events(&mut self, enabled: bool);
+}
+
+pub struct YakoPlayer {
+    device: Option<AudioDevice>,
+    source: Option<Box<dyn AudioSource>>,
+    volume: f32,
+    /// 静音不改变音量，可以在共享引用上设置
+    mute: AtomicBool,
+    /// 优先使用的音频主机
+    preferred_host: Option<HostId>,
+    /// 优先使用的输出设备名称
+    preferred_device: Option<String>,
+    /// 声道映射，空数组时使用设备的标准声道布局
+    output_channel_map: Vec<usize>,
+    /// 播放队列
+    queue: PlayQueue,
+    /// 设备尚未创建时设置的音频处理器
+    pending_dsp_processor: Option<DspProcessor>,
+    /// 是否开启输出音频分析
+    analysis_enabled: bool,
+    /// 峰值和频谱每秒最多重新计算的次数
+    analysis_update_rate_hz: f32,
+    /// 用户指定的解码写入块大小（采样数），未指定时使用动态缓冲区大小的一半
+    decode_chunk_size: Option<usize>,
+    /// 正在播放时打开新文件的方式
+    open_mode: OpenMode,
+    /// 交叉淡化中正在淡出的播放源
+    fading_source: Option<Box<dyn AudioSource>>,
+    /// 第二个 deck 的播放源，使用备用的输出缓冲区
+    deck_source: Option<Box<dyn AudioSource>>,
+    /// 每个 deck 的音量
+    deck_volumes: [f32; 2],
+    /// 播放速度
+    speed: f32,
+    /// 定时停止
+    sleep_timer: SleepTimerMonitor,
+    /// 均衡器设置，创建设备时复制到设备上
+    equalizer: Equalizer,
+    /// 每个声道的延迟（毫秒），创建设备时复制到设备上
+    channel_delays: Vec<u32>,
+    /// 是否开启总输出的限幅器和限幅的上限（dBFS），创建设备时复制到设备上
+    master_limiter: (bool, f32),
+    /// 音量之前各个处理步骤的顺序，创建设备时复制到设备上
+    processing_order: ProcessingOrder,
+    /// 卷积使用的脉冲响应文件和按设备采样率准备好的脉冲响应，设备的采样率改变时重新准备
+    impulse_response: Option<(PathBuf, Arc<ImpulseResponse>)>,
+    /// 是否开启卷积，创建设备时复制到设备上
+    convolution_enabled: bool,
+    /// 播放位置书签
+    bookmarks: BookmarkRecorder,
+    /// 当前文件恢复到的书签位置
+    resume_info: Option<(PathBuf, i64)>,
+    /// 事件订阅
+    events: EventBus,
+    /// 播放时长统计
+    playback_tracker: PlaybackTracker,
+    /// 应用 ReplayGain 的设置
+    replay_gain: ReplayGainSettings,
+    /// 当前曲目实际应用的 ReplayGain 增益，变化时发出事件
+    applied_replay_gain: Option<EffectiveGain>,
+    /// 判断专辑边界时读取的队列中文件的专辑标签
+    album_tags: HashMap<PathBuf, Option<String>>,
+    /// 没有增益标签的文件测量得到的响度
+    loudness_cache: Arc<Mutex<LoudnessCache>>,
+    /// 正在进行的播放队列响度分析
+    loudness_analysis: Option<LoudnessAnalysis>,
+    /// 用户提供的元数据读取器
+    metadata_extractors: Vec<Arc<dyn MetadataExtractor>>,
+    /// 当前播放的文件路径，从读取器打开时为 None
+    current_path: Option<PathBuf>,
+    /// 打开当前文件时的修改时间和大小
+    current_stamp: Option<FileStamp>,
+    /// 标记点和它们所属的文件
+    cue_points: CueList,
+    cue_points_path: Option<PathBuf>,
+    /// 用户指定的播放结束行为，None 时根据播放完毕后的操作、循环模式和播放队列选择
+    end_behavior: Option<EndBehavior>,
+    /// 曲目播放完毕后播放器自己执行的操作
+    finished_action: FinishedAction,
+    /// 按播放队列预先打开的下一首
+    next_source: Option<PreparedSource>,
+    /// 是否无缝循环当前文件
+    seamless_loop: bool,
+    /// 当前曲目是否为试听，试听结束后停止，不播放队列中的下一首
+    auditioning: bool,
+    /// 定位防抖时间（毫秒）
+    seek_debounce_ms: u32,
+    /// 遇到无法解码的数据包时的处理方式
+    error_resilience: ErrorResilience,
+    /// 正在进行的输出录制
+    capture: Option<CaptureSession>,
+    /// 录制时切换曲目是否写入新的文件
+    capture_split_on_track: bool,
+    /// 是否录制音量调整之前的数据
+    capture_pre_volume: bool,
+    /// 用户提供的路径改写函数
+    path_rewriter: Option<PathRewriter>,
+    /// 打开文件时传给 FFmpeg 的协议和解封装器选项
+    input_options: HashMap<String, String>,
+    /// 解码跟不上播放时暂停输出、重新积累的时长（毫秒），0 表示不暂停
+    rebuffer_threshold_ms: u32,
+    /// 动态缓冲区的时长（毫秒），决定解码线程领先设备的最大时长
+    target_buffer_ms: u32,
+    /// 开始播放和定位之后预先缓冲的时长（毫秒），None 表示缓冲到动态缓冲区写满为止
+    prebuffer_ms: Option<u32>,
+    /// 导出文件的采样率和声道数
+    export_sample_format: ExportSampleFormat,
+    /// 音量等级到分贝的映射
+    volume_curve: VolumeCurve,
+    /// 高精度播放位置在两次输出回调之间的推算
+    position_smoother: Mutex<PositionSmoother>,
+    /// 还没有打开设备时，打开文件只读取媒体信息，第一次播放时再打开设备
+    lazy_device: bool,
+    /// 等待第一次播放的文件
+    pending_open: Mutex<Option<PendingOpen>>,
+    /// 打开文件时不读取内嵌的封面，由解码线程在打开之后读取
+    lazy_cover: bool,
+}
+
+impl YakoPlayer {
+    pub fn new() -> YakoPlayer {
+        let events = EventBus::new();
+        YakoPlayer {
+            device: None,
+            source: None,
+            volume: 1.,
+            mute: AtomicBool::new(false),
+            preferred_host: None,
+            preferred_device: None,
+            output_channel_map: Vec::new(),
+            queue: PlayQueue::new(),
+            pending_dsp_processor: None,
+            analysis_enabled: false,
+            analysis_update_rate_hz: analysis::DEFAULT_UPDATE_RATE_HZ,
+            decode_chunk_size: None,
+            open_mode: OpenMode::default(),
+            fading_source: None,
+            deck_source: None,
+            deck_volumes: [1.; 2],
+            speed: 1.,
+            sleep_timer: SleepTimerMonitor::new(),
+            equalizer: Equalizer::new(),
+            channel_delays: Vec::new(),
+            master_limiter: (false, limiter::DEFAULT_CEILING_DB),
+            processing_order: dsp::DEFAULT_PROCESSING_ORDER,
+            impulse_response: None,
+            convolution_enabled: false,
+            bookmarks: BookmarkRecorder::new(),
+            resume_info: None,
+            playback_tracker: PlaybackTracker::new(events.clone()),
+            events,
+            replay_gain: ReplayGainSettings::default(),
+            applied_replay_gain: None,
+            album_tags: HashMap::new(),
+            loudness_cache: Arc::new(Mutex::new(LoudnessCache::new())),
+            loudness_analysis: None,
+            metadata_extractors: Vec::new(),
+            current_path: None,
+            current_stamp: None,
+            cue_points: CueList::new(),
+            cue_points_path: None,
+            end_behavior: None,
+            finished_action: FinishedAction::default(),
+            next_source: None,
+            seamless_loop: false,
+            auditioning: false,
+            seek_debounce_ms: source::DEFAULT_SEEK_DEBOUNCE_MS,
+            error_resilience: ErrorResilience::default(),
+            capture: None,
+            capture_split_on_track: false,
+            capture_pre_volume: false,
+            path_rewriter: None,
+            input_options: HashMap::new(),
+            rebuffer_threshold_ms: 0,
+            target_buffer_ms: DEFAULT_TARGET_BUFFER_MS,
+            prebuffer_ms: None,
+            export_sample_format: ExportSampleFormat::default(),
+            volume_curve: VolumeCurve::default(),
+            position_smoother: Mutex::new(PositionSmoother::new()),
+            lazy_device: true,
+            lazy_cover: false,
+            pending_open: Mutex::new(None),
+        }
+    }
+
+    /// 用用户提供的改写函数得到实际打开的路径
+    fn rewrite_path(&self, path: &Path) -> PathBuf {
+        match self.path_rewriter.as_ref() {
+            Some(rewriter) => PathBuf::from(rewriter(&path.to_string_lossy())),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// 实际听到的位置：解码位置减去输出缓冲区中还没有播放的数据，不可定位的播放源返回 None
+    fn heard_position(&self) -> Option<i64> {
+        let source = self.active_source().filter(|source| source.is_seekable())?;
+        // 变速时缓冲区中的一帧对应文件中 speed 帧
+        let buffered_ms = self.get_buffer_latency_ms() * self.speed as f64;
+        Some((source.get_current_time() - buffered_ms as i64).max(0))
+    }
+
+    /// 当前播放源，没有打开文件时返回错误
+    fn current_source(&self) -> Result<&dyn AudioSource, Error> {
+        self.active_source().context(UnavailableSnafu {
+            message: "no media file is open".to_string(),
+        })
+    }
+
+    /// 读取延迟打开的文件的状态，没有延迟打开的文件时返回 None
+    fn pending<R>(&self, read: impl FnOnce(&PendingOpen) -> R) -> Option<R> {
+        self.pending_open.lock().unwrap().as_ref().map(read)
+    }
+
+    /// 打开了其他文件时换成这个文件保存的标记点，重新打开同一个文件时保留现有的标记点
+    fn load_cue_points(&mut self) {
+        let path = match self.current_path.as_ref() {
+            Some(path) if self.cue_points_path.as_ref() != Some(path) => path.clone(),
+            _ => return,
+        };
+        self.cue_points = CueList::from_points(self.bookmarks.get_cue_points(&path));
+        self.cue_points_path = Some(path);
+    }
+
+    /// 只读取媒体信息，不打开设备和解码线程，第一次播放时再按 path 和 options 重新打开
+    fn defer_open(&mut self, path: &Path, uri: &Path, options: &OpenOptions) -> Result<(), Error> {
+        let input_ctx = match archive::split_entry_path(uri) {
+            Some((archive_path, name)) => {
+                let reader = ZipArchive::open(&archive_path)
+                    .and_then(|archive| archive.open_entry(&name))
+                    .context(ArchiveSnafu)?;
+                source::open_seekable_media(reader).context(SourceSnafu)?
+            },
+            None => source::open_media_file(&uri, &self.input_options).context(SourceSnafu)?,
+        };
+        let mut media_info = source::probe_input(&input_ctx, Some(path), &self.metadata_extractors).context(SourceSnafu)?;
+        drop(input_ctx);
+        // 片段的总长度是片段本身的长度
+        if let Some((start_ms, end_ms)) = options.clip {
+            let end_ms = if media_info.duration >= 0 { end_ms.min(media_info.duration) } else { end_ms };
+            media_info.duration = (end_ms - start_ms).max(0);
+        }
+
+        self.close()?;
+        media_info.cover_generation = media::next_cover_generation();
+        self.events.emit(PlayerEvent::TrackChanged(TechnicalInfo::from(&media_info)));
+        self.emit_artwork_changed(Some(&media_info));
+        self.current_path = Some(path.to_path_buf());
+        self.current_stamp = FileStamp::read(path);
+        self.load_cue_points();
+        *self.pending_open.lock().unwrap() = Some(PendingOpen {
+            path: path.to_path_buf(),
+            options: options.clone(),
+            media_info: Arc::new(media_info),
+            start_ms: 0,
+        });
+        Ok(())
+    }
+
+    /// 设备打开之后真正打开延迟打开的文件，播放之前定位的位置优先于书签
+    fn open_pending(&mut self, pending: PendingOpen) -> Result<(), Error> {
+        self.open_path(&pending.path, &pending.options)?;
+        if let Some(source) = self.source.as_ref().filter(|source| pending.start_ms > 0 && source.is_seekable()) {
+            source.seek(pending.start_ms).context(SourceSnafu)?;
+        }
+        Ok(())
+    }
+
+    /// 使用 open_source 打开新的播放源
+    ///
+    /// 指定了 crossfade 并且正在播放时，当前播放源淡出、新的播放源淡入并立即开始播放，否则关闭当前播放源。
+    /// path 为 None 时（例如读取器）不记录书签
+    fn open_with<F>(&mut self, crossfade: Option<Duration>, path: Option<&Path>, open_source: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut FFmpegSource, &DeviceSampleFormat) -> Result<(), source::Error>,
+    {
+        if self.device.is_none() || !self.device.as_ref().unwrap().is_available() {
+            self.init_device_defalut().unwrap();
+        }
+
+        // TODO: 检测文件类型
+
+        // 已经接着播放的下一首才是被替换的曲目，还没有开始的下一首不再需要
+        self.follow_handover();
+        self.discard_next_source();
+        // 上一次交叉淡化的播放源占用着备用的输出缓冲区，先关闭它
+        self.close_fading_source();
+        self.auditioning = false;
+        // 第二个 deck 占用着备用的输出缓冲区，打开期间不交叉淡化
+        let crossfade = crossfade.filter(|_| self.is_playing() && self.deck_source.is_none());
+
+        if let Some(device) = self.device.as_mut() {
+            match crossfade {
+                Some(duration) => {
+                    device.begin_crossfade(duration);
+                    self.fading_source = self.source.take();
+                },
+                None => {
+                    if let Some(source) = self.source.take() {
+                        YakoPlayer::close_replaced_source(source);
+                    }
+                    device.finish_crossfade();
+                },
+            }
+            device.set_slot_volume(device.active_slot(), self.volume_curve.to_db(self.deck_volumes[MAIN_DECK as usize]));
+        }
+
+        let (source, replay_gain) = self.create_source(path, open_source)?;
+        if crossfade.is_some() {
+            source.streaming().context(SourceSnafu)?;
+        }
+        self.install_source(source, path, replay_gain, false);
+        Ok(())
+    }
+
+    /// 在当前的输出缓冲区上创建并打开播放源，应用播放器的设置和 ReplayGain 增益，打开之后处于暂停状态
+    fn create_source<F>(&mut self, path: Option<&Path>, open_source: F) -> Result<(FFmpegSource, EffectiveGain), Error>
+    where
+        F: FnOnce(&mut FFmpegSource, &DeviceSampleFormat) -> Result<(), source::Error>,
+    {
+        let sample_format = self.device.as_ref().and_then(|device| device.sample_format).context(UnavailableSnafu {
+            message: "the audio device is not open".to_string(),
+        })?;
+        let dynamic_device_buffer_size = YakoPlayer::device_buffer_size(&sample_format, self.target_buffer_ms, self.hold_ms());
+
+        let neighbour_albums = self.neighbour_albums(path);
+        let measured_loudness = self.measured_loudness(path);
+
+        let device = self.device.as_ref().unwrap();
+        // TODO: 重新打开设备后缓冲区实现
+        let mut source = FFmpegSource::new(
+            device.get_output_buffer_producer(),
+            device.get_output_buffer_consumer(),
+            dynamic_device_buffer_size);
+        YakoPlayer::apply_buffer_sizes(&mut source, dynamic_device_buffer_size, self.decode_chunk_size);
+        source.set_speed(self.speed);
+        source.set_seamless_loop(self.loops_track());
+        source.set_lazy_cover(self.lazy_cover);
+        source.set_seek_debounce_ms(self.seek_debounce_ms);
+        source.set_error_resilience(self.error_resilience);
+        source.set_metadata_extractors(self.metadata_extractors.clone());
+        source.set_event_bus(self.events.clone());
+        source.set_prebuffer_frames(self.prebuffer_ms.map(|ms| YakoPlayer::hold_frames(sample_format.sample_rate, ms)));
+        open_source(&mut source, &sample_format).context(SourceSnafu)?;
+        let media_info = source.get_media_info();
+        let use_album = YakoPlayer::continues_album(neighbour_albums.as_deref(), &media_info);
+        let replay_gain_tags = loudness::fill_replay_gain(&media_info.replay_gain, measured_loudness);
+        let replay_gain = self.replay_gain.effective_gain(&replay_gain_tags, use_album);
+        source.set_gain(replay_gain.amplitude);
+        Ok((source, replay_gain))
+    }
+
+    /// 把打开的播放源设为当前曲目
+    ///
+    /// handed_over 表示它是已经接着上一首播放的下一首，播放统计和 TrackChanged 等事件已经在接替时处理
+    fn install_source(&mut self, source: FFmpegSource, path: Option<&Path>, replay_gain: EffectiveGain, handed_over: bool) {
+        self.sleep_timer.set_source(source.status.clone(), source.get_duration());
+        self.bookmarks.set_current(path.map(|path| (path, source.status.clone())));
+        if let Some(device) = self.device.as_ref() {
+            if !handed_over {
+                self.playback_tracker.set_source(
+                    source.status.clone(),
+                    source.get_duration(),
+                    device.played_frame_counter(),
+                    device.clip_counter(),
+                    path.map(Path::to_path_buf));
+            }
+            device.set_input_status(Some(source.status.clone()));
+        }
+        if !handed_over {
+            self.events.emit(PlayerEvent::TrackChanged(TechnicalInfo::from(source.get_media_info().as_ref())));
+            self.emit_artwork_changed(Some(source.get_media_info().as_ref()));
+        }
+        self.current_path = path.map(Path::to_path_buf);
+        self.current_stamp = path.and_then(FileStamp::read);
+        self.load_cue_points();
+        self.source = Some(Box::new(source));
+        self.report_replay_gain(replay_gain);
+        self.update_end_behavior();
+        self.prepare_next_source();
+    }
+
+    /// 是否无缝循环当前曲目：单独开启了无缝循环，或者播放完毕后的操作为 LoopTrack
+    fn loops_track(&self) -> bool {
+        self.seamless_loop || self.finished_action == FinishedAction::LoopTrack
+    }
+
+    /// 播放完毕后按播放队列播放下一首时，预先打开下一首并交给当前播放源
+    ///
+    /// 当前曲目播放完毕后解码线程让下一首紧接着写入同一个输出缓冲区，不需要宿主程序处理事件，
+    /// 播放器在下一次被修改时通过 follow_handover 把下一首设为当前曲目。队列或者设置改变后重新选择
+    fn prepare_next_source(&mut self) {
+        self.follow_handover();
+        let next_path = match self.source.as_ref() {
+            Some(source) if self.finished_action == FinishedAction::AdvanceQueue
+                && !self.seamless_loop
+                && source.get_end_behavior() == EndBehavior::Handover
+                && !source.is_end() => self.queue.neighbours().1.map(Path::to_path_buf),
+            _ => None,
+        };
+        if next_path.is_some() && self.next_source.as_ref().map(|next| &next.path) == next_path.as_ref() {
+            return;
+        }
+        self.discard_next_source();
+        // 下一首恰好在取消之前接替了当前曲目，切换时已经按新的当前曲目准备好了下一首
+        if self.next_source.is_some() {
+            return;
+        }
+        let path = match next_path {
+            Some(path) => path,
+            None => return,
+        };
+        let open_source = match self.source_opener(&path, TimeWindow::default(), None) {
+            Ok(open_source) => open_source,
+            Err(err) => {
+                log::warn!("failed to prepare the next track {}: {}", path.display(), err);
+                return;
+            },
+        };
+        let (source, replay_gain) = match self.create_source(Some(&path), open_source) {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                log::warn!("failed to prepare the next track {}: {}", path.display(), err);
+                return;
+            },
+        };
+        if let Some(device) = self.device.as_ref() {
+            let media_info = source.get_media_info();
+            self.playback_tracker.set_successor(
+                source.status.clone(),
+                source.get_duration(),
+                device.played_frame_counter(),
+                device.clip_counter(),
+                Some(path.clone()),
+                vec![
+                    PlayerEvent::TrackChanged(TechnicalInfo::from(media_info.as_ref())),
+                    PlayerEvent::ArtworkChanged {
+                        generation: media_info.cover_generation,
+                        cover: media_info.cover.clone(),
+                    },
+                ]);
+        }
+        if let Some(current) = self.source.as_ref() {
+            current.set_successor(Some(source.status.clone()));
+        }
+        self.next_source = Some(PreparedSource { path, source, replay_gain });
+    }
+
+    /// 关闭预先打开、还没有开始播放的下一首
+    fn discard_next_source(&mut self) {
+        if let Some(source) = self.source.as_ref() {
+            source.set_successor(None);
+        }
+        // 取消之前已经接替了当前曲目的下一首照常切换
+        self.follow_handover();
+        if let Some(next) = self.next_source.take() {
+            self.playback_tracker.clear_successor();
+            // 还没有开始写入，输出缓冲区中是当前曲目的数据
+            YakoPlayer::detach_replaced_source(Box::new(next.source));
+        }
+    }
+
+    /// 当前曲目已经把输出交给了预先打开的下一首时，把下一首设为当前曲目，播放队列前进到下一首
+    fn follow_handover(&mut self) {
+        if !self.next_source.as_ref().map_or(false, |next| next.source.is_handed_over()) {
+            return;
+        }
+        let next = self.next_source.take().unwrap();
+        if self.queue.neighbours().1 == Some(next.path.as_path()) {
+            self.queue.next();
+        }
+        if let Some(source) = self.source.take() {
+            // 输出缓冲区中是下一首已经写入的数据
+            YakoPlayer::detach_replaced_source(source);
+        }
+        self.install_source(next.source, Some(&next.path), next.replay_gain, true);
+    }
+
+    /// 实际在播放的播放源：当前曲目已经把输出交给了预先打开的下一首时是下一首
+    ///
+    /// 只读取状态的方法不能修改播放器，通过这里读取接着播放的下一首，下一次修改播放器时再由 follow_handover 切换
+    fn active_source(&self) -> Option<&dyn AudioSource> {
+        match self.next_source.as_ref() {
+            Some(next) if next.source.is_handed_over() => Some(&next.source),
+            _ => self.source.as_deref(),
+        }
+    }
+
+    /// 当前播放源和预先打开的下一首，用于把设置同时应用到两者
+    fn sources(&self) -> impl Iterator<Item = &dyn AudioSource> {
+        let current: Option<&dyn AudioSource> = self.source.as_deref();
+        let next: Option<&dyn AudioSource> = self.next_source.as_ref().map(|next| &next.source as &dyn AudioSource);
+        current.into_iter().chain(next)
+    }
+
+    /// 缓冲区或者 ReplayGain 设置改变后，按新的设置重新打开预先打开的下一首
+    fn refresh_next_source(&mut self) {
+        self.discard_next_source();
+        self.prepare_next_source();
+    }
+
+    /// 按路径打开文件的方法：改写路径，压缩包中的文件通过读取器打开
+    fn source_opener(&self, path: &Path, window: TimeWindow, progress: Option<OpenProgress>) -> Result<SourceOpener, Error> {
+        let uri = self.rewrite_path(path);
+        let open_source: SourceOpener = match archive::split_entry_path(&uri) {
+            Some((archive_path, name)) => {
+                let reader = ZipArchive::open(&archive_path)
+                    .and_then(|archive| archive.open_entry(&name))
+                    .context(ArchiveSnafu)?;
+                Box::new(move |source: &mut FFmpegSource, sample_format: &DeviceSampleFormat| {
+                    source.set_window(window);
+                    source.set_open_progress(progress);
+                    source.open_seekable_reader(reader, sample_format)
+                })
+            },
+            None => {
+                let input_options = self.input_options.clone();
+                Box::new(move |source: &mut FFmpegSource, sample_format: &DeviceSampleFormat| {
+                    source.set_window(window);
+                    source.set_open_progress(progress);
+                    source.open(&uri, &input_options, sample_format)
+                })
+            },
+        };
+        Ok(open_source)
+    }
+
+    /// 根据播放完毕后的操作、循环模式和播放队列选择当前播放源结束时的行为
+    ///
+    /// 用户指定的结束行为优先。按播放队列播放下一首并且队列中还有下一首时把输出缓冲区交给下一首，
+    /// 停止、单曲循环和没有下一首时回到开头
+    fn update_end_behavior(&self) {
+        let behavior = if self.auditioning {
+            EndBehavior::RewindAndWait
+        } else {
+            self.end_behavior.unwrap_or_else(|| {
+                if self.finished_action == FinishedAction::AdvanceQueue
+                    && self.queue.repeat_mode() != RepeatMode::One
+                    && self.queue.has_next() {
+                    EndBehavior::Handover
+                } else {
+                    EndBehavior::RewindAndWait
+                }
+            })
+        };
+        if let Some(source) = self.source.as_ref() {
+            source.set_end_behavior(behavior);
+        }
+    }
+
+    /// 发出封面被替换的事件，media_info 为 None 表示关闭了曲目
+    fn emit_artwork_changed(&self, media_info: Option<&MediaInfo>) {
+        self.events.emit(PlayerEvent::ArtworkChanged {
+            generation: media_info.map_or(0, |media_info| media_info.cover_generation),
+            cover: media_info.and_then(|media_info| media_info.cover.clone()),
+        });
+    }
+
+    /// 关闭被替换的播放源，失败时只记录错误并直接丢弃，一个无法关闭的播放源不能妨碍之后打开新的文件
+    fn close_replaced_source(mut source: Box<dyn AudioSource>) {
+        if let Err(err) = source.close() {
+            log::warn!("failed to close the previous source, dropping it: {}", err);
+        }
+    }
+
+    /// 结束不再写入当前输出缓冲区的播放源，不清空缓冲区，失败时和 close_replaced_source 一样只记录错误
+    fn detach_replaced_source(mut source: Box<dyn AudioSource>) {
+        if let Err(err) = source.detach() {
+            log::warn!("failed to stop the previous source, dropping it: {}", err);
+        }
+    }
+
+    /// 关闭交叉淡化中淡出的播放源
+    fn close_fading_source(&mut self) {
+        if let Some(source) = self.fading_source.take() {
+            YakoPlayer::close_replaced_source(source);
+        }
+    }
+
+    /// 在备用的输出缓冲区上打开第二个 deck，和当前曲目同时播放
+    ///
+    /// 交叉淡化中淡出的曲目占用着备用的输出缓冲区，先关闭它。第二个 deck 不记录书签和播放统计，也不发出事件
+    fn open_second_deck(&mut self, path: &Path) -> Result<(), Error> {
+        if self.device.is_none() || !self.device.as_ref().unwrap().is_available() {
+            self.init_device_defalut()?;
+        }
+        let sample_format = self.device.as_ref().unwrap().sample_format.unwrap();
+        let dynamic_device_buffer_size = YakoPlayer::device_buffer_size(&sample_format, self.target_buffer_ms, self.hold_ms());
+        let uri = self.rewrite_path(path);
+
+        self.close_fading_source();
+        self.close_second_deck();
+        let device = self.device.as_mut().unwrap();
+        device.finish_crossfade();
+        let slot = device.open_deck_slot();
+        let mut source = FFmpegSource::new(
+            device.get_slot_producer(slot),
+            device.get_slot_consumer(slot),
+            dynamic_device_buffer_size);
+        YakoPlayer::apply_buffer_sizes(&mut source, dynamic_device_buffer_size, self.decode_chunk_size);
+        source.set_speed(self.speed);
+        source.set_error_resilience(self.error_resilience);
+        source.set_metadata_extractors(self.metadata_extractors.clone());
+        if let Err(err) = source.open(&uri, &self.input_options, &sample_format) {
+            device.close_deck_slot();
+            return Err(err).context(SourceSnafu);
+        }
+        source.set_end_behavior(EndBehavior::RewindAndWait);
+        device.set_slot_input_status(slot, Some(source.status.clone()));
+        self.deck_source = Some(Box::new(source));
+        self.apply_deck_volumes();
+        Ok(())
+    }
+
+    /// 关闭第二个 deck，备用的输出缓冲区恢复静音
+    fn close_second_deck(&mut self) {
+        if let Some(source) = self.deck_source.take() {
+            YakoPlayer::close_replaced_source(source);
+        }
+        if let Some(device) = self.device.as_mut() {
+            device.close_deck_slot();
+        }
+    }
+
+    /// 把每个 deck 的音量应用到各自的输出缓冲区
+    fn apply_deck_volumes(&self) {
+        if let Some(device) = self.device.as_ref() {
+            device.set_slot_volume(device.active_slot(), self.volume_curve.to_db(self.deck_volumes[MAIN_DECK as usize]));
+            if let Some(slot) = device.deck_slot() {
+                device.set_slot_volume(slot, self.volume_curve.to_db(self.deck_volumes[SECOND_DECK as usize]));
+            }
+        }
+    }
+
+    /// 检查 deck 编号
+    fn check_deck(deck: DeckId) -> Result<(), Error> {
+        ensure!(deck == MAIN_DECK || deck == SECOND_DECK, InvalidArgumentSnafu {
+            message: format!("unknown deck {}", deck),
+        });
+        Ok(())
+    }
+
+    /// 淡出已经结束时关闭淡出的播放源
+    fn release_finished_fade(&mut self) {
+        let crossfading = self.device.as_ref().map(|device| device.is_crossfading()).unwrap_or(false);
+        if !crossfading {
+            self.close_fading_source();
+        }
+    }
+
+    /// 把压缩包或者播放列表中的文件加入播放队列，Enqueue 模式下正在播放时只追加到队列末尾，否则替换队列并打开第一个
+    fn open_entries(&mut self, entries: Vec<PathBuf>, has_current_track: bool, options: &OpenOptions) -> Result<(), Error> {
+        if self.open_mode == OpenMode::Enqueue && has_current_track {
+            for entry in entries {
+                self.queue.push(entry);
+            }
+            return Ok(());
+        }
+        self.queue.set_entries(entries);
+        let first = self.queue.set_current(0).map(Path::to_path_buf).unwrap();
+        self.open_path(&first, options)
+    }
+
+    /// 按照打开方式中的交叉淡化时长打开文件，有书签时从记录的位置继续播放
+    fn open_path<P: AsRef<Path>>(&mut self, filepath: &P, options: &OpenOptions) -> Result<(), Error> {
+        let crossfade = match self.open_mode {
+            OpenMode::Crossfade(duration) => Some(duration),
+            _ => None,
+        };
+        self.resume_info = None;
+        let window = match options.clip {
+            Some((start_ms, end_ms)) => {
+                ensure!(0 <= start_ms && start_ms < end_ms, InvalidArgumentSnafu {
+                    message: format!("invalid clip {}..{}", start_ms, end_ms),
+                });
+                TimeWindow::new(start_ms, Some(end_ms))
+            },
+            None => TimeWindow::default(),
+        };
+        let path = filepath.as_ref();
+        if self.lazy_device && self.device.is_none() {
+            let uri = self.rewrite_path(path);
+            return self.defer_open(path, &uri, options);
+        }
+        let open_source = self.source_opener(path, window, options.progress.clone())?;
+        self.open_with(crossfade, Some(path), open_source)?;
+
+        if let Some(source) = self.source.as_ref() {
+            // TODO: 精确定位，目前会定位到记录位置之前的关键帧
+            let position = self.bookmarks.get_position(path)
+                .filter(|position| options.resume && options.clip.is_none() && bookmarks::should_resume(*position, source.get_duration()));
+            if let Some(position) = position.filter(|_| source.is_seekable()) {
+                source.seek(position).context(SourceSnafu)?;
+                self.resume_info = Some((path.to_path_buf(), position));
+            }
+        }
+        Ok(())
+    }
+
+    /// 根据设备采样率计算动态缓冲区大小（采样数），缓冲区至少能容纳两倍的 hold_ms
+    fn device_buffer_size(sample_format: &DeviceSampleFormat, target_ms: u32, hold_ms: u32) -> usize {
+        let buffer_size = YakoPlayer::hold_frames(sample_format.sample_rate, target_ms).max(1);
+        buffer_size.max(YakoPlayer::hold_frames(sample_format.sample_rate, hold_ms) * 2)
+    }
+
+    /// 重新缓冲阈值或者预先缓冲时长对应的帧数
+    fn hold_frames(sample_rate: u32, hold_ms: u32) -> usize {
+        (hold_ms as u64 * sample_rate as u64 / 1000).min(MAX_REBUFFER_FRAMES as u64) as usize
+    }
+
+    /// 解码脉冲响应文件，重采样到 sample_rate，保留文件的声道数
+    ///
+    /// sample_rate 为 None 时使用文件的采样率，超过 MAX_IMPULSE_RESPONSE_SECONDS 的部分被截掉
+    fn prepare_impulse_response(&self, path: &Path, sample_rate: Option<u32>) -> Result<ImpulseResponse, Error> {
+        let uri = self.rewrite_path(path);
+        let frames = FFmpegSource::frames_in(&uri, &self.input_options, TimeWindow::default()).context(SourceSnafu)?;
+        let channels = frames.channels().min(AudioSample::MAX_CHANNELS);
+        let sample_rate = sample_rate.unwrap_or_else(|| frames.sample_rate());
+        drop(frames);
+
+        let mut stream = DecodedStream::open(&uri, &self.input_options, &[], TimeWindow::default(), sample_rate, channels as u16)
+            .context(SourceSnafu)?;
+        let max_samples = (convolution::MAX_IMPULSE_RESPONSE_SECONDS * sample_rate) as usize * channels;
+        let mut samples = Vec::new();
+        let mut buffer = vec![0f32; 4096 * channels];
+        loop {
+            let count = stream.read_samples(&mut buffer).context(SourceSnafu)?;
+            if count == 0 {
+                break;
+            }
+            samples.extend_from_slice(&buffer[..count]);
+            if samples.len() >= max_samples {
+                log::warn!("the impulse response {} is longer than {} seconds and has been truncated",
+                    path.display(), convolution::MAX_IMPULSE_RESPONSE_SECONDS);
+                samples.truncate(max_samples);
+                break;
+            }
+        }
+        ImpulseResponse::new(&samples, channels, sample_rate).context(InvalidArgumentSnafu {
+            message: "the impulse response is empty or silent".to_string(),
+        })
+    }
+
+    /// 把卷积设置复制到设备上，脉冲响应的采样率和设备不同时先按设备的采样率重新准备
+    fn apply_convolution(&mut self) -> Result<(), Error> {
+        let sample_format = match self.device.as_ref().and_then(|device| device.sample_format) {
+            Some(sample_format) => sample_format,
+            None => return Ok(()),
+        };
+        let stale = self.impulse_response.as_ref()
+            .filter(|(_, impulse_response)| impulse_response.sample_rate() != sample_format.sample_rate)
+            .map(|(path, _)| path.clone());
+        if let Some(path) = stale {
+            let prepared = self.prepare_impulse_response(&path, Some(sample_format.sample_rate))?;
+            self.impulse_response = Some((path, Arc::new(prepared)));
+        }
+
+        // 状态的内存和脉冲响应的长度成正比，在锁外分配和释放
+        let state = self.impulse_response.as_ref()
+            .map(|(_, impulse_response)| ConvolutionState::new(impulse_response.clone(), sample_format.channel_count as usize));
+        if let Some(device) = self.device.as_ref() {
+            let previous = {
+                let mut convolver = device.get_convolver().lock().unwrap();
+                let previous = convolver.set_state(state);
+                convolver.set_enabled(self.convolution_enabled);
+                previous
+            };
+            drop(previous);
+        }
+        Ok(())
+    }
+
+    /// 动态缓冲区需要容纳的时长（毫秒），取重新缓冲阈值和预先缓冲时长中较大的一个
+    fn hold_ms(&self) -> u32 {
+        self.rebuffer_threshold_ms.max(self.prebuffer_ms.unwrap_or(0))
+    }
+
+    /// 把缓冲区时长、重新缓冲阈值和预先缓冲时长应用到设备和当前播放源
+    fn apply_hold_settings(&mut self) {
+        self.follow_handover();
+        let target_buffer_ms = self.target_buffer_ms;
+        let hold_ms = self.hold_ms();
+        if let Some(device) = self.device.as_ref() {
+            if let Some(sample_format) = device.sample_format {
+                let sample_rate = sample_format.sample_rate;
+                device.set_rebuffer_frames(YakoPlayer::hold_frames(sample_rate, self.rebuffer_threshold_ms));
+                if let Some(source) = self.source.as_mut() {
+                    let dynamic_device_buffer_size = YakoPlayer::device_buffer_size(&sample_format, target_buffer_ms, hold_ms);
+                    YakoPlayer::apply_buffer_sizes(&mut **source, dynamic_device_buffer_size, self.decode_chunk_size);
+                    source.set_prebuffer_frames(self.prebuffer_ms.map(|ms| YakoPlayer::hold_frames(sample_rate, ms)));
+                }
+            }
+        }
+        self.refresh_next_source();
+    }
+
+    /// 设置播放源的缓冲区大小，并重新应用用户指定的解码块大小
+    fn apply_buffer_sizes(source: &mut dyn AudioSource, buffer_size: usize, decode_chunk_size: Option<usize>) {
+        source.set_dynamic_device_buffer_size(buffer_size);
+        if let Some(chunk_size) = decode_chunk_size {
+            source.set_buffer_chunk_size(chunk_size.min(buffer_size).max(1));
+        }
+    }
+
+    /// 根据当前播放源的标签重新计算 ReplayGain 增益
+    fn update_replay_gain(&mut self) {
+        self.follow_handover();
+        let current_path = self.current_path.clone();
+        let neighbour_albums = self.neighbour_albums(current_path.as_deref());
+        let measured_loudness = self.measured_loudness(current_path.as_deref());
+        if let Some(source) = self.source.as_ref() {
+            let media_info = source.get_media_info();
+            let use_album = YakoPlayer::continues_album(neighbour_albums.as_deref(), &media_info);
+            let replay_gain_tags = loudness::fill_replay_gain(&media_info.replay_gain, measured_loudness);
+            let replay_gain = self.replay_gain.effective_gain(&replay_gain_tags, use_album);
+            source.set_gain(replay_gain.amplitude);
+            self.report_replay_gain(replay_gain);
+        }
+        self.refresh_next_source();
+    }
+
+    /// 记录实际应用的 ReplayGain 增益，和之前不同时发出事件
+    fn report_replay_gain(&mut self, replay_gain: EffectiveGain) {
+        if self.applied_replay_gain != Some(replay_gain) {
+            self.events.emit(PlayerEvent::ReplayGainChanged(replay_gain));
+        }
+        self.applied_replay_gain = Some(replay_gain);
+    }
+
+    /// 专辑模式下从播放队列播放 path 时，播放顺序中相邻曲目的专辑标签。
+    /// 不是专辑模式或者 path 不是队列中的当前曲目时返回 None
+    fn neighbour_albums(&mut self, path: Option<&Path>) -> Option<Vec<String>> {
+        if self.replay_gain.mode != ReplayGainMode::Album || path.is_none() || self.queue.current() != path {
+            return None;
+        }
+        let (previous, next) = self.queue.neighbours();
+        let neighbours: Vec<PathBuf> = [previous, next].iter().flatten().map(|path| path.to_path_buf()).collect();
+        if self.album_tags.len() + neighbours.len() > MAX_ALBUM_TAGS {
+            self.album_tags.clear();
+        }
+        let mut albums = Vec::new();
+        for neighbour in neighbours {
+            let uri = self.rewrite_path(&neighbour);
+            let album = self.album_tags.entry(neighbour)
+                .or_insert_with(|| probe::probe(&uri, &[]).ok().and_then(|media_info| media_info.album));
+            albums.extend(album.clone());
+        }
+        Some(albums)
+    }
+
+    /// analyze_queue_loudness 测量得到的响度，没有开启 ReplayGain 时不读取缓存
+    fn measured_loudness(&self, path: Option<&Path>) -> Option<MeasuredLoudness> {
+        if self.replay_gain.mode == ReplayGainMode::Off {
+            return None;
+        }
+        let uri = self.rewrite_path(path?);
+        self.loudness_cache.lock().unwrap().get(&uri)
+    }
+
+    /// 是否使用专辑增益：单独打开的文件总是使用，从播放队列播放时只有相邻曲目属于同一张专辑才使用
+    fn continues_album(neighbour_albums: Option<&[String]>, media_info: &MediaInfo) -> bool {
+        match (neighbour_albums, media_info.album.as_ref()) {
+            (None, _) => true,
+            (Some(albums), Some(album)) => albums.contains(album),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// 解码线程已经读到结尾，设备还在播放输出缓冲区中剩余的音频
+    fn is_draining(&self) -> bool {
+        let ended = self.active_source().map_or(false, |source| source.is_end());
+        ended && self.device.as_ref().map_or(false, |device| device.is_playing() && device.buffered_frames() > 0)
+    }
+
+    /// 修改均衡器设置，同时应用到设备上
+    ///
+    /// 按设备的采样率在锁外计算好滤波器再整体替换，拖动滑块时音频回调不需要等待
+    fn update_equalizer<F: FnOnce(&mut Equalizer)>(&mut self, update: F) {
+        update(&mut self.equalizer);
+        if let Some(device) = self.device.as_ref() {
+            let mut prepared = self.equalizer.clone();
+            if let Some(sample_format) = device.sample_format {
+                prepared.set_format(sample_format.sample_rate, sample_format.channel_count as usize);
+            }
+            let previous = device.get_equalizer().lock().unwrap().replace(prepared);
+            drop(previous);
+        }
+    }
+
+    /// 打开并播放文件
+    fn open_and_play(&mut self, path: PathBuf) -> Result<(), Error> {
+        self.open_path(&path, &OpenOptions::default())?;
+        self.play()
+    }
+}
+
+/// 动态缓冲区默认能容纳的音频时长（毫秒）
+pub const DEFAULT_TARGET_BUFFER_MS: u32 = 80;
+
+/// 动态缓冲区时长的下限（毫秒），再小时解码线程的调度延迟就会导致缓冲区耗尽
+pub const MIN_TARGET_BUFFER_MS: u32 = 5;
+
+/// 重新缓冲阈值和预先缓冲时长的上限（毫秒），高采样率的设备上还会受到输出缓冲区容量的限制
+const MAX_REBUFFER_THRESHOLD_MS: u32 = 1000;
+
+/// 播放超过这个时间（毫秒）后，“上一首”会回到当前曲目的开头
+const PREVIOUS_RESTART_THRESHOLD: i64 = 3000;
+
+/// 支持的播放速度范围
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 4.;
+
+/// ReplayGain 预增益和默认增益的范围（分贝）
+const MAX_REPLAY_GAIN_PREAMP: f32 = 15.;
+
+/// 最多缓存的专辑标签数量，超过时清空重新读取
+const MAX_ALBUM_TAGS: usize = 256;
+
+impl Default for YakoPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for YakoPlayer {
+    fn init_device_defalut(&mut self) -> Result<(), Error> {
+        self.follow_handover();
+        let preferred_host = self.preferred_host;
+        let preferred_device = self.preferred_device.clone();
+        let target_buffer_ms = self.target_buffer_ms;
+        let hold_ms = self.hold_ms();
+        let channel_map = (!self.output_channel_map.is_empty()).then(|| self.output_channel_map.clone());
+        let mut open_device = |device: &mut AudioDevice| -> Result<(), Error> {
+            let old_format = device.sample_format;
+            device.set_channel_map(channel_map.clone());
+            device.init_device(preferred_host, preferred_device.as_deref()).context(DeviceSnafu)?;
+            device.set_volume(self.volume_curve.to_db(self.volume));
+            device.set_mute(self.mute.load(Ordering::Relaxed));
+            device.get_analysis().set_enabled(self.analysis_enabled);
+            device.get_analysis().set_update_rate_hz(self.analysis_update_rate_hz);
+            device.set_rebuffer_frames(YakoPlayer::hold_frames(
+                device.sample_format.unwrap().sample_rate,
+                self.rebuffer_threshold_ms));
+            device.open().context(DeviceSnafu)?;
+
+            // 如果已经打开了播放源，重新设置动态缓冲区大小，设备的格式改变时让解码线程换成新的格式
+            if let Some(source) = self.source.as_mut() {
+                if old_format.is_some() && device.sample_format != old_format {
+                    source.set_output_format(device.sample_format.unwrap());
+                }
+                let sample_rate = device.sample_format.unwrap().sample_rate;
+                let dynamic_device_buffer_size = YakoPlayer::device_buffer_size(&device.sample_format.unwrap(), target_buffer_ms, hold_ms);
+                YakoPlayer::apply_buffer_sizes(&mut **source, dynamic_device_buffer_size, self.decode_chunk_size);
+                source.set_prebuffer_frames(self.prebuffer_ms.map(|ms| YakoPlayer::hold_frames(sample_rate, ms)));
+            }
+            if let Some(source) = self.deck_source.as_mut() {
+                if old_format.is_some() && device.sample_format != old_format {
+                    source.set_output_format(device.sample_format.unwrap());
+                }
+                let dynamic_device_buffer_size = YakoPlayer::device_buffer_size(&device.sample_format.unwrap(), target_buffer_ms, hold_ms);
+                YakoPlayer::apply_buffer_sizes(&mut **source, dynamic_device_buffer_size, self.decode_chunk_size);
+            }
+
+            Ok(())
+        };
+
+        match &mut self.device {
+            Some(device) => {
+                open_device(device)?;
+            },
+            None => {
+                let mut device = AudioDevice::new();
+                open_device(&mut device)?;
+                if let Some(processor) = self.pending_dsp_processor.take() {
+                    device.set_dsp_processor(Some(processor));
+                }
+                self.sleep_timer.set_output(device.output_control());
+                // 输出流已经开始，均衡器在锁外按设备的采样率和声道数准备好
+                let sample_format = device.sample_format.unwrap();
+                let mut equalizer = self.equalizer.clone();
+                equalizer.set_format(sample_format.sample_rate, sample_format.channel_count as usize);
+                device.get_equalizer().lock().unwrap().replace(equalizer);
+                device.get_channel_delay().lock().unwrap().set_delays(&self.channel_delays);
+                {
+                    let mut limiter = device.get_limiter().lock().unwrap();
+                    limiter.set_ceiling_db(self.master_limiter.1);
+                    limiter.set_enabled(self.master_limiter.0);
+                }
+                device.set_processing_order(self.processing_order);
+                self.device = Some(device);
+            },
+        }
+
+        // 设备的采样率或者声道数可能改变，重新准备卷积状态。脉冲响应无法使用时只关闭卷积，不影响播放
+        if let Err(err) = self.apply_convolution() {
+            log::warn!("failed to prepare the impulse response, convolution is disabled: {}", err);
+            self.impulse_response = None;
+            self.apply_convolution()?;
+        }
+
+        Ok(())
+    }
+
+    /// 按照打开方式打开文件，Enqueue 模式下没有正在播放的曲目时直接打开
+    ///
+    /// 支持 `album.zip!/03 track.flac` 形式的压缩包内路径，直接打开压缩包时播放其中所有的音频文件。
+    /// 打开 m3u/m3u8 播放列表时用其中的文件填充播放队列
+    fn open<P: AsRef<Path>>(&mut self, filepath: &P) -> Result<(), Error> {
+        self.open_with_options(filepath, &OpenOptions::default())
+    }
+
+    fn open_with_options<P: AsRef<Path>>(&mut self, filepath: &P, options: &OpenOptions) -> Result<(), Error> {
+        self.follow_handover();
+        self.release_finished_fade();
+        let has_current_track = self.source.as_ref().map(|source| !source.is_end()).unwrap_or(false);
+        let path = filepath.as_ref();
+
+        // 直接打开压缩包时，按压缩包中的顺序把所有音频文件加入播放队列
+        if archive::is_archive(path) && path.is_file() {
+            let archive = ZipArchive::open(&path).context(ArchiveSnafu)?;
+            let entries: Vec<PathBuf> = archive.audio_entries()
+                .iter()
+                .map(|entry| archive::entry_path(path, &entry.name))
+                .collect();
+            ensure!(!entries.is_empty(), QueueSnafu {
+                message: format!("no audio files found in {}", path.display()),
+            });
+            return self.open_entries(entries, has_current_track, options);
+        }
+
+        // 播放列表不能交给 FFmpeg 打开，把其中的文件加入播放队列
+        if playlist::is_playlist(path) && path.is_file() {
+            let entries: Vec<PathBuf> = playlist::load_m3u(path)
+                .context(PlaylistSnafu)?
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect();
+            ensure!(!entries.is_empty(), QueueSnafu {
+                message: format!("the playlist {} is empty", path.display()),
+            });
+            return self.open_entries(entries, has_current_track, options);
+        }
+
+        if self.open_mode == OpenMode::Enqueue && has_current_track {
+            self.queue.push(path.to_path_buf());
+            return Ok(());
+        }
+        self.open_path(filepath, options)
+    }
+
+    /// 从读取器（例如标准输入）打开，读取器被视为不可定位的流，总长度未知
+    ///
+    /// 读取器无法加入播放队列，Enqueue 模式下也会替换当前曲目
+    fn open_reader(&mut self, reader: Box<dyn Read + Send>) -> Result<(), Error> {
+        let crossfade = match self.open_mode {
+            OpenMode::Crossfade(duration) => Some(duration),
+            _ => None,
+        };
+        self.resume_info = None;
+        self.open_with(crossfade, None, move |source, sample_format| source.open_reader(reader, sample_format))
+    }
+
+    /// 试听：从文件总长度的 start_fraction 处开始播放 duration_ms 毫秒，然后停在试听片段的开头
+    ///
+    /// 基于片段播放实现，不会从书签继续，也不会播放队列中的下一首。打开其他文件即可中断试听
+    fn audition<P: AsRef<Path>>(&mut self, filepath: &P, start_fraction: f64, duration_ms: i64) -> Result<(), Error> {
+        ensure!((0. ..1.).contains(&start_fraction), InvalidArgumentSnafu {
+            message: format!("the start fraction must be in [0, 1), got {}", start_fraction),
+        });
+        ensure!(duration_ms > 0, InvalidArgumentSnafu {
+            message: format!("the audition duration must be positive, got {} ms", duration_ms),
+        });
+        let path = filepath.as_ref();
+        let duration = probe::probe(&self.rewrite_path(path), &[])?.duration;
+        ensure!(duration > 0, UnavailableSnafu {
+            message: format!("the duration of {} is unknown", path.display()),
+        });
+        let start_ms = (duration as f64 * start_fraction) as i64;
+        let end_ms = (start_ms + duration_ms).min(duration);
+        self.open_path(&path, &OpenOptions::new().clip(start_ms, end_ms))?;
+        self.auditioning = true;
+        self.play()
+    }
+
+    fn set_open_mode(&mut self, mode: OpenMode) {
+        self.open_mode = mode;
+    }
+
+    fn get_open_mode(&self) -> OpenMode {
+        self.open_mode
+    }
+
+    /// 关闭当前曲目并释放播放源
+    ///
+    /// 关闭之后和没有打开文件时一样：数值类的获取方法返回 0（总长度返回 -1），is_playing 返回 false，
+    /// get_media_info 返回 None，reopen_current 没有可以重新打开的文件
+    fn close(&mut self) -> Result<(), Error> {
+        self.follow_handover();
+        self.discard_next_source();
+        let pending = self.pending_open.lock().unwrap().take();
+        self.bookmarks.record();
+        self.close_fading_source();
+        let source = self.source.take();
+        self.bookmarks.set_current(None);
+        self.playback_tracker.clear_source();
+        self.current_path = None;
+        self.current_stamp = None;
+        self.auditioning = false;
+        self.applied_replay_gain = None;
+        if let Some(device) = self.device.as_ref() {
+            device.set_input_status(None);
+        }
+        if source.is_some() || pending.is_some() {
+            self.emit_artwork_changed(None);
+        }
+        if let Some(mut source) = source {
+            source.close().context(SourceSnafu)?;
+        }
+        Ok(())
+    }
+
+    /// 把播放器恢复到刚创建时的状态，音频设备保持打开，可以立即重新使用
+    ///
+    /// 关闭当前曲目（包括淡出中的曲目和第二个 deck）并清空输出缓冲区，结束录制和响度分析，移除附加输出，清空播放队列和播放统计。
+    /// 均衡器、声道延迟、用户音频处理器、音频分析和分析的更新频率、播放速度、ReplayGain、定时停止、打开方式、结束行为、
+    /// 无缝循环、定位防抖、错误处理方式、解码块大小、导出格式和 deck 音量恢复默认值。
+    /// 事件订阅、元数据读取器、路径改写函数、输入选项、书签和统计阈值保留，音量和设备选择按 options 决定
+    fn reset(&mut self, options: ResetOptions) -> Result<(), Error> {
+        self.stop_capture();
+        self.cancel_loudness_analysis();
+        self.close()?;
+        self.close_second_deck();
+        self.deck_volumes = [1.; 2];
+        self.apply_deck_volumes();
+        if let Some(device) = self.device.as_ref() {
+            device.finish_crossfade();
+            device.pause();
+            device.clear_output_buffer();
+        }
+        self.fading_source = None;
+        self.resume_info = None;
+        self.position_smoother.lock().unwrap().reset();
+        self.queue = PlayQueue::new();
+        let threshold = self.playback_tracker.get_threshold();
+        let clipping_events = self.playback_tracker.is_clipping_events();
+        self.playback_tracker = PlaybackTracker::new(self.events.clone());
+        self.playback_tracker.set_threshold(threshold);
+        self.playback_tracker.set_clipping_events(clipping_events);
+
+        for (id, _) in self.get_outputs() {
+            self.remove_output(id)?;
+        }
+        self.pending_dsp_processor = None;
+        self.set_dsp_processor(None);
+        self.set_analysis_enabled(false);
+        self.set_analysis_update_rate_hz(analysis::DEFAULT_UPDATE_RATE_HZ)?;
+        let defaults = Equalizer::new();
+        self.update_equalizer(|equalizer| {
+            equalizer.set_enabled(defaults.is_enabled());
+            equalizer.set_auto_gain(defaults.is_auto_gain());
+            equalizer.set_bands(defaults.band_frequencies(), defaults.band_q(), defaults.band_gains());
+        });
+        self.set_channel_delays(&[])?;
+        self.set_master_limiter(false, limiter::DEFAULT_CEILING_DB)?;
+        self.set_processing_order(&dsp::DEFAULT_PROCESSING_ORDER)?;
+        self.set_convolution(false);
+        self.clear_impulse_response();
+        self.capture_split_on_track = false;
+        self.set_capture_pre_volume(false);
+
+        self.speed = 1.;
+        self.replay_gain = ReplayGainSettings::default();
+        self.album_tags.clear();
+        self.sleep_timer.set_timer(SleepTimer::Off);
+        self.open_mode = OpenMode::default();
+        self.end_behavior = None;
+        self.finished_action = FinishedAction::default();
+        self.seamless_loop = false;
+        self.seek_debounce_ms = source::DEFAULT_SEEK_DEBOUNCE_MS;
+        self.error_resilience = ErrorResilience::default();
+        self.decode_chunk_size = None;
+        self.set_rebuffer_threshold_ms(0)?;
+        self.set_target_buffer_ms(DEFAULT_TARGET_BUFFER_MS)?;
+        self.set_prebuffer_ms(None)?;
+        self.lazy_device = true;
+        self.lazy_cover = false;
+        self.export_sample_format = ExportSampleFormat::default();
+
+        if !options.keep_volume {
+            self.volume_curve = VolumeCurve::default();
+            self.set_volume(1.)?;
+            self.set_mute(false)?;
+        }
+        if !options.keep_device_selection {
+            self.preferred_host = None;
+            self.preferred_device = None;
+            self.output_channel_map.clear();
+        }
+        Ok(())
+    }
+
+    fn play(&mut self) -> Result<(), Error> {
+        self.release_finished_fade();
+        self.follow_handover();
+        // 队列可能在打开之后被修改，播放前重新选择结束行为和预先打开的下一首
+        self.update_end_behavior();
+        self.prepare_next_source();
+        // 没有预先打开下一首时，已经结束的播放源不能再播放，直接播放下一首
+        let handed_over = self.source.as_ref()
+            .map(|source| source.is_end() && source.get_end_behavior() == EndBehavior::Handover)
+            .unwrap_or(false);
+        if handed_over && self.play_next()? {
+            return Ok(());
+        }
+        let pending = self.pending_open.lock().unwrap().take();
+        if let Some(pending) = pending {
+            self.init_device_defalut()?;
+            self.open_pending(pending)?;
+        }
+        if self.device.is_none() {
+            self.init_device_defalut()?;
+        } else {
+            self.recover_output()?;
+        }
+
+        if let Some(device) = self.device.as_ref() {
+            device.resume();
+            if let Some(source) = self.source.as_ref() {
+                source.streaming().context(SourceSnafu)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Error> {
+        // 淡出中的曲目也一起停止
+        if let Some(device) = self.device.as_ref() {
+            device.finish_crossfade();
+        }
+        self.bookmarks.record();
+        if let Some(pending) = self.pending_open.lock().unwrap().as_mut() {
+            pending.start_ms = 0;
+        }
+        if let Some(source) = self.active_source() {
+            source.interrupt_wait();
+            source.pause().context(SourceSnafu)?;
+            source.clear_buffer();
+            if source.is_seekable() {
+                source.seek(0).context(SourceSnafu)?;
+            }
+        }
+        self.position_smoother.lock().unwrap().reset();
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), Error> {
+        if let Some(device) = self.device.as_ref() {
+            device.pause();
+            if let Some(source) = self.active_source() {
+                source.pause().context(SourceSnafu)?;
+            }
+        }
+        self.bookmarks.record();
+        Ok(())
+    }
+
+    fn seek(&self, time: i64) -> Result<(), Error> {
+        // 还没有播放过的文件只记录开始位置
+        if let Some(pending) = self.pending_open.lock().unwrap().as_mut() {
+            pending.start_ms = time.max(0);
+            return Ok(());
+        }
+        if let Some(source) = self.active_source() {
+            source.seek(time).context(SourceSnafu)?;
+        }
+        self.position_smoother.lock().unwrap().reset();
+        Ok(())
+    }
+
+    /// 阻塞到当前曲目播放完毕，包括输出缓冲区中剩余的音频，已经播放完毕时立即返回 true。
+    /// 超时、停止或者关闭时返回 false，没有打开文件时也返回 false
+    fn wait_for_end(&self, timeout: Option<Duration>) -> bool {
+        match self.end_signal() {
+            Some(end_signal) => end_signal.wait(timeout),
+            None => false,
+        }
+    }
+
+    /// 当前曲目的播放结束通知，没有打开文件时为 None
+    ///
+    /// 等待的线程持有自己的引用，等待期间不需要访问播放器。停止、关闭或者替换曲目时等待被打断并返回 false
+    fn end_signal(&self) -> Option<Arc<EndSignal>> {
+        self.active_source().map(|source| source.end_signal())
+    }
+
+    fn get_bitrate(&self) -> u32 {
+        match self.active_source() {
+            Some(source) => source.get_bitrate() as u32,
+            None => 0,
+        }
+    }
+
+    /// 总长度（毫秒），未知或者没有打开文件时为 -1
+    fn get_duration(&self) -> i64 {
+        match self.active_source() {
+            Some(source) => source.get_duration(),
+            None => self.pending(|pending| pending.media_info.duration).unwrap_or(-1),
+        }
+    }
+
+    fn get_current_time(&self) -> i64 {
+        match self.active_source() {
+            Some(source) => source.get_current_time(),
+            None => self.pending(|pending| pending.start_ms).unwrap_or(0),
+        }
+    }
+
+    /// 高精度的播放位置（微秒），用于和画面同步
+    ///
+    /// 和 get_current_time 不同，扣除了输出缓冲区中还没有播放的数据，在两个数据包之间按已经播放的帧数推算，
+    /// 精确到输出的一帧。不包括设备缓冲区的延迟，需要更精确时再减去 get_output_latency_ms 和
+    /// get_buffer_latency_ms 的差。没有打开文件时返回 0
+    ///
+    /// 播放时在两次输出回调之间按经过的时间推算，界面每帧读取时连续变化，不会倒退。暂停后返回准确的位置
+    fn get_current_time_us(&self) -> i64 {
+        match (self.active_source(), self.device.as_ref()) {
+            (Some(source), Some(device)) => {
+                let sample_rate = device.sample_format.map_or(0, |format| format.sample_rate);
+                let exact = source.get_current_time_us(device.buffered_frames(), sample_rate);
+                let callback_us = match sample_rate {
+                    0 => 0,
+                    _ => device.callback_frames() as i64 * 1_000_000 / sample_rate as i64,
+                };
+                self.position_smoother.lock().unwrap().position(exact, source.is_streaming(), self.speed, callback_us)
+            },
+            (Some(source), None) => source.get_current_time() * 1000,
+            _ => self.get_current_time() * 1000,
+        }
+    }
+
+    /// 和 get_bitrate 相同，没有打开文件或者码率未知时返回错误而不是 0
+    fn try_get_bitrate(&self) -> Result<u32, Error> {
+        let bitrate = self.current_source()?.get_bitrate();
+        ensure!(bitrate > 0, UnavailableSnafu {
+            message: "the bitrate is unknown".to_string(),
+        });
+        Ok(bitrate as u32)
+    }
+
+    /// 和 get_duration 相同，没有打开文件或者总长度未知（例如网络电台）时返回错误
+    fn try_get_duration(&self) -> Result<i64, Error> {
+        let duration = match self.pending(|pending| pending.media_info.duration) {
+            Some(duration) => duration,
+            None => self.current_source()?.get_duration(),
+        };
+        ensure!(duration >= 0, UnavailableSnafu {
+            message: "the duration is unknown".to_string(),
+        });
+        Ok(duration)
+    }
+
+    /// 和 get_current_time 相同，没有打开文件时返回错误
+    fn try_get_current_time(&self) -> Result<i64, Error> {
+        match self.pending(|pending| pending.start_ms) {
+            Some(start_ms) => Ok(start_ms),
+            None => Ok(self.current_source()?.get_current_time()),
+        }
+    }
+
+    /// 和 get_replay_gain_peak 相同，文件中没有峰值标签时返回错误
+    fn try_get_replay_gain_peak(&self) -> Result<f32, Error> {
+        self.current_source()?;
+        self.get_replay_gain_peak().context(UnavailableSnafu {
+            message: "the file has no ReplayGain peak".to_string(),
+        })
+    }
+
+    /// 播放中或者还在播放输出缓冲区中剩余的音频
+    fn is_playing(&self) -> bool {
+        match self.active_source() {
+            Some(source) => source.is_streaming() || self.is_draining(),
+            None => false,
+        }
+    }
+
+    fn get_volume(&self) -> f32 {
+        self.volume
+    }
+
+    fn set_volume(&mut self, volume: f32) -> Result<(), Error> {
+        self.volume = volume;
+        if let Some(device) = self.device.as_ref() {
+            device.set_volume(self.volume_curve.to_db(volume));
+        }
+        Ok(())
+    }
+
+    /// 设置 set_volume 和 set_output_volume 使用的音量曲线，立即应用到当前音量，默认为指数曲线
+    ///
+    /// 已经设置的附加输出音量在下一次 set_output_volume 时才使用新的曲线
+    fn set_volume_curve(&mut self, curve: VolumeCurve) -> Result<(), Error> {
+        match &curve {
+            VolumeCurve::LinearDb { min_db } => ensure!(min_db.is_finite() && *min_db < 0., InvalidArgumentSnafu {
+                message: format!("the lowest volume must be a negative dB value, got {}", min_db),
+            }),
+            VolumeCurve::Table(table) => ensure!(table.len() >= 2 && table.iter().all(|db| db.is_finite()), InvalidArgumentSnafu {
+                message: "the volume table needs at least two finite dB values".to_string(),
+            }),
+            _ => {},
+        }
+        self.volume_curve = curve;
+        self.set_volume(self.volume)
+    }
+
+    fn get_volume_curve(&self) -> VolumeCurve {
+        self.volume_curve.clone()
+    }
+
+    fn set_mute(&self, mute: bool) -> Result<(), Error> {
+        self.mute.store(mute, Ordering::Relaxed);
+        if let Some(device) = self.device.as_ref() {
+            device.set_mute(mute);
+        }
+        Ok(())
+    }
+
+    /// 获取当前曲目的媒体信息快照，网络电台的标题变化后需要重新获取
+    fn get_media_info(&self) -> Option<Arc<MediaInfo>> {
+        match self.active_source() {
+            Some(source) => Some(source.get_media_info()),
+            None => self.pending(|pending| pending.media_info.clone()),
+        }
+    }
+
+    /// 正在播放的音频流的编码器附加数据（例如 FLAC 的 STREAMINFO、AAC 的 AudioSpecificConfig），
+    /// 和 FFmpeg 中 codecpar->extradata 的内容相同，没有打开文件或者编码格式没有附加数据时返回 None
+    fn get_codec_extradata(&self) -> Option<Vec<u8>> {
+        self.get_media_info()
+            .and_then(|media_info| media_info.codec_extradata.as_ref().map(|extradata| extradata.to_vec()))
+    }
+
+    /// 当前的编码是否是无损的，例如 FLAC、ALAC、APE、WavPack 和 PCM 为无损，MP3、AAC、Opus、Vorbis 为有损。
+    /// 无法判断或者没有打开文件时返回 None
+    fn is_lossless(&self) -> Option<bool> {
+        self.get_media_info().and_then(|media_info| media_info.is_lossless)
+    }
+
+    /// 容器和正在播放的音频流上的所有原始标签，分开返回，没有打开文件时返回 None
+    ///
+    /// 同一个键可能同时出现在两边，例如 FLAC 文件的 Vorbis Comment 会被 FFmpeg 同时放到容器上
+    fn get_all_metadata(&self) -> Option<RawTags> {
+        self.get_media_info().map(|media_info| media_info.raw_tags.clone())
+    }
+
+    /// 添加元数据读取器，从下一次打开文件开始生效
+    fn add_metadata_extractor(&mut self, extractor: Arc<dyn MetadataExtractor>) {
+        self.metadata_extractors.push(extractor);
+    }
+
+    /// 移除所有用户提供的元数据读取器，只使用 FFmpeg 读取
+    fn clear_metadata_extractors(&mut self) {
+        self.metadata_extractors.clear();
+    }
+
+    fn is_seekable(&self) -> bool {
+        match self.active_source() {
+            Some(source) => source.is_seekable(),
+            None => self.pending(|pending| pending.media_info.duration >= 0).unwrap_or(false),
+        }
+    }
+
+    /// 获取实时码率（bit/s）
+    fn get_current_bitrate(&self) -> i64 {
+        match self.active_source() {
+            Some(source) => source.get_current_bitrate(),
+            None => 0,
+        }
+    }
+
+    fn get_status(&self) -> PlayerStatus {
+        match self.active_source() {
+            Some(source) => PlayerStatus {
+                current_time: source.get_current_time(),
+                duration: source.get_duration(),
+                bitrate: source.get_current_bitrate(),
+                is_playing: source.is_streaming() || self.is_draining(),
+                is_seekable: source.is_seekable(),
+                is_source_lost: source.is_source_lost(),
+                is_buffering: self.is_buffering(),
+            },
+            None => PlayerStatus {
+                current_time: self.get_current_time(),
+                duration: self.get_duration(),
+                is_seekable: self.is_seekable(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn set_end_behavior(&mut self, behavior: Option<EndBehavior>) {
+        self.follow_handover();
+        self.end_behavior = behavior;
+        self.update_end_behavior();
+        self.prepare_next_source();
+    }
+
+    fn get_end_behavior(&self) -> EndBehavior {
+        match self.active_source() {
+            Some(source) => source.get_end_behavior(),
+            None => self.end_behavior.unwrap_or_default(),
+        }
+    }
+
+    /// 开启时（默认），还没有打开音频设备的播放器打开文件只读取媒体信息，
+    /// 第一次播放时才打开设备、创建解码线程和预先缓冲，只读取标签的程序不会占用设备
+    ///
+    /// 延迟打开期间可以读取总长度、标签和封面，定位只记录开始播放的位置。从读取器打开时不会延迟。
+    /// 设备打开之后不再延迟
+    fn set_lazy_device(&mut self, lazy: bool) {
+        self.lazy_device = lazy;
+    }
+
+    fn is_lazy_device(&self) -> bool {
+        self.lazy_device
+    }
+
+    /// 开启后打开文件时不复制内嵌的封面，解码线程启动后再读取，封面很大的文件可以更快地开始播放
+    ///
+    /// 读取完成后 get_media_info 返回的 MediaInfo.cover 会更新，并发出 CoverReady 事件。
+    /// 在下一次打开文件时生效
+    fn set_lazy_cover(&mut self, lazy: bool) {
+        self.lazy_cover = lazy;
+    }
+
+    fn is_lazy_cover(&self) -> bool {
+        self.lazy_cover
+    }
+
+    /// 开启后可定位的文件播放到结尾时直接从头继续，首尾之间没有间隙，适合环境音和游戏音乐
+    ///
+    /// 不可定位的流不受影响
+    fn set_seamless_loop(&mut self, enabled: bool) {
+        self.follow_handover();
+        self.seamless_loop = enabled;
+        if let Some(source) = self.source.as_ref() {
+            source.set_seamless_loop(self.loops_track());
+        }
+        self.prepare_next_source();
+    }
+
+    fn is_seamless_loop(&self) -> bool {
+        self.seamless_loop
+    }
+
+    /// 选择曲目播放到结尾时播放器自己执行的操作，不需要宿主程序处理 EndOfStream 事件
+    ///
+    /// Stop 停止并回到开头，LoopTrack 无缝循环当前曲目，AdvanceQueue 紧接着播放队列中的下一首。
+    /// set_end_behavior 指定的结束行为和 set_seamless_loop 仍然优先，但不会改变这里的选择
+    fn set_finished_action(&mut self, action: FinishedAction) {
+        self.follow_handover();
+        self.finished_action = action;
+        if let Some(source) = self.source.as_ref() {
+            source.set_seamless_loop(self.loops_track());
+        }
+        self.update_end_behavior();
+        self.prepare_next_source();
+    }
+
+    fn get_finished_action(&self) -> FinishedAction {
+        self.finished_action
+    }
+
+    /// 设置定位防抖时间（毫秒），拖动进度条时短时间内的多次定位只执行最后一次，0 为不防抖
+    ///
+    /// 距离上一次定位超过防抖时间的定位立即执行，不增加延迟
+    fn set_seek_debounce_ms(&mut self, debounce_ms: u32) {
+        self.follow_handover();
+        self.seek_debounce_ms = debounce_ms;
+        for source in self.sources() {
+            source.set_seek_debounce_ms(debounce_ms);
+        }
+    }
+
+    fn get_seek_debounce_ms(&self) -> u32 {
+        self.seek_debounce_ms
+    }
+
+    /// 设置解码跟不上播放、输出缓冲区耗尽后，暂停输出直到重新积累的时长，避免慢速存储上断断续续地播放。
+    /// 0 表示不暂停（默认），最大值受输出缓冲区容量限制
+    fn set_rebuffer_threshold_ms(&mut self, threshold_ms: u32) -> Result<(), Error> {
+        ensure!(threshold_ms <= MAX_REBUFFER_THRESHOLD_MS, InvalidArgumentSnafu {
+            message: format!("the rebuffer threshold must be at most {} ms, got {} ms", MAX_REBUFFER_THRESHOLD_MS, threshold_ms),
+        });
+        self.rebuffer_threshold_ms = threshold_ms;
+        self.apply_hold_settings();
+        Ok(())
+    }
+
+    fn get_rebuffer_threshold_ms(&self) -> u32 {
+        self.rebuffer_threshold_ms
+    }
+
+    /// 设置动态缓冲区的时长（毫秒），默认 80 毫秒。解码线程最多领先设备这么多，定位、音量以外的设置改变
+    /// 和实时的音频处理器的输入都会晚这么久才被听到
+    ///
+    /// 低延迟的场合（实时效果、乐器）可以减小到 MIN_TARGET_BUFFER_MS，代价是解码线程被调度得不及时时更容易断续。
+    /// 设置了重新缓冲阈值或者预先缓冲时长时，缓冲区至少能容纳它们的两倍
+    fn set_target_buffer_ms(&mut self, target_ms: u32) -> Result<(), Error> {
+        ensure!((MIN_TARGET_BUFFER_MS..=MAX_REBUFFER_THRESHOLD_MS).contains(&target_ms), InvalidArgumentSnafu {
+            message: format!("the buffer duration must be between {} and {} ms, got {} ms",
+                MIN_TARGET_BUFFER_MS, MAX_REBUFFER_THRESHOLD_MS, target_ms),
+        });
+        self.target_buffer_ms = target_ms;
+        self.apply_hold_settings();
+        Ok(())
+    }
+
+    fn get_target_buffer_ms(&self) -> u32 {
+        self.target_buffer_ms
+    }
+
+    /// 设置开始播放和定位之后预先缓冲的时长，缓冲完成或者文件结束后才开始输出，避免开头断断续续。
+    /// None 表示缓冲到动态缓冲区写满为止（默认），Some(0) 表示不预先缓冲，最大值和重新缓冲阈值相同
+    fn set_prebuffer_ms(&mut self, prebuffer_ms: Option<u32>) -> Result<(), Error> {
+        if let Some(prebuffer_ms) = prebuffer_ms {
+            ensure!(prebuffer_ms <= MAX_REBUFFER_THRESHOLD_MS, InvalidArgumentSnafu {
+                message: format!("the prebuffer duration must be at most {} ms, got {} ms", MAX_REBUFFER_THRESHOLD_MS, prebuffer_ms),
+            });
+        }
+        self.prebuffer_ms = prebuffer_ms;
+        self.apply_hold_settings();
+        Ok(())
+    }
+
+    fn get_prebuffer_ms(&self) -> Option<u32> {
+        self.prebuffer_ms
+    }
+
+    /// 网络流是否正在缓冲，或者解码跟不上播放导致输出缓冲区耗尽
+    fn is_buffering(&self) -> bool {
+        let underrun = self.device.as_ref().map_or(false, |device| device.played_frame_counter().is_buffering());
+        underrun || self.active_source().map_or(false, |source| source.is_buffering())
+    }
+
+    /// 设置遇到损坏的数据包时的处理方式，默认跳过
+    ///
+    /// 网络流和损坏的文件中偶尔出现的错误数据包不会中断播放
+    fn set_error_resilience(&mut self, resilience: ErrorResilience) {
+        self.follow_handover();
+        self.error_resilience = resilience;
+        for source in self.sources() {
+            source.set_error_resilience(resilience);
+        }
+    }
+
+    fn get_error_resilience(&self) -> ErrorResilience {
+        self.error_resilience
+    }
+
+    /// 在当前文件的 A、B 两点（毫秒）之间循环播放，播放到 B 点后回到 A 点
+    ///
+    /// 当前位置不在 A、B 之间时立即跳转到 A 点。打开新文件后循环自动取消
+    fn set_ab_loop(&mut self, a_ms: i64, b_ms: i64) -> Result<(), Error> {
+        ensure!(0 <= a_ms && a_ms < b_ms, InvalidArgumentSnafu {
+            message: format!("invalid A-B loop {}..{}", a_ms, b_ms),
+        });
+        let source = self.current_source()?;
+        ensure!(source.is_seekable(), UnavailableSnafu {
+            message: "the media is not seekable".to_string(),
+        });
+        let duration = source.get_duration();
+        ensure!(duration < 0 || b_ms <= duration, InvalidArgumentSnafu {
+            message: format!("the B point {} is beyond the duration {}", b_ms, duration),
+        });
+        source.set_ab_loop(Some((a_ms, b_ms)));
+        let current_time = source.get_current_time();
+        if current_time < a_ms || current_time >= b_ms {
+            self.seek(a_ms)?;
+        }
+        Ok(())
+    }
+
+    fn clear_ab_loop(&mut self) {
+        self.follow_handover();
+        if let Some(source) = self.source.as_ref() {
+            source.set_ab_loop(None);
+        }
+    }
+
+    fn get_ab_loop(&self) -> Option<(i64, i64)> {
+        self.active_source().and_then(|source| source.get_ab_loop())
+    }
+
+    fn is_source_lost(&self) -> bool {
+        self.active_source().map(|source| source.is_source_lost()).unwrap_or(false)
+    }
+
+    /// 当前文件的修改时间或者大小和打开时不一致，或者文件已经不存在
+    fn is_source_changed(&self) -> bool {
+        match (self.current_path.as_ref(), self.current_stamp.as_ref()) {
+            (Some(path), Some(stamp)) => FileStamp::read(path).as_ref() != Some(stamp),
+            _ => false,
+        }
+    }
+
+    /// 重新打开当前文件并定位到原来的位置，用于文件丢失后恢复播放，原来正在播放时继续播放
+    fn reopen_current(&mut self) -> Result<(), Error> {
+        self.follow_handover();
+        let path = self.current_path.clone().context(InvalidArgumentSnafu {
+            message: "no file to reopen".to_string(),
+        })?;
+        let position = self.get_current_time();
+        let was_playing = self.is_playing();
+        self.open_path(&path, &OpenOptions::new().resume(false))?;
+
+        if let Some(source) = self.source.as_ref() {
+            let duration = source.get_duration();
+            if position > 0 && source.is_seekable() && (duration < 0 || position < duration) {
+                source.seek(position).context(SourceSnafu)?;
+            }
+        }
+        if was_playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// 输出设备失效（例如拔出了 USB 声卡）时在其他设备上重新打开输出，并回到实际听到的位置
+    ///
+    /// 设备正常时返回 false。输出缓冲区中还没有播放的数据会被丢弃，位置按缓冲区的时长修正，
+    /// 误差不超过设备缓冲区的长度。新设备的采样格式不同时重新打开当前文件，原来正在播放时继续播放
+    fn recover_output(&mut self) -> Result<bool, Error> {
+        self.follow_handover();
+        let old_format = match self.device.as_ref() {
+            Some(device) if !device.is_available() => device.sample_format,
+            _ => return Ok(false),
+        };
+        let position = self.heard_position();
+        let was_playing = self.is_playing();
+        log::warn!("The output device is no longer available, reopening the output");
+        self.init_device_defalut()?;
+
+        let new_format = self.device.as_ref().and_then(|device| device.sample_format);
+        if new_format != old_format {
+            // 解码线程按原来设备的格式输出，只能重新打开
+            if let Some(path) = self.current_path.clone() {
+                self.open_path(&path, &OpenOptions::new().resume(false))?;
+            }
+        }
+        if let (Some(position), Some(source)) = (position, self.source.as_ref()) {
+            source.seek(position).context(SourceSnafu)?;
+        }
+        if was_playing {
+            if let (Some(device), Some(source)) = (self.device.as_ref(), self.source.as_ref()) {
+                device.resume();
+                source.streaming().context(SourceSnafu)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn set_preferred_host(&mut self, host: HostId) -> Result<(), Error> {
+        self.preferred_host = Some(host);
+        // 已经打开设备时立即切换
+        if self.device.is_some() && self.get_output_host() != Some(host) {
+            self.init_device_defalut()?;
+        }
+        Ok(())
+    }
+
+    fn get_available_hosts(&self) -> Vec<HostId> {
+        cpal::available_hosts()
+    }
+
+    fn get_output_host(&self) -> Option<HostId> {
+        self.device.as_ref().and_then(|device| device.get_host_id())
+    }
+
+    fn get_output_device_name(&self) -> Option<String> {
+        self.device.as_ref()
+            .and_then(|device| device.get_device_name())
+            .map(|name| name.to_string())
+    }
+
+    fn set_output_device(&mut self, name: Option<String>) -> Result<(), Error> {
+        let changed = self.preferred_device != name;
+        self.preferred_device = name;
+        if changed && self.device.is_some() {
+            self.init_device_defalut()?;
+        }
+        Ok(())
+    }
+
+    fn get_output_device_names(&self) -> Vec<String> {
+        AudioDevice::output_device_names(self.get_output_host().or(self.preferred_host))
+    }
+
+    /// 设置声道映射，第 i 个元素是解码后第 i 个声道输出到的设备声道（从 0 开始），传入空数组时恢复默认
+    ///
+    /// 默认按设备的声道数解码，声道数相同时一一对应，立体声的文件输出到前置的左右声道。
+    /// 设备的声道不按标准布局排列时（例如有 4 个独立输出的 USB 声卡），可以设置为 [0, 1]，
+    /// 按立体声解码并只在设备的第 1、2 个声道上播放。没有映射到的设备声道输出静音。
+    /// 已经打开设备时重新打开设备，声道数改变时重新打开当前文件
+    fn set_output_channel_map(&mut self, channel_map: Vec<usize>) -> Result<(), Error> {
+        ensure!(channel_map.len() <= AudioSample::MAX_CHANNELS, InvalidArgumentSnafu {
+            message: format!("at most {} channels are supported", AudioSample::MAX_CHANNELS),
+        });
+        let device_channels = self.device.as_ref()
+            .and_then(|device| device.sample_format)
+            .map(|sample_format| sample_format.device_channel_count as usize);
+        if let Some(device_channels) = device_channels {
+            if let Some(channel) = channel_map.iter().find(|channel| **channel >= device_channels) {
+                return Err(Error::InvalidArgument {
+                    message: format!("the output device has {} channels, channel {} is out of range", device_channels, channel),
+                });
+            }
+        }
+        if channel_map == self.output_channel_map {
+            return Ok(());
+        }
+        self.output_channel_map = channel_map;
+
+        if let Some(device) = self.device.as_ref() {
+            let old_format = device.sample_format;
+            self.init_device_defalut()?;
+            let new_format = self.device.as_ref().and_then(|device| device.sample_format);
+            // 解码线程按原来的声道数输出，只能重新打开
+            if new_format.map(|format| format.channel_count) != old_format.map(|format| format.channel_count)
+                && self.current_path.is_some() {
+                self.reopen_current()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_output_channel_map(&self) -> Vec<usize> {
+        self.output_channel_map.clone()
+    }
+
+    /// 在同一个音频主机上添加一个附加输出设备（例如监听耳机），和主设备同时播放
+    ///
+    /// 附加输出有独立的音量和静音，采样率和主设备不同时会重采样，主设备的采样率改变时会被移除
+    fn add_output(&mut self, device_name: &str) -> Result<OutputId, Error> {
+        let device = self.device.as_mut().context(UnavailableSnafu {
+            message: "the audio device has not been initialized".to_string(),
+        })?;
+        device.add_secondary_output(device_name).context(DeviceSnafu)
+    }
+
+    fn remove_output(&mut self, id: OutputId) -> Result<(), Error> {
+        let removed = self.device.as_mut().map(|device| device.remove_secondary_output(id)).unwrap_or(false);
+        ensure!(removed, InvalidArgumentSnafu {
+            message: format!("unknown output {}", id),
+        });
+        Ok(())
+    }
+
+    /// 设置附加输出的音量，取值范围和 set_volume 相同
+    fn set_output_volume(&mut self, id: OutputId, volume: f32) -> Result<(), Error> {
+        let found = self.device.as_ref()
+            .map(|device| device.set_secondary_volume(id, self.volume_curve.to_db(volume)))
+            .unwrap_or(false);
+        ensure!(found, InvalidArgumentSnafu {
+            message: format!("unknown output {}", id),
+        });
+        Ok(())
+    }
+
+    fn set_output_mute(&mut self, id: OutputId, mute: bool) -> Result<(), Error> {
+        let found = self.device.as_ref().map(|device| device.set_secondary_mute(id, mute)).unwrap_or(false);
+        ensure!(found, InvalidArgumentSnafu {
+            message: format!("unknown output {}", id),
+        });
+        Ok(())
+    }
+
+    /// 列出附加输出的编号和设备名称
+    fn get_outputs(&self) -> Vec<(OutputId, String)> {
+        self.device.as_ref().map(|device| device.secondary_outputs()).unwrap_or_default()
+    }
+
+    /// 在指定的 deck 上打开文件，MAIN_DECK 和 open 相同，SECOND_DECK 和当前曲目同时播放
+    ///
+    /// 第二个 deck 打开期间不交叉淡化，打开方式为 Crossfade 时直接替换当前曲目
+    fn open_deck<P: AsRef<Path>>(&mut self, deck: DeckId, path: &P) -> Result<(), Error> {
+        YakoPlayer::check_deck(deck)?;
+        if deck == MAIN_DECK {
+            self.open_path(path, &OpenOptions::new())
+        } else {
+            self.open_second_deck(path.as_ref())
+        }
+    }
+
+    /// 开始播放指定的 deck，另一个 deck 的状态不变
+    fn play_deck(&mut self, deck: DeckId) -> Result<(), Error> {
+        YakoPlayer::check_deck(deck)?;
+        if deck == MAIN_DECK {
+            return self.play();
+        }
+        let source = self.deck_source.as_deref().context(UnavailableSnafu {
+            message: format!("deck {} is not open", deck),
+        })?;
+        if let Some(device) = self.device.as_ref() {
+            device.resume();
+        }
+        source.streaming().context(SourceSnafu)
+    }
+
+    /// 暂停指定的 deck，另一个 deck 继续播放
+    fn pause_deck(&self, deck: DeckId) -> Result<(), Error> {
+        YakoPlayer::check_deck(deck)?;
+        let source = if deck == MAIN_DECK { self.active_source() } else { self.deck_source.as_deref() };
+        match source {
+            Some(source) => source.pause().context(SourceSnafu),
+            None => Ok(()),
+        }
+    }
+
+    fn close_deck(&mut self, deck: DeckId) -> Result<(), Error> {
+        YakoPlayer::check_deck(deck)?;
+        if deck == MAIN_DECK {
+            return self.close();
+        }
+        self.close_second_deck();
+        Ok(())
+    }
+
+    /// 设置 deck 的音量，按当前的音量曲线换算，在总音量之前应用
+    fn set_deck_volume(&mut self, deck: DeckId, volume: f32) -> Result<(), Error> {
+        YakoPlayer::check_deck(deck)?;
+        self.deck_volumes[deck as usize] = volume;
+        self.apply_deck_volumes();
+        Ok(())
+    }
+
+    fn get_queue(&self) -> &PlayQueue {
+        &self.queue
+    }
+
+    fn get_queue_mut(&mut self) -> &mut PlayQueue {
+        self.follow_handover();
+        &mut self.queue
+    }
+
+    /// 把播放队列保存为 M3U 播放列表，用 open 打开播放列表时恢复队列
+    fn save_playlist<P: AsRef<Path>>(&self, path: &P) -> Result<(), Error> {
+        playlist::save_m3u(path.as_ref(), &self.queue).context(PlaylistSnafu)
+    }
+
+    fn play_queue_entry(&mut self, index: usize) -> Result<(), Error> {
+        self.follow_handover();
+        let path = self.queue.set_current(index)
+            .map(|path| path.to_path_buf())
+            .context(QueueSnafu {
+                message: format!("queue index {} is out of range", index),
+            })?;
+        self.open_and_play(path)
+    }
+
+    fn play_next(&mut self) -> Result<bool, Error> {
+        self.follow_handover();
+        match self.queue.next().map(|path| path.to_path_buf()) {
+            Some(path) => {
+                self.open_and_play(path)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn play_previous(&mut self) -> Result<bool, Error> {
+        self.follow_handover();
+        if self.is_seekable() && self.get_current_time() > PREVIOUS_RESTART_THRESHOLD {
+            self.seek(0)?;
+            return Ok(true);
+        }
+        match self.queue.previous().map(|path| path.to_path_buf()) {
+            Some(path) => {
+                self.open_and_play(path)?;
+                Ok(true)
+            },
+            None => {
+                if self.is_seekable() {
+                    self.seek(0)?;
+                }
+                Ok(false)
+            },
+        }
+    }
+
+    /// 在后台线程中扫描目录下的音频文件，完成时发出 DirectoryScanned 事件
+    fn scan_directory<P: AsRef<Path>>(&self, path: &P, recursive: bool) -> DirectoryScan {
+        DirectoryScan::start(path.as_ref().to_path_buf(), recursive, self.events.clone())
+    }
+
+    /// 扫描目录，用找到的音频文件替换播放队列并播放第一个
+    ///
+    /// 会等待扫描完成，目录很大时可以先调用 scan_directory，收到事件后再设置播放队列
+    fn open_directory<P: AsRef<Path>>(&mut self, path: &P, recursive: bool) -> Result<ScanSummary, Error> {
+        self.follow_handover();
+        let summary = self.scan_directory(path, recursive).wait();
+        for warning in summary.warnings.iter() {
+            log::warn!("{}", warning);
+        }
+        ensure!(!summary.files.is_empty(), QueueSnafu {
+            message: format!("no audio files found in {}", path.as_ref().display()),
+        });
+        self.queue.set_entries(summary.files.clone());
+        self.play_queue_entry(0)?;
+        Ok(summary)
+    }
+
+    /// 并行读取多个文件的媒体信息，结果按输入顺序排列，用于快速扫描音乐库
+    ///
+    /// 只读取文件头，不会打开音频设备，也不会影响正在播放的曲目
+    fn probe_batch(&self, paths: &[PathBuf]) -> Vec<Result<MediaInfo, Error>> {
+        probe::probe_batch(paths, &self.metadata_extractors)
+    }
+
+    /// 在后台线程中把文件中 start_ms 到 end_ms 之间的音频导出为 WAV 或者 FLAC，例如保存一段节选
+    ///
+    /// 采样率和声道数由 set_export_sample_format 决定，默认保持原来的格式，文件头和实际的数据一致。
+    /// 起点和终点精确到采样，不经过音频设备，也不会影响正在播放的曲目
+    fn export_range<P: AsRef<Path>>(
+        &self,
+        input: &P,
+        start_ms: i64,
+        end_ms: i64,
+        output: PathBuf,
+        format: ExportFormat,
+        progress: Option<ExportProgress>,
+    ) -> Result<ExportJob, Error> {
+        ensure!(0 <= start_ms && start_ms < end_ms, InvalidArgumentSnafu {
+            message: format!("invalid export range {}..{}", start_ms, end_ms),
+        });
+        let conversion = match self.export_sample_format {
+            ExportSampleFormat::Native => None,
+            ExportSampleFormat::Device => {
+                let sample_format = self.device.as_ref().and_then(|device| device.sample_format).context(UnavailableSnafu {
+                    message: "the audio device has not been initialized".to_string(),
+                })?;
+                Some((sample_format.sample_rate, sample_format.channel_count))
+            },
+            ExportSampleFormat::Custom { sample_rate, channels } => Some((sample_rate, channels)),
+        };
+        Ok(ExportJob::start(
+            self.rewrite_path(input.as_ref()),
+            self.input_options.clone(),
+            TimeWindow::new(start_ms, Some(end_ms)),
+            output,
+            format,
+            conversion,
+            progress))
+    }
+
+    /// 设置导出文件的采样率和声道数，Device 在开始导出时读取当前输出设备的格式
+    fn set_export_sample_format(&mut self, sample_format: ExportSampleFormat) -> Result<(), Error> {
+        if let ExportSampleFormat::Custom { sample_rate, channels } = sample_format {
+            ensure!(sample_rate > 0 && channels > 0 && channels as usize <= AudioSample::MAX_CHANNELS, InvalidArgumentSnafu {
+                message: format!("unsupported export format: {} Hz, {} channels", sample_rate, channels),
+            });
+        }
+        self.export_sample_format = sample_format;
+        Ok(())
+    }
+
+    fn get_export_sample_format(&self) -> ExportSampleFormat {
+        self.export_sample_format
+    }
+
+    /// 打开一个由调用者拉取解码数据的音频流，转换为指定的采样率和声道数
+    ///
+    /// 不经过音频设备，也不会影响正在播放的曲目
+    fn open_decoder<P: AsRef<Path>>(&self, path: &P, sample_rate: u32, channels: u16) -> Result<DecodedStream, Error> {
+        DecodedStream::open(
+            &self.rewrite_path(path.as_ref()),
+            &self.input_options,
+            &self.metadata_extractors,
+            TimeWindow::default(),
+            sample_rate,
+            channels).context(SourceSnafu)
+    }
+
+    /// 设置打开文件之前改写路径的函数，传入 None 时移除，从下一次打开文件开始生效
+    fn set_path_rewriter(&mut self, rewriter: Option<PathRewriter>) {
+        self.path_rewriter = rewriter;
+    }
+
+    /// 设置打开文件时传给 FFmpeg 的选项，从下一次打开文件开始生效
+    ///
+    /// 例如网络流的 user_agent、timeout（微秒）和 headers，或者解封装器的 fflags
+    fn set_input_options(&mut self, options: HashMap<String, String>) {
+        self.input_options = options;
+    }
+
+    fn get_input_options(&self) -> &HashMap<String, String> {
+        &self.input_options
+    }
+
+    fn set_dsp_processor(&mut self, processor: Option<DspProcessor>) {
+        match self.device.as_ref() {
+            Some(device) => device.set_dsp_processor(processor),
+            None => self.pending_dsp_processor = processor,
+        }
+    }
+
+    fn set_analysis_enabled(&mut self, enabled: bool) {
+        self.analysis_enabled = enabled;
+        if let Some(device) = self.device.as_ref() {
+            device.get_analysis().set_enabled(enabled);
+        }
+    }
+
+    fn is_analysis_enabled(&self) -> bool {
+        self.analysis_enabled
+    }
+
+    /// 设置峰值和频谱每秒最多重新计算的次数，和音频回调的频率无关
+    ///
+    /// 更频繁地调用 get_peak_levels 和 get_spectrum 只会得到上一次的结果，低功耗设备上可以降低频率减少计算量。
+    /// 完全不需要分析时用 set_analysis_enabled 关闭，音频回调中也不再收集样本
+    fn set_analysis_update_rate_hz(&mut self, rate_hz: f32) -> Result<(), Error> {
+        ensure!(rate_hz > 0. && rate_hz <= analysis::MAX_UPDATE_RATE_HZ, InvalidArgumentSnafu {
+            message: format!("the analysis update rate must be greater than 0 and at most {} Hz, got {} Hz",
+                analysis::MAX_UPDATE_RATE_HZ, rate_hz),
+        });
+        self.analysis_update_rate_hz = rate_hz;
+        if let Some(device) = self.device.as_ref() {
+            device.get_analysis().set_update_rate_hz(rate_hz);
+        }
+        Ok(())
+    }
+
+    fn get_analysis_update_rate_hz(&self) -> f32 {
+        self.analysis_update_rate_hz
+    }
+
+    /// 获取上次调用以来左右声道的峰值（振幅比例）
+    fn get_peak_levels(&self) -> (f32, f32) {
+        match self.device.as_ref() {
+            Some(device) if self.analysis_enabled => device.get_analysis().take_peak_levels(),
+            _ => (0., 0.),
+        }
+    }
+
+    /// 获取对数分布的频谱，每个频段的值范围为 0 ~ 1
+    fn get_spectrum(&self, bands: usize) -> Vec<f32> {
+        match self.device.as_ref() {
+            Some(device) if self.analysis_enabled => device.get_analysis().spectrum(bands),
+            _ => vec![0.; bands],
+        }
+    }
+
+    /// 把设备输出的音频录制到文件，已经在录制时先结束原来的文件
+    ///
+    /// 录制的是重采样之后的数据，文件的采样率和声道数和输出设备相同，不是原来文件的格式。
+    /// 默认录制音量调整之后的数据，录制在后台线程中编码写入，来不及写入的数据会被丢弃并计数
+    fn start_capture(&mut self, path: PathBuf, format: CaptureFormat) -> Result<(), Error> {
+        self.stop_capture();
+        let device = self.device.as_ref().ok_or(capture::Error::DeviceNotReady).context(CaptureSnafu)?;
+        let channels = device.sample_format.map(|format| format.channel_count).unwrap_or(2);
+        let tap = device.get_capture();
+        tap.set_pre_volume(self.capture_pre_volume);
+        let session = CaptureSession::start(
+            tap,
+            path,
+            format,
+            channels,
+            self.capture_split_on_track,
+            self.events.subscribe(),
+        ).context(CaptureSnafu)?;
+        self.capture = Some(session);
+        Ok(())
+    }
+
+    /// 结束录制，写入缓冲区中剩余的数据并关闭文件
+    fn stop_capture(&mut self) {
+        if let Some(session) = self.capture.take() {
+            match self.device.as_ref() {
+                Some(device) => session.stop(device.get_capture()),
+                None => drop(session),
+            }
+        }
+    }
+
+    fn is_capturing(&self) -> bool {
+        self.capture.as_ref().map(|session| session.is_running()).unwrap_or(false)
+    }
+
+    /// 开启后每次切换曲目（包括网络电台的曲目标题变化）写入新的文件，之后的文件名为 `name-002.flac` 的形式
+    fn set_capture_split_on_track(&mut self, split_on_track: bool) {
+        self.capture_split_on_track = split_on_track;
+        if let Some(session) = self.capture.as_ref() {
+            session.set_split_on_track(split_on_track);
+        }
+    }
+
+    /// 是否录制音量调整和静音之前的数据
+    fn set_capture_pre_volume(&mut self, pre_volume: bool) {
+        self.capture_pre_volume = pre_volume;
+        if let Some(device) = self.device.as_ref() {
+            device.get_capture().set_pre_volume(pre_volume);
+        }
+    }
+
+    /// 当前录制中因为写入来不及而丢弃的帧数
+    fn get_capture_dropped_frames(&self) -> u64 {
+        match self.device.as_ref() {
+            Some(device) if self.capture.is_some() => device.get_capture().dropped_frames(),
+            _ => 0,
+        }
+    }
+
+    /// 设置解码线程每次写入输出缓冲区的采样数，不能超过动态缓冲区大小
+    ///
+    /// 较小的值降低延迟，较大的值减少解码线程的唤醒次数
+    fn set_decode_chunk_size(&mut self, size: usize) -> Result<(), Error> {
+        self.follow_handover();
+        ensure!(size > 0, InvalidArgumentSnafu {
+            message: "the decode chunk size must be greater than zero".to_string(),
+        });
+        let buffer_size = self.get_dynamic_buffer_size();
+        // 还没有打开设备时无法得知缓冲区大小，应用时再限制
+        ensure!(buffer_size == 0 || size <= buffer_size, InvalidArgumentSnafu {
+            message: format!("the decode chunk size {} exceeds the dynamic buffer size {}", size, buffer_size),
+        });
+
+        self.decode_chunk_size = Some(size);
+        if let Some(source) = self.source.as_mut() {
+            source.set_buffer_chunk_size(size);
+        }
+        self.refresh_next_source();
+        Ok(())
+    }
+
+    fn get_decode_chunk_size(&self) -> usize {
+        match self.active_source() {
+            Some(source) => source.get_buffer_chunk_size(),
+            None => {
+                let buffer_size = self.get_dynamic_buffer_size();
+                match self.decode_chunk_size {
+                    Some(size) if buffer_size == 0 => size,
+                    Some(size) => size.min(buffer_size),
+                    None => buffer_size / 2,
+                }
+            },
+        }
+    }
+
+    /// 获取动态缓冲区大小（采样数），还没有打开设备时返回 0
+    fn get_dynamic_buffer_size(&self) -> usize {
+        match self.active_source() {
+            Some(source) => source.get_dynamic_device_buffer_size(),
+            None => self.device.as_ref()
+                .and_then(|device| device.sample_format)
+                .map(|sample_format| YakoPlayer::device_buffer_size(&sample_format, self.target_buffer_ms, self.hold_ms()))
+                .unwrap_or(0),
+        }
+    }
+
+    /// 解码后的数据到实际播放的延迟（毫秒），包括输出缓冲区和设备缓冲区，可以用来校正视频的时间
+    fn get_output_latency_ms(&self) -> f64 {
+        self.device.as_ref()
+            .map(|device| device.output_latency().as_secs_f64() * 1000.)
+            .unwrap_or(0.)
+    }
+
+    /// 输出缓冲区中等待播放的数据的时长（毫秒），不包括设备缓冲区
+    fn get_buffer_latency_ms(&self) -> f64 {
+        self.device.as_ref()
+            .map(|device| device.buffer_latency().as_secs_f64() * 1000.)
+            .unwrap_or(0.)
+    }
+
+    /// 设置播放速度，变速不变调，之后打开的文件使用同样的速度
+    fn set_speed(&mut self, speed: f32) -> Result<(), Error> {
+        self.follow_handover();
+        ensure!((MIN_SPEED..=MAX_SPEED).contains(&speed), InvalidArgumentSnafu {
+            message: format!("the speed must be between {} and {}", MIN_SPEED, MAX_SPEED),
+        });
+        self.speed = speed;
+        for source in self.sources() {
+            source.set_speed(speed);
+        }
+        Ok(())
+    }
+
+    fn get_speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn set_eq_enabled(&mut self, enabled: bool) {
+        self.update_equalizer(|equalizer| equalizer.set_enabled(enabled));
+    }
+
+    fn is_eq_enabled(&self) -> bool {
+        self.equalizer.is_enabled()
+    }
+
+    /// 设置均衡器频段增益（分贝），范围为 ±24 dB
+    fn set_eq_band_gain(&mut self, index: usize, gain_db: f32) -> Result<(), Error> {
+        ensure!(index < self.equalizer.band_gains().len(), InvalidArgumentSnafu {
+            message: format!("equalizer band {} is out of range", index),
+        });
+        ensure!(gain_db.abs() <= equalizer::MAX_BAND_GAIN, InvalidArgumentSnafu {
+            message: format!("the band gain must be between -{0} and {0} dB", equalizer::MAX_BAND_GAIN),
+        });
+        self.update_equalizer(|equalizer| equalizer.set_band_gain(index, gain_db));
+        Ok(())
+    }
+
+    fn get_eq_band_gains(&self) -> Vec<f32> {
+        self.equalizer.band_gains().to_vec()
+    }
+
+    fn get_eq_band_frequencies(&self) -> Vec<f32> {
+        self.equalizer.band_frequencies().to_vec()
+    }
+
+    /// 换成十段（倍频程）或者三十一段（1/3 倍频程）的图示均衡器，频段使用 ISO 266 的中心频率，
+    /// gains_db 按频率从低到高对应每个频段，范围为 ±24 dB
+    ///
+    /// 之后 set_eq_band_gain 的序号对应新的频段，get_eq_band_frequencies 返回新的中心频率
+    fn set_graphic_eq(&mut self, num_bands: usize, gains_db: &[f32]) -> Result<(), Error> {
+        let (frequencies, q) = equalizer::graphic_layout(num_bands).context(InvalidArgumentSnafu {
+            message: format!("the graphic equalizer must have 10 or 31 bands, not {}", num_bands),
+        })?;
+        ensure!(gains_db.len() == num_bands, InvalidArgumentSnafu {
+            message: format!("expected {} band gains, got {}", num_bands, gains_db.len()),
+        });
+        ensure!(gains_db.iter().all(|gain| gain.abs() <= equalizer::MAX_BAND_GAIN), InvalidArgumentSnafu {
+            message: format!("the band gain must be between -{0} and {0} dB", equalizer::MAX_BAND_GAIN),
+        });
+        self.update_equalizer(|equalizer| equalizer.set_bands(frequencies, q, gains_db));
+        Ok(())
+    }
+
+    /// 开关均衡器自动增益，根据提升的频段自动降低输出，避免削波
+    fn set_eq_auto_gain(&mut self, auto_gain: bool) {
+        self.update_equalizer(|equalizer| equalizer.set_auto_gain(auto_gain));
+    }
+
+    fn is_eq_auto_gain(&self) -> bool {
+        self.equalizer.is_auto_gain()
+    }
+
+    /// 获取自动增益实际应用的补偿（分贝，不大于 0）
+    fn get_eq_compensation(&self) -> f32 {
+        match self.device.as_ref() {
+            Some(device) => device.get_equalizer().lock().unwrap().compensation_db(),
+            None => self.equalizer.compensation_db(),
+        }
+    }
+
+    /// 设置每个声道的延迟（毫秒），用于对齐到听音位置距离不同的音箱，没有指定的声道不延迟
+    fn set_channel_delays(&mut self, delays_ms: &[u32]) -> Result<(), Error> {
+        ensure!(delays_ms.len() <= AudioSample::MAX_CHANNELS, InvalidArgumentSnafu {
+            message: format!("at most {} channels are supported", AudioSample::MAX_CHANNELS),
+        });
+        ensure!(delays_ms.iter().all(|delay| *delay <= delay::MAX_CHANNEL_DELAY_MS), InvalidArgumentSnafu {
+            message: format!("the channel delay must not exceed {} ms", delay::MAX_CHANNEL_DELAY_MS),
+        });
+        self.channel_delays = delays_ms.to_vec();
+        if let Some(device) = self.device.as_ref() {
+            device.get_channel_delay().lock().unwrap().set_delays(delays_ms);
+        }
+        Ok(())
+    }
+
+    fn get_channel_delays(&self) -> Vec<u32> {
+        self.channel_delays.clone()
+    }
+
+    /// 开关总输出的限幅器，ceiling_db 为输出的上限（dBFS），默认关闭
+    ///
+    /// 限幅器在音量和均衡器之后，保证输出不超过上限，防止意外的增益损坏音箱。
+    /// 开启时输出会增加几毫秒的预读延迟
+    fn set_master_limiter(&mut self, enabled: bool, ceiling_db: f32) -> Result<(), Error> {
+        ensure!((limiter::MIN_CEILING_DB..=0.).contains(&ceiling_db), InvalidArgumentSnafu {
+            message: format!("the limiter ceiling must be between {} and 0 dBFS", limiter::MIN_CEILING_DB),
+        });
+        self.master_limiter = (enabled, ceiling_db);
+        if let Some(device) = self.device.as_ref() {
+            let mut limiter = device.get_limiter().lock().unwrap();
+            limiter.set_ceiling_db(ceiling_db);
+            limiter.set_enabled(enabled);
+        }
+        Ok(())
+    }
+
+    fn get_master_limiter(&self) -> (bool, f32) {
+        self.master_limiter
+    }
+
+    /// 调整输出回调中各个处理步骤的顺序，例如把用户处理器放在均衡器之前
+    ///
+    /// stages 必须包含均衡器、按声道延迟、卷积和用户处理器各一次，步骤没有开启时只是跳过。
+    /// 限幅器总是在音量之后最后处理，可以省略，给出时必须在最后
+    fn set_processing_order(&mut self, stages: &[DspStage]) -> Result<(), Error> {
+        let stages = match stages.split_last() {
+            Some((DspStage::Limiter, stages)) => stages,
+            _ => stages,
+        };
+        ensure!(!stages.contains(&DspStage::Limiter), InvalidArgumentSnafu {
+            message: "the master limiter must be the last stage".to_string(),
+        });
+        let order: ProcessingOrder = stages.try_into().ok().context(InvalidArgumentSnafu {
+            message: format!("expected {} stages before the limiter, got {}", dsp::REORDERABLE_STAGE_COUNT, stages.len()),
+        })?;
+        ensure!(dsp::DEFAULT_PROCESSING_ORDER.iter().all(|stage| order.contains(stage)), InvalidArgumentSnafu {
+            message: "each stage must appear exactly once".to_string(),
+        });
+        self.processing_order = order;
+        if let Some(device) = self.device.as_ref() {
+            device.set_processing_order(order);
+        }
+        Ok(())
+    }
+
+    /// 获取处理顺序，限幅器在最后
+    fn get_processing_order(&self) -> Vec<DspStage> {
+        let mut stages = self.processing_order.to_vec();
+        stages.push(DspStage::Limiter);
+        stages
+    }
+
+    /// 加载卷积使用的脉冲响应，例如房间混响或者耳机校正的冲激响应，支持 FFmpeg 可以解码的所有格式
+    ///
+    /// 按设备的采样率重采样，单声道的脉冲响应用于所有声道，声道数和设备相同时逐个声道对应。
+    /// 按能量最大的声道归一化，最长 MAX_IMPULSE_RESPONSE_SECONDS 秒。
+    /// 加载失败时保留之前的脉冲响应，加载不会开启卷积，需要调用 set_convolution
+    fn load_impulse_response<P: AsRef<Path>>(&mut self, path: &P) -> Result<(), Error> {
+        let path = path.as_ref().to_path_buf();
+        let sample_rate = self.device.as_ref()
+            .and_then(|device| device.sample_format)
+            .map(|sample_format| sample_format.sample_rate);
+        let prepared = self.prepare_impulse_response(&path, sample_rate)?;
+        self.impulse_response = Some((path, Arc::new(prepared)));
+        self.apply_convolution()
+    }
+
+    /// 移除脉冲响应，卷积不再起作用
+    fn clear_impulse_response(&mut self) {
+        self.impulse_response = None;
+        // 没有需要准备的脉冲响应，不会失败
+        self.apply_convolution().ok();
+    }
+
+    fn get_impulse_response_path(&self) -> Option<PathBuf> {
+        self.impulse_response.as_ref().map(|(path, _)| path.clone())
+    }
+
+    /// 开关卷积，默认关闭
+    ///
+    /// 卷积在均衡器和声道延迟之后、音量之前，开启时输出会增加 256 帧的延迟
+    fn set_convolution(&mut self, enabled: bool) {
+        self.convolution_enabled = enabled;
+        if let Some(device) = self.device.as_ref() {
+            device.get_convolver().lock().unwrap().set_enabled(enabled);
+        }
+    }
+
+    fn is_convolution_enabled(&self) -> bool {
+        self.convolution_enabled
+    }
+
+    /// 设置定时停止，条件满足时缓慢淡出后暂停，触发后自动变为 Off
+    fn set_sleep_timer(&mut self, timer: SleepTimer) {
+        self.sleep_timer.set_timer(timer);
+    }
+
+    fn get_sleep_timer(&self) -> SleepTimer {
+        self.sleep_timer.get_timer()
+    }
+
+    fn get_sleep_timer_remaining(&self) -> Option<Duration> {
+        self.sleep_timer.get_remaining()
+    }
+
+    /// 设置定时停止前淡出的时长，默认为 10 秒
+    fn set_sleep_fade_duration(&mut self, duration: Duration) {
+        self.sleep_timer.set_fade_duration(duration);
+    }
+
+    /// 当前文件恢复到的书签位置（毫秒），没有从书签恢复时返回 None
+    fn get_resume_info(&self) -> Option<(PathBuf, i64)> {
+        self.resume_info.clone()
+    }
+
+    /// 开关书签，默认关闭
+    fn set_bookmarks_enabled(&mut self, enabled: bool) {
+        self.bookmarks.set_enabled(enabled);
+    }
+
+    fn is_bookmarks_enabled(&self) -> bool {
+        self.bookmarks.is_enabled()
+    }
+
+    /// 改为使用指定的书签文件，文件存在但无法读取时返回错误
+    fn set_bookmarks_path(&mut self, path: PathBuf) -> Result<(), Error> {
+        self.bookmarks.set_path(path).context(BookmarksSnafu)
+    }
+
+    fn get_bookmarks_path(&self) -> PathBuf {
+        self.bookmarks.get_path()
+    }
+
+    /// 在当前的播放位置（扣除了输出缓冲区中还没有播放的数据）添加标记点，返回标记点的编号
+    ///
+    /// 标记点属于当前文件，开启书签时和书签保存在同一个文件中，下次打开这个文件时恢复。
+    /// 从读取器打开时没有文件路径，不能添加标记点
+    fn add_cue_point(&mut self, name: &str) -> Result<CueId, Error> {
+        let path = self.current_path.clone().context(InvalidArgumentSnafu {
+            message: "no file opened".to_string(),
+        })?;
+        let position_ms = self.get_current_time_us() / 1000;
+        let id = self.cue_points.add(name, position_ms);
+        self.bookmarks.set_cue_points(&path, self.cue_points.points());
+        Ok(id)
+    }
+
+    /// 当前文件的标记点，按位置排序
+    fn list_cue_points(&self) -> Vec<CuePoint> {
+        match self.current_path.as_ref() {
+            Some(_) => self.cue_points.points().to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 定位到标记点的位置
+    fn jump_to_cue(&self, id: CueId) -> Result<(), Error> {
+        let position_ms = self.current_path.as_ref()
+            .and_then(|_| self.cue_points.get(id))
+            .map(|point| point.position_ms)
+            .context(InvalidArgumentSnafu {
+                message: format!("unknown cue point: {}", id),
+            })?;
+        self.seek(position_ms)
+    }
+
+    /// 移除标记点，编号不存在时返回 false
+    fn remove_cue_point(&mut self, id: CueId) -> bool {
+        let path = match self.current_path.clone() {
+            Some(path) => path,
+            None => return false,
+        };
+        if !self.cue_points.remove(id) {
+            return false;
+        }
+        self.bookmarks.set_cue_points(&path, self.cue_points.points());
+        true
+    }
+
+    /// 设置应用 ReplayGain 的方式，立即应用到当前曲目（已经缓冲的数据除外）
+    ///
+    /// 专辑模式下从播放队列播放时，播放顺序中相邻的曲目有相同的专辑标签才使用专辑增益，
+    /// 在专辑边界上或者没有专辑增益时使用曲目增益
+    fn set_replay_gain_mode(&mut self, mode: ReplayGainMode) {
+        self.replay_gain.mode = mode;
+        self.update_replay_gain();
+    }
+
+    fn get_replay_gain_mode(&self) -> ReplayGainMode {
+        self.replay_gain.mode
+    }
+
+    /// 设置 ReplayGain 的预增益（分贝），加上预增益后仍然受峰值限制
+    fn set_replay_gain_preamp(&mut self, preamp_db: f32) -> Result<(), Error> {
+        ensure!((-MAX_REPLAY_GAIN_PREAMP..=MAX_REPLAY_GAIN_PREAMP).contains(&preamp_db), InvalidArgumentSnafu {
+            message: format!("the ReplayGain preamp must be between -{0} and {0} dB", MAX_REPLAY_GAIN_PREAMP),
+        });
+        self.replay_gain.preamp_db = preamp_db;
+        self.update_replay_gain();
+        Ok(())
+    }
+
+    fn get_replay_gain_preamp(&self) -> f32 {
+        self.replay_gain.preamp_db
+    }
+
+    /// 设置文件中没有增益标签时使用的增益（分贝），不加预增益，默认为 0
+    fn set_replay_gain_fallback(&mut self, fallback_db: f32) -> Result<(), Error> {
+        ensure!((-MAX_REPLAY_GAIN_PREAMP..=MAX_REPLAY_GAIN_PREAMP).contains(&fallback_db), InvalidArgumentSnafu {
+            message: format!("the ReplayGain fallback gain must be between -{0} and {0} dB", MAX_REPLAY_GAIN_PREAMP),
+        });
+        self.replay_gain.fallback_db = fallback_db;
+        self.update_replay_gain();
+        Ok(())
+    }
+
+    fn get_replay_gain_fallback(&self) -> f32 {
+        self.replay_gain.fallback_db
+    }
+
+    /// 设置是否根据峰值标签限制 ReplayGain 增益，避免削波，默认开启
+    fn set_replay_gain_prevent_clipping(&mut self, enabled: bool) {
+        self.replay_gain.prevent_clipping = enabled;
+        self.update_replay_gain();
+    }
+
+    fn is_replay_gain_prevent_clipping(&self) -> bool {
+        self.replay_gain.prevent_clipping
+    }
+
+    /// 获取当前曲目用于防止削波的峰值，专辑模式下优先使用专辑峰值
+    fn get_replay_gain_peak(&self) -> Option<f32> {
+        let media_info = self.active_source()?.get_media_info();
+        match self.applied_replay_gain {
+            Some(replay_gain) if replay_gain.source == GainSource::Album || replay_gain.source == GainSource::Track => {
+                replay_gain.peak
+            },
+            _ => media_info.replay_gain.track_peak,
+        }
+    }
+
+    /// 获取当前曲目实际应用的 ReplayGain 增益，没有打开文件时返回 None
+    fn get_applied_replay_gain(&self) -> Option<EffectiveGain> {
+        self.applied_replay_gain
+    }
+
+    /// 在后台线程中测量播放队列中没有增益标签的文件的响度，之前的分析会被取消
+    ///
+    /// 每个文件完成时发出 LoudnessAnalyzed 事件。测量结果按路径和修改时间缓存，
+    /// 开启 ReplayGain 后和曲目增益标签一样使用，正在播放的曲目在分析完成后重新打开时才使用测量结果
+    fn analyze_queue_loudness(&mut self) -> Result<(), Error> {
+        ensure!(!self.queue.is_empty(), QueueSnafu {
+            message: "the play queue is empty".to_string(),
+        });
+        self.cancel_loudness_analysis();
+        let targets = self.queue.entries().iter()
+            .map(|path| LoudnessTarget { path: path.clone(), uri: self.rewrite_path(path) })
+            .collect();
+        self.loudness_analysis = Some(LoudnessAnalysis::start(
+            targets,
+            self.input_options.clone(),
+            self.metadata_extractors.clone(),
+            self.loudness_cache.clone(),
+            self.events.clone()));
+        Ok(())
+    }
+
+    /// 取消正在进行的响度分析，已经完成的结果仍然会保存
+    fn cancel_loudness_analysis(&mut self) {
+        if let Some(analysis) = self.loudness_analysis.take() {
+            analysis.cancel();
+        }
+    }
+
+    /// 改为使用指定的响度缓存文件，文件存在但无法读取时返回错误
+    fn set_loudness_cache_path(&mut self, path: PathBuf) -> Result<(), Error> {
+        self.loudness_cache.lock().unwrap().set_path(path).context(LoudnessCacheSnafu)?;
+        self.update_replay_gain();
+        Ok(())
+    }
+
+    fn get_loudness_cache_path(&self) -> PathBuf {
+        self.loudness_cache.lock().unwrap().get_path().to_path_buf()
+    }
+
+    /// 订阅播放器事件，事件在后台线程中发出，接收端需要自行轮询
+    fn subscribe_events(&self) -> Receiver<PlayerEvent> {
+        self.events.subscribe()
+    }
+
+    fn get_playback_stats(&self) -> PlaybackStats {
+        self.playback_tracker.get_stats()
+    }
+
+    /// 当前文件的读取和解码统计，用于诊断播放卡顿，没有打开文件时全部为 0
+    fn get_debug_stats(&self) -> DebugStats {
+        match self.active_source() {
+            Some(source) => source.get_debug_stats(),
+            None => DebugStats::default(),
+        }
+    }
+
+    /// 设置提交播放记录的条件，ratio 的范围是 (0, 1]，max_ms 必须大于 0
+    fn set_scrobble_threshold(&mut self, threshold: ScrobbleThreshold) -> Result<(), Error> {
+        ensure!(threshold.ratio > 0. && threshold.ratio <= 1., InvalidArgumentSnafu {
+            message: format!("the scrobble ratio must be in (0, 1], got {}", threshold.ratio),
+        });
+        ensure!(threshold.max_ms > 0, InvalidArgumentSnafu {
+            message: format!("the scrobble duration must be positive, got {} ms", threshold.max_ms),
+        });
+        self.playback_tracker.set_threshold(threshold);
+        Ok(())
+    }
+
+    fn get_scrobble_threshold(&self) -> ScrobbleThreshold {
+        self.playback_tracker.get_threshold()
+    }
+
+    /// 当前曲目输出时因为音量、均衡器等增益超出最大幅度而被截断的样本数，打开新的曲目时清零
+    fn get_clip_count(&self) -> u64 {
+        self.device.as_ref().map_or(0, |device| device.clip_counter().get())
+    }
+
+    /// 设置是否在发生截断时发出 Clipping 事件，默认关闭
+    fn set_clipping_events(&mut self, enabled: bool) {
+        self.playback_tracker.set_clipping_events(enabled);
+    }
+
+    /// 诊断用：按顺序列出当前启用的处理步骤，不需要转换时为空
+    fn get_active_processing(&self) -> Vec<&'static str> {
+        let mut processing = match self.active_source() {
+            Some(source) => source.get_active_processing(),
+            None => Vec::new(),
+        };
+        if let Some(device) = self.device.as_ref() {
+            processing.extend(device.get_active_processing());
+        }
+        processing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::audio::test_media;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// 使用不连接音频设备的输出，由 render_until 代替输出回调取出数据
+    fn null_player() -> YakoPlayer {
+        let mut player = YakoPlayer::new();
+        player.device = Some(AudioDevice::null(SAMPLE_RATE, 2));
+        player
+    }
+
+    /// 按实际的速度每 10 毫秒取出 10 毫秒的数据，直到 done 返回 true，返回取出的所有数据的第一个声道
+    fn render_until<F: FnMut(&YakoPlayer, &[f32]) -> bool>(player: &YakoPlayer, mut done: F) -> Vec<f32> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut output = Vec::new();
+        while !done(player, &output) {
+            assert!(Instant::now() < deadline, "timed out while rendering");
+            let block = player.device.as_ref().unwrap().render(SAMPLE_RATE as usize / 100);
+            output.extend(block.iter().map(AudioSample::ch1));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        output
+    }
+
+    /// 在临时目录中创建两个两秒长、样本值分别为 0.25 和 0.5 的文件
+    fn two_tracks(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = test_media::temp_dir(name);
+        let first = dir.join("first.wav");
+        let second = dir.join("second.wav");
+        test_media::write_constant_wav(&first, SAMPLE_RATE, SAMPLE_RATE as usize * 2, 0.25);
+        test_media::write_constant_wav(&second, SAMPLE_RATE, SAMPLE_RATE as usize * 2, 0.5);
+        (dir, first, second)
+    }
+
+    #[test]
+    fn final_samples_are_heard_before_the_end_event() {
+        let dir = test_media::temp_dir("end-tail");
+        let path = dir.join("tail.wav");
+        // 半秒的 0.25 之后是 480 帧的 0.5，只有完整地播放到最后才能听到全部的 0.5
+        let mut samples = vec![0.25; SAMPLE_RATE as usize];
+        samples.extend_from_slice(&[0.5; 960]);
+        test_media::write_wav(&path, SAMPLE_RATE, 2, &samples);
+
+        let mut player = null_player();
+        let events = player.subscribe_events();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        let mut ended = false;
+        let output = render_until(&player, |_, _| {
+            ended = ended || events.try_iter().any(|event| event == PlayerEvent::EndOfStream);
+            ended
+        });
+        assert_eq!(output.iter().filter(|sample| **sample == 0.5).count(), 480);
+        assert_eq!(player.get_current_time(), 0);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn waiting_for_a_short_file_returns_true_after_it_has_played() {
+        let dir = test_media::temp_dir("wait-short");
+        let path = dir.join("short.wav");
+        test_media::write_constant_wav(&path, SAMPLE_RATE, SAMPLE_RATE as usize / 10, 0.25);
+
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        // 等待的线程只持有结束通知，不持有播放器
+        let end_signal = player.end_signal().unwrap();
+        let waiter = std::thread::spawn(move || end_signal.wait(Some(Duration::from_secs(10))));
+        player.play().unwrap();
+        let output = render_until(&player, |_, _| waiter.is_finished());
+        assert!(waiter.join().unwrap());
+        assert_eq!(output.iter().filter(|sample| **sample == 0.25).count(), SAMPLE_RATE as usize / 10);
+        // 已经播放完毕时立即返回
+        assert!(player.wait_for_end(Some(Duration::ZERO)));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn closing_interrupts_a_waiting_thread() {
+        let (dir, first, _) = two_tracks("wait-close");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        let end_signal = player.end_signal().unwrap();
+        let waiter = std::thread::spawn(move || end_signal.wait(None));
+        player.close().unwrap();
+        assert!(!waiter.join().unwrap());
+        assert!(player.end_signal().is_none());
+        assert!(!player.wait_for_end(None));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 没有打开文件时获取方法返回的值，关闭之后也应当完全相同
+    fn assert_no_file(player: &YakoPlayer) {
+        assert_eq!(player.get_duration(), -1);
+        assert_eq!(player.get_current_time(), 0);
+        assert_eq!(player.get_current_time_us(), 0);
+        assert_eq!(player.get_bitrate(), 0);
+        assert_eq!(player.get_current_bitrate(), 0);
+        assert!(!player.is_playing());
+        assert!(!player.is_seekable());
+        assert!(player.get_media_info().is_none());
+        assert!(player.end_signal().is_none());
+        assert!(matches!(player.try_get_duration(), Err(Error::Unavailable { .. })));
+        assert!(matches!(player.try_get_current_time(), Err(Error::Unavailable { .. })));
+        assert!(matches!(player.try_get_bitrate(), Err(Error::Unavailable { .. })));
+        let status = player.get_status();
+        assert_eq!((status.current_time, status.duration, status.bitrate), (0, -1, 0));
+        assert!(!status.is_playing && !status.is_seekable);
+    }
+
+    #[test]
+    fn getters_report_no_file_after_closing_a_playing_track() {
+        let (dir, first, _) = two_tracks("close-getters");
+        let mut player = null_player();
+        assert_no_file(&player);
+
+        player.open(&first).unwrap();
+        assert_eq!(player.get_duration(), 2000);
+        assert_eq!(player.get_current_time(), 0);
+        assert!(player.get_media_info().is_some());
+        assert!(!player.is_playing());
+
+        player.play().unwrap();
+        assert!(player.is_playing());
+        render_until(&player, |player, _| player.get_current_time() >= 200);
+
+        player.close().unwrap();
+        assert_no_file(&player);
+        // 关闭之后没有可以重新打开的文件
+        assert!(matches!(player.reopen_current(), Err(Error::InvalidArgument { .. })));
+        // 再次关闭没有影响
+        player.close().unwrap();
+        assert_no_file(&player);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_closed_player_opens_the_next_file_from_the_start() {
+        let (dir, first, second) = two_tracks("close-reopen");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        player.close().unwrap();
+        assert_no_file(&player);
+
+        player.open(&second).unwrap();
+        assert_eq!(player.get_duration(), 2000);
+        assert_eq!(player.get_current_time(), 0);
+        assert!(!player.is_playing());
+        player.play().unwrap();
+        // 输出的是新文件的数据，没有残留上一个文件的数据
+        let output = render_until(&player, |_, output| output.len() >= SAMPLE_RATE as usize / 10);
+        assert!(output.iter().all(|sample| *sample == 0.5 || *sample == 0.), "{:?}", &output[..10]);
+        assert!(output.contains(&0.5));
+
+        player.close().unwrap();
+        assert_no_file(&player);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reset_clears_playback_state_and_keeps_the_device() {
+        let (dir, first, second) = two_tracks("reset");
+        let mut player = null_player();
+        player.get_queue_mut().set_entries(vec![first.clone(), second.clone()]);
+        player.set_volume(0.5).unwrap();
+        player.set_speed(1.5).unwrap();
+        player.set_eq_enabled(true);
+        player.set_eq_band_gain(0, 6.).unwrap();
+        player.set_channel_delays(&[10, 0]).unwrap();
+        player.set_master_limiter(true, -1.).unwrap();
+        player.set_replay_gain_mode(ReplayGainMode::Album);
+        player.set_sleep_timer(SleepTimer::AfterCurrentTrack);
+        player.set_open_mode(OpenMode::Enqueue);
+        player.set_finished_action(FinishedAction::LoopTrack);
+        player.set_seamless_loop(true);
+        player.set_error_resilience(ErrorResilience::Abort);
+        player.set_lazy_cover(true);
+        player.set_analysis_enabled(true);
+        player.open(&first).unwrap();
+        player.play().unwrap();
+        render_until(&player, |player, _| player.get_current_time() >= 100);
+
+        player.reset(ResetOptions::new()).unwrap();
+        assert_no_file(&player);
+        let defaults = YakoPlayer::new();
+        assert!(player.get_queue().is_empty());
+        assert_eq!(player.get_speed(), defaults.get_speed());
+        assert_eq!(player.is_eq_enabled(), defaults.is_eq_enabled());
+        assert_eq!(player.get_eq_band_gains(), defaults.get_eq_band_gains());
+        assert_eq!(player.get_channel_delays(), defaults.get_channel_delays());
+        assert_eq!(player.get_master_limiter(), defaults.get_master_limiter());
+        assert_eq!(player.get_replay_gain_mode(), defaults.get_replay_gain_mode());
+        assert_eq!(player.get_sleep_timer(), defaults.get_sleep_timer());
+        assert_eq!(player.get_open_mode(), defaults.get_open_mode());
+        assert_eq!(player.get_finished_action(), defaults.get_finished_action());
+        assert_eq!(player.is_seamless_loop(), defaults.is_seamless_loop());
+        assert_eq!(player.get_error_resilience(), defaults.get_error_resilience());
+        assert_eq!(player.is_lazy_cover(), defaults.is_lazy_cover());
+        assert_eq!(player.is_analysis_enabled(), defaults.is_analysis_enabled());
+        // 默认保留音量
+        assert_eq!(player.get_volume(), 0.5);
+
+        // 设备仍然打开，不需要重新初始化就可以播放，输出缓冲区中没有上一个文件的数据
+        assert!(player.device.is_some());
+        player.set_volume(1.).unwrap();
+        player.open(&second).unwrap();
+        player.play().unwrap();
+        let output = render_until(&player, |_, output| output.len() >= SAMPLE_RATE as usize / 10);
+        assert!(output.iter().all(|sample| *sample == 0.5 || *sample == 0.));
+        assert!(output.contains(&0.5));
+
+        player.set_volume(0.5).unwrap();
+        player.reset(ResetOptions::new().keep_volume(false)).unwrap();
+        assert_eq!(player.get_volume(), 1.);
+        assert!(player.device.is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 新增字段时这里无法编译，提醒在 reset 中决定它恢复默认值还是保留
+    #[test]
+    fn reset_decides_every_field() {
+        let mut player = null_player();
+        player.reset(ResetOptions::new().keep_volume(false).keep_device_selection(false)).unwrap();
+        let defaults = YakoPlayer::new();
+        let YakoPlayer {
+            // 音频设备总是保持打开
+            device,
+            // 由 close 释放或者清除
+            source,
+            fading_source,
+            deck_source,
+            next_source,
+            pending_open,
+            current_path,
+            current_stamp,
+            auditioning,
+            applied_replay_gain,
+            // 恢复默认值
+            volume,
+            mute,
+            volume_curve: _,
+            preferred_host,
+            preferred_device,
+            output_channel_map,
+            queue,
+            pending_dsp_processor,
+            analysis_enabled,
+            analysis_update_rate_hz,
+            decode_chunk_size,
+            open_mode,
+            deck_volumes,
+            speed,
+            sleep_timer,
+            equalizer,
+            channel_delays,
+            master_limiter,
+            processing_order,
+            impulse_response,
+            convolution_enabled,
+            resume_info,
+            replay_gain,
+            album_tags,
+            loudness_analysis,
+            end_behavior,
+            finished_action,
+            seamless_loop,
+            seek_debounce_ms,
+            error_resilience,
+            capture,
+            capture_split_on_track,
+            capture_pre_volume,
+            rebuffer_threshold_ms,
+            target_buffer_ms,
+            prebuffer_ms,
+            export_sample_format,
+            position_smoother: _,
+            lazy_device,
+            lazy_cover,
+            // 保留：事件订阅、书签和提示点、响度缓存、元数据读取器、路径改写函数和输入选项，
+            // 播放统计重新开始但保留阈值
+            events: _,
+            playback_tracker: _,
+            bookmarks: _,
+            cue_points: _,
+            cue_points_path: _,
+            loudness_cache: _,
+            metadata_extractors: _,
+            path_rewriter: _,
+            input_options: _,
+        } = &player;
+        assert!(device.is_some());
+        assert!(source.is_none() && fading_source.is_none() && deck_source.is_none() && next_source.is_none());
+        assert!(pending_open.lock().unwrap().is_none());
+        assert!(current_path.is_none() && current_stamp.is_none() && applied_replay_gain.is_none());
+        assert!(!auditioning);
+        assert_eq!((*volume, mute.load(Ordering::Relaxed)), (defaults.volume, defaults.mute.load(Ordering::Relaxed)));
+        assert_eq!((preferred_host, preferred_device), (&defaults.preferred_host, &defaults.preferred_device));
+        assert_eq!(output_channel_map, &defaults.output_channel_map);
+        assert!(queue.is_empty());
+        assert!(pending_dsp_processor.is_none());
+        assert_eq!((*analysis_enabled, *analysis_update_rate_hz), (defaults.analysis_enabled, defaults.analysis_update_rate_hz));
+        assert_eq!(decode_chunk_size, &defaults.decode_chunk_size);
+        assert_eq!(open_mode, &defaults.open_mode);
+        assert_eq!((deck_volumes, speed), (&defaults.deck_volumes, &defaults.speed));
+        assert_eq!(sleep_timer.get_timer(), defaults.sleep_timer.get_timer());
+        assert_eq!(equalizer.is_enabled(), defaults.equalizer.is_enabled());
+        assert_eq!(equalizer.band_gains(), defaults.equalizer.band_gains());
+        assert_eq!((channel_delays, master_limiter), (&defaults.channel_delays, &defaults.master_limiter));
+        assert_eq!(processing_order, &defaults.processing_order);
+        assert!(impulse_response.is_none() && !convolution_enabled);
+        assert!(resume_info.is_none() && album_tags.is_empty() && loudness_analysis.is_none());
+        assert_eq!(replay_gain, &defaults.replay_gain);
+        assert_eq!((end_behavior, finished_action), (&defaults.end_behavior, &defaults.finished_action));
+        assert_eq!((seamless_loop, seek_debounce_ms), (&defaults.seamless_loop, &defaults.seek_debounce_ms));
+        assert_eq!(error_resilience, &defaults.error_resilience);
+        assert!(capture.is_none());
+        assert_eq!((capture_split_on_track, capture_pre_volume), (&defaults.capture_split_on_track, &defaults.capture_pre_volume));
+        assert_eq!((rebuffer_threshold_ms, target_buffer_ms, prebuffer_ms), (&defaults.rebuffer_threshold_ms, &defaults.target_buffer_ms, &defaults.prebuffer_ms));
+        assert_eq!(export_sample_format, &defaults.export_sample_format);
+        assert_eq!((lazy_device, lazy_cover), (&defaults.lazy_device, &defaults.lazy_cover));
+    }
+
+    /// 在单独的线程中打开文件，返回错误信息，10 秒内没有返回时测试失败
+    fn open_within_timeout(path: PathBuf) -> Result<(), String> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut player = null_player();
+            let result = player.open(&path).map_err(|err| err.to_string());
+            let _ = player.close();
+            let _ = sender.send(result);
+        });
+        receiver.recv_timeout(Duration::from_secs(10)).expect("opening the file did not return")
+    }
+
+    #[test]
+    fn an_empty_file_is_rejected_without_hanging() {
+        let dir = test_media::temp_dir("empty-file");
+        let path = dir.join("empty.wav");
+        std::fs::write(&path, []).unwrap();
+        let err = open_within_timeout(path).unwrap_err();
+        assert!(err.contains("empty"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_file_without_samples_is_rejected_without_hanging() {
+        let dir = test_media::temp_dir("no-samples");
+        // 只有 WAV 文件头，没有任何音频数据
+        let path = dir.join("header-only.wav");
+        test_media::write_wav(&path, SAMPLE_RATE, 2, &[]);
+        assert!(open_within_timeout(path).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flac_extradata_is_the_stream_info_block() {
+        let dir = test_media::temp_dir("flac-extradata");
+        let path = dir.join("tone.flac");
+        test_media::write_flac(&path, 44100, 2, &vec![0x2000; 44100], None);
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        // STREAMINFO 块在 "fLaC" 和 4 字节的元数据块头之后
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(player.get_codec_extradata().as_deref(), Some(&file[8..42]));
+
+        player.close().unwrap();
+        assert_eq!(player.get_codec_extradata(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_video_without_audio_is_reported_as_no_audio_stream() {
+        let dir = test_media::temp_dir("no-audio-stream");
+        let path = dir.join("video.y4m");
+        test_media::write_y4m(&path, 25);
+        let mut player = null_player();
+        assert!(player.open(&path).unwrap_err().is_no_audio_stream());
+        assert_no_file(&player);
+        // 文件不存在是另一种错误
+        assert!(!player.open(&dir.join("missing.y4m")).unwrap_err().is_no_audio_stream());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lazy_open_reads_the_media_info_without_a_device() {
+        let (dir, first, _) = two_tracks("lazy-device");
+        let mut player = YakoPlayer::new();
+        assert!(player.is_lazy_device());
+        player.open(&first).unwrap();
+        assert!(player.device.is_none());
+        assert!(player.source.is_none());
+        assert_eq!(player.get_duration(), 2000);
+        assert!(player.get_media_info().is_some());
+        assert!(player.is_seekable());
+        assert!(!player.is_playing());
+
+        // 播放之前定位只记录开始的位置
+        player.seek(1000).unwrap();
+        assert_eq!(player.get_current_time(), 1000);
+        assert!(player.source.is_none());
+
+        // 和 play 一样先打开设备再打开文件，从记录的位置开始播放
+        player.device = Some(AudioDevice::null(SAMPLE_RATE, 2));
+        let pending = player.pending_open.lock().unwrap().take().unwrap();
+        player.open_pending(pending).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+        assert!(player.get_current_time() >= 1000, "started at {}", player.get_current_time());
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lazy_device_is_not_used_once_the_device_is_open() {
+        let (dir, first, _) = two_tracks("lazy-device-open");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        assert!(player.source.is_some());
+        assert!(player.pending_open.lock().unwrap().is_none());
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 写入 2 秒的立体声文件，样本的值是所在的位置占总长度的比例
+    fn write_ramp(dir: &Path) -> PathBuf {
+        let path = dir.join("ramp.wav");
+        let frames = SAMPLE_RATE as usize * 2;
+        let samples: Vec<f32> = (0..frames).flat_map(|frame| [frame as f32 / frames as f32; 2]).collect();
+        test_media::write_wav(&path, SAMPLE_RATE, 2, &samples);
+        path
+    }
+
+    #[test]
+    fn rapid_seeks_are_coalesced_into_one_seek_to_the_last_target() {
+        let dir = test_media::temp_dir("rapid-seeks");
+        let path = write_ramp(&dir);
+        let mut player = null_player();
+        player.set_seek_debounce_ms(500);
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| !output.is_empty());
+
+        // 第一次定位立即执行，之后防抖时间内的定位只执行最后一个
+        player.seek(100).unwrap();
+        render_until(&player, |player, _| player.get_debug_stats().seeks == 1);
+        for target in 0..100 {
+            player.seek(target * 10).unwrap();
+        }
+        render_until(&player, |player, _| player.get_debug_stats().seeks == 2);
+        let output = render_until(&player, |_, output| output.iter().any(|sample| *sample > 0.));
+        let first = output.iter().find(|sample| **sample > 0.).unwrap();
+        assert!((0.49..0.5).contains(first), "playback resumed at {}", first);
+        assert_eq!(player.get_debug_stats().seeks, 2);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn current_time_never_goes_back_and_follows_the_rendered_samples() {
+        let dir = test_media::temp_dir("smooth-position");
+        let path = write_ramp(&dir);
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| output.iter().any(|sample| *sample > 0.));
+
+        // 样本值是在文件中的位置，最后取出的样本就是真实的播放位置
+        let mut last = 0;
+        render_until(&player, |player, output| {
+            let position = player.get_current_time_us();
+            assert!(position >= last, "went back from {} to {}", last, position);
+            let actual = (*output.last().unwrap_or(&0.) as f64 * 2_000_000.) as i64;
+            if !output.is_empty() {
+                assert!((position - actual).abs() <= 20_000, "{} is too far from {}", position, actual);
+            }
+            last = position;
+            output.len() >= SAMPLE_RATE as usize
+        });
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 写入 1 秒的 FLAC 文件，带有 8 MB 的 PNG 封面，返回封面的数据
+    fn write_flac_with_large_cover(path: &Path) -> Vec<u8> {
+        let mut cover = b"\x89PNG\r\n\x1a\n".to_vec();
+        cover.resize(8 << 20, 0x5A);
+        test_media::write_flac(path, SAMPLE_RATE, 2, &vec![0x2000; SAMPLE_RATE as usize * 2], Some(("image/png", &cover)));
+        cover
+    }
+
+    #[test]
+    fn lazy_cover_is_published_after_open_returns() {
+        let dir = test_media::temp_dir("lazy-cover");
+        let path = dir.join("cover.flac");
+        let cover = write_flac_with_large_cover(&path);
+        let mut player = null_player();
+        player.set_lazy_cover(true);
+        let events = player.subscribe_events();
+        player.open(&path).unwrap();
+
+        // 打开时发出的封面事件还没有封面，之后解码线程读取完成再发出 CoverReady
+        let opened = events.try_iter()
+            .find_map(|event| match event {
+                PlayerEvent::ArtworkChanged { cover, .. } => Some(cover),
+                _ => None,
+            })
+            .expect("no artwork event during open");
+        assert!(opened.is_none());
+        let ready = loop {
+            match events.recv_timeout(Duration::from_secs(5)).expect("the cover was not published") {
+                PlayerEvent::CoverReady(ready) => break ready,
+                _ => continue,
+            }
+        };
+        assert_eq!(&ready[..], &cover[..]);
+        assert_eq!(player.get_media_info().unwrap().cover.as_deref(), Some(&cover[..]));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cover_is_read_during_open_by_default() {
+        let dir = test_media::temp_dir("eager-cover");
+        let path = dir.join("cover.flac");
+        let cover = write_flac_with_large_cover(&path);
+        let mut player = null_player();
+        assert!(!player.is_lazy_cover());
+        player.open(&path).unwrap();
+        assert_eq!(player.get_media_info().unwrap().cover.as_deref(), Some(&cover[..]));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn frames_are_discarded_once_per_seek() {
+        let dir = test_media::temp_dir("discard-once");
+        let path = write_ramp(&dir);
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| !output.is_empty());
+
+        // 每次定位时输出缓冲区已满，解码线程正在等待写入，丢弃只记录一次而不是按数据块记录
+        for (seeks, target) in [(1, 1500), (2, 200), (3, 900)] {
+            player.seek(target).unwrap();
+            render_until(&player, |player, _| {
+                let stats = player.get_debug_stats();
+                stats.seeks == seeks && stats.buffer_discards == seeks
+            });
+            render_until(&player, |_, output| output.len() > SAMPLE_RATE as usize / 10);
+        }
+        assert_eq!(player.get_debug_stats().buffer_discards, 3);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_new_output_rate_is_used_from_the_heard_position() {
+        let dir = test_media::temp_dir("output-rate");
+        let path = write_ramp(&dir);
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        let before = render_until(&player, |_, output| output.len() > SAMPLE_RATE as usize / 4);
+        let heard = *before.last().unwrap();
+
+        // 设备换成一半的采样率，解码线程从听到的位置按新的采样率重新输出
+        let device_format = player.device.as_ref().unwrap().sample_format.unwrap();
+        player.source.as_ref().unwrap().set_output_format(DeviceSampleFormat { sample_rate: SAMPLE_RATE / 2, ..device_format });
+        render_until(&player, |player, _| player.get_debug_stats().seeks == 1);
+        let output = render_until(&player, |_, output| output.iter().filter(|sample| **sample > 0.).count() > 1000);
+        let start = output.iter().position(|sample| *sample > 0.).unwrap();
+        assert!((output[start] - heard).abs() < 0.05, "resumed at {} after hearing {}", output[start], heard);
+        // 每个输出的样本对应文件中的两个样本
+        let step = (output[start + 1000] - output[start]) / 1000.;
+        let expected = 2. / (SAMPLE_RATE * 2) as f32;
+        assert!((step - expected).abs() < expected * 0.05, "step {} instead of {}", step, expected);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn both_decks_play_together_with_their_own_volumes() {
+        let (dir, first, second) = two_tracks("two-decks");
+        let mut player = null_player();
+        player.set_volume(1.).unwrap();
+        player.open_deck(MAIN_DECK, &first).unwrap();
+        player.open_deck(SECOND_DECK, &second).unwrap();
+        player.set_deck_volume(MAIN_DECK, 1.).unwrap();
+        player.set_deck_volume(SECOND_DECK, 0.5).unwrap();
+        player.play_deck(MAIN_DECK).unwrap();
+        player.play_deck(SECOND_DECK).unwrap();
+
+        let second_gain = crate::audio::volume::db_gain_to_amplitude(player.get_volume_curve().to_db(0.5));
+        let mixed = 0.25 + 0.5 * second_gain;
+        let near = |sample: f32, expected: f32| (sample - expected).abs() < 1e-6;
+        let output = render_until(&player, |_, output| output.iter().filter(|sample| near(**sample, mixed)).count() > 4800);
+        // 两路都有数据之后一直是两路按各自音量相加的结果
+        let start = output.iter().position(|sample| near(*sample, mixed)).unwrap();
+        assert!(output[start..].iter().all(|sample| near(*sample, mixed)));
+
+        // 暂停第一个 deck 之后只剩第二个 deck
+        player.pause_deck(MAIN_DECK).unwrap();
+        render_until(&player, |_, output| output.iter().filter(|sample| near(**sample, 0.5 * second_gain)).count() > 480);
+
+        player.close_deck(SECOND_DECK).unwrap();
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 取出到目前为止的最后一个 ArtworkChanged 事件
+    fn last_artwork(events: &Receiver<PlayerEvent>) -> Option<(u64, Option<Arc<[u8]>>)> {
+        events.try_iter()
+            .filter_map(|event| match event {
+                PlayerEvent::ArtworkChanged { generation, cover } => Some((generation, cover)),
+                _ => None,
+            })
+            .last()
+    }
+
+    #[test]
+    fn artwork_events_follow_the_cover_generation() {
+        let dir = test_media::temp_dir("artwork-events");
+        let first = dir.join("first.flac");
+        let second = dir.join("second.flac");
+        test_media::write_flac(&first, SAMPLE_RATE, 2, &vec![0x2000; SAMPLE_RATE as usize], Some(("image/png", b"first")));
+        test_media::write_flac(&second, SAMPLE_RATE, 2, &vec![0x2000; SAMPLE_RATE as usize], Some(("image/png", b"second")));
+        let mut player = null_player();
+        let events = player.subscribe_events();
+
+        player.open(&first).unwrap();
+        let (first_generation, cover) = last_artwork(&events).expect("no artwork event for the first track");
+        assert_eq!(cover.as_deref(), Some(&b"first"[..]));
+        assert_eq!(player.get_media_info().unwrap().cover_generation, first_generation);
+
+        // 新的曲目有更大的版本，没有再发出事件时版本不变
+        player.open(&second).unwrap();
+        let (second_generation, cover) = last_artwork(&events).expect("no artwork event for the second track");
+        assert_eq!(cover.as_deref(), Some(&b"second"[..]));
+        assert!(second_generation > first_generation);
+        assert_eq!(player.get_media_info().unwrap().cover_generation, second_generation);
+        assert!(last_artwork(&events).is_none());
+
+        player.close().unwrap();
+        assert_eq!(last_artwork(&events), Some((0, None)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cue_points_can_be_added_listed_jumped_to_and_removed() {
+        let dir = test_media::temp_dir("cue-points");
+        let path = write_ramp(&dir);
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+        render_until(&player, |player, _| player.get_current_time() >= 500);
+        let verse = player.add_cue_point("verse").unwrap();
+        let position_ms = player.list_cue_points()[0].position_ms;
+        assert!((500..1000).contains(&position_ms), "added at {}", position_ms);
+        render_until(&player, |player, _| player.get_current_time() >= 1500);
+        let chorus = player.add_cue_point("chorus").unwrap();
+        assert_ne!(verse, chorus);
+        let names: Vec<String> = player.list_cue_points().into_iter().map(|point| point.name).collect();
+        assert_eq!(names, ["verse", "chorus"]);
+
+        // 跳转之后从标记点的位置继续播放
+        player.jump_to_cue(verse).unwrap();
+        render_until(&player, |player, _| player.get_debug_stats().seeks == 1);
+        let output = render_until(&player, |_, output| output.iter().any(|sample| *sample > 0.));
+        let resumed = output.iter().find(|sample| **sample > 0.).unwrap();
+        assert!((resumed - position_ms as f32 / 2000.).abs() < 0.01, "resumed at {}", resumed);
+
+        assert!(player.remove_cue_point(verse));
+        assert!(!player.remove_cue_point(verse));
+        assert!(matches!(player.jump_to_cue(verse), Err(Error::InvalidArgument { .. })));
+        assert_eq!(player.list_cue_points().len(), 1);
+
+        player.close().unwrap();
+        assert!(player.list_cue_points().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cue_points_are_saved_with_the_bookmarks() {
+        let dir = test_media::temp_dir("cue-persist");
+        let path = write_ramp(&dir);
+        let bookmarks = dir.join("bookmarks.json");
+        let mut player = null_player();
+        player.set_bookmarks_path(bookmarks.clone()).unwrap();
+        player.set_bookmarks_enabled(true);
+        player.open(&path).unwrap();
+        player.add_cue_point("start").unwrap();
+        let saved = player.list_cue_points();
+        player.close().unwrap();
+        drop(player);
+
+        // 另一个播放器打开同一个文件时恢复标记点，新的编号不和保存的重复
+        let mut player = null_player();
+        player.set_bookmarks_path(bookmarks).unwrap();
+        player.set_bookmarks_enabled(true);
+        player.open(&path).unwrap();
+        assert_eq!(player.list_cue_points(), saved);
+        let next = player.add_cue_point("next").unwrap();
+        assert!(next > saved[0].id);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flac_is_lossless_and_mp3_is_not() {
+        let dir = test_media::temp_dir("lossless");
+        let flac = dir.join("tone.flac");
+        let mp3 = dir.join("silence.mp3");
+        test_media::write_flac(&flac, 44100, 2, &vec![0x2000; 44100], None);
+        test_media::write_silent_mp3(&mp3, 40);
+        let mut player = null_player();
+        assert_eq!(player.is_lossless(), None);
+        player.open(&flac).unwrap();
+        assert_eq!(player.is_lossless(), Some(true));
+        player.open(&mp3).unwrap();
+        assert_eq!(player.is_lossless(), Some(false));
+
+        player.close().unwrap();
+        assert_eq!(player.is_lossless(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_audio_of_a_video_file_plays_without_reading_the_video() {
+        let dir = test_media::temp_dir("video-file");
+        let path = dir.join("movie.avi");
+        // 1 秒，25 帧视频和 25 个音频数据块交错排列
+        test_media::write_avi(&path, SAMPLE_RATE, 25, Some(0.25));
+        let mut player = null_player();
+        let events = player.subscribe_events();
+        player.open(&path).unwrap();
+        assert!(player.get_media_info().unwrap().has_video);
+        player.play().unwrap();
+
+        let mut ended = false;
+        let output = render_until(&player, |_, _| {
+            ended = ended || events.try_iter().any(|event| event == PlayerEvent::EndOfStream);
+            ended
+        });
+        assert_eq!(output.iter().filter(|sample| **sample == 0.25).count(), SAMPLE_RATE as usize);
+        // 视频流在解封装时就被丢弃，读取的数据包不超过音频数据块的数量
+        let packets_read = player.get_debug_stats().packets_read;
+        assert!(packets_read <= 25, "read {} packets", packets_read);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cover_art_is_not_counted_as_video() {
+        let dir = test_media::temp_dir("cover-not-video");
+        let path = dir.join("cover.flac");
+        test_media::write_flac(&path, SAMPLE_RATE, 2, &vec![0x2000; SAMPLE_RATE as usize], Some(("image/png", b"\x89PNG\r\n\x1a\n")));
+        let mut player = null_player();
+        player.open(&path).unwrap();
+        let media_info = player.get_media_info().unwrap();
+        assert!(!media_info.has_video);
+        assert!(media_info.cover.is_some());
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn decode_chunk_size_is_bounded_by_the_dynamic_buffer() {
+        let (dir, first, _) = two_tracks("chunk-size");
+        let mut player = null_player();
+        assert!(matches!(player.set_decode_chunk_size(0), Err(Error::InvalidArgument { .. })));
+        // 打开文件之前按设备的格式计算缓冲区大小，默认是一半
+        let buffer_size = player.get_dynamic_buffer_size();
+        assert!(buffer_size > 0);
+        assert_eq!(player.get_decode_chunk_size(), buffer_size / 2);
+        assert!(matches!(player.set_decode_chunk_size(buffer_size + 1), Err(Error::InvalidArgument { .. })));
+        player.set_decode_chunk_size(buffer_size).unwrap();
+        assert_eq!(player.get_decode_chunk_size(), buffer_size);
+
+        // 打开文件时沿用设置的值，被拒绝的值不改变当前的值
+        player.open(&first).unwrap();
+        assert_eq!(player.get_dynamic_buffer_size(), buffer_size);
+        assert_eq!(player.get_decode_chunk_size(), buffer_size);
+        player.set_decode_chunk_size(1).unwrap();
+        assert!(matches!(player.set_decode_chunk_size(buffer_size + 1), Err(Error::InvalidArgument { .. })));
+        assert_eq!(player.get_decode_chunk_size(), 1);
+
+        // 最小的块也能正常播放
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replace_mode_switches_to_the_new_track_immediately() {
+        let (dir, first, second) = two_tracks("open-replace");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+
+        player.open(&second).unwrap();
+        player.play().unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(second.as_path()));
+        assert!(player.fading_source.is_none());
+        assert!(player.queue.entries().is_empty());
+        // 第一个文件留在输出缓冲区中的数据也被丢弃
+        let output = render_until(&player, |_, output| output.contains(&0.5));
+        assert!(!output.contains(&0.25));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn enqueue_mode_keeps_playing_the_current_track() {
+        let (dir, first, second) = two_tracks("open-enqueue");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+
+        player.set_open_mode(OpenMode::Enqueue);
+        player.open(&second).unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(first.as_path()));
+        assert_eq!(player.queue.entries(), &[second.clone()]);
+        let output = render_until(&player, |_, output| output.len() >= SAMPLE_RATE as usize / 4);
+        assert!(output.contains(&0.25));
+        assert!(!output.contains(&0.5));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn crossfade_mode_fades_the_current_track_into_the_new_one() {
+        let (dir, first, second) = two_tracks("open-crossfade");
+        let mut player = null_player();
+        player.open(&first).unwrap();
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+
+        player.set_open_mode(OpenMode::Crossfade(Duration::from_millis(200)));
+        player.open(&second).unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(second.as_path()));
+        // 第一个文件没有关闭，在淡化期间继续输出
+        assert!(player.fading_source.is_some());
+        assert!(player.device.as_ref().unwrap().is_crossfading());
+
+        let output = render_until(&player, |player, _| !player.device.as_ref().unwrap().is_crossfading());
+        // 淡化期间两个文件按增益相加，介于两个文件的数值之间
+        assert!(output.iter().any(|sample| 0.25 < *sample && *sample < 0.5));
+        let output = render_until(&player, |_, output| output.len() >= SAMPLE_RATE as usize / 10);
+        assert!(output.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// 用两个文件组成播放队列并从第一个开始播放
+    fn play_two_track_queue(player: &mut YakoPlayer, first: &Path, second: &Path) {
+        player.get_queue_mut().set_entries(vec![first.to_path_buf(), second.to_path_buf()]);
+        player.play_queue_entry(0).unwrap();
+    }
+
+    #[test]
+    fn stop_action_ends_after_the_first_track() {
+        let (dir, first, second) = two_tracks("finished-stop");
+        let mut player = null_player();
+        let events = player.subscribe_events();
+        player.set_finished_action(FinishedAction::Stop);
+        play_two_track_queue(&mut player, &first, &second);
+        assert!(player.next_source.is_none());
+
+        let mut ended = false;
+        let output = render_until(&player, |_, _| {
+            ended = ended || events.try_iter().any(|event| event == PlayerEvent::EndOfStream);
+            ended
+        });
+        assert!(output.contains(&0.25));
+        assert!(!output.contains(&0.5));
+        // 停止之后不再输出
+        let output = render_until(&player, |_, output| output.len() >= SAMPLE_RATE as usize / 10);
+        assert!(output.iter().all(|sample| *sample == 0.));
+        assert!(!player.is_playing());
+        assert_eq!(player.get_current_time(), 0);
+        assert_eq!(player.current_path.as_deref(), Some(first.as_path()));
+        assert_eq!(player.queue.current_index(), Some(0));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loop_track_action_repeats_the_first_track_and_reports_every_pass() {
+        let (dir, first, second) = two_tracks("finished-loop");
+        let mut player = null_player();
+        let events = player.subscribe_events();
+        player.set_finished_action(FinishedAction::LoopTrack);
+        play_two_track_queue(&mut player, &first, &second);
+        assert!(player.next_source.is_none());
+
+        let mut passes = 0;
+        let output = render_until(&player, |_, output| {
+            passes += events.try_iter().filter(|event| *event == PlayerEvent::EndOfStream).count();
+            passes >= 2 && output.len() > SAMPLE_RATE as usize * 3
+        });
+        // 超过一遍的长度之后仍然是第一个文件，没有间隙
+        assert!(output.iter().skip(SAMPLE_RATE as usize / 10).all(|sample| *sample == 0.25));
+        assert!(player.is_playing());
+        player.play().unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(first.as_path()));
+        assert_eq!(player.queue.current_index(), Some(0));
+        assert_eq!(player.get_finished_action(), FinishedAction::LoopTrack);
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_seamlessly_looped_sine_has_no_jump_at_the_wrap() {
+        let dir = test_media::temp_dir("seamless-sine");
+        let path = dir.join("sine.wav");
+        // 0.25 秒正好 250 个周期的 1 kHz 余弦，首尾都在波峰附近，有间隙或者重复时会出现大的跳变
+        let frames = SAMPLE_RATE as usize / 4;
+        let samples: Vec<f32> = (0..frames)
+            .map(|frame| 0.5 * (2. * std::f32::consts::PI * 1000. * frame as f32 / SAMPLE_RATE as f32).cos())
+            .flat_map(|sample| [sample; 2])
+            .collect();
+        test_media::write_wav(&path, SAMPLE_RATE, 2, &samples);
+        let mut player = null_player();
+        player.set_volume(1.).unwrap();
+        player.set_seamless_loop(true);
+        let events = player.subscribe_events();
+        player.open(&path).unwrap();
+        player.play().unwrap();
+
+        let mut passes = 0;
+        let output = render_until(&player, |_, output| {
+            passes += events.try_iter().filter(|event| *event == PlayerEvent::EndOfStream).count();
+            passes >= 3 && output.len() > frames * 4
+        });
+        let start = output.iter().position(|sample| *sample != 0.).unwrap();
+        assert!(output.len() - start > frames * 3);
+        // 相邻样本之间的差不超过正弦波本身的最大斜率
+        let max_step = 0.5 * 2. * std::f32::consts::PI * 1000. / SAMPLE_RATE as f32 + 1e-4;
+        for (index, pair) in output[start..].windows(2).enumerate() {
+            assert!((pair[1] - pair[0]).abs() <= max_step, "jump of {} at {}", pair[1] - pair[0], start + index);
+        }
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn advance_queue_action_plays_the_next_track_without_the_host() {
+        let (dir, first, second) = two_tracks("finished-advance");
+        let mut player = null_player();
+        let events = player.subscribe_events();
+        play_two_track_queue(&mut player, &first, &second);
+        assert_eq!(player.get_finished_action(), FinishedAction::AdvanceQueue);
+        assert_eq!(player.next_source.as_ref().map(|next| next.path.as_path()), Some(second.as_path()));
+
+        // 只取出数据，不调用播放器的任何修改方法
+        let output = render_until(&player, |_, output| output.iter().filter(|sample| **sample == 0.5).count() > 4800);
+        let start = output.iter().position(|sample| *sample == 0.25).unwrap();
+        let switch = output.iter().position(|sample| *sample == 0.5).unwrap();
+        // 两个文件首尾相接，中间没有静音
+        assert!(output[start..switch].iter().all(|sample| *sample == 0.25));
+        assert_eq!(switch - start, SAMPLE_RATE as usize * 2);
+        assert!(player.is_playing());
+        assert!(events.try_iter().any(|event| matches!(event, PlayerEvent::TrackChanged(_))));
+
+        // 下一次修改播放器时把接替的下一首设为当前曲目
+        player.play().unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(second.as_path()));
+        assert_eq!(player.queue.current_index(), Some(1));
+        assert!(player.next_source.is_none());
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn end_behavior_and_seamless_loop_do_not_change_the_finished_action() {
+        let mut player = YakoPlayer::new();
+        player.set_finished_action(FinishedAction::Stop);
+        player.set_end_behavior(None);
+        player.set_seamless_loop(true);
+        player.set_seamless_loop(false);
+        assert_eq!(player.get_finished_action(), FinishedAction::Stop);
+        player.set_finished_action(FinishedAction::LoopTrack);
+        player.set_end_behavior(Some(EndBehavior::Handover));
+        assert_eq!(player.get_finished_action(), FinishedAction::LoopTrack);
+    }
+
+    /// 无法关闭的播放源，模拟卡在网络读取中的解码线程
+    #[derive(Default)]
+    struct StuckSource {
+        end_signal: Arc<EndSignal>,
+    }
+
+    impl AudioSource for StuckSource {
+        fn close(&mut self) -> Result<(), source::Error> {
+            self.detach()
+        }
+        fn detach(&mut self) -> Result<(), source::Error> {
+            Err(source::Error::CloseMediaFile { message: "the decode thread did not exit".to_string() })
+        }
+        fn streaming(&self) -> Result<(), source::Error> {
+            Ok(())
+        }
+        fn set_prebuffer_frames(&self, _frames: Option<usize>) {}
+        fn pause(&self) -> Result<(), source::Error> {
+            Ok(())
+        }
+        fn seek(&self, _time: i64) -> Result<(), source::Error> {
+            Ok(())
+        }
+        fn clear_buffer(&self) {}
+        fn get_duration(&self) -> i64 {
+            -1
+        }
+        fn get_bitrate(&self) -> i64 {
+            0
+        }
+        fn get_current_bitrate(&self) -> i64 {
+            0
+        }
+        fn get_current_time(&self) -> i64 {
+            0
+        }
+        fn get_current_time_us(&self, _buffered_frames: usize, _sample_rate: u32) -> i64 {
+            0
+        }
+        fn set_buffer_chunk_size(&mut self, _size: usize) {}
+        fn get_buffer_chunk_size(&self) -> usize {
+            0
+        }
+        fn is_end(&self) -> bool {
+            false
+        }
+        fn get_debug_stats(&self) -> DebugStats {
+            DebugStats::default()
+        }
+        fn end_signal(&self) -> Arc<EndSignal> {
+            self.end_signal.clone()
+        }
+        fn interrupt_wait(&self) {}
+        fn is_streaming(&self) -> bool {
+            true
+        }
+        fn set_dynamic_device_buffer_size(&self, _size: usize) {}
+        fn get_dynamic_device_buffer_size(&self) -> usize {
+            0
+        }
+        fn get_media_info(&self) -> Arc<MediaInfo> {
+            Arc::new(MediaInfo::default())
+        }
+        fn is_seekable(&self) -> bool {
+            false
+        }
+        fn set_speed(&self, _speed: f32) {}
+        fn get_speed(&self) -> f32 {
+            1.
+        }
+        fn get_active_processing(&self) -> Vec<&'static str> {
+            Vec::new()
+        }
+        fn set_gain(&self, _amplitude: f32) {}
+        fn get_gain(&self) -> f32 {
+            1.
+        }
+        fn is_source_lost(&self) -> bool {
+            true
+        }
+        fn is_buffering(&self) -> bool {
+            false
+        }
+        fn set_end_behavior(&self, _behavior: EndBehavior) {}
+        fn get_end_behavior(&self) -> EndBehavior {
+            EndBehavior::default()
+        }
+        fn set_seamless_loop(&self, _enabled: bool) {}
+        fn set_successor(&self, _successor: Option<Arc<source::FFmpegSourceStatus>>) {}
+        fn is_seamless_loop(&self) -> bool {
+            false
+        }
+        fn set_output_format(&self, _format: DeviceSampleFormat) {}
+        fn set_seek_debounce_ms(&self, _debounce_ms: u32) {}
+        fn get_seek_debounce_ms(&self) -> u32 {
+            0
+        }
+        fn set_error_resilience(&self, _resilience: ErrorResilience) {}
+        fn get_error_resilience(&self) -> ErrorResilience {
+            ErrorResilience::default()
+        }
+        fn set_ab_loop(&self, _ab_loop: Option<(i64, i64)>) {}
+        fn get_ab_loop(&self) -> Option<(i64, i64)> {
+            None
+        }
+    }
+
+    #[test]
+    fn a_source_that_fails_to_close_is_replaced_by_the_next_file() {
+        let (dir, first, second) = two_tracks("close-failure");
+        let mut player = null_player();
+        player.source = Some(Box::<StuckSource>::default());
+        player.open(&first).unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(first.as_path()));
+        player.play().unwrap();
+        render_until(&player, |_, output| output.contains(&0.25));
+
+        // 交叉淡化中淡出的播放源无法关闭时也一样
+        player.fading_source = Some(Box::<StuckSource>::default());
+        player.open(&second).unwrap();
+        assert!(player.fading_source.is_none());
+        assert_eq!(player.current_path.as_deref(), Some(second.as_path()));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn closing_a_stuck_source_reports_the_error_and_still_closes() {
+        let (dir, first, _) = two_tracks("close-failure-player");
+        let mut player = null_player();
+        player.source = Some(Box::<StuckSource>::default());
+        assert!(matches!(player.close(), Err(Error::Source { .. })));
+        assert!(player.source.is_none());
+        assert!(!player.is_playing());
+        player.open(&first).unwrap();
+        assert_eq!(player.current_path.as_deref(), Some(first.as_path()));
+
+        player.close().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn graphic_eq_uses_the_ten_iso_bands() {
+        let mut player = YakoPlayer::new();
+        let gains = [1., 2., 3., 4., 5., -5., -4., -3., -2., -1.];
+        player.set_graphic_eq(10, &gains).unwrap();
+        assert_eq!(player.get_eq_band_frequencies(), equalizer::OCTAVE_BAND_FREQUENCIES.to_vec());
+        assert_eq!(player.get_eq_band_gains(), gains.to_vec());
+    }
+
+    #[test]
+    fn graphic_eq_rejects_a_wrong_number_of_gains() {
+        let mut player = YakoPlayer::new();
+        let frequencies = player.get_eq_band_frequencies();
+        assert!(matches!(player.set_graphic_eq(10, &[0.; 9]), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(player.set_graphic_eq(12, &[0.; 12]), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(player.set_graphic_eq(10, &[30.; 10]), Err(Error::InvalidArgument { .. })));
+        // 被拒绝的设置不改变原来的频段
+        assert_eq!(player.get_eq_band_frequencies(), frequencies);
+    }
+
+    #[test]
+    fn channel_map_rejects_channels_the_device_does_not_have() {
+        let mut player = null_player();
+        assert!(matches!(player.set_output_channel_map(vec![0, 2]), Err(Error::InvalidArgument { .. })));
+        assert!(matches!(player.set_output_channel_map(vec![0; AudioSample::MAX_CHANNELS + 1]), Err(Error::InvalidArgument { .. })));
+        // 被拒绝的映射不改变原来的设置
+        assert!(player.get_output_channel_map().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::queue::PlayQueue;
+
+/// 可以打开的播放列表扩展名
+pub const PLAYLIST_EXTENSIONS: [&str; 2] = ["m3u", "m3u8"];
+
+/// 扩展 M3U 的文件头
+const EXTM3U_HEADER: &str = "#EXTM3U";
+
+/// 扩展 M3U 中每个条目的附加信息，例如 `#EXTINF:215,Artist - Title`
+const EXTINF_PREFIX: &str = "#EXTINF:";
+
+/// 播放列表中的一个条目
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    /// 文件路径，相对路径已经按播放列表所在的目录展开，网络地址保持不变
+    pub path: PathBuf,
+    /// EXTINF 中的时长（毫秒），未知时为 -1
+    pub duration: i64,
+    /// EXTINF 中的标题
+    pub title: Option<String>,
+}
+
+/// 判断是否是已知扩展名的播放列表文件
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| PLAYLIST_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(extension)))
+        .unwrap_or(false)
+}
+
+/// 网络地址（例如 http://）不是文件路径，不按播放列表的目录展开
+fn is_url(location: &str) -> bool {
+    location.find("://").map_or(false, |index| {
+        index > 1 && location[..index].chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+    })
+}
+
+/// 解析 EXTINF 的时长和标题，时长和标题之间可能有 `tvg-id="..."` 等属性
+fn parse_extinf(info: &str) -> (i64, Option<String>) {
+    let (attributes, title) = match info.find(',') {
+        Some(index) => (&info[..index], Some(info[index + 1..].trim())),
+        None => (info, None),
+    };
+    let duration = attributes.split_whitespace()
+        .next()
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .filter(|duration| *duration >= 0.)
+        .map_or(-1, |duration| (duration * 1000.) as i64);
+    let title = title.filter(|title| !title.is_empty()).map(str::to_string);
+    (duration, title)
+}
+
+/// 解码播放列表的内容，m3u8 和带有 BOM 的文件是 UTF-8，
+/// 旧的 m3u 文件可能使用其他编码，不是有效的 UTF-8 时按 Latin-1 读取
+fn decode(content: &[u8]) -> String {
+    let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+    match std::str::from_utf8(content) {
+        Ok(text) => text.to_string(),
+        Err(_) => content.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+/// 读取普通或者扩展 M3U 播放列表，跳过空行和注释
+pub fn load_m3u(path: &Path) -> io::Result<Vec<PlaylistEntry>> {
+    let content = decode(&fs::read(path)?);
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries = Vec::new();
+    let mut info = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(extinf) = line.strip_prefix(EXTINF_PREFIX) {
+            info = Some(parse_extinf(extinf));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let location = PathBuf::from(line);
+        let path = if is_url(line) || location.is_absolute() {
+            location
+        } else {
+            base.join(location)
+        };
+        let (duration, title) = info.take().unwrap_or((-1, None));
+        entries.push(PlaylistEntry { path, duration, title });
+    }
+    Ok(entries)
+}
+
+/// 按队列中的顺序（不是随机播放的顺序）保存为 UTF-8 编码的扩展 M3U 播放列表
+///
+/// 播放列表所在目录下的文件保存为相对路径，移动整个目录之后仍然可以打开
+pub fn save_m3u(path: &Path, queue: &PlayQueue) -> io::Result<()> {
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut content = String::from(EXTM3U_HEADER);
+    content.push('\n');
+    for entry in queue.entries() {
+        let location = entry.strip_prefix(base).unwrap_or(entry);
+        content.push_str(&location.to_string_lossy());
+        content.push('\n');
+    }
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个测试使用单独的临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("yako-player-playlist-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saved_playlist_loads_the_same_entries() {
+        let dir = temp_dir("round-trip");
+        let playlist = dir.join("列表.m3u8");
+        let entries = vec![
+            dir.join("音乐").join("01 - 夜に駆ける.flac"),
+            dir.join("Ünïcödé.mp3"),
+            PathBuf::from("http://example.com/stream.mp3"),
+        ];
+        let mut queue = PlayQueue::new();
+        queue.set_entries(entries.clone());
+        save_m3u(&playlist, &queue).unwrap();
+
+        // 播放列表所在目录下的文件保存为相对路径
+        let content = fs::read_to_string(&playlist).unwrap();
+        assert!(content.starts_with(EXTM3U_HEADER));
+        assert!(!content.contains(&*dir.to_string_lossy()));
+
+        let loaded: Vec<PathBuf> = load_m3u(&playlist).unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(loaded, entries);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extinf_is_read_into_the_next_entry() {
+        let dir = temp_dir("extinf");
+        let playlist = dir.join("list.m3u");
+        fs::write(&playlist, "#EXTM3U\n#EXTINF:215.5,Artist - Title\nsong.mp3\n\n# comment\nother.mp3\n").unwrap();
+
+        let entries = load_m3u(&playlist).unwrap();
+        assert_eq!(entries, vec![
+            PlaylistEntry { path: dir.join("song.mp3"), duration: 215500, title: Some("Artist - Title".to_string()) },
+            PlaylistEntry { path: dir.join("other.mp3"), duration: -1, title: None },
+        ]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn latin1_playlist_is_decoded() {
+        assert_eq!(decode(b"caf\xe9.mp3"), "café.mp3");
+        assert_eq!(decode("\u{feff}曲.mp3".as_bytes()), "曲.mp3");
+    }
+}
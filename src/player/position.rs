@@ -0,0 +1,119 @@
+use std::time::Instant;
+
+/// 在两次输出回调之间按经过的时间推算播放位置，让界面以较高的帧率读取位置时连续变化
+///
+/// 按帧数计算的位置只在设备回调取走数据时变化，每次变化时记录锚点，之后返回锚点加上经过的时间乘以播放速度，
+/// 最多超出锚点一次回调的时长
+pub struct PositionSmoother {
+    /// 按帧数计算的位置（微秒）和记录的时刻
+    anchor: Option<(i64, Instant)>,
+    /// 上一次返回的位置
+    last: i64,
+}
+
+impl PositionSmoother {
+    pub fn new() -> PositionSmoother {
+        PositionSmoother { anchor: None, last: 0 }
+    }
+
+    /// 定位之后清除锚点，从新的位置重新开始推算
+    pub fn reset(&mut self) {
+        self.anchor = None;
+        self.last = 0;
+    }
+
+    /// 按帧数计算的位置 exact（微秒）推算当前位置，max_ahead_us 为一次回调的时长
+    ///
+    /// 没有在播放时直接返回 exact，暂停后位置保持不变。新的锚点略早于上一次推算的位置时（差距在一次回调之内），
+    /// 保持上一次的位置，返回值不会倒退
+    pub fn position(&mut self, exact: i64, playing: bool, speed: f32, max_ahead_us: i64) -> i64 {
+        if !playing {
+            self.anchor = None;
+            self.last = exact;
+            return exact;
+        }
+        let now = Instant::now();
+        let since = match self.anchor {
+            Some((anchor, since)) if anchor == exact => since,
+            _ => {
+                self.anchor = Some((exact, now));
+                now
+            },
+        };
+        let elapsed_us = (now.duration_since(since).as_secs_f64() * 1_000_000. * speed as f64) as i64;
+        let mut position = exact + elapsed_us.min(max_ahead_us.max(0));
+        if position < self.last && self.last - position <= max_ahead_us {
+            position = self.last;
+        }
+        self.last = position;
+        position
+    }
+}
+
+impl Default for PositionSmoother {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    /// 一次回调 10 毫秒
+    const CALLBACK_US: i64 = 10_000;
+
+    #[test]
+    fn smoothed_position_is_monotonic_and_within_one_callback() {
+        let mut smoother = PositionSmoother::new();
+        let start = Instant::now();
+        let mut last = 0;
+        while start.elapsed() < Duration::from_millis(200) {
+            // 真实的位置连续变化，按帧数计算的位置只在每次回调时前进
+            let actual = start.elapsed().as_micros() as i64;
+            let exact = actual / CALLBACK_US * CALLBACK_US;
+            let position = smoother.position(exact, true, 1., CALLBACK_US);
+            let after = start.elapsed().as_micros() as i64;
+            assert!(position >= last, "went back from {} to {}", last, position);
+            assert!(position >= exact && position <= exact + CALLBACK_US);
+            assert!(position <= after + CALLBACK_US && position >= actual - CALLBACK_US,
+                "{} is too far from {}", position, actual);
+            last = position;
+            thread::sleep(Duration::from_micros(700));
+        }
+    }
+
+    #[test]
+    fn pausing_freezes_the_position() {
+        let mut smoother = PositionSmoother::new();
+        smoother.position(50_000, true, 1., CALLBACK_US);
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(smoother.position(55_000, false, 1., CALLBACK_US), 55_000);
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(smoother.position(55_000, false, 1., CALLBACK_US), 55_000);
+    }
+
+    #[test]
+    fn extrapolation_follows_the_speed_and_stops_after_one_callback() {
+        let mut smoother = PositionSmoother::new();
+        smoother.position(0, true, 2., CALLBACK_US);
+        thread::sleep(Duration::from_millis(3));
+        let position = smoother.position(0, true, 2., CALLBACK_US);
+        assert!(position >= 6_000, "only reached {}", position);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(smoother.position(0, true, 2., CALLBACK_US), CALLBACK_US);
+    }
+
+    #[test]
+    fn reset_starts_again_from_the_new_anchor() {
+        let mut smoother = PositionSmoother::new();
+        smoother.position(900_000, true, 1., CALLBACK_US);
+        thread::sleep(Duration::from_millis(2));
+        smoother.position(900_000, true, 1., CALLBACK_US);
+        // 定位到前面之后不保持原来的位置
+        smoother.reset();
+        assert_eq!(smoother.position(100_000, true, 1., CALLBACK_US), 100_000);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+
+use snafu::ResultExt;
+
+use crate::archive::{self, ZipArchive};
+use crate::audio::source;
+use crate::info::media::MediaInfo;
+use crate::metadata::MetadataExtractor;
+
+use super::{Error, ArchiveSnafu, SourceSnafu};
+
+/// 批量读取时最多使用的线程数
+const MAX_PROBE_THREADS: usize = 8;
+
+/// 只读取文件头获取媒体信息，不打开音频设备，也不启动解码线程
+///
+/// 支持 `album.zip!/03 track.flac` 形式的压缩包内路径
+pub fn probe(path: &Path, extractors: &[Arc<dyn MetadataExtractor>]) -> Result<MediaInfo, Error> {
+    let input_ctx = match archive::split_entry_path(path) {
+        Some((archive_path, name)) => {
+            let reader = ZipArchive::open(&archive_path)
+                .and_then(|archive| archive.open_entry(&name))
+                .context(ArchiveSnafu)?;
+            source::open_seekable_media(reader).context(SourceSnafu)?
+        },
+        None => source::open_media_file(&path, &HashMap::new()).context(SourceSnafu)?,
+    };
+    source::probe_input(&input_ctx, Some(path), extractors).context(SourceSnafu)
+}
+
+/// 在有限数量的线程中并行读取多个文件，结果按输入顺序排列
+pub fn probe_batch(paths: &[PathBuf], extractors: &[Arc<dyn MetadataExtractor>]) -> Vec<Result<MediaInfo, Error>> {
+    let thread_count = std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(MAX_PROBE_THREADS)
+        .min(paths.len());
+    if thread_count <= 1 {
+        return paths.iter().map(|path| probe(path, extractors)).collect();
+    }
+
+    let paths = Arc::new(paths.to_vec());
+    let extractors = Arc::new(extractors.to_vec());
+    let next_index = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let threads: Vec<_> = (0..thread_count)
+        .map(|_| {
+            let paths = paths.clone();
+            let extractors = extractors.clone();
+            let next_index = next_index.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || loop {
+                let index = next_index.fetch_add(1, Ordering::Relaxed);
+                if index >= paths.len() {
+                    break;
+                }
+                if sender.send((index, probe(&paths[index], &extractors))).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut results: Vec<Option<Result<MediaInfo, Error>>> = (0..paths.len()).map(|_| None).collect();
+    for (index, result) in receiver {
+        results[index] = Some(result);
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+    results
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err(source::Error::OpenMediaFile {
+            message: "the probe thread panicked".to_string(),
+        }).context(SourceSnafu)))
+        .collect()
+}
@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// 循环模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RepeatMode {
+    /// 播放到列表末尾后停止
+    Off,
+    /// 列表循环
+    All,
+    /// 单曲循环
+    One,
+}
+
+impl Default for RepeatMode {
+    fn default() -> Self {
+        Self::Off
+    }
+}
+
+/// 播放队列
+#[derive(Debug, Default)]
+pub struct PlayQueue {
+    /// 队列中的文件
+    entries: Vec<PathBuf>,
+    /// 播放顺序（entries 的下标），随机播放时被打乱
+    order: Vec<usize>,
+    /// 当前播放位置（order 的下标）
+    position: Option<usize>,
+    repeat_mode: RepeatMode,
+    shuffle: bool,
+}
+
+impl PlayQueue {
+    pub fn new() -> PlayQueue {
+        PlayQueue::default()
+    }
+
+    /// 替换队列中的所有文件，并清除当前播放位置
+    pub fn set_entries(&mut self, entries: Vec<PathBuf>) {
+        self.entries = entries;
+        self.position = None;
+        self.rebuild_order();
+    }
+
+    /// 在队列末尾添加文件
+    pub fn push(&mut self, path: PathBuf) {
+        self.entries.push(path);
+        let index = self.entries.len() - 1;
+        if self.shuffle {
+            // 新文件插入到尚未播放的部分中的随机位置
+            let start = self.position.map(|position| position + 1).unwrap_or(0);
+            let offset = random_below(self.order.len() - start + 1);
+            self.order.insert(start + offset, index);
+        } else {
+            self.order.push(index);
+        }
+    }
+
+    /// 清空队列
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.position = None;
+    }
+
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 当前播放的文件
+    pub fn current(&self) -> Option<&Path> {
+        self.current_index().map(|index| self.entries[index].as_path())
+    }
+
+    /// 当前播放的文件在队列中的下标
+    pub fn current_index(&self) -> Option<usize> {
+        self.position.map(|position| self.order[position])
+    }
+
+    /// 将队列中指定下标的文件设为当前播放的文件
+    pub fn set_current(&mut self, index: usize) -> Option<&Path> {
+        let position = self.order.iter().position(|&entry| entry == index)?;
+        self.position = Some(position);
+        self.current()
+    }
+
+    pub fn repeat_mode(&self) -> RepeatMode {
+        self.repeat_mode
+    }
+
+    pub fn set_repeat_mode(&mut self, repeat_mode: RepeatMode) {
+        self.repeat_mode = repeat_mode;
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// 开关随机播放，当前播放的文件保持不变
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if self.shuffle != shuffle {
+            self.shuffle = shuffle;
+            self.rebuild_order();
+        }
+    }
+
+    /// 是否有下一首（考虑列表循环）
+    pub fn has_next(&self) -> bool {
+        self.next_position().is_some()
+    }
+
+    /// 是否有上一首（考虑列表循环）
+    pub fn has_previous(&self) -> bool {
+        self.previous_position().is_some()
+    }
+
+    /// 切换到下一首
+    pub fn next(&mut self) -> Option<&Path> {
+        let position = self.next_position()?;
+        self.position = Some(position);
+        self.current()
+    }
+
+    /// 切换到上一首
+    pub fn previous(&mut self) -> Option<&Path> {
+        let position = self.previous_position()?;
+        self.position = Some(position);
+        self.current()
+    }
+
+    /// 播放顺序中当前文件的上一首和下一首（考虑列表循环），不改变当前播放位置
+    pub fn neighbours(&self) -> (Option<&Path>, Option<&Path>) {
+        let entry = |position: Option<usize>| {
+            position
+                .filter(|position| Some(*position) != self.position)
+                .map(|position| self.entries[self.order[position]].as_path())
+        };
+        (entry(self.previous_position()), entry(self.next_position()))
+    }
+
+    fn next_position(&self) -> Option<usize> {
+        if self.order.is_empty() {
+            return None;
+        }
+        match self.position {
+            None => Some(0),
+            Some(position) if position + 1 < self.order.len() => Some(position + 1),
+            Some(_) if self.repeat_mode == RepeatMode::All => Some(0),
+            Some(_) => None,
+        }
+    }
+
+    fn previous_position(&self) -> Option<usize> {
+        match self.position {
+            None => None,
+            Some(position) if position > 0 => Some(position - 1),
+            Some(_) if self.repeat_mode == RepeatMode::All => Some(self.order.len() - 1),
+            Some(_) => None,
+        }
+    }
+
+    /// 重新生成播放顺序
+    fn rebuild_order(&mut self) {
+        let current = self.current_index();
+        self.order = (0..self.entries.len()).collect();
+
+        if self.shuffle {
+            // Fisher–Yates 洗牌
+            for i in (1..self.order.len()).rev() {
+                let j = random_below(i + 1);
+                self.order.swap(i, j);
+            }
+            // 当前播放的文件放在最前面
+            if let Some(current) = current {
+                if let Some(position) = self.order.iter().position(|&entry| entry == current) {
+                    self.order.remove(position);
+                    self.order.insert(0, current);
+                }
+            }
+        }
+
+        self.position = current.and_then(|current| self.order.iter().position(|&entry| entry == current));
+    }
+}
+
+/// 生成 [0, upper) 范围内的伪随机数，只用于打乱播放顺序
+fn random_below(upper: usize) -> usize {
+    use std::cell::Cell;
+
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0) | 1
+        );
+    }
+
+    if upper <= 1 {
+        return 0;
+    }
+    STATE.with(|state| {
+        // xorshift64
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x % upper as u64) as usize
+    })
+}
@@ -0,0 +1,148 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+
+use super::event::{EventBus, PlayerEvent};
+
+/// 可以播放的音频文件扩展名
+pub const AUDIO_EXTENSIONS: [&str; 12] = ["wav", "mp3", "flac", "ogg", "opus", "aac", "m4a", "mp4", "wma", "ape", "tak", "alac"];
+
+/// 扫描目录的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanSummary {
+    /// 找到的音频文件，按自然顺序排列
+    pub files: Vec<PathBuf>,
+    /// 跳过的目录和文件
+    pub warnings: Vec<String>,
+}
+
+/// 在后台线程中进行的目录扫描
+pub struct DirectoryScan {
+    thread: JoinHandle<ScanSummary>,
+}
+
+impl DirectoryScan {
+    /// 开始扫描，完成时发出 DirectoryScanned 事件
+    pub fn start(path: PathBuf, recursive: bool, events: EventBus) -> DirectoryScan {
+        let thread = std::thread::spawn(move || {
+            let summary = scan_directory(&path, recursive);
+            events.emit(PlayerEvent::DirectoryScanned {
+                path,
+                files: summary.files.len(),
+                warnings: summary.warnings.len(),
+            });
+            summary
+        });
+        DirectoryScan { thread }
+    }
+
+    /// 等待扫描完成
+    pub fn wait(self) -> ScanSummary {
+        self.thread.join().unwrap_or_else(|_| ScanSummary {
+            files: Vec::new(),
+            warnings: vec!["the directory scan panicked".to_string()],
+        })
+    }
+}
+
+/// 判断是否是已知扩展名的音频文件
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| AUDIO_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(extension)))
+        .unwrap_or(false)
+}
+
+/// 扫描目录下的音频文件，无法读取的目录和文件记录到警告中
+pub fn scan_directory(path: &Path, recursive: bool) -> ScanSummary {
+    let mut summary = ScanSummary::default();
+    let mut visited = HashSet::new();
+    collect_audio_files(path, recursive, &mut visited, &mut summary);
+    summary
+}
+
+fn collect_audio_files(dir: &Path, recursive: bool, visited: &mut HashSet<PathBuf>, summary: &mut ScanSummary) {
+    // 符号链接可能指向上级目录，按真实路径记录已经扫描过的目录
+    match fs::canonicalize(dir) {
+        Ok(real_path) => {
+            if !visited.insert(real_path) {
+                summary.warnings.push(format!("Skipped {}: the directory has already been scanned", dir.display()));
+                return;
+            }
+        },
+        Err(err) => {
+            summary.warnings.push(format!("Failed to read directory {}: {}", dir.display(), err));
+            return;
+        },
+    }
+
+    let mut entries = Vec::new();
+    match fs::read_dir(dir) {
+        Ok(read_dir) => {
+            for entry in read_dir {
+                match entry {
+                    Ok(entry) => entries.push(entry.path()),
+                    Err(err) => summary.warnings.push(format!("Failed to read an entry in {}: {}", dir.display(), err)),
+                }
+            }
+        },
+        Err(err) => {
+            summary.warnings.push(format!("Failed to read directory {}: {}", dir.display(), err));
+            return;
+        },
+    }
+    entries.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    for entry in entries {
+        if entry.is_dir() {
+            if recursive {
+                collect_audio_files(&entry, recursive, visited, summary);
+            }
+        } else if is_audio_file(&entry) {
+            summary.files.push(entry);
+        }
+    }
+}
+
+/// 自然排序：数字部分按数值比较（track 2 在 track 10 之前），其他部分忽略大小写
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                // 去掉前导零后先比较位数，再逐位比较，不会溢出
+                let ordering = x.trim_start_matches('0').len().cmp(&y.trim_start_matches('0').len())
+                    .then_with(|| x.trim_start_matches('0').cmp(y.trim_start_matches('0')))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+        number.push(c);
+        chars.next();
+    }
+    number
+}
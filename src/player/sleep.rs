@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::audio::device::OutputControl;
+use crate::audio::source::FFmpegSourceStatus;
+
+/// 默认的淡出时长
+pub const DEFAULT_SLEEP_FADE_DURATION: Duration = Duration::from_secs(10);
+/// 取消定时时恢复音量的时长
+const CANCEL_RAMP_DURATION: Duration = Duration::from_millis(500);
+/// 检查定时条件的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 定时停止播放
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepTimer {
+    /// 不使用定时
+    Off,
+    /// 经过指定的时间（毫秒）后停止
+    AfterDuration(u64),
+    /// 当前曲目播放结束时停止
+    AfterCurrentTrack,
+}
+
+impl Default for SleepTimer {
+    fn default() -> Self {
+        SleepTimer::Off
+    }
+}
+
+/// 当前播放源的状态
+struct SourceHandle {
+    status: Arc<FFmpegSourceStatus>,
+    /// 总长度（毫秒），未知时为 -1
+    duration: i64,
+}
+
+struct MonitorState {
+    timer: SleepTimer,
+    /// AfterDuration 的截止时间
+    deadline: Option<Instant>,
+    fade_duration: Duration,
+    /// 是否正在淡出
+    fading: bool,
+    output: Option<OutputControl>,
+    source: Option<SourceHandle>,
+}
+
+struct Shared {
+    state: Mutex<MonitorState>,
+    running: AtomicBool,
+}
+
+/// 在后台线程中检查定时条件，满足时缓慢淡出后暂停播放
+pub struct SleepTimerMonitor {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SleepTimerMonitor {
+    pub fn new() -> SleepTimerMonitor {
+        SleepTimerMonitor {
+            shared: Arc::new(Shared {
+                state: Mutex::new(MonitorState {
+                    timer: SleepTimer::Off,
+                    deadline: None,
+                    fade_duration: DEFAULT_SLEEP_FADE_DURATION,
+                    fading: false,
+                    output: None,
+                    source: None,
+                }),
+                running: AtomicBool::new(true),
+            }),
+            thread: None,
+        }
+    }
+
+    /// 设置定时，正在淡出时取消会在短时间内恢复音量
+    pub fn set_timer(&mut self, timer: SleepTimer) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.fading {
+                if let Some(output) = state.output.as_ref() {
+                    output.fade_to(1., CANCEL_RAMP_DURATION);
+                }
+                state.fading = false;
+            }
+            state.timer = timer;
+            state.deadline = match timer {
+                SleepTimer::AfterDuration(ms) => Instant::now().checked_add(Duration::from_millis(ms)),
+                _ => None,
+            };
+        }
+
+        // 第一次使用定时时才创建线程
+        if timer != SleepTimer::Off && self.thread.is_none() {
+            let shared = self.shared.clone();
+            self.thread = Some(std::thread::spawn(move || {
+                while shared.running.load(Ordering::Relaxed) {
+                    SleepTimerMonitor::poll(&mut shared.state.lock().unwrap());
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }));
+        }
+    }
+
+    pub fn get_timer(&self) -> SleepTimer {
+        self.shared.state.lock().unwrap().timer
+    }
+
+    /// 距离停止播放还有多长时间，没有定时或者无法确定时返回 None
+    pub fn get_remaining(&self) -> Option<Duration> {
+        SleepTimerMonitor::remaining(&self.shared.state.lock().unwrap(), Instant::now())
+    }
+
+    /// 设置淡出时长
+    pub fn set_fade_duration(&mut self, duration: Duration) {
+        self.shared.state.lock().unwrap().fade_duration = duration;
+    }
+
+    pub fn get_fade_duration(&self) -> Duration {
+        self.shared.state.lock().unwrap().fade_duration
+    }
+
+    /// 设置用于淡出和暂停的输出句柄
+    pub fn set_output(&mut self, output: OutputControl) {
+        self.shared.state.lock().unwrap().output = Some(output);
+    }
+
+    /// 设置当前播放源，打开新文件后调用
+    pub fn set_source(&mut self, status: Arc<FFmpegSourceStatus>, duration: i64) {
+        self.shared.state.lock().unwrap().source = Some(SourceHandle { status, duration });
+    }
+
+    fn remaining(state: &MonitorState, now: Instant) -> Option<Duration> {
+        match state.timer {
+            SleepTimer::Off => None,
+            SleepTimer::AfterDuration(_) => state.deadline.map(|deadline| deadline.saturating_duration_since(now)),
+            SleepTimer::AfterCurrentTrack => {
+                let source = state.source.as_ref()?;
+                if source.status.is_end.load(Ordering::Relaxed) {
+                    return Some(Duration::ZERO);
+                }
+                if source.duration <= 0 {
+                    return None;
+                }
+                let current_time = source.status.current_time.lock().unwrap().get();
+                Some(Duration::from_millis((source.duration - current_time).max(0) as u64))
+            },
+        }
+    }
+
+    fn poll(state: &mut MonitorState) {
+        let remaining = match SleepTimerMonitor::remaining(state, Instant::now()) {
+            Some(remaining) => remaining,
+            None => return,
+        };
+        let output = match state.output.clone() {
+            Some(output) => output,
+            None => return,
+        };
+        let playing = state.source.as_ref()
+            .map(|source| source.status.playing.load(Ordering::Relaxed))
+            .unwrap_or(false);
+
+        if !state.fading && playing && remaining <= state.fade_duration {
+            // 淡出在定时结束的时刻完成
+            output.fade_to(0., remaining);
+            state.fading = true;
+        }
+
+        let finished = if state.fading { output.is_fade_finished() } else { remaining.is_zero() };
+        if finished {
+            output.pause();
+            if let Some(source) = state.source.as_ref() {
+                source.status.playing.store(false, Ordering::Relaxed);
+            }
+            // 已经暂停，恢复增益以便下次播放
+            output.fade_to(1., Duration::ZERO);
+            state.fading = false;
+            state.timer = SleepTimer::Off;
+            state.deadline = None;
+        }
+    }
+}
+
+impl Default for SleepTimerMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for SleepTimerMonitor {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
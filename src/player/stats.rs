@@ -0,0 +1,335 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::audio::device::{ClipCounter, PlayedFrameCounter};
+use crate::audio::source::FFmpegSourceStatus;
+
+use super::event::{EventBus, PlayerEvent};
+
+/// 检查播放进度的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 提交播放记录的条件：实际播放的时长达到总长度的 ratio 或者 max_ms 毫秒，以先到者为准
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrobbleThreshold {
+    pub ratio: f64,
+    pub max_ms: i64,
+}
+
+impl ScrobbleThreshold {
+    /// 需要实际播放的时长（毫秒），总长度未知时只使用 max_ms
+    fn target(&self, duration: i64) -> f64 {
+        if duration > 0 {
+            (duration as f64 * self.ratio).min(self.max_ms as f64)
+        } else {
+            self.max_ms as f64
+        }
+    }
+}
+
+impl Default for ScrobbleThreshold {
+    fn default() -> Self {
+        ScrobbleThreshold { ratio: 0.5, max_ms: 240_000 }
+    }
+}
+
+/// 当前曲目的播放统计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlaybackStats {
+    /// 实际播放的时长（毫秒），不包括暂停的时间，定位不会增加或者减少
+    pub played_ms: i64,
+    /// 总长度（毫秒），未知时为 -1
+    pub duration_ms: i64,
+    /// 是否已经达到提交播放记录的条件
+    pub scrobble_ready: bool,
+    /// 输出时被截断的样本数
+    pub clipped_samples: u64,
+}
+
+struct TrackState {
+    status: Arc<FFmpegSourceStatus>,
+    counter: PlayedFrameCounter,
+    clips: ClipCounter,
+    path: Option<PathBuf>,
+    duration: i64,
+    /// 上一次检查时的设备播放帧数
+    last_frames: u64,
+    /// 上一次检查时的截断样本数
+    last_clips: u64,
+    /// 上一次检查时输出是否因为解码跟不上而处于缓冲状态
+    buffering: bool,
+    /// 实际播放的时长（毫秒）
+    played: f64,
+    started: bool,
+    scrobbled: bool,
+    finished: bool,
+    /// 预先打开的下一首接替当前曲目时发出的事件
+    announcements: Vec<PlayerEvent>,
+}
+
+impl TrackState {
+    fn new(
+        status: Arc<FFmpegSourceStatus>,
+        duration: i64,
+        counter: PlayedFrameCounter,
+        clips: ClipCounter,
+        path: Option<PathBuf>,
+        announcements: Vec<PlayerEvent>,
+    ) -> TrackState {
+        let last_frames = counter.get();
+        TrackState {
+            status,
+            counter,
+            clips,
+            path,
+            duration,
+            last_frames,
+            last_clips: 0,
+            buffering: false,
+            played: 0.,
+            started: false,
+            scrobbled: false,
+            finished: false,
+            announcements,
+        }
+    }
+
+    fn stats(&self) -> PlaybackStats {
+        PlaybackStats {
+            played_ms: self.played as i64,
+            duration_ms: self.duration,
+            scrobble_ready: self.scrobbled,
+            clipped_samples: self.clips.get(),
+        }
+    }
+
+    fn finish(&mut self, events: &EventBus) {
+        if self.started && !self.finished {
+            self.finished = true;
+            events.emit(PlayerEvent::TrackFinished { played_ms: self.played as i64, duration_ms: self.duration });
+        }
+    }
+}
+
+struct TrackerState {
+    threshold: ScrobbleThreshold,
+    /// 是否发出 Clipping 事件
+    clipping_events: bool,
+    track: Option<TrackState>,
+    /// 预先打开的下一首，当前曲目把输出交给它之后成为当前曲目
+    successor: Option<TrackState>,
+}
+
+impl TrackerState {
+    /// 当前曲目已经把输出交给了下一首时，结束当前曲目并开始统计下一首
+    fn follow_successor(&mut self, events: &EventBus) {
+        if !self.successor.as_ref().map_or(false, |successor| successor.status.handed_over.load(Ordering::Relaxed)) {
+            return;
+        }
+        let mut successor = self.successor.take().unwrap();
+        if let Some(track) = self.track.as_mut() {
+            PlaybackTracker::poll(track, self.threshold, self.clipping_events, events);
+            track.finish(events);
+        }
+        for event in successor.announcements.drain(..) {
+            events.emit(event);
+        }
+        successor.last_frames = successor.counter.get();
+        successor.clips.reset();
+        self.track = Some(successor);
+    }
+}
+
+struct Shared {
+    state: Mutex<TrackerState>,
+    running: AtomicBool,
+}
+
+/// 根据设备实际播放的帧数累计播放时长，并发出播放记录相关的事件
+pub struct PlaybackTracker {
+    shared: Arc<Shared>,
+    events: EventBus,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PlaybackTracker {
+    pub fn new(events: EventBus) -> PlaybackTracker {
+        PlaybackTracker {
+            shared: Arc::new(Shared {
+                state: Mutex::new(TrackerState {
+                    threshold: ScrobbleThreshold::default(),
+                    clipping_events: false,
+                    track: None,
+                    successor: None,
+                }),
+                running: AtomicBool::new(true),
+            }),
+            events,
+            thread: None,
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: ScrobbleThreshold) {
+        self.shared.state.lock().unwrap().threshold = threshold;
+    }
+
+    pub fn get_threshold(&self) -> ScrobbleThreshold {
+        self.shared.state.lock().unwrap().threshold
+    }
+
+    pub fn set_clipping_events(&mut self, enabled: bool) {
+        self.shared.state.lock().unwrap().clipping_events = enabled;
+    }
+
+    pub fn is_clipping_events(&self) -> bool {
+        self.shared.state.lock().unwrap().clipping_events
+    }
+
+    pub fn get_stats(&self) -> PlaybackStats {
+        let state = self.shared.state.lock().unwrap();
+        state.track.as_ref().map(TrackState::stats).unwrap_or(PlaybackStats { duration_ms: -1, ..Default::default() })
+    }
+
+    /// 设置当前播放源，打开新文件后调用，上一个曲目视为播放结束，截断计数从 0 开始
+    pub fn set_source(
+        &mut self,
+        status: Arc<FFmpegSourceStatus>,
+        duration: i64,
+        counter: PlayedFrameCounter,
+        clips: ClipCounter,
+        path: Option<PathBuf>,
+    ) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.follow_successor(&self.events);
+            let threshold = state.threshold;
+            let clipping_events = state.clipping_events;
+            if let Some(track) = state.track.as_mut() {
+                PlaybackTracker::poll(track, threshold, clipping_events, &self.events);
+                track.finish(&self.events);
+            }
+            clips.reset();
+            state.track = Some(TrackState::new(status, duration, counter, clips, path, Vec::new()));
+            state.successor = None;
+        }
+
+        // 第一次打开文件时才创建线程
+        if self.thread.is_none() {
+            let shared = self.shared.clone();
+            let events = self.events.clone();
+            self.thread = Some(std::thread::spawn(move || {
+                while shared.running.load(Ordering::Relaxed) {
+                    {
+                        let mut state = shared.state.lock().unwrap();
+                        let threshold = state.threshold;
+                        let clipping_events = state.clipping_events;
+                        if let Some(track) = state.track.as_mut() {
+                            PlaybackTracker::poll(track, threshold, clipping_events, &events);
+                        }
+                        state.follow_successor(&events);
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }));
+        }
+    }
+
+    /// 设置预先打开的下一首，当前曲目把输出交给它之后，当前曲目视为播放结束，
+    /// 开始统计下一首并发出 announcements 中的事件
+    pub fn set_successor(
+        &mut self,
+        status: Arc<FFmpegSourceStatus>,
+        duration: i64,
+        counter: PlayedFrameCounter,
+        clips: ClipCounter,
+        path: Option<PathBuf>,
+        announcements: Vec<PlayerEvent>,
+    ) {
+        let mut state = self.shared.state.lock().unwrap();
+        // 上一个下一首已经接替了当前曲目时先完成切换，不丢失它的统计
+        state.follow_successor(&self.events);
+        state.successor = Some(TrackState::new(status, duration, counter, clips, path, announcements));
+    }
+
+    /// 取消预先打开的下一首
+    pub fn clear_successor(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.follow_successor(&self.events);
+        state.successor = None;
+    }
+
+    /// 关闭当前曲目后调用，当前曲目视为播放结束，统计恢复为没有曲目时的值
+    pub fn clear_source(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.follow_successor(&self.events);
+        state.successor = None;
+        let threshold = state.threshold;
+        let clipping_events = state.clipping_events;
+        if let Some(track) = state.track.as_mut() {
+            PlaybackTracker::poll(track, threshold, clipping_events, &self.events);
+            track.finish(&self.events);
+        }
+        state.track = None;
+    }
+
+    fn poll(track: &mut TrackState, threshold: ScrobbleThreshold, clipping_events: bool, events: &EventBus) {
+        let is_end = track.status.is_end.load(Ordering::Relaxed);
+        if track.finished && !is_end {
+            // 播放完毕后又从头开始播放，视为新的一次播放
+            track.played = 0.;
+            track.started = false;
+            track.scrobbled = false;
+            track.finished = false;
+            track.clips.reset();
+            track.last_clips = 0;
+        }
+
+        // 按设备实际播放的帧数累计，暂停和缓冲区不足时帧数不变，定位也不会重复计算
+        let frames = track.counter.get();
+        let delta = frames.saturating_sub(track.last_frames);
+        track.last_frames = frames;
+        let sample_rate = track.counter.sample_rate();
+        if delta > 0 && sample_rate > 0 {
+            let speed = f32::from_bits(track.status.speed.load(Ordering::Relaxed)) as f64;
+            track.played += delta as f64 * 1000. / sample_rate as f64 * speed;
+            if !track.started {
+                track.started = true;
+                events.emit(PlayerEvent::NowPlaying { path: track.path.clone(), duration_ms: track.duration });
+            }
+        }
+
+        if track.started && !track.scrobbled && track.played >= threshold.target(track.duration) {
+            track.scrobbled = true;
+            events.emit(PlayerEvent::ScrobbleReady { played_ms: track.played as i64, duration_ms: track.duration });
+        }
+
+        let buffering = track.counter.is_buffering();
+        if buffering != track.buffering {
+            track.buffering = buffering;
+            events.emit(PlayerEvent::Buffering(buffering));
+        }
+
+        let clips = track.clips.get();
+        if clipping_events && clips > track.last_clips {
+            events.emit(PlayerEvent::Clipping { samples: clips - track.last_clips });
+        }
+        track.last_clips = clips;
+
+        // 解码线程读到结尾时输出缓冲区中还有没播放的音频，播放完之后才算结束
+        if is_end && track.counter.buffered_frames() == 0 {
+            track.finish(events);
+        }
+    }
+}
+
+impl Drop for PlaybackTracker {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}